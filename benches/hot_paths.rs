@@ -0,0 +1,81 @@
+//! Benchmarks for loading, searching and drawing a large library
+//!
+//! Run with `cargo bench --features bench --bench hot_paths`. The 100k-book library is generated once into
+//! cargo's target tmp directory and reused by later runs.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ratatui::{backend::TestBackend, Terminal};
+use std::path::PathBuf;
+use tokio::runtime::Runtime;
+
+use tuilibre::app::{BookQuery, Filter, FilterClause, SearchIndex, ViewMode};
+use tuilibre::fixture::generate_library;
+use tuilibre::ui::components::UIComponents;
+use tuilibre::{App, Database};
+
+const BOOKS: usize = 100_000;
+
+/// The synthetic library, generated on first use
+fn library(runtime: &Runtime) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("bench-library-{}", BOOKS));
+    if !path.join("metadata.db").exists() {
+        runtime
+            .block_on(generate_library(&path, BOOKS, 42))
+            .expect("failed to generate the benchmark library");
+    }
+    path
+}
+
+fn hot_paths(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("failed to start tokio");
+    let path = library(&runtime);
+    let database = runtime.block_on(Database::new(&path)).expect("failed to open the library");
+    let books = runtime.block_on(database.load_books()).expect("failed to load books");
+
+    let mut group = c.benchmark_group("library");
+    group.sample_size(10);
+
+    group.bench_function("load_books", |b| {
+        b.to_async(&runtime).iter(|| async { database.load_books().await.unwrap() })
+    });
+
+    let text = BookQuery { text: "dragon".to_string(), clauses: Vec::new() };
+    group.bench_function("search_text", |b| {
+        b.to_async(&runtime).iter(|| async { text.run(&books, &database).await.unwrap() })
+    });
+
+    let index = SearchIndex::build(&books);
+    group.bench_function("search_indexed", |b| {
+        b.to_async(&runtime).iter(|| async { text.run_indexed(&books, &index, &database).await.unwrap() })
+    });
+
+    let format = BookQuery {
+        text: String::new(),
+        clauses: vec![FilterClause::new(Filter::Format("PDF".to_string()))],
+    };
+    group.bench_function("search_format", |b| {
+        b.to_async(&runtime).iter(|| async { format.run(&books, &database).await.unwrap() })
+    });
+
+    for view in [ViewMode::List, ViewMode::Table, ViewMode::Grid] {
+        let mut app = App::new(path.clone());
+        app.books = books.clone();
+        app.selected_book_index = books.len() / 2;
+        app.view = view;
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        let mut components = UIComponents::new();
+        group.bench_function(format!("render_{}", view.name()), |b| {
+            b.iter(|| {
+                terminal
+                    .draw(|frame| components.render_book_list(frame, frame.size(), &app))
+                    .unwrap();
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, hot_paths);
+criterion_main!(benches);
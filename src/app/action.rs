@@ -0,0 +1,259 @@
+//! Every command of the book list in one registry
+//!
+//! The keymap, the quick actions menu and the key help in the status bar are all built
+//! from `ACTIONS`, so a command added here shows up everywhere it applies.
+
+use crossterm::event::KeyCode;
+
+use super::comic::COMIC_FORMATS;
+use super::reader::READABLE_FORMATS;
+use super::{App, Book};
+
+/// A command the user can run from the book list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    GoToLine,
+    JumpToLetter,
+    Hints,
+    Open,
+    OpenAll,
+    Play,
+    OpenWith,
+    Read,
+    ReadAtMatch,
+    Details,
+    EditTitle,
+    Convert,
+    EmbedMetadata,
+    Send,
+    Email,
+    Export,
+    Archive,
+    Mark,
+    VisualSelect,
+    AddTag,
+    RemoveTag,
+    AddToShelf,
+    RemoveFromShelf,
+    CopyPath,
+    Delete,
+    AddBook,
+    BookMenu,
+    Search,
+    AddFilter,
+    RemoveFilter,
+    NegateFilter,
+    TagBrowser,
+    VirtualLibrary,
+    SavedSearches,
+    CycleView,
+    CycleSort,
+    ReverseSort,
+    AbsoluteTimes,
+    CycleTheme,
+    SaveDefaults,
+    Opds,
+    Device,
+    LookUpMetadata,
+    DetectLanguages,
+    SuggestTags,
+    Verify,
+    AuditMetadata,
+    IndexContent,
+    DiskUsage,
+    Cleanup,
+    FormatPolicy,
+    Review,
+    StealLock,
+    QueuedActions,
+    Jobs,
+    SplitLibrary,
+    MergeLibrary,
+    ImportCalibreWeb,
+    SwitchLibrary,
+    RenameLibrary,
+    Quit,
+}
+
+/// How an action is presented and triggered
+#[derive(Debug)]
+pub struct ActionSpec {
+    pub action: Action,
+    pub label: &'static str,
+    /// Keys that run it from the book list; the first one is shown in menus and help
+    pub keys: &'static [KeyCode],
+    /// Offered in the quick actions menu of a book
+    pub on_book: bool,
+    /// Listed in the key help of the status bar
+    pub in_help: bool,
+}
+
+const fn spec(action: Action, label: &'static str, keys: &'static [KeyCode], on_book: bool, in_help: bool) -> ActionSpec {
+    ActionSpec { action, label, keys, on_book, in_help }
+}
+
+/// The registry, in menu and help order
+pub const ACTIONS: &[ActionSpec] = &[
+    spec(Action::MoveUp, "Previous book", &[KeyCode::Up, KeyCode::Char('k')], false, false),
+    spec(Action::MoveDown, "Next book", &[KeyCode::Down, KeyCode::Char('j')], false, false),
+    spec(Action::GoToLine, "Go to book (last without a count)", &[KeyCode::Char('G')], false, false),
+    spec(Action::Open, "Open", &[], true, false),
+    spec(Action::OpenAll, "Open marked books", &[], true, false),
+    spec(Action::Play, "Open in player", &[KeyCode::Char('A')], true, false),
+    spec(Action::OpenWith, "Open with...", &[], true, false),
+    spec(Action::Read, "Read in terminal", &[KeyCode::Char('p')], true, false),
+    spec(Action::ReadAtMatch, "Read at search match", &[KeyCode::Char('n')], true, false),
+    spec(Action::Details, "Details", &[KeyCode::Enter, KeyCode::Right], true, true),
+    spec(Action::BookMenu, "Actions", &[KeyCode::Char(' ')], false, true),
+    spec(Action::EditTitle, "Edit title...", &[], true, false),
+    spec(Action::Convert, "Convert to...", &[], true, false),
+    spec(Action::EmbedMetadata, "Update file metadata", &[], true, false),
+    spec(Action::Send, "Send to device", &[KeyCode::Char('s')], true, false),
+    spec(Action::Email, "Email...", &[KeyCode::Char('e')], true, false),
+    spec(Action::Export, "Export files...", &[KeyCode::Char('E')], true, false),
+    spec(Action::Archive, "Archive as zip/tar...", &[KeyCode::Char('Z')], true, false),
+    spec(Action::Mark, "Mark", &[KeyCode::Char('m')], true, true),
+    spec(Action::VisualSelect, "Visual select", &[KeyCode::Char('V')], false, false),
+    spec(Action::AddTag, "Add tag...", &[], true, false),
+    spec(Action::RemoveTag, "Remove tag...", &[], true, false),
+    spec(Action::AddToShelf, "Add to shelf...", &[KeyCode::Char('l')], true, false),
+    spec(Action::RemoveFromShelf, "Remove from shelf...", &[], true, false),
+    spec(Action::CopyPath, "Copy path", &[], true, false),
+    spec(Action::Delete, "Delete from library...", &[KeyCode::Char('d')], true, false),
+    spec(Action::AddBook, "Add book from file...", &[KeyCode::Char('a')], false, false),
+    spec(Action::Search, "Search", &[KeyCode::Char('/')], false, true),
+    spec(Action::AddFilter, "Filter", &[KeyCode::Char('f')], false, true),
+    spec(Action::RemoveFilter, "Remove filter", &[KeyCode::Char('x')], false, true),
+    spec(Action::NegateFilter, "Negate filter", &[KeyCode::Char('!')], false, false),
+    spec(Action::TagBrowser, "Tag browser", &[KeyCode::Tab], false, false),
+    spec(Action::VirtualLibrary, "Virtual library", &[KeyCode::Char('B')], false, false),
+    spec(Action::SavedSearches, "Saved searches", &[KeyCode::Char('b')], false, false),
+    spec(Action::CycleView, "View", &[KeyCode::Char('v')], false, true),
+    spec(Action::CycleSort, "Sort", &[KeyCode::Char('o')], false, true),
+    spec(Action::ReverseSort, "Reverse sort", &[KeyCode::Char('r')], false, false),
+    spec(Action::AbsoluteTimes, "Absolute times", &[KeyCode::Char('t')], false, false),
+    spec(Action::CycleTheme, "Next theme", &[KeyCode::Char('c')], false, false),
+    spec(Action::SaveDefaults, "Save view as default", &[KeyCode::Char('K')], false, false),
+    spec(Action::JumpToLetter, "Jump A-Z", &[KeyCode::Char('\'')], false, true),
+    spec(Action::Hints, "Open by hint", &[KeyCode::Char('H')], false, false),
+    spec(Action::Opds, "Browse OPDS catalogs", &[KeyCode::Char('O')], false, false),
+    spec(Action::Device, "Books on device", &[KeyCode::Char('D')], false, false),
+    spec(Action::LookUpMetadata, "Look up metadata", &[KeyCode::Char('M')], false, false),
+    spec(Action::DetectLanguages, "Detect languages", &[KeyCode::Char('L')], false, false),
+    spec(Action::SuggestTags, "Suggest tags", &[KeyCode::Char('T')], false, false),
+    spec(Action::Verify, "Verify files", &[KeyCode::Char('F')], false, false),
+    spec(Action::AuditMetadata, "Audit file metadata", &[KeyCode::Char('Y')], false, false),
+    spec(Action::IndexContent, "Index book contents", &[KeyCode::Char('X')], false, false),
+    spec(Action::DiskUsage, "Disk usage", &[KeyCode::Char('u')], false, false),
+    spec(Action::Cleanup, "Cleanup suggestions", &[KeyCode::Char('C')], false, false),
+    spec(Action::FormatPolicy, "Format policy audit", &[KeyCode::Char('P')], false, false),
+    spec(Action::Review, "Review metadata", &[KeyCode::Char('R')], false, false),
+    spec(Action::SplitLibrary, "Split listed books into a new library", &[KeyCode::Char('S')], false, false),
+    spec(Action::MergeLibrary, "Merge another library into this one", &[KeyCode::Char('I')], false, false),
+    spec(Action::ImportCalibreWeb, "Import reading state from calibre-web", &[KeyCode::Char('U')], false, false),
+    spec(Action::StealLock, "Steal library lock", &[KeyCode::Char('W')], false, false),
+    spec(Action::QueuedActions, "Queued offline actions", &[KeyCode::Char('Q')], false, false),
+    spec(Action::Jobs, "Job history", &[KeyCode::Char('J')], false, false),
+    spec(Action::SwitchLibrary, "Switch library", &[KeyCode::Esc, KeyCode::Left], false, false),
+    spec(Action::RenameLibrary, "Rename library...", &[KeyCode::Char('N')], false, false),
+    spec(Action::Quit, "Quit", &[KeyCode::Char('q')], false, true),
+];
+
+impl Action {
+    pub fn spec(&self) -> &'static ActionSpec {
+        ACTIONS
+            .iter()
+            .find(|spec| spec.action == *self)
+            .expect("every action is registered in ACTIONS")
+    }
+
+    pub fn label(&self) -> &'static str {
+        self.spec().label
+    }
+
+    /// The action bound to a key in the book list
+    pub fn for_key(code: KeyCode) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .find(|spec| spec.keys.contains(&code))
+            .map(|spec| spec.action)
+    }
+
+    /// Name of the first key bound to the action, e.g. "Enter" or "s"
+    pub fn key_hint(&self) -> Option<String> {
+        self.spec().keys.first().map(|code| key_name(*code))
+    }
+
+    /// Whether the action makes sense for this book with the current configuration
+    pub fn applies_to(&self, book: &Book, app: &App) -> bool {
+        let has_file = !book.formats.is_empty();
+        match self {
+            Action::Open | Action::OpenWith | Action::Convert | Action::CopyPath | Action::Send | Action::Export | Action::EmbedMetadata => has_file,
+            Action::Email => has_file && !app.config.email.profiles.is_empty(),
+            Action::OpenAll => !app.selected_ids.is_empty(),
+            Action::Play => has_file && book.is_audiobook(),
+            Action::RemoveTag => !book.tags.is_empty() || !app.selected_ids.is_empty(),
+            Action::Read => READABLE_FORMATS.iter().chain(&COMIC_FORMATS).any(|f| book.has_format(f)),
+            Action::ReadAtMatch => app.content_matches.get(&book.id).is_some_and(|m| READABLE_FORMATS.contains(&m.format.as_str())),
+            _ => true,
+        }
+    }
+
+    /// The actions offered in a book's quick actions menu, in registry order
+    pub fn for_book(book: &Book, app: &App) -> Vec<Action> {
+        ACTIONS
+            .iter()
+            .filter(|spec| spec.on_book && spec.action.applies_to(book, app))
+            .map(|spec| spec.action)
+            .collect()
+    }
+
+    /// Menu line, e.g. "Send to device  (s)"
+    pub fn menu_item(&self) -> String {
+        match self.key_hint() {
+            Some(key) => format!("{}  ({})", self.label(), key),
+            None => self.label().to_string(),
+        }
+    }
+}
+
+/// The actions visual select's batch menu offers, each run on every marked book
+pub const BATCH_ACTIONS: &[Action] = &[
+    Action::OpenAll,
+    Action::Export,
+    Action::Archive,
+    Action::Send,
+    Action::EmbedMetadata,
+    Action::AddTag,
+    Action::RemoveTag,
+    Action::AddToShelf,
+    Action::RemoveFromShelf,
+];
+
+/// Key help for the status bar, e.g. "Enter Details | Space Actions | ..."
+pub fn key_help() -> String {
+    ACTIONS
+        .iter()
+        .filter(|spec| spec.in_help)
+        .filter_map(|spec| spec.action.key_hint().map(|key| format!("{} {}", key, spec.label)))
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Display name of a key
+pub fn key_name(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "Enter".to_string(),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::Up => "↑".to_string(),
+        KeyCode::Down => "↓".to_string(),
+        KeyCode::Left => "←".to_string(),
+        KeyCode::Right => "→".to_string(),
+        other => format!("{:?}", other),
+    }
+}
@@ -0,0 +1,173 @@
+//! Audiobooks kept in calibre as M4B, M4A or MP3 files: how long they run, read from the
+//! file's own headers without decoding any audio
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::{Context, Error, Result};
+
+/// Formats holding audio rather than text, best first
+pub const AUDIO_FORMATS: [&str; 3] = ["M4B", "M4A", "MP3"];
+
+/// Bytes searched past an MP3's ID3 tag for the first frame
+const MP3_SYNC_SEARCH: usize = 64 * 1024;
+
+/// Whether `format` (e.g. "m4b") is an audio format
+pub fn is_audio_format(format: &str) -> bool {
+    AUDIO_FORMATS.iter().any(|audio| audio.eq_ignore_ascii_case(format))
+}
+
+/// How long the audio file at `path` plays
+pub fn audio_duration(path: &Path) -> Result<Duration> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let is_mp3 = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"));
+    let duration = if is_mp3 { mp3_duration(&mut file) } else { mp4_duration(&mut file) };
+    duration
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .ok_or_else(|| Error::Parse { context: format!("Invalid audio file: {}", path.display()), message: "no duration found".to_string() })
+}
+
+/// e.g. "11 h 42 min", or "42 min" under an hour
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = (duration.as_secs() + 30) / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{} min", minutes),
+        (hours, minutes) => format!("{} h {} min", hours, minutes),
+    }
+}
+
+/// The duration in the movie header (`moov/mvhd`) of an MP4 file
+fn mp4_duration(file: &mut File) -> std::io::Result<Option<Duration>> {
+    let end = file.metadata()?.len();
+    let Some((moov_start, moov_end)) = find_box(file, 0, end, b"moov")? else {
+        return Ok(None);
+    };
+    let Some((mvhd, _)) = find_box(file, moov_start, moov_end, b"mvhd")? else {
+        return Ok(None);
+    };
+
+    file.seek(SeekFrom::Start(mvhd))?;
+    let mut version = [0; 4];
+    file.read_exact(&mut version)?;
+    let (timescale, units) = if version[0] == 1 {
+        let mut fields = [0; 28];
+        file.read_exact(&mut fields)?;
+        (u32::from_be_bytes(fields[16..20].try_into().unwrap_or_default()), u64::from_be_bytes(fields[20..28].try_into().unwrap_or_default()))
+    } else {
+        let mut fields = [0; 16];
+        file.read_exact(&mut fields)?;
+        (u32::from_be_bytes(fields[8..12].try_into().unwrap_or_default()), u32::from_be_bytes(fields[12..16].try_into().unwrap_or_default()) as u64)
+    };
+    Ok((timescale > 0).then(|| Duration::from_secs_f64(units as f64 / timescale as f64)))
+}
+
+/// Content start and end of the first `kind` box between `start` and `end`
+fn find_box(file: &mut File, start: u64, end: u64, kind: &[u8; 4]) -> std::io::Result<Option<(u64, u64)>> {
+    let mut offset = start;
+    while offset + 8 <= end {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let (mut size, mut header_len) = (u32::from_be_bytes(header[..4].try_into().unwrap_or_default()) as u64, 8);
+        if size == 1 {
+            let mut large = [0; 8];
+            file.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        } else if size == 0 {
+            size = end - offset;
+        }
+        if size < header_len {
+            return Ok(None);
+        }
+        if &header[4..8] == kind {
+            return Ok(Some((offset + header_len, (offset + size).min(end))));
+        }
+        offset += size;
+    }
+    Ok(None)
+}
+
+/// An MP3's duration from its Xing or VBRI header, or else from the first frame's bitrate
+fn mp3_duration(file: &mut File) -> std::io::Result<Option<Duration>> {
+    let length = file.metadata()?.len();
+    let mut head = [0; 10];
+    file.read_exact(&mut head)?;
+    // An ID3v2 tag first, its size in 7-bit bytes, plus a footer when flagged
+    let tag = if &head[..3] == b"ID3" {
+        let size = head[6..10].iter().fold(0u64, |size, &b| size << 7 | (b & 0x7f) as u64);
+        10 + size + if head[5] & 0x10 != 0 { 10 } else { 0 }
+    } else {
+        0
+    };
+
+    file.seek(SeekFrom::Start(tag))?;
+    let mut data = Vec::with_capacity(MP3_SYNC_SEARCH);
+    file.by_ref().take(MP3_SYNC_SEARCH as u64).read_to_end(&mut data)?;
+    let Some((start, frame)) = (0..data.len().saturating_sub(4)).find_map(|i| Mp3Frame::parse(&data[i..i + 4]).map(|f| (i, f))) else {
+        return Ok(None);
+    };
+
+    let frames = frame.vbr_frames(&data[start..]);
+    Ok(Some(match frames {
+        Some(frames) => Duration::from_secs_f64(frames as f64 * frame.samples as f64 / frame.sample_rate as f64),
+        None => {
+            let audio = length.saturating_sub(tag + start as u64);
+            Duration::from_secs_f64(audio as f64 * 8.0 / (frame.bitrate_kbps as f64 * 1000.0))
+        }
+    }))
+}
+
+/// What an MP3 frame header says about the stream
+struct Mp3Frame {
+    mpeg1: bool,
+    mono: bool,
+    bitrate_kbps: u32,
+    sample_rate: u32,
+    /// Samples per frame
+    samples: u32,
+}
+
+impl Mp3Frame {
+    /// A Layer III frame header, None for anything else
+    fn parse(header: &[u8]) -> Option<Self> {
+        if header[0] != 0xff || header[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+        let version = (header[1] >> 3) & 0b11; // 3 MPEG-1, 2 MPEG-2, 0 MPEG-2.5
+        let layer = (header[1] >> 1) & 0b11; // 1 Layer III
+        if version == 1 || layer != 1 {
+            return None;
+        }
+        let mpeg1 = version == 3;
+        const MPEG1_BITRATES: [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+        const MPEG2_BITRATES: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+        let bitrates = if mpeg1 { &MPEG1_BITRATES } else { &MPEG2_BITRATES };
+        let bitrate_kbps = *bitrates.get((header[2] >> 4) as usize).filter(|&&b| b > 0)?;
+        let base_rate = *[44100, 48000, 32000].get(((header[2] >> 2) & 0b11) as usize)?;
+        let sample_rate = match version {
+            3 => base_rate,
+            2 => base_rate / 2,
+            _ => base_rate / 4,
+        };
+        Some(Mp3Frame { mpeg1, mono: header[3] >> 6 == 0b11, bitrate_kbps, sample_rate, samples: if mpeg1 { 1152 } else { 576 } })
+    }
+
+    /// Frame count of a VBR stream, from the Xing ("Xing" or "Info") or VBRI header in `frame`
+    fn vbr_frames(&self, frame: &[u8]) -> Option<u32> {
+        let side_info = match (self.mpeg1, self.mono) {
+            (true, false) => 32,
+            (true, true) | (false, false) => 17,
+            (false, true) => 9,
+        };
+        let be = |at: usize| frame.get(at..at + 4).map(|b| u32::from_be_bytes(b.try_into().unwrap_or_default()));
+        let xing = 4 + side_info;
+        match frame.get(xing..xing + 4) {
+            Some(b"Xing") | Some(b"Info") if be(xing + 4)? & 1 != 0 => return be(xing + 8),
+            _ => {}
+        }
+        (frame.get(36..40) == Some(b"VBRI")).then(|| be(36 + 14)).flatten()
+    }
+}
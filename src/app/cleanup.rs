@@ -0,0 +1,218 @@
+//! The cleanup assistant: the largest files, books never opened, and formats a book has more
+//! of than it needs, with the marked ones pruned in one go
+//!
+//! Pruning a file removes that format from its book; pruning a stale book deletes it.
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet};
+
+use crate::config::CleanupConfig;
+use crate::utils::time::parse_calibre_timestamp;
+
+/// A file of the library, as `Database::load_library_files` lists them
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryFile {
+    pub book_id: i32,
+    pub title: String,
+    /// When the book was added, as calibre stores it
+    pub added: String,
+    pub format: String,
+    pub bytes: u64,
+}
+
+/// The lists of the assistant, one shown at a time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CleanupSection {
+    #[default]
+    Largest,
+    Stale,
+    ExtraFormats,
+}
+
+impl CleanupSection {
+    pub const ALL: [CleanupSection; 3] = [CleanupSection::Largest, CleanupSection::Stale, CleanupSection::ExtraFormats];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CleanupSection::Largest => "Largest files",
+            CleanupSection::Stale => "Never opened",
+            CleanupSection::ExtraFormats => "Extra formats",
+        }
+    }
+
+    fn index(&self) -> usize {
+        CleanupSection::ALL.iter().position(|s| s == self).unwrap_or(0)
+    }
+
+    pub fn next(&self) -> Self {
+        CleanupSection::ALL[(self.index() + 1) % CleanupSection::ALL.len()]
+    }
+}
+
+/// Something that could go, with why it's suggested
+#[derive(Debug, Clone, PartialEq)]
+pub struct CleanupItem {
+    pub book_id: i32,
+    pub title: String,
+    /// The format to remove, None to delete the whole book
+    pub format: Option<String>,
+    pub bytes: u64,
+    pub reason: String,
+    pub marked: bool,
+}
+
+impl CleanupItem {
+    /// e.g. "Dune (PDF)" or "Dune"
+    pub fn label(&self) -> String {
+        match &self.format {
+            Some(format) => format!("{} ({})", self.title, format),
+            None => self.title.clone(),
+        }
+    }
+}
+
+/// The assistant's lists and where the user is in them
+#[derive(Debug, Clone, Default)]
+pub struct CleanupView {
+    pub section: CleanupSection,
+    pub lists: [Vec<CleanupItem>; 3],
+    pub selected: [usize; 3],
+    /// The user was asked to confirm pruning the marked items
+    pub pending_prune: bool,
+}
+
+impl CleanupView {
+    /// Suggest what to prune from every file of the library
+    ///
+    /// `opened` holds the books opened, read or being read in tuilibre; none of those is stale.
+    pub fn suggest(files: &[LibraryFile], opened: &HashSet<i32>, config: &CleanupConfig, now: DateTime<Utc>) -> Self {
+        let mut view = CleanupView::default();
+
+        let mut largest: Vec<&LibraryFile> = files.iter().collect();
+        largest.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.book_id.cmp(&b.book_id)));
+        let formats_of = formats_per_book(files);
+        view.lists[CleanupSection::Largest.index()] = largest
+            .into_iter()
+            .take(config.largest)
+            .map(|file| {
+                let only = formats_of.get(&file.book_id).is_some_and(|formats| formats.len() == 1);
+                let reason = if only { "the book's only file".to_string() } else { String::new() };
+                item(file.book_id, &file.title, Some(&file.format), file.bytes, reason)
+            })
+            .collect();
+
+        // Whole books, oldest first, with the size of all their files
+        let cutoff = now - Duration::days(config.stale_days.max(0));
+        let mut stale: Vec<(DateTime<Utc>, CleanupItem)> = Vec::new();
+        for (book_id, book_files) in &formats_of {
+            let first = book_files[0];
+            let Some(added) = parse_calibre_timestamp(&first.added) else {
+                continue;
+            };
+            if opened.contains(book_id) || added > cutoff {
+                continue;
+            }
+            let bytes = book_files.iter().map(|f| f.bytes).sum();
+            let reason = format!("added {}, never opened", added.format("%Y-%m-%d"));
+            stale.push((added, item(*book_id, &first.title, None, bytes, reason)));
+        }
+        stale.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.book_id.cmp(&b.1.book_id)));
+        view.lists[CleanupSection::Stale.index()] = stale.into_iter().map(|(_, item)| item).collect();
+
+        let mut extra: Vec<CleanupItem> = Vec::new();
+        for book_files in formats_of.values().filter(|f| f.len() > 1) {
+            let keep = keep_format(book_files, &config.keep_formats);
+            for file in book_files.iter().filter(|f| f.format != keep) {
+                extra.push(item(file.book_id, &file.title, Some(&file.format), file.bytes, format!("also has {}", keep)));
+            }
+        }
+        extra.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.book_id.cmp(&b.book_id)));
+        view.lists[CleanupSection::ExtraFormats.index()] = extra;
+
+        view
+    }
+
+    pub fn items(&self) -> &[CleanupItem] {
+        &self.lists[self.section.index()]
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.selected[self.section.index()]
+    }
+
+    pub fn selected_item(&self) -> Option<&CleanupItem> {
+        self.items().get(self.cursor())
+    }
+
+    pub fn count(&self, section: CleanupSection) -> usize {
+        self.lists[section.index()].len()
+    }
+
+    pub fn move_by(&mut self, delta: isize) {
+        let i = self.section.index();
+        let last = self.lists[i].len().saturating_sub(1);
+        self.selected[i] = self.selected[i].saturating_add_signed(delta).min(last);
+    }
+
+    /// Mark or unmark the item under the cursor
+    pub fn toggle_mark(&mut self) {
+        let i = self.section.index();
+        if let Some(item) = self.lists[i].get_mut(self.selected[i]) {
+            item.marked = !item.marked;
+        }
+    }
+
+    /// Mark every item of the list shown, or unmark them if they all were
+    pub fn toggle_all(&mut self) {
+        let list = &mut self.lists[self.section.index()];
+        let mark = !list.iter().all(|item| item.marked);
+        for item in list {
+            item.marked = mark;
+        }
+    }
+
+    /// The marked items of the list shown
+    pub fn marked(&self) -> Vec<CleanupItem> {
+        self.items().iter().filter(|item| item.marked).cloned().collect()
+    }
+
+    /// Drop pruned items from every list; a deleted book takes all its items with it
+    pub fn remove(&mut self, pruned: &[CleanupItem]) {
+        let deleted: HashSet<i32> = pruned.iter().filter(|p| p.format.is_none()).map(|p| p.book_id).collect();
+        let removed: HashSet<(i32, &str)> = pruned
+            .iter()
+            .filter_map(|p| p.format.as_deref().map(|format| (p.book_id, format)))
+            .collect();
+        for (i, list) in self.lists.iter_mut().enumerate() {
+            list.retain(|item| {
+                !deleted.contains(&item.book_id)
+                    && !item.format.as_deref().is_some_and(|format| removed.contains(&(item.book_id, format)))
+            });
+            self.selected[i] = self.selected[i].min(list.len().saturating_sub(1));
+        }
+    }
+}
+
+fn item(book_id: i32, title: &str, format: Option<&str>, bytes: u64, reason: String) -> CleanupItem {
+    CleanupItem { book_id, title: title.to_string(), format: format.map(str::to_string), bytes, reason, marked: false }
+}
+
+/// Each book's files in the order they were added
+fn formats_per_book(files: &[LibraryFile]) -> HashMap<i32, Vec<&LibraryFile>> {
+    let mut books: HashMap<i32, Vec<&LibraryFile>> = HashMap::new();
+    for file in files {
+        books.entry(file.book_id).or_default().push(file);
+    }
+    books
+}
+
+/// The format of a book to keep: the first of `preferred` it has, or else the one added first
+fn keep_format(files: &[&LibraryFile], preferred: &[String]) -> String {
+    preferred
+        .iter()
+        .find(|wanted| files.iter().any(|f| f.format.eq_ignore_ascii_case(wanted)))
+        .and_then(|wanted| files.iter().find(|f| f.format.eq_ignore_ascii_case(wanted)))
+        .unwrap_or(&files[0])
+        .format
+        .clone()
+}
@@ -0,0 +1,172 @@
+//! Comic book archives: CBZ (zip) and CBR (rar) files of page images, flipped through in
+//! `AppMode::Comic`
+//!
+//! CBZ is read directly; CBR needs `unrar` or `bsdtar` on the PATH, since there is no rar
+//! reader to build in.
+
+use std::cmp::Ordering;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::AppMode;
+use crate::error::{Context, Error, Result};
+
+/// Formats holding a comic's pages, best first
+pub const COMIC_FORMATS: [&str; 2] = ["CBZ", "CBR"];
+
+/// Extensions of the entries taken as pages; anything else (ComicInfo.xml, thumbs) is skipped
+const PAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "gif", "webp"];
+
+/// Programs that can list and extract a CBR, tried in order
+const RAR_TOOLS: [&str; 2] = ["unrar", "bsdtar"];
+
+/// The pages of a comic, in reading order
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComicArchive {
+    pub path: PathBuf,
+    /// Entry names of the page images
+    pub pages: Vec<String>,
+    rar: bool,
+}
+
+impl ComicArchive {
+    /// List the pages of a CBZ or CBR, sorted so "page2" comes before "page10"
+    pub fn open(path: &Path) -> Result<Self> {
+        let rar = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("cbr"));
+        let names = if rar { rar_entries(path)? } else { zip_entries(path)? };
+        let mut pages: Vec<String> = names.into_iter().filter(|name| is_page(name)).collect();
+        pages.sort_by(|a, b| natural_cmp(&a.to_lowercase(), &b.to_lowercase()));
+        if pages.is_empty() {
+            return Err(invalid_comic(path, "no page images"));
+        }
+        Ok(ComicArchive { path: path.to_path_buf(), pages, rar })
+    }
+
+    /// The image file of page `index`, as stored in the archive
+    pub fn page_bytes(&self, index: usize) -> Result<Vec<u8>> {
+        let name = self.pages.get(index).ok_or_else(|| Error::Other(format!("No page {} in {}", index + 1, self.path.display())))?;
+        if self.rar {
+            return rar_extract(&self.path, name);
+        }
+        let file = std::fs::File::open(&self.path).with_context(|| format!("Failed to open {}", self.path.display()))?;
+        let mut archive = zip::ZipArchive::new(file).map_err(|e| invalid_comic(&self.path, e))?;
+        let mut bytes = Vec::new();
+        archive
+            .by_name(name)
+            .map_err(|e| invalid_comic(&self.path, e))?
+            .read_to_end(&mut bytes)
+            .with_context(|| format!("Failed to read {} from {}", name, self.path.display()))?;
+        Ok(bytes)
+    }
+
+    /// Key the decoded page is cached under, e.g. "…/Watchmen.cbz#3"
+    pub fn page_key(&self, index: usize) -> PathBuf {
+        PathBuf::from(format!("{}#{}", self.path.display(), index))
+    }
+}
+
+/// A comic open in `AppMode::Comic`
+#[derive(Debug, Clone)]
+pub struct ComicViewer {
+    pub book_id: i32,
+    pub title: String,
+    pub archive: ComicArchive,
+    pub page: usize,
+    /// Mode to go back to when the viewer is closed
+    pub return_to: AppMode,
+}
+
+impl ComicViewer {
+    pub fn new(book_id: i32, title: String, archive: ComicArchive, return_to: AppMode) -> Self {
+        ComicViewer { book_id, title, archive, page: 0, return_to }
+    }
+
+    /// Flip `delta` pages, stopping at the first and last
+    pub fn flip(&mut self, delta: isize) {
+        let last = self.archive.pages.len().saturating_sub(1);
+        self.page = self.page.saturating_add_signed(delta).min(last);
+    }
+
+    pub fn go_to(&mut self, page: usize) {
+        self.page = page.min(self.archive.pages.len().saturating_sub(1));
+    }
+}
+
+fn is_page(name: &str) -> bool {
+    let file = name.rsplit('/').next().unwrap_or(name);
+    // macOS resource forks ride along in many zips as "__MACOSX/._page1.jpg"
+    !file.starts_with('.')
+        && !name.starts_with("__MACOSX/")
+        && file
+            .rsplit_once('.')
+            .is_some_and(|(_, ext)| PAGE_EXTENSIONS.iter().any(|page| ext.eq_ignore_ascii_case(page)))
+}
+
+fn zip_entries(path: &Path) -> Result<Vec<String>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let archive = zip::ZipArchive::new(file).map_err(|e| invalid_comic(path, e))?;
+    Ok(archive.file_names().map(str::to_string).collect())
+}
+
+/// Entry names of a CBR, from the first tool that is installed
+fn rar_entries(path: &Path) -> Result<Vec<String>> {
+    let output = run_rar_tool(path, |tool| match tool {
+        "unrar" => vec!["lb".into(), path.as_os_str().to_owned()],
+        _ => vec!["-tf".into(), path.as_os_str().to_owned()],
+    })?;
+    Ok(String::from_utf8_lossy(&output).lines().map(|line| line.replace('\\', "/")).collect())
+}
+
+fn rar_extract(path: &Path, name: &str) -> Result<Vec<u8>> {
+    run_rar_tool(path, |tool| match tool {
+        "unrar" => vec!["p".into(), "-inul".into(), path.as_os_str().to_owned(), name.into()],
+        _ => vec!["-xOf".into(), path.as_os_str().to_owned(), name.into()],
+    })
+}
+
+/// Run the first of `RAR_TOOLS` found with the arguments `args` gives it, returning its output
+fn run_rar_tool(path: &Path, args: impl Fn(&str) -> Vec<std::ffi::OsString>) -> Result<Vec<u8>> {
+    for tool in RAR_TOOLS {
+        let output = match Command::new(tool).args(args(tool)).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(Error::Io { context: format!("Failed to run {}", tool), source: e }),
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let last_line = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("");
+            return Err(invalid_comic(path, format!("{} exited with {}: {}", tool, output.status, last_line.trim())));
+        }
+        return Ok(output.stdout);
+    }
+    Err(Error::Other(format!("Reading CBR files needs {} installed", RAR_TOOLS.join(" or "))))
+}
+
+/// Compare with runs of digits taken as numbers
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let split = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+                let (na, nb) = (split(a), split(b));
+                let (da, db) = (a[..na].trim_start_matches('0'), b[..nb].trim_start_matches('0'));
+                let order = da.len().cmp(&db.len()).then_with(|| da.cmp(db));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                (a, b) = (&a[na..], &b[nb..]);
+            }
+            (Some(x), Some(y)) if x != y => return x.cmp(&y),
+            (Some(x), Some(_)) => (a, b) = (&a[x.len_utf8()..], &b[x.len_utf8()..]),
+        }
+    }
+}
+
+fn invalid_comic(path: &Path, error: impl std::fmt::Display) -> Error {
+    Error::Parse { context: format!("Invalid comic: {}", path.display()), message: error.to_string() }
+}
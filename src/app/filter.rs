@@ -0,0 +1,593 @@
+//! Book filtering: a free-text query and structured filters combined with AND
+
+use crate::error::Result;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use super::index::SearchIndex;
+use crate::settings::ReadStatus;
+use super::Book;
+use crate::database::Database;
+
+/// A structured filter on the book list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    /// Free text matched like the search bar
+    Text(String),
+    /// Part of the title, in any case
+    Title(String),
+    /// Part of an author's name, in any case
+    Author(String),
+    /// Part of the series name, in any case
+    Series(String),
+    Tag(String),
+    Format(String),
+    /// ISO 639 language code
+    Language(String),
+    /// At least this many stars (1-5)
+    Rating(u8),
+    /// Added to the library within the date range, either end open
+    Added { from: Option<NaiveDate>, to: Option<NaiveDate> },
+    /// Published within the date range, either end open
+    Published { from: Option<NaiveDate>, to: Option<NaiveDate> },
+    /// A numeric field compared to a value, e.g. `size:>10mb`; books without the field never match
+    Number { field: NumberField, comparison: Comparison, value: f64 },
+    /// On the named tuilibre shelf
+    Shelf(String),
+    /// Has a cover, or with `false` lacks one
+    Cover(bool),
+    /// Marked read in tuilibre, or with `false` not (yet) finished
+    Read(bool),
+    /// Has an audio file (M4B, M4A or MP3), or with `false` lacks one: the ebooks
+    Audiobook(bool),
+    /// Has a file verify found locked by DRM, or with `false` none
+    Drm(bool),
+    /// The phrase is in the book's text, as indexed by "Index book contents"
+    Content(String),
+}
+
+impl Filter {
+    /// Short text shown on the filter's chip, e.g. "tag:fantasy"
+    pub fn label(&self) -> String {
+        match self {
+            Filter::Text(text) => format!("\"{}\"", text),
+            Filter::Title(text) => format!("title:{}", text),
+            Filter::Author(text) => format!("author:{}", text),
+            Filter::Series(text) => format!("series:{}", text),
+            Filter::Tag(tag) => format!("tag:{}", tag),
+            Filter::Format(format) => format!("format:{}", format),
+            Filter::Language(code) => format!("lang:{}", code),
+            Filter::Rating(stars) => format!("rating:{}+", stars),
+            Filter::Shelf(name) => format!("shelf:{}", name),
+            Filter::Cover(has) => format!("cover:{}", has),
+            Filter::Read(read) => format!("read:{}", read),
+            Filter::Audiobook(audio) => format!("audiobook:{}", audio),
+            Filter::Drm(drm) => format!("drm:{}", drm),
+            Filter::Content(phrase) => format!("content:\"{}\"", phrase),
+            Filter::Added { from, to } => format!("added:{}", date_range_label(*from, *to)),
+            Filter::Published { from, to } => format!("pubdate:{}", date_range_label(*from, *to)),
+            Filter::Number { field, comparison, value } => {
+                format!("{}:{}{}", field.name(), comparison.symbol(), field.format(*value))
+            }
+        }
+    }
+}
+
+fn date_range_label(from: Option<NaiveDate>, to: Option<NaiveDate>) -> String {
+    format!(
+        "{}..{}",
+        from.map(|d| d.to_string()).unwrap_or_default(),
+        to.map(|d| d.to_string()).unwrap_or_default()
+    )
+}
+
+/// A numeric book field searchable with comparisons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NumberField {
+    /// Stars, 0-5 in half steps
+    Rating,
+    /// Bytes of the largest format
+    Size,
+    SeriesIndex,
+}
+
+impl NumberField {
+    /// The field's name in searches
+    pub fn name(self) -> &'static str {
+        match self {
+            NumberField::Rating => "rating",
+            NumberField::Size => "size",
+            NumberField::SeriesIndex => "series_index",
+        }
+    }
+
+    /// Read a search value, e.g. "10mb" for a size
+    fn parse(self, value: &str) -> Option<f64> {
+        match self {
+            NumberField::Size => parse_size(value),
+            NumberField::Rating | NumberField::SeriesIndex => value.parse().ok(),
+        }
+        .filter(|v: &f64| v.is_finite())
+    }
+
+    /// A value as it would be typed in a search
+    fn format(self, value: f64) -> String {
+        match self {
+            NumberField::Size => {
+                let unit = SIZE_UNITS.iter().rev().find(|(_, bytes)| value >= *bytes && value % bytes == 0.0);
+                match unit {
+                    Some((suffix, bytes)) => format!("{}{}", value / bytes, suffix),
+                    None => value.to_string(),
+                }
+            }
+            NumberField::Rating | NumberField::SeriesIndex => value.to_string(),
+        }
+    }
+}
+
+/// calibre's size suffixes, in bytes
+const SIZE_UNITS: [(&str, f64); 3] = [("kb", 1024.0), ("mb", 1024.0 * 1024.0), ("gb", 1024.0 * 1024.0 * 1024.0)];
+
+/// Bytes in "10mb", "1.5g", "300k" or plain "2048"
+fn parse_size(value: &str) -> Option<f64> {
+    let value = value.to_lowercase();
+    for (suffix, bytes) in SIZE_UNITS {
+        let short = &suffix[..1];
+        if let Some(number) = value.strip_suffix(suffix).or_else(|| value.strip_suffix(short)) {
+            return number.parse::<f64>().ok().map(|n| n * bytes);
+        }
+    }
+    value.strip_suffix('b').unwrap_or(&value).parse().ok()
+}
+
+/// How a field compares to the value in a search like `rating:>=4`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Less,
+    AtMost,
+    Equal,
+    AtLeast,
+    Greater,
+}
+
+impl Comparison {
+    /// Split the operator off a search value; no operator means equal
+    fn split(value: &str) -> (Comparison, &str) {
+        for (symbol, comparison) in [
+            ("<=", Comparison::AtMost),
+            (">=", Comparison::AtLeast),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ] {
+            if let Some(rest) = value.strip_prefix(symbol) {
+                return (comparison, rest);
+            }
+        }
+        (Comparison::Equal, value)
+    }
+
+    /// The operator in searches and in SQL
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::AtMost => "<=",
+            Comparison::Equal => "=",
+            Comparison::AtLeast => ">=",
+            Comparison::Greater => ">",
+        }
+    }
+
+    /// The date range matching a comparison against the days `first..=last`
+    fn date_range(self, first: NaiveDate, last: NaiveDate) -> (Option<NaiveDate>, Option<NaiveDate>) {
+        match self {
+            Comparison::Less => (None, first.pred_opt()),
+            Comparison::AtMost => (None, Some(last)),
+            Comparison::Equal => (Some(first), Some(last)),
+            Comparison::AtLeast => (Some(first), None),
+            Comparison::Greater => (last.succ_opt(), None),
+        }
+    }
+}
+
+/// A filter as applied to the list, optionally negated ("not tagged fantasy")
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterClause {
+    pub filter: Filter,
+    pub negated: bool,
+}
+
+impl FilterClause {
+    pub fn new(filter: Filter) -> Self {
+        FilterClause { filter, negated: false }
+    }
+
+    pub fn label(&self) -> String {
+        if self.negated {
+            format!("-{}", self.filter.label())
+        } else {
+            self.filter.label()
+        }
+    }
+}
+
+/// Everything that narrows the book list; a book must match the text and every clause
+#[derive(Debug, Clone, Default)]
+pub struct BookQuery {
+    /// Matched case-insensitively against title, authors, tags and path
+    pub text: String,
+    pub clauses: Vec<FilterClause>,
+}
+
+impl BookQuery {
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.clauses.is_empty()
+    }
+
+    /// Whether every part can be checked against loaded `Book`s, without the database
+    pub fn runs_in_memory(&self) -> bool {
+        self.clauses.iter().all(|c| {
+            matches!(
+                c.filter,
+                Filter::Text(_)
+                    | Filter::Title(_)
+                    | Filter::Author(_)
+                    | Filter::Tag(_)
+                    | Filter::Added { .. }
+                    | Filter::Shelf(_)
+                    | Filter::Cover(_)
+                    | Filter::Read(_)
+                    | Filter::Audiobook(_)
+                    | Filter::Drm(_)
+            )
+        })
+    }
+
+    /// Books from `books` that match, in their original order
+    ///
+    /// Filters on data `Book` doesn't carry (formats, languages, ratings, sizes) are evaluated in SQL,
+    /// as is everything while some books are loaded without their authors and tags; shelves and
+    /// reading status always in memory, and the books' text in the content index.
+    #[tracing::instrument(skip_all, fields(text = %self.text, clauses = self.clauses.len(), books = books.len()))]
+    pub async fn run(&self, books: &[Book], database: &Database) -> Result<Vec<Book>> {
+        if self.is_empty() {
+            return Ok(books.to_vec());
+        }
+
+        if self.runs_in_memory() && books.iter().all(|b| b.details_loaded) {
+            return Ok(books.iter().filter(|b| self.matches(b)).cloned().collect());
+        }
+
+        let ids = database.query_book_ids(self).await?;
+        Ok(books
+            .iter()
+            .filter(|b| ids.contains(&b.id) && self.matches_local(b))
+            .cloned()
+            .collect())
+    }
+
+    /// Like `run`, but finds text matches through `index`, built over the same `books`
+    ///
+    /// Only queries with a filter on data `Book` doesn't carry touch the database.
+    #[tracing::instrument(skip_all, fields(text = %self.text, clauses = self.clauses.len(), books = books.len()))]
+    pub async fn run_indexed(&self, books: &[Book], index: &SearchIndex, database: &Database) -> Result<Vec<Book>> {
+        if !self.runs_in_memory() || index.len() != books.len() || books.iter().any(|b| !b.details_loaded) {
+            return self.run(books, database).await;
+        }
+
+        Ok(index
+            .matching(&self.text)
+            .into_iter()
+            .map(|i| &books[i])
+            .filter(|b| self.matches_clauses(b))
+            .cloned()
+            .collect())
+    }
+
+    /// In-memory match; only meaningful when `runs_in_memory` is true
+    pub fn matches(&self, book: &Book) -> bool {
+        matches_text(book, &self.text) && self.matches_clauses(book)
+    }
+
+    fn matches_clauses(&self, book: &Book) -> bool {
+        self.clauses.iter().all(|clause| {
+            let hit = match &clause.filter {
+                Filter::Text(text) => matches_text(book, text),
+                Filter::Title(text) => contains_ignoring_case(&book.title, text),
+                Filter::Author(text) => book.authors.iter().any(|a| contains_ignoring_case(a, text)),
+                Filter::Tag(tag) => book.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)),
+                Filter::Added { from, to } => match added_date(book) {
+                    Some(date) => from.is_none_or(|f| date >= f) && to.is_none_or(|t| date <= t),
+                    None => false,
+                },
+                Filter::Shelf(name) => book.shelves.contains(name),
+                Filter::Cover(has) => book.has_cover == *has,
+                Filter::Read(read) => (book.status == Some(ReadStatus::Read)) == *read,
+                Filter::Audiobook(audio) => book.is_audiobook() == *audio,
+                Filter::Drm(drm) => book.has_drm() == *drm,
+                // Not in memory; `run` sends these queries to SQL
+                Filter::Series(_)
+                | Filter::Format(_)
+                | Filter::Language(_)
+                | Filter::Rating(_)
+                | Filter::Published { .. }
+                | Filter::Number { .. }
+                | Filter::Content(_) => true,
+            };
+            hit != clause.negated
+        })
+    }
+
+    /// The clauses on tuilibre's own state alone, which SQL knows nothing about
+    fn matches_local(&self, book: &Book) -> bool {
+        self.clauses.iter().all(|clause| match &clause.filter {
+            Filter::Shelf(name) => book.shelves.contains(name) != clause.negated,
+            Filter::Read(read) => ((book.status == Some(ReadStatus::Read)) == *read) != clause.negated,
+            Filter::Drm(drm) => (book.has_drm() == *drm) != clause.negated,
+            _ => true,
+        })
+    }
+}
+
+/// Case-insensitive match against title, authors, tags and path; empty text matches everything
+fn matches_text(book: &Book, text: &str) -> bool {
+    if text.is_empty() {
+        return true;
+    }
+    let needle = text.to_lowercase();
+    let contains = |s: &str| s.to_lowercase().contains(&needle);
+
+    contains(&book.title)
+        || book.authors.iter().any(|a| contains(a))
+        || book.tags.iter().any(|t| contains(t))
+        || contains(&book.path)
+}
+
+fn contains_ignoring_case(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+/// Date part of calibre's "2024-01-11 10:00:00+00:00" timestamp
+fn added_date(book: &Book) -> Option<NaiveDate> {
+    book.timestamp.get(..10).and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+}
+
+/// Turn a calibre search expression (as stored for virtual libraries) into filter clauses
+///
+/// Understands `title:`, `authors:` and `series:` (matching part of the field), `tags:`,
+/// `formats:`, `languages:`, `cover:` and tuilibre's own `shelf:`, `read:` and
+/// `content:"exact phrase"` terms, comparisons on `rating:`, `size:`, `series_index:`, `date:` and `pubdate:`
+/// like `size:>10mb` or `date:<2023-01-01`, `not` and the implicit `and` between terms.
+/// Anything else, including other fields, is matched as free text; `or` and parentheses are not
+/// supported.
+pub fn parse_calibre_search(expression: &str) -> Vec<FilterClause> {
+    parse_search(expression).0
+}
+
+/// The first problem in a search expression, which `parse_calibre_search` would quietly treat as
+/// free text
+pub fn check_calibre_search(expression: &str) -> Option<SearchError> {
+    parse_search(expression).1
+}
+
+/// Where and why a search expression couldn't be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchError {
+    /// Character offset of the offending term in the expression
+    pub position: usize,
+    pub message: String,
+}
+
+impl SearchError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        SearchError { position, message: message.into() }
+    }
+}
+
+/// The clauses, falling back to free text wherever a term doesn't parse, and the first error
+fn parse_search(expression: &str) -> (Vec<FilterClause>, Option<SearchError>) {
+    let mut clauses = Vec::new();
+    let mut negate = None;
+    let mut errors = Vec::new();
+
+    let (tokens, unclosed_quote) = tokenize(expression);
+    if let Some(position) = unclosed_quote {
+        errors.push(SearchError::new(position, "Unclosed quote"));
+    }
+
+    for (position, token) in tokens {
+        match token.to_lowercase().as_str() {
+            "and" => continue,
+            "not" => {
+                negate = match negate {
+                    Some(_) => None,
+                    None => Some(position),
+                };
+                continue;
+            }
+            "or" => errors.push(SearchError::new(position, "\"or\" isn't supported; terms are always combined with and")),
+            _ if token.starts_with(['(', ')']) || token.ends_with(')') => {
+                errors.push(SearchError::new(position, "Parentheses aren't supported"))
+            }
+            _ => {}
+        }
+
+        let (term, negated) = match token.strip_prefix('-') {
+            Some(rest) if !rest.is_empty() => (rest.to_string(), negate.is_none()),
+            _ => (token, negate.is_some()),
+        };
+        negate = None;
+
+        let filter = match term.split_once(':') {
+            Some((field, value)) => {
+                let field = field.to_lowercase();
+                if let Some(filter) = parse_comparison(&field, value.trim_matches('"')) {
+                    clauses.push(FilterClause { filter, negated });
+                    continue;
+                }
+                if let Some(expected) = expected_value(&field) {
+                    errors.push(SearchError::new(position, format!("{}: expects {}", field, expected)));
+                }
+                let value = value.trim_start_matches('=').trim_matches('"').to_string();
+                let text_field = matches!(
+                    field.as_str(),
+                    "title" | "author" | "authors" | "series" | "tag" | "tags" | "format" | "formats" | "language"
+                        | "languages" | "shelf" | "content"
+                );
+                if text_field && value.is_empty() {
+                    errors.push(SearchError::new(position, format!("{}: needs a value", field)));
+                }
+                match field.as_str() {
+                    "title" => Filter::Title(value),
+                    "author" | "authors" => Filter::Author(value),
+                    "series" => Filter::Series(value),
+                    "tag" | "tags" => Filter::Tag(value),
+                    "format" | "formats" => Filter::Format(value.to_uppercase()),
+                    "language" | "languages" => Filter::Language(value),
+                    "shelf" => Filter::Shelf(value),
+                    "content" => Filter::Content(value),
+                    "cover" | "read" | "audiobook" | "drm" => match parse_bool(&value) {
+                        Some(yes) if field == "cover" => Filter::Cover(yes),
+                        Some(yes) if field == "audiobook" => Filter::Audiobook(yes),
+                        Some(yes) if field == "drm" => Filter::Drm(yes),
+                        Some(yes) => Filter::Read(yes),
+                        None => Filter::Text(value),
+                    },
+                    _ => Filter::Text(value),
+                }
+            }
+            None => Filter::Text(term.trim_matches('"').to_string()),
+        };
+        clauses.push(FilterClause { filter, negated });
+    }
+
+    if let Some(position) = negate {
+        errors.push(SearchError::new(position, "\"not\" needs a term after it"));
+    }
+    let first = errors.into_iter().min_by_key(|e| e.position);
+    (clauses, first)
+}
+
+/// What a typed field's value should look like, for fields whose values can be wrong
+fn expected_value(field: &str) -> Option<&'static str> {
+    match field {
+        "cover" | "read" | "audiobook" | "drm" => Some("true or false"),
+        "rating" | "series_index" => Some("a number, optionally after <, <=, =, >= or >"),
+        "size" => Some("a size like >10mb"),
+        "date" | "added" | "timestamp" | "pubdate" | "published" => {
+            Some("a date like <2023-01-01, 2023-05 or >30daysago")
+        }
+        _ => None,
+    }
+}
+
+/// calibre's spellings of yes and no in searches
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" => Some(true),
+        "false" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// A typed comparison such as `rating:>=4` or `added:<2023-01-01`, if `field` takes one and the
+/// value parses
+fn parse_comparison(field: &str, value: &str) -> Option<Filter> {
+    let (comparison, operand) = Comparison::split(value);
+    let number = |field: NumberField| {
+        let value = field.parse(operand)?;
+        Some(Filter::Number { field, comparison, value })
+    };
+
+    match field {
+        // Kept as the menu's "at least" filter, which the database can index
+        "rating" if comparison == Comparison::AtLeast && operand.parse::<u8>().is_ok_and(|s| (1..=5).contains(&s)) => {
+            operand.parse().ok().map(Filter::Rating)
+        }
+        "rating" => number(NumberField::Rating),
+        "size" => number(NumberField::Size),
+        "series_index" => number(NumberField::SeriesIndex),
+        "date" | "added" | "timestamp" | "pubdate" | "published" => {
+            let (first, last) = parse_date(operand)?;
+            let (from, to) = comparison.date_range(first, last);
+            Some(if field.starts_with("pub") {
+                Filter::Published { from, to }
+            } else {
+                Filter::Added { from, to }
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The days a search date covers: "2023-01-01", a whole "2023-01" or "2023", "today",
+/// "yesterday" or calibre's relative "30daysago"
+fn parse_date(value: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let today = chrono::Local::now().date_naive();
+    let value = value.to_lowercase();
+    let day = |date: NaiveDate| Some((date, date));
+
+    match value.as_str() {
+        "today" => return day(today),
+        "yesterday" => return day(today.pred_opt()?),
+        _ => {}
+    }
+    if let Some(days) = value.strip_suffix("daysago") {
+        return day(today - chrono::Duration::days(days.parse().ok()?));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(&value, "%Y-%m-%d") {
+        return day(date);
+    }
+
+    let mut parts = value.splitn(2, '-');
+    let year: i32 = parts.next()?.parse().ok()?;
+    match parts.next() {
+        Some(month) => {
+            let month: u32 = month.parse().ok()?;
+            let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+            let next = if month == 12 {
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(year, month + 1, 1)?
+            };
+            Some((first, next.pred_opt()?))
+        }
+        None => Some((NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year, 12, 31)?)),
+    }
+}
+
+/// Split on whitespace, keeping double-quoted runs together
+///
+/// Tokens come with the character offset they start at, along with the offset of a quote that
+/// is never closed.
+fn tokenize(expression: &str) -> (Vec<(usize, String)>, Option<usize>) {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+    let mut quote = None;
+
+    for (i, c) in expression.chars().enumerate() {
+        if current.is_empty() {
+            start = i;
+        }
+        match c {
+            '"' => {
+                quote = if quote.is_some() { None } else { Some(i) };
+                current.push(c);
+            }
+            c if c.is_whitespace() && quote.is_none() => {
+                if !current.is_empty() {
+                    tokens.push((start, std::mem::take(&mut current)));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push((start, current));
+    }
+    (tokens, quote)
+}
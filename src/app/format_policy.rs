@@ -0,0 +1,137 @@
+//! The format policy audit: books missing a format they should have, and formats kept next to
+//! a better one, as `[format_policy]` in the config defines them
+//!
+//! Books without any file aren't checked; there is nothing to convert them from.
+
+use std::collections::BTreeMap;
+
+use crate::app::LibraryFile;
+use crate::config::FormatPolicyConfig;
+
+/// How a book breaks the policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyBreach {
+    /// The book has no file in `format`, which every book should have
+    Missing { format: String },
+    /// The book's `format` file is redundant next to its `keep` file
+    Redundant { format: String, keep: String },
+}
+
+/// A book breaking the policy, once per rule it breaks
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyViolation {
+    pub book_id: i32,
+    pub title: String,
+    pub breach: PolicyBreach,
+    /// Size of the redundant file; 0 for a missing format
+    pub bytes: u64,
+    pub marked: bool,
+}
+
+impl PolicyViolation {
+    /// e.g. "no EPUB" or "MOBI next to EPUB"
+    pub fn describe(&self) -> String {
+        match &self.breach {
+            PolicyBreach::Missing { format } => format!("no {}", format),
+            PolicyBreach::Redundant { format, keep } => format!("{} next to {}", format, keep),
+        }
+    }
+}
+
+/// The audit's findings, missing formats first, and where the user is in them
+#[derive(Debug, Clone, Default)]
+pub struct PolicyAudit {
+    pub violations: Vec<PolicyViolation>,
+    pub selected: usize,
+    /// The user was asked to confirm deleting the marked redundant files
+    pub pending_delete: bool,
+}
+
+impl PolicyAudit {
+    /// Check every file of the library against the policy
+    pub fn run(files: &[LibraryFile], policy: &FormatPolicyConfig) -> Self {
+        // Each book's formats, upper case as calibre stores them, with their sizes
+        let mut books: BTreeMap<i32, (&str, BTreeMap<String, u64>)> = BTreeMap::new();
+        for file in files {
+            let (_, formats) = books.entry(file.book_id).or_insert((&file.title, BTreeMap::new()));
+            formats.insert(file.format.to_uppercase(), file.bytes);
+        }
+
+        let mut missing = Vec::new();
+        let mut redundant = Vec::new();
+        for (&book_id, (title, formats)) in &books {
+            for format in policy.required.iter().map(|f| f.to_uppercase()) {
+                if !formats.contains_key(&format) {
+                    missing.push(violation(book_id, title, PolicyBreach::Missing { format }, 0));
+                }
+            }
+            for rule in &policy.redundant {
+                let (keep, drop) = (rule.keep.to_uppercase(), rule.drop.to_uppercase());
+                if let (true, Some(&bytes)) = (formats.contains_key(&keep), formats.get(&drop)) {
+                    redundant.push(violation(book_id, title, PolicyBreach::Redundant { format: drop, keep }, bytes));
+                }
+            }
+        }
+        missing.sort_by_key(|v| v.title.to_lowercase());
+        redundant.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.book_id.cmp(&b.book_id)));
+
+        PolicyAudit { violations: missing.into_iter().chain(redundant).collect(), selected: 0, pending_delete: false }
+    }
+
+    pub fn selected_violation(&self) -> Option<&PolicyViolation> {
+        self.violations.get(self.selected)
+    }
+
+    pub fn move_by(&mut self, delta: isize) {
+        let last = self.violations.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    /// Mark or unmark the violation under the cursor
+    pub fn toggle_mark(&mut self) {
+        if let Some(violation) = self.violations.get_mut(self.selected) {
+            violation.marked = !violation.marked;
+        }
+    }
+
+    /// Mark every violation, or unmark them if they all were
+    pub fn toggle_all(&mut self) {
+        let mark = !self.violations.iter().all(|v| v.marked);
+        for violation in &mut self.violations {
+            violation.marked = mark;
+        }
+    }
+
+    /// The marked redundant files, as book id, format and size
+    pub fn marked_redundant(&self) -> Vec<(i32, String, u64)> {
+        self.violations
+            .iter()
+            .filter(|v| v.marked)
+            .filter_map(|v| match &v.breach {
+                PolicyBreach::Redundant { format, .. } => Some((v.book_id, format.clone(), v.bytes)),
+                PolicyBreach::Missing { .. } => None,
+            })
+            .collect()
+    }
+
+    /// The books marked as missing a format, by the format they're missing
+    pub fn marked_missing(&self) -> BTreeMap<String, Vec<i32>> {
+        let mut missing: BTreeMap<String, Vec<i32>> = BTreeMap::new();
+        for violation in self.violations.iter().filter(|v| v.marked) {
+            if let PolicyBreach::Missing { format } = &violation.breach {
+                missing.entry(format.clone()).or_default().push(violation.book_id);
+            }
+        }
+        missing
+    }
+
+    /// Drop the violations that `fixed` says were dealt with
+    pub fn remove(&mut self, fixed: impl Fn(&PolicyViolation) -> bool) {
+        self.violations.retain(|v| !fixed(v));
+        self.selected = self.selected.min(self.violations.len().saturating_sub(1));
+    }
+}
+
+fn violation(book_id: i32, title: &str, breach: PolicyBreach, bytes: u64) -> PolicyViolation {
+    PolicyViolation { book_id, title: title.to_string(), breach, bytes, marked: false }
+}
@@ -0,0 +1,65 @@
+//! Lowercased search index over the loaded books
+//!
+//! Built once per load so typing in the search bar filters `all_books` without lowercasing
+//! every title again or asking SQLite; field-prefixed queries still go through `BookQuery::run`.
+
+use super::Book;
+
+/// Separates a book's fields so a query can't match across the end of one and the start of the next
+const FIELD_SEPARATOR: char = '\u{0}';
+
+/// Lowercased title, authors, tags and path of each book, in `all_books` order
+#[derive(Debug, Clone, Default)]
+pub struct SearchIndex {
+    haystacks: Vec<String>,
+}
+
+impl SearchIndex {
+    pub fn build(books: &[Book]) -> Self {
+        SearchIndex { haystacks: books.iter().map(haystack).collect() }
+    }
+
+    /// Index books added after the end of `all_books`
+    pub fn extend(&mut self, books: &[Book]) {
+        self.haystacks.extend(books.iter().map(haystack));
+    }
+
+    /// Re-index the book at `position` in `all_books`, e.g. once its authors and tags are loaded
+    pub fn update(&mut self, position: usize, book: &Book) {
+        if let Some(entry) = self.haystacks.get_mut(position) {
+            *entry = haystack(book);
+        }
+    }
+
+    /// Number of books indexed; differs from `all_books` only if the index is out of date
+    pub fn len(&self) -> usize {
+        self.haystacks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.haystacks.is_empty()
+    }
+
+    /// Positions of the books matching `text` the way the search bar does; empty text matches all
+    pub fn matching(&self, text: &str) -> Vec<usize> {
+        if text.is_empty() {
+            return (0..self.haystacks.len()).collect();
+        }
+        let needle = text.to_lowercase();
+        self.haystacks
+            .iter()
+            .enumerate()
+            .filter(|(_, haystack)| haystack.contains(&needle))
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+fn haystack(book: &Book) -> String {
+    let mut haystack = book.title.to_lowercase();
+    for field in book.authors.iter().chain(&book.tags).chain(std::iter::once(&book.path)) {
+        haystack.push(FIELD_SEPARATOR);
+        haystack.push_str(&field.to_lowercase());
+    }
+    haystack
+}
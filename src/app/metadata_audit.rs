@@ -0,0 +1,135 @@
+//! The metadata audit: files whose embedded title, authors or series say something other than
+//! the library, each either pushed from the library into the file or pulled from the file into
+//! the library
+
+use crate::epub::FileMetadata;
+use crate::jobs::EmbedItem;
+
+/// A field the audit compares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditField {
+    Title,
+    Authors,
+    Series,
+}
+
+impl AuditField {
+    pub fn label(self) -> &'static str {
+        match self {
+            AuditField::Title => "Title",
+            AuditField::Authors => "Authors",
+            AuditField::Series => "Series",
+        }
+    }
+
+    /// The field's value in `metadata`, e.g. "Discworld #3", "-" when it has none
+    pub fn show(self, metadata: &FileMetadata) -> String {
+        let value = match self {
+            AuditField::Title => metadata.title.clone(),
+            AuditField::Authors => metadata.authors.join(", "),
+            AuditField::Series => metadata.series.as_ref().map(|(series, index)| format!("{} #{}", series, index)).unwrap_or_default(),
+        };
+        if value.is_empty() {
+            "-".to_string()
+        } else {
+            value
+        }
+    }
+
+    fn differs(self, library: &FileMetadata, file: &FileMetadata) -> bool {
+        match self {
+            AuditField::Title => library.title.trim() != file.title.trim(),
+            AuditField::Authors => {
+                let trimmed = |authors: &[String]| authors.iter().map(|a| a.trim().to_string()).collect::<Vec<_>>();
+                trimmed(&library.authors) != trimmed(&file.authors)
+            }
+            AuditField::Series => match (&library.series, &file.series) {
+                (Some((a, i)), Some((b, j))) => a.trim() != b.trim() || (i - j).abs() > 1e-6,
+                (a, b) => a.is_some() != b.is_some(),
+            },
+        }
+    }
+}
+
+const FIELDS: [AuditField; 3] = [AuditField::Title, AuditField::Authors, AuditField::Series];
+
+/// A file whose metadata differs from the library's
+#[derive(Debug, Clone)]
+pub struct MetadataMismatch {
+    /// The file, with the library's metadata to push into it
+    pub item: EmbedItem,
+    /// What the file says
+    pub file: FileMetadata,
+    /// The fields that differ, in order
+    pub fields: Vec<AuditField>,
+    pub marked: bool,
+}
+
+impl MetadataMismatch {
+    /// Compare what the file says with the library, None when they agree
+    pub fn compare(item: EmbedItem, file: FileMetadata) -> Option<Self> {
+        let fields: Vec<AuditField> = FIELDS.into_iter().filter(|field| field.differs(&item.metadata, &file)).collect();
+        (!fields.is_empty()).then_some(MetadataMismatch { item, file, fields, marked: false })
+    }
+
+    /// e.g. "title, series"
+    pub fn describe(&self) -> String {
+        let fields: Vec<String> = self.fields.iter().map(|f| f.label().to_lowercase()).collect();
+        fields.join(", ")
+    }
+
+    fn is(&self, other: &MetadataMismatch) -> bool {
+        self.item.book_id == other.item.book_id && self.item.format == other.item.format
+    }
+}
+
+/// The files found differing, in the order they were checked, and where the user is in them
+#[derive(Debug, Clone, Default)]
+pub struct MetadataAudit {
+    /// The books audited, to run it again
+    pub book_ids: Vec<i32>,
+    pub mismatches: Vec<MetadataMismatch>,
+    pub selected: usize,
+}
+
+impl MetadataAudit {
+    pub fn new(book_ids: Vec<i32>) -> Self {
+        MetadataAudit { book_ids, ..MetadataAudit::default() }
+    }
+
+    pub fn selected_mismatch(&self) -> Option<&MetadataMismatch> {
+        self.mismatches.get(self.selected)
+    }
+
+    pub fn move_by(&mut self, delta: isize) {
+        let last = self.mismatches.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    /// Mark or unmark the file under the cursor
+    pub fn toggle_mark(&mut self) {
+        if let Some(mismatch) = self.mismatches.get_mut(self.selected) {
+            mismatch.marked = !mismatch.marked;
+        }
+    }
+
+    /// Mark every file, or unmark them if they all were
+    pub fn toggle_all(&mut self) {
+        let mark = !self.mismatches.iter().all(|m| m.marked);
+        for mismatch in &mut self.mismatches {
+            mismatch.marked = mark;
+        }
+    }
+
+    /// Take the marked files out of the audit, or the one under the cursor when none are
+    pub fn take_chosen(&mut self) -> Vec<MetadataMismatch> {
+        let chosen: Vec<MetadataMismatch> = if self.mismatches.iter().any(|m| m.marked) {
+            self.mismatches.iter().filter(|m| m.marked).cloned().collect()
+        } else {
+            self.selected_mismatch().cloned().into_iter().collect()
+        };
+        self.mismatches.retain(|m| !chosen.iter().any(|c| c.is(m)));
+        self.selected = self.selected.min(self.mismatches.len().saturating_sub(1));
+        chosen
+    }
+}
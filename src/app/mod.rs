@@ -0,0 +1,1589 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+pub mod action;
+pub mod audiobook;
+pub mod cleanup;
+pub mod comic;
+pub mod format_policy;
+pub mod filter;
+pub mod index;
+pub mod metadata_audit;
+pub mod reader;
+pub mod sort;
+pub mod tag_browser;
+pub mod usage;
+
+pub use action::{Action, ActionSpec, ACTIONS};
+pub use cleanup::{CleanupItem, CleanupSection, CleanupView, LibraryFile};
+pub use comic::{ComicArchive, ComicViewer, COMIC_FORMATS};
+pub use format_policy::{PolicyAudit, PolicyBreach, PolicyViolation};
+pub use filter::{
+    check_calibre_search, parse_calibre_search, BookQuery, Comparison, Filter, FilterClause, NumberField, SearchError,
+};
+pub use index::SearchIndex;
+pub use metadata_audit::{AuditField, MetadataAudit, MetadataMismatch};
+pub use reader::{Annotation, Reader, ReadingPosition};
+pub use sort::{BookSort, SortKey};
+pub use tag_browser::{TagBrowser, TagCount};
+pub use usage::{format_size, UsageBook, UsageGroup, UsageGrouping, UsageView};
+
+use crate::config::Config;
+use crate::database::{BookChange, BookDetails, ContentMatch, DeletionPreview};
+use crate::device::{Device, DeviceBook};
+use crate::jobs::{ExportPlan, JobRecord, JobStatus};
+use crate::metadata::MetadataProposal;
+use crate::opds::{OpdsEntry, OpdsFeed};
+use crate::calibre_web::CalibreWebUser;
+use crate::queue::QueuedAction;
+use crate::settings::ReadStatus;
+use crate::shelves::Shelves;
+use crate::ui::layout::Density;
+use crate::ui::theme::Theme;
+use crate::utils::locale::Locale;
+use crate::utils::text::html_paragraphs;
+use crate::utils::time::{format_time, parse_calibre_timestamp};
+
+/// Above this many changed books, re-sorting the list beats inserting each one in place
+const PATCH_INSERT_LIMIT: usize = 64;
+
+/// A pause this long between letters starts a new type-ahead prefix
+pub const TYPE_AHEAD_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Application state following the MVP architecture
+#[derive(Debug, Clone)]
+pub struct App {
+    pub books: Vec<Book>,
+    pub all_books: Vec<Book>, // Store all books for search recovery
+    pub pages: Option<BookPages>, // How far a large library loaded a page at a time has got, None once it's all in
+    pub search_index: SearchIndex, // Lowercased text of `all_books`, kept in step by `set_all_books`
+    pub selected_book_index: usize,
+    pub search_query: String,
+    pub search_layers: Vec<String>, // Earlier searches the current one narrows, oldest first
+    pub search_plain: bool, // Take the search as plain text even if it has field terms
+    pub search_full_text: bool, // Search the text of the books through calibre's full-text index instead
+    pub fts_snippets: HashMap<i32, String>, // Where the full-text search found each listed book, shown in its row
+    pub content_matches: HashMap<i32, ContentMatch>, // Where a `content:` search found its phrase in each listed book
+    pub mode: AppMode,
+    pub library_path: PathBuf,
+    pub library_alias: Option<String>, // Name the user gave the library, shown in the title bar
+    pub config: Config,
+    pub selected_ids: HashSet<i32>, // Books marked for batch actions
+    pub visual_select: bool,        // Space marks books and Enter offers batch actions, see `Action::VisualSelect`
+    pub range_anchor: Option<i32>,  // Book the range being marked with `V` starts at
+    pub job: Option<JobStatus>,      // Background job shown in the status bar
+    pub status_message: Option<String>,
+    pub device_view: Option<DeviceView>,
+    pub jobs_view: Option<JobsView>, // Jobs run on this library, shown in `AppMode::Jobs`
+    pub usage_view: Option<UsageView>, // The library's files added up, shown in `AppMode::Usage`
+    pub cleanup_view: Option<CleanupView>, // Files and books suggested for pruning, shown in `AppMode::Cleanup`
+    pub policy_audit: Option<PolicyAudit>, // Books breaking the format policy, shown in `AppMode::FormatPolicy`
+    pub metadata_audit: Option<MetadataAudit>, // Files whose metadata differs from the library, shown in `AppMode::MetadataAudit`
+    pub on_device: HashSet<i32>,     // Library books found on the connected device
+    pub menu: Option<Menu>,          // Popup menu shown over the current view
+    pub prompt: Option<Prompt>,      // Text input shown over the current view
+    pub pending_delete: Option<DeleteConfirm>, // Book waiting for the user to confirm its deletion
+    pub opds_view: Option<OpdsView>,
+    pub review: ReviewQueue,         // Metadata proposals waiting for the user
+    pub edit: Option<EditForm>,      // Title and authors being edited in `AppMode::Edit`
+    pub reader: Option<Reader>,      // Book open in `AppMode::Reader`
+    pub comic: Option<ComicViewer>,  // Comic open in `AppMode::Comic`
+    pub details_scroll: Cell<u16>,   // Lines the description in the details view is scrolled by, kept in range as it's drawn
+    pub details_tab: DetailsTab,     // Tab of the details view shown below the metadata
+    pub pending_jump: bool,          // Next letter key jumps to that index section
+    pub hint_input: Option<String>,  // Hint letters typed so far while hints are shown over the rows
+    pub type_ahead: Option<(String, Instant)>, // Title prefix typed so far and when it was last extended
+    pub search_stats: Option<SearchStats>, // Outcome of the last search, shown in the search bar
+    pub filters: Vec<FilterClause>,  // Active filters, shown as chips under the title bar
+    pub tag_browser: Option<TagBrowser>, // Tag pane beside the book list, None while it is hidden
+    pub view: ViewMode,
+    pub sort: BookSort,              // Order of the visible list; `all_books` stays in title-sort order
+    pub table_columns: Vec<TableColumn>, // Columns of the table view, from the config or calibre
+    pub hidden_categories: BTreeSet<String>, // calibre's tag browser categories the user hid, e.g. "languages"
+    pub virtual_library: Option<VirtualLibrary>,
+    pub shelves: Shelves,            // The open library's shelves, mirrored onto each book's `shelves`
+    pub reading: BTreeMap<i32, ReadStatus>, // Books being read or finished in the open library
+    pub drm: BTreeMap<i32, BTreeMap<String, String>>, // DRM found on each book's formats by the last verify
+    pub annotations: BTreeMap<i32, Vec<Annotation>>, // Bookmarks and highlights made in the reader, by book id
+    pub absolute_times: bool,        // Show absolute times even when the configured format is relative
+    pub read_only: Option<String>,   // Who holds the library's write lock when it was opened read-only
+    pub snapshot: Option<DateTime<Utc>>, // When the snapshot browsed in place of the offline library was taken
+    pub queued: Vec<QueuedAction>,   // Actions taken while the library was offline, waiting to be applied
+    pub scroll: RefCell<HashMap<ScrollView, ScrollState>>, // Where each list was left, updated as it's drawn
+}
+
+/// Loading a large library a page at a time, see `Database::load_books_page`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookPages {
+    /// Books read so far
+    pub loaded: usize,
+    /// Books in the library when it was opened, or read since if more were added
+    pub total: usize,
+    /// Title sort and id of the last book read, where the next page starts
+    pub after: (String, i32),
+}
+
+/// What the details view shows below a book's metadata
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DetailsTab {
+    #[default]
+    Description,
+    /// Bookmarks and highlights made in the reader
+    Annotations,
+}
+
+impl DetailsTab {
+    pub fn next(self) -> Self {
+        match self {
+            DetailsTab::Description => DetailsTab::Annotations,
+            DetailsTab::Annotations => DetailsTab::Description,
+        }
+    }
+}
+
+/// Lists that keep their own place, so leaving one and coming back doesn't lose it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScrollView {
+    /// The whole book list, in any layout
+    Books,
+    /// The book list narrowed by a search in progress
+    Search,
+    /// The tag pane beside the book list, kept while the pane is hidden
+    Tags,
+    /// The details view's annotations tab
+    Annotations,
+    Device,
+    Review,
+    Jobs,
+    Usage,
+    Cleanup,
+    FormatPolicy,
+    MetadataAudit,
+}
+
+/// A list's selected row and the first row scrolled into view
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScrollState {
+    pub selected: usize,
+    pub offset: usize,
+}
+
+/// How the book list is laid out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ViewMode {
+    #[default]
+    List,
+    Table,
+    Grid,
+}
+
+impl ViewMode {
+    pub fn next(self) -> Self {
+        match self {
+            ViewMode::List => ViewMode::Table,
+            ViewMode::Table => ViewMode::Grid,
+            ViewMode::Grid => ViewMode::List,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ViewMode::List => "list",
+            ViewMode::Table => "table",
+            ViewMode::Grid => "grid",
+        }
+    }
+}
+
+/// A column of the table view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TableColumn {
+    Title,
+    Authors,
+    Formats,
+    Tags,
+    Added,
+    Modified,
+}
+
+impl TableColumn {
+    /// The table's columns when neither the config nor calibre says otherwise
+    pub const DEFAULT: [TableColumn; 5] =
+        [TableColumn::Title, TableColumn::Authors, TableColumn::Formats, TableColumn::Tags, TableColumn::Added];
+
+    pub fn header(self) -> &'static str {
+        match self {
+            TableColumn::Title => "Title",
+            TableColumn::Authors => "Authors",
+            TableColumn::Formats => "Format",
+            TableColumn::Tags => "Tags",
+            TableColumn::Added => "Added",
+            TableColumn::Modified => "Modified",
+        }
+    }
+
+    /// The column calibre's book list shows for the same field, by its lookup name
+    pub fn for_calibre_field(field: &str) -> Option<TableColumn> {
+        match field {
+            "title" => Some(TableColumn::Title),
+            "authors" => Some(TableColumn::Authors),
+            "formats" => Some(TableColumn::Formats),
+            "tags" => Some(TableColumn::Tags),
+            "timestamp" => Some(TableColumn::Added),
+            "last_modified" => Some(TableColumn::Modified),
+            _ => None,
+        }
+    }
+}
+
+/// A calibre virtual library the list is restricted to
+#[derive(Debug, Clone)]
+pub struct VirtualLibrary {
+    pub name: String,
+    pub clauses: Vec<FilterClause>,
+}
+
+/// A named search from tuilibre's settings or calibre's preferences
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedSearch {
+    pub name: String,
+    pub expression: String,
+    pub source: SearchSource,
+}
+
+/// Where a saved search is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchSource {
+    /// tuilibre's library settings
+    Local,
+    /// calibre's saved searches
+    Calibre,
+    /// calibre's virtual libraries, which tuilibre only reads
+    VirtualLibrary,
+}
+
+impl SearchSource {
+    /// Shown after the search in the list, empty for tuilibre's own
+    pub fn label(self) -> &'static str {
+        match self {
+            SearchSource::Local => "",
+            SearchSource::Calibre => " [calibre]",
+            SearchSource::VirtualLibrary => " [virtual library]",
+        }
+    }
+}
+
+/// How many books the current search matched and how long it took
+#[derive(Debug, Clone, Copy)]
+pub struct SearchStats {
+    pub count: usize,
+    pub elapsed: Duration,
+}
+
+impl SearchStats {
+    /// E.g. "1,234 results (12 ms)"
+    pub fn describe(&self, locale: &Locale) -> String {
+        let ms = self.elapsed.as_millis();
+        match self.count {
+            0 => format!("no results ({} ms)", ms),
+            1 => format!("1 result ({} ms)", ms),
+            count => format!("{} results ({} ms)", locale.format_number(count), ms),
+        }
+    }
+}
+
+/// Sections of the index strip beside the book list
+pub const INDEX_LETTERS: [char; 27] = [
+    '#', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M',
+    'N', 'O', 'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
+];
+
+/// Put the shelves a book is on, its reading status and its DRM on it
+fn mark_book(book: &mut Book, shelves: &Shelves, reading: &BTreeMap<i32, ReadStatus>, drm: &BTreeMap<i32, BTreeMap<String, String>>) {
+    book.shelves = shelves
+        .iter()
+        .filter(|(_, shelf)| shelf.books.contains(&book.id))
+        .map(|(name, _)| name.clone())
+        .collect();
+    book.status = reading.get(&book.id).copied();
+    book.drm = drm.get(&book.id).cloned().unwrap_or_default();
+}
+
+/// What `all_books` is ordered by; SQLite's NOCASE collation only folds ASCII
+fn title_key(book: &Book) -> String {
+    book.sort.to_ascii_lowercase()
+}
+
+/// What a search typed into the search bar asks for, for callers without an `App`
+pub fn search_query(search: &str) -> BookQuery {
+    let (text, clauses) = search_terms(search);
+    BookQuery { text, clauses }
+}
+
+/// A search as plain text, or as clauses if it has field terms like `formats:pdf`
+fn search_terms(search: &str) -> (String, Vec<FilterClause>) {
+    let terms = parse_calibre_search(search);
+    if terms.iter().any(|c| !matches!(c.filter, Filter::Text(_))) {
+        (String::new(), terms)
+    } else {
+        (search.to_string(), Vec::new())
+    }
+}
+
+/// Letters hints are made of, home row first
+const HINT_LETTERS: [char; 9] = ['a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l'];
+
+/// Two-letter hints for `count` rows ("aa", "as", ...), or fewer if there aren't enough
+pub fn hint_labels(count: usize) -> Vec<String> {
+    HINT_LETTERS
+        .iter()
+        .flat_map(|&first| HINT_LETTERS.iter().map(move |&second| format!("{}{}", first, second)))
+        .take(count)
+        .collect()
+}
+
+/// A feed being browsed and the entry selected in it
+#[derive(Debug, Clone)]
+pub struct OpdsPage {
+    pub feed: OpdsFeed,
+    pub selected: usize,
+}
+
+/// Navigation stack of the OPDS browser, starting at the catalog list
+#[derive(Debug, Clone)]
+pub struct OpdsView {
+    pub pages: Vec<OpdsPage>,
+}
+
+impl OpdsView {
+    pub fn new(root: OpdsFeed) -> Self {
+        OpdsView {
+            pages: vec![OpdsPage { feed: root, selected: 0 }],
+        }
+    }
+
+    pub fn current(&self) -> Option<&OpdsPage> {
+        self.pages.last()
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut OpdsPage> {
+        self.pages.last_mut()
+    }
+
+    pub fn selected_entry(&self) -> Option<&OpdsEntry> {
+        self.current().and_then(|page| page.feed.entries.get(page.selected))
+    }
+}
+
+/// What a popup menu is choosing
+#[derive(Debug, Clone, PartialEq)]
+pub enum MenuKind {
+    EmailProfile,
+    FilterKind,
+    /// Choosing a value for a filter; the filters match the menu items
+    FilterValue(Vec<Filter>),
+    /// Choosing a virtual library; the first item is the whole library
+    VirtualLibrary,
+    /// Quick actions on the selected book; the actions match the menu items
+    BookActions(Vec<Action>),
+    /// Choosing the format to convert the selected book to
+    ConvertTo(Vec<String>),
+    /// Choosing which of the selected book's files to open
+    OpenFormat(Vec<BookFormat>),
+    /// Choosing what to do with edits to a book that changed elsewhere
+    WriteConflict(Vec<PendingEdit>),
+    /// Previewing the names files will be exported under; choosing any item runs the export
+    ExportPreview(ExportPlan),
+    /// Copying or moving the listed books into a new library at this folder
+    SplitMode(PathBuf),
+    /// Choosing a library from history to merge into this one
+    MergeSource(Vec<PathBuf>),
+    /// Previewing what merging the library at this path does; choosing any item runs it
+    MergePreview(PathBuf),
+    /// Choosing whose reading state to import from the calibre-web database at this path
+    CalibreWebUser(PathBuf, Vec<CalibreWebUser>),
+    /// Choosing a saved search to run; the searches match the menu items
+    SavedSearch(Vec<SavedSearch>),
+    /// Actions queued while the library was offline; the items match `App::queued`
+    QueuedActions,
+    /// Jumping to a chapter of the book in the reader; the items are its chapters
+    Chapters,
+}
+
+/// What a text prompt is asking for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    OpenWith,
+    AddTag,
+    RemoveTag,
+    EditTitle,
+    ExportTo,
+    ArchiveTo,
+    SplitTo,
+    AddToShelf,
+    RemoveFromShelf,
+    CalibreWebDb,
+    SaveSearch,
+    RenameLibrary,
+    /// Path of an ebook file to add as a new book
+    AddBook,
+    /// Authors separated by "&", from the edit form
+    EditAuthors,
+}
+
+/// An edit held back because the book was changed elsewhere after it was loaded
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEdit {
+    pub kind: PromptKind,
+    pub input: String,
+    pub book_id: i32,
+    /// The title as loaded, to tell whether the other change touched it too
+    pub loaded_title: String,
+    /// The authors as loaded, likewise
+    pub loaded_authors: Vec<String>,
+}
+
+impl PendingEdit {
+    pub fn new(kind: PromptKind, input: impl Into<String>, book: &Book) -> Self {
+        PendingEdit {
+            kind,
+            input: input.into(),
+            book_id: book.id,
+            loaded_title: book.title.clone(),
+            loaded_authors: book.authors.clone(),
+        }
+    }
+
+    /// What the edit writes to the book; None for prompts that don't edit it
+    pub fn change(&self) -> Option<BookChange> {
+        match self.kind {
+            PromptKind::AddTag => Some(BookChange::AddTags(vec![self.input.clone()])),
+            PromptKind::RemoveTag => Some(BookChange::RemoveTags(vec![self.input.clone()])),
+            PromptKind::EditTitle => Some(BookChange::Title(self.input.clone())),
+            PromptKind::EditAuthors => Some(BookChange::Authors(parse_authors(&self.input))),
+            PromptKind::OpenWith
+            | PromptKind::ExportTo
+            | PromptKind::ArchiveTo
+            | PromptKind::SplitTo
+            | PromptKind::AddToShelf
+            | PromptKind::RemoveFromShelf
+            | PromptKind::CalibreWebDb
+            | PromptKind::SaveSearch
+            | PromptKind::RenameLibrary
+            | PromptKind::AddBook => None,
+        }
+    }
+}
+
+/// A field of the edit form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditField {
+    Title,
+    Authors,
+}
+
+impl EditField {
+    pub const ALL: [EditField; 2] = [EditField::Title, EditField::Authors];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            EditField::Title => "Title",
+            EditField::Authors => "Authors",
+        }
+    }
+}
+
+/// The selected book's title and authors as being edited in `AppMode::Edit`
+#[derive(Debug, Clone)]
+pub struct EditForm {
+    pub title: String,
+    /// Separated by " & ", as calibre writes author lists
+    pub authors: String,
+    pub field: EditField,
+    /// The mode to go back to when done
+    pub return_mode: AppMode,
+}
+
+impl EditForm {
+    pub fn new(book: &Book, return_mode: AppMode) -> Self {
+        EditForm {
+            title: book.title.clone(),
+            authors: book.authors.join(" & "),
+            field: EditField::Title,
+            return_mode,
+        }
+    }
+
+    pub fn value(&self, field: EditField) -> &str {
+        match field {
+            EditField::Title => &self.title,
+            EditField::Authors => &self.authors,
+        }
+    }
+
+    /// The field being typed into
+    pub fn input_mut(&mut self) -> &mut String {
+        match self.field {
+            EditField::Title => &mut self.title,
+            EditField::Authors => &mut self.authors,
+        }
+    }
+
+    pub fn next_field(&mut self) {
+        self.field = match self.field {
+            EditField::Title => EditField::Authors,
+            EditField::Authors => EditField::Title,
+        };
+    }
+
+    /// The edits to write for what changed from `book`
+    pub fn edits(&self, book: &Book) -> Vec<PendingEdit> {
+        let mut edits = Vec::new();
+        let title = self.title.trim();
+        if title != book.title {
+            edits.push(PendingEdit::new(PromptKind::EditTitle, title, book));
+        }
+        if parse_authors(&self.authors) != book.authors {
+            edits.push(PendingEdit::new(PromptKind::EditAuthors, self.authors.trim(), book));
+        }
+        edits
+    }
+}
+
+/// Authors typed as "Terry Pratchett & Neil Gaiman"
+pub fn parse_authors(text: &str) -> Vec<String> {
+    text.split('&').map(str::trim).filter(|a| !a.is_empty()).map(String::from).collect()
+}
+
+/// A one-line text input shown over the current view
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub title: String,
+    pub input: String,
+}
+
+impl Prompt {
+    pub fn new(kind: PromptKind, title: impl Into<String>, input: impl Into<String>) -> Self {
+        Prompt {
+            kind,
+            title: title.into(),
+            input: input.into(),
+        }
+    }
+}
+
+/// A book about to be deleted, shown with everything that goes with it until the user confirms
+#[derive(Debug, Clone)]
+pub struct DeleteConfirm {
+    pub book_id: i32,
+    pub title: String,
+    pub preview: DeletionPreview,
+    /// Delete the book's folder too, not just its rows in the database
+    pub remove_files: bool,
+}
+
+/// A popup list of choices
+#[derive(Debug, Clone)]
+pub struct Menu {
+    pub kind: MenuKind,
+    pub title: String,
+    pub items: Vec<String>,
+    pub selected: usize,
+}
+
+impl Menu {
+    pub fn new(kind: MenuKind, title: impl Into<String>, items: Vec<String>) -> Self {
+        Menu {
+            kind,
+            title: title.into(),
+            items,
+            selected: 0,
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(self.items.len().saturating_sub(1));
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+}
+
+/// Books on a connected e-reader, shown in the device pane
+#[derive(Debug, Clone)]
+pub struct DeviceView {
+    pub device: Device,
+    pub books: Vec<DeviceBook>,
+    pub selected: usize,
+    pub pending_delete: bool,
+}
+
+impl DeviceView {
+    pub fn get_selected_book(&self) -> Option<&DeviceBook> {
+        self.books.get(self.selected)
+    }
+}
+
+/// Background jobs recorded for the open library, newest first
+#[derive(Debug, Clone)]
+pub struct JobsView {
+    pub records: Vec<JobRecord>,
+    pub selected: usize,
+    /// When this run of tuilibre started, to tell its jobs from earlier ones
+    pub session: DateTime<Utc>,
+}
+
+impl JobsView {
+    pub fn get_selected(&self) -> Option<&JobRecord> {
+        self.records.get(self.selected)
+    }
+}
+
+/// Metadata proposals from the enrichment job, reviewed one batch at a time
+#[derive(Debug, Clone, Default)]
+pub struct ReviewQueue {
+    pub proposals: Vec<MetadataProposal>,
+    pub selected: usize,
+    pub field: usize, // Field under the cursor in the selected proposal
+}
+
+impl ReviewQueue {
+    /// The proposals currently on screen
+    pub fn batch(&self, batch_size: usize) -> &[MetadataProposal] {
+        &self.proposals[..self.proposals.len().min(batch_size.max(1))]
+    }
+
+    pub fn select_next(&mut self, batch_size: usize) {
+        self.selected = (self.selected + 1).min(self.batch(batch_size).len().saturating_sub(1));
+        self.field = 0;
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+        self.field = 0;
+    }
+
+    pub fn get_selected(&self) -> Option<&MetadataProposal> {
+        self.proposals.get(self.selected)
+    }
+
+    /// Move the field cursor, wrapping around the selected proposal's changes
+    pub fn cycle_field(&mut self, forward: bool) {
+        let count = self.get_selected().map_or(0, |p| p.changes.len());
+        if count == 0 {
+            return;
+        }
+        self.field = if forward {
+            (self.field + 1) % count
+        } else {
+            (self.field + count - 1) % count
+        };
+    }
+
+    /// Accept or reject the field under the cursor
+    pub fn toggle_field(&mut self) {
+        if let Some(proposal) = self.proposals.get_mut(self.selected) {
+            proposal.toggle(self.field);
+        }
+    }
+
+    /// Remove the selected proposal from the queue
+    pub fn take_selected(&mut self) -> Option<MetadataProposal> {
+        if self.selected >= self.proposals.len() {
+            return None;
+        }
+        let proposal = self.proposals.remove(self.selected);
+        self.selected = self.selected.min(self.proposals.len().saturating_sub(1));
+        self.field = 0;
+        Some(proposal)
+    }
+
+    /// Remove the whole current batch from the queue
+    pub fn take_batch(&mut self, batch_size: usize) -> Vec<MetadataProposal> {
+        let count = self.batch(batch_size).len();
+        self.selected = 0;
+        self.field = 0;
+        self.proposals.drain(..count).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppMode {
+    Normal,      // Normal browsing mode
+    Search,      // Search mode
+    Details,     // Details view mode
+    DetailsFromSearch, // Details view accessed from search mode
+    LibrarySelection, // Library selection mode
+    Device,      // Books on the connected e-reader
+    Opds,        // Browsing remote OPDS catalogs
+    Review,      // Reviewing proposed metadata changes
+    Edit,        // Editing the selected book's title and authors
+    Reader,      // Reading the selected book's EPUB or TXT file
+    Comic,       // Flipping through the pages of the selected book's CBZ or CBR file
+    Jobs,        // Background jobs run before, with their failures
+    Usage,       // Disk space taken by each author, tag, series or format
+    Cleanup,     // Files and books suggested for pruning
+    FormatPolicy, // Books missing a required format or keeping a redundant one
+    MetadataAudit, // Files whose embedded metadata differs from the library
+}
+
+impl App {
+    pub fn new(library_path: PathBuf) -> Self {
+        App {
+            books: Vec::new(),
+            all_books: Vec::new(),
+            pages: None,
+            search_index: SearchIndex::default(),
+            selected_book_index: 0,
+            search_query: String::new(),
+            search_layers: Vec::new(),
+            search_plain: false,
+            search_full_text: false,
+            fts_snippets: HashMap::new(),
+            content_matches: HashMap::new(),
+            mode: AppMode::Normal,
+            library_path,
+            library_alias: None,
+            config: Config::default(),
+            selected_ids: HashSet::new(),
+            visual_select: false,
+            range_anchor: None,
+            job: None,
+            status_message: None,
+            device_view: None,
+            jobs_view: None,
+            usage_view: None,
+            cleanup_view: None,
+            policy_audit: None,
+            metadata_audit: None,
+            on_device: HashSet::new(),
+            menu: None,
+            opds_view: None,
+            review: ReviewQueue::default(),
+            edit: None,
+            reader: None,
+            comic: None,
+            details_scroll: Cell::new(0),
+            details_tab: DetailsTab::default(),
+            pending_jump: false,
+            hint_input: None,
+            type_ahead: None,
+            prompt: None,
+            pending_delete: None,
+            search_stats: None,
+            filters: Vec::new(),
+            tag_browser: None,
+            view: ViewMode::default(),
+            sort: BookSort::default(),
+            table_columns: TableColumn::DEFAULT.to_vec(),
+            hidden_categories: BTreeSet::new(),
+            virtual_library: None,
+            shelves: Shelves::new(),
+            reading: BTreeMap::new(),
+            drm: BTreeMap::new(),
+            annotations: BTreeMap::new(),
+            absolute_times: false,
+            read_only: None,
+            snapshot: None,
+            queued: Vec::new(),
+            scroll: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn get_selected_book(&self) -> Option<&Book> {
+        self.books.get(self.selected_book_index)
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected_book_index < self.books.len().saturating_sub(1) {
+            self.selected_book_index += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if self.selected_book_index > 0 {
+            self.selected_book_index -= 1;
+        }
+    }
+
+    /// Move the cursor `count` books down
+    pub fn select_next_by(&mut self, count: usize) {
+        self.selected_book_index = (self.selected_book_index + count).min(self.books.len().saturating_sub(1));
+    }
+
+    /// Move the cursor `count` books up
+    pub fn select_previous_by(&mut self, count: usize) {
+        self.selected_book_index = self.selected_book_index.saturating_sub(count);
+    }
+
+    /// Move to the 1-based line `line`, or the last book if it's past the end
+    pub fn select_line(&mut self, line: usize) {
+        self.selected_book_index = line.saturating_sub(1).min(self.books.len().saturating_sub(1));
+    }
+
+    pub fn set_books(&mut self, books: Vec<Book>) {
+        self.selected_book_index = 0;
+        self.books = books;
+    }
+
+    /// Replace the whole library's books and re-index them for searching
+    pub fn set_all_books(&mut self, books: Vec<Book>) {
+        self.search_index = SearchIndex::build(&books);
+        self.all_books = books;
+        self.mark_books();
+    }
+
+    /// Replace the library's shelves and note on each book which ones it is on
+    pub fn set_shelves(&mut self, shelves: Shelves) {
+        self.shelves = shelves;
+        self.mark_books();
+    }
+
+    /// Replace the library's reading status and note it on each book
+    pub fn set_reading(&mut self, reading: BTreeMap<i32, ReadStatus>) {
+        self.reading = reading;
+        self.mark_books();
+    }
+
+    /// Replace the DRM found on the library's files and note it on each book
+    pub fn set_drm(&mut self, drm: BTreeMap<i32, BTreeMap<String, String>>) {
+        self.drm = drm;
+        self.mark_books();
+    }
+
+    /// Note the DRM verify found on one of a book's files, or that it had none; true if that
+    /// changed what was known
+    pub fn record_drm(&mut self, book_id: i32, format: &str, scheme: Option<String>) -> bool {
+        let formats = self.drm.entry(book_id).or_default();
+        let changed = match scheme {
+            Some(scheme) => formats.insert(format.to_string(), scheme.clone()) != Some(scheme),
+            None => formats.remove(format).is_some(),
+        };
+        if formats.is_empty() {
+            self.drm.remove(&book_id);
+        }
+        if changed {
+            let drm = self.drm.get(&book_id).cloned().unwrap_or_default();
+            for book in self.all_books.iter_mut().chain(self.books.iter_mut()).filter(|b| b.id == book_id) {
+                book.drm = drm.clone();
+            }
+        }
+        changed
+    }
+
+    /// Copy what tuilibre keeps about books outside calibre onto the loaded books
+    fn mark_books(&mut self) {
+        for book in &mut self.all_books {
+            mark_book(book, &self.shelves, &self.reading, &self.drm);
+        }
+    }
+
+    /// Note that the first `page` of the library's `total` books is in, leaving the rest to be
+    /// loaded a page at a time
+    pub fn start_paging(&mut self, page: &[Book], total: usize) {
+        self.pages = match page.last() {
+            Some(last) if page.len() < total => Some(BookPages {
+                loaded: page.len(),
+                total,
+                after: (last.sort.clone(), last.id),
+            }),
+            _ => None,
+        };
+    }
+
+    /// Add the next page of the library, returning the books added at the end of `all_books`
+    ///
+    /// A refresh may have patched in a book from a later page, so those are skipped, and the
+    /// list re-sorted if one now sorts after the page; None then, as the new books aren't all at
+    /// the end. A page shorter than `limit` is the last.
+    pub fn append_page(&mut self, page: Vec<Book>, limit: usize) -> Option<Vec<Book>> {
+        if let Some(pages) = &mut self.pages {
+            pages.loaded += page.len();
+            pages.total = pages.total.max(pages.loaded);
+            match page.last() {
+                Some(last) if page.len() >= limit => pages.after = (last.sort.clone(), last.id),
+                _ => self.pages = None,
+            }
+        }
+
+        let known: HashSet<i32> = self.all_books.iter().map(|b| b.id).collect();
+        let mut added: Vec<Book> = page.into_iter().filter(|b| !known.contains(&b.id)).collect();
+        for book in &mut added {
+            mark_book(book, &self.shelves, &self.reading, &self.drm);
+        }
+
+        let in_order = match (self.all_books.last(), added.first()) {
+            (Some(last), Some(first)) => title_key(last) <= title_key(first),
+            _ => true,
+        };
+        if in_order {
+            self.search_index.extend(&added);
+            self.all_books.extend(added.iter().cloned());
+            Some(added)
+        } else {
+            self.all_books.extend(added);
+            self.all_books.sort_by_cached_key(title_key);
+            self.search_index = SearchIndex::build(&self.all_books);
+            None
+        }
+    }
+
+    /// Put loaded authors, tags and descriptions on the books they belong to, listed or not
+    pub fn fill_details(&mut self, details: &HashMap<i32, BookDetails>) {
+        for (position, book) in self.all_books.iter_mut().enumerate() {
+            if let Some(found) = details.get(&book.id) {
+                book.set_details(found.clone());
+                self.search_index.update(position, book);
+            }
+        }
+        for book in &mut self.books {
+            if let Some(found) = details.get(&book.id) {
+                book.set_details(found.clone());
+            }
+        }
+    }
+
+    /// Listed books without their details that are on screen or within a screen of it, so
+    /// scrolling a screen either way finds them loaded
+    pub fn ids_missing_details(&self, rows: usize) -> Vec<i32> {
+        let place = self.scroll_state(self.book_scroll_view());
+        let start = place.offset.min(self.selected_book_index).saturating_sub(rows);
+        let end = (place.offset.max(self.selected_book_index) + rows * 2).min(self.books.len());
+        self.books
+            .get(start..end)
+            .unwrap_or_default()
+            .iter()
+            .filter(|b| !b.details_loaded)
+            .map(|b| b.id)
+            .collect()
+    }
+
+    /// Newest `last_modified` among the loaded books, where the next refresh picks up from
+    pub fn last_modified(&self) -> String {
+        self.all_books.iter().map(|b| b.last_modified.as_str()).max().unwrap_or_default().to_string()
+    }
+
+    /// Swap re-read books into `all_books` and drop those no longer in the library
+    ///
+    /// Keeps the title-sort order `load_books` returns; `books` is left for the caller to rebuild.
+    pub fn patch_books(&mut self, changed: Vec<Book>, existing: &HashSet<i32>) {
+        let changed_ids: HashSet<i32> = changed.iter().map(|b| b.id).collect();
+        self.all_books.retain(|b| existing.contains(&b.id) && !changed_ids.contains(&b.id));
+        self.selected_ids.retain(|id| existing.contains(id));
+
+        if changed.len() <= PATCH_INSERT_LIMIT {
+            for book in changed {
+                let book_key = title_key(&book);
+                let at = self.all_books.partition_point(|b| title_key(b) <= book_key);
+                self.all_books.insert(at, book);
+            }
+        } else {
+            self.all_books.extend(changed);
+            self.all_books.sort_by_cached_key(title_key);
+        }
+        self.search_index = SearchIndex::build(&self.all_books);
+        self.mark_books();
+    }
+
+    /// Put the visible books in the order of `order`, ids as `Database::sorted_book_ids` ranks them
+    pub fn apply_sort_order(&mut self, order: &[i32]) {
+        let rank: HashMap<i32, usize> = order.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+        self.books.sort_by_key(|book| rank.get(&book.id).copied().unwrap_or(usize::MAX));
+    }
+
+    /// Title of the book list pane, with the order it's sorted in, e.g. "Books · Date added ↓"
+    pub fn book_list_title(&self) -> String {
+        if self.visual_select {
+            format!("Books · {} · VISUAL", self.sort.label())
+        } else {
+            format!("Books · {}", self.sort.label())
+        }
+    }
+
+    /// Move to the first book in the letter's section, or the next section after it
+    pub fn jump_to_letter(&mut self, letter: char) {
+        let rank = |c: char| INDEX_LETTERS.iter().position(|&l| l == c).unwrap_or(0);
+        let target = rank(letter.to_ascii_uppercase());
+        // '#' titles sort wherever their first character puts them, so look for an exact match first
+        let found = self.books.iter().position(|b| rank(b.index_letter()) == target)
+            .or_else(|| self.books.iter().position(|b| rank(b.index_letter()) > target));
+        self.selected_book_index = found.unwrap_or(self.books.len().saturating_sub(1));
+    }
+
+    /// The free-text search and the searches it narrows, combined with the virtual library and
+    /// the active filters
+    ///
+    /// A search with field terms like `formats:pdf` is parsed the way virtual libraries are,
+    /// unless it's to be taken as plain text.
+    pub fn book_query(&self) -> BookQuery {
+        let library_clauses = self.virtual_library.iter().flat_map(|vl| vl.clauses.iter());
+        let layer_clauses = self.search_layers.iter().flat_map(|layer| match search_terms(layer) {
+            (text, _) if !text.is_empty() => vec![FilterClause::new(Filter::Text(text))],
+            (_, clauses) => clauses,
+        });
+        // The full-text search narrows the list separately, in `UI::refresh_books`
+        let (text, search_clauses) = if self.search_full_text {
+            (String::new(), Vec::new())
+        } else if self.search_plain {
+            (self.search_query.clone(), Vec::new())
+        } else {
+            search_terms(&self.search_query)
+        };
+        BookQuery {
+            text,
+            clauses: library_clauses
+                .chain(&self.filters)
+                .cloned()
+                .chain(layer_clauses)
+                .chain(search_clauses)
+                .collect(),
+        }
+    }
+
+    /// The phrase of the first `content:` term narrowing the list, whose matches are shown
+    pub fn content_phrase(&self) -> Option<String> {
+        self.book_query().clauses.into_iter().find_map(|clause| match clause.filter {
+            Filter::Content(phrase) if !clause.negated => Some(phrase),
+            _ => None,
+        })
+    }
+
+    /// Why the search being typed doesn't parse, unless it's taken as plain text
+    pub fn search_error(&self) -> Option<SearchError> {
+        if self.search_plain || self.search_full_text {
+            return None;
+        }
+        check_calibre_search(&self.search_query)
+    }
+
+    /// The search with the searches it narrows, as one expression
+    pub fn full_search(&self) -> String {
+        let parts = self.search_layers.iter().chain([&self.search_query]);
+        parts.map(|s| s.trim()).filter(|s| !s.is_empty()).collect::<Vec<_>>().join(" ")
+    }
+
+    /// Take the search as a layer to narrow down further, starting a new empty one
+    pub fn push_search_layer(&mut self) -> bool {
+        if self.search_query.trim().is_empty() {
+            return false;
+        }
+        self.search_layers.push(std::mem::take(&mut self.search_query));
+        true
+    }
+
+    /// Go back to editing the last layer, dropping the narrowing after it
+    pub fn pop_search_layer(&mut self) -> bool {
+        match self.search_layers.pop() {
+            Some(layer) => {
+                self.search_query = layer;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether a type-ahead prefix is being typed, i.e. the last letter came recently
+    pub fn type_ahead_active(&self) -> bool {
+        self.type_ahead.as_ref().is_some_and(|(_, at)| at.elapsed() < TYPE_AHEAD_TIMEOUT)
+    }
+
+    /// Add `c` to the type-ahead prefix, or start a new one, and move to the next book whose
+    /// title (or title sort) starts with it, ignoring case; false if none does
+    pub fn type_ahead_find(&mut self, c: char) -> bool {
+        let mut prefix = match self.type_ahead.take() {
+            Some((prefix, at)) if at.elapsed() < TYPE_AHEAD_TIMEOUT => prefix,
+            _ => String::new(),
+        };
+        prefix.extend(c.to_lowercase());
+
+        // A longer prefix may still fit the current book; a new one looks past it
+        let start = if prefix.chars().count() > 1 { self.selected_book_index } else { self.selected_book_index + 1 };
+        let count = self.books.len();
+        let found = (0..count).map(|i| (start + i) % count).find(|&i| {
+            let book = &self.books[i];
+            book.title.to_lowercase().starts_with(&prefix) || book.sort.to_lowercase().starts_with(&prefix)
+        });
+        if let Some(index) = found {
+            self.selected_book_index = index;
+        }
+        self.type_ahead = Some((prefix, Instant::now()));
+        found.is_some()
+    }
+
+    /// Index of the quick filter whose clauses are exactly the active filters
+    pub fn active_quick_filter(&self) -> Option<usize> {
+        if self.filters.is_empty() {
+            return None;
+        }
+        let quick = &self.config.quick_filters.filters;
+        quick.iter().position(|q| parse_calibre_search(&q.search) == self.filters)
+    }
+
+    /// Mark or unmark the book under the cursor
+    pub fn toggle_selected(&mut self) {
+        if let Some(id) = self.get_selected_book().map(|b| b.id) {
+            if !self.selected_ids.remove(&id) {
+                self.selected_ids.insert(id);
+            }
+        }
+    }
+
+    /// Whether to render for screen readers
+    pub fn accessible(&self) -> bool {
+        self.config.accessibility.enabled
+    }
+
+    /// What has focus, spelled out for screen readers, e.g. "Book 3 of 12: Dune by Frank Herbert, marked"
+    pub fn announcement(&self) -> Option<String> {
+        if let Some(menu) = &self.menu {
+            let item = menu.items.get(menu.selected)?;
+            return Some(format!("{} menu: {}, {} of {}", menu.title, item, menu.selected + 1, menu.items.len()));
+        }
+        if let Some(prompt) = &self.prompt {
+            return Some(format!("{}: {}", prompt.title, prompt.input));
+        }
+
+        match self.mode {
+            AppMode::Normal if self.tag_browser.as_ref().is_some_and(|b| b.focused) => {
+                let browser = self.tag_browser.as_ref()?;
+                let tag = browser.selected_tag()?;
+                let mut text = format!("Tag {} of {}: {}, {} books", browser.selected + 1, browser.tags.len(), tag.name, tag.count);
+                if tag_browser::is_tag_filtered(&self.filters, &tag.name) {
+                    text.push_str(", filtering");
+                }
+                Some(text)
+            }
+            AppMode::Normal | AppMode::Search => {
+                let Some(book) = self.get_selected_book() else {
+                    return Some("No books".to_string());
+                };
+                let mut text = format!(
+                    "Book {} of {}: {} by {}",
+                    self.selected_book_index + 1,
+                    self.books.len(),
+                    book.title,
+                    book.author_list()
+                );
+                if self.is_selected(book) {
+                    text.push_str(", marked");
+                }
+                if self.on_device.contains(&book.id) {
+                    text.push_str(", on device");
+                }
+                Some(text)
+            }
+            AppMode::Details | AppMode::DetailsFromSearch => {
+                self.get_selected_book().map(|book| format!("Details of {}", book.title))
+            }
+            AppMode::Device => {
+                let view = self.device_view.as_ref()?;
+                let book = view.get_selected_book()?;
+                Some(format!("Device book {} of {}: {}", view.selected + 1, view.books.len(), book.title))
+            }
+            AppMode::Opds => {
+                let page = self.opds_view.as_ref()?.current()?;
+                let entry = page.feed.entries.get(page.selected)?;
+                Some(format!("Entry {} of {}: {}", page.selected + 1, page.feed.entries.len(), entry.title))
+            }
+            AppMode::Review => {
+                let proposal = self.review.get_selected()?;
+                let change = proposal.changes.get(self.review.field)?;
+                let state = if change.accepted { "accepted" } else { "not accepted" };
+                Some(format!("{}: {} {}, {}", proposal.book_title, change.field.label(), change.proposed, state))
+            }
+            AppMode::Edit => {
+                let edit = self.edit.as_ref()?;
+                Some(format!("Editing {}: {}", edit.field.label(), edit.value(edit.field)))
+            }
+            AppMode::Reader => {
+                let reader = self.reader.as_ref()?;
+                Some(format!(
+                    "Reading {}, chapter {} of {}: {}",
+                    reader.title,
+                    reader.chapter + 1,
+                    reader.chapters.len(),
+                    reader.current().title
+                ))
+            }
+            AppMode::Comic => {
+                let comic = self.comic.as_ref()?;
+                Some(format!("{}, page {} of {}", comic.title, comic.page + 1, comic.archive.pages.len()))
+            }
+            AppMode::Jobs => {
+                let view = self.jobs_view.as_ref()?;
+                let record = view.get_selected()?;
+                Some(format!(
+                    "Job {} of {}: {}, {}",
+                    view.selected + 1,
+                    view.records.len(),
+                    record.label,
+                    record.result
+                ))
+            }
+            AppMode::Usage => {
+                let view = self.usage_view.as_ref()?;
+                match (view.open_group(), view.selected_book(), view.selected_group()) {
+                    (Some(group), Some(book), _) => Some(format!(
+                        "Book {} of {} in {}: {}, {}",
+                        view.book_selected + 1,
+                        group.books.len(),
+                        group.name,
+                        book.title,
+                        format_size(book.bytes)
+                    )),
+                    (None, _, Some(group)) => Some(format!(
+                        "{} {} of {}: {}, {}, {} books",
+                        view.grouping.label(),
+                        view.selected + 1,
+                        view.groups.len(),
+                        group.name,
+                        format_size(group.bytes),
+                        group.books.len()
+                    )),
+                    _ => None,
+                }
+            }
+            AppMode::Cleanup => {
+                let view = self.cleanup_view.as_ref()?;
+                let item = view.selected_item()?;
+                Some(format!(
+                    "{} {} of {}: {}, {}",
+                    view.section.label(),
+                    view.cursor() + 1,
+                    view.items().len(),
+                    item.label(),
+                    format_size(item.bytes)
+                ))
+            }
+            AppMode::FormatPolicy => {
+                let audit = self.policy_audit.as_ref()?;
+                let violation = audit.selected_violation()?;
+                Some(format!(
+                    "Violation {} of {}: {}, {}",
+                    audit.selected + 1,
+                    audit.violations.len(),
+                    violation.title,
+                    violation.describe()
+                ))
+            }
+            AppMode::MetadataAudit => {
+                let audit = self.metadata_audit.as_ref()?;
+                let mismatch = audit.selected_mismatch()?;
+                Some(format!(
+                    "File {} of {}: {} ({}), {} differ",
+                    audit.selected + 1,
+                    audit.mismatches.len(),
+                    mismatch.item.title,
+                    mismatch.item.format,
+                    mismatch.describe()
+                ))
+            }
+            AppMode::LibrarySelection => None,
+        }
+    }
+
+    /// Conventions for numbers and dates, from the config or the environment
+    pub fn locale(&self) -> Locale {
+        self.config.display.locale()
+    }
+
+    /// Where `view` was last drawn, or the top if it hasn't been
+    pub fn scroll_state(&self, view: ScrollView) -> ScrollState {
+        self.scroll.borrow().get(&view).copied().unwrap_or_default()
+    }
+
+    /// Remember where `view` is; called while drawing, hence `&self`
+    pub fn record_scroll(&self, view: ScrollView, selected: usize, offset: usize) {
+        self.scroll.borrow_mut().insert(view, ScrollState { selected, offset });
+    }
+
+    /// Select row `selected` of `view`, leaving its scroll for the next draw to adjust
+    pub fn select_in(&self, view: ScrollView, selected: usize) {
+        let offset = self.scroll_state(view).offset;
+        self.record_scroll(view, selected, offset);
+    }
+
+    /// The annotation selected in the details view's annotations tab
+    pub fn annotation_selected(&self) -> usize {
+        self.scroll_state(ScrollView::Annotations).selected
+    }
+
+    /// Show the tag pane with the keyboard on it, back on the tag selected when it was hidden
+    pub fn show_tag_browser(&mut self, tags: Vec<TagCount>) {
+        let mut browser = TagBrowser::new(tags);
+        browser.selected = self.scroll_state(ScrollView::Tags).selected.min(browser.tags.len().saturating_sub(1));
+        self.tag_browser = Some(browser);
+    }
+
+    /// Hide the tag pane, remembering its selected tag for when it is shown again
+    pub fn hide_tag_browser(&mut self) {
+        if let Some(browser) = self.tag_browser.take() {
+            self.select_in(ScrollView::Tags, browser.selected);
+        }
+    }
+
+    /// The list the book views are currently showing
+    pub fn book_scroll_view(&self) -> ScrollView {
+        match self.mode {
+            AppMode::Search | AppMode::DetailsFromSearch => ScrollView::Search,
+            _ => ScrollView::Books,
+        }
+    }
+
+    pub fn theme(&self) -> Theme {
+        Theme::from_config(&self.config.display)
+    }
+
+    pub fn density(&self) -> Density {
+        self.config.display.density
+    }
+
+    /// When the book was added, as configured for display
+    pub fn format_added(&self, book: &Book) -> String {
+        self.format_timestamp(&book.timestamp)
+    }
+
+    /// A calibre timestamp as configured for display
+    pub fn format_timestamp(&self, timestamp: &str) -> String {
+        match parse_calibre_timestamp(timestamp) {
+            Some(time) => format_time(time, &self.config.display.time_format, self.absolute_times, &self.locale()),
+            None => timestamp.to_string(),
+        }
+    }
+
+    pub fn is_selected(&self, book: &Book) -> bool {
+        self.selected_ids.contains(&book.id)
+    }
+
+    /// Indexes of `books` from the range anchor to the cursor, either way round
+    pub fn pending_range(&self) -> Option<(usize, usize)> {
+        let anchor_id = self.range_anchor?;
+        let anchor = self.books.iter().position(|b| b.id == anchor_id)?;
+        Some((anchor.min(self.selected_book_index), anchor.max(self.selected_book_index)))
+    }
+
+    /// Marker for the selection column: "●" marked, "◌" inside `range`, the `pending_range`
+    pub fn selection_marker(&self, index: usize, book: &Book, range: Option<(usize, usize)>) -> &'static str {
+        if self.is_selected(book) {
+            "●"
+        } else if range.is_some_and(|(first, last)| (first..=last).contains(&index)) {
+            "◌"
+        } else {
+            ""
+        }
+    }
+
+    /// Start a range at the cursor, or mark the books from the range's start to the cursor;
+    /// returns how many were marked
+    pub fn mark_range(&mut self) -> Option<usize> {
+        let Some((first, last)) = self.pending_range() else {
+            self.range_anchor = self.get_selected_book().map(|b| b.id);
+            return None;
+        };
+        self.range_anchor = None;
+        let ids: Vec<i32> = self.books[first..=last].iter().map(|b| b.id).collect();
+        self.selected_ids.extend(&ids);
+        Some(ids.len())
+    }
+
+    /// Books targeted by a batch action: the marked books, or the current one if none are marked
+    pub fn selection_or_current(&self) -> Vec<&Book> {
+        if self.selected_ids.is_empty() {
+            self.get_selected_book().into_iter().collect()
+        } else {
+            self.all_books.iter().filter(|b| self.selected_ids.contains(&b.id)).collect()
+        }
+    }
+}
+
+// Simplified book model for MVP
+#[derive(Debug, Clone, Serialize)]
+pub struct Book {
+    pub id: i32,
+    pub title: String,
+    pub sort: String, // Title sort, e.g. "Hobbit, The"
+    pub authors: Vec<String>,
+    pub path: String,
+    pub has_cover: bool,
+    pub timestamp: String,
+    /// `last_modified` as loaded, to notice edits made elsewhere since
+    pub last_modified: String,
+    /// Every file of the book, in the order calibre added them; the first opens by default
+    pub formats: Vec<BookFormat>,
+    pub tags: Vec<String>,
+    pub series: Option<String>,
+    /// Position in `series`; calibre keeps 1.0 for books outside a series
+    pub series_index: f64,
+    /// Out of 10 as calibre stores it, two per star; None when unrated
+    pub rating: Option<u8>,
+    /// The description, as HTML
+    pub comments: Option<String>,
+    /// Authors, tags and description are loaded; a book from `Database::load_books_page` gets
+    /// them once it's shown
+    #[serde(skip)]
+    pub details_loaded: bool,
+    /// The book has a format registered but its file wasn't on disk when loaded
+    pub file_missing: bool,
+    /// Names of the shelves the book is on, filled in by `App`
+    pub shelves: Vec<String>,
+    /// Being read or finished, filled in by `App`; `None` is unread
+    pub status: Option<ReadStatus>,
+    /// Formats found locked by DRM when the files were verified, with the DRM's name, filled
+    /// in by `App`
+    pub drm: BTreeMap<String, String>,
+}
+
+/// One file of a book (a row of calibre's `data` table)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BookFormat {
+    pub format: String, // Upper case, e.g. "EPUB"
+    pub name: String,   // File name without the extension
+    pub size: i64,
+}
+
+impl BookFormat {
+    /// File name inside the book directory
+    pub fn file_name(&self) -> String {
+        format!("{}.{}", self.name, self.format.to_lowercase())
+    }
+}
+
+/// Something a book lacks, flagged in the list so metadata-only stubs stand out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookGap {
+    NoCover,
+    NoFormats,
+    MissingFile,
+}
+
+impl BookGap {
+    /// Marker drawn after the book in the list
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BookGap::NoCover => "▫",
+            BookGap::NoFormats => "∅",
+            BookGap::MissingFile => "⚠",
+        }
+    }
+
+    /// Spelled out for screen readers and the details pane
+    pub fn description(self) -> &'static str {
+        match self {
+            BookGap::NoCover => "no cover",
+            BookGap::NoFormats => "no files",
+            BookGap::MissingFile => "file missing",
+        }
+    }
+}
+
+impl Book {
+    /// Take the authors, tags and description left out when the book was loaded in a page
+    pub fn set_details(&mut self, details: BookDetails) {
+        self.authors = details.authors;
+        self.tags = details.tags;
+        self.comments = details.comments;
+        self.details_loaded = true;
+    }
+
+    pub fn author_list(&self) -> String {
+        self.authors.join(", ")
+    }
+
+    pub fn tag_list(&self) -> String {
+        self.tags.join(", ")
+    }
+
+    /// e.g. "Discworld #3" or "Discworld #1.5", None outside a series
+    pub fn series_label(&self) -> Option<String> {
+        self.series.as_ref().map(|series| format!("{} #{}", series, self.series_index))
+    }
+
+    /// e.g. "★★★½☆" for a rating of 7, None when unrated
+    pub fn stars(&self) -> Option<String> {
+        self.rating.map(|rating| {
+            let (full, half) = ((rating / 2) as usize, (rating % 2) as usize);
+            format!("{}{}{}", "★".repeat(full), "½".repeat(half), "☆".repeat(5 - full - half))
+        })
+    }
+
+    /// The description as paragraphs of plain text, empty without one
+    pub fn description(&self) -> Vec<String> {
+        self.comments.as_deref().map(|html| html_paragraphs(html).1).unwrap_or_default()
+    }
+
+    /// Whether the book is in the same series as `other`
+    pub fn same_series(&self, other: &Book) -> bool {
+        self.series.is_some() && self.series == other.series
+    }
+
+    /// Section of the A-Z index the book belongs to, '#' for anything not A-Z
+    pub fn index_letter(&self) -> char {
+        let key = if self.sort.is_empty() { &self.title } else { &self.sort };
+        match key.chars().next() {
+            Some(c) if c.is_ascii_alphabetic() => c.to_ascii_uppercase(),
+            _ => '#',
+        }
+    }
+
+    /// Full path of the book's first file: library_path/book_folder/filename.format
+    pub fn file_path(&self, library_path: &Path) -> Option<PathBuf> {
+        self.formats.first().map(|format| self.format_path(library_path, format))
+    }
+
+    /// Full path of one of the book's files
+    pub fn format_path(&self, library_path: &Path, format: &BookFormat) -> PathBuf {
+        library_path.join(&self.path).join(format.file_name())
+    }
+
+    /// Whether the book has a file in `format`, e.g. "epub"
+    pub fn has_format(&self, format: &str) -> bool {
+        self.formats.iter().any(|f| f.format.eq_ignore_ascii_case(format))
+    }
+
+    /// Whether the book has an audio file, see `AUDIO_FORMATS`
+    pub fn is_audiobook(&self) -> bool {
+        self.formats.iter().any(|f| audiobook::is_audio_format(&f.format))
+    }
+
+    /// Whether verify found any of the book's files locked by DRM
+    pub fn has_drm(&self) -> bool {
+        !self.drm.is_empty()
+    }
+
+    /// The book's formats, e.g. "EPUB, PDF"
+    pub fn format_list(&self) -> String {
+        self.formats.iter().map(|f| f.format.as_str()).collect::<Vec<_>>().join(", ")
+    }
+
+    /// What the book lacks, most serious first
+    pub fn gaps(&self) -> Vec<BookGap> {
+        let mut gaps = Vec::new();
+        if self.formats.is_empty() {
+            gaps.push(BookGap::NoFormats);
+        } else if self.file_missing {
+            gaps.push(BookGap::MissingFile);
+        }
+        if !self.has_cover {
+            gaps.push(BookGap::NoCover);
+        }
+        gaps
+    }
+
+    pub fn display_title(&self) -> String {
+        if self.title.chars().count() > 50 {
+            let chars: Vec<char> = self.title.chars().collect();
+            format!("{}...", chars.iter().take(47).collect::<String>())
+        } else {
+            self.title.clone()
+        }
+    }
+}
\ No newline at end of file
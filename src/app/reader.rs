@@ -0,0 +1,570 @@
+//! Reading EPUB and plain-text books inside the terminal, for machines with nothing to open them in
+//!
+//! The position is kept as a chapter and paragraph rather than a screen line, so it still
+//! points at the same text when the terminal is resized or the book reopened, and so are the
+//! bookmarks and highlights made while reading.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use unicode_width::UnicodeWidthChar;
+
+use super::AppMode;
+use crate::error::{Context, Error, Result};
+use crate::utils::text::html_paragraphs;
+
+/// Formats the reader can show, best first
+pub const READABLE_FORMATS: [&str; 2] = ["EPUB", "TXT"];
+
+/// Characters of a bookmarked paragraph kept to recognise it by in lists
+const EXCERPT_CHARS: usize = 80;
+
+/// Lines in a plain-text file that start a chapter, compared in lowercase
+const TXT_CHAPTER_PREFIXES: [&str; 4] = ["chapter ", "part ", "book ", "prologue"];
+
+/// A chapter as paragraphs of plain text
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub paragraphs: Vec<String>,
+}
+
+/// Where the reader left a book, saved per library
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadingPosition {
+    pub chapter: usize,
+    pub paragraph: usize,
+}
+
+/// A bookmark or highlight made in the reader, saved per library with the book's id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub chapter: usize,
+    /// The bookmarked paragraph, or the first one highlighted
+    pub paragraph: usize,
+    /// The last paragraph highlighted; None for a bookmark
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<usize>,
+    /// The chapter's title when it was made
+    pub chapter_title: String,
+    /// The highlighted paragraphs, one per line, or the start of the bookmarked one
+    pub text: String,
+    pub created: DateTime<Utc>,
+}
+
+impl Annotation {
+    pub fn is_highlight(&self) -> bool {
+        self.end.is_some()
+    }
+
+    /// Whether this highlight covers `paragraph` of `chapter`
+    fn highlights(&self, chapter: usize, paragraph: usize) -> bool {
+        self.chapter == chapter && self.end.is_some_and(|end| (self.paragraph..=end).contains(&paragraph))
+    }
+
+    /// One line for lists, e.g. "🔖 Chapter 2: The Middle — It was a dark…"
+    pub fn summary(&self) -> String {
+        let marker = if self.is_highlight() { "🖍" } else { "🔖" };
+        let text = self.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        format!("{} {} — {}", marker, self.chapter_title, text)
+    }
+}
+
+/// The annotations as a Markdown document: highlights quoted and bookmarks listed, under the
+/// title of each chapter in reading order
+pub fn annotations_markdown(title: &str, authors: &str, annotations: &[Annotation]) -> String {
+    let mut sorted: Vec<&Annotation> = annotations.iter().collect();
+    sorted.sort_by_key(|a| (a.chapter, a.paragraph));
+
+    let mut markdown = format!("# {}\n\n", title);
+    if !authors.is_empty() {
+        markdown.push_str(&format!("*{}*\n\n", authors));
+    }
+    let mut chapter = None;
+    for annotation in sorted {
+        if chapter != Some(annotation.chapter) {
+            chapter = Some(annotation.chapter);
+            markdown.push_str(&format!("## {}\n\n", annotation.chapter_title));
+        }
+        if annotation.is_highlight() {
+            let quoted: Vec<String> = annotation.text.lines().map(|line| format!("> {}", line)).collect();
+            markdown.push_str(&quoted.join("\n>\n"));
+            markdown.push_str("\n\n");
+        } else {
+            markdown.push_str(&format!("- 🔖 Bookmark: {}\n\n", annotation.text));
+        }
+    }
+    markdown
+}
+
+/// A book open in `AppMode::Reader`
+#[derive(Debug, Clone)]
+pub struct Reader {
+    pub book_id: i32,
+    pub title: String,
+    pub chapters: Vec<Chapter>,
+    pub chapter: usize,
+    /// Paragraph at the top of the page
+    pub paragraph: usize,
+    /// Lines of that paragraph scrolled past
+    pub line: usize,
+    /// Width and height of the text when last drawn, which paging goes by
+    pub viewport: Cell<(u16, u16)>,
+    /// Mode to go back to when the reader is closed
+    pub return_to: AppMode,
+    /// The phrase of the content search the book was opened at, highlighted on the page
+    pub found: Option<String>,
+    /// The book's bookmarks and highlights, in reading order
+    pub annotations: Vec<Annotation>,
+    /// The first and last paragraph of the highlight being made, from where `v` was pressed
+    /// to the cursor
+    pub selection: Option<(usize, usize)>,
+}
+
+impl Reader {
+    pub fn new(book_id: i32, title: String, chapters: Vec<Chapter>, position: Option<ReadingPosition>, return_to: AppMode) -> Self {
+        let mut reader = Reader {
+            book_id,
+            title,
+            chapters,
+            chapter: 0,
+            paragraph: 0,
+            line: 0,
+            viewport: Cell::new((80, 24)),
+            return_to,
+            found: None,
+            annotations: Vec::new(),
+            selection: None,
+        };
+        if let Some(position) = position {
+            reader.chapter = position.chapter.min(reader.chapters.len().saturating_sub(1));
+            reader.paragraph = position.paragraph.min(reader.current().paragraphs.len().saturating_sub(1));
+        }
+        reader
+    }
+
+    pub fn position(&self) -> ReadingPosition {
+        ReadingPosition { chapter: self.chapter, paragraph: self.paragraph }
+    }
+
+    pub fn current(&self) -> &Chapter {
+        &self.chapters[self.chapter]
+    }
+
+    /// The lines filling a `width` by `height` page from the current position, each with the
+    /// paragraph it belongs to
+    pub fn page(&self, width: u16, height: u16) -> Vec<(usize, String)> {
+        let paragraphs = &self.current().paragraphs;
+        let first = self.paragraph.min(paragraphs.len());
+        let mut lines = Vec::with_capacity(height as usize);
+        let mut skip = self.line;
+        for (index, paragraph) in paragraphs.iter().enumerate().skip(first) {
+            for line in wrap(paragraph, width).into_iter().chain(std::iter::once(String::new())) {
+                if skip > 0 {
+                    skip -= 1;
+                    continue;
+                }
+                if lines.len() == height as usize {
+                    return lines;
+                }
+                lines.push((index, line));
+            }
+        }
+        lines
+    }
+
+    /// Whether the last page of the chapter is showing
+    fn at_chapter_end(&self) -> bool {
+        let (width, height) = self.viewport.get();
+        self.page(width, height.saturating_add(1)).len() <= height as usize
+    }
+
+    /// Scroll by `lines`, down when positive, without leaving the chapter
+    pub fn scroll_by(&mut self, lines: isize) {
+        let (width, height) = self.viewport.get();
+        if lines > 0 && self.at_chapter_end() {
+            return;
+        }
+        self.scroll_within(width, lines);
+        // Never scroll so far that the page shows nothing
+        if self.paragraph >= self.current().paragraphs.len() {
+            self.paragraph = self.current().paragraphs.len();
+            self.line = 0;
+            self.scroll_within(width, -(height as isize - 1));
+        }
+    }
+
+    fn scroll_within(&mut self, width: u16, lines: isize) {
+        let paragraphs = &self.chapters[self.chapter].paragraphs;
+        let height_of = |p: usize| paragraphs.get(p).map_or(0, |text| wrap(text, width).len() + 1);
+        let (mut paragraph, mut line) = (self.paragraph, self.line);
+        let mut remaining = lines.unsigned_abs();
+        if lines >= 0 {
+            while remaining > 0 && paragraph < paragraphs.len() {
+                let left = height_of(paragraph) - line;
+                if remaining < left {
+                    line += remaining;
+                    remaining = 0;
+                } else {
+                    remaining -= left;
+                    paragraph += 1;
+                    line = 0;
+                }
+            }
+        } else {
+            while remaining > 0 {
+                if line > 0 {
+                    let taken = line.min(remaining);
+                    line -= taken;
+                    remaining -= taken;
+                } else if paragraph > 0 {
+                    paragraph -= 1;
+                    line = height_of(paragraph);
+                } else {
+                    break;
+                }
+            }
+        }
+        self.paragraph = paragraph;
+        self.line = line;
+    }
+
+    /// The next page, or the start of the next chapter after the last one
+    pub fn next_page(&mut self) {
+        if self.at_chapter_end() {
+            self.next_chapter();
+        } else {
+            let height = self.viewport.get().1 as isize;
+            self.scroll_by((height - 1).max(1));
+        }
+    }
+
+    /// The previous page, or the last page of the previous chapter from the first one
+    pub fn previous_page(&mut self) {
+        if self.paragraph == 0 && self.line == 0 {
+            if self.chapter > 0 {
+                self.chapter -= 1;
+                self.end_of_chapter();
+            }
+        } else {
+            let height = self.viewport.get().1 as isize;
+            self.scroll_by(-(height - 1).max(1));
+        }
+    }
+
+    pub fn next_chapter(&mut self) {
+        if self.chapter + 1 < self.chapters.len() {
+            self.go_to_chapter(self.chapter + 1);
+        }
+    }
+
+    pub fn previous_chapter(&mut self) {
+        self.go_to_chapter(self.chapter.saturating_sub(1));
+    }
+
+    pub fn go_to_chapter(&mut self, chapter: usize) {
+        self.chapter = chapter.min(self.chapters.len().saturating_sub(1));
+        self.paragraph = 0;
+        self.line = 0;
+    }
+
+    /// Go to the first paragraph of `chapter` holding `phrase`, or the chapter's start when
+    /// none does, e.g. since the file changed after it was indexed
+    pub fn go_to_match(&mut self, chapter: usize, phrase: &str) {
+        self.go_to_chapter(chapter);
+        let wanted = format!(" {} ", comparable(phrase));
+        if let Some(paragraph) = self.current().paragraphs.iter().position(|p| format!(" {} ", comparable(p)).contains(&wanted)) {
+            self.paragraph = paragraph;
+        }
+        self.found = Some(phrase.to_string());
+    }
+
+    /// Bookmark the paragraph at the top of the page, or remove its bookmark; true when added
+    pub fn toggle_bookmark(&mut self) -> bool {
+        let Some(paragraph) = self.top_paragraph() else {
+            return false;
+        };
+        let chapter = self.chapter;
+        if let Some(at) = self.annotations.iter().position(|a| !a.is_highlight() && a.chapter == chapter && a.paragraph == paragraph) {
+            self.annotations.remove(at);
+            return false;
+        }
+        let text = self.current().paragraphs[paragraph].chars().take(EXCERPT_CHARS).collect::<String>();
+        let excerpt = if text.chars().count() < self.current().paragraphs[paragraph].chars().count() { format!("{}…", text) } else { text };
+        self.add(Annotation {
+            chapter,
+            paragraph,
+            end: None,
+            chapter_title: self.current().title.clone(),
+            text: excerpt,
+            created: Utc::now(),
+        });
+        true
+    }
+
+    /// Whether the paragraph at the top of the page is bookmarked
+    pub fn is_bookmarked(&self) -> bool {
+        let paragraph = self.top_paragraph();
+        self.annotations.iter().any(|a| !a.is_highlight() && a.chapter == self.chapter && Some(a.paragraph) == paragraph)
+    }
+
+    /// Whether `paragraph` of the current chapter is highlighted
+    pub fn is_highlighted(&self, paragraph: usize) -> bool {
+        self.annotations.iter().any(|a| a.highlights(self.chapter, paragraph))
+    }
+
+    /// Whether `paragraph` is in the highlight being made
+    pub fn is_selected(&self, paragraph: usize) -> bool {
+        self.selection.is_some_and(|(anchor, cursor)| (anchor.min(cursor)..=anchor.max(cursor)).contains(&paragraph))
+    }
+
+    /// Start a highlight at the paragraph at the top of the page
+    pub fn start_selection(&mut self) {
+        self.selection = self.top_paragraph().map(|paragraph| (paragraph, paragraph));
+    }
+
+    /// Move the end of the highlight being made by `delta` paragraphs, keeping it on the page
+    pub fn move_selection(&mut self, delta: isize) {
+        let Some((anchor, cursor)) = self.selection else {
+            return;
+        };
+        let last = self.current().paragraphs.len().saturating_sub(1);
+        let cursor = cursor.saturating_add_signed(delta).min(last);
+        self.selection = Some((anchor, cursor));
+        if cursor < self.paragraph || (cursor == self.paragraph && self.line > 0) {
+            self.paragraph = cursor;
+            self.line = 0;
+        } else {
+            let (width, height) = self.viewport.get();
+            while self.page(width, height).last().is_some_and(|(shown, _)| *shown < cursor) && !self.at_chapter_end() {
+                self.scroll_by(1);
+            }
+        }
+    }
+
+    /// Save the highlight being made; false when there was none
+    pub fn finish_selection(&mut self) -> bool {
+        let Some((anchor, cursor)) = self.selection.take() else {
+            return false;
+        };
+        let (start, end) = (anchor.min(cursor), anchor.max(cursor));
+        let chapter = self.chapter;
+        if self.annotations.iter().any(|a| a.chapter == chapter && a.paragraph == start && a.end == Some(end)) {
+            return false;
+        }
+        self.add(Annotation {
+            chapter,
+            paragraph: start,
+            end: Some(end),
+            chapter_title: self.current().title.clone(),
+            text: self.current().paragraphs[start..=end].join("\n"),
+            created: Utc::now(),
+        });
+        true
+    }
+
+    /// Go to where an annotation was made
+    pub fn go_to_annotation(&mut self, annotation: &Annotation) {
+        self.go_to_chapter(annotation.chapter);
+        self.paragraph = annotation.paragraph.min(self.current().paragraphs.len().saturating_sub(1));
+    }
+
+    fn top_paragraph(&self) -> Option<usize> {
+        let paragraphs = self.current().paragraphs.len();
+        (paragraphs > 0).then(|| self.paragraph.min(paragraphs - 1))
+    }
+
+    fn add(&mut self, annotation: Annotation) {
+        self.annotations.push(annotation);
+        self.annotations.sort_by_key(|a| (a.chapter, a.paragraph));
+    }
+
+    /// The last page of the current chapter
+    pub fn end_of_chapter(&mut self) {
+        let (width, height) = self.viewport.get();
+        self.paragraph = self.current().paragraphs.len();
+        self.line = 0;
+        self.scroll_within(width, -(height as isize));
+    }
+
+    /// How far through the book the top of the page is, by paragraphs, from 0 to 1
+    pub fn progress(&self) -> f64 {
+        let total: usize = self.chapters.iter().map(|c| c.paragraphs.len()).sum();
+        let before: usize = self.chapters[..self.chapter].iter().map(|c| c.paragraphs.len()).sum::<usize>() + self.paragraph;
+        if total == 0 {
+            0.0
+        } else {
+            before as f64 / total as f64
+        }
+    }
+}
+
+/// The words of `text` in lowercase, one space apart, as the content index compares them
+fn comparable(text: &str) -> String {
+    let lower: String = text.chars().map(|c| if c.is_alphanumeric() { c } else { ' ' }).collect::<String>().to_lowercase();
+    lower.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Character positions in `line` of each occurrence of `phrase` as whole words, ignoring case
+pub fn phrase_positions(line: &str, phrase: &str) -> Vec<usize> {
+    let lower = |text: &str| text.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect::<Vec<char>>();
+    let (line, phrase) = (lower(line), lower(phrase.trim()));
+    if phrase.is_empty() {
+        return Vec::new();
+    }
+    let boundary = |at: Option<&char>| at.is_none_or(|c| !c.is_alphanumeric());
+    let mut positions = Vec::new();
+    for start in 0..line.len().saturating_sub(phrase.len() - 1) {
+        let end = start + phrase.len();
+        let whole = boundary(start.checked_sub(1).and_then(|i| line.get(i))) && boundary(line.get(end));
+        if whole && line[start..end] == phrase[..] {
+            positions.extend(start..end);
+        }
+    }
+    positions
+}
+
+/// Break `text` into lines of at most `width` columns, at spaces where there are any
+pub fn wrap(text: &str, width: u16) -> Vec<String> {
+    let width = width.max(1) as usize;
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+    for word in text.split(' ').filter(|w| !w.is_empty()) {
+        let word_width: usize = word.chars().map(|c| c.width().unwrap_or(0)).sum();
+        if line_width > 0 && line_width + 1 + word_width <= width {
+            line.push(' ');
+            line.push_str(word);
+            line_width += 1 + word_width;
+            continue;
+        }
+        if line_width > 0 {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        }
+        // Text without spaces, like Chinese or Japanese, is broken anywhere
+        for c in word.chars() {
+            let c_width = c.width().unwrap_or(0);
+            if line_width + c_width > width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            line.push(c);
+            line_width += c_width;
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Read a book's EPUB or TXT file as chapters
+pub fn load_chapters(path: &Path, format: &str) -> Result<Vec<Chapter>> {
+    let chapters = match format.to_uppercase().as_str() {
+        "EPUB" => epub_chapters(path)?,
+        "TXT" => txt_chapters(path)?,
+        other => return Err(Error::Other(format!("{} books can't be read in tuilibre", other))),
+    };
+    if chapters.is_empty() {
+        return Err(Error::Other(format!("No text found in {}", path.display())));
+    }
+    Ok(chapters)
+}
+
+/// Split at lines that look like chapter headings, or keep the whole file as one chapter
+fn txt_chapters(path: &Path) -> Result<Vec<Chapter>> {
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let text = String::from_utf8_lossy(&bytes).replace("\r\n", "\n");
+
+    let mut chapters = vec![Chapter { title: String::new(), paragraphs: Vec::new() }];
+    for block in text.split("\n\n") {
+        let paragraph = block.split_whitespace().collect::<Vec<_>>().join(" ");
+        if paragraph.is_empty() {
+            continue;
+        }
+        let lower = paragraph.to_lowercase();
+        let is_heading = paragraph.chars().count() <= 60 && TXT_CHAPTER_PREFIXES.iter().any(|p| lower.starts_with(p));
+        if is_heading && !chapters.last().is_some_and(|c| c.paragraphs.is_empty()) {
+            chapters.push(Chapter { title: paragraph.clone(), paragraphs: Vec::new() });
+        } else if is_heading {
+            if let Some(chapter) = chapters.last_mut() {
+                chapter.title = paragraph.clone();
+            }
+        }
+        if let Some(chapter) = chapters.last_mut() {
+            chapter.paragraphs.push(paragraph);
+        }
+    }
+    chapters.retain(|c| !c.paragraphs.is_empty());
+    for (i, chapter) in chapters.iter_mut().enumerate() {
+        if chapter.title.is_empty() {
+            chapter.title = if i == 0 { "Beginning".to_string() } else { format!("Part {}", i + 1) };
+        }
+    }
+    Ok(chapters)
+}
+
+/// The documents of the EPUB's spine in reading order, skipping those without text
+fn epub_chapters(path: &Path) -> Result<Vec<Chapter>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| invalid_epub(path, e))?;
+
+    let container = read_entry(&mut archive, "META-INF/container.xml").map_err(|e| invalid_epub(path, e))?;
+    let opf_path = {
+        let doc = roxmltree::Document::parse(&container).map_err(|e| invalid_epub(path, e))?;
+        doc.descendants()
+            .find(|n| n.has_tag_name("rootfile"))
+            .and_then(|n| n.attribute("full-path"))
+            .map(str::to_string)
+            .ok_or_else(|| invalid_epub(path, "no rootfile in container.xml"))?
+    };
+    let opf = read_entry(&mut archive, &opf_path).map_err(|e| invalid_epub(path, e))?;
+    let base = Path::new(&opf_path).parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let documents: Vec<PathBuf> = {
+        let doc = roxmltree::Document::parse(&opf).map_err(|e| invalid_epub(path, e))?;
+        let manifest: Vec<(&str, &str)> = doc
+            .descendants()
+            .filter(|n| n.has_tag_name("item"))
+            .filter_map(|n| Some((n.attribute("id")?, n.attribute("href")?)))
+            .collect();
+        doc.descendants()
+            .filter(|n| n.has_tag_name("itemref"))
+            .filter_map(|n| n.attribute("idref"))
+            .filter_map(|idref| manifest.iter().find(|(id, _)| *id == idref))
+            .map(|(_, href)| {
+                let href = href.split('#').next().unwrap_or_default();
+                base.join(percent_encoding::percent_decode_str(href).decode_utf8_lossy().as_ref())
+            })
+            .collect()
+    };
+
+    let mut chapters = Vec::new();
+    for document in documents {
+        let name = document.to_string_lossy().replace('\\', "/");
+        let Ok(html) = read_entry(&mut archive, &name) else {
+            tracing::debug!("Missing EPUB document {} in {}", name, path.display());
+            continue;
+        };
+        let (heading, paragraphs) = html_paragraphs(&html);
+        if paragraphs.is_empty() {
+            continue;
+        }
+        let title = heading.unwrap_or_else(|| format!("Chapter {}", chapters.len() + 1));
+        chapters.push(Chapter { title, paragraphs });
+    }
+    Ok(chapters)
+}
+
+fn read_entry(archive: &mut zip::ZipArchive<std::fs::File>, name: &str) -> std::result::Result<String, Box<dyn std::error::Error>> {
+    let mut bytes = Vec::new();
+    archive.by_name(name)?.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn invalid_epub(path: &Path, error: impl std::fmt::Display) -> Error {
+    Error::Parse { context: format!("Invalid EPUB: {}", path.display()), message: error.to_string() }
+}
@@ -0,0 +1,73 @@
+//! Orders the book list can be sorted in
+//!
+//! `load_books` returns books in title-sort order, which `all_books` keeps; any other order
+//! is applied to the visible list from the ids `Database::sorted_book_ids` ranks.
+
+use serde::{Deserialize, Serialize};
+
+/// What the book list is sorted by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    Title,
+    Author,
+    DateAdded,
+    Series,
+    Rating,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 5] = [SortKey::Title, SortKey::Author, SortKey::DateAdded, SortKey::Series, SortKey::Rating];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::Title => "Title",
+            SortKey::Author => "Author",
+            SortKey::DateAdded => "Date added",
+            SortKey::Series => "Series",
+            SortKey::Rating => "Rating",
+        }
+    }
+
+    /// The key after this one, wrapping around
+    pub fn next(self) -> SortKey {
+        let index = SortKey::ALL.iter().position(|&k| k == self).unwrap_or(0);
+        SortKey::ALL[(index + 1) % SortKey::ALL.len()]
+    }
+
+    /// Newest and best rated come first when switching to these
+    fn descending_by_default(self) -> bool {
+        matches!(self, SortKey::DateAdded | SortKey::Rating)
+    }
+}
+
+/// The key the book list is sorted by and in which direction; ties stay in title order
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookSort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl BookSort {
+    /// The order `load_books` returns, which needs no re-sorting
+    pub fn is_default(&self) -> bool {
+        *self == BookSort::default()
+    }
+
+    /// Switch to the next key, in its natural direction
+    pub fn cycle(&mut self) {
+        self.key = self.key.next();
+        self.descending = self.key.descending_by_default();
+    }
+
+    pub fn reverse(&mut self) {
+        self.descending = !self.descending;
+    }
+
+    /// e.g. "Date added ↓"
+    pub fn label(&self) -> String {
+        format!("{} {}", self.key.label(), if self.descending { "↓" } else { "↑" })
+    }
+}
@@ -0,0 +1,67 @@
+//! The tag browser, a pane beside the book list listing every tag like calibre's
+//!
+//! Picking a tag filters the list by it through an ordinary `Filter::Tag` chip, so the
+//! browser keeps no filter of its own and removing the chip undoes it.
+
+use super::{Filter, FilterClause};
+
+/// A tag and how many books carry it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagCount {
+    pub name: String,
+    pub count: usize,
+}
+
+/// The tag pane's list and whether it has the keyboard
+#[derive(Debug, Clone, Default)]
+pub struct TagBrowser {
+    pub tags: Vec<TagCount>,
+    pub selected: usize,
+    /// Keys go to the pane rather than the book list
+    pub focused: bool,
+}
+
+impl TagBrowser {
+    pub fn new(tags: Vec<TagCount>) -> Self {
+        TagBrowser { tags, selected: 0, focused: true }
+    }
+
+    /// Swap in freshly counted tags, staying on the selected one if it is still there
+    pub fn set_tags(&mut self, tags: Vec<TagCount>) {
+        let selected = self.selected_tag().map(|tag| tag.name.clone());
+        self.tags = tags;
+        self.selected = selected
+            .and_then(|name| self.tags.iter().position(|tag| tag.name == name))
+            .unwrap_or(self.selected)
+            .min(self.tags.len().saturating_sub(1));
+    }
+
+    pub fn selected_tag(&self) -> Option<&TagCount> {
+        self.tags.get(self.selected)
+    }
+
+    pub fn select_next_by(&mut self, n: usize) {
+        self.selected = (self.selected + n).min(self.tags.len().saturating_sub(1));
+    }
+
+    pub fn select_previous_by(&mut self, n: usize) {
+        self.selected = self.selected.saturating_sub(n);
+    }
+}
+
+/// Filter by `tag` in place of any other tag (negated ones stay), or drop the filter if it is the one already applied
+///
+/// Returns whether the tag is now filtered by.
+pub fn toggle_tag_filter(filters: &mut Vec<FilterClause>, tag: &str) -> bool {
+    let already = filters.iter().any(|c| !c.negated && c.filter == Filter::Tag(tag.to_string()));
+    filters.retain(|c| c.negated || !matches!(c.filter, Filter::Tag(_)));
+    if !already {
+        filters.push(FilterClause::new(Filter::Tag(tag.to_string())));
+    }
+    !already
+}
+
+/// Whether the list is filtered by `tag`, to highlight it in the pane
+pub fn is_tag_filtered(filters: &[FilterClause], tag: &str) -> bool {
+    filters.iter().any(|c| !c.negated && matches!(&c.filter, Filter::Tag(t) if t == tag))
+}
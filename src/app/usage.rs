@@ -0,0 +1,189 @@
+//! Disk usage of the library by author, tag, series or format, like ncdu for books
+//!
+//! A book by two authors or with three tags counts in full under each, so the groups can add
+//! up to more than the library; grouped by format, only that format's files count.
+
+use std::collections::{HashMap, HashSet};
+
+/// Books marked at once by "mark the biggest"
+pub const BIGGEST_MARKED: usize = 10;
+
+/// What the files are added up by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UsageGrouping {
+    #[default]
+    Author,
+    Tag,
+    Series,
+    Format,
+}
+
+impl UsageGrouping {
+    pub fn label(&self) -> &'static str {
+        match self {
+            UsageGrouping::Author => "author",
+            UsageGrouping::Tag => "tag",
+            UsageGrouping::Series => "series",
+            UsageGrouping::Format => "format",
+        }
+    }
+
+    /// Name of the group of books that have none, e.g. no tags
+    pub fn none_label(&self) -> &'static str {
+        match self {
+            UsageGrouping::Author => "(no author)",
+            UsageGrouping::Tag => "(no tags)",
+            UsageGrouping::Series => "(no series)",
+            UsageGrouping::Format => "(no format)",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            UsageGrouping::Author => UsageGrouping::Tag,
+            UsageGrouping::Tag => UsageGrouping::Series,
+            UsageGrouping::Series => UsageGrouping::Format,
+            UsageGrouping::Format => UsageGrouping::Author,
+        }
+    }
+}
+
+/// A book's files within a group
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageBook {
+    pub id: i32,
+    pub title: String,
+    pub bytes: u64,
+    pub files: usize,
+}
+
+/// Books sharing an author, tag, series or format, biggest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageGroup {
+    pub name: String,
+    pub bytes: u64,
+    pub books: Vec<UsageBook>,
+}
+
+/// The disk usage view: groups biggest first, or the books of the one drilled into
+#[derive(Debug, Clone)]
+pub struct UsageView {
+    pub grouping: UsageGrouping,
+    pub groups: Vec<UsageGroup>,
+    /// Bytes of every file in the library, each counted once
+    pub total: u64,
+    pub selected: usize,
+    /// The group drilled into, its books listed in place of the groups
+    pub open: Option<usize>,
+    pub book_selected: usize,
+}
+
+impl UsageView {
+    pub fn new(grouping: UsageGrouping, groups: Vec<UsageGroup>, total: u64) -> Self {
+        UsageView { grouping, groups, total, selected: 0, open: None, book_selected: 0 }
+    }
+
+    pub fn selected_group(&self) -> Option<&UsageGroup> {
+        self.groups.get(self.selected)
+    }
+
+    pub fn open_group(&self) -> Option<&UsageGroup> {
+        self.open.and_then(|i| self.groups.get(i))
+    }
+
+    pub fn selected_book(&self) -> Option<&UsageBook> {
+        self.open_group().and_then(|group| group.books.get(self.book_selected))
+    }
+
+    /// Rows of the list on screen: the open group's books or the groups
+    pub fn len(&self) -> usize {
+        match self.open_group() {
+            Some(group) => group.books.len(),
+            None => self.groups.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The cursor of the list on screen
+    pub fn cursor(&self) -> usize {
+        if self.open.is_some() { self.book_selected } else { self.selected }
+    }
+
+    /// Move the cursor of the list on screen by `delta` rows
+    pub fn move_by(&mut self, delta: isize) {
+        let last = self.len().saturating_sub(1);
+        let cursor = if self.open.is_some() { &mut self.book_selected } else { &mut self.selected };
+        *cursor = cursor.saturating_add_signed(delta).min(last);
+    }
+
+    /// List the selected group's books
+    pub fn drill_in(&mut self) {
+        if self.selected_group().is_some() {
+            self.open = Some(self.selected);
+            self.book_selected = 0;
+        }
+    }
+
+    /// Back to the groups
+    pub fn drill_out(&mut self) {
+        self.open = None;
+    }
+
+    /// Ids of the selected book, or of every book of the selected group
+    pub fn ids_at_cursor(&self) -> Vec<i32> {
+        match (self.open_group(), self.selected_group()) {
+            (Some(_), _) => self.selected_book().map(|book| vec![book.id]).unwrap_or_default(),
+            (None, Some(group)) => group.books.iter().map(|book| book.id).collect(),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    /// The `count` biggest books of the open group, or of the whole library, with their bytes
+    pub fn biggest(&self, count: usize) -> Vec<(i32, u64)> {
+        let mut books: Vec<(i32, u64)> = match self.open_group() {
+            Some(group) => group.books.iter().map(|book| (book.id, book.bytes)).collect(),
+            None if self.grouping == UsageGrouping::Format => {
+                // A book's formats are in different groups, so add them up first
+                let mut sizes = HashMap::new();
+                for book in self.groups.iter().flat_map(|group| &group.books) {
+                    *sizes.entry(book.id).or_insert(0) += book.bytes;
+                }
+                sizes.into_iter().collect()
+            }
+            None => {
+                let mut seen = HashSet::new();
+                self.groups
+                    .iter()
+                    .flat_map(|group| &group.books)
+                    .filter(|book| seen.insert(book.id))
+                    .map(|book| (book.id, book.bytes))
+                    .collect()
+            }
+        };
+        books.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        books.truncate(count);
+        books
+    }
+}
+
+/// e.g. "812 B", "4.2 MB" or "1.3 GB", in powers of 1024 like the rest of tuilibre
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if size < 10.0 {
+        format!("{:.1} {}", size, UNITS[unit])
+    } else {
+        format!("{:.0} {}", size, UNITS[unit])
+    }
+}
@@ -0,0 +1,85 @@
+//! Reading state kept by calibre-web in its own `app.db`, for people moving over from it
+//!
+//! calibre-web refers to books by their calibre id, so its state applies to the library it
+//! was serving as-is.
+
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{Connection, SqliteConnection};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use crate::error::{Context, Error, Result};
+use crate::settings::ReadStatus;
+
+/// calibre-web's `read_status` values
+const STATUS_FINISHED: i64 = 1;
+const STATUS_IN_PROGRESS: i64 = 2;
+
+/// An account in calibre-web
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibreWebUser {
+    pub id: i64,
+    pub name: String,
+}
+
+/// One user's reading status and shelves, by calibre book id
+#[derive(Debug, Clone, Default)]
+pub struct CalibreWebState {
+    pub reading: BTreeMap<i32, ReadStatus>,
+    pub shelves: BTreeMap<String, BTreeSet<i32>>,
+}
+
+async fn connect(app_db: &Path) -> Result<SqliteConnection> {
+    if !app_db.is_file() {
+        return Err(Error::FileMissing(app_db.to_path_buf()));
+    }
+    let options = SqliteConnectOptions::new().filename(app_db).read_only(true);
+    SqliteConnection::connect_with(&options)
+        .await
+        .with_context(|| format!("Failed to open {}", app_db.display()))
+}
+
+/// Accounts in calibre-web's database, in the order they were created
+pub async fn load_users(app_db: &Path) -> Result<Vec<CalibreWebUser>> {
+    let mut conn = connect(app_db).await?;
+    let users: Vec<(i64, String)> = sqlx::query_as("SELECT id, name FROM user ORDER BY id")
+        .fetch_all(&mut conn)
+        .await?;
+    conn.close().await?;
+    // calibre-web's built-in guest account never reads anything
+    Ok(users
+        .into_iter()
+        .filter(|(_, name)| name != "Guest")
+        .map(|(id, name)| CalibreWebUser { id, name })
+        .collect())
+}
+
+/// What `user` read or is reading, and the shelves they own
+pub async fn load_state(app_db: &Path, user: i64) -> Result<CalibreWebState> {
+    let mut conn = connect(app_db).await?;
+    let statuses: Vec<(i32, i64)> = sqlx::query_as("SELECT book_id, read_status FROM book_read_link WHERE user_id = ?")
+        .bind(user)
+        .fetch_all(&mut conn)
+        .await?;
+    let shelved: Vec<(String, i32)> = sqlx::query_as(
+        "SELECT s.name, l.book_id FROM shelf s JOIN book_shelf_link l ON l.shelf = s.id WHERE s.user_id = ?",
+    )
+    .bind(user)
+    .fetch_all(&mut conn)
+    .await?;
+    conn.close().await?;
+
+    let mut state = CalibreWebState::default();
+    for (book, status) in statuses {
+        let status = match status {
+            STATUS_FINISHED => ReadStatus::Read,
+            STATUS_IN_PROGRESS => ReadStatus::Reading,
+            _ => continue,
+        };
+        state.reading.insert(book, status);
+    }
+    for (shelf, book) in shelved {
+        state.shelves.entry(shelf).or_default().insert(book);
+    }
+    Ok(state)
+}
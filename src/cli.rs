@@ -0,0 +1,101 @@
+//! Exit codes and error reports of the non-interactive subcommands, for scripts wrapping tuilibre
+//!
+//! The codes are stable: scripts can tell a missing library from a locked one, or from a
+//! command that ran fine but found nothing to work on. With `--output json` the error is
+//! reported on stderr as `{"error": {"kind": ..., "exit_code": ..., "message": ...}}`.
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// How a subcommand failed; success is 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitStatus {
+    /// Anything not covered below, e.g. some conversions failed
+    Failure,
+    /// Bad arguments, as clap reports them
+    Usage,
+    /// No calibre library at the given path
+    LibraryNotFound,
+    /// The command ran but matched nothing to work on
+    NoResults,
+    /// Another program holds the library's write lock
+    LibraryLocked,
+    /// `metadata.db` couldn't be read or written
+    Database,
+    /// No book has the id given
+    BookNotFound,
+    /// Interrupted with Ctrl-C
+    Cancelled,
+}
+
+impl ExitStatus {
+    pub fn code(self) -> i32 {
+        match self {
+            ExitStatus::Failure => 1,
+            ExitStatus::Usage => 2,
+            ExitStatus::LibraryNotFound => 3,
+            ExitStatus::NoResults => 4,
+            ExitStatus::LibraryLocked => 5,
+            ExitStatus::Database => 6,
+            ExitStatus::BookNotFound => 7,
+            ExitStatus::Cancelled => 130,
+        }
+    }
+}
+
+/// How subcommands print what they found and report errors
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Readable text, tab-separated where there are columns; errors on stderr
+    #[default]
+    Text,
+    /// JSON on stdout; errors as one JSON object on stderr
+    Json,
+}
+
+/// Why a subcommand stopped, with the exit code it ends the process with
+#[derive(Debug, Clone, Serialize)]
+pub struct CliError {
+    #[serde(rename = "kind")]
+    pub status: ExitStatus,
+    pub exit_code: i32,
+    pub message: String,
+}
+
+impl CliError {
+    pub fn new(status: ExitStatus, message: impl Into<String>) -> Self {
+        CliError { status, exit_code: status.code(), message: message.into() }
+    }
+
+    /// Print the error the way `output` asks for
+    pub fn report(&self, output: OutputFormat) {
+        match output {
+            OutputFormat::Text if self.status == ExitStatus::NoResults => eprintln!("{}", self.message),
+            OutputFormat::Text => eprintln!("❌ Error: {}", self.message),
+            OutputFormat::Json => {
+                let report = serde_json::json!({ "error": self });
+                eprintln!("{}", report);
+            }
+        }
+    }
+}
+
+/// Whether `--output json` is among the raw arguments, for reporting errors parsing the rest
+pub fn json_requested() -> bool {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter().any(|a| a == "--output=json") || args.windows(2).any(|pair| pair[0] == "--output" && pair[1] == "json")
+}
+
+/// Sort a failure by the library error that caused it, if any
+impl From<anyhow::Error> for CliError {
+    fn from(error: anyhow::Error) -> Self {
+        let status = match error.chain().find_map(|e| e.downcast_ref::<tuilibre::Error>()) {
+            Some(tuilibre::Error::LibraryNotFound(_)) => ExitStatus::LibraryNotFound,
+            Some(tuilibre::Error::LibraryLocked(_)) => ExitStatus::LibraryLocked,
+            Some(tuilibre::Error::DatabaseError(_) | tuilibre::Error::SchemaUnsupported(_)) => ExitStatus::Database,
+            _ => ExitStatus::Failure,
+        };
+        CliError::new(status, format!("{:#}", error))
+    }
+}
@@ -0,0 +1,536 @@
+use crate::error::{Context, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::app::TableColumn;
+use crate::metadata::MetadataSource;
+use crate::ui::color::ColorSupport;
+use crate::ui::cover::CoverProtocol;
+use crate::ui::layout::Density;
+use crate::ui::theme::{PaletteOverrides, ThemeName};
+use crate::utils::locale::Locale;
+use crate::utils::time::TimeFormat;
+
+/// User configuration loaded from `~/.config/tuilibre/config.toml`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Device profiles keyed by a user-chosen name, e.g. `[devices.kindle]`
+    pub devices: BTreeMap<String, DeviceConfig>,
+    pub email: EmailConfig,
+    pub opds: OpdsConfig,
+    pub metadata: MetadataConfig,
+    pub export: ExportConfig,
+    pub cleanup: CleanupConfig,
+    pub format_policy: FormatPolicyConfig,
+    pub open: OpenConfig,
+    pub shelves: ShelvesConfig,
+    pub tracking: TrackingConfig,
+    pub searches: SearchesConfig,
+    pub quick_filters: QuickFiltersConfig,
+    pub navigation: NavigationConfig,
+    pub dashboard: DashboardConfig,
+    pub offline: OfflineConfig,
+    pub database: DatabaseConfig,
+    pub display: DisplayConfig,
+    pub accessibility: AccessibilityConfig,
+}
+
+/// How things are shown in the UI
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// "relative" (default), "absolute", or a strftime pattern such as "%d %b %Y"
+    pub time_format: TimeFormat,
+    /// Locale for numbers and dates, e.g. "de-DE"; taken from `LANG` when unset
+    pub locale: Option<String>,
+    /// "dark" (the default), "light", "solarized", "high-contrast" or "colorblind"; c switches
+    /// between them while tuilibre runs
+    pub theme: ThemeName,
+    /// Colors replacing the theme's by role, e.g. `accent = "#268bd2"` or `bad = "light-red"`
+    pub palette: PaletteOverrides,
+    /// Mark the selected row with "▶" instead of a background color
+    pub selection_marker: bool,
+    /// "truecolor", "256" or "16"; detected from `COLORTERM` and `TERM` when unset
+    pub colors: Option<ColorSupport>,
+    /// "comfortable", "compact" (one-line bars) or "borderless"
+    pub density: Density,
+    /// Columns of the table view in order, e.g. `["title", "authors", "modified"]`; follows
+    /// calibre's book list when unset
+    pub table_columns: Option<Vec<TableColumn>>,
+    /// How the details view shows covers: "auto" (default, asks the terminal), "kitty",
+    /// "sixel", "iterm2", "halfblocks" or "off"
+    #[serde(alias = "covers")]
+    pub graphics: CoverProtocol,
+    /// Tuck books of the same series that follow each other in the list under the first one
+    pub group_series: bool,
+}
+
+/// The start screen summarizing the libraries in history, shown instead of the plain selector
+/// once there are two or more
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DashboardConfig {
+    pub enabled: bool,
+    /// Recently added titles listed for each library
+    pub recent: usize,
+}
+
+impl Default for DashboardConfig {
+    fn default() -> Self {
+        DashboardConfig { enabled: true, recent: 3 }
+    }
+}
+
+/// Libraries on network shares and removable drives, which aren't always there
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OfflineConfig {
+    /// Keep a copy of `metadata.db` in `~/.cache/tuilibre` each time such a library is opened,
+    /// so it can be browsed read-only while its share or drive is absent
+    pub snapshots: bool,
+}
+
+impl Default for OfflineConfig {
+    fn default() -> Self {
+        OfflineConfig { snapshots: true }
+    }
+}
+
+/// Connecting to `metadata.db`, which may be on a slow network share
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    /// How long a query waits for calibre to finish writing before giving up
+    pub busy_timeout_secs: u64,
+    /// How long opening the library may take before giving up
+    pub connect_timeout_secs: u64,
+    /// Warn once when a query takes longer than this; 0 turns the warning off
+    pub slow_query_ms: u64,
+    /// A library with more books than this opens after the first page of them and loads the
+    /// rest while it's browsed, each book's authors, tags and description once it's shown; 0
+    /// loads every book up front
+    pub page_size: usize,
+    /// How often to look for changes calibre or calibre-web made to the library while it's
+    /// open, reloading the books they touched; 0 turns it off
+    pub refresh_secs: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig { busy_timeout_secs: 5, connect_timeout_secs: 30, slow_query_ms: 1500, page_size: 5000, refresh_secs: 2 }
+    }
+}
+
+impl DatabaseConfig {
+    pub fn busy_timeout(&self) -> Duration {
+        Duration::from_secs(self.busy_timeout_secs)
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.connect_timeout_secs)
+    }
+
+    pub fn slow_query(&self) -> Option<Duration> {
+        (self.slow_query_ms > 0).then(|| Duration::from_millis(self.slow_query_ms))
+    }
+
+    /// None when changes made elsewhere aren't looked for
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        (self.refresh_secs > 0).then(|| Duration::from_secs(self.refresh_secs))
+    }
+
+    /// Whether a library of `total` books is loaded a page at a time
+    pub fn pages(&self, total: usize) -> bool {
+        self.page_size > 0 && total > self.page_size
+    }
+}
+
+/// Moving around the book list
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NavigationConfig {
+    /// Typing lowercase letters jumps to the next book whose title starts with them, like a file
+    /// manager; lowercase hotkeys such as j, k and m then only work from the actions menu
+    pub type_ahead: bool,
+}
+
+/// Screen reader support
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilityConfig {
+    /// Plain text without emoji, single-column layouts, and the focused item announced in the status line
+    pub enabled: bool,
+    /// Draw on the alternate screen; turn off to keep everything in the terminal's scrollback
+    pub alternate_screen: bool,
+}
+
+impl Default for AccessibilityConfig {
+    fn default() -> Self {
+        AccessibilityConfig {
+            enabled: false,
+            alternate_screen: true,
+        }
+    }
+}
+
+impl DisplayConfig {
+    pub fn locale(&self) -> Locale {
+        Locale::resolve(self.locale.as_deref())
+    }
+}
+
+/// A configured e-reader
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceConfig {
+    /// Mount point, `*` matches any single path component (e.g. `/media/*/KOBOeReader`)
+    pub mount: String,
+    /// Preferred formats, best first
+    pub formats: Vec<String>,
+    /// Folder on the device where books are copied
+    pub folder: String,
+    /// Convert EPUBs to KEPUB while sending
+    pub kepubify: bool,
+}
+
+/// Send-to-email settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailConfig {
+    /// Sender address
+    pub from: String,
+    /// Command that reads a full message on stdin, e.g. `sendmail -t` or `msmtp -t`
+    pub command: String,
+    /// Recipients keyed by a user-chosen name, e.g. `[email.profiles.my-kindle]`
+    pub profiles: BTreeMap<String, EmailProfile>,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        EmailConfig {
+            from: String::new(),
+            command: "sendmail -t".to_string(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+/// One email recipient with its own format preference
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailProfile {
+    pub to: String,
+    /// Preferred formats, best first
+    pub formats: Vec<String>,
+    /// Optional subject (Kindle uses "convert" to request conversion)
+    pub subject: Option<String>,
+}
+
+/// Remote OPDS catalogs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpdsConfig {
+    /// Catalog root URLs keyed by display name
+    pub catalogs: BTreeMap<String, String>,
+}
+
+/// Copying books out of the library under readable names
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportConfig {
+    /// File name of each exported file; `/` makes folders. Fields: `{title}`, `{author}`,
+    /// `{authors}`, `{series}`, `{series_index}` (" #2", empty outside a series), `{ext}`, `{id}`
+    pub template: String,
+    /// Folder offered when exporting; `~/` is the home directory
+    pub directory: String,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        ExportConfig {
+            template: "{author} - {series}{series_index} - {title}.{ext}".to_string(),
+            directory: "~/Exports".to_string(),
+        }
+    }
+}
+
+/// What the cleanup assistant suggests pruning
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CleanupConfig {
+    /// Files listed as the largest
+    pub largest: usize,
+    /// Books added longer ago than this and never opened from tuilibre are listed as stale
+    pub stale_days: i64,
+    /// Formats to keep when a book has several, best first; the others are listed as extra.
+    /// A book with none of them keeps the format it was added in
+    pub keep_formats: Vec<String>,
+}
+
+impl Default for CleanupConfig {
+    fn default() -> Self {
+        CleanupConfig {
+            largest: 50,
+            stale_days: 365,
+            keep_formats: vec!["EPUB".to_string(), "AZW3".to_string(), "MOBI".to_string(), "PDF".to_string()],
+        }
+    }
+}
+
+/// Formats the library should hold, checked by the format policy audit
+///
+/// ```toml
+/// [format_policy]
+/// required = ["EPUB"]
+/// redundant = [{ keep = "EPUB", drop = "MOBI" }]
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FormatPolicyConfig {
+    /// Formats every book with files should have; missing ones can be converted to
+    pub required: Vec<String>,
+    /// Formats a book doesn't need once it has another
+    pub redundant: Vec<RedundantFormat>,
+}
+
+/// "Drop `drop` when the book also has `keep`"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedundantFormat {
+    pub keep: String,
+    pub drop: String,
+}
+
+impl FormatPolicyConfig {
+    pub fn is_empty(&self) -> bool {
+        self.required.is_empty() && self.redundant.is_empty()
+    }
+}
+
+/// Where shelves keep their books besides tuilibre's own settings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShelfBacking {
+    /// Only tuilibre knows about shelves
+    #[default]
+    Local,
+    /// Each shelf is a calibre tag, `tag_prefix` followed by the shelf name
+    Tag,
+    /// Each shelf is a value of the calibre custom column `column`
+    Column,
+}
+
+/// Shelves: collections of books kept by tuilibre, optionally mirrored into calibre
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShelvesConfig {
+    /// "local" (default), "tag" or "column"
+    pub backing: ShelfBacking,
+    /// Prefix of the tags backing shelves, e.g. "shelf:" for "shelf:To read"
+    pub tag_prefix: String,
+    /// Lookup name of a calibre custom column of text that allows several values, without
+    /// the leading '#'; create it in calibre first
+    pub column: String,
+}
+
+impl Default for ShelvesConfig {
+    fn default() -> Self {
+        ShelvesConfig {
+            backing: ShelfBacking::Local,
+            tag_prefix: "shelf:".to_string(),
+            column: "shelves".to_string(),
+        }
+    }
+}
+
+/// Copying tuilibre's record of opened and read books into calibre custom columns, off unless a column is set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackingConfig {
+    /// Lookup name of an integer custom column for how many times each book was opened
+    pub open_count_column: Option<String>,
+    /// Lookup name of a date custom column for when each book was last opened
+    pub last_opened_column: Option<String>,
+    /// Lookup name of a yes/no custom column for whether each book was read, written on import
+    pub read_column: Option<String>,
+}
+
+impl TrackingConfig {
+    pub fn writes_calibre(&self) -> bool {
+        self.open_count_column.is_some() || self.last_opened_column.is_some()
+    }
+}
+
+/// How books open, by tag
+///
+/// ```toml
+/// [[open.rules]]
+/// tag = "comics"
+/// command = "mcomix"
+///
+/// [[open.rules]]
+/// tag = "reference"
+/// format = "PDF"
+///
+/// [open]
+/// audio_player = "mpv --no-video"
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenConfig {
+    /// Checked in order; the first with a tag the book has applies
+    pub rules: Vec<OpenRule>,
+    /// Program "Open in player" plays audiobooks with, followed by any arguments; the system
+    /// player without it
+    pub audio_player: Option<String>,
+}
+
+/// How to open books with a tag, instead of asking for the format and using the system viewer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenRule {
+    /// Matched case-insensitively; "comics" also covers calibre's hierarchical "comics.manga"
+    pub tag: String,
+    /// Format to open, e.g. "PDF"; the usual choice applies to books without it
+    pub format: Option<String>,
+    /// Program to open the file with, followed by any arguments; the system viewer without it
+    pub command: Option<String>,
+}
+
+impl OpenRule {
+    fn matches(&self, tags: &[String]) -> bool {
+        let wanted = self.tag.to_lowercase();
+        tags.iter().any(|tag| {
+            let tag = tag.to_lowercase();
+            tag == wanted || tag.strip_prefix(&wanted).is_some_and(|rest| rest.starts_with('.'))
+        })
+    }
+}
+
+impl OpenConfig {
+    /// The rule for a book with `tags`, if any
+    pub fn rule_for(&self, tags: &[String]) -> Option<&OpenRule> {
+        self.rules.iter().find(|rule| rule.matches(tags))
+    }
+}
+
+/// Named searches kept for reuse, and how the books' text is indexed for `content:` searches
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchesConfig {
+    /// Save searches into calibre's own saved searches, so calibre lists them too; otherwise
+    /// they are kept with tuilibre's library settings
+    pub write_calibre: bool,
+    /// Program that extracts the text of PDFs for the content index (ships with poppler); empty
+    /// leaves PDFs out
+    pub pdftotext: String,
+}
+
+impl Default for SearchesConfig {
+    fn default() -> Self {
+        SearchesConfig { write_calibre: false, pdftotext: "pdftotext".to_string() }
+    }
+}
+
+/// Filters applied with one function key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuickFiltersConfig {
+    /// Show the filters and their keys in a line under the title bar
+    pub show_bar: bool,
+    /// Bound to F1, F2... in order, up to F8
+    pub filters: Vec<QuickFilter>,
+}
+
+/// A named search, e.g. `{ name = "PDF only", search = "formats:pdf" }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuickFilter {
+    pub name: String,
+    /// calibre search syntax, as for virtual libraries
+    pub search: String,
+}
+
+impl QuickFilter {
+    fn new(name: &str, search: &str) -> Self {
+        QuickFilter { name: name.to_string(), search: search.to_string() }
+    }
+}
+
+impl QuickFiltersConfig {
+    /// Most function keys a quick filter can have
+    pub const MAX: usize = 8;
+
+    /// The filter bound to function key `n` (1-based)
+    pub fn for_key(&self, n: u8) -> Option<&QuickFilter> {
+        let index = (n as usize).checked_sub(1).filter(|&i| i < Self::MAX)?;
+        self.filters.get(index)
+    }
+}
+
+impl Default for QuickFiltersConfig {
+    fn default() -> Self {
+        QuickFiltersConfig {
+            show_bar: true,
+            filters: vec![
+                QuickFilter::new("Unread", "read:false"),
+                QuickFilter::new("Favorites", "rating:>=4"),
+                QuickFilter::new("Recently added", "date:>30daysago"),
+                QuickFilter::new("No cover", "cover:false"),
+                QuickFilter::new("PDF only", "formats:pdf"),
+            ],
+        }
+    }
+}
+
+/// Online metadata lookup settings, and how metadata is written into the books' files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetadataConfig {
+    /// Sources to query, in order of preference
+    pub sources: Vec<MetadataSource>,
+    /// Number of proposals shown per review batch
+    pub batch_size: usize,
+    /// Program that writes metadata into files other than EPUB (ships with calibre); empty
+    /// leaves those files alone
+    pub ebook_meta: String,
+}
+
+impl Default for MetadataConfig {
+    fn default() -> Self {
+        MetadataConfig {
+            sources: vec![MetadataSource::OpenLibrary, MetadataSource::Google],
+            batch_size: 20,
+            ebook_meta: "ebook-meta".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Get the config file path in user's home directory
+    pub fn get_config_file_path() -> Result<PathBuf> {
+        let home_dir = dirs::home_dir()
+            .ok_or_else(|| Error::Other("Could not find user home directory".to_string()))?;
+
+        Ok(home_dir.join(".config").join("tuilibre").join("config.toml"))
+    }
+
+    /// Load config from file, falling back to defaults if it doesn't exist
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::get_config_file_path()?)
+    }
+
+    /// Load config from a file other than the user's, falling back to defaults if it doesn't exist
+    pub fn load_from(config_path: &Path) -> Result<Self> {
+        if config_path.exists() {
+            let content = fs::read_to_string(config_path)
+                .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
+
+            toml::from_str(&content)
+                .with_context(|| format!("Failed to parse config file: {}", config_path.display()))
+        } else {
+            Ok(Self::default())
+        }
+    }
+}
@@ -1,143 +1,838 @@
-use anyhow::Result;
-use sqlx::{SqlitePool, Row};
-use std::path::Path;
+use crate::error::{Error, Result};
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool, Row};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use crate::app::Book;
+use super::lock::{LibraryLock, LockAttempt};
+use super::timing::QueryTimer;
+use super::NewBook;
+use crate::config::DatabaseConfig;
+use crate::app::audiobook::AUDIO_FORMATS;
+use crate::app::{Book, BookFormat, BookQuery, BookSort, Filter, FilterClause, NumberField, SortKey, TagCount};
+
+/// Tables every calibre library has and tuilibre reads
+const REQUIRED_TABLES: [&str; 5] = ["books", "authors", "books_authors_link", "tags", "data"];
+
+/// Books whose details `load_book_details`, series or formats are asked for in one query,
+/// kept well under SQLite's limit on bound parameters
+const DETAILS_CHUNK: usize = 500;
+
+/// Loading progress is reported after this many books
+const PROGRESS_EVERY: usize = 250;
+
+/// Follows each LIKE, so `contains_pattern` can escape the wildcards
+const LIKE_ESCAPE: &str = r" ESCAPE '\'";
+
+/// Connect with the configured timeouts
+pub(super) async fn connect(options: SqliteConnectOptions, config: &DatabaseConfig) -> Result<SqlitePool> {
+    let options = options.busy_timeout(config.busy_timeout());
+    Ok(SqlitePoolOptions::new().acquire_timeout(config.connect_timeout()).connect_with(options).await?)
+}
+
+/// Books with their formats, authors and tags; filtered and ordered by `fetch_books`
+///
+/// Each list comes from its own subquery, so the lists don't multiply each other.
+const BOOKS_SELECT: &str = r#"
+    SELECT
+        b.id,
+        b.title,
+        b.sort,
+        b.path,
+        b.has_cover,
+        b.timestamp,
+        b.last_modified,
+        b.series_index,
+        (SELECT s.name FROM books_series_link bsl JOIN series s ON s.id = bsl.series
+            WHERE bsl.book = b.id) as series,
+        (SELECT r.rating FROM books_ratings_link brl JOIN ratings r ON r.id = brl.rating
+            WHERE brl.book = b.id) as rating,
+        (SELECT text FROM comments WHERE book = b.id) as comments,
+        (SELECT GROUP_CONCAT(d.format || char(31) || d.name || char(31) || d.uncompressed_size, char(30))
+            FROM (SELECT * FROM data WHERE book = b.id ORDER BY id) d) as formats,
+        (SELECT GROUP_CONCAT(name, ', ')
+            FROM (SELECT a.name FROM books_authors_link bal JOIN authors a ON a.id = bal.author
+                  WHERE bal.book = b.id ORDER BY bal.id)) as authors,
+        (SELECT GROUP_CONCAT(name, ', ')
+            FROM (SELECT t.name FROM books_tags_link btl JOIN tags t ON t.id = btl.tag
+                  WHERE btl.book = b.id ORDER BY t.name)) as tags
+    FROM books b
+"#;
+
+/// `BOOKS_SELECT` without authors, tags and description, for loading a large library a page
+/// at a time; `load_book_details` fills those in for the books being shown
+const BOOK_PAGE_SELECT: &str = r#"
+    SELECT
+        b.id,
+        b.title,
+        b.sort,
+        b.path,
+        b.has_cover,
+        b.timestamp,
+        b.last_modified,
+        b.series_index,
+        (SELECT s.name FROM books_series_link bsl JOIN series s ON s.id = bsl.series
+            WHERE bsl.book = b.id) as series,
+        (SELECT r.rating FROM books_ratings_link brl JOIN ratings r ON r.id = brl.rating
+            WHERE brl.book = b.id) as rating,
+        (SELECT GROUP_CONCAT(d.format || char(31) || d.name || char(31) || d.uncompressed_size, char(30))
+            FROM (SELECT * FROM data WHERE book = b.id ORDER BY id) d) as formats
+    FROM books b
+"#;
+
+/// A book's authors, tags and description, which `load_books_page` leaves out
+#[derive(Debug, Clone, Default)]
+pub struct BookDetails {
+    pub authors: Vec<String>,
+    pub tags: Vec<String>,
+    pub comments: Option<String>,
+}
+
+/// A stored file of a book in one format (a row of calibre's `data` table)
+#[derive(Debug, Clone)]
+pub struct FormatEntry {
+    pub book_id: i32,
+    pub title: String,
+    pub path: String,
+    pub format: String,
+    pub name: String,
+}
+
+/// A book lacking metadata that can be looked up online
+#[derive(Debug, Clone)]
+pub struct IncompleteBook {
+    pub id: i32,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub missing_isbn: bool,
+    pub missing_description: bool,
+    pub missing_cover: bool,
+}
+
+/// A book with a description, for suggesting tags from it
+#[derive(Debug, Clone)]
+pub struct DescribedBook {
+    pub id: i32,
+    pub title: String,
+    pub description: String,
+    pub tags: Vec<String>,
+}
+
+impl FormatEntry {
+    /// Pick the first format in `preferred` that is available
+    pub fn best_of<'a>(preferred: &[String], available: &[&'a FormatEntry]) -> Option<&'a FormatEntry> {
+        preferred.iter().find_map(|wanted| {
+            available.iter().copied().find(|f| f.format.eq_ignore_ascii_case(wanted))
+        })
+    }
+
+    /// File name of this format inside the book directory
+    pub fn filename(&self) -> String {
+        format!("{}.{}", self.name, self.format.to_lowercase())
+    }
+}
 
 /// Database connection manager for calibre libraries
+#[derive(Clone)]
 pub struct Database {
-    pool: SqlitePool,
+    pub(super) pool: SqlitePool,
+    pub(super) library_path: PathBuf,
+    /// The library's write lock, None when another process holds it and this connection is read-only
+    pub(super) lock: Arc<Mutex<Option<LibraryLock>>>,
+    /// When the snapshot read instead of the offline library was taken, see `snapshot.rs`
+    pub(super) snapshot: Option<DateTime<Utc>>,
+    pub(super) timer: Arc<QueryTimer>,
 }
 
 impl Database {
+    /// Open the library's `metadata.db` with the default timeouts, see `open`
     pub async fn new(library_path: &Path) -> Result<Self> {
+        Self::open(library_path, &DatabaseConfig::default()).await
+    }
+
+    /// Open the library's `metadata.db`, checking that it looks like calibre's
+    ///
+    /// Also takes the library's write lock; if another process has it, the connection is read-only.
+    pub async fn open(library_path: &Path, config: &DatabaseConfig) -> Result<Self> {
+        Self::connect(library_path, config, true).await
+    }
+
+    /// `open` without taking the write lock, for reading a library another instance may be
+    /// writing to without keeping that instance read-only meanwhile
+    pub async fn open_read_only(library_path: &Path, config: &DatabaseConfig) -> Result<Self> {
+        Self::connect(library_path, config, false).await
+    }
+
+    async fn connect(library_path: &Path, config: &DatabaseConfig, lock: bool) -> Result<Self> {
         let db_path = library_path.join("metadata.db");
-        let connection_string = format!("sqlite:{}", db_path.display());
+        if !db_path.is_file() {
+            return Err(Error::LibraryNotFound(library_path.to_path_buf()));
+        }
+
+        let timer = QueryTimer::new(config.slow_query());
+        let started = Instant::now();
+        let pool = connect(SqliteConnectOptions::new().filename(&db_path), config).await?;
+
+        let tables: Vec<String> = sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table'")
+            .fetch_all(&pool)
+            .await?;
+        if let Some(missing) = REQUIRED_TABLES.iter().find(|t| !tables.iter().any(|name| name == *t)) {
+            return Err(Error::SchemaUnsupported(format!("{} has no {} table", db_path.display(), missing)));
+        }
+        timer.record(started);
+
+        let lock = match lock.then(|| LibraryLock::acquire(library_path)).transpose()? {
+            Some(LockAttempt::Acquired(lock)) => Some(lock),
+            Some(LockAttempt::HeldBy(_)) | None => None,
+        };
+
+        Ok(Database {
+            pool,
+            library_path: library_path.to_path_buf(),
+            lock: Arc::new(Mutex::new(lock)),
+            snapshot: None,
+            timer: Arc::new(timer),
+        })
+    }
+
+    /// Books matching `query`, for callers that don't keep the book list loaded
+    pub async fn search(&self, query: &BookQuery) -> Result<Vec<Book>> {
+        let books = self.load_books().await?;
+        query.run(&books, self).await
+    }
+
+    /// A book's current title and `last_modified`, or None if it has been deleted
+    pub async fn book_revision(&self, book_id: i32) -> Result<Option<(String, String)>> {
+        let row: Option<(String, Option<String>)> =
+            sqlx::query_as("SELECT title, last_modified FROM books WHERE id = ?")
+                .bind(book_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(row.map(|(title, modified)| (title, modified.unwrap_or_default())))
+    }
+
+    /// The uuid calibre gave the library, which stays the same when the library is moved
+    pub async fn library_uuid(&self) -> Result<Option<String>> {
+        Ok(sqlx::query_scalar("SELECT uuid FROM library_id LIMIT 1").fetch_optional(&self.pool).await?)
+    }
+
+    /// A book's current authors, in order, to tell whether they changed since it was loaded
+    pub async fn book_authors(&self, book_id: i32) -> Result<Vec<String>> {
+        Ok(sqlx::query_scalar(
+            "SELECT a.name FROM books_authors_link bal JOIN authors a ON a.id = bal.author WHERE bal.book = ? ORDER BY bal.id",
+        )
+        .bind(book_id)
+        .fetch_all(&self.pool)
+        .await?)
+    }
 
-        let pool = SqlitePool::connect(&connection_string).await?;
-        Ok(Database { pool })
+    /// Wait for running queries to finish and close the connections
+    pub async fn close(&self) {
+        self.pool.close().await;
     }
 
     /// Load all books from the library (MVP simplified version)
+    #[tracing::instrument(skip_all)]
     pub async fn load_books(&self) -> Result<Vec<Book>> {
+        let query: QueryBuilder<Sqlite> = QueryBuilder::new(BOOKS_SELECT);
+        self.fetch_books(query, &mut |_| {}).await
+    }
+
+    /// `load_books`, telling `progress` how many books are loaded so far every few hundred
+    pub async fn load_books_with_progress(&self, mut progress: impl FnMut(usize) + Send) -> Result<Vec<Book>> {
+        let query: QueryBuilder<Sqlite> = QueryBuilder::new(BOOKS_SELECT);
+        self.fetch_books(query, &mut progress).await
+    }
+
+    /// `limit` books in title-sort order after the book with the given title sort and id (from
+    /// the start for None), without their authors, tags and description; see `load_book_details`
+    ///
+    /// Pages start after the last book read rather than at an offset, so books added or deleted
+    /// while the rest of the library loads don't shift it and leave a gap or a repeat.
+    #[tracing::instrument(skip(self))]
+    pub async fn load_books_page(&self, after: Option<(&str, i32)>, limit: usize) -> Result<Vec<Book>> {
+        let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(BOOK_PAGE_SELECT);
+        if let Some((sort, id)) = after {
+            query.push(" WHERE (COALESCE(b.sort, '') COLLATE NOCASE, b.id) > (")
+                .push_bind(sort.to_string()).push(", ").push_bind(id).push(")");
+        }
+        // In the order `App` keeps books in, ties broken by id so no book lands on two pages or none
+        query.push(" ORDER BY COALESCE(b.sort, '') COLLATE NOCASE, b.id LIMIT ").push_bind(limit as i64);
+        self.books_from_query(query, &mut |_| {}).await
+    }
+
+    /// Authors, tags and description of the given books, for books loaded by `load_books_page`
+    #[tracing::instrument(skip_all, fields(ids = ids.len()))]
+    pub async fn load_book_details(&self, ids: &[i32]) -> Result<HashMap<i32, BookDetails>> {
+        let mut details = HashMap::new();
+        // Kept well under SQLite's limit on bound parameters
+        for chunk in ids.chunks(DETAILS_CHUNK) {
+            let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(r#"
+                SELECT
+                    b.id,
+                    (SELECT text FROM comments WHERE book = b.id) as comments,
+                    (SELECT GROUP_CONCAT(name, ', ')
+                        FROM (SELECT a.name FROM books_authors_link bal JOIN authors a ON a.id = bal.author
+                              WHERE bal.book = b.id ORDER BY bal.id)) as authors,
+                    (SELECT GROUP_CONCAT(name, ', ')
+                        FROM (SELECT t.name FROM books_tags_link btl JOIN tags t ON t.id = btl.tag
+                              WHERE btl.book = b.id ORDER BY t.name)) as tags
+                FROM books b
+                WHERE b.id IN ("#);
+            let mut separated = query.separated(", ");
+            for id in chunk {
+                separated.push_bind(*id);
+            }
+            query.push(")");
+
+            let started = Instant::now();
+            let rows = query.build().fetch_all(&self.pool).await?;
+            self.timer.record(started);
+            details.extend(rows.iter().map(|row| (row.get("id"), details_from_row(row))));
+        }
+        Ok(details)
+    }
+
+    /// One book by id, None if there is no such book
+    pub async fn load_book(&self, book_id: i32) -> Result<Option<Book>> {
+        let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(BOOKS_SELECT);
+        query.push(" WHERE b.id = ").push_bind(book_id);
+        Ok(self.fetch_books(query, &mut |_| {}).await?.pop())
+    }
+
+    /// How many books the library has, to show loading progress against
+    pub async fn count_books(&self) -> Result<usize> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM books").fetch_one(&self.pool).await?;
+        Ok(count as usize)
+    }
+
+    /// Books with the given ids or a `last_modified` after `since`, for patching a loaded list
+    ///
+    /// Calibre stores `last_modified` in a fixed format, so comparing the text compares the times.
+    #[tracing::instrument(skip_all, fields(ids = ids.len()))]
+    pub async fn load_changed_books(&self, ids: &[i32], since: &str) -> Result<Vec<Book>> {
+        let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(BOOKS_SELECT);
+        query.push(" WHERE b.last_modified > ").push_bind(since.to_string());
+        if !ids.is_empty() {
+            query.push(" OR b.id IN (");
+            let mut separated = query.separated(", ");
+            for id in ids {
+                separated.push_bind(*id);
+            }
+            query.push(")");
+        }
+        self.fetch_books(query, &mut |_| {}).await
+    }
+
+    /// Ids of every book in the library, to notice books deleted elsewhere
+    pub async fn load_book_ids(&self) -> Result<HashSet<i32>> {
+        let ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM books").fetch_all(&self.pool).await?;
+        Ok(ids.into_iter().collect())
+    }
+
+    /// Ids of every book in `sort` order, ties in title-sort order
+    ///
+    /// Books without a series or rating go last either way.
+    pub async fn sorted_book_ids(&self, sort: BookSort) -> Result<Vec<i32>> {
+        let direction = if sort.descending { "DESC" } else { "ASC" };
+        let order = match sort.key {
+            SortKey::Title => format!("b.sort COLLATE NOCASE {}", direction),
+            SortKey::Author => format!("b.author_sort COLLATE NOCASE {}", direction),
+            SortKey::DateAdded => format!("b.timestamp {}", direction),
+            SortKey::Series => format!(
+                "series IS NULL, series COLLATE NOCASE {0}, b.series_index {0}",
+                direction
+            ),
+            SortKey::Rating => format!("rating IS NULL, rating {}", direction),
+        };
+        let sql = format!(r#"
+            SELECT b.id,
+                (SELECT s.sort FROM books_series_link bsl JOIN series s ON s.id = bsl.series WHERE bsl.book = b.id) AS series,
+                (SELECT r.rating FROM books_ratings_link brl JOIN ratings r ON r.id = brl.rating
+                    WHERE brl.book = b.id AND r.rating > 0) AS rating
+            FROM books b
+            ORDER BY {}, b.sort COLLATE NOCASE
+        "#, order);
+
+        let started = Instant::now();
+        let rows = sqlx::query(&sql).fetch_all(&self.pool).await?;
+        self.timer.record(started);
+        Ok(rows.into_iter().map(|row| row.get("id")).collect())
+    }
+
+    /// Run a query starting with `BOOKS_SELECT`, adding the order
+    async fn fetch_books(&self, mut query: QueryBuilder<'_, Sqlite>, progress: &mut (dyn FnMut(usize) + Send)) -> Result<Vec<Book>> {
+        query.push(" ORDER BY b.sort");
+        self.books_from_query(query, progress).await
+    }
+
+    /// Run a query starting with `BOOKS_SELECT` or `BOOK_PAGE_SELECT` as it is
+    ///
+    /// Checking each book's file is on disk is the slow part on a share, so progress counts that.
+    async fn books_from_query(&self, mut query: QueryBuilder<'_, Sqlite>, progress: &mut (dyn FnMut(usize) + Send)) -> Result<Vec<Book>> {
+        let started = Instant::now();
+        let rows = query.build().fetch_all(&self.pool).await?;
+        self.timer.record(started);
+
+        let mut books = Vec::new();
+        for row in rows {
+            // A page leaves the details out, see `BOOK_PAGE_SELECT`
+            let details_loaded = row.try_get::<Option<String>, _>("authors").is_ok();
+            let details = if details_loaded { details_from_row(&row) } else { BookDetails::default() };
+            let mut book = Book {
+                id: row.get("id"),
+                title: row.get("title"),
+                sort: row.get::<Option<String>, _>("sort").unwrap_or_default(),
+                authors: details.authors,
+                path: row.get("path"),
+                has_cover: row.get("has_cover"),
+                timestamp: row.get("timestamp"),
+                last_modified: row.get::<Option<String>, _>("last_modified").unwrap_or_default(),
+                formats: parse_formats(&row.get::<Option<String>, _>("formats").unwrap_or_default()),
+                tags: details.tags,
+                series: row.get("series"),
+                series_index: row.get::<Option<f64>, _>("series_index").unwrap_or(1.0),
+                rating: row.get::<Option<i64>, _>("rating").filter(|r| *r > 0).map(|r| r.clamp(0, 10) as u8),
+                comments: details.comments,
+                details_loaded,
+                file_missing: false,
+                shelves: Vec::new(),
+                status: None,
+                drm: BTreeMap::new(),
+            };
+            book.file_missing = book.file_path(&self.library_path).is_some_and(|path| !path.exists());
+            books.push(book);
+            if books.len() % PROGRESS_EVERY == 0 {
+                progress(books.len());
+            }
+        }
+        progress(books.len());
+
+        Ok(books)
+    }
+
+    /// Find books that have `from_format` but not yet `to_format`
+    pub async fn find_conversion_candidates(&self, from_format: &str, to_format: &str) -> Result<Vec<FormatEntry>> {
         let rows = sqlx::query(r#"
-            SELECT
-                b.id,
-                b.title,
-                b.path,
-                b.has_cover,
-                b.timestamp,
-                COALESCE(d.format, '') as format,
-                COALESCE(d.name, '') as filename,
-                GROUP_CONCAT(a.name, ', ') as authors,
-                GROUP_CONCAT(t.name, ', ') as tags
+            SELECT b.id, b.title, b.path, d.format, d.name
             FROM books b
-            LEFT JOIN books_authors_link bal ON b.id = bal.book
-            LEFT JOIN authors a ON bal.author = a.id
-            LEFT JOIN data d ON b.id = d.book
-            LEFT JOIN books_tags_link btl ON b.id = btl.book
-            LEFT JOIN tags t ON btl.tag = t.id
-            GROUP BY b.id
+            JOIN data d ON d.book = b.id AND d.format = ?
+            WHERE NOT EXISTS (
+                SELECT 1 FROM data d2 WHERE d2.book = b.id AND d2.format = ?
+            )
             ORDER BY b.sort
         "#)
+        .bind(from_format.to_uppercase())
+        .bind(to_format.to_uppercase())
         .fetch_all(&self.pool)
         .await?;
 
-        let mut books = Vec::new();
-        for row in rows {
-            let authors: String = row.get("authors");
-            let author_list = if authors.is_empty() {
-                vec!["Unknown".to_string()]
-            } else {
-                authors.split(", ").map(|s| s.to_string()).collect()
-            };
+        Ok(rows
+            .into_iter()
+            .map(|row| FormatEntry {
+                book_id: row.get("id"),
+                title: row.get("title"),
+                path: row.get("path"),
+                format: row.get("format"),
+                name: row.get("name"),
+            })
+            .collect())
+    }
 
-            let tags: String = row.get("tags");
-            let tag_list = if tags.is_empty() {
-                vec![]
-            } else {
-                tags.split(", ").map(|s| s.to_string()).collect()
-            };
+    /// Register a new format file for a book in the `data` table
+    pub async fn add_format(&self, book_id: i32, format: &str, name: &str, size: u64) -> Result<()> {
+        self.check_writable()?;
+        sqlx::query("INSERT OR REPLACE INTO data (book, format, uncompressed_size, name) VALUES (?, ?, ?, ?)")
+            .bind(book_id)
+            .bind(format.to_uppercase())
+            .bind(size as i64)
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
 
-            books.push(Book {
-                id: row.get("id"),
+        Ok(())
+    }
+
+    /// Load every stored format in the library
+    pub async fn load_all_formats(&self) -> Result<Vec<FormatEntry>> {
+        let rows = sqlx::query(r#"
+            SELECT b.id, b.title, b.path, d.format, d.name
+            FROM data d
+            JOIN books b ON b.id = d.book
+        "#)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| FormatEntry {
+                book_id: row.get("id"),
                 title: row.get("title"),
-                authors: author_list,
                 path: row.get("path"),
-                has_cover: row.get("has_cover"),
-                timestamp: row.get("timestamp"),
                 format: row.get("format"),
-                filename: row.get("filename"),
-                tags: tag_list,
-            });
+                name: row.get("name"),
+            })
+            .collect())
+    }
+
+    /// Series name and position of the given books that are in a series
+    pub async fn load_series(&self, book_ids: &[i32]) -> Result<HashMap<i32, (String, f64)>> {
+        let mut series = HashMap::new();
+        for chunk in book_ids.chunks(DETAILS_CHUNK) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let sql = format!(r#"
+                SELECT b.id, s.name, b.series_index
+                FROM books_series_link bsl
+                JOIN books b ON b.id = bsl.book
+                JOIN series s ON s.id = bsl.series
+                WHERE bsl.book IN ({})
+            "#, placeholders);
+
+            let mut query = sqlx::query(&sql);
+            for id in chunk {
+                query = query.bind(id);
+            }
+
+            let rows = query.fetch_all(&self.pool).await?;
+            series.extend(rows.into_iter().map(|row| (row.get("id"), (row.get("name"), row.get("series_index")))));
         }
+        Ok(series)
+    }
 
-        Ok(books)
+    /// Load every stored format of the given books
+    pub async fn load_formats(&self, book_ids: &[i32]) -> Result<Vec<FormatEntry>> {
+        let mut formats = Vec::new();
+        for chunk in book_ids.chunks(DETAILS_CHUNK) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let sql = format!(r#"
+                SELECT b.id, b.title, b.path, d.format, d.name
+                FROM data d
+                JOIN books b ON b.id = d.book
+                WHERE d.book IN ({})
+            "#, placeholders);
+
+            let mut query = sqlx::query(&sql);
+            for id in chunk {
+                query = query.bind(id);
+            }
+
+            let rows = query.fetch_all(&self.pool).await?;
+            formats.extend(rows.into_iter().map(|row| FormatEntry {
+                book_id: row.get("id"),
+                title: row.get("title"),
+                path: row.get("path"),
+                format: row.get("format"),
+                name: row.get("name"),
+            }));
+        }
+        Ok(formats)
     }
 
-    /// Simple search functionality
-    pub async fn search_books(&self, query: &str) -> Result<Vec<Book>> {
-        let search_term = format!("%{}%", query);
+    /// Find books missing an ISBN, a description, or a cover
+    pub async fn find_incomplete_books(&self) -> Result<Vec<IncompleteBook>> {
+        let rows = sqlx::query(r#"
+            SELECT * FROM (
+                SELECT
+                    b.id,
+                    b.title,
+                    b.has_cover,
+                    COALESCE((
+                        SELECT GROUP_CONCAT(a.name, ', ')
+                        FROM books_authors_link bal JOIN authors a ON a.id = bal.author
+                        WHERE bal.book = b.id
+                    ), '') AS authors,
+                    EXISTS(SELECT 1 FROM identifiers i WHERE i.book = b.id AND i.type = 'isbn') AS has_isbn,
+                    EXISTS(SELECT 1 FROM comments c WHERE c.book = b.id AND TRIM(c.text) <> '') AS has_description
+                FROM books b
+                ORDER BY b.sort
+            )
+            WHERE NOT has_isbn OR NOT has_description OR NOT has_cover
+        "#)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let authors: String = row.get("authors");
+                IncompleteBook {
+                    id: row.get("id"),
+                    title: row.get("title"),
+                    authors: authors.split(", ").filter(|a| !a.is_empty()).map(|a| a.to_string()).collect(),
+                    missing_isbn: !row.get::<bool, _>("has_isbn"),
+                    missing_description: !row.get::<bool, _>("has_description"),
+                    missing_cover: !row.get::<bool, _>("has_cover"),
+                }
+            })
+            .collect())
+    }
+
+    /// Find books with no language set, returning their ids and titles
+    pub async fn find_books_without_language(&self) -> Result<Vec<(i32, String)>> {
+        let books = sqlx::query_as(r#"
+            SELECT b.id, b.title FROM books b
+            WHERE NOT EXISTS (SELECT 1 FROM books_languages_link l WHERE l.book = b.id)
+            ORDER BY b.sort
+        "#)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(books)
+    }
 
+    /// Load every book that has a description, with its current tags
+    pub async fn load_described_books(&self) -> Result<Vec<DescribedBook>> {
         let rows = sqlx::query(r#"
             SELECT
                 b.id,
                 b.title,
-                b.path,
-                b.has_cover,
-                b.timestamp,
-                COALESCE(d.format, '') as format,
-                COALESCE(d.name, '') as filename,
-                GROUP_CONCAT(a.name, ', ') as authors,
-                GROUP_CONCAT(t.name, ', ') as tags
+                c.text AS description,
+                COALESCE((
+                    SELECT GROUP_CONCAT(t.name, ', ')
+                    FROM books_tags_link btl JOIN tags t ON t.id = btl.tag
+                    WHERE btl.book = b.id
+                ), '') AS tags
             FROM books b
-            LEFT JOIN books_authors_link bal ON b.id = bal.book
-            LEFT JOIN authors a ON bal.author = a.id
-            LEFT JOIN data d ON b.id = d.book
-            LEFT JOIN books_tags_link btl ON b.id = btl.book
-            LEFT JOIN tags t ON btl.tag = t.id
-            WHERE b.title LIKE ? OR a.name LIKE ? OR t.name LIKE ? OR b.path LIKE ?
-            GROUP BY b.id
+            JOIN comments c ON c.book = b.id
+            WHERE TRIM(c.text) <> ''
             ORDER BY b.sort
-            LIMIT 100
         "#)
-        .bind(&search_term)
-        .bind(&search_term)
-        .bind(&search_term)
-        .bind(&search_term)
         .fetch_all(&self.pool)
         .await?;
 
-        // Convert rows to books (same logic as load_books)
-        let mut books = Vec::new();
-        for row in rows {
-            let authors: String = row.get("authors");
-            let author_list = if authors.is_empty() {
-                vec!["Unknown".to_string()]
-            } else {
-                authors.split(", ").map(|s| s.to_string()).collect()
-            };
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let tags: String = row.get("tags");
+                DescribedBook {
+                    id: row.get("id"),
+                    title: row.get("title"),
+                    description: row.get("description"),
+                    tags: tags.split(", ").filter(|t| !t.is_empty()).map(|t| t.to_string()).collect(),
+                }
+            })
+            .collect())
+    }
 
-            let tags: String = row.get("tags");
-            let tag_list = if tags.is_empty() {
-                vec![]
-            } else {
-                tags.split(", ").map(|s| s.to_string()).collect()
-            };
+    /// ISBN of every book that has one, to recognize the same book in another library
+    pub async fn load_isbns(&self) -> Result<HashMap<i32, String>> {
+        let rows: Vec<(i32, String)> = sqlx::query_as("SELECT book, val FROM identifiers WHERE type = 'isbn' AND val <> ''")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().collect())
+    }
 
-            books.push(Book {
-                id: row.get("id"),
-                title: row.get("title"),
-                authors: author_list,
-                path: row.get("path"),
-                has_cover: row.get("has_cover"),
-                timestamp: row.get("timestamp"),
-                format: row.get("format"),
-                filename: row.get("filename"),
-                tags: tag_list,
-            });
+    /// A book's metadata in the form `add_book` takes, for copying it into another library
+    pub async fn load_new_book(&self, book: &Book) -> Result<NewBook> {
+        let comments: Option<String> = sqlx::query_scalar("SELECT text FROM comments WHERE book = ?")
+            .bind(book.id)
+            .fetch_optional(&self.pool)
+            .await?;
+        let identifiers: Vec<(String, String)> = sqlx::query_as("SELECT type, val FROM identifiers WHERE book = ?")
+            .bind(book.id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(NewBook {
+            title: book.title.clone(),
+            authors: book.authors.clone(),
+            tags: book.tags.clone(),
+            comments,
+            identifiers,
+        })
+    }
+
+    /// Names of all tags in the library
+    pub async fn load_tag_names(&self) -> Result<Vec<String>> {
+        let names = sqlx::query_scalar("SELECT name FROM tags ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(names)
+    }
+
+    /// Every tag some book carries, with how many do, for the tag browser
+    pub async fn load_tags(&self) -> Result<Vec<TagCount>> {
+        let rows: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT t.name, COUNT(l.book) FROM tags t JOIN books_tags_link l ON l.tag = t.id GROUP BY t.id ORDER BY t.name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|(name, count)| TagCount { name, count: count as usize }).collect())
+    }
+
+    /// Ids of the books matching the query's text and every one of its clauses
+    #[tracing::instrument(skip_all)]
+    pub async fn query_book_ids(&self, book_query: &BookQuery) -> Result<HashSet<i32>> {
+        let mut query: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT b.id FROM books b WHERE 1 = 1");
+
+        // The search text behaves like one more (never negated) text clause
+        let text_clause = (!book_query.text.is_empty())
+            .then(|| FilterClause::new(Filter::Text(book_query.text.clone())));
+
+        for clause in text_clause.iter().chain(&book_query.clauses) {
+            // Shelves, reading status and DRM aren't in calibre's tables; `BookQuery::run` checks them.
+            // The books' text is in the content index, looked up below
+            if matches!(clause.filter, Filter::Shelf(_) | Filter::Read(_) | Filter::Drm(_) | Filter::Content(_)) {
+                continue;
+            }
+            query.push(if clause.negated { " AND NOT (" } else { " AND (" });
+            match &clause.filter {
+                Filter::Text(text) => {
+                    let term = contains_pattern(text);
+                    query.push("b.title LIKE ").push_bind(term.clone()).push(LIKE_ESCAPE)
+                        .push(" OR b.path LIKE ").push_bind(term.clone()).push(LIKE_ESCAPE)
+                        .push(" OR EXISTS (SELECT 1 FROM books_authors_link bal JOIN authors a ON a.id = bal.author WHERE bal.book = b.id AND a.name LIKE ")
+                        .push_bind(term.clone()).push(LIKE_ESCAPE)
+                        .push(") OR EXISTS (SELECT 1 FROM books_tags_link btl JOIN tags t ON t.id = btl.tag WHERE btl.book = b.id AND t.name LIKE ")
+                        .push_bind(term).push(LIKE_ESCAPE)
+                        .push(")");
+                }
+                Filter::Title(text) => {
+                    query.push("b.title LIKE ").push_bind(contains_pattern(text)).push(LIKE_ESCAPE);
+                }
+                Filter::Author(text) => {
+                    query.push("EXISTS (SELECT 1 FROM books_authors_link bal JOIN authors a ON a.id = bal.author WHERE bal.book = b.id AND a.name LIKE ")
+                        .push_bind(contains_pattern(text)).push(LIKE_ESCAPE)
+                        .push(")");
+                }
+                Filter::Series(text) => {
+                    query.push("EXISTS (SELECT 1 FROM books_series_link bsl JOIN series s ON s.id = bsl.series WHERE bsl.book = b.id AND s.name LIKE ")
+                        .push_bind(contains_pattern(text)).push(LIKE_ESCAPE)
+                        .push(")");
+                }
+                Filter::Tag(tag) => {
+                    query.push("EXISTS (SELECT 1 FROM books_tags_link btl JOIN tags t ON t.id = btl.tag WHERE btl.book = b.id AND t.name = ")
+                        .push_bind(tag.clone())
+                        .push(")");
+                }
+                Filter::Format(format) => {
+                    query.push("EXISTS (SELECT 1 FROM data d WHERE d.book = b.id AND d.format = ")
+                        .push_bind(format.to_uppercase())
+                        .push(")");
+                }
+                Filter::Language(code) => {
+                    query.push("EXISTS (SELECT 1 FROM books_languages_link bll JOIN languages l ON l.id = bll.lang_code WHERE bll.book = b.id AND l.lang_code = ")
+                        .push_bind(code.clone())
+                        .push(")");
+                }
+                Filter::Rating(stars) => {
+                    // calibre stores ratings out of 10
+                    query.push("EXISTS (SELECT 1 FROM books_ratings_link brl JOIN ratings r ON r.id = brl.rating WHERE brl.book = b.id AND r.rating >= ")
+                        .push_bind(*stars as i64 * 2)
+                        .push(")");
+                }
+                Filter::Shelf(_) | Filter::Read(_) | Filter::Drm(_) | Filter::Content(_) => {
+                    query.push("1 = 1");
+                }
+                Filter::Cover(has) => {
+                    query.push("b.has_cover = ").push_bind(*has);
+                }
+                Filter::Audiobook(audio) => {
+                    let formats = AUDIO_FORMATS.iter().map(|f| format!("'{}'", f)).collect::<Vec<_>>().join(", ");
+                    let exists = format!("EXISTS (SELECT 1 FROM data d WHERE d.book = b.id AND d.format IN ({}))", formats);
+                    query.push(if *audio { exists } else { format!("NOT {}", exists) });
+                }
+                Filter::Added { from, to } | Filter::Published { from, to } => {
+                    let column = if matches!(clause.filter, Filter::Added { .. }) { "b.timestamp" } else { "b.pubdate" };
+                    query.push("1 = 1");
+                    if let Some(from) = from {
+                        query.push(format!(" AND date({}) >= ", column)).push_bind(from.to_string());
+                    }
+                    if let Some(to) = to {
+                        query.push(format!(" AND date({}) <= ", column)).push_bind(to.to_string());
+                    }
+                }
+                Filter::Number { field, comparison, value } => {
+                    // NULL for books without the field, which then match no comparison
+                    let column = match field {
+                        NumberField::Rating => "(SELECT r.rating / 2.0 FROM books_ratings_link brl JOIN ratings r ON r.id = brl.rating WHERE brl.book = b.id AND r.rating > 0)",
+                        NumberField::Size => "(SELECT MAX(d.uncompressed_size) FROM data d WHERE d.book = b.id)",
+                        NumberField::SeriesIndex => "(CASE WHEN EXISTS (SELECT 1 FROM books_series_link bsl WHERE bsl.book = b.id) THEN b.series_index END)",
+                    };
+                    query.push(format!("{} {} ", column, comparison.symbol())).push_bind(*value);
+                }
+            }
+            query.push(")");
         }
 
-        Ok(books)
+        let started = Instant::now();
+        let ids: Vec<i32> = query.build_query_scalar().fetch_all(&self.pool).await?;
+        self.timer.record(started);
+        let mut ids: HashSet<i32> = ids.into_iter().collect();
+
+        for clause in &book_query.clauses {
+            if let Filter::Content(phrase) = &clause.filter {
+                let found = self.content_book_ids(phrase).await?;
+                ids.retain(|id| found.contains(id) != clause.negated);
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Formats present in the library, e.g. "EPUB"
+    pub async fn load_format_names(&self) -> Result<Vec<String>> {
+        let names = sqlx::query_scalar("SELECT DISTINCT format FROM data ORDER BY format")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(names)
+    }
+
+    /// Language codes used by books in the library
+    pub async fn load_language_codes(&self) -> Result<Vec<String>> {
+        let codes = sqlx::query_scalar(r#"
+            SELECT DISTINCT l.lang_code FROM languages l
+            JOIN books_languages_link bll ON bll.lang_code = l.id
+            ORDER BY l.lang_code
+        "#)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(codes)
     }
-}
\ No newline at end of file
+}
+
+/// Authors, tags and description from a row with the columns `BOOKS_SELECT` names them by
+fn details_from_row(row: &SqliteRow) -> BookDetails {
+    let authors: String = row.get::<Option<String>, _>("authors").unwrap_or_default();
+    let authors = if authors.is_empty() {
+        vec!["Unknown".to_string()]
+    } else {
+        authors.split(", ").map(|s| s.to_string()).collect()
+    };
+
+    let tags: String = row.get::<Option<String>, _>("tags").unwrap_or_default();
+    let tags = if tags.is_empty() {
+        vec![]
+    } else {
+        tags.split(", ").map(|s| s.to_string()).collect()
+    };
+
+    BookDetails {
+        authors,
+        tags,
+        comments: row.get::<Option<String>, _>("comments").filter(|c| !c.trim().is_empty()),
+    }
+}
+
+/// A LIKE pattern matching `text` anywhere, with `%` and `_` in it taken literally as
+/// `BookQuery::run` takes them
+fn contains_pattern(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// Formats as `BOOKS_SELECT` concatenates them: fields split by \x1f, formats by \x1e
+fn parse_formats(formats: &str) -> Vec<BookFormat> {
+    formats
+        .split('\x1e')
+        .filter_map(|entry| {
+            let mut fields = entry.split('\x1f');
+            Some(BookFormat {
+                format: fields.next().filter(|f| !f.is_empty())?.to_string(),
+                name: fields.next()?.to_string(),
+                size: fields.next().and_then(|s| s.parse().ok()).unwrap_or(0),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,236 @@
+//! tuilibre's own index of the text inside the books, for `content:"exact phrase"` searches
+//!
+//! The text is extracted by the content indexing job into an FTS5 table kept in tuilibre's
+//! cache, `~/.cache/tuilibre/content/<hash of the library path>.db`, one row per chapter (or
+//! PDF page) so a match can say where in the book it is. It works without calibre's own
+//! full-text index and can be deleted at any time; indexing again only reads the files that
+//! changed.
+
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::{Connection, Executor, Row};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::snapshot::fnv1a;
+use super::Database;
+use crate::error::{Context, Error, Result};
+
+const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS indexed_files (
+        book INTEGER NOT NULL,
+        format TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        modified INTEGER NOT NULL,
+        PRIMARY KEY (book, format)
+    );
+    CREATE TABLE IF NOT EXISTS chapters (
+        id INTEGER PRIMARY KEY,
+        book INTEGER NOT NULL,
+        format TEXT NOT NULL,
+        chapter INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS chapters_book ON chapters (book);
+    -- Each chapter's text, under the chapter's id
+    CREATE VIRTUAL TABLE IF NOT EXISTS book_text USING fts5(
+        text,
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+"#;
+
+/// Marks the start of the matched words in a `ContentMatch` snippet
+pub const MATCH_START: char = '\u{2}';
+/// Marks the end of the matched words in a `ContentMatch` snippet
+pub const MATCH_END: char = '\u{3}';
+
+/// Words of text kept around the match in its snippet
+const SNIPPET_WORDS: i64 = 16;
+
+/// Where a content search found its phrase in a book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentMatch {
+    pub book_id: i32,
+    /// The format the text was extracted from, e.g. "EPUB"
+    pub format: String,
+    /// The chapter as the reader counts them from 0, or the PDF page
+    pub chapter: usize,
+    /// The text around the match on one line, the matched words between `MATCH_START` and
+    /// `MATCH_END`
+    pub snippet: String,
+}
+
+impl ContentMatch {
+    /// Where the match is, e.g. "EPUB, chapter 3" or "PDF, page 12"
+    pub fn location(&self) -> String {
+        let unit = if self.format == "PDF" { "page" } else { "chapter" };
+        format!("{}, {} {}", self.format, unit, self.chapter + 1)
+    }
+
+    /// The snippet without its markers
+    pub fn plain_snippet(&self) -> String {
+        self.snippet.replace([MATCH_START, MATCH_END], "")
+    }
+}
+
+/// The size and modification time (in seconds) a file had when its text was indexed, to tell
+/// whether it needs indexing again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    pub size: i64,
+    pub modified: i64,
+}
+
+impl FileStamp {
+    /// The file's current stamp, None when it can't be read
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        let modified = meta.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs() as i64;
+        Some(FileStamp { size: meta.len() as i64, modified })
+    }
+}
+
+impl Database {
+    /// Books whose indexed text contains `phrase`, its words in order, ignoring case and accents
+    pub async fn content_book_ids(&self, phrase: &str) -> Result<HashSet<i32>> {
+        let path = content_index_path(&self.library_path)?;
+        if !path.is_file() {
+            return Err(Error::Other("No content index yet; build one with \"Index book contents\"".to_string()));
+        }
+        let mut conn = open_index(&path).await?;
+        let ids: Vec<i32> = sqlx::query_scalar(
+            "SELECT DISTINCT c.book FROM book_text JOIN chapters c ON c.id = book_text.rowid WHERE book_text MATCH ?",
+        )
+            .bind(phrase_expression(phrase))
+            .fetch_all(&mut conn)
+            .await?;
+        Ok(ids.into_iter().collect())
+    }
+
+    /// The best match of `phrase` in each book whose indexed text holds it, with a snippet
+    pub async fn content_matches(&self, phrase: &str) -> Result<Vec<ContentMatch>> {
+        let path = content_index_path(&self.library_path)?;
+        if !path.is_file() {
+            return Ok(Vec::new());
+        }
+        let mut conn = open_index(&path).await?;
+        let rows: Vec<(i32, String, i64, String)> = sqlx::query_as(
+            "SELECT c.book, c.format, c.chapter, snippet(book_text, 0, ?, ?, '…', ?)
+             FROM book_text JOIN chapters c ON c.id = book_text.rowid
+             WHERE book_text MATCH ? ORDER BY rank",
+        )
+        .bind(MATCH_START.to_string())
+        .bind(MATCH_END.to_string())
+        .bind(SNIPPET_WORDS)
+        .bind(phrase_expression(phrase))
+        .fetch_all(&mut conn)
+        .await?;
+
+        let mut seen = HashSet::new();
+        Ok(rows
+            .into_iter()
+            .filter(|(book_id, ..)| seen.insert(*book_id))
+            .map(|(book_id, format, chapter, snippet)| ContentMatch {
+                book_id,
+                format,
+                chapter: chapter.max(0) as usize,
+                snippet: snippet.split_whitespace().collect::<Vec<_>>().join(" "),
+            })
+            .collect())
+    }
+
+    /// The stamp of each file in the content index, by book and format
+    pub async fn indexed_files(&self) -> Result<HashMap<(i32, String), FileStamp>> {
+        let mut conn = self.content_index().await?;
+        let rows = sqlx::query("SELECT book, format, size, modified FROM indexed_files").fetch_all(&mut conn).await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let stamp = FileStamp { size: row.get("size"), modified: row.get("modified") };
+                ((row.get("book"), row.get("format")), stamp)
+            })
+            .collect())
+    }
+
+    /// Put a file's text into the content index, one entry per chapter, replacing whatever the
+    /// book had indexed before
+    pub async fn index_text(&self, book_id: i32, format: &str, stamp: FileStamp, chapters: &[String]) -> Result<()> {
+        let mut conn = self.content_index().await?;
+        let mut tx = conn.begin().await?;
+        remove_book(&mut tx, book_id).await?;
+        for (chapter, text) in chapters.iter().enumerate() {
+            let id = sqlx::query("INSERT INTO chapters (book, format, chapter) VALUES (?, ?, ?)")
+                .bind(book_id)
+                .bind(format)
+                .bind(chapter as i64)
+                .execute(&mut *tx)
+                .await?
+                .last_insert_rowid();
+            sqlx::query("INSERT INTO book_text (rowid, text) VALUES (?, ?)")
+                .bind(id)
+                .bind(text)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("INSERT INTO indexed_files (book, format, size, modified) VALUES (?, ?, ?, ?)")
+            .bind(book_id)
+            .bind(format)
+            .bind(stamp.size)
+            .bind(stamp.modified)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Drop the text of books that are gone from the library or have nothing left to index
+    pub async fn prune_content_index(&self, keep: &HashSet<i32>) -> Result<usize> {
+        let mut conn = self.content_index().await?;
+        let books: Vec<i32> = sqlx::query_scalar("SELECT DISTINCT book FROM indexed_files").fetch_all(&mut conn).await?;
+        let gone: Vec<i32> = books.into_iter().filter(|id| !keep.contains(id)).collect();
+        let mut tx = conn.begin().await?;
+        for &book_id in &gone {
+            remove_book(&mut tx, book_id).await?;
+        }
+        tx.commit().await?;
+        Ok(gone.len())
+    }
+
+    /// Open the library's content index, creating it on first use
+    async fn content_index(&self) -> Result<SqliteConnection> {
+        let path = content_index_path(&self.library_path)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create content index directory: {}", dir.display()))?;
+        }
+        let mut conn = open_index(&path).await?;
+        conn.execute(SCHEMA).await?;
+        Ok(conn)
+    }
+}
+
+async fn open_index(path: &Path) -> Result<SqliteConnection> {
+    let options = SqliteConnectOptions::new().filename(path).create_if_missing(true);
+    Ok(SqliteConnection::connect_with(&options).await?)
+}
+
+async fn remove_book(conn: &mut SqliteConnection, book_id: i32) -> Result<()> {
+    sqlx::query("DELETE FROM book_text WHERE rowid IN (SELECT id FROM chapters WHERE book = ?)")
+        .bind(book_id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("DELETE FROM chapters WHERE book = ?").bind(book_id).execute(&mut *conn).await?;
+    sqlx::query("DELETE FROM indexed_files WHERE book = ?").bind(book_id).execute(&mut *conn).await?;
+    Ok(())
+}
+
+/// An FTS5 phrase query for `phrase`, quoted so none of it is read as query syntax
+fn phrase_expression(phrase: &str) -> String {
+    format!("\"{}\"", phrase.replace('"', "\"\""))
+}
+
+/// Where a library's content index is kept: `~/.cache/tuilibre/content/<hash of its path>.db`
+fn content_index_path(library_path: &Path) -> Result<PathBuf> {
+    let home_dir = dirs::home_dir().ok_or_else(|| Error::Other("Could not find user home directory".to_string()))?;
+    let library_path = library_path.canonicalize().unwrap_or_else(|_| library_path.to_path_buf());
+    let name = format!("{:016x}.db", fnv1a(library_path.to_string_lossy().as_bytes()));
+    Ok(home_dir.join(".cache").join("tuilibre").join("content").join(name))
+}
@@ -0,0 +1,127 @@
+//! calibre custom columns, looked up by the name calibre shows as `#label`
+
+use sqlx::Row;
+
+use super::write::touch_book;
+use super::Database;
+use crate::config::TrackingConfig;
+use crate::error::{Error, Result};
+
+/// A custom column as defined in calibre's `custom_columns` table
+pub(super) struct CustomColumn {
+    pub id: i64,
+    pub label: String,
+    pub datatype: String,
+    pub is_multiple: bool,
+}
+
+impl CustomColumn {
+    /// Table of the column's values; for single-valued columns other than text, one row per book
+    pub fn values_table(&self) -> String {
+        format!("custom_column_{}", self.id)
+    }
+
+    /// Statement setting a book's value in a single-valued column, binding the book then the value
+    pub fn set_value_sql(&self) -> String {
+        format!(
+            "INSERT INTO {} (book, value) VALUES (?, ?) ON CONFLICT(book) DO UPDATE SET value = excluded.value",
+            self.values_table()
+        )
+    }
+
+    /// Table linking books to values, for columns that share values between books
+    pub fn link_table(&self) -> String {
+        format!("books_custom_column_{}_link", self.id)
+    }
+
+    /// Fail unless the column holds `datatype`, described for the user as `what`
+    pub fn expect(&self, datatype: &str, what: &str) -> Result<()> {
+        if self.datatype == datatype {
+            Ok(())
+        } else {
+            Err(Error::Other(format!("Custom column #{} must be {}, not {}", self.label, what, self.datatype)))
+        }
+    }
+}
+
+impl Database {
+    /// The custom column with this lookup name, with or without the leading '#'
+    pub(super) async fn custom_column(&self, label: &str) -> Result<CustomColumn> {
+        let label = label.trim_start_matches('#');
+        let row = sqlx::query("SELECT id, datatype, is_multiple FROM custom_columns WHERE label = ?")
+            .bind(label)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| Error::Other(format!("No custom column #{} in this library; create it in calibre first", label)))?;
+
+        Ok(CustomColumn {
+            id: row.get("id"),
+            label: label.to_string(),
+            datatype: row.get("datatype"),
+            is_multiple: row.get("is_multiple"),
+        })
+    }
+
+    /// The open count calibre has for a book, if the count column is set and has a value
+    pub async fn load_open_count(&self, config: &TrackingConfig, book_id: i32) -> Result<Option<i64>> {
+        let Some(label) = &config.open_count_column else {
+            return Ok(None);
+        };
+        let column = self.custom_column(label).await?;
+        column.expect("int", "an integer")?;
+        let sql = format!("SELECT value FROM {} WHERE book = ?", column.values_table());
+        let count = sqlx::query_scalar(&sql).bind(book_id).fetch_optional(&self.pool).await?;
+        Ok(count)
+    }
+
+    /// Write a book's open count and last-opened time into the configured columns
+    pub async fn write_open_record(&self, config: &TrackingConfig, book_id: i32, count: i64, last: &str) -> Result<()> {
+        if !config.writes_calibre() {
+            return Ok(());
+        }
+        self.check_writable()?;
+
+        let count_column = match &config.open_count_column {
+            Some(label) => Some(self.custom_column(label).await?),
+            None => None,
+        };
+        let last_column = match &config.last_opened_column {
+            Some(label) => Some(self.custom_column(label).await?),
+            None => None,
+        };
+
+        let mut tx = self.pool.begin().await?;
+        if let Some(column) = count_column {
+            column.expect("int", "an integer")?;
+            let sql = column.set_value_sql();
+            sqlx::query(&sql).bind(book_id).bind(count).execute(&mut *tx).await?;
+        }
+        if let Some(column) = last_column {
+            column.expect("datetime", "a date")?;
+            let sql = column.set_value_sql();
+            sqlx::query(&sql).bind(book_id).bind(last).execute(&mut *tx).await?;
+        }
+        touch_book(&mut tx, book_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Set the configured read column for each book, `true` meaning read
+    pub async fn write_read_flags(&self, config: &TrackingConfig, flags: &[(i32, bool)]) -> Result<()> {
+        let Some(label) = &config.read_column else {
+            return Ok(());
+        };
+        self.check_writable()?;
+        let column = self.custom_column(label).await?;
+        column.expect("bool", "yes/no")?;
+
+        let sql = column.set_value_sql();
+        let mut tx = self.pool.begin().await?;
+        for &(book_id, read) in flags {
+            sqlx::query(&sql).bind(book_id).bind(read).execute(&mut *tx).await?;
+            touch_book(&mut tx, book_id).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+//! Searching the text of books through calibre's full-text index
+//!
+//! calibre 6 and later extract the text of each book into `full-text-search.db`, next to
+//! `metadata.db`, once full-text searching is turned on in its preferences. Its FTS5 table
+//! uses calibre's own tokenizer, which SQLite elsewhere doesn't have; without it the
+//! extracted text is scanned directly, which is slower but finds the same words.
+
+use sqlx::sqlite::SqliteConnection;
+use sqlx::{QueryBuilder, Sqlite};
+use std::collections::HashSet;
+
+use super::Database;
+use crate::error::{Error, Result};
+
+/// At most this many matching texts are read, best matches first when the index can rank them
+const FTS_LIMIT: i64 = 1000;
+
+/// Characters of text kept on each side of a match in its snippet
+const SNIPPET_CONTEXT: i64 = 40;
+
+/// A book whose text contains the searched words, with where they were found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FtsMatch {
+    pub book_id: i32,
+    /// The format the text was extracted from, e.g. "EPUB"
+    pub format: String,
+    /// The text around the first match, on one line
+    pub snippet: String,
+}
+
+impl Database {
+    /// Books whose text contains every word of `query`, one match per book
+    pub async fn fts_search(&self, query: &str) -> Result<Vec<FtsMatch>> {
+        let words: Vec<&str> = query.split_whitespace().collect();
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        let path = self.library_path.join("full-text-search.db");
+        if !path.is_file() {
+            return Err(Error::FileMissing(path));
+        }
+
+        // Attached per connection, so the search holds on to one for its whole length
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query("ATTACH DATABASE ? AS fts")
+            .bind(path.to_string_lossy().into_owned())
+            .execute(&mut *conn)
+            .await?;
+        let rows = match ranked_matches(&mut conn, &words).await {
+            Ok(rows) => Ok(rows),
+            Err(_) => scanned_matches(&mut conn, &words).await,
+        };
+        sqlx::query("DETACH DATABASE fts").execute(&mut *conn).await?;
+
+        let mut seen = HashSet::new();
+        Ok(rows?
+            .into_iter()
+            .filter(|(book_id, _, _)| seen.insert(*book_id))
+            .map(|(book_id, format, snippet)| FtsMatch {
+                book_id,
+                format,
+                snippet: snippet.split_whitespace().collect::<Vec<_>>().join(" "),
+            })
+            .collect())
+    }
+}
+
+/// Ask the FTS5 index, which only works where calibre's tokenizer is loaded
+async fn ranked_matches(conn: &mut SqliteConnection, words: &[&str]) -> Result<Vec<(i32, String, String)>> {
+    // Each word quoted, so the text is never read as FTS5 query syntax
+    let expression = words.iter().map(|w| format!("\"{}\"", w.replace('"', "\"\""))).collect::<Vec<_>>().join(" ");
+    let rows = sqlx::query_as(
+        "SELECT t.book, t.format, snippet(books_fts, 0, '', '', '…', 12)
+         FROM fts.books_fts JOIN fts.books_text t ON t.id = books_fts.rowid
+         WHERE books_fts MATCH ? ORDER BY rank LIMIT ?",
+    )
+    .bind(expression)
+    .bind(FTS_LIMIT)
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
+
+/// Scan the extracted text for every word, ignoring ASCII case, cutting the snippet around the first
+async fn scanned_matches(conn: &mut SqliteConnection, words: &[&str]) -> Result<Vec<(i32, String, String)>> {
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT book, format, substr(searchable_text, max(1, instr(lower(searchable_text), lower(");
+    query.push_bind(words[0].to_string());
+    query.push(")) - ");
+    query.push_bind(SNIPPET_CONTEXT);
+    query.push("), ");
+    query.push_bind(SNIPPET_CONTEXT * 2 + words[0].chars().count() as i64);
+    query.push(") FROM fts.books_text WHERE 1 = 1");
+    for word in words {
+        query.push(" AND instr(lower(searchable_text), lower(");
+        query.push_bind(word.to_string());
+        query.push(")) > 0");
+    }
+    query.push(" ORDER BY book LIMIT ");
+    query.push_bind(FTS_LIMIT);
+
+    let rows = query.build_query_as().fetch_all(conn).await?;
+    Ok(rows)
+}
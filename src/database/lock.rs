@@ -0,0 +1,279 @@
+//! Advisory lock so only one process writes to a library at a time
+//!
+//! The lock is a `.tuilibre.lock` file next to `metadata.db` holding the owner as JSON.
+//! Scripts that want to stay out of tuilibre's way can check for it, or create it
+//! themselves while they write; a lock file that can't be parsed is still respected.
+//! While tuilibre holds the lock it also keeps the file open under an OS lock (`flock` or
+//! `LockFileEx`), so two instances taking over the same stale lock can't both win.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{ErrorKind, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use super::Database;
+use crate::error::{Context, Error, Result};
+
+/// Name of the lock file in the library folder
+pub const LOCK_FILE: &str = ".tuilibre.lock";
+
+/// Who holds a library's lock
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockOwner {
+    pub pid: u32,
+    pub host: String,
+    pub since: DateTime<Utc>,
+}
+
+impl LockOwner {
+    fn current() -> Self {
+        LockOwner {
+            pid: std::process::id(),
+            host: hostname(),
+            since: Utc::now(),
+        }
+    }
+
+    /// A holder we know nothing about, such as a script's own lock file
+    fn unknown() -> Self {
+        LockOwner { pid: 0, host: String::new(), since: Utc::now() }
+    }
+
+    /// Read the owner from a lock file; None if there is no lock
+    pub fn read(library_path: &Path) -> Result<Option<LockOwner>> {
+        let path = library_path.join(LOCK_FILE);
+        match fs::read_to_string(&path) {
+            Ok(content) => Ok(Some(serde_json::from_str(&content).unwrap_or_else(|_| LockOwner::unknown()))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read lock file: {}", path.display())),
+        }
+    }
+
+    /// Whether the owner was a process on this machine that has since exited
+    pub fn is_stale(&self) -> bool {
+        self.pid != 0 && self.host == hostname() && !process_alive(self.pid)
+    }
+
+    /// e.g. "pid 4242 on laptop since 2024-01-11 10:00"
+    pub fn describe(&self) -> String {
+        if self.pid == 0 {
+            return "another program".to_string();
+        }
+        format!("pid {} on {} since {}", self.pid, self.host, self.since.format("%Y-%m-%d %H:%M"))
+    }
+}
+
+/// Outcome of trying to take a library's lock
+#[derive(Debug)]
+pub enum LockAttempt {
+    Acquired(LibraryLock),
+    HeldBy(LockOwner),
+}
+
+/// A held lock; the file is removed when it is dropped
+#[derive(Debug)]
+pub struct LibraryLock {
+    path: PathBuf,
+    owner: LockOwner,
+    /// Kept open for the OS lock, which goes with it
+    _file: fs::File,
+}
+
+impl LibraryLock {
+    /// Take the lock unless another live process has it; a stale lock is taken over
+    pub fn acquire(library_path: &Path) -> Result<LockAttempt> {
+        let path = library_path.join(LOCK_FILE);
+        let owner = LockOwner::current();
+
+        loop {
+            let (file, created) = match fs::OpenOptions::new().read(true).write(true).create_new(true).open(&path) {
+                Ok(file) => (file, true),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    match fs::OpenOptions::new().read(true).write(true).open(&path) {
+                        Ok(file) => (file, false),
+                        // Released in between, try again
+                        Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                        Err(e) if e.kind() == ErrorKind::PermissionDenied => return Ok(LockAttempt::HeldBy(held_by(library_path))),
+                        Err(e) => return Err(e).with_context(|| format!("Failed to open lock file: {}", path.display())),
+                    }
+                }
+                // A library on read-only media can't be locked, or written to either
+                Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                    return Ok(LockAttempt::HeldBy(LockOwner::unknown()));
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to create lock file: {}", path.display()));
+                }
+            };
+
+            match file.try_lock() {
+                Ok(()) => {}
+                // Another instance has it, or is just taking it over
+                Err(fs::TryLockError::WouldBlock) => return Ok(LockAttempt::HeldBy(held_by(library_path))),
+                // No OS locks on this file system (some network shares); the owner in the file is all we have
+                Err(fs::TryLockError::Error(e)) if e.kind() == ErrorKind::Unsupported => {}
+                Err(fs::TryLockError::Error(e)) => {
+                    return Err(e).with_context(|| format!("Failed to lock file: {}", path.display()));
+                }
+            }
+
+            // A lock file of a script, an older tuilibre or another machine has no OS lock on it
+            if !created {
+                match LockOwner::read(library_path)? {
+                    Some(holder) if holder.is_stale() => {}
+                    Some(holder) => return Ok(LockAttempt::HeldBy(holder)),
+                    None => continue,
+                }
+            }
+
+            let content = serde_json::to_string(&owner)?;
+            let mut file = file;
+            file.set_len(0)
+                .and_then(|()| file.rewind())
+                .and_then(|()| file.write_all(content.as_bytes()))
+                .with_context(|| format!("Failed to write lock file: {}", path.display()))?;
+
+            // The holder we waited on removed the file on its way out, so what we locked is gone
+            if !same_file(&file, &path) {
+                continue;
+            }
+            return Ok(LockAttempt::Acquired(LibraryLock { path, owner, _file: file }));
+        }
+    }
+
+    /// Take the lock even though someone else holds it, e.g. a crashed instance on another machine
+    pub fn steal(library_path: &Path) -> Result<LibraryLock> {
+        let path = library_path.join(LOCK_FILE);
+        let owner = LockOwner::current();
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| format!("Failed to write lock file: {}", path.display()))?;
+        file.write_all(serde_json::to_string(&owner)?.as_bytes())
+            .with_context(|| format!("Failed to write lock file: {}", path.display()))?;
+        // Held by the live process we're stealing from; it lets go once it sees the file names us
+        let _ = file.try_lock();
+        Ok(LibraryLock { path, owner, _file: file })
+    }
+
+    /// Whether the lock file still names us, i.e. nobody has stolen the lock
+    pub fn is_held(&self) -> bool {
+        let library_path = self.path.parent().unwrap_or(Path::new("."));
+        matches!(LockOwner::read(library_path), Ok(Some(owner)) if owner == self.owner)
+    }
+}
+
+impl Drop for LibraryLock {
+    fn drop(&mut self) {
+        // Leave a lock that was stolen from us alone. The OS lock goes with the file after
+        // this, so whoever opened it meanwhile finds it removed once they get the lock
+        if self.is_held() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Who holds the lock, for a lock file we couldn't take
+fn held_by(library_path: &Path) -> LockOwner {
+    LockOwner::read(library_path).ok().flatten().unwrap_or_else(LockOwner::unknown)
+}
+
+/// Whether the open lock file is still the one at `path`, not one removed and created anew
+#[cfg(unix)]
+fn same_file(file: &fs::File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(open), Ok(current)) => open.dev() == current.dev() && open.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+/// Windows won't remove a file another process has open, so it is the same one
+#[cfg(not(unix))]
+fn same_file(_file: &fs::File, path: &Path) -> bool {
+    path.exists()
+}
+
+/// Name of this machine, so locks from other machines sharing the library aren't judged stale
+fn hostname() -> String {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(target_os = "linux")]
+fn process_alive(pid: u32) -> bool {
+    // An exited process that hasn't been reaped yet still shows up, in state Z
+    match fs::read_to_string(Path::new("/proc").join(pid.to_string()).join("stat")) {
+        Ok(stat) => stat.rsplit(')').next().and_then(|rest| rest.split_whitespace().next()) != Some("Z"),
+        Err(_) => false,
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn process_alive(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(true)
+}
+
+/// Without a cheap check, assume the owner is alive; the lock can still be stolen
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+impl Database {
+    /// Whether another process holds the library's lock, so writes are refused
+    pub fn is_read_only(&self) -> bool {
+        self.lock.lock().map(|lock| lock.is_none()).unwrap_or(true)
+    }
+
+    /// Who holds the lock when this connection is read-only; nobody for an offline snapshot
+    pub fn lock_owner(&self) -> Option<LockOwner> {
+        if !self.is_read_only() || self.snapshot.is_some() {
+            return None;
+        }
+        LockOwner::read(&self.library_path).ok().flatten().or_else(|| Some(LockOwner::unknown()))
+    }
+
+    /// Take the library's lock from whoever holds it and allow writes again
+    pub fn steal_lock(&self) -> Result<()> {
+        if self.snapshot.is_some() {
+            return Err(Error::LibraryOffline(self.library_path.clone()));
+        }
+        let stolen = LibraryLock::steal(&self.library_path)?;
+        if let Ok(mut lock) = self.lock.lock() {
+            *lock = Some(stolen);
+        }
+        Ok(())
+    }
+
+    /// Fail unless this connection still holds the library's lock
+    pub(super) fn check_writable(&self) -> Result<()> {
+        if self.snapshot.is_some() {
+            return Err(Error::LibraryOffline(self.library_path.clone()));
+        }
+        let mut lock = self.lock.lock().map_err(|_| Error::Other("Library lock poisoned".to_string()))?;
+        // Stolen by another instance; dropping ours leaves their lock file alone
+        if lock.as_ref().is_some_and(|held| !held.is_held()) {
+            *lock = None;
+        }
+        if lock.is_some() {
+            return Ok(());
+        }
+        drop(lock);
+        let holder = self.lock_owner().map(|o| o.describe()).unwrap_or_default();
+        Err(Error::LibraryLocked(holder))
+    }
+}
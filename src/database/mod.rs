@@ -1,4 +1,23 @@
 pub mod connection;
+pub mod content;
+pub mod custom;
+pub mod fts;
+pub mod lock;
 pub mod models;
+pub mod prefs;
+pub mod searches;
+pub mod shelves;
+pub mod snapshot;
+pub mod split;
+pub mod timing;
+pub mod usage;
+pub mod watch;
+pub mod write;
 
-pub use connection::Database;
\ No newline at end of file
+pub use connection::{BookDetails, Database, DescribedBook, FormatEntry, IncompleteBook};
+pub use content::{ContentMatch, FileStamp};
+pub use fts::FtsMatch;
+pub use lock::{LibraryLock, LockOwner};
+pub use snapshot::snapshot_taken;
+pub use watch::{database_stamp, DatabaseStamp};
+pub use write::{BookChange, DeletionPreview, EditOutcome, NewBook};
@@ -0,0 +1,85 @@
+//! calibre's display preferences, as stored in the library's preferences table
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::Database;
+use crate::app::TableColumn;
+use crate::error::Result;
+
+/// Where calibre keeps the layout of its main book list
+const BOOK_LIST_STATE: &str = "library_view books view state";
+/// calibre's tag browser categories the user chose to hide
+const HIDDEN_CATEGORIES: &str = "tag_browser_hidden_categories";
+
+/// How calibre shows this library, as far as tuilibre follows it
+#[derive(Debug, Clone, Default)]
+pub struct DisplayPrefs {
+    /// Lookup names of the book list's columns in calibre's order, hidden ones left out
+    pub columns: Vec<String>,
+    pub hidden_categories: BTreeSet<String>,
+}
+
+impl DisplayPrefs {
+    /// The table columns matching calibre's book list, or `None` if it shows none tuilibre has
+    pub fn table_columns(&self) -> Option<Vec<TableColumn>> {
+        let columns: Vec<TableColumn> = self.columns.iter().filter_map(|c| TableColumn::for_calibre_field(c)).collect();
+        (!columns.is_empty()).then_some(columns)
+    }
+}
+
+#[derive(Deserialize)]
+struct BookListState {
+    #[serde(default)]
+    hidden_columns: Vec<String>,
+    #[serde(default)]
+    column_positions: BTreeMap<String, i64>,
+}
+
+/// A set in calibre's JSON, either a plain list or `{"__class__": "set", "__value__": [...]}`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonSet {
+    List(Vec<String>),
+    Tagged {
+        #[serde(rename = "__value__")]
+        value: Vec<String>,
+    },
+}
+
+impl Database {
+    /// calibre's book list columns and hidden tag browser categories; empty where calibre
+    /// hasn't stored them
+    pub async fn load_display_prefs(&self) -> Result<DisplayPrefs> {
+        let mut prefs = DisplayPrefs::default();
+
+        if let Some(state) = self.load_pref::<BookListState>(BOOK_LIST_STATE).await? {
+            let mut positions: Vec<(i64, String)> = state
+                .column_positions
+                .into_iter()
+                .filter(|(column, _)| !state.hidden_columns.contains(column))
+                .map(|(column, position)| (position, column))
+                .collect();
+            positions.sort();
+            prefs.columns = positions.into_iter().map(|(_, column)| column).collect();
+        }
+
+        prefs.hidden_categories = match self.load_pref::<JsonSet>(HIDDEN_CATEGORIES).await? {
+            Some(JsonSet::List(categories) | JsonSet::Tagged { value: categories }) => categories.into_iter().collect(),
+            None => BTreeSet::new(),
+        };
+
+        Ok(prefs)
+    }
+
+    /// A JSON preference as calibre stores it; `None` if unset or not in the expected shape
+    pub(super) async fn load_pref<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let value: Option<String> = sqlx::query_scalar("SELECT val FROM preferences WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(value.and_then(|json| serde_json::from_str(&json).ok()))
+    }
+}
@@ -0,0 +1,45 @@
+//! Named searches calibre keeps in its preferences table: virtual libraries and saved searches
+
+use std::collections::BTreeMap;
+
+use super::Database;
+use crate::error::Result;
+
+const VIRTUAL_LIBRARIES: &str = "virtual_libraries";
+const SAVED_SEARCHES: &str = "saved_searches";
+
+impl Database {
+    /// Virtual libraries defined in calibre, as name and search expression
+    pub async fn load_virtual_libraries(&self) -> Result<BTreeMap<String, String>> {
+        self.load_search_map(VIRTUAL_LIBRARIES).await
+    }
+
+    /// Searches saved in calibre, as name and search expression
+    pub async fn load_saved_searches(&self) -> Result<BTreeMap<String, String>> {
+        self.load_search_map(SAVED_SEARCHES).await
+    }
+
+    /// Save a search into calibre under `name`, replacing one of the same name, or delete it
+    /// with `None`
+    pub async fn write_saved_search(&self, name: &str, expression: Option<&str>) -> Result<()> {
+        self.check_writable()?;
+        let mut searches = self.load_saved_searches().await?;
+        match expression {
+            Some(expression) => searches.insert(name.to_string(), expression.to_string()),
+            None => searches.remove(name),
+        };
+
+        let json = serde_json::to_string(&searches)?;
+        sqlx::query("INSERT INTO preferences (key, val) VALUES (?, ?) ON CONFLICT(key) DO UPDATE SET val = excluded.val")
+            .bind(SAVED_SEARCHES)
+            .bind(json)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// A preference holding a JSON object of names to search expressions; empty if unset
+    async fn load_search_map(&self, key: &str) -> Result<BTreeMap<String, String>> {
+        Ok(self.load_pref(key).await?.unwrap_or_default())
+    }
+}
@@ -0,0 +1,119 @@
+//! Shelf membership stored in calibre, as prefixed tags or values of a custom column
+
+use sqlx::{Sqlite, Transaction};
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::write::{link_tags, touch_book};
+use super::Database;
+use crate::config::{ShelfBacking, ShelvesConfig};
+use crate::error::{Error, Result};
+
+/// Tables of a calibre custom column that holds several text values per book
+struct ColumnTables {
+    values: String,
+    link: String,
+}
+
+impl Database {
+    /// Books on each shelf as calibre has them, keyed by shelf name; empty for local shelves
+    pub async fn load_shelf_members(&self, config: &ShelvesConfig) -> Result<BTreeMap<String, BTreeSet<i32>>> {
+        let rows: Vec<(String, i32)> = match config.backing {
+            ShelfBacking::Local => return Ok(BTreeMap::new()),
+            ShelfBacking::Tag => {
+                let rows: Vec<(String, i32)> = sqlx::query_as(
+                    "SELECT t.name, btl.book FROM tags t JOIN books_tags_link btl ON btl.tag = t.id",
+                )
+                .fetch_all(&self.pool)
+                .await?;
+                rows.into_iter()
+                    .filter_map(|(tag, book)| strip_prefix_ignore_case(&tag, &config.tag_prefix).map(|name| (name.to_string(), book)))
+                    .collect()
+            }
+            ShelfBacking::Column => {
+                let tables = self.column_tables(&config.column).await?;
+                let sql = format!("SELECT v.value, l.book FROM {} v JOIN {} l ON l.value = v.id", tables.values, tables.link);
+                sqlx::query_as(&sql).fetch_all(&self.pool).await?
+            }
+        };
+
+        let mut shelves: BTreeMap<String, BTreeSet<i32>> = BTreeMap::new();
+        for (name, book) in rows {
+            shelves.entry(name).or_default().insert(book);
+        }
+        Ok(shelves)
+    }
+
+    /// Put books on a shelf and take others off it, in calibre
+    pub async fn write_shelf_members(&self, config: &ShelvesConfig, name: &str, add: &[i32], remove: &[i32]) -> Result<()> {
+        if config.backing == ShelfBacking::Local || (add.is_empty() && remove.is_empty()) {
+            return Ok(());
+        }
+        self.check_writable()?;
+
+        let tables = match config.backing {
+            ShelfBacking::Column => Some(self.column_tables(&config.column).await?),
+            _ => None,
+        };
+        let mut tx = self.pool.begin().await?;
+        for &book_id in add {
+            match &tables {
+                Some(tables) => link_column_value(&mut tx, tables, book_id, name).await?,
+                None => link_tags(&mut tx, book_id, &[format!("{}{}", config.tag_prefix, name)]).await?,
+            }
+            touch_book(&mut tx, book_id).await?;
+        }
+        for &book_id in remove {
+            match &tables {
+                Some(tables) => {
+                    let sql = format!("DELETE FROM {} WHERE book = ? AND value IN (SELECT id FROM {} WHERE value = ?)", tables.link, tables.values);
+                    sqlx::query(&sql).bind(book_id).bind(name).execute(&mut *tx).await?;
+                }
+                None => {
+                    sqlx::query("DELETE FROM books_tags_link WHERE book = ? AND tag IN (SELECT id FROM tags WHERE name = ?)")
+                        .bind(book_id)
+                        .bind(format!("{}{}", config.tag_prefix, name))
+                        .execute(&mut *tx)
+                        .await?;
+                }
+            }
+            touch_book(&mut tx, book_id).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// The tables behind the custom column with this lookup name, which must hold several text values
+    async fn column_tables(&self, label: &str) -> Result<ColumnTables> {
+        let column = self.custom_column(label).await?;
+        if column.datatype != "text" || !column.is_multiple {
+            return Err(Error::Other(format!(
+                "Custom column #{} must be text that allows several values (\"like tags\")",
+                column.label
+            )));
+        }
+        Ok(ColumnTables {
+            values: column.values_table(),
+            link: column.link_table(),
+        })
+    }
+}
+
+async fn link_column_value(tx: &mut Transaction<'_, Sqlite>, tables: &ColumnTables, book_id: i32, value: &str) -> Result<()> {
+    sqlx::query(&format!("INSERT OR IGNORE INTO {} (value) VALUES (?)", tables.values))
+        .bind(value)
+        .execute(&mut **tx)
+        .await?;
+    let sql = format!(
+        "INSERT OR IGNORE INTO {} (book, value) SELECT ?, id FROM {} WHERE value = ?",
+        tables.link, tables.values
+    );
+    sqlx::query(&sql).bind(book_id).bind(value).execute(&mut **tx).await?;
+    Ok(())
+}
+
+/// `text` without `prefix`, compared ignoring ASCII case like calibre's tag names
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    let name = &text[prefix.len()..];
+    (head.eq_ignore_ascii_case(prefix) && !name.is_empty()).then_some(name)
+}
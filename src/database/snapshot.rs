@@ -0,0 +1,77 @@
+//! Copies of `metadata.db` kept in tuilibre's cache, to browse a library while it is offline
+//!
+//! A snapshot is taken each time a library on a network share or removable drive is opened,
+//! or one found slow to answer, and refreshed each time a library that has one is opened.
+//! While the share or drive is absent the library opens from its snapshot, read-only.
+
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::SqliteConnectOptions;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::connection::connect;
+use super::timing::QueryTimer;
+use super::Database;
+use crate::config::DatabaseConfig;
+use crate::error::{Context, Error, Result};
+
+impl Database {
+    /// Open the snapshot of a library whose `metadata.db` can't be reached, read-only
+    pub async fn open_snapshot(library_path: &Path, config: &DatabaseConfig) -> Result<Self> {
+        let path = snapshot_path(library_path)?;
+        let taken = snapshot_taken(library_path).ok_or_else(|| Error::LibraryNotFound(library_path.to_path_buf()))?;
+        let pool = connect(SqliteConnectOptions::new().filename(&path).read_only(true), config).await?;
+
+        Ok(Database {
+            pool,
+            library_path: library_path.to_path_buf(),
+            lock: Arc::new(Mutex::new(None)),
+            snapshot: Some(taken),
+            timer: Arc::new(QueryTimer::new(config.slow_query())),
+        })
+    }
+
+    /// When the snapshot this connection reads was taken; None when it reads the library itself
+    pub fn snapshot(&self) -> Option<DateTime<Utc>> {
+        self.snapshot
+    }
+
+    /// Copy `metadata.db` into the cache, replacing the library's previous snapshot
+    ///
+    /// `VACUUM INTO` gives a consistent copy even while calibre is writing.
+    pub async fn save_snapshot(&self) -> Result<()> {
+        let path = snapshot_path(&self.library_path)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create snapshot directory: {}", dir.display()))?;
+        }
+        let partial = path.with_extension("db.part");
+        let _ = fs::remove_file(&partial);
+
+        sqlx::query("VACUUM INTO ?")
+            .bind(partial.to_string_lossy().into_owned())
+            .execute(&self.pool)
+            .await?;
+        fs::rename(&partial, &path).with_context(|| format!("Failed to write snapshot: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// When the library's snapshot was taken, None if it has none
+pub fn snapshot_taken(library_path: &Path) -> Option<DateTime<Utc>> {
+    let modified = fs::metadata(snapshot_path(library_path).ok()?).ok()?.modified().ok()?;
+    Some(modified.into())
+}
+
+/// Where a library's snapshot is kept: `~/.cache/tuilibre/snapshots/<hash of its path>.db`
+fn snapshot_path(library_path: &Path) -> Result<PathBuf> {
+    let home_dir = dirs::home_dir().ok_or_else(|| Error::Other("Could not find user home directory".to_string()))?;
+    let library_path = library_path.canonicalize().unwrap_or_else(|_| library_path.to_path_buf());
+    let name = format!("{:016x}.db", fnv1a(library_path.to_string_lossy().as_bytes()));
+    Ok(home_dir.join(".cache").join("tuilibre").join("snapshots").join(name))
+}
+
+/// FNV-1a, which unlike `DefaultHasher` gives the same file name across Rust versions
+pub(super) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
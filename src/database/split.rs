@@ -0,0 +1,106 @@
+//! Carving part of a library off into a new calibre library
+
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{Connection, QueryBuilder, Row, Sqlite, SqliteConnection};
+use std::path::Path;
+
+use super::Database;
+use crate::error::{Context, Error, Result};
+
+/// Value tables whose rows are dropped once no remaining book links to them
+const LINKED_VALUES: &[(&str, &str, &str)] = &[
+    ("authors", "books_authors_link", "author"),
+    ("tags", "books_tags_link", "tag"),
+    ("series", "books_series_link", "series"),
+    ("publishers", "books_publishers_link", "publisher"),
+    ("languages", "books_languages_link", "lang_code"),
+];
+
+/// Ids bound per statement, well under SQLite's parameter limit
+const CHUNK: usize = 500;
+
+impl Database {
+    /// Write `target/metadata.db` as a copy of this library's database holding only `book_ids`
+    ///
+    /// Everything calibre keeps besides the books, such as custom columns and preferences,
+    /// comes along; the copy gets its own library id. The books' folders are not copied.
+    pub async fn write_subset(&self, target: &Path, book_ids: &[i32]) -> Result<()> {
+        let db_path = target.join("metadata.db");
+        if db_path.exists() {
+            return Err(Error::Other(format!("{} already has a calibre library", target.display())));
+        }
+        std::fs::create_dir_all(target).with_context(|| format!("Failed to create {}", target.display()))?;
+
+        sqlx::query("VACUUM INTO ?")
+            .bind(db_path.to_string_lossy().to_string())
+            .execute(&self.pool)
+            .await?;
+
+        let pruned = async {
+            let options = SqliteConnectOptions::new().filename(&db_path);
+            let mut conn = SqliteConnection::connect_with(&options).await?;
+            prune(&mut conn, book_ids).await?;
+            conn.close().await?;
+            Ok::<_, Error>(())
+        };
+        if let Err(e) = pruned.await {
+            // A library with books it doesn't have files for is worse than none
+            let _ = std::fs::remove_file(&db_path);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+/// Delete every book not in `keep`, with whatever refers to it, and values no book uses any more
+async fn prune(conn: &mut SqliteConnection, keep: &[i32]) -> Result<()> {
+    let mut tx = conn.begin().await?;
+
+    sqlx::query("CREATE TEMP TABLE kept (id INTEGER PRIMARY KEY)").execute(&mut *tx).await?;
+    for chunk in keep.chunks(CHUNK) {
+        let mut insert: QueryBuilder<Sqlite> = QueryBuilder::new("INSERT OR IGNORE INTO kept(id) ");
+        insert.push_values(chunk, |mut row, id| {
+            row.push_bind(*id);
+        });
+        insert.build().execute(&mut *tx).await?;
+    }
+    sqlx::query("DELETE FROM books WHERE id NOT IN (SELECT id FROM kept)").execute(&mut *tx).await?;
+
+    // calibre's delete trigger covers its own tables, but not every library has it, and
+    // custom columns and plugins add tables of their own; all of them name the book `book`
+    let tables: Vec<String> = sqlx::query(
+        "SELECT m.name FROM sqlite_master m JOIN pragma_table_info(m.name) c
+         WHERE m.type = 'table' AND c.name = 'book'",
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|row| row.get("name"))
+    .collect();
+    for table in tables {
+        let sql = format!("DELETE FROM \"{}\" WHERE book NOT IN (SELECT id FROM books)", table.replace('"', "\"\""));
+        sqlx::query(&sql).execute(&mut *tx).await?;
+    }
+
+    for (values, link, column) in LINKED_VALUES {
+        let exists: bool = sqlx::query_scalar("SELECT COUNT(*) = 2 FROM sqlite_master WHERE type = 'table' AND name IN (?, ?)")
+            .bind(values)
+            .bind(link)
+            .fetch_one(&mut *tx)
+            .await?;
+        if exists {
+            let sql = format!("DELETE FROM {} WHERE id NOT IN (SELECT {} FROM {})", values, column, link);
+            sqlx::query(&sql).execute(&mut *tx).await?;
+        }
+    }
+
+    sqlx::query("UPDATE library_id SET uuid = ?")
+        .bind(uuid::Uuid::new_v4().to_string())
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DROP TABLE kept").execute(&mut *tx).await?;
+    tx.commit().await?;
+
+    sqlx::query("VACUUM").execute(&mut *conn).await?;
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! Noticing slow queries, which usually mean `metadata.db` is on a slow network share
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::Database;
+
+/// The first query of a connection that took longer than the threshold
+#[derive(Debug, Default)]
+pub struct QueryTimer {
+    /// None turns the warning off
+    threshold: Option<Duration>,
+    slow: Mutex<Option<Duration>>,
+    /// Set once the slow query was reported, so it is only reported once
+    reported: AtomicBool,
+}
+
+impl QueryTimer {
+    pub fn new(threshold: Option<Duration>) -> Self {
+        QueryTimer { threshold, ..Default::default() }
+    }
+
+    /// Note how long a query that began at `started` took
+    pub fn record(&self, started: Instant) {
+        let elapsed = started.elapsed();
+        if self.threshold.is_some_and(|threshold| elapsed > threshold) && !self.reported.load(Ordering::Relaxed) {
+            if let Ok(mut slow) = self.slow.lock() {
+                slow.get_or_insert(elapsed);
+            }
+        }
+    }
+}
+
+impl Database {
+    /// How long the first slow query took, the first time this is asked after one
+    pub fn take_slow_query(&self) -> Option<Duration> {
+        let slow = self.timer.slow.lock().ok()?.take()?;
+        self.timer.reported.store(true, Ordering::Relaxed);
+        Some(slow)
+    }
+}
@@ -0,0 +1,101 @@
+//! The size of the library's files, added up by author, tag, series or format or file by file
+
+use sqlx::Row;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use super::Database;
+use crate::app::{LibraryFile, UsageBook, UsageGroup, UsageGrouping};
+use crate::error::Result;
+
+impl Database {
+    /// The library's files added up by `grouping`, biggest group first, with each group's books
+    /// biggest first; books without an author, tag or series make a group of their own
+    #[tracing::instrument(skip(self))]
+    pub async fn load_usage(&self, grouping: UsageGrouping) -> Result<Vec<UsageGroup>> {
+        // A book's files are summed once per group it's in
+        let (name, join, key) = match grouping {
+            UsageGrouping::Author => (
+                "g.name",
+                "LEFT JOIN books_authors_link l ON l.book = b.id LEFT JOIN authors g ON g.id = l.author",
+                "g.id",
+            ),
+            UsageGrouping::Tag => (
+                "g.name",
+                "LEFT JOIN books_tags_link l ON l.book = b.id LEFT JOIN tags g ON g.id = l.tag",
+                "g.id",
+            ),
+            UsageGrouping::Series => (
+                "g.name",
+                "LEFT JOIN books_series_link l ON l.book = b.id LEFT JOIN series g ON g.id = l.series",
+                "g.id",
+            ),
+            UsageGrouping::Format => ("d.format", "", "d.format"),
+        };
+        let sql = format!(r#"
+            SELECT {name} AS name, b.id, b.title, SUM(d.uncompressed_size) AS bytes, COUNT(*) AS files
+            FROM data d
+            JOIN books b ON b.id = d.book
+            {join}
+            GROUP BY {key}, b.id
+            ORDER BY bytes DESC, b.sort
+        "#);
+
+        let started = Instant::now();
+        let rows = sqlx::query(&sql).fetch_all(&self.pool).await?;
+        self.timer.record(started);
+
+        let mut groups: Vec<UsageGroup> = Vec::new();
+        let mut positions = HashMap::new();
+        for row in rows {
+            let name = row.get::<Option<String>, _>("name").unwrap_or_else(|| grouping.none_label().to_string());
+            let book = UsageBook {
+                id: row.get("id"),
+                title: row.get("title"),
+                bytes: row.get::<Option<i64>, _>("bytes").unwrap_or(0).max(0) as u64,
+                files: row.get::<i64, _>("files") as usize,
+            };
+            let at = *positions.entry(name.clone()).or_insert_with(|| {
+                groups.push(UsageGroup { name, bytes: 0, books: Vec::new() });
+                groups.len() - 1
+            });
+            groups[at].bytes += book.bytes;
+            groups[at].books.push(book);
+        }
+        groups.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+        Ok(groups)
+    }
+
+    /// Every file of the library with its book, each book's in the order they were added
+    pub async fn load_library_files(&self) -> Result<Vec<LibraryFile>> {
+        let started = Instant::now();
+        let rows = sqlx::query(r#"
+            SELECT d.book, b.title, b.timestamp, d.format, d.uncompressed_size
+            FROM data d
+            JOIN books b ON b.id = d.book
+            ORDER BY d.book, d.id
+        "#)
+        .fetch_all(&self.pool)
+        .await?;
+        self.timer.record(started);
+
+        Ok(rows
+            .into_iter()
+            .map(|row| LibraryFile {
+                book_id: row.get("book"),
+                title: row.get("title"),
+                added: row.get::<Option<String>, _>("timestamp").unwrap_or_default(),
+                format: row.get("format"),
+                bytes: row.get::<Option<i64>, _>("uncompressed_size").unwrap_or(0).max(0) as u64,
+            })
+            .collect())
+    }
+
+    /// Bytes of every file in the library
+    pub async fn total_file_size(&self) -> Result<u64> {
+        let total: i64 = sqlx::query_scalar("SELECT COALESCE(SUM(uncompressed_size), 0) FROM data")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(total.max(0) as u64)
+    }
+}
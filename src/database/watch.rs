@@ -0,0 +1,19 @@
+//! Noticing writes to `metadata.db` made by calibre, calibre-web or anything else
+
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Modification time and size of `metadata.db` and its write-ahead log, None for a file that
+/// isn't there
+pub type DatabaseStamp = Vec<Option<(SystemTime, u64)>>;
+
+/// The library's stamp now; it changes with every write
+pub fn database_stamp(library_path: &Path) -> DatabaseStamp {
+    ["metadata.db", "metadata.db-wal"]
+        .iter()
+        .map(|name| {
+            let meta = std::fs::metadata(library_path.join(name)).ok()?;
+            Some((meta.modified().ok()?, meta.len()))
+        })
+        .collect()
+}
@@ -0,0 +1,715 @@
+use crate::error::{Context, Error, Result};
+use chrono::Utc;
+use sqlx::{QueryBuilder, Row, Sqlite, Transaction};
+use std::path::{Path, PathBuf};
+
+use super::Database;
+
+
+/// Metadata for a book being added to the library
+#[derive(Debug, Clone, Default)]
+pub struct NewBook {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub tags: Vec<String>,
+    pub comments: Option<String>,
+    pub identifiers: Vec<(String, String)>,
+}
+
+/// One field an edit changes, written together with the edit's other changes by
+/// `Database::edit_book`
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookChange {
+    Title(String),
+    Authors(Vec<String>),
+    AddTags(Vec<String>),
+    RemoveTags(Vec<String>),
+}
+
+/// What came of `Database::edit_book`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditOutcome {
+    Written,
+    /// The book was changed elsewhere since it was loaded; nothing was written
+    Changed,
+    /// The book is gone; nothing was written
+    Deleted,
+}
+
+/// Everything deleting a book removes, shown before the user confirms
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeletionPreview {
+    pub book_dir: PathBuf,
+    /// Tables with rows of the book, and how many
+    pub rows: Vec<(String, i64)>,
+    /// Files and folders in the book's folder, with their sizes
+    pub files: Vec<(PathBuf, u64)>,
+}
+
+impl Database {
+    /// Add a book with a single format file, copying the file into the library
+    ///
+    /// Returns the id of the new book.
+    pub async fn add_book(&self, library_path: &Path, book: &NewBook, file: &Path, cover: Option<&[u8]>) -> Result<i32> {
+        self.check_writable()?;
+        if !file.is_file() {
+            return Err(Error::FileMissing(file.to_path_buf()));
+        }
+        let format = file
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_uppercase())
+            .ok_or_else(|| Error::Other(format!("File has no extension: {}", file.display())))?;
+        let size = std::fs::metadata(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?
+            .len();
+
+        let title = if book.title.trim().is_empty() { "Unknown" } else { book.title.trim() };
+        let authors = if book.authors.is_empty() {
+            vec!["Unknown".to_string()]
+        } else {
+            book.authors.clone()
+        };
+
+        let mut tx = self.pool.begin().await?;
+        let triggers = suspend_calibre_triggers(&mut tx).await?;
+        let book_id = insert_book_row(&mut tx, title, &authors[0]).await?;
+
+        for author in &authors {
+            let author_id = upsert_author(&mut tx, author).await?;
+            sqlx::query("INSERT OR IGNORE INTO books_authors_link (book, author) VALUES (?, ?)")
+                .bind(book_id)
+                .bind(author_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        link_tags(&mut tx, book_id, &book.tags).await?;
+
+        if let Some(comments) = book.comments.as_deref().filter(|c| !c.trim().is_empty()) {
+            sqlx::query("INSERT OR REPLACE INTO comments (book, text) VALUES (?, ?)")
+                .bind(book_id)
+                .bind(comments)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for (kind, value) in &book.identifiers {
+            sqlx::query("INSERT OR REPLACE INTO identifiers (book, type, val) VALUES (?, ?, ?)")
+                .bind(book_id)
+                .bind(kind)
+                .bind(value)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        // calibre layout: "<author>/<title> (<id>)/<title> - <author>.<ext>"
+        let book_path = format!("{}/{} ({})", safe_component(&authors[0]), safe_component(title), book_id);
+        let file_name = format!("{} - {}", safe_component(title), safe_component(&authors[0]));
+
+        sqlx::query("UPDATE books SET path = ?, has_cover = ? WHERE id = ?")
+            .bind(&book_path)
+            .bind(cover.is_some())
+            .bind(book_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO data (book, format, uncompressed_size, name) VALUES (?, ?, ?, ?)")
+            .bind(book_id)
+            .bind(&format)
+            .bind(size as i64)
+            .bind(&file_name)
+            .execute(&mut *tx)
+            .await?;
+
+        restore_triggers(&mut tx, &triggers).await?;
+
+        // Copy files before committing so the database never points at missing files
+        let book_dir = library_path.join(&book_path);
+        let copied = copy_book_files(&book_dir, &file_name, &format, file, cover);
+        if let Err(e) = copied {
+            let _ = std::fs::remove_dir_all(&book_dir);
+            return Err(e);
+        }
+
+        if let Err(e) = tx.commit().await {
+            let _ = std::fs::remove_dir_all(&book_dir);
+            return Err(e.into());
+        }
+
+        Ok(book_id)
+    }
+}
+
+impl Database {
+    /// Copy a file into an existing book's folder as another of its formats
+    ///
+    /// The file is named like the book's other formats; an existing file of the same format is replaced.
+    pub async fn add_format_file(&self, library_path: &Path, book_id: i32, file: &Path) -> Result<()> {
+        self.check_writable()?;
+        let format = file
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_uppercase())
+            .ok_or_else(|| Error::Other(format!("File has no extension: {}", file.display())))?;
+        let row = sqlx::query(r#"
+            SELECT b.path, b.title, b.author_sort, (SELECT name FROM data WHERE book = b.id LIMIT 1) AS name
+            FROM books b WHERE b.id = ?
+        "#)
+        .bind(book_id)
+        .fetch_one(&self.pool)
+        .await?;
+        let book_path: String = row.get("path");
+        let name = match row.get::<Option<String>, _>("name") {
+            Some(name) => name,
+            None => format!("{} - {}", safe_component(row.get("title")), safe_component(row.get("author_sort"))),
+        };
+
+        let book_dir = library_path.join(&book_path);
+        std::fs::create_dir_all(&book_dir).with_context(|| format!("Failed to create {}", book_dir.display()))?;
+        let target = book_dir.join(format!("{}.{}", name, format.to_lowercase()));
+        let size = std::fs::copy(file, &target)
+            .with_context(|| format!("Failed to copy {} to {}", file.display(), target.display()))?;
+
+        if let Err(e) = self.add_format(book_id, &format, &name, size).await {
+            let _ = std::fs::remove_file(&target);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+/// Drop the triggers that call SQL functions only calibre defines (`title_sort`, `uuid4`),
+/// returning their definitions so they can be restored in the same transaction
+///
+/// SQLite fails any statement whose triggers reference an unknown function, so writes to
+/// `books` are impossible without this. Callers fill the affected columns themselves.
+pub(super) async fn suspend_calibre_triggers(tx: &mut Transaction<'_, Sqlite>) -> Result<Vec<String>> {
+    let triggers: Vec<(String, String)> = sqlx::query_as(r#"
+        SELECT name, sql FROM sqlite_master
+        WHERE type = 'trigger' AND (sql LIKE '%title_sort(%' OR sql LIKE '%uuid4(%')
+    "#)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    for (name, _) in &triggers {
+        sqlx::query(&format!("DROP TRIGGER \"{}\"", name))
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(triggers.into_iter().map(|(_, sql)| sql).collect())
+}
+
+/// Recreate triggers dropped by `suspend_calibre_triggers`
+pub(super) async fn restore_triggers(tx: &mut Transaction<'_, Sqlite>, triggers: &[String]) -> Result<()> {
+    for sql in triggers {
+        sqlx::query(sql).execute(&mut **tx).await?;
+    }
+    Ok(())
+}
+
+impl Database {
+    /// Rename a book, keeping its files where they are
+    pub async fn set_title(&self, book_id: i32, title: &str) -> Result<()> {
+        self.check_writable()?;
+        let mut tx = self.pool.begin().await?;
+        let triggers = suspend_calibre_triggers(&mut tx).await?;
+        write_title(&mut tx, book_id, title).await?;
+        touch_book(&mut tx, book_id).await?;
+        restore_triggers(&mut tx, &triggers).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Replace a book's authors, in order
+    pub async fn set_authors(&self, book_id: i32, authors: &[String]) -> Result<()> {
+        self.check_writable()?;
+        let mut tx = self.pool.begin().await?;
+        let triggers = suspend_calibre_triggers(&mut tx).await?;
+        write_authors(&mut tx, book_id, authors).await?;
+        touch_book(&mut tx, book_id).await?;
+        restore_triggers(&mut tx, &triggers).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Write all of an edit's changes to a book in one transaction, unless its `last_modified`
+    /// is no longer `loaded_modified`; None writes over whatever changed meanwhile
+    ///
+    /// The check is the transaction's first write, so no other writer can get in between.
+    pub async fn edit_book(&self, book_id: i32, loaded_modified: Option<&str>, changes: &[BookChange]) -> Result<EditOutcome> {
+        self.check_writable()?;
+        let mut tx = self.pool.begin().await?;
+        let triggers = suspend_calibre_triggers(&mut tx).await?;
+
+        let mut touch: QueryBuilder<Sqlite> = QueryBuilder::new("UPDATE books SET last_modified = ");
+        touch.push_bind(calibre_timestamp()).push(" WHERE id = ").push_bind(book_id);
+        if let Some(loaded) = loaded_modified {
+            touch.push(" AND COALESCE(last_modified, '') = ").push_bind(loaded);
+        }
+        if touch.build().execute(&mut *tx).await?.rows_affected() == 0 {
+            let exists: Option<i32> = sqlx::query_scalar("SELECT id FROM books WHERE id = ?")
+                .bind(book_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+            // Dropping the transaction rolls back the suspended triggers
+            return Ok(if exists.is_some() { EditOutcome::Changed } else { EditOutcome::Deleted });
+        }
+
+        for change in changes {
+            match change {
+                BookChange::Title(title) => write_title(&mut tx, book_id, title).await?,
+                BookChange::Authors(authors) => write_authors(&mut tx, book_id, authors).await?,
+                BookChange::AddTags(tags) => link_tags(&mut tx, book_id, tags).await?,
+                BookChange::RemoveTags(tags) => unlink_tags(&mut tx, book_id, tags).await?,
+            }
+        }
+        restore_triggers(&mut tx, &triggers).await?;
+        tx.commit().await?;
+        Ok(EditOutcome::Written)
+    }
+
+    /// Put a book in a series at `index`, or take it out of its series with None
+    pub async fn set_series(&self, book_id: i32, series: Option<(&str, f64)>) -> Result<()> {
+        self.check_writable()?;
+        let mut tx = self.pool.begin().await?;
+        let triggers = suspend_calibre_triggers(&mut tx).await?;
+        sqlx::query("DELETE FROM books_series_link WHERE book = ?")
+            .bind(book_id)
+            .execute(&mut *tx)
+            .await?;
+        if let Some((name, _)) = series {
+            sqlx::query("INSERT OR IGNORE INTO series (name, sort) VALUES (?, ?)")
+                .bind(name)
+                .bind(title_sort(name))
+                .execute(&mut *tx)
+                .await?;
+            let series_id: i32 = sqlx::query_scalar("SELECT id FROM series WHERE name = ?")
+                .bind(name)
+                .fetch_one(&mut *tx)
+                .await?;
+            sqlx::query("INSERT INTO books_series_link (book, series) VALUES (?, ?)")
+                .bind(book_id)
+                .bind(series_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        // calibre keeps 1.0 for books outside a series
+        sqlx::query("UPDATE books SET series_index = ? WHERE id = ?")
+            .bind(series.map_or(1.0, |(_, index)| index))
+            .bind(book_id)
+            .execute(&mut *tx)
+            .await?;
+        touch_book(&mut tx, book_id).await?;
+        restore_triggers(&mut tx, &triggers).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Replace a book's languages with the given ISO 639 codes, in order
+    pub async fn set_languages(&self, book_id: i32, codes: &[String]) -> Result<()> {
+        self.check_writable()?;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM books_languages_link WHERE book = ?")
+            .bind(book_id)
+            .execute(&mut *tx)
+            .await?;
+        for (order, code) in codes.iter().enumerate() {
+            sqlx::query("INSERT OR IGNORE INTO languages (lang_code) VALUES (?)")
+                .bind(code)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(r#"
+                INSERT OR IGNORE INTO books_languages_link (book, lang_code, item_order)
+                SELECT ?, id, ? FROM languages WHERE lang_code = ?
+            "#)
+            .bind(book_id)
+            .bind(order as i64)
+            .bind(code)
+            .execute(&mut *tx)
+            .await?;
+        }
+        touch_book(&mut tx, book_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Add tags to a book, keeping the ones it already has
+    pub async fn add_tags(&self, book_id: i32, tags: &[String]) -> Result<()> {
+        self.check_writable()?;
+        let mut tx = self.pool.begin().await?;
+        link_tags(&mut tx, book_id, tags).await?;
+        touch_book(&mut tx, book_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Remove tags from a book; tags it doesn't have are ignored
+    pub async fn remove_tags(&self, book_id: i32, tags: &[String]) -> Result<()> {
+        self.check_writable()?;
+        let mut tx = self.pool.begin().await?;
+        unlink_tags(&mut tx, book_id, tags).await?;
+        touch_book(&mut tx, book_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Set (or replace) an identifier such as `isbn` for a book
+    pub async fn set_identifier(&self, book_id: i32, kind: &str, value: &str) -> Result<()> {
+        self.check_writable()?;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT OR REPLACE INTO identifiers (book, type, val) VALUES (?, ?, ?)")
+            .bind(book_id)
+            .bind(kind)
+            .bind(value)
+            .execute(&mut *tx)
+            .await?;
+        touch_book(&mut tx, book_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Set the description (calibre "comments") of a book
+    pub async fn set_comments(&self, book_id: i32, text: &str) -> Result<()> {
+        self.check_writable()?;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT OR REPLACE INTO comments (book, text) VALUES (?, ?)")
+            .bind(book_id)
+            .bind(text)
+            .execute(&mut *tx)
+            .await?;
+        touch_book(&mut tx, book_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Store `image` as the book's cover.jpg and flag the book as having a cover
+    pub async fn set_cover(&self, library_path: &Path, book_id: i32, image: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        let book_path: String = sqlx::query_scalar("SELECT path FROM books WHERE id = ?")
+            .bind(book_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        let cover_path = library_path.join(&book_path).join("cover.jpg");
+        std::fs::write(&cover_path, image)
+            .with_context(|| format!("Failed to write {}", cover_path.display()))?;
+
+        let mut tx = self.pool.begin().await?;
+        let triggers = suspend_calibre_triggers(&mut tx).await?;
+        sqlx::query("UPDATE books SET has_cover = 1 WHERE id = ?")
+            .bind(book_id)
+            .execute(&mut *tx)
+            .await?;
+        touch_book(&mut tx, book_id).await?;
+        restore_triggers(&mut tx, &triggers).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// What deleting a book would remove: its rows in every table keyed by book, and the
+    /// files in its folder
+    pub async fn deletion_preview(&self, library_path: &Path, book_id: i32) -> Result<DeletionPreview> {
+        let book_dir = library_path.join(checked_book_path(&self.book_path(book_id).await?)?);
+
+        let mut rows = vec![("books".to_string(), 1)];
+        for table in book_tables(&self.pool).await? {
+            let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM \"{}\" WHERE book = ?", table))
+                .bind(book_id)
+                .fetch_one(&self.pool)
+                .await?;
+            if count > 0 {
+                rows.push((table, count));
+            }
+        }
+
+        let mut files = Vec::new();
+        if let Ok(entries) = std::fs::read_dir(&book_dir) {
+            for entry in entries.flatten() {
+                let bytes = entry.metadata().map(|m| m.len()).unwrap_or(0);
+                files.push((entry.path(), bytes));
+            }
+        }
+        files.sort();
+
+        Ok(DeletionPreview { book_dir, rows, files })
+    }
+
+    /// Remove a book from the database, and with `remove_files` its folder with every format in it
+    pub async fn delete_book(&self, library_path: &Path, book_id: i32, remove_files: bool) -> Result<()> {
+        self.check_writable()?;
+        let book_path = self.book_path(book_id).await?;
+        let relative = checked_book_path(&book_path)?;
+
+        // calibre's delete trigger removes the book's links, formats and comments; libraries
+        // without it would keep them, so they're removed here as well
+        let tables = book_tables(&self.pool).await?;
+        let mut tx = self.pool.begin().await?;
+        for table in &tables {
+            sqlx::query(&format!("DELETE FROM \"{}\" WHERE book = ?", table))
+                .bind(book_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("DELETE FROM books WHERE id = ?")
+            .bind(book_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        if !remove_files {
+            return Ok(());
+        }
+        let book_dir = library_path.join(relative);
+        if book_dir.exists() {
+            std::fs::remove_dir_all(&book_dir)
+                .with_context(|| format!("Failed to delete {}", book_dir.display()))?;
+        }
+        // The author folder goes too once its last book is gone; remove_dir leaves it otherwise
+        if let Some(author_dir) = book_dir.parent().filter(|dir| *dir != library_path) {
+            let _ = std::fs::remove_dir(author_dir);
+        }
+
+        Ok(())
+    }
+
+    async fn book_path(&self, book_id: i32) -> Result<String> {
+        Ok(sqlx::query_scalar("SELECT path FROM books WHERE id = ?")
+            .bind(book_id)
+            .fetch_one(&self.pool)
+            .await?)
+    }
+
+    /// Record the new size of a format whose file was rewritten in place
+    pub async fn set_format_size(&self, book_id: i32, format: &str, size: u64) -> Result<()> {
+        self.check_writable()?;
+        sqlx::query("UPDATE data SET uncompressed_size = ? WHERE book = ? AND format = ?")
+            .bind(size as i64)
+            .bind(book_id)
+            .bind(format.to_uppercase())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Remove one format of a book, deleting its file; the book and its other formats stay
+    pub async fn remove_format(&self, library_path: &Path, book_id: i32, format: &str) -> Result<()> {
+        self.check_writable()?;
+        // calibre stores formats in upper case
+        let format = format.to_uppercase();
+        let row = sqlx::query("SELECT b.path, d.name FROM data d JOIN books b ON b.id = d.book WHERE d.book = ? AND d.format = ?")
+            .bind(book_id)
+            .bind(&format)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| Error::Other(format!("Book {} has no {} file", book_id, format)))?;
+        let book_path: String = row.get("path");
+        let name: String = row.get("name");
+
+        let relative = Path::new(&book_path);
+        if book_path.is_empty() || relative.components().any(|c| !matches!(c, std::path::Component::Normal(_))) {
+            return Err(Error::Other(format!("Refusing to delete a file of book with unexpected path: {:?}", book_path)));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM data WHERE book = ? AND format = ?")
+            .bind(book_id)
+            .bind(&format)
+            .execute(&mut *tx)
+            .await?;
+        touch_book(&mut tx, book_id).await?;
+        tx.commit().await?;
+
+        let file = library_path.join(relative).join(format!("{}.{}", name, format.to_lowercase()));
+        if file.exists() {
+            std::fs::remove_file(&file).with_context(|| format!("Failed to delete {}", file.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// The book's folder relative to the library, refused when empty or escaping it, since
+/// that would point at the library itself
+fn checked_book_path(book_path: &str) -> Result<&Path> {
+    let relative = Path::new(book_path);
+    if book_path.is_empty() || relative.components().any(|c| !matches!(c, std::path::Component::Normal(_))) {
+        return Err(Error::Other(format!("Refusing to delete book with unexpected path: {:?}", book_path)));
+    }
+    Ok(relative)
+}
+
+/// Tables other than `books` with rows per book: the link tables, `data`, `comments`,
+/// `identifiers` and those of custom columns
+async fn book_tables(pool: &sqlx::SqlitePool) -> Result<Vec<String>> {
+    Ok(sqlx::query_scalar(
+        "SELECT m.name FROM sqlite_master m JOIN pragma_table_info(m.name) c \
+         WHERE m.type = 'table' AND c.name = 'book' ORDER BY m.name",
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Bump `last_modified` so calibre notices the change
+///
+/// Must run with calibre's triggers suspended or on a table without them.
+pub(super) async fn touch_book(tx: &mut Transaction<'_, Sqlite>, book_id: i32) -> Result<()> {
+    let triggers = suspend_calibre_triggers(tx).await?;
+    sqlx::query("UPDATE books SET last_modified = ? WHERE id = ?")
+        .bind(calibre_timestamp())
+        .bind(book_id)
+        .execute(&mut **tx)
+        .await?;
+    restore_triggers(tx, &triggers).await?;
+    Ok(())
+}
+
+/// Set a book's title and title sort; calibre's triggers must be suspended
+async fn write_title(tx: &mut Transaction<'_, Sqlite>, book_id: i32, title: &str) -> Result<()> {
+    sqlx::query("UPDATE books SET title = ?, sort = ? WHERE id = ?")
+        .bind(title)
+        .bind(title_sort(title))
+        .bind(book_id)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Replace a book's authors and author sort; calibre's triggers must be suspended
+async fn write_authors(tx: &mut Transaction<'_, Sqlite>, book_id: i32, authors: &[String]) -> Result<()> {
+    let Some(first) = authors.first() else {
+        return Err(Error::Other("A book needs at least one author".to_string()));
+    };
+    sqlx::query("DELETE FROM books_authors_link WHERE book = ?")
+        .bind(book_id)
+        .execute(&mut **tx)
+        .await?;
+    for author in authors {
+        let author_id = upsert_author(tx, author).await?;
+        sqlx::query("INSERT OR IGNORE INTO books_authors_link (book, author) VALUES (?, ?)")
+            .bind(book_id)
+            .bind(author_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    sqlx::query("UPDATE books SET author_sort = ? WHERE id = ?")
+        .bind(author_sort(first))
+        .bind(book_id)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Take tags off a book, leaving the tags themselves
+async fn unlink_tags(tx: &mut Transaction<'_, Sqlite>, book_id: i32, tags: &[String]) -> Result<()> {
+    for tag in tags {
+        sqlx::query("DELETE FROM books_tags_link WHERE book = ? AND tag IN (SELECT id FROM tags WHERE name = ?)")
+            .bind(book_id)
+            .bind(tag)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Insert the `books` row, filling the columns calibre's insert trigger would set
+async fn insert_book_row(tx: &mut Transaction<'_, Sqlite>, title: &str, first_author: &str) -> Result<i32> {
+    let now = calibre_timestamp();
+    let row = sqlx::query(r#"
+        INSERT INTO books (title, sort, author_sort, timestamp, pubdate, last_modified, uuid, path)
+        VALUES (?, ?, ?, ?, ?, ?, ?, '')
+        RETURNING id
+    "#)
+    .bind(title)
+    .bind(title_sort(title))
+    .bind(author_sort(first_author))
+    .bind(&now)
+    .bind(&now)
+    .bind(&now)
+    .bind(uuid::Uuid::new_v4().to_string())
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(row.get("id"))
+}
+
+/// Create missing tags and link them to the book
+pub(super) async fn link_tags(tx: &mut Transaction<'_, Sqlite>, book_id: i32, tags: &[String]) -> Result<()> {
+    for tag in tags {
+        sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+            .bind(tag)
+            .execute(&mut **tx)
+            .await?;
+        sqlx::query("INSERT OR IGNORE INTO books_tags_link (book, tag) SELECT ?, id FROM tags WHERE name = ?")
+            .bind(book_id)
+            .bind(tag)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Find or create an author, returning its id
+async fn upsert_author(tx: &mut Transaction<'_, Sqlite>, name: &str) -> Result<i32> {
+    sqlx::query("INSERT OR IGNORE INTO authors (name, sort) VALUES (?, ?)")
+        .bind(name)
+        .bind(author_sort(name))
+        .execute(&mut **tx)
+        .await?;
+
+    let id = sqlx::query_scalar("SELECT id FROM authors WHERE name = ?")
+        .bind(name)
+        .fetch_one(&mut **tx)
+        .await?;
+    Ok(id)
+}
+
+fn copy_book_files(book_dir: &Path, file_name: &str, format: &str, file: &Path, cover: Option<&[u8]>) -> Result<()> {
+    std::fs::create_dir_all(book_dir)
+        .with_context(|| format!("Failed to create {}", book_dir.display()))?;
+
+    let target = book_dir.join(format!("{}.{}", file_name, format.to_lowercase()));
+    std::fs::copy(file, &target)
+        .with_context(|| format!("Failed to copy {} to {}", file.display(), target.display()))?;
+
+    if let Some(cover) = cover {
+        std::fs::write(book_dir.join("cover.jpg"), cover)
+            .with_context(|| "Failed to write cover image")?;
+    }
+
+    Ok(())
+}
+
+/// Timestamp in the format calibre stores
+pub fn calibre_timestamp() -> String {
+    Utc::now().format("%Y-%m-%d %H:%M:%S%.6f+00:00").to_string()
+}
+
+/// Title sort as calibre computes it by default: leading English articles moved to the end
+pub fn title_sort(title: &str) -> String {
+    for article in ["The ", "A ", "An "] {
+        if let Some(rest) = title.strip_prefix(article) {
+            return format!("{}, {}", rest, article.trim());
+        }
+    }
+    title.to_string()
+}
+
+/// Author sort as calibre computes it by default: "First Last" becomes "Last, First"
+pub fn author_sort(author: &str) -> String {
+    let parts: Vec<&str> = author.split_whitespace().collect();
+    match parts.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{}, {}", last, rest.join(" ")),
+        _ => author.to_string(),
+    }
+}
+
+/// Make a string safe to use as a single path component, like calibre does
+pub fn safe_component(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| if "/\\:*?\"<>|".contains(c) || c.is_control() { '_' } else { c })
+        .collect();
+    let trimmed = cleaned.trim().trim_end_matches('.');
+    let truncated: String = trimmed.chars().take(80).collect();
+    if truncated.is_empty() { "Unknown".to_string() } else { truncated }
+}
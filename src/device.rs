@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::config::DeviceConfig;
+use crate::database::FormatEntry;
+
+/// File extensions treated as books when listing a device
+const BOOK_EXTENSIONS: &[&str] = &["epub", "kepub", "azw", "azw3", "mobi", "pdf", "txt", "cbz", "cbr", "fb2"];
+
+/// Kind of e-reader, used to pick sensible defaults
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Kindle,
+    Kobo,
+}
+
+/// How books are laid out and which formats a device prefers
+#[derive(Debug, Clone)]
+pub struct DeviceProfile {
+    pub name: String,
+    /// Preferred formats, best first (uppercase, as stored by calibre)
+    pub formats: Vec<String>,
+    /// Folder on the device (relative to the mount point) where books are copied
+    pub books_dir: PathBuf,
+    /// Convert EPUBs to KEPUB with `kepubify` while sending
+    pub kepubify: bool,
+}
+
+impl DeviceProfile {
+    /// Built-in profile for a device kind
+    pub fn builtin(kind: DeviceKind) -> Self {
+        match kind {
+            DeviceKind::Kindle => DeviceProfile {
+                name: "Kindle".to_string(),
+                formats: vec!["AZW3".into(), "MOBI".into(), "AZW".into(), "PDF".into(), "TXT".into()],
+                books_dir: PathBuf::from("documents"),
+                kepubify: false,
+            },
+            DeviceKind::Kobo => DeviceProfile {
+                name: "Kobo".to_string(),
+                formats: vec!["KEPUB".into(), "EPUB".into(), "PDF".into(), "CBZ".into(), "TXT".into()],
+                books_dir: PathBuf::new(),
+                kepubify: true,
+            },
+        }
+    }
+
+    /// Profile defined in the `[devices]` section of the config file
+    pub fn from_config(name: &str, config: &DeviceConfig) -> Self {
+        DeviceProfile {
+            name: name.to_string(),
+            formats: config.formats.iter().map(|f| f.to_uppercase()).collect(),
+            books_dir: PathBuf::from(&config.folder),
+            kepubify: config.kepubify,
+        }
+    }
+
+    /// Pick the best available format for this device
+    pub fn best_format<'a>(&self, available: &[&'a FormatEntry]) -> Option<&'a FormatEntry> {
+        FormatEntry::best_of(&self.formats, available)
+    }
+}
+
+/// A mounted e-reader
+#[derive(Debug, Clone)]
+pub struct Device {
+    /// Recognized device kind, `None` for configured devices of unknown make
+    pub kind: Option<DeviceKind>,
+    pub mount_point: PathBuf,
+    pub profile: DeviceProfile,
+}
+
+impl Device {
+    /// Directory on the device where books are stored
+    pub fn books_path(&self) -> PathBuf {
+        self.mount_point.join(&self.profile.books_dir)
+    }
+
+    /// Free space on the device in bytes, if it can be determined
+    pub fn free_space(&self) -> Option<u64> {
+        free_space(&self.mount_point)
+    }
+}
+
+/// A book file found on a device
+#[derive(Debug, Clone)]
+pub struct DeviceBook {
+    pub path: PathBuf,
+    pub title: String,
+    pub size: u64,
+    /// Library book this file matches, if any
+    pub book_id: Option<i32>,
+}
+
+impl Device {
+    /// List the book files on the device and match them against the library formats
+    pub fn list_books(&self, formats: &[FormatEntry]) -> Vec<DeviceBook> {
+        // calibre names files "<title> - <author>", which is also what we send
+        let by_name: HashMap<String, i32> = formats
+            .iter()
+            .map(|f| (normalize_name(&f.name), f.book_id))
+            .collect();
+
+        let mut books = Vec::new();
+        collect_book_files(&self.books_path(), &mut books);
+
+        for book in &mut books {
+            let stem = book.path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+            // Kobo KEPUBs are usually named "<name>.kepub.epub"
+            let stem = stem.strip_suffix(".kepub").unwrap_or(stem);
+            book.book_id = by_name.get(&normalize_name(stem)).copied();
+        }
+
+        books.sort_by_key(|b| b.title.to_lowercase());
+        books
+    }
+}
+
+fn normalize_name(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Recursively collect book files, skipping hidden and sidecar (.sdr) folders
+fn collect_book_files(dir: &Path, books: &mut Vec<DeviceBook>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        if path.is_dir() {
+            if !name.ends_with(".sdr") {
+                collect_book_files(&path, books);
+            }
+            continue;
+        }
+
+        let is_book = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|ext| BOOK_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+        if is_book {
+            books.push(DeviceBook {
+                title: path.file_stem().and_then(|s| s.to_str()).unwrap_or(&name).to_string(),
+                size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                path,
+                book_id: None,
+            });
+        }
+    }
+}
+
+/// Find mounted devices: configured profiles first, then auto-detected Kindles and Kobos
+pub fn detect_devices(configured: &BTreeMap<String, DeviceConfig>) -> Vec<Device> {
+    let mut devices = Vec::new();
+
+    for (name, config) in configured {
+        for mount_point in expand_mount_pattern(&config.mount) {
+            devices.push(Device {
+                kind: identify_device(&mount_point),
+                mount_point,
+                profile: DeviceProfile::from_config(name, config),
+            });
+        }
+    }
+
+    for root in mount_roots() {
+        let Ok(entries) = std::fs::read_dir(&root) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if devices.iter().any(|d| d.mount_point == path) {
+                continue;
+            }
+
+            if let Some(kind) = identify_device(&path) {
+                devices.push(Device {
+                    kind: Some(kind),
+                    mount_point: path,
+                    profile: DeviceProfile::builtin(kind),
+                });
+            }
+        }
+    }
+
+    devices
+}
+
+/// Recognize a device from the layout of its mount point
+pub fn identify_device(path: &Path) -> Option<DeviceKind> {
+    if path.join(".kobo").is_dir() {
+        Some(DeviceKind::Kobo)
+    } else if path.join("documents").is_dir() && path.join("system").is_dir() {
+        Some(DeviceKind::Kindle)
+    } else {
+        None
+    }
+}
+
+/// Existing directories matching a mount pattern; `*` matches one path component
+fn expand_mount_pattern(pattern: &str) -> Vec<PathBuf> {
+    let pattern = match (pattern.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(pattern),
+    };
+
+    let mut matches = vec![PathBuf::new()];
+    for component in pattern.components() {
+        let part = component.as_os_str().to_string_lossy();
+        if !part.contains('*') {
+            matches.iter_mut().for_each(|m| m.push(component));
+            continue;
+        }
+
+        matches = matches
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flat_map(|entries| entries.flatten())
+            .filter(|entry| wildcard_match(&part, &entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .collect();
+    }
+
+    matches.retain(|m| !m.as_os_str().is_empty() && m.is_dir());
+    matches
+}
+
+/// Match `name` against a pattern where `*` stands for any run of characters
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.is_empty()
+}
+
+/// Directories under which removable drives are usually mounted
+fn mount_roots() -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+
+    if cfg!(target_os = "linux") {
+        if let Ok(user) = std::env::var("USER") {
+            roots.push(PathBuf::from("/media").join(&user));
+            roots.push(PathBuf::from("/run/media").join(&user));
+        }
+        roots.push(PathBuf::from("/media"));
+        roots.push(PathBuf::from("/mnt"));
+    } else if cfg!(target_os = "macos") {
+        roots.push(PathBuf::from("/Volumes"));
+    }
+
+    roots
+}
+
+/// Free space on the filesystem holding `path`, as reported by `df`
+pub fn free_space(path: &Path) -> Option<u64> {
+    let output = std::process::Command::new("df")
+        .arg("-Pk")
+        .arg(path)
+        .output()
+        .ok()?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let available_kb: u64 = stdout.lines().nth(1)?.split_whitespace().nth(3)?.parse().ok()?;
+    Some(available_kb * 1024)
+}
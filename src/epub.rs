@@ -0,0 +1,410 @@
+//! The package metadata (OPF) of EPUB files, read when a file is added to the library and
+//! written back when the library's metadata is embedded in the file
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use roxmltree::{Document, Node};
+
+use crate::database::NewBook;
+use crate::error::{Context, Error, Result};
+
+/// Start of every JPEG file; calibre keeps covers as cover.jpg, so other images are left out
+const JPEG_MAGIC: [u8; 2] = [0xff, 0xd8];
+
+/// Dublin Core, the namespace of the OPF's `dc:` elements
+const DC_NS: &str = "http://purl.org/dc/elements/1.1/";
+
+/// The OPF's own namespace, of `<meta>` and `<item>`
+const OPF_NS: &str = "http://www.idpf.org/2007/opf";
+
+/// Manifest id and file name of a cover added to an EPUB that had none
+const ADDED_COVER: (&str, &str) = ("tuilibre-cover", "tuilibre-cover.jpg");
+
+/// What an EPUB says about itself in the `<metadata>` of its OPF
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EpubMetadata {
+    pub title: Option<String>,
+    /// Creators with no role or the author role, in order
+    pub authors: Vec<String>,
+    /// The `dc:subject` entries, which calibre takes as tags
+    pub subjects: Vec<String>,
+    /// HTML, as calibre keeps comments
+    pub description: Option<String>,
+    pub isbn: Option<String>,
+    /// From calibre's `calibre:series` metas, or else an EPUB 3 `belongs-to-collection`
+    pub series: Option<(String, f64)>,
+    /// The cover image named by the manifest, as stored
+    pub cover: Option<Vec<u8>>,
+}
+
+/// The library's metadata of a book, as written into its files
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileMetadata {
+    pub title: String,
+    pub authors: Vec<String>,
+    /// The series and the book's position in it
+    pub series: Option<(String, f64)>,
+}
+
+/// Read the metadata of the EPUB at `path`
+pub fn read_metadata(path: &Path) -> Result<EpubMetadata> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| invalid_epub(path, e))?;
+    let (opf_path, opf) = read_opf(&mut archive).map_err(|e| invalid_epub(path, e))?;
+    let doc = Document::parse(&opf).map_err(|e| invalid_epub(path, e))?;
+    let Some(metadata) = doc.descendants().find(|n| n.has_tag_name("metadata")) else {
+        return Err(invalid_epub(path, "no <metadata> in the OPF"));
+    };
+
+    let authors = dc_elements(metadata, "creator").filter(|node| is_author(metadata, *node)).filter_map(text).collect();
+
+    let isbn = dc_elements(metadata, "identifier").find_map(|node| {
+        let value = text(node)?;
+        let scheme = node.attributes().find(|a| a.name() == "scheme").map(|a| a.value());
+        let lower = value.to_lowercase();
+        let isbn = if scheme.is_some_and(|s| s.eq_ignore_ascii_case("isbn")) {
+            value.as_str()
+        } else if lower.starts_with("urn:isbn:") {
+            &value[9..]
+        } else if lower.starts_with("isbn:") {
+            &value[5..]
+        } else {
+            return None;
+        };
+        let digits: String = isbn.chars().filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x').collect();
+        (digits.len() == 10 || digits.len() == 13).then(|| digits.to_uppercase())
+    });
+
+    let cover = cover_item(&doc, metadata).and_then(|item| {
+        let name = entry_name(&opf_path, item.attribute("href")?);
+        let mut bytes = Vec::new();
+        archive.by_name(&name).ok()?.read_to_end(&mut bytes).ok()?;
+        Some(bytes)
+    });
+
+    let title = dc_elements(metadata, "title").find_map(text);
+    let subjects = dc_elements(metadata, "subject").filter_map(text).collect();
+    let description = dc_elements(metadata, "description").find_map(text);
+    let series = series(metadata);
+    Ok(EpubMetadata { title, authors, subjects, description, isbn, series, cover })
+}
+
+/// The series and position `metadata` gives, calibre's way first
+fn series(metadata: Node) -> Option<(String, f64)> {
+    let meta = |name: &str| metadata.children().find(|m| m.attribute("name") == Some(name)).and_then(|m| m.attribute("content"));
+    if let Some(series) = meta("calibre:series").map(str::trim).filter(|s| !s.is_empty()) {
+        let index = meta("calibre:series_index").and_then(|i| i.trim().parse().ok()).unwrap_or(1.0);
+        return Some((series.to_string(), index));
+    }
+
+    let refined = |id: &str, property: &str| {
+        metadata
+            .children()
+            .find(|m| m.attribute("property") == Some(property) && m.attribute("refines") == Some(&format!("#{}", id)))
+            .and_then(text)
+    };
+    metadata.children().filter(|m| m.attribute("property") == Some("belongs-to-collection")).find_map(|collection| {
+        let id = collection.attribute("id").unwrap_or_default();
+        if refined(id, "collection-type").is_some_and(|kind| kind != "series") {
+            return None;
+        }
+        let index = refined(id, "group-position").and_then(|i| i.parse().ok()).unwrap_or(1.0);
+        Some((text(collection)?, index))
+    })
+}
+
+/// The metadata to add `file` with, and its cover if it has a JPEG one
+///
+/// EPUBs are described by their OPF; other files, and EPUBs whose OPF says nothing, by their
+/// name, read as calibre does: "Title - Author".
+pub fn import_metadata(file: &Path) -> (NewBook, Option<Vec<u8>>) {
+    let stem = file.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let (title, author) = match stem.split_once(" - ") {
+        Some((title, author)) => (title.trim().to_string(), Some(author.trim().to_string())),
+        None => (stem.trim().to_string(), None),
+    };
+    let mut book = NewBook { title, authors: author.into_iter().collect(), ..NewBook::default() };
+
+    let is_epub = file.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("epub"));
+    if !is_epub {
+        return (book, None);
+    }
+    let metadata = match read_metadata(file) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            tracing::debug!("Adding {} by its file name: {}", file.display(), e);
+            return (book, None);
+        }
+    };
+
+    if let Some(title) = metadata.title {
+        book.title = title;
+    }
+    if !metadata.authors.is_empty() {
+        book.authors = metadata.authors;
+    }
+    book.tags = metadata.subjects;
+    book.comments = metadata.description;
+    book.identifiers = metadata.isbn.into_iter().map(|isbn| ("isbn".to_string(), isbn)).collect();
+    let cover = metadata.cover.filter(|image| image.starts_with(&JPEG_MAGIC));
+    (book, cover)
+}
+
+/// Write `metadata` into the OPF of the EPUB at `path`, replacing the title, authors and series
+/// it had, along with `cover` when given
+///
+/// The rest of the OPF is kept as written. The cover replaces the image the manifest names
+/// when that is a JPEG and is added as a new item when there is none; a cover in another image
+/// format is left alone. The EPUB is written next to itself and renamed over the original, so
+/// a failure leaves the original as it was.
+pub fn write_metadata(path: &Path, metadata: &FileMetadata, cover: Option<&[u8]>) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| invalid_epub(path, e))?;
+    let (opf_path, opf) = read_opf(&mut archive).map_err(|e| invalid_epub(path, e))?;
+    let names: HashSet<String> = archive.file_names().map(str::to_string).collect();
+    let cover = cover.filter(|image| image.starts_with(&JPEG_MAGIC));
+    let (opf, cover_entry) = rewrite_opf(&opf, &opf_path, metadata, cover.is_some(), &names).map_err(|e| invalid_epub(path, e))?;
+
+    let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let temp = path.with_file_name(format!(".{}.tuilibre", file_name));
+    let written = (|| {
+        let out = File::create(&temp).with_context(|| format!("Failed to create {}", temp.display()))?;
+        let mut writer = zip::ZipWriter::new(out);
+        let deflated = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        // Entries are copied still compressed, which keeps the mimetype first and stored
+        for index in 0..archive.len() {
+            let entry = archive.by_index_raw(index)?;
+            if entry.name() == opf_path {
+                drop(entry);
+                writer.start_file(opf_path.as_str(), deflated)?;
+                writer.write_all(opf.as_bytes()).with_context(|| format!("Failed to write {}", temp.display()))?;
+            } else if Some(entry.name()) != cover_entry.as_deref() {
+                writer.raw_copy_file(entry)?;
+            }
+        }
+        if let (Some(name), Some(image)) = (&cover_entry, cover) {
+            // JPEGs don't shrink, so the cover is stored as it is
+            writer.start_file(name.as_str(), zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored))?;
+            writer.write_all(image).with_context(|| format!("Failed to write {}", temp.display()))?;
+        }
+        writer.finish()?;
+        std::fs::rename(&temp, path).with_context(|| format!("Failed to replace {}", path.display()))
+    })();
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    written
+}
+
+/// The OPF with its metadata replaced by `metadata`, and the entry the cover goes in when
+/// `has_cover` and there is a JPEG cover to replace or none yet
+///
+/// Only the `<metadata>` element is rewritten, and the `<manifest>` when a cover item is
+/// added, by splicing the source text so everything else stays byte for byte.
+fn rewrite_opf(
+    opf: &str,
+    opf_path: &str,
+    metadata: &FileMetadata,
+    has_cover: bool,
+    names: &HashSet<String>,
+) -> std::result::Result<(String, Option<String>), String> {
+    let doc = Document::parse(opf).map_err(|e| e.to_string())?;
+    let Some(node) = doc.descendants().find(|n| n.has_tag_name("metadata")) else {
+        return Err("no <metadata> in the OPF".to_string());
+    };
+    let (Some(first), Some(last)) = (node.first_child(), node.last_child()) else {
+        return Err("empty <metadata> in the OPF".to_string());
+    };
+    let epub3 = doc.root_element().attribute("version").is_some_and(|v| v.starts_with('3'));
+    let opf_prefix = prefix_in(node, OPF_NS).unwrap_or_default();
+    let (dc, dc_xmlns) = match prefix_in(node, DC_NS) {
+        Some(prefix) => (prefix, String::new()),
+        None => ("dc:".to_string(), format!(" xmlns:dc=\"{}\"", DC_NS)),
+    };
+
+    // The cover: replace a JPEG item's file, add an item when there is none
+    let existing = cover_item(&doc, node);
+    let (cover_entry, add_item) = match existing {
+        _ if !has_cover => (None, false),
+        Some(item) => {
+            let jpeg = item.attribute("media-type") == Some("image/jpeg");
+            (item.attribute("href").filter(|_| jpeg).map(|href| entry_name(opf_path, href)), false)
+        }
+        None => {
+            let name = entry_name(opf_path, ADDED_COVER.1);
+            let manifest = doc.descendants().find(|n| n.has_tag_name("manifest"));
+            let free = !names.contains(&name) && manifest.is_some_and(|m| m.children().any(|c| c.is_element()));
+            (free.then_some(name), free)
+        }
+    };
+
+    // What's replaced goes, with the <meta refines="#id"> that describe it
+    let replaced = |child: Node| {
+        let dc_name = child.tag_name().namespace() == Some(DC_NS);
+        let name = child.tag_name().name();
+        let meta_name = child.attribute("name").unwrap_or_default();
+        (dc_name && name == "title")
+            || (dc_name && name == "creator" && is_author(node, child))
+            || (name == "meta" && matches!(meta_name, "calibre:series" | "calibre:series_index"))
+            || (name == "meta" && child.attribute("property") == Some("belongs-to-collection"))
+            || (name == "meta" && meta_name == "cover" && add_item)
+    };
+    let dropped_ids: HashSet<String> =
+        node.children().filter(|c| c.is_element() && replaced(*c)).filter_map(|c| c.attribute("id")).map(|id| format!("#{}", id)).collect();
+    let dropped = |child: Node| replaced(child) || child.attribute("refines").is_some_and(|r| dropped_ids.contains(r));
+
+    let indent = node
+        .children()
+        .find(|c| c.is_text())
+        .map(|c| &opf[c.range()])
+        .and_then(|ws| ws.rsplit_once('\n'))
+        .map(|(_, indent)| indent.to_string())
+        .unwrap_or_else(|| "    ".to_string());
+    let mut content = String::new();
+    // Whitespace before a dropped element goes with it, so no blank lines are left
+    let mut pending = "";
+    for child in node.children() {
+        let source = &opf[child.range()];
+        if child.is_text() && source.trim().is_empty() {
+            pending = source;
+        } else if child.is_element() && dropped(child) {
+            pending = "";
+        } else {
+            content.push_str(pending);
+            content.push_str(source);
+            pending = "";
+        }
+    }
+
+    let mut added = vec![format!("<{dc}title{dc_xmlns}>{}</{dc}title>", escape(&metadata.title))];
+    added.extend(metadata.authors.iter().map(|author| format!("<{dc}creator{dc_xmlns}>{}</{dc}creator>", escape(author))));
+    if let Some((series, index)) = &metadata.series {
+        added.push(format!("<{opf_prefix}meta name=\"calibre:series\" content=\"{}\"/>", escape(series)));
+        added.push(format!("<{opf_prefix}meta name=\"calibre:series_index\" content=\"{}\"/>", index));
+        if epub3 {
+            added.push(format!("<{opf_prefix}meta property=\"belongs-to-collection\" id=\"tuilibre-series\">{}</{opf_prefix}meta>", escape(series)));
+            added.push(format!("<{opf_prefix}meta refines=\"#tuilibre-series\" property=\"collection-type\">series</{opf_prefix}meta>"));
+            added.push(format!("<{opf_prefix}meta refines=\"#tuilibre-series\" property=\"group-position\">{}</{opf_prefix}meta>", index));
+        }
+    }
+    if add_item {
+        added.push(format!("<{opf_prefix}meta name=\"cover\" content=\"{}\"/>", ADDED_COVER.0));
+    }
+    for element in added {
+        content.push('\n');
+        content.push_str(&indent);
+        content.push_str(&element);
+    }
+    content.push_str(if pending.is_empty() { "\n" } else { pending });
+
+    let mut edits = vec![(first.range().start..last.range().end, content)];
+    if add_item {
+        if let Some(item) = doc.descendants().find(|n| n.has_tag_name("manifest")).and_then(|m| m.children().rev().find(|c| c.is_element())) {
+            let before = item.prev_sibling().filter(|s| s.is_text()).map(|s| &opf[s.range()]).unwrap_or("\n");
+            let properties = if epub3 { " properties=\"cover-image\"" } else { "" };
+            let item_prefix = prefix_in(item, OPF_NS).unwrap_or_default();
+            let added = format!("{}<{item_prefix}item id=\"{}\" href=\"{}\" media-type=\"image/jpeg\"{}/>", before, ADDED_COVER.0, ADDED_COVER.1, properties);
+            edits.push((item.range().end..item.range().end, added));
+        }
+    }
+
+    // Spliced from the end, so earlier ranges still point at the right text
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    let mut rewritten = opf.to_string();
+    for (range, text) in edits {
+        rewritten.replace_range(range, &text);
+    }
+    // Never write an OPF readers can't parse
+    Document::parse(&rewritten).map_err(|e| format!("rewritten OPF is invalid: {}", e))?;
+    Ok((rewritten, cover_entry))
+}
+
+/// The prefix, e.g. "dc:", that names `namespace` where `node` is; empty when it is the default
+/// namespace there and None when it isn't declared
+fn prefix_in(node: Node, namespace: &str) -> Option<String> {
+    if node.lookup_namespace_uri(None) == Some(namespace) {
+        return Some(String::new());
+    }
+    node.lookup_prefix(namespace).filter(|prefix| !prefix.is_empty()).map(|prefix| format!("{}:", prefix))
+}
+
+/// Whether the `dc:creator` `node` is an author: it has no role or the author role
+///
+/// EPUB 3 gives roles in `<meta refines="#id" property="role">`, EPUB 2 in an `opf:role`
+/// attribute.
+fn is_author(metadata: Node, node: Node) -> bool {
+    let refined_role = || {
+        let id = node.attribute("id")?;
+        metadata
+            .children()
+            .find(|m| m.attribute("property") == Some("role") && m.attribute("refines") == Some(&format!("#{}", id)))
+            .and_then(|m| m.text())
+    };
+    let role = node.attributes().find(|a| a.name() == "role").map(|a| a.value()).or_else(refined_role);
+    role.is_none_or(|role| role == "aut")
+}
+
+/// The manifest item of the cover: the one with the EPUB 3 `cover-image` property, or the one
+/// an EPUB 2 `<meta name="cover">` points at
+fn cover_item<'a, 'input>(doc: &'a Document<'input>, metadata: Node) -> Option<Node<'a, 'input>> {
+    let items: Vec<Node> = doc.descendants().filter(|n| n.has_tag_name("item")).collect();
+    let by_property = items
+        .iter()
+        .find(|item| item.attribute("properties").is_some_and(|p| p.split_whitespace().any(|p| p == "cover-image")));
+    let by_meta = || {
+        let id = metadata.children().find(|m| m.attribute("name") == Some("cover"))?.attribute("content")?;
+        items.iter().find(|item| item.attribute("id") == Some(id))
+    };
+    by_property.or_else(by_meta).copied()
+}
+
+/// The zip entry a manifest `href` names, relative to the OPF at `opf_path`
+fn entry_name(opf_path: &str, href: &str) -> String {
+    let base = Path::new(opf_path).parent().unwrap_or(Path::new(""));
+    let decoded = percent_encoding::percent_decode_str(href).decode_utf8_lossy().into_owned();
+    base.join(decoded).to_string_lossy().replace('\\', "/")
+}
+
+/// The path and text of the OPF, as container.xml names it
+fn read_opf(archive: &mut zip::ZipArchive<File>) -> std::result::Result<(String, String), Box<dyn std::error::Error>> {
+    let container = read_entry(archive, "META-INF/container.xml")?;
+    let opf_path = {
+        let doc = Document::parse(&container)?;
+        doc.descendants()
+            .find(|n| n.has_tag_name("rootfile"))
+            .and_then(|n| n.attribute("full-path"))
+            .map(str::to_string)
+            .ok_or("no rootfile in container.xml")?
+    };
+    let opf = read_entry(archive, &opf_path)?;
+    Ok((opf_path, opf))
+}
+
+/// `text` with the characters XML reserves escaped
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The `dc:` elements named `name` in `metadata`
+fn dc_elements<'a, 'input>(metadata: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    metadata.children().filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+/// An element's trimmed text, None when empty
+fn text(node: Node) -> Option<String> {
+    let text = node.text()?.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn read_entry(archive: &mut zip::ZipArchive<File>, name: &str) -> std::result::Result<String, Box<dyn std::error::Error>> {
+    let mut bytes = Vec::new();
+    archive.by_name(name)?.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn invalid_epub(path: &Path, error: impl std::fmt::Display) -> Error {
+    Error::Parse { context: format!("Invalid EPUB: {}", path.display()), message: error.to_string() }
+}
@@ -0,0 +1,130 @@
+//! Errors returned by the library API
+
+use std::fmt::Display;
+use std::path::PathBuf;
+
+/// What went wrong, by kind, so callers can match on it
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A query or the connection to `metadata.db` failed
+    #[error("Database error: {0}")]
+    DatabaseError(sqlx::Error),
+    /// There is no `metadata.db` at the given library path
+    #[error("No calibre library found at: {}", .0.display())]
+    LibraryNotFound(PathBuf),
+    /// `metadata.db` lacks a table or column tuilibre needs, e.g. from a much older calibre
+    #[error("Unsupported calibre database: {0}")]
+    SchemaUnsupported(String),
+    /// Another process holds the library's write lock, so this connection only reads
+    #[error("Library is read-only, locked by {0}")]
+    LibraryLocked(String),
+    /// The library's share or drive is absent and its snapshot is open instead, which only reads
+    #[error("Library is offline, browsing a snapshot: {}", .0.display())]
+    LibraryOffline(PathBuf),
+    /// A file the library refers to isn't on disk
+    #[error("File not found: {}", .0.display())]
+    FileMissing(PathBuf),
+    /// Setting up, drawing to or reading from the terminal failed
+    #[error("Terminal error: {0}")]
+    TerminalError(#[source] std::io::Error),
+    /// Reading or writing a file failed
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// A config, history, settings or downloaded file couldn't be parsed or written
+    #[error("{context}: {message}")]
+    Parse { context: String, message: String },
+    /// An HTTP request failed
+    #[error("{context}: {message}")]
+    Network { context: String, message: String },
+    /// Anything else, such as an input the operation refuses
+    #[error("{0}")]
+    Other(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// Describe what was being done when a file, parse or network error happened;
+    /// the other kinds already say what they are about and are left unchanged
+    pub fn context(self, context: impl Display) -> Self {
+        match self {
+            Error::Io { source, .. } => Error::Io { context: context.to_string(), source },
+            Error::Parse { message, .. } => Error::Parse { context: context.to_string(), message },
+            Error::Network { message, .. } => Error::Network { context: context.to_string(), message },
+            other => other,
+        }
+    }
+
+    fn parse(error: impl Display) -> Self {
+        Error::Parse { context: "Invalid data".to_string(), message: error.to_string() }
+    }
+}
+
+/// Add context to a failed result, in the manner of `anyhow::Context`
+pub trait Context<T> {
+    fn with_context<C: Display, F: FnOnce() -> C>(self, context: F) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for std::result::Result<T, E> {
+    fn with_context<C: Display, F: FnOnce() -> C>(self, context: F) -> Result<T> {
+        self.map_err(|e| e.into().context(context()))
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(error: sqlx::Error) -> Self {
+        // SQLite reports tables and columns a query needs but the file lacks this way
+        let message = error.to_string();
+        if message.contains("no such table") || message.contains("no such column") {
+            Error::SchemaUnsupported(message)
+        } else {
+            Error::DatabaseError(error)
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(source: std::io::Error) -> Self {
+        Error::Io { context: "I/O error".to_string(), source }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::parse(error)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(error: toml::de::Error) -> Self {
+        Error::parse(error)
+    }
+}
+
+impl From<roxmltree::Error> for Error {
+    fn from(error: roxmltree::Error) -> Self {
+        Error::parse(error)
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(error: zip::result::ZipError) -> Self {
+        Error::parse(error)
+    }
+}
+
+impl From<ureq::Error> for Error {
+    fn from(error: ureq::Error) -> Self {
+        Error::Network { context: "Request failed".to_string(), message: error.to_string() }
+    }
+}
+
+impl From<tokio::task::JoinError> for Error {
+    fn from(error: tokio::task::JoinError) -> Self {
+        Error::Other(format!("Background task failed: {}", error))
+    }
+}
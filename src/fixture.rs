@@ -0,0 +1,295 @@
+//! Synthetic calibre libraries for benchmarks and testing
+//!
+//! Only `metadata.db` is written; the books' directories and files are not created.
+
+use crate::error::{Context, Result};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{Connection, Executor, QueryBuilder, Sqlite, SqliteConnection};
+use std::path::Path;
+
+/// The parts of calibre's schema tuilibre reads and writes
+const SCHEMA: &str = r#"
+CREATE TABLE books ( id INTEGER PRIMARY KEY AUTOINCREMENT, title TEXT NOT NULL DEFAULT 'Unknown' COLLATE NOCASE, sort TEXT COLLATE NOCASE, timestamp TIMESTAMP DEFAULT CURRENT_TIMESTAMP, pubdate TIMESTAMP DEFAULT CURRENT_TIMESTAMP, series_index REAL NOT NULL DEFAULT 1.0, author_sort TEXT COLLATE NOCASE, isbn TEXT DEFAULT "" COLLATE NOCASE, lccn TEXT DEFAULT "" COLLATE NOCASE, path TEXT NOT NULL DEFAULT "", flags INTEGER NOT NULL DEFAULT 1, uuid TEXT, has_cover BOOL DEFAULT 0, last_modified TIMESTAMP NOT NULL DEFAULT "2000-01-01 00:00:00+00:00");
+CREATE TABLE authors ( id INTEGER PRIMARY KEY, name TEXT NOT NULL COLLATE NOCASE, sort TEXT COLLATE NOCASE, link TEXT NOT NULL DEFAULT "", UNIQUE(name));
+CREATE TABLE books_authors_link ( id INTEGER PRIMARY KEY, book INTEGER NOT NULL, author INTEGER NOT NULL, UNIQUE(book, author));
+CREATE TABLE tags ( id INTEGER PRIMARY KEY, name TEXT NOT NULL COLLATE NOCASE, link TEXT NOT NULL DEFAULT "", UNIQUE (name));
+CREATE TABLE books_tags_link ( id INTEGER PRIMARY KEY, book INTEGER NOT NULL, tag INTEGER NOT NULL, UNIQUE(book, tag));
+CREATE TABLE data ( id INTEGER PRIMARY KEY, book INTEGER NOT NULL, format TEXT NOT NULL COLLATE NOCASE, uncompressed_size INTEGER NOT NULL, name TEXT NOT NULL, UNIQUE(book, format));
+CREATE TABLE series ( id INTEGER PRIMARY KEY, name TEXT NOT NULL COLLATE NOCASE, sort TEXT COLLATE NOCASE, link TEXT NOT NULL DEFAULT "", UNIQUE (name));
+CREATE TABLE books_series_link ( id INTEGER PRIMARY KEY, book INTEGER NOT NULL, series INTEGER NOT NULL, UNIQUE(book));
+CREATE TABLE ratings ( id INTEGER PRIMARY KEY, rating INTEGER CHECK(rating > -1 AND rating < 11), link TEXT NOT NULL DEFAULT "", UNIQUE (rating));
+CREATE TABLE books_ratings_link ( id INTEGER PRIMARY KEY, book INTEGER NOT NULL, rating INTEGER NOT NULL, UNIQUE(book, rating));
+CREATE TABLE comments ( id INTEGER PRIMARY KEY, book INTEGER NOT NULL, text TEXT NOT NULL COLLATE NOCASE, UNIQUE(book));
+CREATE TABLE languages ( id INTEGER PRIMARY KEY, lang_code TEXT NOT NULL COLLATE NOCASE, link TEXT NOT NULL DEFAULT "", UNIQUE(lang_code));
+CREATE TABLE books_languages_link ( id INTEGER PRIMARY KEY, book INTEGER NOT NULL, lang_code INTEGER NOT NULL, item_order INTEGER NOT NULL DEFAULT 0, UNIQUE(book, lang_code));
+CREATE TABLE identifiers ( id INTEGER PRIMARY KEY, book INTEGER NOT NULL, type TEXT NOT NULL DEFAULT "isbn" COLLATE NOCASE, val TEXT NOT NULL COLLATE NOCASE, UNIQUE(book, type));
+CREATE TABLE preferences ( id INTEGER PRIMARY KEY, key TEXT NOT NULL, val TEXT NOT NULL, UNIQUE(key));
+CREATE TABLE library_id ( id INTEGER PRIMARY KEY, uuid TEXT NOT NULL, UNIQUE(uuid));
+CREATE TABLE custom_columns ( id INTEGER PRIMARY KEY AUTOINCREMENT, label TEXT NOT NULL, name TEXT NOT NULL, datatype TEXT NOT NULL, mark_for_delete BOOL DEFAULT 0 NOT NULL, editable BOOL DEFAULT 1 NOT NULL, display TEXT DEFAULT "{}" NOT NULL, is_multiple BOOL DEFAULT 0 NOT NULL, normalized BOOL NOT NULL, UNIQUE(label));
+"#;
+
+const FIRST_NAMES: &[&str] = &[
+    "Ada", "Alan", "Ursula", "Isaac", "Octavia", "Frank", "Mary", "Terry", "Neil", "Iain",
+    "Ann", "Ted", "Jorge", "Italo", "Haruki", "Chimamanda", "Kazuo", "Toni", "Gabriel", "Doris",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Le Guin", "Asimov", "Butler", "Herbert", "Shelley", "Pratchett", "Gaiman", "Banks", "Leckie", "Chiang",
+    "Borges", "Calvino", "Murakami", "Adichie", "Ishiguro", "Morrison", "Márquez", "Lessing", "Clarke", "Lem",
+];
+
+const TITLE_WORDS: &[&str] = &[
+    "Night", "River", "Empire", "Glass", "Winter", "Machine", "Garden", "Shadow", "Station", "Dragon",
+    "Silence", "Harbor", "Mirror", "Storm", "Library", "Crown", "Orbit", "Forest", "Memory", "Stone",
+];
+
+const TAGS: &[&str] = &[
+    "fantasy", "scifi", "classic", "history", "poetry", "mystery", "romance", "horror", "biography", "essays",
+    "philosophy", "science", "travel", "cooking", "art", "comics", "children", "thriller", "drama", "humor",
+];
+
+const FORMATS: &[&str] = &["EPUB", "AZW3", "MOBI", "PDF", "CBZ", "TXT"];
+
+const LANGUAGES: &[&str] = &["eng", "deu", "fra", "spa", "zho", "jpn"];
+
+/// Rows per multi-row INSERT, well under SQLite's bound parameter limit
+const CHUNK: usize = 500;
+
+/// Create `path/metadata.db` holding `books` random books, the same ones for the same `seed`
+pub async fn generate_library(path: &Path, books: usize, seed: u64) -> Result<()> {
+    std::fs::create_dir_all(path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let db_path = path.join("metadata.db");
+    if db_path.exists() {
+        std::fs::remove_file(&db_path)
+            .with_context(|| format!("Failed to replace {}", db_path.display()))?;
+    }
+
+    let options = SqliteConnectOptions::new().filename(&db_path).create_if_missing(true);
+    let mut conn = SqliteConnection::connect_with(&options).await?;
+    conn.execute(SCHEMA).await?;
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut tx = conn.begin().await?;
+
+    sqlx::query("INSERT INTO library_id(uuid) VALUES (?)")
+        .bind(format!("{:032x}", rng.gen::<u128>()))
+        .execute(&mut *tx)
+        .await?;
+
+    // Roughly four books per author, as in a typical personal library
+    let authors: Vec<String> = (0..(books / 4).max(1))
+        .map(|i| {
+            let first = FIRST_NAMES.choose(&mut rng).copied().unwrap_or("Anon");
+            let last = LAST_NAMES.choose(&mut rng).copied().unwrap_or("Author");
+            format!("{} {} {}", first, last, i)
+        })
+        .collect();
+    insert_names(&mut tx, "authors", &authors).await?;
+    let tags: Vec<String> = TAGS.iter().map(|t| t.to_string()).collect();
+    insert_names(&mut tx, "tags", &tags).await?;
+    // About ten books per series, though most books aren't in one
+    let series: Vec<String> = (0..(books / 10).max(1))
+        .map(|i| format!("{} Cycle {}", TITLE_WORDS.choose(&mut rng).copied().unwrap_or("Saga"), i))
+        .collect();
+    insert_names(&mut tx, "series", &series).await?;
+    for stars in 1..=5 {
+        sqlx::query("INSERT INTO ratings(id, rating) VALUES (?, ?)")
+            .bind(stars)
+            .bind(stars * 2)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for (i, code) in LANGUAGES.iter().enumerate() {
+        sqlx::query("INSERT INTO languages(id, lang_code) VALUES (?, ?)")
+            .bind(i as i64 + 1)
+            .bind(code)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let ids: Vec<usize> = (1..=books).collect();
+    for chunk in ids.chunks(CHUNK) {
+        let rows: Vec<SyntheticBook> = chunk
+            .iter()
+            .map(|&id| SyntheticBook::random(&mut rng, id, &authors, series.len()))
+            .collect();
+        insert_books(&mut tx, &rows).await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// One generated book and its links
+struct SyntheticBook {
+    id: usize,
+    title: String,
+    author: usize,
+    author_name: String,
+    /// Second author, on about one book in ten
+    coauthor: Option<usize>,
+    tags: Vec<usize>,
+    /// Series and position in it
+    series: Option<(usize, u32)>,
+    /// Stars, 1-5
+    rating: Option<u32>,
+    description: Option<String>,
+    formats: Vec<&'static str>,
+    language: usize,
+    timestamp: String,
+    has_cover: bool,
+}
+
+impl SyntheticBook {
+    fn random(rng: &mut StdRng, id: usize, authors: &[String], series: usize) -> Self {
+        let words = rng.gen_range(1..=4);
+        let title = (0..words)
+            .map(|_| TITLE_WORDS.choose(rng).copied().unwrap_or("Book"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let author = rng.gen_range(0..authors.len());
+        let coauthor = Some(rng.gen_range(0..authors.len())).filter(|&a| a != author && rng.gen_bool(0.1));
+
+        let mut tags: Vec<usize> = (0..rng.gen_range(0..=3)).map(|_| rng.gen_range(0..TAGS.len())).collect();
+        tags.sort_unstable();
+        tags.dedup();
+        let format_count = rng.gen_range(1..=3);
+        let mut formats: Vec<&'static str> = FORMATS.choose_multiple(rng, format_count).copied().collect();
+        formats.sort_unstable();
+
+        let series = rng.gen_bool(0.3).then(|| (rng.gen_range(0..series), rng.gen_range(1..=8)));
+        let rating = rng.gen_bool(0.5).then(|| rng.gen_range(1..=5));
+        let description = rng.gen_bool(0.4).then(|| {
+            let words: Vec<String> = (0..rng.gen_range(8..40))
+                .map(|_| TITLE_WORDS.choose(rng).copied().unwrap_or("word").to_lowercase())
+                .collect();
+            format!("<p>{}.</p>", words.join(" "))
+        });
+
+        SyntheticBook {
+            id,
+            title: format!("The {} {}", title, id),
+            author,
+            author_name: authors[author].clone(),
+            coauthor,
+            tags,
+            series,
+            rating,
+            description,
+            formats,
+            language: rng.gen_range(0..LANGUAGES.len()),
+            timestamp: format!(
+                "{:04}-{:02}-{:02} 10:00:00+00:00",
+                rng.gen_range(2008..=2024),
+                rng.gen_range(1..=12),
+                rng.gen_range(1..=28)
+            ),
+            has_cover: rng.gen_bool(0.8),
+        }
+    }
+
+    fn path(&self) -> String {
+        format!("{}/{} ({})", self.author_name, self.title, self.id)
+    }
+
+    fn file_name(&self) -> String {
+        format!("{} - {}", self.title, self.author_name)
+    }
+}
+
+/// Fill a calibre name table (`authors`, `tags`), ids starting at 1
+async fn insert_names(tx: &mut sqlx::Transaction<'_, Sqlite>, table: &str, names: &[String]) -> Result<()> {
+    for (start, chunk) in names.chunks(CHUNK).enumerate() {
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!("INSERT INTO {}(id, name) ", table));
+        builder.push_values(chunk.iter().enumerate(), |mut row, (i, name)| {
+            row.push_bind((start * CHUNK + i + 1) as i64).push_bind(name);
+        });
+        builder.build().execute(&mut **tx).await?;
+    }
+    Ok(())
+}
+
+async fn insert_books(tx: &mut sqlx::Transaction<'_, Sqlite>, books: &[SyntheticBook]) -> Result<()> {
+    let mut builder: QueryBuilder<Sqlite> =
+        QueryBuilder::new("INSERT INTO books(id, title, sort, author_sort, path, timestamp, last_modified, has_cover, series_index) ");
+    builder.push_values(books, |mut row, book| {
+        row.push_bind(book.id as i64)
+            .push_bind(&book.title)
+            .push_bind(&book.title)
+            .push_bind(&book.author_name)
+            .push_bind(book.path())
+            .push_bind(&book.timestamp)
+            .push_bind(&book.timestamp)
+            .push_bind(book.has_cover)
+            .push_bind(book.series.map_or(1.0, |(_, index)| index as f64));
+    });
+    builder.build().execute(&mut **tx).await?;
+
+    let authors: Vec<(usize, usize)> = books
+        .iter()
+        .flat_map(|b| std::iter::once(b.author).chain(b.coauthor).map(move |a| (b.id, a)))
+        .collect();
+    insert_links(tx, "books_authors_link(book, author)", &authors).await?;
+
+    let languages: Vec<(usize, usize)> = books.iter().map(|b| (b.id, b.language)).collect();
+    insert_links(tx, "books_languages_link(book, lang_code)", &languages).await?;
+
+    let tags: Vec<(usize, usize)> = books
+        .iter()
+        .flat_map(|b| b.tags.iter().map(move |&t| (b.id, t)))
+        .collect();
+    insert_links(tx, "books_tags_link(book, tag)", &tags).await?;
+
+    let series: Vec<(usize, usize)> = books.iter().filter_map(|b| b.series.map(|(s, _)| (b.id, s))).collect();
+    insert_links(tx, "books_series_link(book, series)", &series).await?;
+
+    // Rating rows 1-5 hold 2-10, calibre's half-star scale
+    let ratings: Vec<(usize, usize)> = books
+        .iter()
+        .filter_map(|b| b.rating.map(|stars| (b.id, stars as usize - 1)))
+        .collect();
+    insert_links(tx, "books_ratings_link(book, rating)", &ratings).await?;
+
+    let described: Vec<&SyntheticBook> = books.iter().filter(|b| b.description.is_some()).collect();
+    for chunk in described.chunks(CHUNK) {
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new("INSERT INTO comments(book, text) ");
+        builder.push_values(chunk, |mut row, book| {
+            row.push_bind(book.id as i64).push_bind(book.description.as_deref().unwrap_or_default());
+        });
+        builder.build().execute(&mut **tx).await?;
+    }
+
+    let formats: Vec<(&SyntheticBook, &str)> = books
+        .iter()
+        .flat_map(|b| b.formats.iter().map(move |&f| (b, f)))
+        .collect();
+    for chunk in formats.chunks(CHUNK) {
+        let mut builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new("INSERT INTO data(book, format, uncompressed_size, name) ");
+        builder.push_values(chunk, |mut row, (book, format)| {
+            row.push_bind(book.id as i64)
+                .push_bind(*format)
+                .push_bind(512 * 1024_i64)
+                .push_bind(book.file_name());
+        });
+        builder.build().execute(&mut **tx).await?;
+    }
+
+    Ok(())
+}
+
+/// Fill a link table such as `books_tags_link(book, tag)` from (book id, zero-based item) pairs
+async fn insert_links(tx: &mut sqlx::Transaction<'_, Sqlite>, table: &str, links: &[(usize, usize)]) -> Result<()> {
+    for chunk in links.chunks(CHUNK) {
+        let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!("INSERT INTO {} ", table));
+        builder.push_values(chunk, |mut row, (book, item)| {
+            row.push_bind(*book as i64).push_bind(*item as i64 + 1);
+        });
+        builder.build().execute(&mut **tx).await?;
+    }
+    Ok(())
+}
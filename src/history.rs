@@ -1,10 +1,12 @@
-use anyhow::{Context, Result};
+use crate::error::{Context, Error, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 use chrono::{DateTime, Utc};
 
+use crate::utils::mounts::{is_mounted, removable_mount};
+
 /// Library usage history
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LibraryHistory {
@@ -18,6 +20,39 @@ pub struct LibraryEntry {
     pub last_used: DateTime<Utc>,
     pub use_count: u32,
     pub book_count: Option<i32>,
+    /// Kept at the top of the selector and never dropped from history
+    #[serde(default)]
+    pub pinned: bool,
+    /// Name given to the library in tuilibre, shown instead of its directory name
+    #[serde(default)]
+    pub alias: Option<String>,
+    /// Uuid of the calibre library, to find it again after it is moved
+    #[serde(default)]
+    pub library_id: Option<String>,
+    /// Mount point of the network share or removable drive the library is on
+    #[serde(default)]
+    pub mount: Option<PathBuf>,
+}
+
+impl LibraryEntry {
+    /// The alias, or else the recorded or directory name
+    pub fn display_name(&self) -> String {
+        self.alias
+            .clone()
+            .or_else(|| self.name.clone())
+            .unwrap_or_else(|| self.path.file_name().and_then(|n| n.to_str()).unwrap_or("Unknown").to_string())
+    }
+
+    /// The mount point of the share or drive the library is on, if that is absent now
+    pub fn absent_mount(&self) -> Option<&Path> {
+        self.mount.as_deref().filter(|mount| !is_mounted(mount))
+    }
+}
+
+impl Default for LibraryHistory {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LibraryHistory {
@@ -31,7 +66,7 @@ impl LibraryHistory {
     /// Get the history file path in user's home directory
     pub fn get_history_file_path() -> Result<PathBuf> {
         let home_dir = dirs::home_dir()
-            .ok_or_else(|| anyhow::anyhow!("Could not find user home directory"))?;
+            .ok_or_else(|| Error::Other("Could not find user home directory".to_string()))?;
 
         let config_dir = home_dir.join(".config").join("tuilibre");
         fs::create_dir_all(&config_dir)
@@ -42,10 +77,13 @@ impl LibraryHistory {
 
     /// Load history from file
     pub fn load() -> Result<Self> {
-        let history_path = Self::get_history_file_path()?;
+        Self::load_from(&Self::get_history_file_path()?)
+    }
 
+    /// Load history from a file other than the user's, e.g. when embedding the library
+    pub fn load_from(history_path: &Path) -> Result<Self> {
         if history_path.exists() {
-            let content = fs::read_to_string(&history_path)
+            let content = fs::read_to_string(history_path)
                 .with_context(|| format!("Failed to read history file: {}", history_path.display()))?;
 
             let history: LibraryHistory = serde_json::from_str(&content)
@@ -60,12 +98,15 @@ impl LibraryHistory {
 
     /// Save history to file
     pub fn save(&self) -> Result<()> {
-        let history_path = Self::get_history_file_path()?;
+        self.save_to(&Self::get_history_file_path()?)
+    }
 
+    /// Save history to a file other than the user's
+    pub fn save_to(&self, history_path: &Path) -> Result<()> {
         let content = serde_json::to_string_pretty(self)
             .with_context(|| "Failed to serialize history")?;
 
-        fs::write(&history_path, content)
+        fs::write(history_path, content)
             .with_context(|| format!("Failed to write history file: {}", history_path.display()))?;
 
         Ok(())
@@ -85,6 +126,7 @@ impl LibraryHistory {
             if book_count.is_some() {
                 entry.book_count = book_count;
             }
+            entry.mount = removable_mount(&path);
         } else {
             // Add new entry
             let entry = LibraryEntry {
@@ -93,6 +135,10 @@ impl LibraryHistory {
                 last_used: Utc::now(),
                 use_count: 1,
                 book_count,
+                pinned: false,
+                alias: None,
+                library_id: None,
+                mount: removable_mount(&path),
             };
             self.libraries.push(entry);
         }
@@ -118,8 +164,14 @@ impl LibraryHistory {
                 .then_with(|| b.use_count.cmp(&a.use_count))
         });
 
-        // Limit to reasonable number (keep last 20)
-        unique_libraries.truncate(20);
+        // Limit to reasonable number (keep pinned ones and the last 20 others)
+        let mut unpinned = 0;
+        unique_libraries.retain(|entry| {
+            if !entry.pinned {
+                unpinned += 1;
+            }
+            entry.pinned || unpinned <= 20
+        });
 
         LibraryHistory {
             libraries: unique_libraries,
@@ -141,6 +193,82 @@ impl LibraryHistory {
         !self.libraries.is_empty()
     }
 
+    /// Pin or unpin a library, adding it to history if needed; returns whether it is now pinned
+    pub fn toggle_pinned(&mut self, path: &Path) -> bool {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+        match self.libraries.iter_mut().find(|e| e.path == path) {
+            Some(entry) => {
+                entry.pinned = !entry.pinned;
+                entry.pinned
+            }
+            None => {
+                self.libraries.push(LibraryEntry {
+                    path,
+                    name: None,
+                    last_used: Utc::now(),
+                    use_count: 0,
+                    book_count: None,
+                    pinned: true,
+                    alias: None,
+                    library_id: None,
+                mount: None,
+                });
+                true
+            }
+        }
+    }
+
+    /// Give a library an alias, or drop it with `None`, adding the library to history if needed
+    pub fn set_alias(&mut self, path: &Path, alias: Option<String>) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let alias = alias.map(|a| a.trim().to_string()).filter(|a| !a.is_empty());
+
+        match self.libraries.iter_mut().find(|e| e.path == path) {
+            Some(entry) => entry.alias = alias,
+            None => self.libraries.push(LibraryEntry {
+                path,
+                name: None,
+                last_used: Utc::now(),
+                use_count: 0,
+                book_count: None,
+                pinned: false,
+                alias,
+                library_id: None,
+                mount: None,
+            }),
+        }
+    }
+
+    /// The alias given to a library, if any
+    pub fn alias(&self, path: &Path) -> Option<&str> {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.libraries.iter().find(|e| e.path == path).and_then(|e| e.alias.as_deref())
+    }
+
+    /// Record the uuid of a library already in history
+    pub fn set_library_id(&mut self, path: &Path, library_id: String) {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if let Some(entry) = self.libraries.iter_mut().find(|e| e.path == path) {
+            entry.library_id = Some(library_id);
+        }
+    }
+
+    /// Libraries in history whose directory no longer holds a calibre library
+    pub fn missing_libraries(&self) -> impl Iterator<Item = &LibraryEntry> {
+        self.libraries.iter().filter(|e| !e.path.join("metadata.db").exists())
+    }
+
+    /// Point a library's history entry at the directory it was moved to, keeping its alias,
+    /// pin and usage
+    pub fn relink(&mut self, old_path: &Path, new_path: &Path) {
+        let new_path = new_path.canonicalize().unwrap_or_else(|_| new_path.to_path_buf());
+        if let Some(entry) = self.libraries.iter_mut().find(|e| e.path == old_path) {
+            entry.mount = removable_mount(&new_path);
+            entry.path = new_path;
+        }
+    }
+
     /// Remove a library from history
     pub fn remove_library(&mut self, index: usize) -> Result<()> {
         if index < self.libraries.len() {
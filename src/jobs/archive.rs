@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+use crate::app::Book;
+use crate::database::FormatEntry;
+use crate::error::{Context, Error, Result};
+use crate::jobs::{CancelToken, JobFailure, JobUpdate};
+
+/// Name of the CSV listing every archived book, at the root of the archive
+pub const MANIFEST_NAME: &str = "manifest.csv";
+
+/// Container written, chosen by the destination's extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+}
+
+impl ArchiveFormat {
+    /// Format for a destination path; None if it is neither .zip nor .tar
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "zip" => Some(ArchiveFormat::Zip),
+            "tar" => Some(ArchiveFormat::Tar),
+            _ => None,
+        }
+    }
+}
+
+/// One book to archive: its files plus the metadata written next to them
+#[derive(Debug, Clone)]
+pub struct ArchiveBook {
+    pub book: Book,
+    pub series: Option<(String, f64)>,
+    /// Files in the library, stored under the book's folder by their own names
+    pub files: Vec<PathBuf>,
+}
+
+/// Books to put in one archive
+#[derive(Debug, Clone)]
+pub struct ArchivePlan {
+    pub destination: PathBuf,
+    pub format: ArchiveFormat,
+    pub books: Vec<ArchiveBook>,
+}
+
+/// Result of writing an archive
+#[derive(Debug, Default)]
+pub struct ArchiveSummary {
+    pub books: usize,
+    pub files: usize,
+    pub failed: Vec<JobFailure>,
+}
+
+/// Lay the books out as calibre does, `Author/Title (id)/`, with a `metadata.opf` in each folder
+pub fn plan_archive(
+    destination: &Path,
+    format: ArchiveFormat,
+    library_path: &Path,
+    books: &[&Book],
+    formats: &[FormatEntry],
+    series: &HashMap<i32, (String, f64)>,
+) -> ArchivePlan {
+    let books = books
+        .iter()
+        .map(|book| ArchiveBook {
+            book: (*book).clone(),
+            series: series.get(&book.id).cloned(),
+            files: formats
+                .iter()
+                .filter(|entry| entry.book_id == book.id)
+                .map(|entry| library_path.join(&entry.path).join(entry.filename()))
+                .collect(),
+        })
+        .collect();
+    ArchivePlan { destination: destination.to_path_buf(), format, books }
+}
+
+/// Write the archive in the background, removing it again if cancelled or it can't be finished
+pub async fn archive_books(
+    plan: ArchivePlan,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) -> ArchiveSummary {
+    let destination = plan.destination.clone();
+    let total = plan.books.len();
+    let progress = updates.clone();
+    let stop = cancel.clone();
+    let written = tokio::task::spawn_blocking(move || write_archive(plan, &progress, &stop)).await;
+
+    let (summary, stopped_at) = match written.map_err(Error::from).and_then(|result| result) {
+        Ok(written) => written,
+        Err(e) => {
+            let _ = std::fs::remove_file(&destination);
+            let message = format!("❌ Failed to write {}: {}", destination.display(), e);
+            let _ = updates.send(JobUpdate::Finished { message });
+            return ArchiveSummary::default();
+        }
+    };
+    let message = if cancel.is_cancelled() {
+        // Half an archive is no use for sharing or backup
+        let _ = std::fs::remove_file(&destination);
+        cancel.finish_message(format!("Removed the unfinished {}", destination.display()), stopped_at, total)
+    } else {
+        archive_message(&destination, &summary)
+    };
+    let _ = updates.send(JobUpdate::Failed(summary.failed.clone()));
+    let _ = updates.send(JobUpdate::Finished { message });
+    summary
+}
+
+/// Returns what was written and how many books were reached before a cancel
+fn write_archive(
+    plan: ArchivePlan,
+    updates: &mpsc::UnboundedSender<JobUpdate>,
+    cancel: &CancelToken,
+) -> Result<(ArchiveSummary, usize)> {
+    if let Some(folder) = plan.destination.parent().filter(|f| !f.as_os_str().is_empty()) {
+        std::fs::create_dir_all(folder).with_context(|| format!("Failed to create {}", folder.display()))?;
+    }
+    // Never write over an existing file
+    let file = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&plan.destination)
+        .with_context(|| format!("Failed to create {}", plan.destination.display()))?;
+    let mut archive = ArchiveWriter::new(plan.format, file);
+    let mut summary = ArchiveSummary::default();
+    let mut manifest = String::from("id,title,authors,tags,series,series_index,added,files\n");
+    let total = plan.books.len();
+    let mut stopped_at = total;
+
+    for (done, entry) in plan.books.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let book = &entry.book;
+        let _ = updates.send(JobUpdate::Progress { done, total, current: book.title.clone() });
+
+        let mut stored = Vec::new();
+        for source in &entry.files {
+            let name = source.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let path = format!("{}/{}", book.path, name);
+            match File::open(source) {
+                Ok(mut file) => {
+                    let size = file.metadata()?.len();
+                    archive.add(&path, size, &mut file)?;
+                    stored.push(path);
+                }
+                Err(e) => summary.failed.push(JobFailure::new(Some(book.id), book.title.clone(), format!("{}: {}", name, e))),
+            }
+        }
+
+        let opf = opf(book, entry.series.as_ref());
+        archive.add(&format!("{}/metadata.opf", book.path), opf.len() as u64, &mut opf.as_bytes())?;
+        manifest.push_str(&manifest_row(book, entry.series.as_ref(), &stored));
+        summary.books += 1;
+        summary.files += stored.len();
+    }
+
+    archive.add(MANIFEST_NAME, manifest.len() as u64, &mut manifest.as_bytes())?;
+    archive.finish()?;
+    Ok((summary, stopped_at))
+}
+
+/// Zip or tar output, written one entry at a time
+enum ArchiveWriter {
+    Zip(zip::ZipWriter<File>),
+    Tar(BufWriter<File>),
+}
+
+impl ArchiveWriter {
+    fn new(format: ArchiveFormat, file: File) -> Self {
+        match format {
+            ArchiveFormat::Zip => ArchiveWriter::Zip(zip::ZipWriter::new(file)),
+            ArchiveFormat::Tar => ArchiveWriter::Tar(BufWriter::new(file)),
+        }
+    }
+
+    fn add(&mut self, name: &str, size: u64, content: &mut impl io::Read) -> Result<()> {
+        match self {
+            ArchiveWriter::Zip(zip) => {
+                let options = zip::write::FileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated)
+                    .large_file(size >= u32::MAX as u64);
+                zip.start_file(name, options)?;
+                io::copy(content, zip)?;
+            }
+            ArchiveWriter::Tar(tar) => {
+                // Names past ustar's 100 bytes go in a GNU long-name entry first
+                if name.len() > 100 {
+                    let long_name = format!("{}\0", name);
+                    tar.write_all(&tar_header("././@LongLink", long_name.len() as u64, b'L'))?;
+                    tar.write_all(long_name.as_bytes())?;
+                    tar_pad(tar, long_name.len() as u64)?;
+                }
+                tar.write_all(&tar_header(name, size, b'0'))?;
+                let copied = io::copy(content, tar)?;
+                if copied != size {
+                    return Err(Error::Other(format!("{} changed while it was archived", name)));
+                }
+                tar_pad(tar, size)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            ArchiveWriter::Zip(mut zip) => {
+                zip.finish()?;
+            }
+            ArchiveWriter::Tar(mut tar) => {
+                // Two empty blocks end a tar
+                tar.write_all(&[0; 1024])?;
+                tar.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A 512-byte ustar header for a regular file (`b'0'`) or a GNU long name (`b'L'`)
+fn tar_header(name: &str, size: u64, kind: u8) -> [u8; 512] {
+    let mut header = [0u8; 512];
+    let name = name.as_bytes();
+    let name_len = name.len().min(100);
+    header[..name_len].copy_from_slice(&name[..name_len]);
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+    let mtime = chrono::Utc::now().timestamp().max(0);
+    header[136..148].copy_from_slice(format!("{:011o}\0", mtime).as_bytes());
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with its own field read as spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+    header
+}
+
+/// Fill the rest of the last 512-byte block of an entry
+fn tar_pad(tar: &mut impl Write, size: u64) -> io::Result<()> {
+    let rest = (512 - size % 512) % 512;
+    tar.write_all(&vec![0; rest as usize])
+}
+
+/// Calibre-style OPF metadata for one book
+fn opf(book: &Book, series: Option<&(String, f64)>) -> String {
+    let mut metadata = format!(
+        "    <dc:identifier opf:scheme=\"calibre\" id=\"calibre_id\">{}</dc:identifier>\n    <dc:title>{}</dc:title>\n",
+        book.id,
+        xml_escape(&book.title)
+    );
+    for author in &book.authors {
+        metadata.push_str(&format!("    <dc:creator opf:role=\"aut\">{}</dc:creator>\n", xml_escape(author)));
+    }
+    for tag in &book.tags {
+        metadata.push_str(&format!("    <dc:subject>{}</dc:subject>\n", xml_escape(tag)));
+    }
+    if let Some((name, index)) = series {
+        metadata.push_str(&format!("    <meta name=\"calibre:series\" content=\"{}\"/>\n", xml_escape(name)));
+        metadata.push_str(&format!("    <meta name=\"calibre:series_index\" content=\"{}\"/>\n", index));
+    }
+    metadata.push_str(&format!("    <meta name=\"calibre:timestamp\" content=\"{}\"/>\n", xml_escape(&book.timestamp)));
+    metadata.push_str(&format!("    <meta name=\"calibre:title_sort\" content=\"{}\"/>\n", xml_escape(&book.sort)));
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" unique-identifier=\"calibre_id\" version=\"2.0\">\n  \
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\">\n\
+         {}  </metadata>\n\
+         </package>\n",
+        metadata
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// One line of the manifest; multiple authors, tags and files are separated by " & ", ", " and "; "
+fn manifest_row(book: &Book, series: Option<&(String, f64)>, files: &[String]) -> String {
+    let (series_name, series_index) = match series {
+        Some((name, index)) => (name.clone(), index.to_string()),
+        None => (String::new(), String::new()),
+    };
+    let fields = [
+        book.id.to_string(),
+        book.title.clone(),
+        book.authors.join(" & "),
+        book.tag_list(),
+        series_name,
+        series_index,
+        book.timestamp.clone(),
+        files.join("; "),
+    ];
+    let mut row = fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(",");
+    row.push('\n');
+    row
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn archive_message(destination: &Path, summary: &ArchiveSummary) -> String {
+    let mut message = format!(
+        "🗜 Archived {} books ({} files) to {}",
+        summary.books,
+        summary.files,
+        destination.display()
+    );
+    if let Some(failure) = summary.failed.first() {
+        message.push_str(&format!(" | ❌ {} failed ({}: {})", summary.failed.len(), failure.title, failure.error));
+    }
+    message
+}
@@ -0,0 +1,86 @@
+//! Comparing the metadata embedded in the books' files with the library's, for the metadata
+//! audit
+//!
+//! EPUBs are read directly; other formats through calibre's `ebook-meta` when it is installed,
+//! and are skipped when it isn't.
+
+use tokio::sync::mpsc;
+
+use crate::app::MetadataMismatch;
+use crate::epub::{self, FileMetadata};
+use crate::jobs::embed::{is_epub, read_with_ebook_meta};
+use crate::jobs::{CancelToken, EmbedItem, JobFailure, JobUpdate};
+
+/// Read each item's file and report the ones whose title, authors or series differ from the
+/// library's, one at a time
+pub async fn audit_metadata(
+    items: Vec<EmbedItem>,
+    mut ebook_meta: String,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) {
+    let total = items.len();
+    let mut stopped_at = total;
+    let mut checked = 0;
+    let mut differing = 0;
+    let mut skipped = 0;
+    let mut failures = Vec::new();
+
+    for (done, item) in items.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let _ = updates.send(JobUpdate::Progress { done, total, current: format!("{} ({})", item.title, item.format) });
+
+        let read = if is_epub(&item.format) {
+            let path = item.path.clone();
+            tokio::task::spawn_blocking(move || epub::read_metadata(&path))
+                .await
+                .map_err(|e| format!("Metadata reader panicked: {}", e))
+                .and_then(|read| read.map_err(|e| e.to_string()))
+                .map(|metadata| FileMetadata {
+                    title: metadata.title.unwrap_or_default(),
+                    authors: metadata.authors,
+                    series: metadata.series,
+                })
+        } else if ebook_meta.is_empty() {
+            skipped += 1;
+            continue;
+        } else {
+            match read_with_ebook_meta(&ebook_meta, &item.path).await {
+                Some(read) => read,
+                None => {
+                    ebook_meta.clear();
+                    skipped += 1;
+                    continue;
+                }
+            }
+        };
+
+        match read {
+            Ok(file) => {
+                checked += 1;
+                if let Some(mismatch) = MetadataMismatch::compare(item, file) {
+                    differing += 1;
+                    let _ = updates.send(JobUpdate::Mismatch(Box::new(mismatch)));
+                }
+            }
+            Err(error) => failures.push(JobFailure::new(Some(item.book_id), format!("{} ({})", item.title, item.format), error)),
+        }
+    }
+
+    let mut message = match differing {
+        0 => format!("✔ The metadata of {} files matches the library", checked),
+        _ => format!("🔎 {} of {} files differ from the library", differing, checked),
+    };
+    if skipped > 0 {
+        message.push_str(&format!(" ({} skipped: other formats need ebook-meta from calibre)", skipped));
+    }
+    if let Some(failure) = failures.first() {
+        message.push_str(&format!(" | ❌ {} unreadable ({}: {})", failures.len(), failure.title, failure.error));
+    }
+    let _ = updates.send(JobUpdate::Failed(failures));
+    let message = cancel.finish_message(message, stopped_at, total);
+    let _ = updates.send(JobUpdate::Finished { message });
+}
@@ -0,0 +1,158 @@
+//! Extracting the text of the books into tuilibre's content index, for `content:` searches
+//!
+//! One file per book is read, EPUB before TXT before PDF: EPUBs and plain text the way the
+//! reader shows them, chapter by chapter, and PDFs page by page through `pdftotext` (from
+//! poppler) when it is installed. Files unchanged since they were last indexed are skipped, so
+//! running it again after adding books only reads the new ones.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+use crate::app::reader::load_chapters;
+use crate::app::Book;
+use crate::database::{Database, FileStamp};
+use crate::jobs::{CancelToken, JobFailure, JobUpdate};
+
+/// Formats text is extracted from, best first
+const CONTENT_FORMATS: [&str; 3] = ["EPUB", "TXT", "PDF"];
+
+/// The file of a book whose text goes into the index
+#[derive(Debug, Clone)]
+pub struct ContentItem {
+    pub book_id: i32,
+    pub title: String,
+    pub format: String,
+    pub path: PathBuf,
+}
+
+/// The best file of each book text can be extracted from; books with none are left out
+pub fn plan_content_index(library_path: &Path, books: &[Book]) -> Vec<ContentItem> {
+    books
+        .iter()
+        .filter_map(|book| {
+            let format = CONTENT_FORMATS
+                .iter()
+                .find_map(|wanted| book.formats.iter().find(|f| f.format.eq_ignore_ascii_case(wanted)))?;
+            Some(ContentItem {
+                book_id: book.id,
+                title: book.title.clone(),
+                format: format.format.to_uppercase(),
+                path: book.format_path(library_path, format),
+            })
+        })
+        .collect()
+}
+
+/// Index the text of every item whose file changed since it was last indexed, with `pdftotext`
+/// for PDFs; when that is empty or not installed PDFs are skipped
+///
+/// `items` is taken to be the whole library: books missing from it are dropped from the index.
+pub async fn index_contents(
+    database: Database,
+    items: Vec<ContentItem>,
+    mut pdftotext: String,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) {
+    let indexed = match database.indexed_files().await {
+        Ok(indexed) => indexed,
+        Err(e) => {
+            let message = format!("❌ Failed to open the content index: {}", e);
+            let _ = updates.send(JobUpdate::Finished { message });
+            return;
+        }
+    };
+    let keep: HashSet<i32> = items.iter().map(|item| item.book_id).collect();
+    let dropped = database.prune_content_index(&keep).await.unwrap_or(0);
+
+    let total = items.len();
+    let mut stopped_at = total;
+    let mut added = 0;
+    let mut current = 0;
+    let mut skipped = 0;
+    let mut failures = Vec::new();
+
+    for (done, item) in items.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let _ = updates.send(JobUpdate::Progress { done, total, current: format!("{} ({})", item.title, item.format) });
+
+        let Some(stamp) = FileStamp::of(&item.path) else {
+            failures.push(JobFailure::new(Some(item.book_id), format!("{} ({})", item.title, item.format), "File not found"));
+            continue;
+        };
+        if indexed.get(&(item.book_id, item.format.clone())) == Some(&stamp) {
+            current += 1;
+            continue;
+        }
+
+        let extracted = if item.format == "PDF" {
+            if pdftotext.is_empty() {
+                skipped += 1;
+                continue;
+            }
+            match pdf_pages(&pdftotext, &item.path).await {
+                Some(pages) => pages,
+                None => {
+                    pdftotext.clear();
+                    skipped += 1;
+                    continue;
+                }
+            }
+        } else {
+            let (path, format) = (item.path.clone(), item.format.clone());
+            tokio::task::spawn_blocking(move || {
+                let chapters = load_chapters(&path, &format).map_err(|e| e.to_string())?;
+                Ok(chapters.into_iter().map(|chapter| chapter.paragraphs.join("\n")).collect())
+            })
+            .await
+            .unwrap_or_else(|e| Err(format!("Text extraction panicked: {}", e)))
+        };
+
+        let stored = match extracted {
+            Ok(chapters) => database.index_text(item.book_id, &item.format, stamp, &chapters).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        match stored {
+            Ok(()) => added += 1,
+            Err(error) => failures.push(JobFailure::new(Some(item.book_id), format!("{} ({})", item.title, item.format), error)),
+        }
+    }
+
+    let mut message = format!("📇 Indexed the text of {} books ({} already up to date)", added, current);
+    if dropped > 0 {
+        message.push_str(&format!(", dropped {} gone from the library", dropped));
+    }
+    if skipped > 0 {
+        message.push_str(&format!(" | {} PDFs skipped: they need pdftotext from poppler", skipped));
+    }
+    if let Some(failure) = failures.first() {
+        message.push_str(&format!(" | ❌ {} unreadable ({}: {})", failures.len(), failure.title, failure.error));
+    }
+    let _ = updates.send(JobUpdate::Failed(failures));
+    let message = cancel.finish_message(message, stopped_at, total);
+    let _ = updates.send(JobUpdate::Finished { message });
+}
+
+/// The text of each page of the PDF at `path`, None when `pdftotext` isn't installed
+async fn pdf_pages(program: &str, path: &Path) -> Option<Result<Vec<String>, String>> {
+    let output = match Command::new(program).args(["-q", "-enc", "UTF-8"]).arg(path).arg("-").kill_on_drop(true).output().await {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => return Some(Err(format!("Failed to run {}: {}", program, e))),
+    };
+    if !output.status.success() {
+        return Some(Err(format!("{} exited with {}", program, output.status)));
+    }
+    // Pages end with a form feed
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut pages: Vec<String> = text.split('\u{c}').map(|page| page.split_whitespace().collect::<Vec<_>>().join(" ")).collect();
+    if pages.last().is_some_and(|page| page.is_empty()) {
+        pages.pop();
+    }
+    Some(Ok(pages))
+}
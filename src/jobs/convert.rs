@@ -0,0 +1,264 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use tokio::sync::{mpsc, Semaphore};
+
+use super::{CancelToken, JobFailure, JobUpdate};
+use crate::database::Database;
+
+/// Program used to convert between ebook formats (ships with calibre)
+pub const DEFAULT_CONVERTER: &str = "ebook-convert";
+
+/// A single format conversion for one book
+#[derive(Debug, Clone)]
+pub struct ConversionTask {
+    pub book_id: i32,
+    pub title: String,
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub target_format: String,
+}
+
+/// Progress reported by the queue while it runs
+#[derive(Debug, Clone)]
+pub enum ConversionEvent {
+    Started { index: usize, attempt: u32 },
+    Retrying { index: usize, attempt: u32, error: String },
+    Finished { index: usize, duration: Duration },
+    Failed { index: usize, error: String },
+}
+
+/// Result of running a conversion queue to completion
+#[derive(Debug, Default)]
+pub struct ConversionSummary {
+    pub succeeded: Vec<ConversionTask>,
+    pub failed: Vec<(ConversionTask, String)>,
+    /// Not converted because the queue was cancelled first
+    pub cancelled: Vec<ConversionTask>,
+    pub elapsed: Duration,
+}
+
+impl ConversionSummary {
+    pub fn total(&self) -> usize {
+        self.succeeded.len() + self.failed.len() + self.cancelled.len()
+    }
+}
+
+/// Queue of conversions processed by a bounded number of parallel workers
+pub struct ConversionQueue {
+    tasks: Vec<ConversionTask>,
+    workers: usize,
+    retries: u32,
+    converter: String,
+}
+
+impl ConversionQueue {
+    pub fn new(workers: usize) -> Self {
+        ConversionQueue {
+            tasks: Vec::new(),
+            workers: workers.max(1),
+            retries: 1,
+            converter: DEFAULT_CONVERTER.to_string(),
+        }
+    }
+
+    /// Number of extra attempts made for a failed conversion
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Use a different converter program instead of `ebook-convert`
+    pub fn with_converter(mut self, converter: impl Into<String>) -> Self {
+        self.converter = converter.into();
+        self
+    }
+
+    pub fn push(&mut self, task: ConversionTask) {
+        self.tasks.push(task);
+    }
+
+    pub fn tasks(&self) -> &[ConversionTask] {
+        &self.tasks
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Run every queued conversion, reporting progress through `events`
+    ///
+    /// Once `cancel` fires, running converters are stopped and waiting tasks are not started.
+    pub async fn run(self, events: mpsc::UnboundedSender<ConversionEvent>, cancel: CancelToken) -> ConversionSummary {
+        let started = Instant::now();
+        let permits = Arc::new(Semaphore::new(self.workers));
+        let converter = Arc::new(self.converter);
+        let retries = self.retries;
+
+        // Kept so a task whose worker panicked can still be reported as failed
+        let tasks = self.tasks.clone();
+        let mut handles = Vec::with_capacity(self.tasks.len());
+        for (index, task) in self.tasks.into_iter().enumerate() {
+            let permits = Arc::clone(&permits);
+            let converter = Arc::clone(&converter);
+            let events = events.clone();
+            let cancel = cancel.clone();
+
+            handles.push(tokio::spawn(async move {
+                // The semaphore is never closed, so acquiring can't fail
+                let _permit = permits.acquire_owned().await.ok();
+                let result = convert_with_retries(index, &task, &converter, retries, &events, &cancel).await;
+                (task, result)
+            }));
+        }
+
+        let mut summary = ConversionSummary::default();
+        for (index, handle) in handles.into_iter().enumerate() {
+            match handle.await {
+                Ok((task, Some(Ok(())))) => summary.succeeded.push(task),
+                Ok((task, Some(Err(e)))) => summary.failed.push((task, e)),
+                Ok((task, None)) => summary.cancelled.push(task),
+                Err(e) => {
+                    let error = format!("Conversion worker panicked: {}", e);
+                    let _ = events.send(ConversionEvent::Failed { index, error: error.clone() });
+                    summary.failed.push((tasks[index].clone(), error));
+                }
+            }
+        }
+
+        summary.elapsed = started.elapsed();
+        summary
+    }
+}
+
+/// Run a conversion queue as a background job, registering each new format with the library
+pub async fn convert_books(
+    database: Database,
+    queue: ConversionQueue,
+    tx: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) {
+    let total = queue.len();
+    let titles: Vec<String> = queue.tasks().iter().map(|t| t.title.clone()).collect();
+
+    let (events, mut rx) = mpsc::unbounded_channel();
+    let progress = tx.clone();
+    let forwarder = tokio::spawn(async move {
+        let mut done = 0;
+        while let Some(event) = rx.recv().await {
+            match event {
+                ConversionEvent::Started { index, .. } => {
+                    let current = titles[index].clone();
+                    let _ = progress.send(JobUpdate::Progress { done, total, current });
+                }
+                ConversionEvent::Finished { .. } | ConversionEvent::Failed { .. } => done += 1,
+                ConversionEvent::Retrying { .. } => {}
+            }
+        }
+    });
+
+    let summary = queue.run(events, cancel.clone()).await;
+    let _ = forwarder.await;
+
+    for task in &summary.succeeded {
+        let size = std::fs::metadata(&task.output).map(|m| m.len()).unwrap_or(0);
+        let name = task.output.file_stem().and_then(|n| n.to_str()).unwrap_or_default();
+        if let Err(e) = database.add_format(task.book_id, &task.target_format, name, size).await {
+            let _ = tx.send(JobUpdate::Finished {
+                message: format!("❌ Failed to register {} for {}: {}", task.target_format, task.title, e),
+            });
+            return;
+        }
+    }
+    if !summary.succeeded.is_empty() {
+        let _ = tx.send(JobUpdate::LibraryChanged(summary.succeeded.iter().map(|t| t.book_id).collect()));
+    }
+
+    let failures = summary
+        .failed
+        .iter()
+        .map(|(task, error)| JobFailure::new(Some(task.book_id), task.title.clone(), error.clone()))
+        .collect();
+    let _ = tx.send(JobUpdate::Failed(failures));
+
+    let message = match summary.failed.first() {
+        Some((task, error)) => format!("❌ Failed to convert {}: {}", task.title, error),
+        None => format!("✅ Converted {} books", summary.succeeded.len()),
+    };
+    let message = cancel.finish_message(message, total - summary.cancelled.len(), total);
+    let _ = tx.send(JobUpdate::Finished { message });
+}
+
+/// Convert one task, retrying up to `retries` extra times
+///
+/// Returns `None` if the queue was cancelled before the task could finish.
+async fn convert_with_retries(
+    index: usize,
+    task: &ConversionTask,
+    converter: &str,
+    retries: u32,
+    events: &mpsc::UnboundedSender<ConversionEvent>,
+    cancel: &CancelToken,
+) -> Option<Result<(), String>> {
+    let started = Instant::now();
+    let mut attempt = 1;
+
+    loop {
+        if cancel.is_cancelled() {
+            return None;
+        }
+        let _ = events.send(ConversionEvent::Started { index, attempt });
+
+        let converted = tokio::select! {
+            converted = run_converter(converter, task) => converted,
+            _ = cancel.cancelled() => {
+                // Dropping the converter kills it; don't leave a half-written file behind
+                let _ = std::fs::remove_file(&task.output);
+                return None;
+            }
+        };
+
+        match converted {
+            Ok(()) => {
+                let _ = events.send(ConversionEvent::Finished { index, duration: started.elapsed() });
+                return Some(Ok(()));
+            }
+            Err(error) if attempt <= retries => {
+                let _ = events.send(ConversionEvent::Retrying { index, attempt, error });
+                attempt += 1;
+            }
+            Err(error) => {
+                let _ = events.send(ConversionEvent::Failed { index, error: error.clone() });
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+/// Invoke the converter program for a single task
+async fn run_converter(converter: &str, task: &ConversionTask) -> Result<(), String> {
+    if !task.input.exists() {
+        return Err(format!("Input file not found: {}", task.input.display()));
+    }
+
+    let output = Command::new(converter)
+        .arg(&task.input)
+        .arg(&task.output)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run {}: {}", converter, e))?;
+
+    if output.status.success() && task.output.exists() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last_line = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("");
+        Err(format!("{} exited with {}: {}", converter, output.status, last_line.trim()))
+    }
+}
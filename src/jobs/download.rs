@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+
+use crate::database::{Database, NewBook};
+use crate::error::{Error, Result};
+use crate::jobs::{CancelToken, JobUpdate};
+use crate::opds::{self, OpdsEntry};
+
+/// Download OPDS publications and add them to the library
+pub async fn download_entries(
+    database: Database,
+    library_path: PathBuf,
+    entries: Vec<OpdsEntry>,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) {
+    let total = entries.len();
+    let mut stopped_at = total;
+    let mut added = Vec::new();
+    let mut failed: Vec<(String, String)> = Vec::new();
+
+    for (done, entry) in entries.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let _ = updates.send(JobUpdate::Progress { done, total, current: entry.title.clone() });
+
+        match download_entry(&database, &library_path, &entry).await {
+            Ok(book_id) => added.push(book_id),
+            Err(e) => failed.push((entry.title, e.to_string())),
+        }
+    }
+
+    let mut message = format!("📥 Added {} books from OPDS", added.len());
+    if !added.is_empty() {
+        let _ = updates.send(JobUpdate::LibraryChanged(added));
+    }
+    if let Some((title, reason)) = failed.first() {
+        message.push_str(&format!(" | ❌ {} failed ({}: {})", failed.len(), title, reason));
+    }
+    let message = cancel.finish_message(message, stopped_at, total);
+    let _ = updates.send(JobUpdate::Finished { message });
+}
+
+async fn download_entry(database: &Database, library_path: &std::path::Path, entry: &OpdsEntry) -> Result<i32> {
+    let (link, ext) = entry
+        .best_acquisition()
+        .ok_or_else(|| Error::Other("no supported format offered".to_string()))?;
+
+    let temp_dir = std::env::temp_dir();
+    let stem = format!("tuilibre-opds-{}", uuid::Uuid::new_v4());
+    let file = opds::download(&link.href, &temp_dir, &stem, ext).await?;
+
+    // A missing cover shouldn't stop the book from being added
+    let cover = match &entry.cover {
+        Some(url) => opds::fetch_bytes(url).await.ok(),
+        None => None,
+    };
+
+    let mut identifiers = Vec::new();
+    if let Some(id) = &entry.identifier {
+        if let Some(isbn) = id.strip_prefix("urn:isbn:") {
+            identifiers.push(("isbn".to_string(), isbn.to_string()));
+        }
+    }
+
+    let book = NewBook {
+        title: entry.title.clone(),
+        authors: entry.authors.clone(),
+        tags: entry.categories.clone(),
+        comments: entry.summary.clone(),
+        identifiers,
+    };
+
+    let result = database.add_book(library_path, &book, &file, cover.as_deref()).await;
+    let _ = std::fs::remove_file(&file);
+    result
+}
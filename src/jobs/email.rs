@@ -0,0 +1,151 @@
+use base64::Engine;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+use crate::app::Book;
+use crate::config::{EmailConfig, EmailProfile};
+use crate::database::FormatEntry;
+use crate::jobs::{CancelToken, JobFailure, JobUpdate};
+
+/// One email to send, with a single book attached
+#[derive(Debug, Clone)]
+pub struct EmailItem {
+    pub book_id: i32,
+    pub title: String,
+    pub attachment: std::path::PathBuf,
+}
+
+/// Emails to send plus books without a suitable format
+#[derive(Debug, Default)]
+pub struct EmailPlan {
+    pub items: Vec<EmailItem>,
+    pub skipped: Vec<JobFailure>,
+}
+
+/// Choose the attachment format of each book for the recipient
+pub fn plan_email(profile: &EmailProfile, library_path: &Path, books: &[&Book], formats: &[FormatEntry]) -> EmailPlan {
+    let mut by_book: HashMap<i32, Vec<&FormatEntry>> = HashMap::new();
+    for entry in formats {
+        by_book.entry(entry.book_id).or_default().push(entry);
+    }
+
+    let mut plan = EmailPlan::default();
+    for book in books {
+        let available = by_book.get(&book.id).map(Vec::as_slice).unwrap_or(&[]);
+        match FormatEntry::best_of(&profile.formats, available) {
+            Some(entry) => plan.items.push(EmailItem {
+                book_id: book.id,
+                title: book.title.clone(),
+                attachment: library_path.join(&entry.path).join(entry.filename()),
+            }),
+            None => plan.skipped.push(JobFailure::new(Some(book.id), book.title.clone(), format!("no format wanted by {}", profile.to))),
+        }
+    }
+
+    plan
+}
+
+/// Send one email per book through the configured sendmail-compatible command
+pub async fn send_emails(
+    config: EmailConfig,
+    profile: EmailProfile,
+    plan: EmailPlan,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) {
+    let total = plan.items.len();
+    let mut stopped_at = total;
+    let mut sent = 0;
+    let mut failed = plan.skipped;
+
+    for (done, item) in plan.items.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let _ = updates.send(JobUpdate::Progress { done, total, current: item.title.clone() });
+
+        match send_one(&config, &profile, &item).await {
+            Ok(()) => sent += 1,
+            Err(e) => failed.push(JobFailure::new(Some(item.book_id), item.title, e)),
+        }
+    }
+
+    let mut message = format!("✉️  Emailed {} books to {}", sent, profile.to);
+    if let Some(failure) = failed.first() {
+        message.push_str(&format!(" | ❌ {} failed ({}: {})", failed.len(), failure.title, failure.error));
+    }
+    let message = cancel.finish_message(message, stopped_at, total);
+    let _ = updates.send(JobUpdate::Failed(failed));
+    let _ = updates.send(JobUpdate::Finished { message });
+}
+
+async fn send_one(config: &EmailConfig, profile: &EmailProfile, item: &EmailItem) -> Result<(), String> {
+    let data = tokio::fs::read(&item.attachment)
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", item.attachment.display(), e))?;
+
+    let filename = item.attachment.file_name().and_then(|n| n.to_str()).unwrap_or("book");
+    let subject = profile.subject.clone().unwrap_or_else(|| item.title.clone());
+    let message = build_message(&config.from, &profile.to, &subject, filename, &data);
+
+    let mut parts = config.command.split_whitespace();
+    let program = parts.next().ok_or("Email command is empty")?;
+
+    let mut child = Command::new(program)
+        .args(parts)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(message.as_bytes()).await.map_err(|e| e.to_string())?;
+    }
+
+    let output = child.wait_with_output().await.map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+/// Build a multipart MIME message with a single attachment
+fn build_message(from: &str, to: &str, subject: &str, filename: &str, data: &[u8]) -> String {
+    let boundary = format!("tuilibre-{:x}", chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default());
+    let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+    let encoded_subject = base64::engine::general_purpose::STANDARD.encode(subject);
+    let encoded_filename = utf8_percent_encode(filename, NON_ALPHANUMERIC);
+
+    let mut message = String::with_capacity(encoded.len() + 1024);
+    if !from.is_empty() {
+        message.push_str(&format!("From: {}\r\n", from));
+    }
+    message.push_str(&format!("To: {}\r\n", to));
+    message.push_str(&format!("Subject: =?UTF-8?B?{}?=\r\n", encoded_subject));
+    message.push_str("MIME-Version: 1.0\r\n");
+    message.push_str(&format!("Content-Type: multipart/mixed; boundary=\"{}\"\r\n\r\n", boundary));
+
+    message.push_str(&format!("--{}\r\n", boundary));
+    message.push_str("Content-Type: text/plain; charset=utf-8\r\n\r\n");
+    message.push_str("Sent from tuilibre\r\n");
+
+    message.push_str(&format!("--{}\r\n", boundary));
+    message.push_str("Content-Type: application/octet-stream\r\n");
+    message.push_str(&format!("Content-Disposition: attachment; filename*=UTF-8''{}\r\n", encoded_filename));
+    message.push_str("Content-Transfer-Encoding: base64\r\n\r\n");
+    for line in encoded.as_bytes().chunks(76) {
+        message.push_str(std::str::from_utf8(line).unwrap_or_default());
+        message.push_str("\r\n");
+    }
+    message.push_str(&format!("--{}--\r\n", boundary));
+
+    message
+}
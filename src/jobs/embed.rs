@@ -0,0 +1,213 @@
+//! Writing the library's metadata into the books' own files, so copies sent to a device or
+//! exported carry the title, authors, series and cover the library has
+//!
+//! EPUBs are rewritten directly; other formats go through calibre's `ebook-meta` when it is
+//! installed. Each file's new size is recorded, since a rewritten file no longer matches the
+//! size calibre had for it.
+
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+use crate::app::Book;
+use crate::database::Database;
+use crate::epub::{self, FileMetadata};
+use crate::jobs::{CancelToken, JobFailure, JobUpdate};
+
+/// Formats whose metadata tuilibre writes itself
+const EPUB_FORMATS: [&str; 2] = ["EPUB", "KEPUB"];
+
+/// Formats `ebook-meta` can write metadata into
+const EBOOK_META_FORMATS: [&str; 12] = ["AZW3", "AZW", "MOBI", "PRC", "PDF", "DOCX", "ODT", "FB2", "RTF", "PDB", "TXTZ", "HTMLZ"];
+
+/// One file to write metadata into
+#[derive(Debug, Clone)]
+pub struct EmbedItem {
+    pub book_id: i32,
+    pub title: String,
+    pub format: String,
+    pub path: PathBuf,
+    pub metadata: FileMetadata,
+    /// The book's cover.jpg, when it has one
+    pub cover: Option<PathBuf>,
+}
+
+/// Every file of `books` that metadata can be written into
+pub fn plan_embed(library_path: &Path, books: &[&Book]) -> Vec<EmbedItem> {
+    books
+        .iter()
+        .flat_map(|book| {
+            let metadata = FileMetadata {
+                title: book.title.clone(),
+                authors: book.authors.clone(),
+                series: book.series.clone().map(|series| (series, book.series_index)),
+            };
+            let cover = book.has_cover.then(|| library_path.join(&book.path).join("cover.jpg"));
+            book.formats
+                .iter()
+                .filter(|format| is_embeddable(&format.format))
+                .map(move |format| EmbedItem {
+                    book_id: book.id,
+                    title: book.title.clone(),
+                    format: format.format.clone(),
+                    path: book.format_path(library_path, format),
+                    metadata: metadata.clone(),
+                    cover: cover.clone(),
+                })
+        })
+        .collect()
+}
+
+/// Write each item's metadata into its file, one at a time, with `ebook_meta` for formats other
+/// than EPUB; when that is empty or not installed those files are skipped
+pub async fn embed_metadata(
+    database: Database,
+    items: Vec<EmbedItem>,
+    mut ebook_meta: String,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) {
+    let total = items.len();
+    let mut stopped_at = total;
+    let mut updated = Vec::new();
+    let mut skipped = 0;
+    let mut failures = Vec::new();
+
+    for (done, item) in items.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let _ = updates.send(JobUpdate::Progress { done, total, current: format!("{} ({})", item.title, item.format) });
+
+        let written = if is_epub(&item.format) {
+            let (path, metadata, cover) = (item.path.clone(), item.metadata.clone(), item.cover.clone());
+            tokio::task::spawn_blocking(move || {
+                let cover = cover.and_then(|cover| std::fs::read(cover).ok());
+                epub::write_metadata(&path, &metadata, cover.as_deref()).map_err(|e| e.to_string())
+            })
+            .await
+            .unwrap_or_else(|e| Err(format!("Metadata writer panicked: {}", e)))
+        } else if ebook_meta.is_empty() {
+            skipped += 1;
+            continue;
+        } else {
+            match run_ebook_meta(&ebook_meta, &item).await {
+                Some(result) => result,
+                None => {
+                    ebook_meta.clear();
+                    skipped += 1;
+                    continue;
+                }
+            }
+        };
+
+        let recorded = match written {
+            Ok(()) => match std::fs::metadata(&item.path) {
+                Ok(meta) => database.set_format_size(item.book_id, &item.format, meta.len()).await.map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            },
+            Err(e) => Err(e),
+        };
+        match recorded {
+            Ok(()) => updated.push(item.book_id),
+            Err(error) => failures.push(JobFailure::new(Some(item.book_id), format!("{} ({})", item.title, item.format), error)),
+        }
+    }
+
+    if !updated.is_empty() {
+        let mut ids = updated.clone();
+        ids.dedup();
+        let _ = updates.send(JobUpdate::LibraryChanged(ids));
+    }
+    let message = match failures.first() {
+        Some(failure) => format!("❌ Failed to update {}: {}", failure.title, failure.error),
+        None if skipped > 0 => format!("✅ Updated metadata in {} files ({} skipped: other formats need ebook-meta from calibre)", updated.len(), skipped),
+        None => format!("✅ Updated metadata in {} files", updated.len()),
+    };
+    let _ = updates.send(JobUpdate::Failed(failures));
+    let message = cancel.finish_message(message, stopped_at, total);
+    let _ = updates.send(JobUpdate::Finished { message });
+}
+
+/// Whether metadata can be written into files of `format`, e.g. "EPUB"
+fn is_embeddable(format: &str) -> bool {
+    is_epub(format) || EBOOK_META_FORMATS.iter().any(|f| f.eq_ignore_ascii_case(format))
+}
+
+pub fn is_epub(format: &str) -> bool {
+    EPUB_FORMATS.iter().any(|f| f.eq_ignore_ascii_case(format))
+}
+
+/// Set the item's title, authors, series and cover with `ebook-meta`, None when it isn't installed
+async fn run_ebook_meta(program: &str, item: &EmbedItem) -> Option<Result<(), String>> {
+    let (series, index) = match &item.metadata.series {
+        Some((series, index)) => (series.clone(), index.to_string()),
+        None => (String::new(), "1".to_string()),
+    };
+    let mut command = Command::new(program);
+    command
+        .arg(&item.path)
+        .args(["--title", &item.metadata.title])
+        // calibre separates authors with " & " since names can hold commas
+        .args(["--authors", &item.metadata.authors.join(" & ")])
+        .args(["--series", &series, "--index", &index]);
+    if let Some(cover) = &item.cover {
+        command.arg("--cover").arg(cover);
+    }
+    Some(run(program, command).await?.map(|_| ()))
+}
+
+/// The title, authors and series `ebook-meta` reads from the file at `path`, None when it isn't
+/// installed
+pub async fn read_with_ebook_meta(program: &str, path: &Path) -> Option<Result<FileMetadata, String>> {
+    let mut command = Command::new(program);
+    command.arg(path);
+    Some(run(program, command).await?.map(|stdout| parse_ebook_meta(&stdout)))
+}
+
+/// Read `ebook-meta`'s listing, lines like "Title               : Mort"
+fn parse_ebook_meta(output: &str) -> FileMetadata {
+    let mut metadata = FileMetadata::default();
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "Title" => metadata.title = value.to_string(),
+            // "Terry Pratchett [Pratchett, Terry] & Neil Gaiman [Gaiman, Neil]"
+            "Author(s)" => {
+                metadata.authors = value
+                    .split(" & ")
+                    .map(|author| author.split_once(" [").map_or(author, |(name, _)| name).trim().to_string())
+                    .filter(|author| !author.is_empty())
+                    .collect();
+            }
+            // "Discworld #4"
+            "Series" => {
+                metadata.series = match value.rsplit_once(" #") {
+                    Some((series, index)) => Some((series.trim().to_string(), index.trim().parse().unwrap_or(1.0))),
+                    None => (!value.is_empty()).then(|| (value.to_string(), 1.0)),
+                };
+            }
+            _ => {}
+        }
+    }
+    metadata
+}
+
+/// Run an `ebook-meta` command, returning its output; None when the program isn't installed
+async fn run(program: &str, mut command: Command) -> Option<Result<String, String>> {
+    let output = match command.kill_on_drop(true).output().await {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => return Some(Err(format!("Failed to run {}: {}", program, e))),
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let last_line = stderr.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("");
+        return Some(Err(format!("{} exited with {}: {}", program, output.status, last_line.trim())));
+    }
+    Some(Ok(String::from_utf8_lossy(&output.stdout).into_owned()))
+}
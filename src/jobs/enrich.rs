@@ -0,0 +1,134 @@
+use tokio::sync::mpsc;
+
+use crate::database::{Database, IncompleteBook};
+use crate::error::Result;
+use crate::jobs::{CancelToken, JobUpdate};
+use crate::metadata::{FieldChange, MetadataCandidate, MetadataField, MetadataProposal, MetadataSource};
+
+/// Look up missing ISBNs, descriptions and covers for every book that lacks them
+///
+/// Nothing is written here: each book with something to fill in is sent back
+/// as a `JobUpdate::Proposal` so the user can review it first.
+pub async fn enrich_library(
+    database: Database,
+    sources: Vec<MetadataSource>,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) {
+    let books = match database.find_incomplete_books().await {
+        Ok(books) => books,
+        Err(e) => {
+            let message = format!("❌ Failed to scan library: {}", e);
+            let _ = updates.send(JobUpdate::Finished { message });
+            return;
+        }
+    };
+
+    let total = books.len();
+    let mut stopped_at = total;
+    let mut proposed = 0;
+    let mut failed: Vec<(String, String)> = Vec::new();
+
+    for (done, book) in books.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let _ = updates.send(JobUpdate::Progress { done, total, current: book.title.clone() });
+
+        // Lookups only read, so one still waiting on the network can be dropped
+        let found = tokio::select! {
+            found = propose(&book, &sources) => found,
+            _ = cancel.cancelled() => {
+                stopped_at = done;
+                break;
+            }
+        };
+
+        match found {
+            Ok(Some(proposal)) => {
+                proposed += 1;
+                if updates.send(JobUpdate::Proposal(proposal)).is_err() {
+                    // UI is gone, stop querying
+                    return;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => failed.push((book.title, e.to_string())),
+        }
+    }
+
+    let mut message = format!("🔎 Checked {} books, {} proposals to review (R)", total, proposed);
+    if let Some((title, reason)) = failed.first() {
+        message.push_str(&format!(" | ❌ {} failed ({}: {})", failed.len(), title, reason));
+    }
+    let message = cancel.finish_message(message, stopped_at, total);
+    let _ = updates.send(JobUpdate::Finished { message });
+}
+
+/// Query sources in order until one fills at least one missing field
+async fn propose(book: &IncompleteBook, sources: &[MetadataSource]) -> Result<Option<MetadataProposal>> {
+    let author = book.authors.first().map(String::as_str).unwrap_or_default();
+    let mut last_error = None;
+    let mut reached_any = false;
+
+    for source in sources {
+        let candidates = match source.search(&book.title, author).await {
+            Ok(candidates) => {
+                reached_any = true;
+                candidates
+            }
+            Err(e) => {
+                last_error = Some(e);
+                continue;
+            }
+        };
+
+        for candidate in candidates.into_iter().filter(|c| c.matches_title(&book.title)) {
+            let changes = diff_candidate(book, candidate);
+            // Only worth reviewing if it fills something in
+            if changes.iter().any(|c| c.accepted) {
+                return Ok(Some(MetadataProposal {
+                    book_id: book.id,
+                    book_title: book.title.clone(),
+                    origin: source.name().to_string(),
+                    changes,
+                }));
+            }
+        }
+    }
+
+    // Only report an error when no source could be reached at all
+    match last_error {
+        Some(e) if !reached_any => Err(e),
+        _ => Ok(None),
+    }
+}
+
+/// Fill missing fields from the candidate and offer differing title/authors as opt-in replacements
+fn diff_candidate(book: &IncompleteBook, candidate: MetadataCandidate) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+
+    if !candidate.title.is_empty() && candidate.title != book.title {
+        changes.push(FieldChange::replace(MetadataField::Title, book.title.clone(), candidate.title));
+    }
+    if !candidate.authors.is_empty() && candidate.authors != book.authors {
+        changes.push(FieldChange::replace(
+            MetadataField::Authors,
+            // calibre's separator, since names can hold commas ("King, Jr.")
+            book.authors.join(" & "),
+            candidate.authors.join(" & "),
+        ));
+    }
+    if let Some(isbn) = candidate.isbn.filter(|_| book.missing_isbn) {
+        changes.push(FieldChange::fill(MetadataField::Isbn, isbn));
+    }
+    if let Some(description) = candidate.description.filter(|_| book.missing_description) {
+        changes.push(FieldChange::fill(MetadataField::Description, description));
+    }
+    if let Some(url) = candidate.cover_url.filter(|_| book.missing_cover) {
+        changes.push(FieldChange::fill(MetadataField::Cover, url));
+    }
+
+    changes
+}
@@ -0,0 +1,186 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+use crate::app::Book;
+use crate::database::write::safe_component;
+use crate::database::FormatEntry;
+use crate::jobs::{CancelToken, JobFailure, JobUpdate};
+
+/// One book file to copy out of the library
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportItem {
+    pub book_id: i32,
+    pub title: String,
+    pub source: PathBuf,
+    /// Path under the export folder, as shown in the preview
+    pub name: PathBuf,
+    /// The template's name was taken, so a number was added to it
+    pub renamed: bool,
+}
+
+/// Files to export plus books that were skipped while planning
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportPlan {
+    pub directory: PathBuf,
+    pub items: Vec<ExportItem>,
+    pub skipped: Vec<JobFailure>,
+}
+
+/// Result of an export
+#[derive(Debug, Default)]
+pub struct ExportSummary {
+    pub exported: usize,
+    pub failed: Vec<JobFailure>,
+}
+
+/// Name every format of each book with the template, numbering names that collide
+///
+/// Names collide with each other or with files already in `directory`; nothing is overwritten.
+pub fn plan_export(
+    template: &str,
+    directory: &Path,
+    library_path: &Path,
+    books: &[&Book],
+    formats: &[FormatEntry],
+    series: &HashMap<i32, (String, f64)>,
+) -> ExportPlan {
+    let mut by_book: HashMap<i32, Vec<&FormatEntry>> = HashMap::new();
+    for entry in formats {
+        by_book.entry(entry.book_id).or_default().push(entry);
+    }
+
+    let mut plan = ExportPlan { directory: directory.to_path_buf(), ..Default::default() };
+    let mut taken = HashSet::new();
+    for book in books {
+        let Some(entries) = by_book.get(&book.id) else {
+            plan.skipped.push(JobFailure::new(Some(book.id), book.title.clone(), "no files"));
+            continue;
+        };
+
+        for entry in entries {
+            let wanted = render_name(template, book, &entry.format, series.get(&book.id));
+            let name = unique_name(&wanted, |name| !taken.contains(name) && !directory.join(name).exists());
+            taken.insert(name.clone());
+            plan.items.push(ExportItem {
+                book_id: book.id,
+                title: book.title.clone(),
+                source: library_path.join(&entry.path).join(entry.filename()),
+                renamed: name != wanted,
+                name,
+            });
+        }
+    }
+
+    plan
+}
+
+/// Fill in the template for one file of a book, making each folder and file name safe
+pub fn render_name(template: &str, book: &Book, format: &str, series: Option<&(String, f64)>) -> PathBuf {
+    let (series_name, series_index) = match series {
+        Some((name, index)) => (name.clone(), format!(" #{}", index)),
+        None => (String::new(), String::new()),
+    };
+    let fields = [
+        ("{title}", book.title.clone()),
+        ("{authors}", book.authors.join(" & ")),
+        ("{author}", book.authors.first().cloned().unwrap_or_default()),
+        ("{series}", series_name),
+        ("{series_index}", series_index),
+        ("{ext}", format.to_lowercase()),
+        ("{id}", book.id.to_string()),
+    ];
+
+    // Split first so a '/' in a title can't make a folder
+    let parts: Vec<&str> = template.split('/').collect();
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let filled = fields.iter().fold(part.to_string(), |text, (field, value)| {
+                text.replace(field, &value.replace(['/', '\\'], "_"))
+            });
+            let (stem, ext) = match filled.rsplit_once('.') {
+                Some((stem, ext)) if i == parts.len() - 1 => (stem.to_string(), Some(ext.to_string())),
+                _ => (filled, None),
+            };
+            let stem = safe_component(&drop_empty_parts(&stem));
+            match ext {
+                Some(ext) => format!("{}.{}", stem, ext),
+                None => stem,
+            }
+        })
+        .collect()
+}
+
+/// Drop the " - " pieces a missing field left empty, e.g. "Herbert -  - Dune"
+fn drop_empty_parts(name: &str) -> String {
+    name.split(" - ")
+        .map(|part| part.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" - ")
+}
+
+/// `wanted`, or "name (2).ext", "name (3).ext"... if it's not `free`
+fn unique_name(wanted: &Path, free: impl Fn(&Path) -> bool) -> PathBuf {
+    if free(wanted) {
+        return wanted.to_path_buf();
+    }
+    let stem = wanted.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let ext = wanted.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (2..)
+        .map(|n| wanted.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|candidate| free(candidate))
+        .expect("some numbered name is free")
+}
+
+/// Copy planned files into the export folder
+pub async fn export_books(
+    plan: ExportPlan,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) -> ExportSummary {
+    let mut summary = ExportSummary { failed: plan.skipped, ..Default::default() };
+    let total = plan.items.len();
+    let mut stopped_at = total;
+
+    for (done, item) in plan.items.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let _ = updates.send(JobUpdate::Progress { done, total, current: item.title.clone() });
+
+        let destination = plan.directory.join(&item.name);
+        if let Some(folder) = destination.parent() {
+            if let Err(e) = tokio::fs::create_dir_all(folder).await {
+                summary.failed.push(JobFailure::new(Some(item.book_id), item.title, e.to_string()));
+                continue;
+            }
+        }
+        // Something may have appeared there since the preview
+        if destination.exists() {
+            summary.failed.push(JobFailure::new(Some(item.book_id), item.title, format!("{} already exists", item.name.display())));
+            continue;
+        }
+
+        match tokio::fs::copy(&item.source, &destination).await {
+            Ok(_) => summary.exported += 1,
+            Err(e) => summary.failed.push(JobFailure::new(Some(item.book_id), item.title, e.to_string())),
+        }
+    }
+
+    let message = cancel.finish_message(export_message(&plan.directory, &summary), stopped_at, total);
+    let _ = updates.send(JobUpdate::Failed(summary.failed.clone()));
+    let _ = updates.send(JobUpdate::Finished { message });
+    summary
+}
+
+fn export_message(directory: &Path, summary: &ExportSummary) -> String {
+    let mut message = format!("📦 Exported {} files to {}", summary.exported, directory.display());
+    if let Some(failure) = summary.failed.first() {
+        message.push_str(&format!(" | ❌ {} failed ({}: {})", summary.failed.len(), failure.title, failure.error));
+    }
+    message
+}
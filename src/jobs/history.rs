@@ -0,0 +1,210 @@
+//! Background jobs run before, with what they were given and how they went
+//!
+//! Kept in `~/.config/tuilibre/jobs.json` so a failed batch can be looked into and its
+//! failed books run again after tuilibre was restarted.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Context, Error, Result};
+
+/// Jobs kept in the history file, oldest dropped first
+const MAX_RECORDS: usize = 200;
+
+/// Which job ran, with the parameters it was started with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobKind {
+    Convert { format: String },
+    Send { device: String },
+    Email { profile: String },
+    Export { directory: PathBuf },
+    Archive { destination: PathBuf },
+    Split { target: PathBuf },
+    Merge { source: PathBuf },
+    Verify,
+    Download,
+    LookUpMetadata,
+    DetectLanguages,
+    SuggestTags,
+    EmbedMetadata,
+    AuditMetadata,
+    IndexContent,
+}
+
+impl JobKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobKind::Convert { .. } => "Convert",
+            JobKind::Send { .. } => "Send to device",
+            JobKind::Email { .. } => "Email",
+            JobKind::Export { .. } => "Export",
+            JobKind::Archive { .. } => "Archive",
+            JobKind::Split { .. } => "Split library",
+            JobKind::Merge { .. } => "Merge library",
+            JobKind::Verify => "Verify files",
+            JobKind::Download => "OPDS download",
+            JobKind::LookUpMetadata => "Look up metadata",
+            JobKind::DetectLanguages => "Detect languages",
+            JobKind::SuggestTags => "Suggest tags",
+            JobKind::EmbedMetadata => "Update file metadata",
+            JobKind::AuditMetadata => "Audit file metadata",
+            JobKind::IndexContent => "Index book contents",
+        }
+    }
+
+    /// The parameters in a few words, empty for jobs that take none
+    pub fn parameters(&self) -> String {
+        match self {
+            JobKind::Convert { format } => format!("to {}", format),
+            JobKind::Send { device } => device.clone(),
+            JobKind::Email { profile } => profile.clone(),
+            JobKind::Export { directory } => directory.display().to_string(),
+            JobKind::Archive { destination } => destination.display().to_string(),
+            JobKind::Split { target } => target.display().to_string(),
+            JobKind::Merge { source } => source.display().to_string(),
+            JobKind::Verify
+            | JobKind::Download
+            | JobKind::LookUpMetadata
+            | JobKind::DetectLanguages
+            | JobKind::SuggestTags
+            | JobKind::EmbedMetadata
+            | JobKind::AuditMetadata
+            | JobKind::IndexContent => String::new(),
+        }
+    }
+
+    /// Whether the job works book by book, so its failed books can be run again on their own
+    pub fn retries_books(&self) -> bool {
+        matches!(
+            self,
+            JobKind::Convert { .. } | JobKind::Send { .. } | JobKind::Email { .. } | JobKind::Export { .. } | JobKind::Verify | JobKind::EmbedMetadata
+        )
+    }
+}
+
+/// One item a job couldn't do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobFailure {
+    /// The book it was for, None when it failed before getting to a book, e.g. while planning
+    pub book_id: Option<i32>,
+    pub title: String,
+    pub error: String,
+}
+
+impl JobFailure {
+    pub fn new(book_id: Option<i32>, title: impl Into<String>, error: impl Into<String>) -> Self {
+        JobFailure { book_id, title: title.into(), error: error.into() }
+    }
+}
+
+/// A finished job
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JobRecord {
+    /// When the tuilibre session that ran it started
+    pub session: DateTime<Utc>,
+    pub library: PathBuf,
+    pub kind: JobKind,
+    /// As shown in the status bar while it ran, e.g. "Sending to Kobo"
+    pub label: String,
+    pub started: DateTime<Utc>,
+    pub seconds: f64,
+    /// The closing message
+    pub result: String,
+    pub cancelled: bool,
+    pub failures: Vec<JobFailure>,
+}
+
+impl JobRecord {
+    /// The books that failed, once each, in the order they failed
+    pub fn failed_book_ids(&self) -> Vec<i32> {
+        let mut ids = Vec::new();
+        for id in self.failures.iter().filter_map(|f| f.book_id) {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        ids
+    }
+
+    /// Everything known about the job as plain text, for pasting into a bug report
+    pub fn details(&self) -> String {
+        let mut text = format!("{}: {}\n", self.kind.name(), self.label);
+        let parameters = self.kind.parameters();
+        if !parameters.is_empty() {
+            text.push_str(&format!("Parameters: {}\n", parameters));
+        }
+        text.push_str(&format!("Started: {}\n", self.started.to_rfc3339()));
+        text.push_str(&format!("Duration: {:.1}s\n", self.seconds));
+        text.push_str(&format!("Result: {}\n", self.result));
+        for failure in &self.failures {
+            match failure.book_id {
+                Some(id) => text.push_str(&format!("Failed: {} (book {}): {}\n", failure.title, id, failure.error)),
+                None => text.push_str(&format!("Failed: {}: {}\n", failure.title, failure.error)),
+            }
+        }
+        text
+    }
+}
+
+/// Every job recorded, oldest first
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobHistory {
+    records: Vec<JobRecord>,
+}
+
+impl JobHistory {
+    /// Get the job history file path in user's home directory
+    pub fn get_history_file_path() -> Result<PathBuf> {
+        let home_dir = dirs::home_dir()
+            .ok_or_else(|| Error::Other("Could not find user home directory".to_string()))?;
+
+        let config_dir = home_dir.join(".config").join("tuilibre");
+        fs::create_dir_all(&config_dir)
+            .with_context(|| format!("Failed to create config directory: {}", config_dir.display()))?;
+
+        Ok(config_dir.join("jobs.json"))
+    }
+
+    /// Load the history, empty if no job was recorded yet
+    pub fn load() -> Result<Self> {
+        let path = Self::get_history_file_path()?;
+
+        if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read job history: {}", path.display()))?;
+
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse job history: {}", path.display()))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::get_history_file_path()?;
+
+        let content = serde_json::to_string_pretty(self)
+            .with_context(|| "Failed to serialize job history")?;
+
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write job history: {}", path.display()))?;
+
+        Ok(())
+    }
+
+    pub fn push(&mut self, record: JobRecord) {
+        self.records.push(record);
+        if self.records.len() > MAX_RECORDS {
+            self.records.drain(..self.records.len() - MAX_RECORDS);
+        }
+    }
+
+    /// The jobs run on a library, newest first
+    pub fn for_library(&self, library_path: &Path) -> Vec<JobRecord> {
+        let library = library_path.canonicalize().unwrap_or_else(|_| library_path.to_path_buf());
+        self.records.iter().rev().filter(|r| r.library == library).cloned().collect()
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+use crate::database::{Database, FormatEntry};
+use crate::error::{Error, Result};
+use crate::jobs::{CancelToken, JobUpdate};
+use crate::metadata::{FieldChange, MetadataField, MetadataProposal};
+use crate::utils::text::strip_tags;
+
+/// Formats text can be sampled from, best first
+const SAMPLE_FORMATS: [&str; 2] = ["EPUB", "TXT"];
+
+/// Characters of text fed to the detector per book
+const SAMPLE_CHARS: usize = 20_000;
+
+/// Detect the language of books that have none set, proposing it for review
+pub async fn detect_languages(
+    database: Database,
+    library_path: PathBuf,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) {
+    let scanned = async {
+        let books = database.find_books_without_language().await?;
+        let ids: Vec<i32> = books.iter().map(|(id, _)| *id).collect();
+        let formats = database.load_formats(&ids).await?;
+        Ok::<_, Error>((books, formats))
+    };
+    let (books, formats) = match scanned.await {
+        Ok(found) => found,
+        Err(e) => {
+            let message = format!("❌ Failed to scan library: {}", e);
+            let _ = updates.send(JobUpdate::Finished { message });
+            return;
+        }
+    };
+
+    let preferred: Vec<String> = SAMPLE_FORMATS.iter().map(|f| f.to_string()).collect();
+    let total = books.len();
+    let mut stopped_at = total;
+    let mut proposed = 0;
+    let mut skipped = 0;
+
+    for (done, (book_id, title)) in books.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let _ = updates.send(JobUpdate::Progress { done, total, current: title.clone() });
+
+        let available: Vec<&FormatEntry> = formats.iter().filter(|f| f.book_id == book_id).collect();
+        let Some(entry) = FormatEntry::best_of(&preferred, &available) else {
+            skipped += 1;
+            continue;
+        };
+
+        let path = library_path.join(&entry.path).join(entry.filename());
+        let format = entry.format.to_uppercase();
+        let detected = tokio::task::spawn_blocking(move || detect_file(&path, &format)).await;
+
+        match detected {
+            Ok(Some(code)) => {
+                proposed += 1;
+                let proposal = MetadataProposal {
+                    book_id,
+                    book_title: title,
+                    origin: "Language detection".to_string(),
+                    changes: vec![FieldChange::fill(MetadataField::Languages, code)],
+                };
+                if updates.send(JobUpdate::Proposal(proposal)).is_err() {
+                    return;
+                }
+            }
+            _ => skipped += 1,
+        }
+    }
+
+    let message = format!(
+        "🌐 Detected languages for {} of {} books, review with R ({} skipped)",
+        proposed, total, skipped
+    );
+    let message = cancel.finish_message(message, stopped_at, total);
+    let _ = updates.send(JobUpdate::Finished { message });
+}
+
+/// Detect a file's language, returning its ISO 639-3 code if the guess is reliable
+fn detect_file(path: &Path, format: &str) -> Option<String> {
+    let text = match format {
+        "EPUB" => sample_epub(path).ok()?,
+        _ => sample_txt(path).ok()?,
+    };
+
+    let info = whatlang::detect(&text)?;
+    info.is_reliable().then(|| info.lang().code().to_string())
+}
+
+fn sample_txt(path: &Path) -> Result<String> {
+    let mut bytes = Vec::new();
+    std::fs::File::open(path)?
+        .take(SAMPLE_CHARS as u64 * 4)
+        .read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).chars().take(SAMPLE_CHARS).collect())
+}
+
+/// Collect text from the EPUB's HTML documents until there is enough to detect from
+fn sample_epub(path: &Path) -> Result<String> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let mut names: Vec<String> = archive
+        .file_names()
+        .filter(|name| {
+            let name = name.to_lowercase();
+            name.ends_with(".xhtml") || name.ends_with(".html") || name.ends_with(".htm")
+        })
+        .map(|name| name.to_string())
+        .collect();
+    names.sort();
+
+    let mut text = String::new();
+    for name in names {
+        let mut html = String::new();
+        archive.by_name(&name)?.read_to_string(&mut html)?;
+        text.push_str(&strip_tags(&html));
+        text.push(' ');
+        if text.chars().count() >= SAMPLE_CHARS {
+            break;
+        }
+    }
+    Ok(text)
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+use crate::app::Book;
+use crate::config::DatabaseConfig;
+use crate::database::{Database, FormatEntry};
+use crate::error::{Error, Result};
+use crate::jobs::{CancelToken, JobFailure, JobUpdate};
+
+/// What merging does with one book of the other library
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeAction {
+    /// Not in this library yet
+    Add,
+    /// Already here as `into`, which lacks these formats
+    AddFormats { into: i32, formats: Vec<String> },
+    /// Already here as `duplicate_of`, with every format
+    Skip { duplicate_of: i32 },
+}
+
+/// Every book of the other library and what to do with it
+#[derive(Debug, Clone)]
+pub struct MergePlan {
+    pub source: PathBuf,
+    pub books: Vec<(Book, MergeAction)>,
+}
+
+impl MergePlan {
+    /// Counts of books to add, to add formats to, and to skip
+    pub fn counts(&self) -> (usize, usize, usize) {
+        self.books.iter().fold((0, 0, 0), |(add, merge, skip), (_, action)| match action {
+            MergeAction::Add => (add + 1, merge, skip),
+            MergeAction::AddFormats { .. } => (add, merge + 1, skip),
+            MergeAction::Skip { .. } => (add, merge, skip + 1),
+        })
+    }
+}
+
+/// Result of a merge
+#[derive(Debug, Default)]
+pub struct MergeSummary {
+    pub added: usize,
+    pub merged: usize,
+    pub skipped: usize,
+    pub failed: Vec<JobFailure>,
+}
+
+/// The other library's books matched against this one's
+///
+/// A book is a duplicate if it has the same ISBN, or failing that the same title and first author
+/// ignoring case.
+pub async fn plan_merge(source: &Database, source_path: PathBuf, target: &Database) -> Result<MergePlan> {
+    let incoming = source.load_books().await?;
+    let incoming_isbns = source.load_isbns().await?;
+    let incoming_formats = source.load_all_formats().await?;
+    let existing = target.load_books().await?;
+    let existing_isbns = target.load_isbns().await?;
+    let existing_formats = target.load_all_formats().await?;
+
+    let by_isbn: HashMap<&str, i32> = existing_isbns.iter().map(|(id, isbn)| (isbn.as_str(), *id)).collect();
+    let by_title: HashMap<(String, String), i32> = existing.iter().map(|b| (match_key(b), b.id)).collect();
+    let formats_of = |formats: &[FormatEntry], id: i32| -> Vec<String> {
+        formats.iter().filter(|f| f.book_id == id).map(|f| f.format.to_uppercase()).collect()
+    };
+
+    let books = incoming
+        .into_iter()
+        .map(|book| {
+            let duplicate = incoming_isbns
+                .get(&book.id)
+                .and_then(|isbn| by_isbn.get(isbn.as_str()))
+                .or_else(|| by_title.get(&match_key(&book)))
+                .copied();
+            let action = match duplicate {
+                None => MergeAction::Add,
+                Some(into) => {
+                    let have = formats_of(&existing_formats, into);
+                    let formats: Vec<String> = formats_of(&incoming_formats, book.id)
+                        .into_iter()
+                        .filter(|f| !have.contains(f))
+                        .collect();
+                    if formats.is_empty() {
+                        MergeAction::Skip { duplicate_of: into }
+                    } else {
+                        MergeAction::AddFormats { into, formats }
+                    }
+                }
+            };
+            (book, action)
+        })
+        .collect();
+
+    Ok(MergePlan { source: source_path, books })
+}
+
+/// Lowercased title and first author
+fn match_key(book: &Book) -> (String, String) {
+    let author = book.authors.first().map(|a| a.to_lowercase()).unwrap_or_default();
+    (book.title.trim().to_lowercase(), author)
+}
+
+/// Copy the other library's books in, with their metadata, cover and every format
+///
+/// Duplicates are matched again rather than taken from the preview, in case either library changed.
+pub async fn merge_library(
+    database: Database,
+    library_path: PathBuf,
+    source_path: PathBuf,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) -> MergeSummary {
+    let mut summary = MergeSummary::default();
+    let scanned = async {
+        let source = Database::open_read_only(&source_path, &DatabaseConfig::default()).await?;
+        let plan = plan_merge(&source, source_path.clone(), &database).await?;
+        let formats = source.load_all_formats().await?;
+        Ok::<_, Error>((source, plan, formats))
+    };
+    let (source, plan, formats) = match scanned.await {
+        Ok(scanned) => scanned,
+        Err(e) => {
+            let message = format!("❌ Failed to read {}: {}", source_path.display(), e);
+            let _ = updates.send(JobUpdate::Finished { message });
+            return summary;
+        }
+    };
+
+    let total = plan.books.len();
+    let mut stopped_at = total;
+    let mut changed = Vec::new();
+    for (done, (book, action)) in plan.books.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let _ = updates.send(JobUpdate::Progress { done, total, current: book.title.clone() });
+
+        let files: Vec<PathBuf> = formats
+            .iter()
+            .filter(|f| f.book_id == book.id)
+            .map(|f| plan.source.join(&f.path).join(f.filename()))
+            .collect();
+
+        let result = match &action {
+            MergeAction::Skip { .. } => {
+                summary.skipped += 1;
+                continue;
+            }
+            MergeAction::AddFormats { into, formats } => {
+                let mut result = Ok(*into);
+                for file in files.iter().filter(|file| has_format(file, formats)) {
+                    result = result.and(database.add_format_file(&library_path, *into, file).await.map(|()| *into));
+                }
+                result
+            }
+            MergeAction::Add => add_copy(&database, &source, &library_path, &plan.source, &book, &files).await,
+        };
+        match result {
+            Ok(id) => {
+                if action == MergeAction::Add {
+                    summary.added += 1;
+                } else {
+                    summary.merged += 1;
+                }
+                changed.push(id);
+            }
+            Err(e) => summary.failed.push(JobFailure::new(None, book.title, e.to_string())),
+        }
+    }
+
+    source.close().await;
+    let _ = updates.send(JobUpdate::LibraryChanged(changed));
+    let message = cancel.finish_message(merge_message(&plan.source, &summary), stopped_at, total);
+    let _ = updates.send(JobUpdate::Failed(summary.failed.clone()));
+    let _ = updates.send(JobUpdate::Finished { message });
+    summary
+}
+
+/// Add a book from the other library with its first file, then its other files
+async fn add_copy(
+    database: &Database,
+    source: &Database,
+    library_path: &Path,
+    source_path: &Path,
+    book: &Book,
+    files: &[PathBuf],
+) -> Result<i32> {
+    let Some((first, rest)) = files.split_first() else {
+        return Err(Error::Other("no files to copy".to_string()));
+    };
+    let metadata = source.load_new_book(book).await?;
+    let cover = book
+        .has_cover
+        .then(|| std::fs::read(source_path.join(&book.path).join("cover.jpg")).ok())
+        .flatten();
+
+    let id = database.add_book(library_path, &metadata, first, cover.as_deref()).await?;
+    for file in rest {
+        database.add_format_file(library_path, id, file).await?;
+    }
+    Ok(id)
+}
+
+fn has_format(file: &Path, formats: &[String]) -> bool {
+    file.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|ext| formats.iter().any(|f| f.eq_ignore_ascii_case(ext)))
+}
+
+fn merge_message(source: &Path, summary: &MergeSummary) -> String {
+    let mut message = format!(
+        "🔀 Merged {}: {} added, {} given new formats, {} duplicates skipped",
+        source.display(),
+        summary.added,
+        summary.merged,
+        summary.skipped
+    );
+    if let Some(failure) = summary.failed.first() {
+        message.push_str(&format!(" | ❌ {} failed ({}: {})", summary.failed.len(), failure.title, failure.error));
+    }
+    message
+}
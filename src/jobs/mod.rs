@@ -0,0 +1,113 @@
+//! Background jobs that operate on many books at once
+
+pub mod archive;
+pub mod audit;
+pub mod content;
+pub mod convert;
+pub mod download;
+pub mod embed;
+pub mod email;
+pub mod enrich;
+pub mod export;
+pub mod history;
+pub mod language;
+pub mod merge;
+pub mod review;
+pub mod send;
+pub mod split;
+pub mod tags;
+pub mod verify;
+
+pub use archive::{archive_books, plan_archive, ArchiveFormat};
+pub use audit::audit_metadata;
+pub use content::{index_contents, plan_content_index, ContentItem};
+pub use convert::{convert_books, ConversionEvent, ConversionQueue, ConversionSummary, ConversionTask};
+pub use download::download_entries;
+pub use embed::{embed_metadata, plan_embed, EmbedItem};
+pub use email::{plan_email, send_emails, EmailPlan};
+pub use enrich::enrich_library;
+pub use export::{export_books, plan_export, ExportPlan};
+pub use history::{JobFailure, JobHistory, JobKind, JobRecord};
+pub use language::detect_languages;
+pub use merge::{merge_library, plan_merge, MergePlan};
+pub use review::apply_proposals;
+pub use send::{plan_send, send_books, SendPlan, SendSummary};
+pub use split::{split_library, SplitMode};
+pub use tags::suggest_tags;
+pub use verify::{plan_verify, verify_files, FileCheck, VerifyItem, VerifyTally};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::Notify;
+
+use crate::app::MetadataMismatch;
+use crate::metadata::MetadataProposal;
+
+/// Update sent from a running background job to the UI
+#[derive(Debug, Clone)]
+pub enum JobUpdate {
+    Progress { done: usize, total: usize, current: String },
+    /// These books were added or changed, so they should be re-read into the book list
+    LibraryChanged(Vec<i32>),
+    /// Metadata found for a book, waiting for review
+    Proposal(MetadataProposal),
+    /// A file whose embedded metadata differs from the library's, for the metadata audit
+    Mismatch(Box<MetadataMismatch>),
+    /// The DRM found on a book's file as it was verified, None when the file has none
+    Drm { book_id: i32, format: String, scheme: Option<String> },
+    /// Books the job couldn't do, sent just before it finishes
+    Failed(Vec<JobFailure>),
+    Finished { message: String },
+}
+
+/// State of the background job shown in the status bar
+#[derive(Debug, Clone)]
+pub struct JobStatus {
+    pub label: String,
+    pub done: usize,
+    pub total: usize,
+    pub current: String,
+    /// Cancel was requested; the job is finishing the book it is on
+    pub cancelling: bool,
+}
+
+/// Shared flag a running job checks between books, set when the user cancels it
+///
+/// Jobs stop before starting the next book, so whatever they already wrote stays consistent.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the job is cancelled, for racing against work that can be abandoned midway
+    pub async fn cancelled(&self) {
+        let notified = self.notify.notified();
+        tokio::pin!(notified);
+        // Register before checking, so a cancel in between isn't missed
+        notified.as_mut().enable();
+        if !self.is_cancelled() {
+            notified.await;
+        }
+    }
+
+    /// The job's closing message, noting how far it got if it was cancelled
+    pub fn finish_message(&self, message: String, done: usize, total: usize) -> String {
+        if self.is_cancelled() {
+            format!("⏹ Cancelled after {} of {} | {}", done, total, message)
+        } else {
+            message
+        }
+    }
+}
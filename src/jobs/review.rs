@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+use crate::app::parse_authors;
+use crate::database::Database;
+use crate::error::Result;
+use crate::jobs::JobUpdate;
+use crate::metadata::{FieldChange, MetadataField, MetadataProposal};
+use crate::opds;
+
+/// Write the accepted fields of reviewed proposals to the library
+///
+/// A book counts as updated once any of its changes was written; one whose changes all failed
+/// is only counted among the failures, which are counted by book.
+pub async fn apply_proposals(
+    database: Database,
+    library_path: PathBuf,
+    proposals: Vec<MetadataProposal>,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+) {
+    let total = proposals.len();
+    let mut applied = Vec::new();
+    // Each book with a failed change, with the first failure's reason
+    let mut failed: Vec<(String, String)> = Vec::new();
+
+    for (done, proposal) in proposals.into_iter().enumerate() {
+        let _ = updates.send(JobUpdate::Progress { done, total, current: proposal.book_title.clone() });
+
+        let mut written = false;
+        let mut errors = Vec::new();
+        for change in proposal.accepted_changes() {
+            match apply_change(&database, &library_path, proposal.book_id, change).await {
+                Ok(()) => written = true,
+                Err(e) => errors.push(format!("{}: {}", change.field.label(), e)),
+            }
+        }
+        if written {
+            applied.push(proposal.book_id);
+        }
+        if !errors.is_empty() {
+            failed.push((proposal.book_title.clone(), errors.join("; ")));
+        }
+    }
+
+    let mut message = format!("✅ Updated metadata for {} books", applied.len());
+    if !applied.is_empty() {
+        let _ = updates.send(JobUpdate::LibraryChanged(applied));
+    }
+    if let Some((title, reason)) = failed.first() {
+        message.push_str(&format!(" | ❌ {} books with failed changes ({}: {})", failed.len(), title, reason));
+    }
+    let _ = updates.send(JobUpdate::Finished { message });
+}
+
+async fn apply_change(database: &Database, library_path: &Path, book_id: i32, change: &FieldChange) -> Result<()> {
+    let value = change.proposed.trim();
+    match change.field {
+        MetadataField::Title => database.set_title(book_id, value).await,
+        MetadataField::Authors => database.set_authors(book_id, &parse_authors(value)).await,
+        MetadataField::Isbn => database.set_identifier(book_id, "isbn", value).await,
+        MetadataField::Description => database.set_comments(book_id, value).await,
+        MetadataField::Cover => {
+            let image = if value.starts_with("http://") || value.starts_with("https://") {
+                opds::fetch_bytes(value).await?
+            } else {
+                std::fs::read(value)?
+            };
+            database.set_cover(library_path, book_id, &image).await
+        }
+        MetadataField::Languages => {
+            let codes: Vec<String> = value
+                .split(',')
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty())
+                .collect();
+            database.set_languages(book_id, &codes).await
+        }
+        MetadataField::Tag => database.add_tags(book_id, &[value.to_string()]).await,
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+use crate::app::Book;
+use crate::database::FormatEntry;
+use crate::device::Device;
+use crate::jobs::{CancelToken, JobFailure, JobUpdate};
+
+/// Program used to turn EPUBs into Kobo KEPUBs
+pub const KEPUBIFY: &str = "kepubify";
+
+/// One book file to copy onto a device
+#[derive(Debug, Clone)]
+pub struct SendItem {
+    pub book_id: i32,
+    pub title: String,
+    pub format: String,
+    pub source: PathBuf,
+    pub destination: PathBuf,
+}
+
+/// Files to send plus books that were skipped while planning
+#[derive(Debug, Default)]
+pub struct SendPlan {
+    pub items: Vec<SendItem>,
+    pub skipped: Vec<JobFailure>,
+}
+
+/// Result of a batch send
+#[derive(Debug, Default)]
+pub struct SendSummary {
+    pub sent: usize,
+    pub failed: Vec<JobFailure>,
+    /// EPUBs sent as-is because kepubify was unavailable or failed
+    pub kepub_fallbacks: usize,
+}
+
+/// Choose the best format of each book for the device
+pub fn plan_send(device: &Device, library_path: &Path, books: &[&Book], formats: &[FormatEntry]) -> SendPlan {
+    let mut by_book: HashMap<i32, Vec<&FormatEntry>> = HashMap::new();
+    for entry in formats {
+        by_book.entry(entry.book_id).or_default().push(entry);
+    }
+
+    let mut plan = SendPlan::default();
+    for book in books {
+        let available = by_book.get(&book.id).map(Vec::as_slice).unwrap_or(&[]);
+        match device.profile.best_format(available) {
+            Some(entry) => {
+                let filename = entry.filename();
+                plan.items.push(SendItem {
+                    book_id: book.id,
+                    title: book.title.clone(),
+                    format: entry.format.clone(),
+                    source: library_path.join(&entry.path).join(&filename),
+                    destination: device.books_path().join(&filename),
+                });
+            }
+            None => plan.skipped.push(JobFailure::new(
+                Some(book.id),
+                book.title.clone(),
+                format!("no format suitable for {}", device.profile.name),
+            )),
+        }
+    }
+
+    plan
+}
+
+/// Copy planned items onto the device, checking free space before each file
+pub async fn send_books(
+    device: Device,
+    plan: SendPlan,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) -> SendSummary {
+    let mut summary = SendSummary {
+        failed: plan.skipped,
+        ..Default::default()
+    };
+    let mut free = device.free_space();
+    let total = plan.items.len();
+    let mut stopped_at = total;
+
+    if let Err(e) = tokio::fs::create_dir_all(device.books_path()).await {
+        summary.failed.extend(plan.items.into_iter().map(|item| JobFailure::new(Some(item.book_id), item.title, e.to_string())));
+        let _ = updates.send(JobUpdate::Failed(summary.failed.clone()));
+        let _ = updates.send(JobUpdate::Finished { message: send_message(&device, &summary) });
+        return summary;
+    }
+
+    for (done, item) in plan.items.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let _ = updates.send(JobUpdate::Progress { done, total, current: item.title.clone() });
+
+        let size = match tokio::fs::metadata(&item.source).await {
+            Ok(meta) => meta.len(),
+            Err(_) => {
+                summary.failed.push(JobFailure::new(Some(item.book_id), item.title, format!("{} file missing", item.format)));
+                continue;
+            }
+        };
+
+        if let Some(available) = free {
+            if size > available {
+                summary.failed.push(JobFailure::new(Some(item.book_id), item.title, "not enough space on device"));
+                continue;
+            }
+        }
+
+        // Kobo reads KEPUBs much better, so convert on the fly when the profile asks for it
+        let kepub = if device.profile.kepubify && item.format.eq_ignore_ascii_case("EPUB") {
+            let output = std::env::temp_dir().join(format!("tuilibre-{}.kepub.epub", item.book_id));
+            match kepubify(&item.source, &output).await {
+                Ok(()) => Some(output),
+                Err(_) => {
+                    // Fall back to the plain EPUB, which Kobo can still read
+                    summary.kepub_fallbacks += 1;
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let (source, destination) = match &kepub {
+            Some(output) => (output.clone(), item.destination.with_extension("kepub.epub")),
+            None => (item.source.clone(), item.destination.clone()),
+        };
+
+        match tokio::fs::copy(&source, &destination).await {
+            Ok(copied) => {
+                summary.sent += 1;
+                free = free.map(|f| f.saturating_sub(copied));
+            }
+            Err(e) => summary.failed.push(JobFailure::new(Some(item.book_id), item.title, e.to_string())),
+        }
+
+        if let Some(output) = kepub {
+            let _ = tokio::fs::remove_file(output).await;
+        }
+    }
+
+    let message = cancel.finish_message(send_message(&device, &summary), stopped_at, total);
+    let _ = updates.send(JobUpdate::Failed(summary.failed.clone()));
+    let _ = updates.send(JobUpdate::Finished { message });
+    summary
+}
+
+/// Convert an EPUB into a KEPUB at `output`
+async fn kepubify(source: &Path, output: &Path) -> Result<(), String> {
+    let result = Command::new(KEPUBIFY)
+        .arg("-o")
+        .arg(output)
+        .arg(source)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run {}: {}", KEPUBIFY, e))?;
+
+    if result.status.success() && output.exists() {
+        Ok(())
+    } else {
+        Err(format!("{} failed: {}", KEPUBIFY, String::from_utf8_lossy(&result.stderr).trim()))
+    }
+}
+
+fn send_message(device: &Device, summary: &SendSummary) -> String {
+    let mut message = format!("📤 Sent {} books to {}", summary.sent, device.profile.name);
+    if summary.kepub_fallbacks > 0 {
+        message.push_str(&format!(" ({} as plain EPUB, kepubify failed)", summary.kepub_fallbacks));
+    }
+    if let Some(failure) = summary.failed.first() {
+        message.push_str(&format!(" | ❌ {} failed ({}: {})", summary.failed.len(), failure.title, failure.error));
+    }
+    message
+}
@@ -0,0 +1,117 @@
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+use crate::app::Book;
+use crate::database::Database;
+use crate::jobs::{CancelToken, JobFailure, JobUpdate};
+
+/// Whether split-off books stay in this library too
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitMode {
+    Copy,
+    /// Delete the books here once the new library has them
+    Move,
+}
+
+/// Result of a split
+#[derive(Debug, Default)]
+pub struct SplitSummary {
+    pub copied: usize,
+    pub failed: Vec<JobFailure>,
+}
+
+/// Copy the books' folders into a new library at `target`, then write its database
+///
+/// Only books whose folder was copied completely end up in the new library, and with
+/// `SplitMode::Move` only those are deleted here, so a cancel or failure loses nothing.
+pub async fn split_library(
+    database: Database,
+    library_path: PathBuf,
+    books: Vec<Book>,
+    target: PathBuf,
+    mode: SplitMode,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) -> SplitSummary {
+    let mut summary = SplitSummary::default();
+    if target.join("metadata.db").exists() {
+        let message = format!("❌ {} already has a calibre library", target.display());
+        let _ = updates.send(JobUpdate::Finished { message });
+        return summary;
+    }
+
+    let total = books.len();
+    let mut stopped_at = total;
+    let mut copied = Vec::new();
+    for (done, book) in books.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let _ = updates.send(JobUpdate::Progress { done, total, current: book.title.clone() });
+
+        let source = library_path.join(&book.path);
+        // A metadata-only entry may never have had a folder
+        if book.formats.is_empty() && !source.exists() {
+            copied.push((book.id, book.title));
+            continue;
+        }
+        let destination = target.join(&book.path);
+        match tokio::task::spawn_blocking(move || copy_dir(&source, &destination)).await {
+            Ok(Ok(())) => copied.push((book.id, book.title)),
+            Ok(Err(e)) => summary.failed.push(JobFailure::new(Some(book.id), book.title, e.to_string())),
+            Err(e) => summary.failed.push(JobFailure::new(Some(book.id), book.title, e.to_string())),
+        }
+    }
+
+    let ids: Vec<i32> = copied.iter().map(|(id, _)| *id).collect();
+    if let Err(e) = database.write_subset(&target, &ids).await {
+        let message = format!("❌ Failed to create the library at {}: {}", target.display(), e);
+        let _ = updates.send(JobUpdate::Finished { message });
+        return summary;
+    }
+    summary.copied = copied.len();
+
+    if mode == SplitMode::Move {
+        let mut moved = Vec::new();
+        for (id, title) in copied {
+            match database.delete_book(&library_path, id, true).await {
+                Ok(()) => moved.push(id),
+                Err(e) => summary.failed.push(JobFailure::new(Some(id), title, format!("not removed here: {}", e))),
+            }
+        }
+        let _ = updates.send(JobUpdate::LibraryChanged(moved));
+    }
+
+    let message = cancel.finish_message(split_message(&target, mode, &summary), stopped_at, total);
+    let _ = updates.send(JobUpdate::Failed(summary.failed.clone()));
+    let _ = updates.send(JobUpdate::Finished { message });
+    summary
+}
+
+/// Copy a book's folder with everything in it
+fn copy_dir(source: &Path, destination: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(destination)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let target = destination.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+fn split_message(target: &Path, mode: SplitMode, summary: &SplitSummary) -> String {
+    let verb = match mode {
+        SplitMode::Copy => "Copied",
+        SplitMode::Move => "Moved",
+    };
+    let mut message = format!("✂ {} {} books into the new library at {}", verb, summary.copied, target.display());
+    if let Some(failure) = summary.failed.first() {
+        message.push_str(&format!(" | ❌ {} failed ({}: {})", summary.failed.len(), failure.title, failure.error));
+    }
+    message
+}
@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+use tokio::sync::mpsc;
+
+use crate::database::{Database, DescribedBook};
+use crate::error::Error;
+use crate::jobs::{CancelToken, JobUpdate};
+use crate::metadata::{FieldChange, MetadataField, MetadataProposal};
+use crate::utils::text::strip_tags;
+
+/// Genres and the word stems that hint at them
+const GENRES: &[(&str, &[&str])] = &[
+    ("Fantasy", &["dragon", "wizard", "magic", "sorcer", "elves", "elven", "enchant", "quest", "kingdom"]),
+    ("Science Fiction", &["spaceship", "starship", "galaxy", "galactic", "planet", "alien", "robot", "interstellar", "android"]),
+    ("Mystery", &["detective", "murder", "investigat", "clue", "suspect", "sleuth"]),
+    ("Thriller", &["conspiracy", "assassin", "spy", "espionage", "hostage", "fugitive"]),
+    ("Romance", &["love", "romance", "romantic", "passion", "heart", "wedding"]),
+    ("Horror", &["haunt", "ghost", "vampire", "zombie", "terror", "demon", "nightmare"]),
+    ("History", &["century", "historical", "empire", "dynasty", "revolution", "medieval"]),
+    ("Biography", &["biography", "memoir", "autobiograph"]),
+    ("Science", &["physics", "biology", "chemistry", "scientific", "evolution", "universe"]),
+    ("Philosophy", &["philosoph", "ethics", "moral", "existential"]),
+    ("Humor", &["hilarious", "comic", "funny", "satire", "satirical"]),
+];
+
+/// Distinct stems a description must contain before its genre is suggested
+const MIN_GENRE_HITS: usize = 2;
+
+/// Existing tags shorter than this are too ambiguous to look for in descriptions
+const MIN_TAG_LEN: usize = 4;
+
+/// Suggest tags for books from their descriptions, for the user to pick from in review
+///
+/// `book_ids` limits the job to those books; empty means the whole library.
+pub async fn suggest_tags(
+    database: Database,
+    book_ids: Vec<i32>,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) {
+    let loaded = async {
+        let books = database.load_described_books().await?;
+        let tags = database.load_tag_names().await?;
+        Ok::<_, Error>((books, tags))
+    };
+    let (mut books, library_tags) = match loaded.await {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            let message = format!("❌ Failed to load descriptions: {}", e);
+            let _ = updates.send(JobUpdate::Finished { message });
+            return;
+        }
+    };
+
+    if !book_ids.is_empty() {
+        let wanted: HashSet<i32> = book_ids.into_iter().collect();
+        books.retain(|b| wanted.contains(&b.id));
+    }
+
+    let total = books.len();
+    let mut stopped_at = total;
+    let mut proposed = 0;
+
+    for (done, book) in books.into_iter().enumerate() {
+        if cancel.is_cancelled() {
+            stopped_at = done;
+            break;
+        }
+        let _ = updates.send(JobUpdate::Progress { done, total, current: book.title.clone() });
+
+        let changes: Vec<FieldChange> = suggestions_for(&book, &library_tags)
+            .into_iter()
+            .map(|tag| FieldChange::suggest(MetadataField::Tag, tag))
+            .collect();
+        if changes.is_empty() {
+            continue;
+        }
+
+        proposed += 1;
+        let proposal = MetadataProposal {
+            book_id: book.id,
+            book_title: book.title,
+            origin: "Description keywords".to_string(),
+            changes,
+        };
+        if updates.send(JobUpdate::Proposal(proposal)).is_err() {
+            return;
+        }
+    }
+
+    let message = format!("🏷 Tag suggestions for {} of {} described books, review with R", proposed, total);
+    let message = cancel.finish_message(message, stopped_at, total);
+    let _ = updates.send(JobUpdate::Finished { message });
+}
+
+/// Tags the description hints at that the book doesn't have yet
+fn suggestions_for(book: &DescribedBook, library_tags: &[String]) -> Vec<String> {
+    let text = strip_tags(&book.description).to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    // Space-padded so phrases only match on word boundaries
+    let phrase_text = format!(" {} ", words.join(" "));
+
+    let has = |tag: &str| book.tags.iter().any(|t| t.eq_ignore_ascii_case(tag));
+    let mut suggestions: Vec<String> = Vec::new();
+
+    for tag in library_tags {
+        if tag.chars().count() < MIN_TAG_LEN || has(tag) {
+            continue;
+        }
+        let phrase: Vec<String> = tag
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_string())
+            .collect();
+        if !phrase.is_empty() && phrase_text.contains(&format!(" {} ", phrase.join(" "))) {
+            suggestions.push(tag.clone());
+        }
+    }
+
+    for (genre, stems) in GENRES {
+        if has(genre) || suggestions.iter().any(|s| s.eq_ignore_ascii_case(genre)) {
+            continue;
+        }
+        let hits = stems
+            .iter()
+            .filter(|stem| words.iter().any(|w| w.starts_with(*stem)))
+            .count();
+        if hits >= MIN_GENRE_HITS {
+            suggestions.push(genre.to_string());
+        }
+    }
+
+    suggestions
+}
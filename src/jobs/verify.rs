@@ -0,0 +1,321 @@
+//! Checking that every file of the library is there and can be read
+//!
+//! Files are checked by a pool of workers, each taking the next file off a shared queue, so
+//! a slow disk or a huge PDF doesn't hold up the rest. Zip-based formats have every entry
+//! read back against its checksum, PDFs are checked for their header and trailer, and
+//! anything else must read to the end at least as long as calibre recorded it.
+//!
+//! Intact files are also checked for DRM: Adobe, Readium LCP, Apple and other encryption in
+//! EPUBs, Kindle vouchers and DRMION containers in KFX, and Mobipocket encryption in MOBI and
+//! AZW files. PDFs aren't; most encrypted ones only restrict printing and still open.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+use crate::app::Book;
+use crate::jobs::{CancelToken, JobFailure, JobUpdate};
+
+/// Formats stored as zip archives
+const ZIP_FORMATS: [&str; 6] = ["EPUB", "KEPUB", "CBZ", "DOCX", "ODT", "ZIP"];
+
+/// Formats in a Palm database with a Mobipocket header
+const MOBI_FORMATS: [&str; 5] = ["MOBI", "AZW", "AZW3", "AZW4", "PRC"];
+
+/// EPUB encryption algorithms that only obfuscate embedded fonts, leaving the text readable
+const FONT_OBFUSCATION: [&str; 2] = ["http://www.idpf.org/2008/embedding", "http://ns.adobe.com/pdf/enc#RC"];
+
+/// Magic at the start of an encrypted KFX container
+const DRMION_MAGIC: &[u8] = b"\xeaDRMION\xee";
+
+/// One file to check
+#[derive(Debug, Clone)]
+pub struct VerifyItem {
+    pub book_id: i32,
+    pub title: String,
+    pub format: String,
+    pub path: PathBuf,
+    /// Size calibre recorded when the file was added
+    pub size: u64,
+}
+
+/// What checking a file found
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileCheck {
+    Ok,
+    /// Intact, but locked by DRM, named here, so standard readers can't open it
+    Protected(String),
+    Missing,
+    Corrupted(String),
+}
+
+/// Files checked so far by outcome
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VerifyTally {
+    pub ok: usize,
+    pub protected: usize,
+    pub missing: usize,
+    pub corrupted: usize,
+}
+
+impl VerifyTally {
+    pub fn add(&mut self, check: &FileCheck) {
+        match check {
+            FileCheck::Ok => self.ok += 1,
+            FileCheck::Protected(_) => self.protected += 1,
+            FileCheck::Missing => self.missing += 1,
+            FileCheck::Corrupted(_) => self.corrupted += 1,
+        }
+    }
+
+    pub fn checked(&self) -> usize {
+        self.ok + self.protected + self.missing + self.corrupted
+    }
+}
+
+impl fmt::Display for VerifyTally {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "✅ {} ok · 🚫 {} missing · ❌ {} corrupted", self.ok, self.missing, self.corrupted)?;
+        if self.protected > 0 {
+            write!(f, " · 🔒 {} DRM", self.protected)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every file of the books
+pub fn plan_verify(library_path: &Path, books: &[&Book]) -> Vec<VerifyItem> {
+    books
+        .iter()
+        .flat_map(|book| {
+            book.formats.iter().map(|format| VerifyItem {
+                book_id: book.id,
+                title: book.title.clone(),
+                format: format.format.clone(),
+                path: book.format_path(library_path, format),
+                size: format.size.max(0) as u64,
+            })
+        })
+        .collect()
+}
+
+/// Check one file, reading it from disk; blocking
+pub fn check_file(item: &VerifyItem) -> FileCheck {
+    let file = match File::open(&item.path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return FileCheck::Missing,
+        Err(e) => return FileCheck::Corrupted(e.to_string()),
+    };
+    let length = match file.metadata() {
+        Ok(meta) => meta.len(),
+        Err(e) => return FileCheck::Corrupted(e.to_string()),
+    };
+    if length == 0 {
+        return FileCheck::Corrupted("empty file".to_string());
+    }
+
+    let format = item.format.to_uppercase();
+    let checked = if ZIP_FORMATS.contains(&format.as_str()) {
+        check_zip(file)
+    } else if format == "PDF" {
+        check_pdf(file, length)
+    } else if length < item.size {
+        Err(format!("{} bytes shorter than when it was added", item.size - length))
+    } else {
+        io::copy(&mut io::BufReader::new(file), &mut io::sink()).map(|_| ()).map_err(|e| e.to_string())
+    };
+    if let Err(reason) = checked {
+        return FileCheck::Corrupted(reason);
+    }
+    match drm_scheme(&item.path, &format) {
+        Some(scheme) => FileCheck::Protected(scheme.to_string()),
+        None => FileCheck::Ok,
+    }
+}
+
+/// The DRM locking an intact file, if any; formats without known DRM are never flagged
+pub fn drm_scheme(path: &Path, format: &str) -> Option<&'static str> {
+    let mut file = File::open(path).ok()?;
+    match format {
+        "EPUB" | "KEPUB" => epub_drm(file),
+        "KFX" | "KFX-ZIP" => {
+            let mut magic = [0u8; 8];
+            file.read_exact(&mut magic).ok()?;
+            if magic == DRMION_MAGIC {
+                return Some("Kindle");
+            }
+            // A KFX-ZIP bundles its book and, when it is locked, the voucher holding the key
+            let mut archive = zip::ZipArchive::new(io::BufReader::new(file)).ok()?;
+            let locked = archive.file_names().any(|name| name.ends_with(".voucher"));
+            let drmion = (0..archive.len()).any(|i| {
+                let mut head = [0u8; 8];
+                archive.by_index(i).is_ok_and(|mut entry| entry.read_exact(&mut head).is_ok() && head == DRMION_MAGIC)
+            });
+            (locked || drmion).then_some("Kindle")
+        }
+        _ if MOBI_FORMATS.contains(&format) => mobi_drm(file),
+        _ => None,
+    }
+}
+
+/// An EPUB's DRM from the license files each scheme adds to META-INF, or from encryption.xml
+/// listing anything besides obfuscated fonts
+fn epub_drm(file: File) -> Option<&'static str> {
+    let mut archive = zip::ZipArchive::new(io::BufReader::new(file)).ok()?;
+    let has = |name: &str| archive.file_names().any(|entry| entry.eq_ignore_ascii_case(name));
+    if has("META-INF/rights.xml") {
+        return Some("Adobe ADEPT");
+    }
+    if has("META-INF/license.lcpl") {
+        return Some("Readium LCP");
+    }
+    if has("META-INF/sinf.xml") {
+        return Some("Apple FairPlay");
+    }
+
+    let mut encryption = String::new();
+    archive.by_name("META-INF/encryption.xml").ok()?.read_to_string(&mut encryption).ok()?;
+    let doc = roxmltree::Document::parse(&encryption).ok()?;
+    let encrypted = doc
+        .descendants()
+        .filter(|node| node.has_tag_name("EncryptionMethod"))
+        .filter_map(|node| node.attribute("Algorithm"))
+        .any(|algorithm| !FONT_OBFUSCATION.contains(&algorithm));
+    encrypted.then_some("encrypted")
+}
+
+/// The encryption type in the Mobipocket header of the first record: 1 and 2 are DRM
+fn mobi_drm(mut file: File) -> Option<&'static str> {
+    let mut header = [0u8; 82];
+    file.read_exact(&mut header).ok()?;
+    if &header[60..68] != b"BOOKMOBI" {
+        return None;
+    }
+    let record0 = u32::from_be_bytes(header[78..82].try_into().ok()?);
+    file.seek(SeekFrom::Start(record0 as u64 + 12)).ok()?;
+    let mut encryption = [0u8; 2];
+    file.read_exact(&mut encryption).ok()?;
+    match u16::from_be_bytes(encryption) {
+        0 => None,
+        _ => Some("Mobipocket"),
+    }
+}
+
+/// Read every entry to the end, which has the zip crate compare its checksum
+fn check_zip(file: File) -> Result<(), String> {
+    let mut archive = zip::ZipArchive::new(io::BufReader::new(file)).map_err(|e| e.to_string())?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
+        let name = entry.name().to_string();
+        io::copy(&mut entry, &mut io::sink()).map_err(|e| format!("{}: {}", name, e))?;
+    }
+    Ok(())
+}
+
+/// A PDF starts with "%PDF-" and a complete one has "%%EOF" near its end
+fn check_pdf(mut file: File, length: u64) -> Result<(), String> {
+    let mut header = [0u8; 5];
+    file.read_exact(&mut header).map_err(|e| e.to_string())?;
+    if &header != b"%PDF-" {
+        return Err("not a PDF".to_string());
+    }
+
+    let tail_length = length.min(1024);
+    file.seek(SeekFrom::End(-(tail_length as i64))).map_err(|e| e.to_string())?;
+    let mut tail = Vec::with_capacity(tail_length as usize);
+    file.read_to_end(&mut tail).map_err(|e| e.to_string())?;
+    if !tail.windows(5).any(|w| w == b"%%EOF") {
+        return Err("truncated, no end of file marker".to_string());
+    }
+    Ok(())
+}
+
+/// Check files with `workers` at a time, sending each result as it comes
+///
+/// Once `cancel` fires, files being checked are finished and the rest are left.
+pub async fn check_files(
+    items: Vec<VerifyItem>,
+    workers: usize,
+    results: mpsc::UnboundedSender<(VerifyItem, FileCheck)>,
+    cancel: CancelToken,
+) {
+    let queue = Arc::new(Mutex::new(VecDeque::from(items)));
+    let mut handles = Vec::new();
+    for _ in 0..workers.max(1) {
+        let queue = Arc::clone(&queue);
+        let results = results.clone();
+        let cancel = cancel.clone();
+        handles.push(tokio::spawn(async move {
+            while !cancel.is_cancelled() {
+                // The lock is only held while taking the next item, so a panic can't poison it midway
+                let Some(item) = queue.lock().ok().and_then(|mut queue| queue.pop_front()) else {
+                    break;
+                };
+                let checked = tokio::task::spawn_blocking(move || {
+                    let check = check_file(&item);
+                    (item, check)
+                })
+                .await;
+                if let Ok(result) = checked {
+                    let _ = results.send(result);
+                }
+            }
+        }));
+    }
+    for handle in handles {
+        let _ = handle.await;
+    }
+}
+
+/// Workers to check files with when none are configured: one per CPU
+pub fn default_workers() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4)
+}
+
+/// Run `check_files` as a background job, keeping a running tally in the status bar
+pub async fn verify_files(
+    items: Vec<VerifyItem>,
+    workers: usize,
+    updates: mpsc::UnboundedSender<JobUpdate>,
+    cancel: CancelToken,
+) -> VerifyTally {
+    let total = items.len();
+    let (results, mut rx) = mpsc::unbounded_channel();
+    let checking = tokio::spawn(check_files(items, workers, results, cancel.clone()));
+
+    let mut tally = VerifyTally::default();
+    let mut failures = Vec::new();
+    while let Some((item, check)) = rx.recv().await {
+        tally.add(&check);
+        let current = format!("{} · {}", tally, item.title);
+        let _ = updates.send(JobUpdate::Progress { done: tally.checked(), total, current });
+        let drm = match &check {
+            FileCheck::Protected(scheme) => Some(scheme.clone()),
+            _ => None,
+        };
+        let _ = updates.send(JobUpdate::Drm { book_id: item.book_id, format: item.format.clone(), scheme: drm });
+        match check {
+            FileCheck::Ok | FileCheck::Protected(_) => {}
+            FileCheck::Missing => {
+                failures.push(JobFailure::new(Some(item.book_id), item.title, format!("{} file missing", item.format)));
+            }
+            FileCheck::Corrupted(reason) => {
+                failures.push(JobFailure::new(Some(item.book_id), item.title, format!("{}: {}", item.format, reason)));
+            }
+        }
+    }
+    let _ = checking.await;
+
+    let mut message = format!("🩺 Verified {} files: {}", tally.checked(), tally);
+    if let Some(failure) = failures.first() {
+        message.push_str(&format!(" | {}: {}", failure.title, failure.error));
+    }
+    let message = cancel.finish_message(message, tally.checked(), total);
+    let _ = updates.send(JobUpdate::Failed(failures));
+    let _ = updates.send(JobUpdate::Finished { message });
+    tally
+}
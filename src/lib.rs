@@ -2,13 +2,46 @@
 //!
 //! This library provides the core functionality for the tuilibre application,
 //! including database access, UI components, and application state management.
+//!
+//! The calibre-access layer can be used without the TUI: nothing outside `ui`
+//! prints or exits the process, and every failure comes back as an [`Error`].
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use tuilibre::{BookQuery, Database};
+//!
+//! # async fn example() -> tuilibre::Result<()> {
+//! let database = Database::new(Path::new("/path/to/Calibre Library")).await?;
+//! let query = BookQuery { text: "tolkien".to_string(), ..Default::default() };
+//! for book in database.search(&query).await? {
+//!     println!("{} by {}", book.title, book.authors.join(" & "));
+//! }
+//! # Ok(())
+//! # }
+//! ```
 
 pub mod app;
+pub mod calibre_web;
+pub mod config;
 pub mod database;
+pub mod device;
+pub mod epub;
+pub mod error;
 pub mod ui;
 pub mod utils;
 pub mod history;
+pub mod jobs;
+pub mod metadata;
+pub mod opds;
+pub mod queue;
+pub mod settings;
+pub mod shelves;
+#[cfg(feature = "dev")]
+pub mod fixture;
 
-pub use app::{App, Book};
-pub use database::Database;
+pub use app::{parse_calibre_search, App, Book, BookQuery, Filter, FilterClause};
+pub use config::Config;
+pub use database::{Database, FormatEntry, NewBook};
+pub use error::{Error, Result};
+pub use history::{LibraryEntry, LibraryHistory};
 pub use ui::UI;
\ No newline at end of file
@@ -1,17 +1,25 @@
+mod cli;
+
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
-
-mod app;
-mod database;
-mod ui;
-mod utils;
-mod history;
-
-use app::App;
-use database::Database;
-use ui::UI;
-use history::LibraryHistory;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use cli::{CliError, ExitStatus, OutputFormat};
+use tuilibre::app::{self, App, Book};
+use tuilibre::config::{Config, DatabaseConfig};
+use tuilibre::database::{database_stamp, snapshot_taken, Database};
+use tuilibre::ui::picker::pick_book;
+use tuilibre::ui::splash::{LoadStage, Splash};
+use tuilibre::ui::theme::Theme;
+use tuilibre::ui::UI;
+use tuilibre::history::LibraryHistory;
+use tuilibre::utils::output::{self, ListFormat};
+use tuilibre::utils::signals;
+use tuilibre::jobs::{self, CancelToken, ConversionEvent, ConversionQueue, ConversionTask, FileCheck, VerifyTally};
 
 #[derive(Parser)]
 #[command(name = "tuilibre")]
@@ -20,43 +28,201 @@ use history::LibraryHistory;
 struct Args {
     /// Path to the calibre library directory (contains metadata.db)
     /// Can be provided as: tuilibre /path/to/library OR tuilibre --library /path/to/library
-    #[arg(short, long, default_value = ".")]
+    #[arg(short, long, default_value = ".", global = true)]
     library: PathBuf,
 
     /// (Deprecated) Positional argument for library path - kept for compatibility
     /// Use --library or provide the path directly instead
     #[arg()]
     library_path: Option<PathBuf>,
+
+    /// How the non-interactive subcommands print results and errors: text, or json for scripts
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, global = true)]
+    output: OutputFormat,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print every book of the library, one per line: id, title and authors
+    List {
+        /// plain, json or csv; follows --output without it
+        #[arg(long, value_enum)]
+        format: Option<ListFormat>,
+    },
+
+    /// Print the books a search finds, in calibre's syntax, e.g. "tags:fantasy author:pratchett"
+    Search {
+        /// The search, as typed into the search bar
+        query: String,
+
+        /// plain, json or csv; follows --output without it
+        #[arg(long, value_enum)]
+        format: Option<ListFormat>,
+    },
+
+    /// Print a book's metadata and files
+    Info {
+        /// The book's id in calibre
+        id: i32,
+    },
+
+    /// Convert every book with one format into another using ebook-convert
+    Convert {
+        /// Source format (e.g. azw3)
+        #[arg(long)]
+        from: String,
+
+        /// Target format (e.g. epub)
+        #[arg(long)]
+        to: String,
+
+        /// Number of parallel ebook-convert workers
+        #[arg(short, long, default_value_t = 2)]
+        jobs: usize,
+
+        /// Extra attempts for a failed conversion
+        #[arg(long, default_value_t = 1)]
+        retries: u32,
+    },
+
+    /// Add ebook files as new books, described by their EPUB metadata or else their file name
+    Add {
+        /// The files, e.g. book.epub or "Title - Author.pdf"
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// List a book's formats with their size and path
+    Formats {
+        /// The book's id in calibre
+        id: i32,
+    },
+
+    /// Copy a book's cover image to a file
+    Cover {
+        /// The book's id in calibre
+        id: i32,
+
+        /// Where to write the cover, "-" for stdout; prints the cover's path without it
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Print the path of one of a book's files
+    Path {
+        /// The book's id in calibre
+        id: i32,
+
+        /// Which format, e.g. epub; the book's first format without it
+        #[arg(long)]
+        format: Option<String>,
+    },
+
+    /// Pick a book by fuzzy search and print the path of its file, for use in scripts
+    Pick {
+        /// Text to start the search with
+        query: Option<String>,
+
+        /// Print the book's id instead of a path
+        #[arg(long)]
+        id: bool,
+
+        /// Print the path of this format, e.g. epub; the book's first format without it
+        #[arg(long)]
+        format: Option<String>,
+    },
+
+    /// Check that every file of the library is there and readable, printing the ones that aren't
+    /// and those locked by DRM
+    Verify {
+        /// Number of files checked at once; one per CPU without it
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+
+    /// Print a line for every book added to or removed from the library until interrupted
+    Watch {
+        /// Seconds between checks of metadata.db
+        #[arg(long, default_value_t = 2)]
+        interval: u64,
+    },
+
+    /// Developer tools
+    #[cfg(feature = "dev")]
+    Dev {
+        #[command(subcommand)]
+        command: DevCommand,
+    },
+}
+
+#[cfg(feature = "dev")]
+#[derive(Subcommand)]
+enum DevCommand {
+    /// Create a library of random books at --library for testing and benchmarking
+    GenLibrary {
+        /// Number of books
+        #[arg(long, default_value_t = 1000)]
+        books: usize,
+
+        /// Random seed; the same seed gives the same library
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+
+        /// Replace an existing metadata.db
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = Args::parse();
+    let args = Args::try_parse().unwrap_or_else(|error| {
+        // Help and version aren't errors; usage errors are reported like any other for scripts
+        if error.use_stderr() && cli::json_requested() {
+            CliError::new(ExitStatus::Usage, error.to_string().trim()).report(OutputFormat::Json);
+            std::process::exit(ExitStatus::Usage.code());
+        }
+        error.exit()
+    });
 
     // Use positional argument if provided, otherwise use the --library argument
-    let mut library_path = if args.library_path.is_some() {
-        args.library_path.unwrap()
-    } else {
-        args.library
-    };
+    let mut library_path = args.library_path.unwrap_or(args.library);
 
-    // Check if library path exists and has metadata.db
+    if let Some(command) = args.command {
+        if let Err(error) = run_command(command, &library_path, args.output).await {
+            error.report(args.output);
+            std::process::exit(error.exit_code);
+        }
+        return Ok(());
+    }
+
+    // SIGINT, SIGTERM and SIGHUP let the UI finish writing and restore the terminal before exiting
+    let shutdown = CancelToken::default();
+    signals::listen_for_shutdown(shutdown.clone());
+
+    // Check if library path exists and has metadata.db, or is offline with a snapshot
     let mut library_valid = library_path.exists();
     if library_valid {
         let db_path = library_path.join("metadata.db");
         library_valid = db_path.exists();
     }
+    library_valid |= snapshot_taken(&library_path).is_some();
 
     // If no valid library provided, show library selection UI
     if !library_valid {
         println!("🔍 未指定有效的 calibre 图书馆，正在搜索已知的图书馆...");
 
         // Initialize UI for library selection
-        let mut ui = UI::new();
+        let mut ui = UI::new().with_shutdown(shutdown.clone());
 
         if let Some(selected_path) = ui.select_library().await? {
             library_path = selected_path;
             println!("✅ 选择了图书馆: {}", library_path.display());
+        } else if shutdown.is_cancelled() {
+            return Ok(());
         } else {
             eprintln!("❌ 未选择图书馆，退出程序。");
             eprintln!("\n💡 手动指定图书馆路径:");
@@ -72,120 +238,157 @@ async fn main() -> Result<()> {
 
     // Double-check that the selected library is valid
     let db_path = library_path.join("metadata.db");
-    if !db_path.exists() {
+    if !db_path.exists() && snapshot_taken(&library_path).is_none() {
         eprintln!("❌ Error: No calibre database found at: {}", db_path.display());
         eprintln!("💡 Make sure the directory contains a calibre library with metadata.db");
         std::process::exit(1);
     }
 
-    // Initialize database connection with better error handling
-    let database = Database::new(&library_path)
-        .await
-        .with_context(|| format!("Failed to connect to calibre database at: {}", db_path.display()))?;
-
-    // Save this library to history (for direct path usage)
-    if let Err(e) = save_library_to_history(&library_path, &database).await {
-        eprintln!("Warning: Failed to save library to history: {}", e);
-    }
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("Warning: Failed to load config: {}", e);
+        Config::default()
+    });
 
-    // Load initial books
-    let books = database.load_books().await
-        .with_context(|| "Failed to load books from database")?;
+    // Open the library behind a loading screen; loading only reads, so a signal can cut it short
+    let Some((database, books, total, mut splash)) = load_library(&library_path, &config, &shutdown).await? else {
+        return Ok(());
+    };
 
     if books.is_empty() {
+        drop(splash);
         eprintln!("⚠️  Warning: No books found in this calibre library.");
         eprintln!("💡 The database appears to be empty.");
         std::process::exit(0);
     }
 
-    println!("📚 Loaded {} books from calibre library", books.len());
-
     // Initialize application state
-    let all_books = books.clone();
-    let mut app = App {
-        books,
-        all_books,
-        selected_book_index: 0,
-        search_query: String::new(),
-        mode: app::AppMode::Normal,
-        library_path,
-    };
-
-    // Initialize UI
-    let mut ui = UI::new();
+    let mut app = App::new(library_path);
+    app.config = config;
+    splash.stage(LoadStage::BuildingIndex)?;
+    app.set_all_books(books.clone());
+    app.start_paging(&books, total);
+    app.books = books;
+    drop(splash);
+
+    // Initialize UI and open the library the way it was saved
+    let mut ui = UI::new().with_shutdown(shutdown.clone());
+    ui.apply_library_defaults(&mut app, &database).await;
 
     // Main application loop with library switching support
     let mut database = database;
-    loop {
-        // Run the application with current library
-        match ui.run(&mut app, &database).await? {
-            Some(_) => {
-                // User wants to switch libraries - show library selector
-                println!("\n🔍 选择新的图书馆...");
-                if let Some(new_library_path) = ui.select_library().await? {
-                    println!("✅ 选择了图书馆: {}", new_library_path.display());
-
-                    // Load the new library directly
-                    println!("📚 正在加载新图书馆...");
-
-                    // Initialize database connection for new library
-                    let new_db_path = new_library_path.join("metadata.db");
-                    if !new_db_path.exists() {
-                        eprintln!("❌ 错误: 找不到 calibre 数据库: {}", new_db_path.display());
-                        std::process::exit(1);
-                    }
-
-                    let new_database = Database::new(&new_library_path)
-                        .await
-                        .with_context(|| format!("Failed to connect to calibre database at: {}", new_db_path.display()))?;
-
-                    // Save to history
-                    if let Err(e) = save_library_to_history(&new_library_path, &new_database).await {
-                        eprintln!("Warning: Failed to save library to history: {}", e);
-                    }
+    while let Some(reopen) = ui.run(&mut app, &database).await? {
+        // An offline library that came back is reopened; otherwise show the library selector
+        let chosen = if reopen.as_os_str().is_empty() {
+            println!("\n🔍 选择新的图书馆...");
+            ui.select_library().await?
+        } else {
+            Some(reopen)
+        };
+        if let Some(new_library_path) = chosen {
+            println!("✅ 选择了图书馆: {}", new_library_path.display());
+
+            let new_db_path = new_library_path.join("metadata.db");
+            if !new_db_path.exists() && snapshot_taken(&new_library_path).is_none() {
+                eprintln!("❌ 错误: 找不到 calibre 数据库: {}", new_db_path.display());
+                std::process::exit(1);
+            }
 
-                    // Load new books
-                    let new_books = new_database.load_books().await
-                        .with_context(|| "Failed to load books from database")?;
+            let Some((new_database, new_books, total, mut splash)) = load_library(&new_library_path, &app.config, &shutdown).await? else {
+                break;
+            };
 
-                    if new_books.is_empty() {
-                        eprintln!("⚠️  Warning: No books found in this calibre library.");
-                        std::process::exit(0);
-                    }
+            if new_books.is_empty() {
+                drop(splash);
+                eprintln!("⚠️  Warning: No books found in this calibre library.");
+                std::process::exit(0);
+            }
 
-                    println!("📚 Loaded {} books from calibre library", new_books.len());
-
-                    // Update app state
-                    let all_new_books = new_books.clone();
-                    app.books = new_books;
-                    app.all_books = all_new_books;
-                    app.selected_book_index = 0;
-                    app.search_query.clear();
-                    app.mode = app::AppMode::Normal;
-                    app.library_path = new_library_path.clone();
-
-                    // Update database reference
-                    database = new_database;
-
-                    // Continue the loop with the new library
-                    continue;
-                } else {
-                    println!("❌ 未选择图书馆，退出程序。");
-                    std::process::exit(0);
-                }
-            },
-            None => {
-                // Normal exit
-                break;
+            // Update app state
+            splash.stage(LoadStage::BuildingIndex)?;
+            app.set_all_books(new_books.clone());
+            app.start_paging(&new_books, total);
+            app.books = new_books;
+            drop(splash);
+            app.selected_book_index = 0;
+            app.scroll.get_mut().clear();
+            app.search_query.clear();
+            app.mode = app::AppMode::Normal;
+            app.library_path = new_library_path.clone();
+            app.selected_ids.clear();
+            app.search_stats = None;
+
+            // Update database reference
+            database = new_database;
+            ui.apply_library_defaults(&mut app, &database).await;
+        } else {
+            // Closing the library below keeps its lock from being left behind
+            if !shutdown.is_cancelled() {
+                println!("❌ 未选择图书馆，退出程序。");
             }
+            break;
         }
     }
 
+    // Let any write still in flight finish before the connections go away
+    database.close().await;
     Ok(())
 }
 
+/// Connect to the library and load its books behind the loading screen, which is returned
+/// for the caller to show the index being built on
+///
+/// A large library comes back with just its first page, along with how many books it has.
+///
+/// None when a signal cut loading short.
+async fn load_library(library_path: &Path, config: &Config, shutdown: &CancelToken) -> Result<Option<(Database, Vec<Book>, usize, Splash)>> {
+    let mut splash = Splash::start(library_path, config)?;
+    let database = connect_library(library_path, &config.database).await?;
+
+    // Save this library to history (for direct path usage)
+    if let Err(e) = save_library_to_history(library_path, &database).await {
+        tracing::warn!("Failed to save library to history: {}", e);
+    }
+
+    splash.stage(LoadStage::Counting)?;
+    let total = database.count_books().await.with_context(|| "Failed to count books")?;
+
+    splash.stage(LoadStage::LoadingBooks)?;
+    let loading = async {
+        if config.database.pages(total) {
+            database.load_books_page(None, config.database.page_size).await
+        } else {
+            database.load_books_with_progress(|done| { let _ = splash.progress(done, total); }).await
+        }
+    };
+    let books = tokio::select! {
+        books = loading => books.with_context(|| "Failed to load books from database")?,
+        _ = shutdown.cancelled() => return Ok(None),
+    };
+    Ok(Some((database, books, total, splash)))
+}
+
+/// Connect to the library, or to its snapshot while its share or drive is offline
+async fn connect_library(library_path: &Path, config: &DatabaseConfig) -> Result<Database> {
+    let db_path = library_path.join("metadata.db");
+    if !db_path.exists() && snapshot_taken(library_path).is_some() {
+        return Database::open_snapshot(library_path, config)
+            .await
+            .with_context(|| format!("Failed to open the offline snapshot of: {}", library_path.display()));
+    }
+
+    Database::open(library_path, config)
+        .await
+        .with_context(|| format!("Failed to connect to calibre database at: {}", db_path.display()))
+}
+
 /// Save library to history
-async fn save_library_to_history(library_path: &PathBuf, database: &Database) -> anyhow::Result<()> {
+///
+/// Skipped for an offline snapshot, whose path can't tell which share or drive it is on.
+async fn save_library_to_history(library_path: &Path, database: &Database) -> anyhow::Result<()> {
+    if database.snapshot().is_some() {
+        return Ok(());
+    }
+
     let mut history = LibraryHistory::load().unwrap_or_else(|e| {
         eprintln!("Warning: Failed to load history: {}", e);
         LibraryHistory::new()
@@ -198,11 +401,553 @@ async fn save_library_to_history(library_path: &PathBuf, database: &Database) ->
         .map(|s| s.to_string());
 
     // Get book count
-    let books = database.load_books().await?;
-    let book_count = Some(books.len() as i32);
+    let book_count = Some(database.count_books().await? as i32);
 
     history.add_library(library_path, library_name, book_count);
+    // Lets the selector recognize the library if it is moved
+    if let Ok(Some(uuid)) = database.library_uuid().await {
+        history.set_library_id(library_path, uuid);
+    }
     history.save()?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Run a non-interactive subcommand against the given library
+async fn run_command(command: Command, library_path: &Path, output: OutputFormat) -> Result<(), CliError> {
+    match command {
+        Command::List { format } => {
+            let database = open_read_only(library_path).await?;
+            let books = database.load_books().await.with_context(|| "Failed to load books from database")?;
+            print_books(&books, list_format(format, output));
+            if books.is_empty() {
+                return Err(CliError::new(ExitStatus::NoResults, "No books in this library"));
+            }
+            Ok(())
+        }
+        Command::Search { query, format } => {
+            if let Some(error) = app::check_calibre_search(&query) {
+                return Err(CliError::new(ExitStatus::Usage, format!("Bad search at character {}: {}", error.position + 1, error.message)));
+            }
+            let database = open_read_only(library_path).await?;
+            let books = database.search(&app::search_query(&query)).await.with_context(|| "Failed to search the library")?;
+            print_books(&books, list_format(format, output));
+            if books.is_empty() {
+                return Err(CliError::new(ExitStatus::NoResults, format!("No books match \"{}\"", query)));
+            }
+            Ok(())
+        }
+        Command::Info { id } => {
+            let database = open_read_only(library_path).await?;
+            let book = find_book(&database, id).await?;
+            print_book_info(&book, library_path, output);
+            Ok(())
+        }
+        Command::Formats { id } => {
+            let database = open_read_only(library_path).await?;
+            let book = find_book(&database, id).await?;
+            let formats: Vec<_> = book
+                .formats
+                .iter()
+                .map(|f| (f, book.format_path(library_path, f)))
+                .collect();
+            match output {
+                OutputFormat::Text => {
+                    for (format, path) in &formats {
+                        println!("{}\t{}\t{}", format.format, format.size, path.display());
+                    }
+                }
+                OutputFormat::Json => {
+                    let formats: Vec<_> = formats
+                        .iter()
+                        .map(|(f, path)| serde_json::json!({ "format": f.format, "size": f.size, "path": path }))
+                        .collect();
+                    println!("{}", serde_json::json!({ "id": book.id, "title": book.title, "formats": formats }));
+                }
+            }
+            if formats.is_empty() {
+                return Err(CliError::new(ExitStatus::NoResults, format!("\"{}\" has no files", book.title)));
+            }
+            Ok(())
+        }
+        Command::Cover { id, out } => {
+            let database = open_read_only(library_path).await?;
+            let book = find_book(&database, id).await?;
+            let cover = library_path.join(&book.path).join("cover.jpg");
+            if !book.has_cover || !cover.is_file() {
+                return Err(CliError::new(ExitStatus::NoResults, format!("\"{}\" has no cover", book.title)));
+            }
+            let written = match out {
+                Some(out) if out.as_os_str() == "-" => {
+                    let image = std::fs::read(&cover).with_context(|| format!("Failed to read {}", cover.display()))?;
+                    std::io::Write::write_all(&mut std::io::stdout(), &image).with_context(|| "Failed to write the cover")?;
+                    return Ok(());
+                }
+                Some(out) => {
+                    std::fs::copy(&cover, &out).with_context(|| format!("Failed to write {}", out.display()))?;
+                    out
+                }
+                None => cover,
+            };
+            print_path(&written, book.id, output);
+            Ok(())
+        }
+        Command::Path { id, format } => {
+            let database = open_read_only(library_path).await?;
+            let book = find_book(&database, id).await?;
+            print_path(&book_file(&book, library_path, format.as_deref())?, book.id, output);
+            Ok(())
+        }
+        Command::Pick { query, id, format } => {
+            let database = open_read_only(library_path).await?;
+            let books = database.load_books().await.with_context(|| "Failed to load books from database")?;
+            if books.is_empty() {
+                return Err(CliError::new(ExitStatus::NoResults, "No books in this library"));
+            }
+            let config = Config::load().unwrap_or_default();
+            let theme = Theme::from_config(&config.display);
+            let picked = pick_book(&books, query.as_deref().unwrap_or_default(), &theme).with_context(|| "Failed to show the picker")?;
+            let Some(index) = picked else {
+                return Err(CliError::new(ExitStatus::Cancelled, "Nothing picked"));
+            };
+            let book = &books[index];
+            if id {
+                match output {
+                    OutputFormat::Text => println!("{}", book.id),
+                    OutputFormat::Json => println!("{}", serde_json::json!({ "id": book.id })),
+                }
+            } else {
+                print_path(&book_file(book, library_path, format.as_deref())?, book.id, output);
+            }
+            Ok(())
+        }
+        Command::Watch { interval } => {
+            let database = open_read_only(library_path).await?;
+            watch_library(&database, library_path, Duration::from_secs(interval.max(1)), output).await
+        }
+        Command::Verify { jobs } => {
+            let database = open_read_only(library_path).await?;
+            run_verify(&database, library_path, jobs.unwrap_or_else(jobs::verify::default_workers), output).await
+        }
+        Command::Add { files } => {
+            let database = open_database(library_path).await?;
+            if let Some(owner) = database.lock_owner() {
+                let message = format!("The library is locked by {}, close it there first", owner.describe());
+                return Err(CliError::new(ExitStatus::LibraryLocked, message));
+            }
+            add_files(&database, library_path, &files, output).await
+        }
+        Command::Convert { from, to, jobs, retries } => {
+            let database = open_database(library_path).await?;
+            if let Some(owner) = database.lock_owner() {
+                let message = format!("The library is locked by {}, close it there first", owner.describe());
+                return Err(CliError::new(ExitStatus::LibraryLocked, message));
+            }
+            run_conversion(&database, library_path, &from, &to, jobs, retries).await
+        }
+        #[cfg(feature = "dev")]
+        Command::Dev { command: DevCommand::GenLibrary { books, seed, force } } => {
+            generate_library(library_path, books, seed, force).await
+        }
+    }
+}
+
+/// The book with the given id, failing if there is none
+async fn find_book(database: &Database, id: i32) -> Result<Book, CliError> {
+    let book = database.load_book(id).await.with_context(|| format!("Failed to load book {}", id))?;
+    book.ok_or_else(|| CliError::new(ExitStatus::BookNotFound, format!("No book with id {}", id)))
+}
+
+/// Path of the book's file in `format`, or of its first file
+fn book_file(book: &Book, library_path: &Path, format: Option<&str>) -> Result<PathBuf, CliError> {
+    let chosen = match format {
+        Some(wanted) => book.formats.iter().find(|f| f.format.eq_ignore_ascii_case(wanted)),
+        None => book.formats.first(),
+    };
+    let Some(chosen) = chosen else {
+        let message = match format {
+            Some(wanted) => format!("\"{}\" has no {} file", book.title, wanted.to_uppercase()),
+            None => format!("\"{}\" has no files", book.title),
+        };
+        return Err(CliError::new(ExitStatus::NoResults, message));
+    };
+    Ok(book.format_path(library_path, chosen))
+}
+
+/// How to list books: as `--format` asks, or else like the rest of the output
+fn list_format(format: Option<ListFormat>, output: OutputFormat) -> ListFormat {
+    format.unwrap_or(match output {
+        OutputFormat::Text => ListFormat::Plain,
+        OutputFormat::Json => ListFormat::Json,
+    })
+}
+
+/// Print a listing of books
+///
+/// Stops quietly once stdout is closed, e.g. piped into `head`.
+fn print_books(books: &[Book], format: ListFormat) {
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let _ = output::write_books(&mut out, books, format).and_then(|()| out.flush());
+}
+
+/// Print everything tuilibre knows about a book, a field per line, or as one JSON object
+fn print_book_info(book: &Book, library_path: &Path, output: OutputFormat) {
+    let formats: Vec<(&app::BookFormat, PathBuf)> = book.formats.iter().map(|f| (f, book.format_path(library_path, f))).collect();
+    match output {
+        OutputFormat::Text => {
+            println!("ID:          {}", book.id);
+            println!("Title:       {}", book.title);
+            println!("Authors:     {}", book.author_list());
+            if let Some(series) = book.series_label() {
+                println!("Series:      {}", series);
+            }
+            if !book.tags.is_empty() {
+                println!("Tags:        {}", book.tag_list());
+            }
+            if let Some(stars) = book.stars() {
+                println!("Rating:      {}", stars);
+            }
+            println!("Added:       {}", book.timestamp);
+            println!("Path:        {}", library_path.join(&book.path).display());
+            for (format, path) in &formats {
+                println!("Format:      {}\t{}\t{}", format.format, format.size, path.display());
+            }
+            let description = book.description();
+            if !description.is_empty() {
+                println!();
+                println!("{}", description.join("\n\n"));
+            }
+        }
+        OutputFormat::Json => {
+            let formats: Vec<_> = formats
+                .iter()
+                .map(|(f, path)| serde_json::json!({ "format": f.format, "size": f.size, "path": path }))
+                .collect();
+            let info = serde_json::json!({
+                "id": book.id,
+                "title": book.title,
+                "authors": book.authors,
+                "series": book.series,
+                "series_index": book.series.as_ref().map(|_| book.series_index),
+                "tags": book.tags,
+                "rating": book.rating,
+                "added": book.timestamp,
+                "path": library_path.join(&book.path),
+                "formats": formats,
+                "description": book.description().join("\n\n"),
+            });
+            println!("{}", info);
+        }
+    }
+}
+
+/// Print a path alone, for `$(tuilibre path 42)`, or as JSON
+fn print_path(path: &Path, id: i32, output: OutputFormat) {
+    match output {
+        OutputFormat::Text => println!("{}", path.display()),
+        OutputFormat::Json => println!("{}", serde_json::json!({ "id": id, "path": path })),
+    }
+}
+
+/// Connect to the library's database and take its write lock, failing if there isn't one
+async fn open_database(library_path: &Path) -> Result<Database, CliError> {
+    connect_database(library_path, true).await
+}
+
+/// `open_database` for commands that only read, which leave the lock to whoever writes
+async fn open_read_only(library_path: &Path) -> Result<Database, CliError> {
+    connect_database(library_path, false).await
+}
+
+async fn connect_database(library_path: &Path, lock: bool) -> Result<Database, CliError> {
+    let db_path = library_path.join("metadata.db");
+    if !db_path.exists() {
+        let message = format!("No calibre database found at: {}", db_path.display());
+        return Err(CliError::new(ExitStatus::LibraryNotFound, message));
+    }
+
+    let config = Config::load().unwrap_or_default();
+    let connecting = if lock {
+        Database::open(library_path, &config.database).await
+    } else {
+        Database::open_read_only(library_path, &config.database).await
+    };
+    let database = connecting
+        .with_context(|| format!("Failed to connect to calibre database at: {}", db_path.display()))?;
+    Ok(database)
+}
+
+/// Write a synthetic library, refusing to replace a real one unless forced
+#[cfg(feature = "dev")]
+async fn generate_library(library_path: &Path, books: usize, seed: u64, force: bool) -> Result<(), CliError> {
+    let db_path = library_path.join("metadata.db");
+    if db_path.exists() && !force {
+        let message = format!("{} already exists; pass --force to replace it", db_path.display());
+        return Err(CliError::new(ExitStatus::Failure, message));
+    }
+
+    println!("🔄 Generating {} books in {}", books, library_path.display());
+    tuilibre::fixture::generate_library(library_path, books, seed).await
+        .with_context(|| format!("Failed to generate a library at: {}", library_path.display()))?;
+    println!("✅ Generated {}", db_path.display());
+    Ok(())
+}
+
+/// Poll the library and print the books that come and go, until Ctrl-C or a termination signal
+///
+/// `metadata.db` and its write-ahead log are only read again once one of them changed.
+async fn watch_library(database: &Database, library_path: &Path, interval: Duration, output: OutputFormat) -> Result<(), CliError> {
+    let mut known: HashMap<i32, String> = database
+        .load_books()
+        .await
+        .with_context(|| "Failed to load books from database")?
+        .into_iter()
+        .map(|b| (b.id, b.title))
+        .collect();
+    if output == OutputFormat::Text {
+        eprintln!("👀 Watching {} books in {}, Ctrl-C to stop", known.len(), library_path.display());
+    }
+
+    let shutdown = CancelToken::default();
+    signals::listen_for_shutdown(shutdown.clone());
+    let mut stamp = database_stamp(library_path);
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.cancelled() => return Ok(()),
+        }
+        let current = database_stamp(library_path);
+        if current == stamp {
+            continue;
+        }
+        stamp = current;
+
+        let ids = database.load_book_ids().await.with_context(|| "Failed to read the library's books")?;
+        let mut removed: Vec<i32> = known.keys().filter(|id| !ids.contains(id)).copied().collect();
+        removed.sort_unstable();
+        for id in removed {
+            let title = known.remove(&id).unwrap_or_default();
+            match output {
+                OutputFormat::Text => println!("-\t{}\t{}", id, title),
+                OutputFormat::Json => println!("{}", serde_json::json!({ "event": "removed", "id": id, "title": title })),
+            }
+        }
+
+        let mut added: Vec<i32> = ids.into_iter().filter(|id| !known.contains_key(id)).collect();
+        added.sort_unstable();
+        for id in added {
+            // Gone again before it could be read, e.g. an import that was undone
+            let Some(book) = database.load_book(id).await.with_context(|| format!("Failed to load book {}", id))? else {
+                continue;
+            };
+            match output {
+                OutputFormat::Text => println!("+\t{}\t{}\t{}", book.id, book.title, book.author_list()),
+                OutputFormat::Json => {
+                    let event = serde_json::json!({
+                        "event": "added",
+                        "id": book.id,
+                        "title": book.title,
+                        "authors": book.authors,
+                        "path": library_path.join(&book.path),
+                    });
+                    println!("{}", event);
+                }
+            }
+            known.insert(book.id, book.title);
+        }
+    }
+}
+
+/// Add each file as a new book, printing its id and title; a file that fails doesn't stop the rest
+async fn add_files(database: &Database, library_path: &Path, files: &[PathBuf], output: OutputFormat) -> Result<(), CliError> {
+    let mut failed = 0;
+    for file in files {
+        let (book, cover) = tuilibre::epub::import_metadata(file);
+        match database.add_book(library_path, &book, file, cover.as_deref()).await {
+            Ok(id) => match output {
+                OutputFormat::Text => println!("{}\t{}\t{}", id, book.title, book.authors.join(" & ")),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::json!({ "id": id, "title": book.title, "authors": book.authors, "file": file })
+                ),
+            },
+            Err(e) => {
+                failed += 1;
+                eprintln!("❌ {}: {}", file.display(), e);
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(CliError::new(ExitStatus::Failure, format!("{} of {} files not added", failed, files.len())));
+    }
+    Ok(())
+}
+
+/// Check every file of the library, printing each missing, corrupted or DRM-locked one as it's found
+///
+/// Problems go to stdout, one per line; the running tally goes to stderr when it's a terminal.
+/// DRM alone doesn't fail the check, since the files are intact.
+async fn run_verify(database: &Database, library_path: &Path, workers: usize, output: OutputFormat) -> Result<(), CliError> {
+    let books = database.load_books().await.with_context(|| "Failed to load books from database")?;
+    let books: Vec<&Book> = books.iter().collect();
+    let items = jobs::plan_verify(library_path, &books);
+    if items.is_empty() {
+        return Err(CliError::new(ExitStatus::NoResults, "No book files in this library"));
+    }
+
+    let total = items.len();
+    let progress = std::io::stderr().is_terminal();
+    let cancel = CancelToken::default();
+    signals::listen_for_shutdown(cancel.clone());
+    let (results, mut rx) = mpsc::unbounded_channel();
+    let checking = tokio::spawn(jobs::verify::check_files(items, workers.max(1), results, cancel.clone()));
+
+    let mut tally = VerifyTally::default();
+    while let Some((item, check)) = rx.recv().await {
+        tally.add(&check);
+        let problem = match &check {
+            FileCheck::Ok => None,
+            FileCheck::Protected(scheme) => Some(("drm", scheme.clone())),
+            FileCheck::Missing => Some(("missing", String::new())),
+            FileCheck::Corrupted(reason) => Some(("corrupted", reason.clone())),
+        };
+        if let Some((kind, reason)) = problem {
+            if progress {
+                eprint!("\r\x1b[K");
+            }
+            match output {
+                OutputFormat::Text => {
+                    println!("{}\t{}\t{}\t{}\t{}", kind, item.book_id, item.format, item.title, reason);
+                }
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::json!({
+                        "problem": kind,
+                        "id": item.book_id,
+                        "format": item.format,
+                        "title": item.title,
+                        "path": item.path,
+                        "reason": reason,
+                    })
+                ),
+            }
+        }
+        if progress {
+            eprint!("\r\x1b[K[{}/{}] {}", tally.checked(), total, tally);
+        }
+    }
+    let _ = checking.await;
+    if progress {
+        eprintln!();
+    }
+
+    if cancel.is_cancelled() {
+        Err(CliError::new(ExitStatus::Cancelled, format!("Cancelled after {} of {} files", tally.checked(), total)))
+    } else if tally.missing + tally.corrupted > 0 {
+        let message = format!("{} missing and {} corrupted of {} files", tally.missing, tally.corrupted, total);
+        Err(CliError::new(ExitStatus::Failure, message))
+    } else {
+        if output == OutputFormat::Text && !progress {
+            eprintln!("{}", tally);
+        }
+        Ok(())
+    }
+}
+
+/// Queue and run conversions for every book missing the target format
+async fn run_conversion(
+    database: &Database,
+    library_path: &Path,
+    from: &str,
+    to: &str,
+    jobs: usize,
+    retries: u32,
+) -> Result<(), CliError> {
+    let candidates = database.find_conversion_candidates(from, to).await
+        .with_context(|| "Failed to query books for conversion")?;
+
+    if candidates.is_empty() {
+        let message = format!("No {} books without {} found, nothing to convert", from.to_uppercase(), to.to_uppercase());
+        return Err(CliError::new(ExitStatus::NoResults, message));
+    }
+
+    let mut queue = ConversionQueue::new(jobs).with_retries(retries);
+    for entry in candidates {
+        let book_dir = library_path.join(&entry.path);
+        queue.push(ConversionTask {
+            book_id: entry.book_id,
+            title: entry.title,
+            input: book_dir.join(format!("{}.{}", entry.name, entry.format.to_lowercase())),
+            output: book_dir.join(format!("{}.{}", entry.name, to.to_lowercase())),
+            target_format: to.to_uppercase(),
+        });
+    }
+
+    let total = queue.len();
+    let titles: Vec<String> = queue.tasks().iter().map(|t| t.title.clone()).collect();
+    println!("🔄 Converting {} books from {} to {} with {} workers", total, from.to_uppercase(), to.to_uppercase(), jobs.max(1));
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let printer = tokio::spawn(async move {
+        let mut done = 0;
+        while let Some(event) = rx.recv().await {
+            match event {
+                ConversionEvent::Started { index, attempt: 1 } => {
+                    println!("⏳ Converting: {}", titles[index]);
+                }
+                ConversionEvent::Started { .. } => {}
+                ConversionEvent::Retrying { index, attempt, error } => {
+                    println!("🔁 Retrying ({}): {} - {}", attempt, titles[index], error);
+                }
+                ConversionEvent::Finished { index, duration } => {
+                    done += 1;
+                    println!("[{}/{}] ✅ {} ({:.1}s)", done, total, titles[index], duration.as_secs_f64());
+                }
+                ConversionEvent::Failed { index, error } => {
+                    done += 1;
+                    println!("[{}/{}] ❌ {} - {}", done, total, titles[index], error);
+                }
+            }
+        }
+    });
+
+    // Ctrl-C stops the running converters and skips the rest; finished books are still registered
+    let cancel = CancelToken::default();
+    let interrupt = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            println!("\n⏹ Cancelling, stopping running conversions...");
+            interrupt.cancel();
+        }
+    });
+
+    let summary = queue.run(tx, cancel).await;
+    let _ = printer.await;
+
+    // Register the new files so calibre sees the added format
+    for task in &summary.succeeded {
+        let size = std::fs::metadata(&task.output).map(|m| m.len()).unwrap_or(0);
+        let name = task.output.file_stem().and_then(|n| n.to_str()).unwrap_or_default();
+        if let Err(e) = database.add_format(task.book_id, &task.target_format, name, size).await {
+            eprintln!("Warning: Failed to register {} for {}: {}", task.target_format, task.title, e);
+        }
+    }
+
+    println!("\n📊 Conversion finished in {:.1}s", summary.elapsed.as_secs_f64());
+    println!("   ✅ Converted: {}", summary.succeeded.len());
+    println!("   ❌ Failed:    {}", summary.failed.len());
+    if !summary.cancelled.is_empty() {
+        println!("   ⏹ Cancelled: {}", summary.cancelled.len());
+    }
+    for (task, error) in &summary.failed {
+        println!("      - {}: {}", task.title, error);
+    }
+
+    if !summary.cancelled.is_empty() {
+        Err(CliError::new(ExitStatus::Cancelled, format!("Cancelled with {} conversions left", summary.cancelled.len())))
+    } else if !summary.failed.is_empty() {
+        Err(CliError::new(ExitStatus::Failure, format!("{} of {} conversions failed", summary.failed.len(), total)))
+    } else {
+        Ok(())
+    }
+}
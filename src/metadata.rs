@@ -0,0 +1,221 @@
+use crate::error::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Online source of book metadata
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataSource {
+    OpenLibrary,
+    Google,
+}
+
+impl MetadataSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetadataSource::OpenLibrary => "Open Library",
+            MetadataSource::Google => "Google Books",
+        }
+    }
+
+    /// Search for candidates matching a title and author
+    pub async fn search(&self, title: &str, author: &str) -> Result<Vec<MetadataCandidate>> {
+        let source = *self;
+        let url = match source {
+            MetadataSource::OpenLibrary => format!(
+                "https://openlibrary.org/search.json?title={}&author={}&limit=5&fields=title,author_name,isbn,cover_i,first_sentence",
+                urlencode(title),
+                urlencode(author)
+            ),
+            MetadataSource::Google => format!(
+                "https://www.googleapis.com/books/v1/volumes?q=intitle:{}+inauthor:{}&maxResults=5",
+                urlencode(title),
+                urlencode(author)
+            ),
+        };
+
+        let body = tokio::task::spawn_blocking(move || -> Result<Value> {
+            ureq::get(&url)
+                .call()
+                .with_context(|| format!("{} request failed", source.name()))?
+                .into_json()
+                .with_context(|| format!("Invalid response from {}", source.name()))
+        })
+        .await??;
+
+        Ok(match source {
+            MetadataSource::OpenLibrary => parse_open_library(&body),
+            MetadataSource::Google => parse_google(&body),
+        })
+    }
+}
+
+/// Metadata found for a book by an online source
+#[derive(Debug, Clone, Default)]
+pub struct MetadataCandidate {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub isbn: Option<String>,
+    pub description: Option<String>,
+    pub cover_url: Option<String>,
+}
+
+impl MetadataCandidate {
+    /// Whether the candidate plausibly describes the book with the given title
+    pub fn matches_title(&self, title: &str) -> bool {
+        let normalize = |s: &str| -> String {
+            s.to_lowercase().chars().filter(|c| c.is_alphanumeric()).collect()
+        };
+        let (a, b) = (normalize(&self.title), normalize(title));
+        !a.is_empty() && (a.contains(&b) || b.contains(&a))
+    }
+}
+
+/// A book field that a proposal can change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataField {
+    Title,
+    Authors,
+    Isbn,
+    Description,
+    Cover,
+    Languages,
+    /// A tag to add alongside the existing ones
+    Tag,
+}
+
+impl MetadataField {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MetadataField::Title => "Title",
+            MetadataField::Authors => "Authors",
+            MetadataField::Isbn => "ISBN",
+            MetadataField::Description => "Description",
+            MetadataField::Cover => "Cover",
+            MetadataField::Languages => "Languages",
+            MetadataField::Tag => "Add tag",
+        }
+    }
+}
+
+/// One field's current and proposed value
+#[derive(Debug, Clone)]
+pub struct FieldChange {
+    pub field: MetadataField,
+    pub current: String,
+    /// New value; for covers an image URL or a local file path
+    pub proposed: String,
+    pub accepted: bool,
+}
+
+impl FieldChange {
+    /// A change that fills an empty field, accepted unless the user objects
+    pub fn fill(field: MetadataField, proposed: String) -> Self {
+        FieldChange { field, current: String::new(), proposed, accepted: true }
+    }
+
+    /// A suggestion the user has to opt into, e.g. a tag guessed from the description
+    pub fn suggest(field: MetadataField, proposed: String) -> Self {
+        FieldChange { field, current: String::new(), proposed, accepted: false }
+    }
+
+    /// A change that overwrites an existing value, rejected unless the user opts in
+    pub fn replace(field: MetadataField, current: String, proposed: String) -> Self {
+        FieldChange { field, current, proposed, accepted: false }
+    }
+}
+
+/// Changes proposed for one book, reviewed field by field before being written
+#[derive(Debug, Clone)]
+pub struct MetadataProposal {
+    pub book_id: i32,
+    pub book_title: String,
+    /// Where the proposed values came from, e.g. "Open Library"
+    pub origin: String,
+    pub changes: Vec<FieldChange>,
+}
+
+impl MetadataProposal {
+    pub fn accepted_changes(&self) -> impl Iterator<Item = &FieldChange> {
+        self.changes.iter().filter(|c| c.accepted)
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        if let Some(change) = self.changes.get_mut(index) {
+            change.accepted = !change.accepted;
+        }
+    }
+
+    /// Short list of the accepted fields, e.g. "ISBN, Cover"
+    pub fn summary(&self) -> String {
+        let fields: Vec<&str> = self.accepted_changes().map(|c| c.field.label()).collect();
+        if fields.is_empty() {
+            "nothing accepted".to_string()
+        } else {
+            fields.join(", ")
+        }
+    }
+}
+
+fn parse_open_library(body: &Value) -> Vec<MetadataCandidate> {
+    let Some(docs) = body["docs"].as_array() else {
+        return Vec::new();
+    };
+
+    docs.iter()
+        .map(|doc| MetadataCandidate {
+            title: doc["title"].as_str().unwrap_or_default().to_string(),
+            authors: string_array(&doc["author_name"]),
+            // Prefer ISBN-13 when several editions are listed
+            isbn: string_array(&doc["isbn"])
+                .into_iter()
+                .max_by_key(|isbn| isbn.len()),
+            description: string_array(&doc["first_sentence"]).into_iter().next(),
+            cover_url: doc["cover_i"]
+                .as_i64()
+                .map(|id| format!("https://covers.openlibrary.org/b/id/{}-L.jpg", id)),
+        })
+        .collect()
+}
+
+fn parse_google(body: &Value) -> Vec<MetadataCandidate> {
+    let Some(items) = body["items"].as_array() else {
+        return Vec::new();
+    };
+
+    items
+        .iter()
+        .map(|item| {
+            let info = &item["volumeInfo"];
+            let identifiers = info["industryIdentifiers"].as_array().cloned().unwrap_or_default();
+            let isbn = ["ISBN_13", "ISBN_10"].iter().find_map(|kind| {
+                identifiers
+                    .iter()
+                    .find(|id| id["type"].as_str() == Some(kind))
+                    .and_then(|id| id["identifier"].as_str())
+                    .map(|s| s.to_string())
+            });
+
+            MetadataCandidate {
+                title: info["title"].as_str().unwrap_or_default().to_string(),
+                authors: string_array(&info["authors"]),
+                isbn,
+                description: info["description"].as_str().map(|s| s.to_string()),
+                cover_url: info["imageLinks"]["thumbnail"]
+                    .as_str()
+                    .map(|s| s.replace("http://", "https://")),
+            }
+        })
+        .collect()
+}
+
+fn string_array(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|items| items.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect())
+        .unwrap_or_default()
+}
+
+fn urlencode(value: &str) -> String {
+    percent_encoding::utf8_percent_encode(value, percent_encoding::NON_ALPHANUMERIC).to_string()
+}
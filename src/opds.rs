@@ -0,0 +1,217 @@
+use crate::error::{Context, Error, Result};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+const ATOM_NS: &str = "http://www.w3.org/2005/Atom";
+const ACQUISITION_REL: &str = "http://opds-spec.org/acquisition";
+const IMAGE_REL: &str = "http://opds-spec.org/image";
+
+/// Formats we know how to store, best first, with their MIME types
+const ACQUISITION_TYPES: &[(&str, &str)] = &[
+    ("application/epub+zip", "epub"),
+    ("application/x-mobi8-ebook", "azw3"),
+    ("application/x-mobipocket-ebook", "mobi"),
+    ("application/pdf", "pdf"),
+    ("application/vnd.comicbook+zip", "cbz"),
+    ("application/x-cbz", "cbz"),
+    ("text/plain", "txt"),
+];
+
+/// One page of an OPDS catalog
+#[derive(Debug, Clone, Default)]
+pub struct OpdsFeed {
+    pub title: String,
+    pub url: String,
+    pub entries: Vec<OpdsEntry>,
+    pub next: Option<String>,
+}
+
+/// A navigation link or a downloadable publication in a feed
+#[derive(Debug, Clone, Default)]
+pub struct OpdsEntry {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub summary: Option<String>,
+    pub categories: Vec<String>,
+    pub identifier: Option<String>,
+    /// Link to a sub-feed, for navigation entries
+    pub navigation: Option<String>,
+    pub acquisitions: Vec<OpdsLink>,
+    pub cover: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OpdsLink {
+    pub href: String,
+    pub mime: String,
+}
+
+impl OpdsEntry {
+    /// Best downloadable format, with its file extension
+    pub fn best_acquisition(&self) -> Option<(&OpdsLink, &'static str)> {
+        ACQUISITION_TYPES.iter().find_map(|(mime, ext)| {
+            self.acquisitions
+                .iter()
+                .find(|link| link.mime.split(';').next().unwrap_or("").trim() == *mime)
+                .map(|link| (link, *ext))
+        })
+    }
+
+    pub fn is_navigation(&self) -> bool {
+        self.acquisitions.is_empty() && self.navigation.is_some()
+    }
+}
+
+impl OpdsFeed {
+    /// Root pseudo-feed listing the configured catalogs
+    pub fn catalogs<'a>(catalogs: impl Iterator<Item = (&'a String, &'a String)>) -> Self {
+        OpdsFeed {
+            title: "OPDS Catalogs".to_string(),
+            url: String::new(),
+            entries: catalogs
+                .map(|(name, url)| OpdsEntry {
+                    title: name.clone(),
+                    summary: Some(url.clone()),
+                    navigation: Some(url.clone()),
+                    ..Default::default()
+                })
+                .collect(),
+            next: None,
+        }
+    }
+}
+
+/// Download and parse a feed
+pub async fn fetch_feed(url: &str) -> Result<OpdsFeed> {
+    let url = url.to_string();
+    tokio::task::spawn_blocking(move || {
+        let body = ureq::get(&url)
+            .set("Accept", "application/atom+xml, application/xml")
+            .call()
+            .with_context(|| format!("Failed to fetch {}", url))?
+            .into_string()
+            .with_context(|| format!("Failed to read {}", url))?;
+        parse_feed(&body, &url)
+    })
+    .await?
+}
+
+/// Download `url` into `dir` as `<stem>.<ext>`
+pub async fn download(url: &str, dir: &Path, stem: &str, ext: &str) -> Result<PathBuf> {
+    let url = url.to_string();
+    let target = dir.join(format!("{}.{}", stem, ext));
+    tokio::task::spawn_blocking(move || {
+        let mut bytes = Vec::new();
+        ureq::get(&url)
+            .call()
+            .with_context(|| format!("Failed to download {}", url))?
+            .into_reader()
+            .read_to_end(&mut bytes)
+            .with_context(|| format!("Failed to download {}", url))?;
+        std::fs::write(&target, bytes)
+            .with_context(|| format!("Failed to write {}", target.display()))?;
+        Ok(target)
+    })
+    .await?
+}
+
+/// Fetch a small resource such as a cover image
+pub async fn fetch_bytes(url: &str) -> Result<Vec<u8>> {
+    let url = url.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut bytes = Vec::new();
+        ureq::get(&url)
+            .call()
+            .with_context(|| format!("Failed to fetch {}", url))?
+            .into_reader()
+            .read_to_end(&mut bytes)?;
+        Ok(bytes)
+    })
+    .await?
+}
+
+/// Parse an OPDS 1.x Atom feed, resolving links against `base_url`
+pub fn parse_feed(xml: &str, base_url: &str) -> Result<OpdsFeed> {
+    let doc = roxmltree::Document::parse(xml).with_context(|| "Invalid OPDS feed")?;
+    let root = doc.root_element();
+    if !root.has_tag_name((ATOM_NS, "feed")) {
+        return Err(Error::Other(format!("Not an OPDS feed: {}", base_url)));
+    }
+
+    let base = Url::parse(base_url).ok();
+    let resolve = |href: &str| -> String {
+        base.as_ref()
+            .and_then(|b| b.join(href).ok())
+            .map(|u| u.to_string())
+            .unwrap_or_else(|| href.to_string())
+    };
+
+    let mut feed = OpdsFeed {
+        title: child_text(root, "title").unwrap_or_default(),
+        url: base_url.to_string(),
+        ..Default::default()
+    };
+
+    for node in root.children().filter(|n| n.is_element()) {
+        if node.has_tag_name((ATOM_NS, "link")) && node.attribute("rel") == Some("next") {
+            feed.next = node.attribute("href").map(resolve);
+        }
+
+        if !node.has_tag_name((ATOM_NS, "entry")) {
+            continue;
+        }
+
+        let mut entry = OpdsEntry {
+            title: child_text(node, "title").unwrap_or_default(),
+            summary: child_text(node, "summary").or_else(|| child_text(node, "content")),
+            ..Default::default()
+        };
+
+        for child in node.children().filter(|n| n.is_element()) {
+            match child.tag_name().name() {
+                "author" => {
+                    if let Some(name) = child_text(child, "name") {
+                        entry.authors.push(name);
+                    }
+                }
+                "category" => {
+                    if let Some(label) = child.attribute("label").or(child.attribute("term")) {
+                        entry.categories.push(label.to_string());
+                    }
+                }
+                "identifier" => {
+                    entry.identifier = child.text().map(|t| t.trim().to_string());
+                }
+                "link" => {
+                    let Some(href) = child.attribute("href") else {
+                        continue;
+                    };
+                    let rel = child.attribute("rel").unwrap_or("");
+                    let mime = child.attribute("type").unwrap_or("");
+
+                    if rel.starts_with(ACQUISITION_REL) {
+                        entry.acquisitions.push(OpdsLink { href: resolve(href), mime: mime.to_string() });
+                    } else if rel == IMAGE_REL {
+                        entry.cover = Some(resolve(href));
+                    } else if mime.starts_with("application/atom+xml") {
+                        entry.navigation = Some(resolve(href));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        feed.entries.push(entry);
+    }
+
+    Ok(feed)
+}
+
+fn child_text(node: roxmltree::Node, name: &str) -> Option<String> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name() == name)
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
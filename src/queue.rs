@@ -0,0 +1,137 @@
+//! Actions taken while a library is offline, applied once it can be written again
+//!
+//! The queue is kept with the library's settings, so it survives restarts. Each edit remembers
+//! the book as the snapshot had it, to notice when it was changed elsewhere in the meantime.
+
+use serde::{Deserialize, Serialize};
+
+use crate::app::{parse_authors, Book, PendingEdit, PromptKind};
+use crate::database::Database;
+use crate::error::Result;
+
+/// An action waiting for the library to come back
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedAction {
+    pub kind: QueuedKind,
+    /// Why it wasn't applied when the library came back, e.g. the title was changed elsewhere
+    #[serde(default)]
+    pub held: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QueuedKind {
+    Edit(QueuedEdit),
+    /// Send the books to the first e-reader connected when it is applied
+    Send { book_ids: Vec<i32>, titles: Vec<String> },
+    /// Email the books to the recipients of a configured profile
+    Email { profile: String, book_ids: Vec<i32>, titles: Vec<String> },
+}
+
+/// Which field an edit changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EditKind {
+    Title,
+    /// Separated by "&"
+    Authors,
+    /// Added to the book's tags
+    Tag,
+    /// Removed from the book's tags
+    Untag,
+}
+
+/// An edit to one book, with the book as it was when the edit was made
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedEdit {
+    pub field: EditKind,
+    pub value: String,
+    pub book_id: i32,
+    pub loaded_title: String,
+    pub loaded_authors: Vec<String>,
+    pub loaded_modified: String,
+}
+
+impl QueuedAction {
+    pub fn new(kind: QueuedKind) -> Self {
+        QueuedAction { kind, held: None }
+    }
+
+    /// Queue an edit made from a prompt or the edit form; None for prompts that don't edit a book
+    pub fn edit(edit: &PendingEdit, book: &Book) -> Option<Self> {
+        let field = match edit.kind {
+            PromptKind::EditTitle => EditKind::Title,
+            PromptKind::EditAuthors => EditKind::Authors,
+            PromptKind::AddTag => EditKind::Tag,
+            PromptKind::RemoveTag => EditKind::Untag,
+            _ => return None,
+        };
+        Some(QueuedAction::new(QueuedKind::Edit(QueuedEdit {
+            field,
+            value: edit.input.clone(),
+            book_id: book.id,
+            loaded_title: book.title.clone(),
+            loaded_authors: book.authors.clone(),
+            loaded_modified: book.last_modified.clone(),
+        })))
+    }
+
+    /// One line for the pending actions view, e.g. `Retitle "Dune" as "Dune Messiah"`
+    pub fn describe(&self) -> String {
+        let line = match &self.kind {
+            QueuedKind::Edit(edit) => match edit.field {
+                EditKind::Title => format!("Retitle \"{}\" as \"{}\"", edit.loaded_title, edit.value),
+                EditKind::Authors => format!("Set the authors of \"{}\" to {}", edit.loaded_title, edit.value),
+                EditKind::Tag => format!("Tag \"{}\" with {}", edit.loaded_title, edit.value),
+                EditKind::Untag => format!("Remove {} from the tags of \"{}\"", edit.value, edit.loaded_title),
+            },
+            QueuedKind::Send { titles, .. } => format!("Send {} to the e-reader", describe_titles(titles)),
+            QueuedKind::Email { profile, titles, .. } => format!("Email {} to {}", describe_titles(titles), profile),
+        };
+        match &self.held {
+            Some(reason) => format!("{}  (held: {})", line, reason),
+            None => line,
+        }
+    }
+}
+
+impl QueuedEdit {
+    /// Why the edit would overwrite a change made elsewhere, None if it is safe to write
+    ///
+    /// As when merging a write conflict, only a change to the same field counts; adding a
+    /// tag never conflicts. A deleted book is reported too.
+    pub async fn conflict(&self, database: &Database) -> Result<Option<String>> {
+        let Some((title, modified)) = database.book_revision(self.book_id).await? else {
+            return Ok(Some("the book was deleted".to_string()));
+        };
+        if modified == self.loaded_modified {
+            return Ok(None);
+        }
+        Ok(match self.field {
+            EditKind::Title if title != self.loaded_title => Some(format!("the title was changed to \"{}\"", title)),
+            EditKind::Authors => {
+                let authors = database.book_authors(self.book_id).await?;
+                (authors != self.loaded_authors).then(|| format!("the authors were changed to {}", authors.join(" & ")))
+            }
+            _ => None,
+        })
+    }
+
+    /// Write the edit
+    pub async fn apply(&self, database: &Database) -> Result<()> {
+        match self.field {
+            EditKind::Title => database.set_title(self.book_id, &self.value).await,
+            EditKind::Authors => database.set_authors(self.book_id, &parse_authors(&self.value)).await,
+            EditKind::Tag => database.add_tags(self.book_id, std::slice::from_ref(&self.value)).await,
+            EditKind::Untag => database.remove_tags(self.book_id, std::slice::from_ref(&self.value)).await,
+        }
+    }
+}
+
+/// `"Dune"` for one book, `3 books` for more
+fn describe_titles(titles: &[String]) -> String {
+    match titles {
+        [title] => format!("\"{}\"", title),
+        _ => format!("{} books", titles.len()),
+    }
+}
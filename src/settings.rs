@@ -0,0 +1,120 @@
+use crate::error::{Context, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::app::{Annotation, BookSort, FilterClause, ReadingPosition, ViewMode};
+use crate::queue::QueuedAction;
+use crate::shelves::Shelves;
+
+/// What a library looks like when it is opened
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibrarySettings {
+    pub view: ViewMode,
+    pub sort: BookSort,
+    pub filters: Vec<FilterClause>,
+    /// Name of a calibre virtual library to restrict the list to
+    pub virtual_library: Option<String>,
+    pub shelves: Shelves,
+    /// How often and when each book was opened from tuilibre, keyed by book id
+    pub opens: BTreeMap<i32, OpenRecord>,
+    /// Books being read or finished; unread books aren't listed
+    pub reading: BTreeMap<i32, ReadStatus>,
+    /// Where the built-in reader left each book, keyed by book id
+    pub positions: BTreeMap<i32, ReadingPosition>,
+    /// DRM found on each book's files by the last verify, by format, keyed by book id
+    pub drm: BTreeMap<i32, BTreeMap<String, String>>,
+    /// Bookmarks and highlights made in the built-in reader, keyed by book id
+    pub annotations: BTreeMap<i32, Vec<Annotation>>,
+    /// Searches saved in tuilibre only, by name
+    pub saved_searches: BTreeMap<String, String>,
+    /// Actions taken while the library was offline, applied once it is back
+    pub queued: Vec<QueuedAction>,
+}
+
+/// Where the reader is with a book
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReadStatus {
+    Reading,
+    Read,
+}
+
+impl ReadStatus {
+    pub fn label(self) -> &'static str {
+        match self {
+            ReadStatus::Reading => "Reading",
+            ReadStatus::Read => "Read",
+        }
+    }
+}
+
+/// Times a book was opened and the calibre timestamp of the last time
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OpenRecord {
+    pub count: i64,
+    pub last: String,
+}
+
+/// Per-library settings saved in `~/.config/tuilibre/library_settings.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LibrarySettingsStore {
+    libraries: BTreeMap<PathBuf, LibrarySettings>,
+}
+
+impl LibrarySettingsStore {
+    /// Get the settings file path in user's home directory
+    pub fn get_settings_file_path() -> Result<PathBuf> {
+        let home_dir = dirs::home_dir()
+            .ok_or_else(|| Error::Other("Could not find user home directory".to_string()))?;
+
+        let config_dir = home_dir.join(".config").join("tuilibre");
+        fs::create_dir_all(&config_dir)
+            .with_context(|| format!("Failed to create config directory: {}", config_dir.display()))?;
+
+        Ok(config_dir.join("library_settings.json"))
+    }
+
+    /// Load settings from file, empty if none were saved yet
+    pub fn load() -> Result<Self> {
+        let path = Self::get_settings_file_path()?;
+
+        if path.exists() {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read settings file: {}", path.display()))?;
+
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse settings file: {}", path.display()))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Save settings to file
+    pub fn save(&self) -> Result<()> {
+        let path = Self::get_settings_file_path()?;
+
+        let content = serde_json::to_string_pretty(self)
+            .with_context(|| "Failed to serialize library settings")?;
+
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write settings file: {}", path.display()))?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, library_path: &Path) -> Option<&LibrarySettings> {
+        self.libraries.get(&Self::key(library_path))
+    }
+
+    pub fn set(&mut self, library_path: &Path, settings: LibrarySettings) {
+        self.libraries.insert(Self::key(library_path), settings);
+    }
+
+    /// Libraries are keyed by canonical path so `./Books` and `/home/me/Books` match
+    fn key(library_path: &Path) -> PathBuf {
+        library_path.canonicalize().unwrap_or_else(|_| library_path.to_path_buf())
+    }
+}
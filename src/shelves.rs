@@ -0,0 +1,79 @@
+//! Shelves: named collections of books kept by tuilibre
+//!
+//! With `[shelves] backing = "tag"` or `"column"` every shelf is mirrored into calibre, and
+//! membership is merged both ways each time the shelves are synced.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::config::{ShelfBacking, ShelvesConfig};
+use crate::database::Database;
+use crate::error::Result;
+
+/// Shelves of a library keyed by name
+pub type Shelves = BTreeMap<String, Shelf>;
+
+/// One shelf's books, and what calibre had when the two last agreed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Shelf {
+    pub books: BTreeSet<i32>,
+    /// Membership after the last sync, to tell additions from removals on either side
+    pub synced: BTreeSet<i32>,
+    /// The backing `synced` was taken from, e.g. "tag:shelf:"; a different one means never synced
+    pub synced_with: Option<String>,
+}
+
+impl Shelf {
+    /// Membership after taking both sides' changes since the last sync
+    ///
+    /// A book stays if neither side removed it and is added if either side added it.
+    pub fn merge(&self, remote: &BTreeSet<i32>, backing: &str) -> BTreeSet<i32> {
+        let empty = BTreeSet::new();
+        let synced = if self.synced_with.as_deref() == Some(backing) { &self.synced } else { &empty };
+
+        let kept = self.books.intersection(remote);
+        let added_here = self.books.difference(synced);
+        let added_there = remote.difference(synced);
+        kept.chain(added_here).chain(added_there).copied().collect()
+    }
+}
+
+impl ShelvesConfig {
+    /// Names the calibre side shelves are synced with, `None` when they are local only
+    pub fn backing_key(&self) -> Option<String> {
+        match self.backing {
+            ShelfBacking::Local => None,
+            ShelfBacking::Tag => Some(format!("tag:{}", self.tag_prefix)),
+            ShelfBacking::Column => Some(format!("column:{}", self.column.trim_start_matches('#'))),
+        }
+    }
+}
+
+/// Merge `shelves` with calibre's copy and write the result to both
+///
+/// Shelves only calibre has are picked up. Returns the books whose calibre membership changed.
+pub async fn sync_shelves(database: &Database, config: &ShelvesConfig, shelves: &mut Shelves) -> Result<Vec<i32>> {
+    let Some(backing) = config.backing_key() else {
+        return Ok(Vec::new());
+    };
+    let mut remote = database.load_shelf_members(config).await?;
+    for name in remote.keys() {
+        shelves.entry(name.clone()).or_default();
+    }
+
+    let mut changed = BTreeSet::new();
+    for (name, shelf) in shelves.iter_mut() {
+        let there = remote.remove(name).unwrap_or_default();
+        let merged = shelf.merge(&there, &backing);
+        let add: Vec<i32> = merged.difference(&there).copied().collect();
+        let remove: Vec<i32> = there.difference(&merged).copied().collect();
+        database.write_shelf_members(config, name, &add, &remove).await?;
+        changed.extend(add.into_iter().chain(remove));
+
+        shelf.books = merged.clone();
+        shelf.synced = merged;
+        shelf.synced_with = Some(backing.clone());
+    }
+    Ok(changed.into_iter().collect())
+}
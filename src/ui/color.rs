@@ -0,0 +1,121 @@
+//! Terminal color support and mapping theme colors down to what the terminal can show
+
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// How many colors the terminal can display
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorSupport {
+    #[serde(rename = "truecolor")]
+    TrueColor,
+    #[serde(rename = "256")]
+    Ansi256,
+    #[serde(rename = "16")]
+    Ansi16,
+}
+
+/// xterm's values for the 16 named colors, in palette order
+const ANSI_PALETTE: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Channel levels of the 6x6x6 cube in the 256-color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorSupport {
+    /// Guess from the environment: `COLORTERM` for truecolor, `TERM` for 256 colors, otherwise 16
+    ///
+    /// Checked once per run; SSH passes `TERM` through, so remote sessions get the local terminal's answer.
+    pub fn detect() -> Self {
+        static DETECTED: OnceLock<ColorSupport> = OnceLock::new();
+        *DETECTED.get_or_init(|| {
+            let colorterm = std::env::var("COLORTERM").unwrap_or_default().to_lowercase();
+            let term = std::env::var("TERM").unwrap_or_default().to_lowercase();
+
+            if colorterm == "truecolor" || colorterm == "24bit" || term.contains("direct") {
+                ColorSupport::TrueColor
+            } else if term.contains("256color") {
+                ColorSupport::Ansi256
+            } else {
+                ColorSupport::Ansi16
+            }
+        })
+    }
+
+    /// The closest color this terminal can show; named colors always pass through
+    pub fn map(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorSupport::TrueColor, _) => color,
+            (ColorSupport::Ansi256, Color::Rgb(r, g, b)) => Color::Indexed(nearest_indexed((r, g, b))),
+            (ColorSupport::Ansi16, Color::Rgb(r, g, b)) => nearest_named((r, g, b)),
+            (ColorSupport::Ansi16, Color::Indexed(i)) => nearest_named(indexed_rgb(i)),
+            _ => color,
+        }
+    }
+}
+
+/// Closest entry of the 256-color cube or grayscale ramp
+fn nearest_indexed(rgb: (u8, u8, u8)) -> u8 {
+    let level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - c as i32).abs())
+            .unwrap_or(0)
+    };
+    let (r, g, b) = (level(rgb.0), level(rgb.1), level(rgb.2));
+    let cube = 16 + 36 * r as u8 + 6 * g as u8 + b as u8;
+
+    let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let gray = 232 + ((average.saturating_sub(8)) / 10).min(23) as u8;
+
+    if distance(rgb, indexed_rgb(gray)) < distance(rgb, indexed_rgb(cube)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+/// Closest of the 16 named colors
+fn nearest_named(rgb: (u8, u8, u8)) -> Color {
+    ANSI_PALETTE
+        .iter()
+        .min_by_key(|(_, value)| distance(rgb, *value))
+        .map(|(color, _)| *color)
+        .unwrap_or(Color::White)
+}
+
+/// RGB value of a 256-color palette index
+fn indexed_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_PALETTE[index as usize].1,
+        16..=231 => {
+            let i = index - 16;
+            (CUBE_LEVELS[(i / 36) as usize], CUBE_LEVELS[(i / 6 % 6) as usize], CUBE_LEVELS[(i % 6) as usize])
+        }
+        _ => {
+            let level = 8 + (index - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+/// Squared distance, weighted toward green as the eye is
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    2 * d(a.0, b.0) + 4 * d(a.1, b.1) + 3 * d(a.2, b.2)
+}
@@ -1,213 +1,1852 @@
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect},
-    style::{Color, Style},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    widgets::{block::Title, Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState},
     Frame,
 };
 
-use crate::app::{App, AppMode};
-use crate::ui::selector::{LibrarySelector, LibraryInfo};
+use crate::app::audiobook::{audio_duration, format_duration, AUDIO_FORMATS};
+use crate::app::reader::{phrase_positions, wrap};
+use crate::app::tag_browser::is_tag_filtered;
+use crate::app::{action, format_size, ComicArchive, COMIC_FORMATS, hint_labels, App, AppMode, Book, CleanupSection, DeleteConfirm, DetailsTab, EditField, PolicyBreach, Menu, Prompt, ScrollView, SearchError, TableColumn, TagBrowser, ViewMode, INDEX_LETTERS};
+use crate::config::QuickFiltersConfig;
+use crate::database::content::{MATCH_END, MATCH_START};
+use crate::ui::dashboard::{Dashboard, DIGIT_JUMPS};
+use crate::ui::layout::{Density, LayoutManager};
+use crate::ui::selector::LibrarySelector;
+use crate::ui::theme::Theme;
+use crate::ui::color::ColorSupport;
+use crate::ui::cover::{CoverImage, CoverProtocol};
+use crate::error::Result;
+use crate::utils::text::strip_emoji;
+use crate::utils::time::format_time;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Widest the reader sets its text, in columns
+const READER_WIDTH: u16 = 80;
+
+/// Cells of the bar showing how far a running job got
+const PROGRESS_BAR_WIDTH: usize = 12;
 
 /// UI component renderer
-pub struct UIComponents;
+pub struct UIComponents {
+    /// Where the index strip was last drawn, for mouse clicks
+    index_strip: Option<Rect>,
+    /// Where each filter chip was last drawn, for mouse clicks
+    filter_chips: Vec<Rect>,
+    /// Where each visible book was last drawn and its index in the list, for mouse clicks
+    book_cells: Vec<(Rect, usize)>,
+    /// Last cover loaded and the image, None when it couldn't be decoded
+    cover: Option<(PathBuf, Option<CoverImage>)>,
+    /// Where the details view left room for a cover drawn with a graphics protocol, and which
+    cover_placement: Option<(PathBuf, Rect)>,
+    /// Last comic archive listed for the details view, None when it couldn't be read
+    comic: Option<(PathBuf, Option<ComicArchive>)>,
+    /// Last audio file measured for the details view, None when its length couldn't be read
+    audio: Option<(PathBuf, Option<Duration>)>,
+}
+
+impl Default for UIComponents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UIComponents {
+    pub fn new() -> Self {
+        UIComponents {
+            index_strip: None,
+            filter_chips: Vec::new(),
+            book_cells: Vec::new(),
+            cover: None,
+            cover_placement: None,
+            comic: None,
+            audio: None,
+        }
+    }
+
+    /// Render title bar
+    pub fn render_title_bar(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        if app.mode == AppMode::Search {
+            self.render_search_bar(frame, area, app);
+            return;
+        }
+
+        let locale = app.locale();
+        let title = if let (AppMode::Opds, Some(page)) = (&app.mode, app.opds_view.as_ref().and_then(|v| v.current())) {
+            format!("OPDS - {} ({} entries)", page.feed.title, page.feed.entries.len())
+        } else if app.mode == AppMode::Review {
+            let batch = app.review.batch(app.config.metadata.batch_size).len();
+            format!("Metadata review - {} proposals ({} in this batch)", app.review.proposals.len(), batch)
+        } else if let (AppMode::Jobs, Some(view)) = (&app.mode, &app.jobs_view) {
+            format!("Job history - {} jobs", view.records.len())
+        } else if app.mode == AppMode::Usage {
+            format!("Disk usage - {} marked", app.selected_ids.len())
+        } else if let (AppMode::FormatPolicy, Some(audit)) = (&app.mode, &app.policy_audit) {
+            let marked = audit.violations.iter().filter(|v| v.marked).count();
+            format!("Format policy - {} violations ({} marked)", audit.violations.len(), marked)
+        } else if let (AppMode::MetadataAudit, Some(audit)) = (&app.mode, &app.metadata_audit) {
+            let marked = audit.mismatches.iter().filter(|m| m.marked).count();
+            format!("Metadata audit - {} files differ ({} marked)", audit.mismatches.len(), marked)
+        } else if let (AppMode::Cleanup, Some(view)) = (&app.mode, &app.cleanup_view) {
+            let marked = view.lists.iter().flatten().filter(|item| item.marked).count();
+            format!("Cleanup suggestions - {} marked", marked)
+        } else if let (AppMode::Device, Some(view)) = (&app.mode, &app.device_view) {
+            format!("{} - {} books on device ({})",
+                view.device.profile.name,
+                view.books.len(),
+                view.device.mount_point.display()
+            )
+        } else {
+            let mut library = match &app.library_alias {
+                Some(alias) => format!("tuilibre · {}", alias),
+                None => "tuilibre".to_string(),
+            };
+            if let Some(vl) = &app.virtual_library {
+                library.push_str(&format!(" [{}]", vl.name));
+            }
+            if app.snapshot.is_some() {
+                library.push_str(" (offline snapshot)");
+            } else if app.read_only.is_some() {
+                library.push_str(" (read-only)");
+            }
+            if app.selected_ids.is_empty() {
+                format!("{} - {} books", library, locale.format_number(app.books.len()))
+            } else {
+                format!("{} - {} books ({} marked)", library, locale.format_number(app.books.len()), app.selected_ids.len())
+            }
+        };
+
+        let title = if app.accessible() { strip_emoji(&title) } else { title };
+        let title_widget = Paragraph::new(title)
+            .style(Style::default().fg(theme.accent))
+            .block(app.density().bar_block());
+
+        frame.render_widget(title_widget, area);
+    }
+
+    /// Render the query being typed with the result count and search time
+    fn render_search_bar(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let mut spans = vec![Span::styled("Search: ", Style::default().fg(theme.accent))];
+        for layer in &app.search_layers {
+            spans.push(Span::styled(format!("{} › ", layer), Style::default().fg(theme.dim)));
+        }
+        spans.push(Span::styled(app.search_query.clone(), Style::default().fg(theme.accent)));
+        if app.search_full_text {
+            spans.push(Span::styled("  [full text]", Style::default().fg(theme.dim)));
+        } else if app.search_plain {
+            spans.push(Span::styled("  [plain text]", Style::default().fg(theme.dim)));
+        }
+
+        if let Some(stats) = &app.search_stats {
+            let style = if stats.count == 0 {
+                Style::default().fg(theme.bad).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(theme.dim)
+            };
+            spans.push(Span::raw("  "));
+            spans.push(Span::styled(stats.describe(&app.locale()), style));
+            // Only the books loaded so far are searched until the rest of the library is in
+            if let Some(pages) = &app.pages {
+                let locale = app.locale();
+                spans.push(Span::styled(
+                    format!("  · searched {} of {} books, still loading", locale.format_number(pages.loaded), locale.format_number(pages.total)),
+                    Style::default().fg(theme.label),
+                ));
+            }
+        }
+
+        let border_style = match &app.search_stats {
+            Some(stats) if stats.count == 0 => Style::default().fg(theme.bad),
+            _ => Style::default(),
+        };
+        let search_bar = Paragraph::new(Line::from(spans))
+            .block(app.density().bar_block().border_style(border_style));
+
+        frame.render_widget(search_bar, area);
+    }
+
+    /// Render why the search doesn't parse, with a caret under the offending term
+    pub fn render_search_error(&self, frame: &mut Frame, area: Rect, bar_area: Rect, app: &App, error: &SearchError) {
+        let theme = app.theme();
+        // Where the query starts in the search bar: after the prompt and any narrowed searches
+        let mut before = String::from("Search: ");
+        for layer in &app.search_layers {
+            before.push_str(&format!("{} › ", layer));
+        }
+        before.extend(app.search_query.chars().take(error.position));
+        let inner = app.density().bar_block().inner(bar_area);
+        let indent = inner.x.saturating_sub(area.x) as usize + Span::raw(before).width();
+
+        let line = Line::from(vec![
+            Span::raw(" ".repeat(indent)),
+            Span::styled("^ ", Style::default().fg(theme.bad).add_modifier(Modifier::BOLD)),
+            Span::styled(error.message.clone(), Style::default().fg(theme.bad)),
+            Span::styled("  (Ctrl-t: plain text)", Style::default().fg(theme.dim)),
+        ]);
+        frame.render_widget(Paragraph::new(line), area);
+    }
+
+    /// Render the quick filters with their function keys, the active one highlighted
+    pub fn render_quick_filter_bar(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        if area.height == 0 {
+            return;
+        }
+
+        let active = app.active_quick_filter();
+        let mut spans = Vec::new();
+        for (i, quick) in app.config.quick_filters.filters.iter().take(QuickFiltersConfig::MAX).enumerate() {
+            let style = if active == Some(i) {
+                Style::default().bg(theme.accent).fg(theme.on_color)
+            } else {
+                Style::default().fg(theme.dim)
+            };
+            spans.push(Span::styled(format!("F{}", i + 1), style.add_modifier(Modifier::BOLD)));
+            spans.push(Span::styled(format!(" {} ", quick.name), style));
+            spans.push(Span::raw(" "));
+        }
+
+        frame.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+
+    /// Render active filters as numbered chips; `2x` or a click removes one, `2!` negates it
+    pub fn render_filter_chips(&mut self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        self.filter_chips.clear();
+        if area.height == 0 {
+            return;
+        }
+
+        let mut spans = Vec::new();
+        let mut x = area.x;
+        for (i, filter) in app.filters.iter().enumerate() {
+            let chip = if app.accessible() {
+                format!(" {}:{} ", i + 1, filter.label())
+            } else {
+                format!(" {}:{} ✕ ", i + 1, filter.label())
+            };
+            let width = chip.chars().count() as u16;
+            self.filter_chips.push(Rect { x, y: area.y, width, height: 1 });
+            let color = if filter.negated { theme.bad } else { theme.dim };
+            spans.push(Span::styled(chip, Style::default().bg(color).fg(theme.on_color)));
+            spans.push(Span::raw(" "));
+            x += width + 1;
+        }
+
+        frame.render_widget(Paragraph::new(Line::from(spans)), area);
+    }
+
+    /// Index of the filter chip at a screen position
+    pub fn filter_chip_hit(&self, column: u16, row: u16) -> Option<usize> {
+        self.filter_chips.iter().position(|chip| {
+            row == chip.y && column >= chip.x && column < chip.x + chip.width
+        })
+    }
+
+    /// Render the book list in the current view, with the A-Z strip beside it
+    pub fn render_book_list(&mut self, frame: &mut Frame, area: Rect, app: &App) {
+        let panes = LayoutManager::create_book_list_layout(area, app.tag_browser.is_some() && !app.accessible());
+        if let Some(browser) = app.tag_browser.as_ref().filter(|_| !app.accessible()) {
+            self.render_tag_browser(frame, panes[0], app, browser);
+        }
+        let area = panes[1];
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Min(0),     // Books
+                Constraint::Length(3),  // A-Z index strip
+            ])
+            .split(area);
+
+        self.book_cells.clear();
+        if app.accessible() {
+            // One plain column: no index strip, no table or grid
+            self.index_strip = None;
+            self.render_book_rows(frame, area, app);
+            return;
+        }
+        match app.view {
+            ViewMode::List => self.render_book_rows(frame, chunks[0], app),
+            ViewMode::Table => self.render_book_table(frame, chunks[0], app),
+            ViewMode::Grid => self.render_book_grid(frame, chunks[0], app),
+        }
+
+        self.render_index_strip(frame, chunks[1], app);
+        self.render_hints(frame, app);
+    }
+
+    /// The tag pane: each tag with its count, the ones filtered by in the accent colour
+    fn render_tag_browser(&self, frame: &mut Frame, area: Rect, app: &App, browser: &TagBrowser) {
+        let theme = app.theme();
+        let items: Vec<ListItem> = browser.tags
+            .iter()
+            .map(|tag| {
+                let style = if is_tag_filtered(&app.filters, &tag.name) {
+                    Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default()
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(tag.name.clone(), style),
+                    Span::styled(format!(" {}", tag.count), Style::default().fg(theme.dim)),
+                ]))
+            })
+            .collect();
+
+        let mut block = app.density().block("Tags");
+        if browser.focused {
+            block = block.border_style(Style::default().fg(theme.focus));
+        }
+        let mut list = List::new(items).block(block);
+        if browser.focused {
+            list = list.highlight_style(theme.selected()).highlight_symbol(theme.selection_symbol());
+        }
+
+        let mut state = ListState::default().with_offset(app.scroll_state(ScrollView::Tags).offset);
+        state.select(Some(browser.selected));
+        frame.render_stateful_widget(list, area, &mut state);
+        app.record_scroll(ScrollView::Tags, browser.selected, state.offset());
+    }
+
+    /// Draw a hint over the start of each visible book that still matches what was typed
+    fn render_hints(&self, frame: &mut Frame, app: &App) {
+        let Some(typed) = &app.hint_input else {
+            return;
+        };
+        let theme = app.theme();
+        let style = Style::default().bg(theme.focus).fg(theme.on_color).add_modifier(Modifier::BOLD);
+        for ((cell, _), label) in self.book_cells.iter().zip(hint_labels(self.book_cells.len())) {
+            if !label.starts_with(typed.as_str()) || cell.width < 2 {
+                continue;
+            }
+            let area = Rect { x: cell.x, y: cell.y, width: 2, height: 1 };
+            frame.render_widget(Paragraph::new(label).style(style), area);
+        }
+    }
+
+    /// Indices of the books drawn on screen, top to bottom, in the order hints are given out
+    pub fn visible_books(&self) -> Vec<usize> {
+        self.book_cells.iter().map(|(_, index)| *index).collect()
+    }
+
+    /// One line per book: title, authors and path
+    fn render_book_rows(&mut self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let range = app.pending_range();
+        let items: Vec<ListItem> = app.books
+            .iter()
+            .enumerate()
+            .map(|(i, book)| {
+                let style = if i == app.selected_book_index {
+                    theme.selected()
+                } else {
+                    Style::default()
+                };
+
+                let path_display = if book.path.chars().count() > 30 {
+                    let chars: Vec<char> = book.path.chars().collect();
+                    format!("...{}", chars.iter().skip(chars.len().saturating_sub(27)).collect::<String>())
+                } else {
+                    book.path.clone()
+                };
+
+                if app.accessible() {
+                    let mut content = format!("{} by {}", book.title, book.author_list());
+                    if let Some(series) = book.series_label() {
+                        content.push_str(&format!(", {}", series));
+                    }
+                    if let Some(snippet) = app.fts_snippets.get(&book.id) {
+                        content.push_str(&format!(", found in {}", snippet));
+                    } else if let Some(found) = app.content_matches.get(&book.id) {
+                        content.push_str(&format!(", found in {}: {}", found.location(), found.plain_snippet()));
+                    }
+                    if app.is_selected(book) {
+                        content.push_str(", marked");
+                    }
+                    if app.on_device.contains(&book.id) {
+                        content.push_str(", on device");
+                    }
+                    if book.is_audiobook() {
+                        content.push_str(", audiobook");
+                    }
+                    if book.has_drm() {
+                        content.push_str(", DRM protected");
+                    }
+                    for gap in book.gaps() {
+                        content.push_str(", ");
+                        content.push_str(gap.description());
+                    }
+                    return ListItem::new(content).style(style);
+                }
+
+                let marker = format!("{:<2}", app.selection_marker(i, book, range));
+                let mut row_markers = if app.on_device.contains(&book.id) { " 📱" } else { "" }.to_string();
+                if book.is_audiobook() {
+                    row_markers.push_str(" 🎧");
+                }
+                if book.has_drm() {
+                    row_markers.push_str(" 🔒");
+                }
+
+                // Grouped, a book following one of its series only repeats its number
+                let grouped = app.config.display.group_series && i > 0 && book.same_series(&app.books[i - 1]);
+                let (indent, series) = match book.series_label() {
+                    Some(_) if grouped => ("  ↳ ", format!(" (#{})", book.series_index)),
+                    Some(label) => ("", format!(" ({})", label)),
+                    None => ("", String::new()),
+                };
+
+                // A full-text or content match shows where it was found in place of the path
+                let snippet = app.fts_snippets.get(&book.id);
+                let found = app.content_matches.get(&book.id);
+                let title = format!("{}{}{}", marker, indent, book.display_title());
+                let content = match (snippet, found) {
+                    (None, None) => format!(" - {} [{}]{}", book.author_list(), path_display, row_markers),
+                    _ => format!(" - {}{}", book.author_list(), row_markers),
+                };
+
+                let mut spans = vec![
+                    Span::raw(title),
+                    Span::styled(series, Style::default().fg(theme.label)),
+                    Span::raw(content),
+                ];
+                if let Some(snippet) = snippet {
+                    spans.push(Span::styled(format!("  “{}”", snippet), Style::default().fg(theme.dim)));
+                } else if let Some(found) = found {
+                    let dim = Style::default().fg(theme.dim);
+                    spans.push(Span::styled("  “", dim));
+                    spans.extend(snippet_spans(&found.snippet, dim, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)));
+                    spans.push(Span::styled("”", dim));
+                }
+                spans.push(Span::styled(gap_markers(book), Style::default().fg(theme.dim)));
+                let line = Line::from(spans);
+                ListItem::new(line).style(style)
+            })
+            .collect();
+
+        let block = app.density().block(app.book_list_title());
+        let inner = block.inner(area);
+        let list = List::new(items)
+            .block(block)
+            .highlight_symbol(theme.selection_symbol());
+
+        let scroll_view = app.book_scroll_view();
+        let mut list_state = ListState::default().with_offset(app.scroll_state(scroll_view).offset);
+        list_state.select(Some(app.selected_book_index));
+
+        frame.render_stateful_widget(list, area, &mut list_state);
+        app.record_scroll(scroll_view, app.selected_book_index, list_state.offset());
+        self.record_rows(inner, 0, list_state.offset(), app.books.len());
+    }
+
+    /// Columns for title, authors, format, tags and date added
+    fn render_book_table(&mut self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let columns = &app.table_columns;
+        let range = app.pending_range();
+        let header = Row::new(std::iter::once("").chain(columns.iter().map(|c| c.header())))
+            .style(Style::default().fg(theme.label).add_modifier(Modifier::BOLD));
+
+        let rows: Vec<Row> = app.books
+            .iter()
+            .enumerate()
+            .map(|(i, book)| {
+                let marker = app.selection_marker(i, book, range);
+                let cells = columns.iter().map(|column| match column {
+                    TableColumn::Title => Cell::from(Line::from(vec![
+                        Span::raw(book.title.clone()),
+                        Span::styled(gap_markers(book), Style::default().fg(theme.dim)),
+                    ])),
+                    TableColumn::Authors => Cell::from(book.author_list()),
+                    TableColumn::Formats => Cell::from(book.format_list()),
+                    TableColumn::Tags => Cell::from(book.tag_list()),
+                    TableColumn::Added => Cell::from(app.format_added(book)),
+                    TableColumn::Modified => Cell::from(app.format_timestamp(&book.last_modified)),
+                });
+                Row::new(std::iter::once(Cell::from(marker)).chain(cells))
+            })
+            .collect();
+        let widths: Vec<Constraint> = std::iter::once(Constraint::Length(1))
+            .chain(columns.iter().map(|column| match column {
+                TableColumn::Title => Constraint::Percentage(35),
+                TableColumn::Authors => Constraint::Percentage(25),
+                TableColumn::Formats => Constraint::Length(6),
+                TableColumn::Tags => Constraint::Percentage(15),
+                TableColumn::Added | TableColumn::Modified => Constraint::Length(16),
+            }))
+            .collect();
+
+        let block = app.density().block(app.book_list_title());
+        let inner = block.inner(area);
+        let table = Table::new(rows)
+            .header(header)
+            .block(block)
+            .widths(&widths)
+            .highlight_style(theme.selected())
+            .highlight_symbol(theme.selection_symbol());
+
+        let scroll_view = app.book_scroll_view();
+        let mut state = TableState::default().with_offset(app.scroll_state(scroll_view).offset);
+        state.select(Some(app.selected_book_index));
+
+        frame.render_stateful_widget(table, area, &mut state);
+        app.record_scroll(scroll_view, app.selected_book_index, state.offset());
+        self.record_rows(inner, 1, state.offset(), app.books.len());
+    }
+
+    /// Remember the one-line rows drawn inside a pane, below `header` lines
+    fn record_rows(&mut self, inner: Rect, header: u16, offset: usize, len: usize) {
+        for line in 0..inner.height.saturating_sub(header) {
+            let index = offset + line as usize;
+            if index >= len {
+                break;
+            }
+            let row = Rect { x: inner.x, y: inner.y + header + line, width: inner.width, height: 1 };
+            self.book_cells.push((row, index));
+        }
+    }
+
+    /// Index of the book drawn at a screen position
+    pub fn book_hit(&self, column: u16, row: u16) -> Option<usize> {
+        self.book_cells
+            .iter()
+            .find(|(cell, _)| {
+                column >= cell.x && column < cell.x + cell.width && row >= cell.y && row < cell.y + cell.height
+            })
+            .map(|(_, index)| *index)
+    }
+
+    /// Cards of title and authors, scrolled so the selected card is visible
+    fn render_book_grid(&mut self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let range = app.pending_range();
+        const CARD_WIDTH: u16 = 28;
+        const CARD_HEIGHT: u16 = 4;
+
+        let density = app.density();
+        let block = density.block(app.book_list_title());
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        // Without borders a card is its two lines and a blank one, and selection shows as a background
+        let bordered = density.card_borders() != Borders::NONE;
+        let card_height = if bordered { CARD_HEIGHT } else { 3 };
+
+        let columns = (inner.width / CARD_WIDTH).max(1) as usize;
+        let visible_rows = (inner.height / card_height).max(1) as usize;
+        // Keep the rows scrolled to last time unless the selected card has left them
+        let scroll_view = app.book_scroll_view();
+        let selected_row = app.selected_book_index / columns;
+        let first_row = (app.scroll_state(scroll_view).offset / columns)
+            .clamp(selected_row.saturating_sub(visible_rows - 1), selected_row);
+        app.record_scroll(scroll_view, app.selected_book_index, first_row * columns);
+
+        let visible = app.books
+            .iter()
+            .enumerate()
+            .skip(first_row * columns)
+            .take(visible_rows * columns);
+        for (i, book) in visible {
+            let slot = i - first_row * columns;
+            let card = Rect {
+                x: inner.x + (slot % columns) as u16 * CARD_WIDTH,
+                y: inner.y + (slot / columns) as u16 * card_height,
+                width: (CARD_WIDTH - u16::from(!bordered)).min(inner.width),
+                height: card_height.min(inner.height),
+            };
+
+            let border_style = if i == app.selected_book_index {
+                Style::default().fg(theme.focus).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(theme.dim)
+            };
+            let marker = match app.selection_marker(i, book, range) {
+                "" => String::new(),
+                marker => format!("{} ", marker),
+            };
+            let lines = vec![
+                Line::from(Span::styled(
+                    format!("{}{}", marker, book.title),
+                    Style::default().add_modifier(Modifier::BOLD),
+                )),
+                Line::from(vec![
+                    Span::styled(book.author_list(), Style::default().fg(theme.muted)),
+                    Span::styled(gap_markers(book), Style::default().fg(theme.dim)),
+                ]),
+            ];
+
+            let mut widget = Paragraph::new(lines)
+                .block(Block::default().borders(density.card_borders()).border_style(border_style));
+            if !bordered && i == app.selected_book_index {
+                widget = widget.style(theme.selected());
+            }
+            frame.render_widget(widget, card);
+            self.book_cells.push((card, i));
+        }
+    }
+
+    /// Render the A-Z strip, dimming empty sections and highlighting the current one
+    fn render_index_strip(&mut self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let present: Vec<char> = app.books.iter().map(|b| b.index_letter()).collect();
+        let current = app.get_selected_book().map(|b| b.index_letter());
+
+        let lines: Vec<Line> = (0..area.height)
+            .filter_map(|row| index_letter_at(area, row))
+            .map(|letter| {
+                let style = if Some(letter) == current {
+                    theme.selected()
+                } else if present.contains(&letter) {
+                    Style::default().fg(theme.accent)
+                } else {
+                    Style::default().fg(theme.dim)
+                };
+                let marker = if Some(letter) == current && theme.selection_marker { '▶' } else { ' ' };
+                Line::from(Span::styled(format!("{}{} ", marker, letter), style))
+            })
+            .collect();
+
+        frame.render_widget(Paragraph::new(lines), area);
+        self.index_strip = Some(area);
+    }
+
+    /// Letter of the index strip at a screen position, if the strip is there
+    pub fn index_strip_hit(&self, column: u16, row: u16) -> Option<char> {
+        let area = self.index_strip?;
+        if column < area.x || column >= area.x + area.width || row < area.y {
+            return None;
+        }
+        index_letter_at(area, row - area.y)
+    }
+
+    /// Where a cover is to be drawn with a graphics protocol this frame, with the image
+    pub fn cover_placement(&self) -> Option<(&Path, Rect, &CoverImage)> {
+        let (path, area) = self.cover_placement.as_ref()?;
+        match &self.cover {
+            Some((loaded, Some(image))) if loaded == path => Some((path.as_path(), *area, image)),
+            _ => None,
+        }
+    }
+
+    /// Leave no room for a graphics cover, e.g. under a popup it would hide
+    pub fn clear_cover_placement(&mut self) {
+        self.cover_placement = None;
+    }
+
+    /// The image cached under `key`, decoded with `load` once and kept until another is shown
+    fn load_cover(&mut self, key: &Path, load: impl FnOnce() -> Result<CoverImage>) -> Option<&CoverImage> {
+        if self.cover.as_ref().map(|(loaded, _)| loaded.as_path()) != Some(key) {
+            let image = load().map_err(|e| tracing::debug!("No cover preview: {}", e)).ok();
+            self.cover = Some((key.to_path_buf(), image));
+        }
+        self.cover.as_ref().and_then(|(_, image)| image.as_ref())
+    }
+
+    /// Page `index` of a comic, decoded once and kept like a cover
+    fn load_page(&mut self, archive: &ComicArchive, index: usize) -> Option<&CoverImage> {
+        let what = format!("page {} of {}", index + 1, archive.path.display());
+        self.load_cover(&archive.page_key(index), || CoverImage::decode(archive.page_bytes(index)?, &what))
+    }
+
+    /// The pages of the book's comic file, listed once and kept until another book is shown
+    fn load_comic(&mut self, book: &Book, library_path: &Path) -> Option<&ComicArchive> {
+        let format = COMIC_FORMATS
+            .iter()
+            .find_map(|wanted| book.formats.iter().find(|f| f.format.eq_ignore_ascii_case(wanted)))?;
+        let path = book.format_path(library_path, format);
+        if self.comic.as_ref().map(|(loaded, _)| loaded.as_path()) != Some(path.as_path()) {
+            let archive = ComicArchive::open(&path).map_err(|e| tracing::debug!("No page count: {}", e)).ok();
+            self.comic = Some((path, archive));
+        }
+        self.comic.as_ref().and_then(|(_, archive)| archive.as_ref())
+    }
+
+    /// How long the book's audio file plays, read once and kept until another book is shown
+    fn load_duration(&mut self, book: &Book, library_path: &Path) -> Option<Duration> {
+        let format = AUDIO_FORMATS
+            .iter()
+            .find_map(|wanted| book.formats.iter().find(|f| f.format.eq_ignore_ascii_case(wanted)))?;
+        let path = book.format_path(library_path, format);
+        if self.audio.as_ref().map(|(loaded, _)| loaded.as_path()) != Some(path.as_path()) {
+            let duration = audio_duration(&path).map_err(|e| tracing::debug!("No duration: {}", e)).ok();
+            self.audio = Some((path, duration));
+        }
+        self.audio.as_ref().and_then(|(_, duration)| *duration)
+    }
+
+    /// Render book details, with the cover beside them when the book has one; a comic without
+    /// one shows its first page instead
+    pub fn render_book_details(&mut self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        if let Some(book) = app.get_selected_book() {
+            let protocol = app.config.display.graphics.resolve();
+            let comic = self.load_comic(book, &app.library_path).cloned();
+            let mut area = area;
+            let key = match &comic {
+                Some(archive) if !book.has_cover => Some(archive.page_key(0)),
+                _ => book.has_cover.then(|| app.library_path.join(&book.path).join("cover.jpg")),
+            };
+            if let Some(path) = key.filter(|_| protocol != CoverProtocol::Off && !app.accessible()) {
+                let image = match &comic {
+                    Some(archive) if !book.has_cover => self.load_page(archive, 0),
+                    _ => self.load_cover(&path, || CoverImage::load(&path)),
+                };
+                if let Some(image) = image {
+                    let chunks = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([
+                            Constraint::Length((area.width * 2 / 5).clamp(16, 40).min(area.width / 2)),  // Cover
+                            Constraint::Min(0),  // Details
+                        ])
+                        .split(area);
+                    let block = app.density().block("Cover");
+                    let inner = image.fit(block.inner(chunks[0]));
+                    frame.render_widget(block, chunks[0]);
+                    if protocol.is_graphics() {
+                        self.cover_placement = Some((path, inner));
+                    } else {
+                        let support = app.config.display.colors.unwrap_or_else(ColorSupport::detect);
+                        image.render_blocks(inner, frame.buffer_mut(), support);
+                    }
+                    area = chunks[1];
+                }
+            }
+
+            let mut details = vec![
+                Line::from(vec![
+                    Span::styled("Title: ", Style::default().fg(theme.label)),
+                    Span::raw(&book.title),
+                ]),
+                Line::from(vec![
+                    Span::styled("Authors: ", Style::default().fg(theme.label)),
+                    Span::raw(book.author_list()),
+                ]),
+            ];
+
+            if let Some(series) = book.series_label() {
+                details.push(Line::from(vec![
+                    Span::styled("Series: ", Style::default().fg(theme.label)),
+                    Span::raw(series),
+                ]));
+            }
+
+            if let (Some(rating), Some(stars)) = (book.rating, book.stars()) {
+                let rating = if app.accessible() {
+                    Span::raw(format!("{} of 5 stars", rating as f32 / 2.0))
+                } else {
+                    Span::styled(stars, Style::default().fg(theme.accent))
+                };
+                details.push(Line::from(vec![Span::styled("Rating: ", Style::default().fg(theme.label)), rating]));
+            }
+
+            // Add tags if available
+            if !book.tags.is_empty() {
+                details.push(Line::from(vec![
+                    Span::styled("Tags: ", Style::default().fg(theme.label)),
+                    Span::raw(book.tag_list()),
+                ]));
+            }
+
+            if let Some(status) = book.status {
+                details.push(Line::from(vec![
+                    Span::styled("Status: ", Style::default().fg(theme.label)),
+                    Span::raw(status.label()),
+                ]));
+            }
+
+            if !book.shelves.is_empty() {
+                details.push(Line::from(vec![
+                    Span::styled("Shelves: ", Style::default().fg(theme.label)),
+                    Span::raw(book.shelves.join(", ")),
+                ]));
+            }
+
+            if !book.formats.is_empty() {
+                details.push(Line::from(vec![
+                    Span::styled("Formats: ", Style::default().fg(theme.label)),
+                    Span::raw(book.format_list()),
+                ]));
+            }
+
+            if let Some(archive) = &comic {
+                details.push(Line::from(vec![
+                    Span::styled("Pages: ", Style::default().fg(theme.label)),
+                    Span::raw(archive.pages.len().to_string()),
+                ]));
+            }
+
+            if let Some(duration) = self.load_duration(book, &app.library_path) {
+                details.push(Line::from(vec![
+                    Span::styled("Duration: ", Style::default().fg(theme.label)),
+                    Span::raw(format_duration(duration)),
+                ]));
+            }
+
+            if book.has_drm() {
+                let locked: Vec<String> = book.drm.iter().map(|(format, scheme)| format!("{} ({})", format, scheme)).collect();
+                details.push(Line::from(vec![
+                    Span::styled("DRM: ", Style::default().fg(theme.label)),
+                    Span::styled(format!("🔒 {}", locked.join(", ")), Style::default().fg(theme.bad)),
+                ]));
+            }
+
+            if let Some(found) = app.content_matches.get(&book.id) {
+                let mut spans = vec![
+                    Span::styled("Found: ", Style::default().fg(theme.label)),
+                    Span::styled(format!("{}  ", found.location()), Style::default().fg(theme.muted)),
+                ];
+                spans.extend(snippet_spans(&found.snippet, Style::default(), Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)));
+                details.push(Line::from(spans));
+            }
+
+            details.extend(vec![
+                Line::from(vec![
+                    Span::styled("Path: ", Style::default().fg(theme.label)),
+                    Span::raw(&book.path),
+                ]),
+                Line::from(vec![
+                    Span::styled("Cover: ", Style::default().fg(theme.label)),
+                    Span::raw(match (book.has_cover, &comic) {
+                        (true, _) => "Yes",
+                        (false, Some(_)) => "First page",
+                        (false, None) => "No",
+                    }),
+                ]),
+                Line::from(vec![
+                    Span::styled("Added: ", Style::default().fg(theme.label)),
+                    Span::raw(app.format_added(book)),
+                ]),
+            ]);
+
+            let block = app.density().block("Book Details");
+            let description = book.description();
+            let annotations_tab = app.details_tab == DetailsTab::Annotations;
+            let chunks = if description.is_empty() && !annotations_tab {
+                vec![area, Rect::default()]
+            } else {
+                let chrome = area.height - block.inner(area).height;
+                Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([
+                        Constraint::Length(details.len() as u16 + chrome),  // Fields
+                        Constraint::Min(0),  // Description
+                    ])
+                    .split(area)
+                    .to_vec()
+            };
+
+            let details_widget = Paragraph::new(details).block(block);
+            frame.render_widget(details_widget, chunks[0]);
+            if annotations_tab {
+                self.render_annotations(frame, chunks[1], app, book);
+            } else if !description.is_empty() {
+                self.render_description(frame, chunks[1], app, &description);
+            }
+        }
+    }
+
+    /// The bookmarks and highlights made in the book, in the details view's annotations tab
+    fn render_annotations(&self, frame: &mut Frame, area: Rect, app: &App, book: &Book) {
+        let theme = app.theme();
+        let annotations = app.annotations.get(&book.id).map(Vec::as_slice).unwrap_or_default();
+        let title = format!("Annotations ({})", annotations.len());
+        if annotations.is_empty() {
+            let hint = "None yet: in the reader, m bookmarks the page and v highlights paragraphs";
+            let widget = Paragraph::new(Span::styled(hint, Style::default().fg(theme.dim))).block(app.density().block(title));
+            frame.render_widget(widget, area);
+            return;
+        }
+
+        let selected = app.annotation_selected().min(annotations.len() - 1);
+        let items: Vec<ListItem> = annotations
+            .iter()
+            .enumerate()
+            .map(|(i, annotation)| {
+                let style = if i == selected { theme.selected() } else { Style::default() };
+                ListItem::new(annotation.summary()).style(style)
+            })
+            .collect();
+        let list = List::new(items)
+            .block(app.density().block(title))
+            .highlight_symbol(theme.selection_symbol());
+
+        let mut list_state = ListState::default().with_offset(app.scroll_state(ScrollView::Annotations).offset);
+        list_state.select(Some(selected));
+        frame.render_stateful_widget(list, area, &mut list_state);
+        app.record_scroll(ScrollView::Annotations, selected, list_state.offset());
+    }
+
+    /// The book's description under its details, scrolled by `App::details_scroll`
+    fn render_description(&self, frame: &mut Frame, area: Rect, app: &App, paragraphs: &[String]) {
+        let inner = app.density().block("").inner(area);
+        let mut lines = Vec::new();
+        for (i, paragraph) in paragraphs.iter().enumerate() {
+            if i > 0 {
+                lines.push(String::new());
+            }
+            lines.extend(wrap(paragraph, inner.width));
+        }
+
+        let overflow = lines.len().saturating_sub(inner.height as usize);
+        let offset = (app.details_scroll.get() as usize).min(overflow);
+        app.details_scroll.set(offset as u16);
+        let title = if overflow > 0 {
+            format!("Description ({}%) ↑↓ scroll", (offset * 100).checked_div(overflow).unwrap_or(100))
+        } else {
+            "Description".to_string()
+        };
+
+        let text: Vec<Line> = lines.into_iter().skip(offset).map(Line::from).collect();
+        let widget = Paragraph::new(text).block(app.density().block(title));
+        frame.render_widget(widget, area);
+    }
+
+    /// Render the page of the book open in the reader, in a column narrow enough to read
+    pub fn render_reader(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let Some(reader) = &app.reader else {
+            return;
+        };
+
+        let chapter = reader.current();
+        let bookmark = if reader.is_bookmarked() { " 🔖" } else { "" };
+        let mut block = app.density().block(format!("{} — {}{}", reader.title, chapter.title, bookmark));
+        if app.density() != Density::Borderless {
+            let progress = format!(" {}/{} · {:.0}% ", reader.chapter + 1, reader.chapters.len(), reader.progress() * 100.0);
+            block = block.title(Title::from(progress).alignment(Alignment::Right));
+        }
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let width = inner.width.min(READER_WIDTH);
+        let text_area = Rect { x: inner.x + (inner.width - width) / 2, width, ..inner };
+        reader.viewport.set((text_area.width, text_area.height));
+
+        let lines: Vec<Line> = reader
+            .page(text_area.width, text_area.height)
+            .into_iter()
+            .map(|(paragraph, line)| {
+                let found = reader.found.as_deref().map(|phrase| phrase_positions(&line, phrase)).unwrap_or_default();
+                // Paragraphs highlighted, or being highlighted, are drawn in their own colours
+                let marked = if reader.is_selected(paragraph) {
+                    Style::default().fg(theme.selection_fg).bg(theme.selection_bg)
+                } else if reader.is_highlighted(paragraph) {
+                    Style::default().fg(theme.label).add_modifier(Modifier::ITALIC)
+                } else {
+                    Style::default()
+                };
+                if line == chapter.title {
+                    Line::from(Span::styled(line, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)))
+                } else if !found.is_empty() {
+                    let spans = highlight_positions(&line, &found, Style::default().fg(theme.selection_fg).bg(theme.selection_bg));
+                    Line::from(spans.into_iter().map(|span| Span::styled(span.content, marked.patch(span.style))).collect::<Vec<_>>())
+                } else {
+                    Line::from(Span::styled(line, marked))
+                }
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines), text_area);
+    }
+
+    /// Render the comic viewer's page, centred and as large as it fits
+    pub fn render_comic(&mut self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let Some(comic) = &app.comic else {
+            return;
+        };
+
+        let mut block = app.density().block(comic.title.as_str());
+        if app.density() != Density::Borderless {
+            let progress = format!(" {}/{} ", comic.page + 1, comic.archive.pages.len());
+            block = block.title(Title::from(progress).alignment(Alignment::Right));
+        }
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let protocol = app.config.display.graphics.resolve();
+        let Some(image) = self.load_page(&comic.archive, comic.page) else {
+            let name = &comic.archive.pages[comic.page];
+            let message = format!("Page {} can't be shown: {} (only JPEG and PNG pages are drawn)", comic.page + 1, name);
+            frame.render_widget(Paragraph::new(Span::styled(message, Style::default().fg(theme.muted))), inner);
+            return;
+        };
+        let fit = image.fit(inner);
+        let page = Rect { x: inner.x + (inner.width - fit.width) / 2, ..fit };
+        if protocol.is_graphics() {
+            self.cover_placement = Some((comic.archive.page_key(comic.page), page));
+        } else {
+            let support = app.config.display.colors.unwrap_or_else(ColorSupport::detect);
+            image.render_blocks(page, frame.buffer_mut(), support);
+        }
+    }
+
+    /// Render the edit form, with a cursor after the field being typed into
+    pub fn render_edit_form(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let Some(form) = &app.edit else {
+            return;
+        };
+
+        let mut lines = Vec::new();
+        for field in EditField::ALL {
+            let focused = field == form.field;
+            let value = if focused { format!("{}▏", form.value(field)) } else { form.value(field).to_string() };
+            lines.push(Line::from(vec![
+                Span::styled(format!("{}: ", field.label()), Style::default().fg(theme.label)),
+                Span::styled(value, if focused { theme.selected() } else { Style::default() }),
+            ]));
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled("Separate authors with &", Style::default().fg(theme.dim))));
+
+        let form_widget = Paragraph::new(lines).block(app.density().block("Edit Book"));
+        frame.render_widget(form_widget, area);
+    }
+
+    /// Render the books found on the connected device
+    pub fn render_device_view(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let Some(view) = &app.device_view else {
+            return;
+        };
+
+        let locale = app.locale();
+        let items: Vec<ListItem> = view.books
+            .iter()
+            .enumerate()
+            .map(|(i, book)| {
+                let style = if i == view.selected {
+                    theme.selected()
+                } else if book.book_id.is_some() {
+                    Style::default().fg(theme.good)
+                } else {
+                    Style::default().fg(theme.dim)
+                };
+
+                let marker = if book.book_id.is_some() { "✓" } else { "?" };
+                let content = format!("{} {} ({} KB)", marker, book.title, locale.format_number((book.size / 1024) as usize));
+
+                ListItem::new(content).style(style)
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(app.density().block("Device Books (✓ = in library)"))
+            .highlight_symbol(theme.selection_symbol());
+
+        let mut list_state = ListState::default().with_offset(app.scroll_state(ScrollView::Device).offset);
+        list_state.select(Some(view.selected));
+
+        frame.render_stateful_widget(list, area, &mut list_state);
+        app.record_scroll(ScrollView::Device, view.selected, list_state.offset());
+    }
+
+    /// Render the recorded background jobs with the details of the selected one
+    pub fn render_jobs_view(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let Some(view) = &app.jobs_view else {
+            return;
+        };
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Percentage(60),  // Jobs
+                Constraint::Percentage(40),  // Details
+            ])
+            .split(area);
+
+        let locale = app.locale();
+        let items: Vec<ListItem> = view.records
+            .iter()
+            .enumerate()
+            .map(|(i, record)| {
+                let style = if i == view.selected {
+                    theme.selected()
+                } else if record.session != view.session {
+                    Style::default().fg(theme.dim)
+                } else {
+                    Style::default()
+                };
+
+                let marker = if record.cancelled {
+                    "⏹"
+                } else if record.failures.is_empty() && !record.result.starts_with('❌') {
+                    "✅"
+                } else {
+                    "❌"
+                };
+                let started = format_time(record.started, &app.config.display.time_format, app.absolute_times, &locale);
+                let mut content = format!("{} {} · {} · {}", marker, started, record.label, format_seconds(record.seconds));
+                if !record.failures.is_empty() {
+                    content.push_str(&format!(" · {} failed", record.failures.len()));
+                }
+
+                ListItem::new(content).style(style)
+            })
+            .collect();
+
+        let this_session = view.records.iter().filter(|r| r.session == view.session).count();
+        let title = format!("Jobs ({} this session, {} earlier)", this_session, view.records.len() - this_session);
+        let list = List::new(items)
+            .block(app.density().block(title))
+            .highlight_symbol(theme.selection_symbol());
+
+        let mut list_state = ListState::default().with_offset(app.scroll_state(ScrollView::Jobs).offset);
+        list_state.select(Some(view.selected));
+
+        frame.render_stateful_widget(list, chunks[0], &mut list_state);
+        app.record_scroll(ScrollView::Jobs, view.selected, list_state.offset());
+
+        let details = view.get_selected().map(|record| record.details()).unwrap_or_default();
+        let details = if app.accessible() { strip_emoji(&details) } else { details };
+        let details_widget = Paragraph::new(details)
+            .wrap(ratatui::widgets::Wrap { trim: false })
+            .block(app.density().block("Details"));
+
+        frame.render_widget(details_widget, chunks[1]);
+    }
+
+    /// Render the disk usage view: groups or the open group's books, biggest first, each with a
+    /// bar against the biggest and its share of the library or group
+    pub fn render_usage_view(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let Some(view) = &app.usage_view else {
+            return;
+        };
+
+        let (rows, whole, title): (Vec<UsageRow>, u64, String) = match view.open_group() {
+            Some(group) => (
+                group.books.iter().map(|book| {
+                    let files = if book.files == 1 { String::new() } else { format!("  ({} files)", book.files) };
+                    (book.bytes, book.title.clone(), files, app.selected_ids.contains(&book.id))
+                }).collect(),
+                group.bytes,
+                format!("Disk usage by {} › {} · {} books · {}", view.grouping.label(), group.name, group.books.len(), format_size(group.bytes)),
+            ),
+            None => (
+                view.groups.iter().map(|group| {
+                    let marked = group.books.iter().all(|book| app.selected_ids.contains(&book.id));
+                    (group.bytes, group.name.clone(), format!("  ({} books)", group.books.len()), marked)
+                }).collect(),
+                view.total,
+                format!("Disk usage by {} · {} groups · {} in all · Tab regroup", view.grouping.label(), view.groups.len(), format_size(view.total)),
+            ),
+        };
+        let biggest = rows.first().map(|row| row.0).unwrap_or(0);
+
+        let cursor = view.cursor();
+        let items: Vec<ListItem> = rows
+            .iter()
+            .enumerate()
+            .map(|(i, (bytes, name, extra, marked))| {
+                let marker = if *marked { "● " } else { "  " };
+                let share = if whole > 0 { *bytes as f64 * 100.0 / whole as f64 } else { 0.0 };
+                let bar = progress_bar(*bytes as usize, biggest as usize, PROGRESS_BAR_WIDTH);
+                let style = if i == cursor { theme.selected() } else { Style::default() };
+                ListItem::new(Line::from(vec![
+                    Span::raw(marker),
+                    Span::styled(format!("{:>8} {:>5.1}% ", format_size(*bytes), share), Style::default().fg(theme.accent)),
+                    Span::styled(format!("{} ", bar), Style::default().fg(theme.label)),
+                    Span::raw(name.clone()),
+                    Span::styled(extra.clone(), Style::default().fg(theme.muted)),
+                ]))
+                .style(style)
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(app.density().block(title))
+            .highlight_symbol(theme.selection_symbol());
+        let mut list_state = ListState::default().with_offset(app.scroll_state(ScrollView::Usage).offset);
+        list_state.select(Some(cursor));
+
+        frame.render_stateful_widget(list, area, &mut list_state);
+        app.record_scroll(ScrollView::Usage, cursor, list_state.offset());
+    }
 
-impl UIComponents {
-    pub fn new() -> Self {
-        UIComponents
+    /// Render the cleanup assistant: the lists as tabs with their counts, and the one shown with
+    /// each suggestion's size and why it's there
+    pub fn render_cleanup_view(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let Some(view) = &app.cleanup_view else {
+            return;
+        };
+
+        let tabs = CleanupSection::ALL
+            .iter()
+            .map(|section| {
+                let tab = format!("{} ({})", section.label(), view.count(*section));
+                if *section == view.section { format!("[{}]", tab) } else { tab }
+            })
+            .collect::<Vec<_>>()
+            .join("  ");
+        let marked_bytes: u64 = view.items().iter().filter(|item| item.marked).map(|item| item.bytes).sum();
+        let title = format!("{} · {} marked here · Tab next list", tabs, format_size(marked_bytes));
+
+        let cursor = view.cursor();
+        let items: Vec<ListItem> = view
+            .items()
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let marker = if item.marked { "● " } else { "  " };
+                let style = if i == cursor { theme.selected() } else { Style::default() };
+                let reason = if item.reason.is_empty() { String::new() } else { format!("  {}", item.reason) };
+                ListItem::new(Line::from(vec![
+                    Span::raw(marker),
+                    Span::styled(format!("{:>8} ", format_size(item.bytes)), Style::default().fg(theme.accent)),
+                    Span::raw(item.label()),
+                    Span::styled(reason, Style::default().fg(theme.muted)),
+                ]))
+                .style(style)
+            })
+            .collect();
+
+        if items.is_empty() {
+            let empty = Paragraph::new(format!("Nothing to suggest under {}", view.section.label().to_lowercase()))
+                .style(Style::default().fg(theme.muted))
+                .block(app.density().block(title));
+            frame.render_widget(empty, area);
+            return;
+        }
+
+        let list = List::new(items)
+            .block(app.density().block(title))
+            .highlight_symbol(theme.selection_symbol());
+        let mut list_state = ListState::default().with_offset(app.scroll_state(ScrollView::Cleanup).offset);
+        list_state.select(Some(cursor));
+
+        frame.render_stateful_widget(list, area, &mut list_state);
+        app.record_scroll(ScrollView::Cleanup, cursor, list_state.offset());
     }
 
-    /// Render title bar
-    pub fn render_title_bar(&self, frame: &mut Frame, area: Rect, app: &App) {
-        let title = if app.mode == AppMode::Search {
-            format!("Search: {}", app.search_query)
-        } else {
-            format!("tuilibre - {} books", app.books.len())
+    /// Render the format policy audit: each violation with the book and what it breaks
+    pub fn render_policy_audit(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let Some(audit) = &app.policy_audit else {
+            return;
         };
 
-        let title_widget = Paragraph::new(title)
-            .style(Style::default().fg(Color::Cyan))
-            .block(Block::default().borders(Borders::ALL));
+        let items: Vec<ListItem> = audit
+            .violations
+            .iter()
+            .enumerate()
+            .map(|(i, violation)| {
+                let marker = if violation.marked { "● " } else { "  " };
+                let style = if i == audit.selected { theme.selected() } else { Style::default() };
+                let (fix, size) = match &violation.breach {
+                    PolicyBreach::Missing { .. } => ("convert", String::new()),
+                    PolicyBreach::Redundant { .. } => ("delete ", format!("  {}", format_size(violation.bytes))),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw(marker),
+                    Span::styled(format!("{:<18} ", violation.describe()), Style::default().fg(theme.accent)),
+                    Span::raw(violation.title.clone()),
+                    Span::styled(format!("  {}{}", fix, size), Style::default().fg(theme.muted)),
+                ]))
+                .style(style)
+            })
+            .collect();
 
-        frame.render_widget(title_widget, area);
+        let list = List::new(items)
+            .block(app.density().block("Violations · c convert marked · d delete marked · r re-run"))
+            .highlight_symbol(theme.selection_symbol());
+        let mut list_state = ListState::default().with_offset(app.scroll_state(ScrollView::FormatPolicy).offset);
+        list_state.select(Some(audit.selected));
+
+        frame.render_stateful_widget(list, area, &mut list_state);
+        app.record_scroll(ScrollView::FormatPolicy, audit.selected, list_state.offset());
     }
 
-    /// Render book list
-    pub fn render_book_list(&mut self, frame: &mut Frame, area: Rect, app: &App) {
-        let items: Vec<ListItem> = app.books
+    /// Render the files whose metadata differs from the library, with the selected file's fields
+    /// side by side
+    pub fn render_metadata_audit(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let Some(audit) = &app.metadata_audit else {
+            return;
+        };
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Percentage(70),  // Files
+                Constraint::Percentage(30),  // Fields of the selected file
+            ])
+            .split(area);
+
+        let block = app.density().block("Files · p push library → file · l pull file → library · r re-run");
+        if audit.mismatches.is_empty() {
+            let note = if app.job.is_some() { "Reading the files..." } else { "Every file's metadata matches the library" };
+            frame.render_widget(Paragraph::new(note).style(Style::default().fg(theme.muted)).block(block), chunks[0]);
+        } else {
+            let items: Vec<ListItem> = audit
+                .mismatches
+                .iter()
+                .enumerate()
+                .map(|(i, mismatch)| {
+                    let marker = if mismatch.marked { "● " } else { "  " };
+                    let style = if i == audit.selected { theme.selected() } else { Style::default() };
+                    ListItem::new(Line::from(vec![
+                        Span::raw(marker),
+                        Span::styled(format!("{:<6}", mismatch.item.format), Style::default().fg(theme.accent)),
+                        Span::raw(mismatch.item.title.clone()),
+                        Span::styled(format!("  {} differ", mismatch.describe()), Style::default().fg(theme.muted)),
+                    ]))
+                    .style(style)
+                })
+                .collect();
+            let list = List::new(items).block(block).highlight_symbol(theme.selection_symbol());
+            let mut list_state = ListState::default().with_offset(app.scroll_state(ScrollView::MetadataAudit).offset);
+            list_state.select(Some(audit.selected));
+            frame.render_stateful_widget(list, chunks[0], &mut list_state);
+            app.record_scroll(ScrollView::MetadataAudit, audit.selected, list_state.offset());
+        }
+
+        let lines: Vec<Line> = audit
+            .selected_mismatch()
+            .map(|mismatch| {
+                mismatch
+                    .fields
+                    .iter()
+                    .flat_map(|field| {
+                        [
+                            Line::from(Span::styled(field.label(), Style::default().fg(theme.accent))),
+                            Line::from(format!("  Library: {}", field.show(&mismatch.item.metadata))),
+                            Line::from(format!("  File:    {}", field.show(&mismatch.file))),
+                        ]
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let fields = Paragraph::new(lines)
+            .wrap(ratatui::widgets::Wrap { trim: false })
+            .block(app.density().block("Differences"));
+        frame.render_widget(fields, chunks[1]);
+    }
+
+    /// Render the current OPDS feed with a summary of the selected entry
+    pub fn render_opds_view(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let Some(page) = app.opds_view.as_ref().and_then(|v| v.current()) else {
+            return;
+        };
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Percentage(70),  // Entries
+                Constraint::Percentage(30),  // Summary
+            ])
+            .split(area);
+
+        let items: Vec<ListItem> = page.feed.entries
             .iter()
             .enumerate()
-            .map(|(i, book)| {
-                let style = if i == app.selected_book_index {
-                    Style::default().bg(Color::Blue).fg(Color::White)
+            .map(|(i, entry)| {
+                let style = if i == page.selected {
+                    theme.selected()
                 } else {
                     Style::default()
                 };
 
-                let path_display = if book.path.chars().count() > 30 {
-                    let chars: Vec<char> = book.path.chars().collect();
-                    format!("...{}", chars.iter().skip(chars.len().saturating_sub(27)).collect::<String>())
+                let content = if entry.is_navigation() {
+                    format!("📁 {}", entry.title)
                 } else {
-                    book.path.clone()
+                    let format = entry.best_acquisition()
+                        .map(|(_, ext)| ext.to_uppercase())
+                        .unwrap_or_else(|| "?".to_string());
+                    format!("📖 {} - {} [{}]", entry.title, entry.authors.join(", "), format)
                 };
 
-                let content = format!("{} - {} [{}]",
-                    book.display_title(),
-                    book.author_list(),
-                    path_display
-                );
+                ListItem::new(content).style(style)
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(app.density().block(page.feed.title.as_str()))
+            .highlight_symbol(theme.selection_symbol());
+
+        let mut list_state = ListState::default();
+        list_state.select(Some(page.selected));
+
+        frame.render_stateful_widget(list, chunks[0], &mut list_state);
+
+        let summary = page.feed.entries
+            .get(page.selected)
+            .and_then(|e| e.summary.clone())
+            .unwrap_or_default();
+        let summary_widget = Paragraph::new(summary)
+            .wrap(ratatui::widgets::Wrap { trim: true })
+            .block(app.density().block("Summary"));
+
+        frame.render_widget(summary_widget, chunks[1]);
+    }
+
+    /// Render the current batch of proposals and a side-by-side diff of the selected one
+    pub fn render_review_view(&self, frame: &mut Frame, area: Rect, app: &App) {
+        let theme = app.theme();
+        let batch = app.review.batch(app.config.metadata.batch_size);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Percentage(35),  // Proposals
+                Constraint::Percentage(65),  // Field diff
+            ])
+            .split(area);
 
+        let items: Vec<ListItem> = batch
+            .iter()
+            .enumerate()
+            .map(|(i, proposal)| {
+                let style = if i == app.review.selected {
+                    theme.selected()
+                } else {
+                    Style::default()
+                };
+                let content = format!("{} + {} ({})", proposal.book_title, proposal.summary(), proposal.origin);
                 ListItem::new(content).style(style)
             })
             .collect();
 
         let list = List::new(items)
-            .block(Block::default().borders(Borders::ALL).title("Books"));
+            .block(app.density().block("Proposed changes"))
+            .highlight_symbol(theme.selection_symbol());
+
+        let mut list_state = ListState::default().with_offset(app.scroll_state(ScrollView::Review).offset);
+        list_state.select(Some(app.review.selected));
+
+        frame.render_stateful_widget(list, chunks[0], &mut list_state);
+        app.record_scroll(ScrollView::Review, app.review.selected, list_state.offset());
+
+        let Some(proposal) = batch.get(app.review.selected) else {
+            return;
+        };
+
+        // Marker, field name, then current and proposed values sharing the rest
+        let value_width = (chunks[1].width.saturating_sub(2 + 4 + 12 + 3) / 2).max(10) as usize;
+        let widths = [
+            Constraint::Length(4),
+            Constraint::Length(12),
+            Constraint::Percentage(50),
+            Constraint::Percentage(50),
+        ];
+
+        let rows: Vec<Row> = proposal.changes
+            .iter()
+            .enumerate()
+            .map(|(i, change)| {
+                let current = wrap_text(&change.current, value_width, 6);
+                let proposed = wrap_text(&change.proposed, value_width, 6);
+                let height = current.len().max(proposed.len()).max(1) as u16;
+
+                let marker = if change.accepted { "[x]" } else { "[ ]" };
+                let style = if i == app.review.field {
+                    theme.selected()
+                } else if change.accepted {
+                    Style::default().fg(theme.good)
+                } else {
+                    Style::default().fg(theme.dim)
+                };
+
+                Row::new(vec![
+                    Cell::from(marker),
+                    Cell::from(format!("{}{}", theme.marker(i == app.review.field), change.field.label())),
+                    Cell::from(current.join("\n")),
+                    Cell::from(proposed.join("\n")),
+                ])
+                .height(height)
+                .bottom_margin(1)
+                .style(style)
+            })
+            .collect();
+
+        let header = Row::new(vec!["", "Field", "Current", "Proposed"])
+            .style(Style::default().fg(theme.label))
+            .bottom_margin(1);
+
+        let title = format!("{} - from {}", proposal.book_title, proposal.origin);
+        let table = Table::new(rows)
+            .header(header)
+            .widths(&widths)
+            .block(app.density().block(title));
+
+        frame.render_widget(table, chunks[1]);
+    }
+
+    /// Render a popup menu centered over `area`
+    pub fn render_menu(&self, frame: &mut Frame, area: Rect, menu: &Menu, theme: &Theme) {
+        let width = menu.items
+            .iter()
+            .map(|item| item.chars().count())
+            .chain(std::iter::once(menu.title.chars().count()))
+            .max()
+            .unwrap_or(0) as u16 + 6;
+        let height = menu.items.len() as u16 + 2;
+
+        let popup = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width: width.min(area.width),
+            height: height.min(area.height),
+        };
+
+        let items: Vec<ListItem> = menu.items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let style = if i == menu.selected {
+                    theme.selected()
+                } else {
+                    Style::default()
+                };
+                ListItem::new(format!(" {} ", item)).style(style)
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(menu.title.as_str()))
+            .highlight_symbol(theme.selection_symbol());
 
         let mut list_state = ListState::default();
-        list_state.select(Some(app.selected_book_index));
+        list_state.select(Some(menu.selected));
 
-        frame.render_stateful_widget(list, area, &mut list_state);
+        frame.render_widget(Clear, popup);
+        frame.render_stateful_widget(list, popup, &mut list_state);
     }
 
-    /// Render book details
-    pub fn render_book_details(&self, frame: &mut Frame, area: Rect, app: &App) {
-        if let Some(book) = app.get_selected_book() {
-            let mut details = vec![
-                Line::from(vec![
-                    Span::styled("Title: ", Style::default().fg(Color::Yellow)),
-                    Span::raw(&book.title),
-                ]),
-                Line::from(vec![
-                    Span::styled("Authors: ", Style::default().fg(Color::Yellow)),
-                    Span::raw(book.author_list()),
-                ]),
-            ];
+    /// Render the delete confirmation centered over `area`: the database rows and files that
+    /// would go, so the user sees the whole of it before pressing 'y'
+    pub fn render_delete_confirm(&self, frame: &mut Frame, area: Rect, confirm: &DeleteConfirm, library_path: &Path, theme: &Theme) {
+        let preview = &confirm.preview;
+        let mut lines = vec![
+            Line::from(Span::styled(confirm.title.clone(), Style::default().add_modifier(Modifier::BOLD))),
+            Line::from(""),
+            Line::from(Span::styled("Database rows", Style::default().fg(theme.label))),
+        ];
+        lines.extend(preview.rows.iter().map(|(table, count)| Line::from(format!("  {}: {}", table, count))));
+        lines.push(Line::from(""));
 
-            // Add tags if available
-            if !book.tags.is_empty() {
-                details.push(Line::from(vec![
-                    Span::styled("Tags: ", Style::default().fg(Color::Yellow)),
-                    Span::raw(book.tag_list()),
-                ]));
+        let folder = preview.book_dir.strip_prefix(library_path).unwrap_or(&preview.book_dir);
+        if confirm.remove_files {
+            let bytes: u64 = preview.files.iter().map(|(_, bytes)| bytes).sum();
+            lines.push(Line::from(Span::styled(
+                format!("Files in {} ({})", folder.display(), format_size(bytes)),
+                Style::default().fg(theme.label),
+            )));
+            if preview.files.is_empty() {
+                lines.push(Line::from("  (none on disk)"));
             }
+            lines.extend(preview.files.iter().map(|(path, bytes)| {
+                let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                Line::from(format!("  {}  {}", name, format_size(*bytes)))
+            }));
+        } else {
+            lines.push(Line::from(Span::styled(format!("Files in {} are kept", folder.display()), Style::default().fg(theme.label))));
+        }
+        lines.push(Line::from(""));
+        let files_key = if confirm.remove_files { "f Keep files" } else { "f Remove files" };
+        lines.push(Line::from(Span::styled(format!("y Delete | {} | n Cancel", files_key), Style::default().fg(theme.muted))));
 
-            details.extend(vec![
-                Line::from(vec![
-                    Span::styled("Path: ", Style::default().fg(Color::Yellow)),
-                    Span::raw(&book.path),
-                ]),
-                Line::from(vec![
-                    Span::styled("Cover: ", Style::default().fg(Color::Yellow)),
-                    Span::raw(if book.has_cover { "Yes" } else { "No" }),
-                ]),
-                Line::from(vec![
-                    Span::styled("Added: ", Style::default().fg(Color::Yellow)),
-                    Span::raw(&book.timestamp),
-                ]),
-            ]);
+        let width = lines.iter().map(|line| line.width()).max().unwrap_or(0) as u16 + 4;
+        let height = lines.len() as u16 + 2;
+        let popup = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width: width.min(area.width),
+            height: height.min(area.height),
+        };
 
-            let details_widget = Paragraph::new(details)
-                .block(Block::default().borders(Borders::ALL).title("Book Details"));
+        let dialog = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(" Delete book? ").border_style(Style::default().fg(theme.bad)));
 
-            frame.render_widget(details_widget, area);
-        }
+        frame.render_widget(Clear, popup);
+        frame.render_widget(dialog, popup);
+    }
+
+    /// Render a one-line text input centered over `area`
+    pub fn render_prompt(&self, frame: &mut Frame, area: Rect, prompt: &Prompt) {
+        let width = 50.min(area.width);
+        let popup = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(3) / 2,
+            width,
+            height: 3.min(area.height),
+        };
+
+        let input = Paragraph::new(format!("{}▏", prompt.input))
+            .block(Block::default().borders(Borders::ALL).title(prompt.title.as_str()));
+
+        frame.render_widget(Clear, popup);
+        frame.render_widget(input, popup);
     }
 
     /// Render status bar
     pub fn render_status_bar(&self, frame: &mut Frame, area: Rect, app: &App) {
-        let help_text = match app.mode {
-            AppMode::Normal => "↑↓ Navigate | Enter Details | / Search | ESC Library | q Quit",
-            AppMode::Search => "ESC Back | Enter Select | q Quit",
-            AppMode::Details => "ESC Back | Enter Open | q Quit",
-            AppMode::DetailsFromSearch => "ESC Back to Search | Enter Open | q Quit",
+        let theme = app.theme();
+        // Running jobs and messages take precedence over the key help
+        if let Some(job) = &app.job {
+            let (icon, hint) = if job.cancelling { ("⏹ Cancelling", "") } else { ("⏳", " | Esc cancel") };
+            let bar = if job.total > 0 { format!("{} ", progress_bar(job.done, job.total, PROGRESS_BAR_WIDTH)) } else { String::new() };
+            let progress = format!(
+                "{} {} {}[{}/{}] {}{}",
+                icon, job.label, bar, (job.done + 1).min(job.total), job.total, job.current, hint
+            );
+            let progress = if app.accessible() { strip_emoji(&progress) } else { progress };
+            let status_widget = Paragraph::new(progress)
+                .style(Style::default().fg(theme.label))
+                .block(app.density().bar_block());
+            frame.render_widget(status_widget, area);
+            return;
+        }
+
+        if let Some(message) = &app.status_message {
+            let message = if app.accessible() { strip_emoji(message) } else { message.clone() };
+            let status_widget = Paragraph::new(message)
+                .style(Style::default().fg(theme.good))
+                .block(app.density().bar_block());
+            frame.render_widget(status_widget, area);
+            return;
+        }
+
+        if let Some(pages) = &app.pages {
+            let loading = format!(
+                "📚 Loading books {} [{}/{}]",
+                progress_bar(pages.loaded, pages.total, PROGRESS_BAR_WIDTH), pages.loaded, pages.total
+            );
+            let loading = if app.accessible() { strip_emoji(&loading) } else { loading };
+            let status_widget = Paragraph::new(loading)
+                .style(Style::default().fg(theme.label))
+                .block(app.density().bar_block());
+            frame.render_widget(status_widget, area);
+            return;
+        }
+
+        // Built from the action registry so new commands appear without touching this
+        let normal_help = format!("↑↓ Navigate | {}", action::key_help());
+        let found = app.get_selected_book().is_some_and(|b| app.content_matches.contains_key(&b.id));
+        let help_text: &str = match app.mode {
+            _ if app.pending_delete.is_some() => "y Delete | f Keep or remove files | any other key Cancel",
+            AppMode::Normal if app.visual_select => "↑↓ Navigate | Space Mark | V Range start/end | Enter Batch actions | ESC End visual select",
+            AppMode::Normal => &normal_help,
+            AppMode::Search => "ESC Back | Enter Select | Tab Narrow | ^T Plain text | ^F Full text | ^S Save | q Quit",
+            AppMode::Details | AppMode::DetailsFromSearch if app.details_tab == DetailsTab::Annotations => {
+                "ESC Back | Tab Description | ↑↓ Select | Enter Read here | d Delete | x Export Markdown | p Read | q Quit"
+            }
+            AppMode::Reader if app.reader.as_ref().is_some_and(|r| r.selection.is_some()) => "↑↓ Extend highlight | Enter Save | ESC Cancel",
+            AppMode::Details if found => "ESC Back | Enter Open | p Read | n Read at match | e Edit | d Delete | ↑↓ Scroll description | Tab Annotations | q Quit",
+            AppMode::DetailsFromSearch if found => "ESC Back to Search | Enter Open | p Read | n Read at match | e Edit | d Delete | ↑↓ Scroll description | Tab Annotations | q Quit",
+            AppMode::Details => "ESC Back | Enter Open | p Read | e Edit | d Delete | ↑↓ Scroll description | Tab Annotations | q Quit",
+            AppMode::DetailsFromSearch => "ESC Back to Search | Enter Open | p Read | e Edit | d Delete | ↑↓ Scroll description | Tab Annotations | q Quit",
             AppMode::LibrarySelection => "↑↓ Select | Enter Open | q Quit",
+            AppMode::Device => "↑↓ Navigate | d Delete from device | ESC Back | q Quit",
+            AppMode::Opds => "↑↓ Navigate | Enter Open/Download | n Next page | ESC Back | q Quit",
+            AppMode::Review => "↑↓ Book | Tab Field | Space Toggle field | a Apply | x Reject | A/X Apply/Reject batch | ESC Back",
+            AppMode::Edit => "Tab Next field | Enter Save | ESC Cancel",
+            AppMode::Reader => "Space/b Page | ↑↓ Scroll | n/p Chapter | t Contents | g/G Start/End | m Bookmark | v Highlight | ESC Close",
+            AppMode::Comic => "Space/→ Next | b/← Previous | g/G First/Last | ESC Close",
+            AppMode::Jobs => "↑↓ Navigate | r Retry failed books | y Copy details | ESC Back | q Quit",
+            AppMode::Usage => "↑↓ Navigate | Enter Open | Tab Group by | Space Mark | b Mark 10 biggest | ESC Back | q Quit",
+            AppMode::FormatPolicy => "↑↓ Navigate | Space Mark | a Mark all | c Convert marked | d Delete marked | r Re-run | Enter Show | ESC Back",
+            AppMode::MetadataAudit => "↑↓ Navigate | Space Mark | a Mark all | p Push library → file | l Pull file → library | r Re-run | Enter Show | ESC Back",
+            AppMode::Cleanup => "↑↓ Navigate | Tab List | Space Mark | a Mark all | d Delete marked | Enter Show | ESC Back | q Quit",
         };
 
         let status_widget = Paragraph::new(help_text)
-            .style(Style::default().fg(Color::Gray))
-            .block(Block::default().borders(Borders::ALL));
+            .style(Style::default().fg(theme.muted))
+            .block(app.density().bar_block());
 
         frame.render_widget(status_widget, area);
     }
 
-    /// Render library selection screen
-    pub fn render_library_selection(&self, frame: &mut Frame, area: Rect, selector: &LibrarySelector, selected_index: usize) {
+    /// Render library selection screen, with the search query and matches highlighted while searching
+    pub fn render_library_selection(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        selector: &LibrarySelector,
+        selected_index: usize,
+        in_search_mode: bool,
+        renaming: Option<&str>,
+    ) {
+        let theme = selector.theme();
+        let bar_height = selector.density().bar_height();
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
-                Constraint::Length(3),  // Title bar
+                Constraint::Length(bar_height),  // Title bar
                 Constraint::Min(0),      // Library list
-                Constraint::Length(3),  // Status bar
+                Constraint::Length(bar_height),  // Status bar
             ])
             .split(area);
 
-        // Render title bar with search query
-        let title = if selector.get_search_query().is_empty() {
-            "选择 calibre 图书馆".to_string()
+        // Render title bar with search indicator
+        let title = if let Some(alias) = renaming {
+            format!("重命名: {} (Enter 保存, 留空恢复目录名, ESC 取消)", alias)
+        } else if in_search_mode {
+            format!("搜索: {} (按 ESC 退出搜索)", selector.get_search_query())
         } else {
-            format!("搜索: {}", selector.get_search_query())
+            "选择 calibre 图书馆".to_string()
         };
         let title_widget = Paragraph::new(title)
-            .style(Style::default().fg(Color::Cyan))
-            .block(Block::default().borders(Borders::ALL));
+            .style(Style::default().fg(theme.accent))
+            .block(selector.density().bar_block());
 
         frame.render_widget(title_widget, chunks[0]);
 
-        // Render library list
-        let items: Vec<ListItem> = selector.get_filtered_libraries()
-            .iter()
-            .enumerate()
-            .map(|(i, lib)| {
-                let style = if i == selected_index {
-                    Style::default().bg(Color::Blue).fg(Color::White)
-                } else {
-                    Style::default()
-                };
+        // Render library list, with a header above each section
+        let highlight = Style::default().fg(theme.label).add_modifier(Modifier::BOLD);
+        let header_style = Style::default().fg(theme.accent).add_modifier(Modifier::BOLD);
+        let mut items: Vec<ListItem> = Vec::new();
+        let mut selected_row = 0;
+        let mut section = None;
+        for (i, m) in selector.get_filtered_libraries().iter().enumerate() {
+            let lib = &m.library;
+            if section != Some(lib.section) {
+                section = Some(lib.section);
+                let title = if selector.is_accessible() { strip_emoji(lib.section.title()) } else { lib.section.title().to_string() };
+                items.push(ListItem::new(Line::from(Span::styled(title, header_style))));
+            }
 
-                let book_count = lib.book_count.unwrap_or(0);
-                let mut content = if lib.from_history {
-                    format!("⭐ {} - {} ({} 本书)",
-                        lib.name,
-                        lib.path.display(),
-                        book_count
-                    )
-                } else {
-                    format!("{} - {} ({} 本书)",
-                        lib.name,
-                        lib.path.display(),
-                        book_count
-                    )
-                };
+            let style = if i == selected_index {
+                selected_row = items.len();
+                theme.selected()
+            } else if lib.offline_mount.is_some() {
+                Style::default().fg(theme.dim)
+            } else {
+                Style::default()
+            };
+
+            let mut spans = vec![Span::raw("  ")];
+            spans.extend(highlight_positions(&lib.name, &m.name_positions, highlight));
+            spans.push(Span::raw(" - "));
+            if let Some(old_path) = &lib.moved_from {
+                spans.push(Span::styled(format!("{} → ", old_path.display()), Style::default().fg(theme.dim)));
+            }
+            spans.extend(highlight_positions(&lib.path.display().to_string(), &m.path_positions, highlight));
+            spans.push(Span::raw(format!(" ({} 本书)", selector.format_book_count(lib))));
 
-                // Add last used info for history libraries
-                if let Some(last_used) = &lib.last_used {
-                    content.push_str(&format!(" [上次使用: {}]", last_used));
+            // Add last used info for history libraries
+            if let Some(last_used) = selector.format_last_used(lib) {
+                spans.push(Span::raw(format!(" [上次使用: {}]", last_used)));
+            }
+            if let Some(mount) = &lib.offline_mount {
+                match selector.format_snapshot(lib) {
+                    Some(taken) => spans.push(Span::raw(format!(" (未挂载: {}, Enter 浏览 {} 的离线快照)", mount.display(), taken))),
+                    None => spans.push(Span::raw(format!(" (未挂载: {}, 挂载后按 Enter 重试)", mount.display()))),
                 }
+            }
 
-                ListItem::new(content).style(style)
-            })
-            .collect();
+            items.push(ListItem::new(Line::from(spans)).style(style));
+        }
 
         let list = List::new(items)
-            .block(Block::default().borders(Borders::ALL).title("发现的图书馆"));
+            .block(selector.density().block("发现的图书馆"))
+            .highlight_symbol(theme.selection_symbol());
 
         let mut list_state = ListState::default();
-        list_state.select(Some(selected_index));
+        list_state.select(Some(selected_row));
 
         frame.render_stateful_widget(list, chunks[1], &mut list_state);
 
-        // Render status bar
-        let help_text = "↑↓ 选择 | Enter 确认 | q 退出 | ⭐ = 历史记录中的库";
+        // Render status bar with search controls
+        let help_text = if let Some(notice) = selector.notice() {
+            notice
+        } else if in_search_mode {
+            "输入搜索 | ↑↓ 导航 | Enter 选择 | ESC 退出搜索"
+        } else {
+            "↑↓/j/k 导航 | Enter 选择 | / 搜索 | p 置顶/取消置顶 | r 重命名 | t 绝对时间 | q 退出"
+        };
         let status_widget = Paragraph::new(help_text)
-            .style(Style::default().fg(Color::Gray))
-            .block(Block::default().borders(Borders::ALL));
+            .style(Style::default().fg(theme.muted))
+            .block(selector.density().bar_block());
+
+        frame.render_widget(status_widget, chunks[2]);
+    }
+
+    /// Render the dashboard: one card per library with its counts, recent additions and reads
+    pub fn render_dashboard(&self, frame: &mut Frame, area: Rect, dashboard: &Dashboard) {
+        let theme = dashboard.theme();
+        let density = dashboard.density();
+        let bar_height = density.bar_height();
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(bar_height),  // Title bar
+                Constraint::Min(0),      // Library cards
+                Constraint::Length(bar_height),  // Status bar
+            ])
+            .split(area);
+
+        let title = format!("tuilibre - {} 个图书馆", dashboard.summaries().len());
+        let title_widget = Paragraph::new(title)
+            .style(Style::default().fg(theme.accent))
+            .block(density.bar_block());
+        frame.render_widget(title_widget, chunks[0]);
+
+        let label = Style::default().fg(theme.label);
+        let dim = Style::default().fg(theme.dim);
+        let mut items: Vec<ListItem> = Vec::new();
+        for (i, lib) in dashboard.summaries().iter().enumerate() {
+            let key = if i < DIGIT_JUMPS { format!("[{}] ", i + 1) } else { "    ".to_string() };
+            let pin = if lib.pinned { if dashboard.is_accessible() { "(置顶) " } else { "📌 " } } else { "" };
+            let mut lines = vec![Line::from(vec![
+                Span::styled(key, Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)),
+                Span::raw(pin),
+                Span::styled(lib.name.clone(), Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(
+                    format!("  {} 本书 · 上次使用: {}", dashboard.format_book_count(lib), dashboard.format_last_used(lib)),
+                    dim,
+                ),
+            ])];
+
+            if let Some(error) = &lib.error {
+                lines.push(Line::from(vec![
+                    Span::raw("    "),
+                    Span::styled(format!("无法读取: {}", error), Style::default().fg(theme.bad)),
+                ]));
+            } else {
+                if !lib.recent.is_empty() {
+                    lines.push(Line::from(vec![
+                        Span::styled("    最近添加: ", label),
+                        Span::raw(lib.recent.join(" · ")),
+                    ]));
+                }
+                if !lib.reading.is_empty() {
+                    lines.push(Line::from(vec![
+                        Span::styled("    在读: ", label),
+                        Span::raw(lib.reading.join(" · ")),
+                    ]));
+                }
+            }
+            lines.push(Line::from(""));
+
+            let style = if i == dashboard.selected { theme.selected() } else { Style::default() };
+            items.push(ListItem::new(lines).style(style));
+        }
+
+        let list = List::new(items)
+            .block(density.block("图书馆总览"))
+            .highlight_symbol(theme.selection_symbol());
+        let mut list_state = ListState::default();
+        list_state.select(Some(dashboard.selected));
+        frame.render_stateful_widget(list, chunks[1], &mut list_state);
 
+        let help_text = "1-9 打开 | ↑↓/j/k 导航 | Enter 打开 | l 所有图书馆 | q 退出";
+        let status_widget = Paragraph::new(help_text)
+            .style(Style::default().fg(theme.muted))
+            .block(density.bar_block());
         frame.render_widget(status_widget, chunks[2]);
     }
 
     /// Render no libraries found message
-    pub fn render_no_libraries(&self, frame: &mut Frame, area: Rect) {
+    pub fn render_no_libraries(&self, frame: &mut Frame, area: Rect, theme: &Theme) {
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
@@ -220,7 +1859,7 @@ impl UIComponents {
         // Render title bar
         let title = "未找到 calibre 图书馆";
         let title_widget = Paragraph::new(title)
-            .style(Style::default().fg(Color::Red))
+            .style(Style::default().fg(theme.bad))
             .block(Block::default().borders(Borders::ALL));
 
         frame.render_widget(title_widget, chunks[0]);
@@ -240,7 +1879,7 @@ impl UIComponents {
         ];
 
         let message_widget = Paragraph::new(message)
-            .style(Style::default().fg(Color::Yellow))
+            .style(Style::default().fg(theme.label))
             .block(Block::default().borders(Borders::ALL));
 
         frame.render_widget(message_widget, chunks[1]);
@@ -248,9 +1887,143 @@ impl UIComponents {
         // Render status bar
         let help_text = "按任意键退出";
         let status_widget = Paragraph::new(help_text)
-            .style(Style::default().fg(Color::Gray))
+            .style(Style::default().fg(theme.muted))
+            .block(Block::default().borders(Borders::ALL));
+
+        frame.render_widget(status_widget, chunks[2]);
+    }
+
+    /// Render the wait while libraries are looked for, which a slow network mount can drag out
+    pub fn render_discovering(&self, frame: &mut Frame, area: Rect, theme: &Theme, elapsed: Duration, stopping: bool) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),  // Title bar
+                Constraint::Min(0),      // Message
+                Constraint::Length(3),  // Status bar
+            ])
+            .split(area);
+
+        let title = Paragraph::new("正在查找 calibre 图书馆")
+            .style(Style::default().fg(theme.accent))
+            .block(Block::default().borders(Borders::ALL));
+        frame.render_widget(title, chunks[0]);
+
+        let message = if stopping {
+            "⏹ 正在停止查找…".to_string()
+        } else {
+            format!("🔍 正在搜索常见位置… 已用 {} 秒", elapsed.as_secs())
+        };
+        let message_widget = Paragraph::new(message)
+            .style(Style::default().fg(theme.label))
             .block(Block::default().borders(Borders::ALL));
+        frame.render_widget(message_widget, chunks[1]);
 
+        let status_widget = Paragraph::new("ESC 停止并列出已找到的 | ^C 退出")
+            .style(Style::default().fg(theme.muted))
+            .block(Block::default().borders(Borders::ALL));
         frame.render_widget(status_widget, chunks[2]);
     }
-}
\ No newline at end of file
+}
+
+/// Spans of `text` with the characters at `positions` in `style`
+fn highlight_positions(text: &str, positions: &[usize], style: Style) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut run = String::new();
+    let mut run_matched = false;
+
+    for (i, c) in text.chars().enumerate() {
+        let matched = positions.contains(&i);
+        if matched != run_matched && !run.is_empty() {
+            let text = std::mem::take(&mut run);
+            spans.push(if run_matched { Span::styled(text, style) } else { Span::raw(text) });
+        }
+        run_matched = matched;
+        run.push(c);
+    }
+    if !run.is_empty() {
+        spans.push(if run_matched { Span::styled(run, style) } else { Span::raw(run) });
+    }
+    spans
+}
+
+/// Spans of a content search snippet, the matched words in `matched` and the rest in `style`
+fn snippet_spans(snippet: &str, style: Style, matched: Style) -> Vec<Span<'static>> {
+    snippet
+        .split(MATCH_START)
+        .enumerate()
+        .flat_map(|(i, part)| {
+            // Every part but the first opens with matched words
+            let (hit, rest) = match part.split_once(MATCH_END) {
+                Some((hit, rest)) if i > 0 => (hit, rest),
+                _ => ("", part),
+            };
+            [Span::styled(hit.to_string(), matched), Span::styled(rest.to_string(), style)]
+        })
+        .filter(|span| !span.content.is_empty())
+        .collect()
+}
+
+/// Break text into lines of at most `width` characters, keeping at most `max_lines`
+fn wrap_text(text: &str, width: usize, max_lines: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            last.push('…');
+        }
+    }
+    lines
+}
+
+/// Markers for what a book lacks, e.g. " ∅ ▫" for a metadata-only entry without a cover
+fn gap_markers(book: &Book) -> String {
+    book.gaps().iter().map(|gap| format!(" {}", gap.symbol())).collect()
+}
+
+/// Letter shown on a row of the index strip, spreading the letters out when rows are short
+fn index_letter_at(area: Rect, row: u16) -> Option<char> {
+    let rows = area.height as usize;
+    let row = row as usize;
+    if row >= rows.min(INDEX_LETTERS.len()) {
+        return None;
+    }
+    if rows >= INDEX_LETTERS.len() {
+        return Some(INDEX_LETTERS[row]);
+    }
+    Some(INDEX_LETTERS[row * INDEX_LETTERS.len() / rows])
+}
+
+/// e.g. "▕██████░░░░▏" for 60%, `width` cells between the ends
+fn progress_bar(done: usize, total: usize, width: usize) -> String {
+    let filled = (done.min(total) * width).checked_div(total).unwrap_or(0);
+    format!("▕{}{}▏", "█".repeat(filled), "░".repeat(width - filled))
+}
+
+/// A row of the disk usage view: size, name, what else to say about it, and whether it's marked
+type UsageRow = (u64, String, String, bool);
+
+/// A job's duration, e.g. "4.2s" or "3m 05s"
+fn format_seconds(seconds: f64) -> String {
+    if seconds < 60.0 {
+        format!("{:.1}s", seconds)
+    } else {
+        let whole = seconds as u64;
+        format!("{}m {:02}s", whole / 60, whole % 60)
+    }
+}
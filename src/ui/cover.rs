@@ -0,0 +1,498 @@
+//! Book covers in the details view
+//!
+//! Terminals that speak a graphics protocol (kitty, iTerm2 or sixel) get the picture itself,
+//! written after ratatui has drawn the frame; everywhere else it is drawn with "▀" half blocks,
+//! two pixels to a cell.
+//!
+//! Which protocol the terminal speaks is asked once at startup: a kitty graphics query answers
+//! for kitty, and the device attributes every terminal reports say whether it draws sixels.
+//! Inside tmux or screen the pictures (and the queries) are wrapped in the multiplexer's
+//! passthrough escape so they reach the terminal around it; when tmux has passthrough turned
+//! off, half blocks are used instead.
+
+use base64::Engine;
+use ratatui::{buffer::Buffer, layout::Rect, style::Color};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use super::color::ColorSupport;
+use crate::error::{Context, Error, Result};
+
+/// Largest side of the picture sent over a graphics protocol, in pixels
+const MAX_GRAPHICS_SIZE: u32 = 800;
+
+/// Cell size assumed when the terminal doesn't report its size in pixels
+const FALLBACK_CELL_PIXELS: (u32, u32) = (8, 16);
+
+/// First bytes of every PNG file
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// A kitty graphics query for a 1x1 image, answered only by terminals that speak the protocol
+const KITTY_QUERY: &str = "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\";
+
+/// Primary device attributes request, which every terminal answers; "4" among them means sixel
+const DEVICE_ATTRIBUTES_QUERY: &str = "\x1b[c";
+
+/// How long the terminal gets to answer the queries
+const QUERY_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Longest string screen passes on in one escape; longer sequences are split over several
+const SCREEN_CHUNK: usize = 760;
+
+static DETECTED: OnceLock<CoverProtocol> = OnceLock::new();
+static PASSTHROUGH: OnceLock<Option<Multiplexer>> = OnceLock::new();
+
+/// How covers are drawn, the `graphics` setting
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoverProtocol {
+    /// Asked of the terminal
+    #[default]
+    Auto,
+    Kitty,
+    Iterm2,
+    Sixel,
+    /// Unicode half blocks, which any color terminal can show
+    #[serde(alias = "blocks")]
+    HalfBlocks,
+    /// No cover in the details view
+    Off,
+}
+
+impl CoverProtocol {
+    /// `Auto` resolved to what the terminal answered in `detect`, or else to what its
+    /// environment variables suggest; half blocks for any picture a multiplexer won't pass on
+    pub fn resolve(self) -> Self {
+        let blocked = multiplexer().is_some() && passthrough().is_none();
+        if self != CoverProtocol::Auto {
+            return if blocked && self.is_graphics() { CoverProtocol::HalfBlocks } else { self };
+        }
+        *DETECTED.get_or_init(|| if blocked { CoverProtocol::HalfBlocks } else { from_environment() })
+    }
+
+    /// Ask the terminal which protocol it speaks, once per run; call it in raw mode, before
+    /// any key is read, so the answers aren't taken for key presses
+    pub fn detect() {
+        DETECTED.get_or_init(|| {
+            if multiplexer().is_some() && passthrough().is_none() {
+                return CoverProtocol::HalfBlocks;
+            }
+            // iTerm2 answers as a sixel terminal but draws its own protocol better
+            if std::env::var("TERM_PROGRAM").is_ok_and(|program| program == "iTerm.app") {
+                return CoverProtocol::Iterm2;
+            }
+            query_terminal().unwrap_or_else(from_environment)
+        });
+    }
+
+    /// Whether the cover is written past ratatui with escape sequences
+    pub fn is_graphics(self) -> bool {
+        matches!(self, CoverProtocol::Kitty | CoverProtocol::Iterm2 | CoverProtocol::Sixel)
+    }
+
+    /// What removes a picture shown with this protocol; the others are overwritten by a full redraw
+    pub fn clear_sequence(self) -> Option<String> {
+        (self == CoverProtocol::Kitty).then(|| passed_through("\x1b_Ga=d,d=A,q=2\x1b\\"))
+    }
+}
+
+/// A decoded cover, or a comic page shown the same way
+#[derive(Debug, Clone)]
+pub struct CoverImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<[u8; 3]>,
+    /// The file as read, which iTerm2 takes as it is
+    encoded: Vec<u8>,
+}
+
+impl CoverImage {
+    /// Read and decode a book's `cover.jpg`
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("Failed to read cover: {}", path.display()))?;
+        Self::decode(data, &format!("cover: {}", path.display()))
+    }
+
+    /// Decode a JPEG or PNG file; `what` names it in errors, e.g. "page 3 of Watchmen.cbz"
+    pub fn decode(encoded: Vec<u8>, what: &str) -> Result<Self> {
+        if encoded.starts_with(PNG_SIGNATURE) {
+            return Self::decode_png(encoded, what);
+        }
+        let mut decoder = jpeg_decoder::Decoder::new(encoded.as_slice());
+        let data = decoder
+            .decode()
+            .map_err(|e| Error::Parse { context: format!("Invalid {}", what), message: e.to_string() })?;
+        let info = decoder.info().ok_or_else(|| Error::Other(format!("Invalid {}", what)))?;
+
+        let pixels = match info.pixel_format {
+            jpeg_decoder::PixelFormat::RGB24 => data.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect(),
+            jpeg_decoder::PixelFormat::L8 => data.iter().map(|&l| [l, l, l]).collect(),
+            jpeg_decoder::PixelFormat::L16 => data.chunks_exact(2).map(|p| [p[0], p[0], p[0]]).collect(),
+            jpeg_decoder::PixelFormat::CMYK32 => data
+                .chunks_exact(4)
+                .map(|p| {
+                    let k = 255 - p[3] as u32;
+                    let channel = |c: u8| ((255 - c as u32) * k / 255) as u8;
+                    [channel(p[0]), channel(p[1]), channel(p[2])]
+                })
+                .collect(),
+        };
+        Ok(CoverImage { width: info.width as u32, height: info.height as u32, pixels, encoded })
+    }
+
+    /// Decode a PNG, with transparent parts laid on white like a page
+    fn decode_png(encoded: Vec<u8>, what: &str) -> Result<Self> {
+        let invalid = |e: png::DecodingError| Error::Parse { context: format!("Invalid {}", what), message: e.to_string() };
+        let mut decoder = png::Decoder::new(encoded.as_slice());
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(invalid)?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut data).map_err(invalid)?;
+        data.truncate(frame.buffer_size());
+
+        let on_white = |c: u8, alpha: u8| ((c as u32 * alpha as u32 + 255 * (255 - alpha as u32)) / 255) as u8;
+        let pixels = match frame.color_type {
+            png::ColorType::Rgb => data.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect(),
+            png::ColorType::Rgba => data.chunks_exact(4).map(|p| [on_white(p[0], p[3]), on_white(p[1], p[3]), on_white(p[2], p[3])]).collect(),
+            png::ColorType::Grayscale => data.iter().map(|&l| [l, l, l]).collect(),
+            png::ColorType::GrayscaleAlpha => data
+                .chunks_exact(2)
+                .map(|p| {
+                    let l = on_white(p[0], p[1]);
+                    [l, l, l]
+                })
+                .collect(),
+            // EXPAND turns palettes into RGB, so this isn't reached
+            png::ColorType::Indexed => return Err(Error::Other(format!("Invalid {}: unexpanded palette", what))),
+        };
+        Ok(CoverImage { width: frame.width, height: frame.height, pixels, encoded })
+    }
+
+    /// The largest area inside `area` with the cover's proportions, at its top left; cells are
+    /// taken to be twice as tall as wide
+    pub fn fit(&self, area: Rect) -> Rect {
+        if self.width == 0 || self.height == 0 {
+            return Rect { width: 0, height: 0, ..area };
+        }
+        let mut width = area.width as u32;
+        let mut height = width * self.height / self.width / 2;
+        if height > area.height as u32 {
+            height = area.height as u32;
+            width = (height * 2 * self.width / self.height).min(area.width as u32);
+        }
+        Rect { width: width as u16, height: height as u16, ..area }
+    }
+
+    /// Shrink (or stretch) to `width` by `height`, averaging the pixels each one covers
+    fn scaled(&self, width: u32, height: u32) -> Vec<[u8; 3]> {
+        let mut out = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            let (y0, y1) = span(y, height, self.height);
+            for x in 0..width {
+                let (x0, x1) = span(x, width, self.width);
+                let mut sum = [0u32; 3];
+                for sy in y0..y1 {
+                    for sx in x0..x1 {
+                        let p = self.pixels[(sy * self.width + sx) as usize];
+                        for c in 0..3 {
+                            sum[c] += p[c] as u32;
+                        }
+                    }
+                }
+                let count = ((y1 - y0) * (x1 - x0)).max(1);
+                out.push([(sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8]);
+            }
+        }
+        out
+    }
+
+    /// Draw into `area` with half blocks, the top pixel as the foreground and the bottom as the background
+    pub fn render_blocks(&self, area: Rect, buf: &mut Buffer, support: ColorSupport) {
+        let (width, height) = (area.width as u32, area.height as u32 * 2);
+        let pixels = self.scaled(width, height);
+        let color = |p: [u8; 3]| support.map(Color::Rgb(p[0], p[1], p[2]));
+        for row in 0..area.height {
+            for col in 0..area.width {
+                let top = pixels[(row as u32 * 2 * width + col as u32) as usize];
+                let bottom = pixels[((row as u32 * 2 + 1) * width + col as u32) as usize];
+                buf.get_mut(area.x + col, area.y + row).set_char('▀').set_fg(color(top)).set_bg(color(bottom));
+            }
+        }
+    }
+
+    /// Escape sequences showing the cover over `area` with `protocol`, cursor movement included
+    ///
+    /// In a multiplexer the picture is wrapped for passthrough, each of kitty's chunks on its
+    /// own so no escape grows past what tmux buffers; the cursor is left to the multiplexer.
+    pub fn graphics_sequence(&self, protocol: CoverProtocol, area: Rect) -> String {
+        let mut out = format!("\x1b[{};{}H", area.y + 1, area.x + 1);
+        let (cell_width, cell_height) = cell_pixels();
+        let fit = |w: u32, h: u32| {
+            let scale = (MAX_GRAPHICS_SIZE as f64 / w.max(h) as f64).min(1.0);
+            (((w as f64 * scale) as u32).max(1), ((h as f64 * scale) as u32).max(1))
+        };
+        let (width, height) = fit(area.width as u32 * cell_width, area.height as u32 * cell_height);
+        let engine = base64::engine::general_purpose::STANDARD;
+
+        match protocol {
+            CoverProtocol::Kitty => {
+                let data = engine.encode(self.scaled(width, height).concat());
+                let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let more = (i + 1 < chunks.len()) as u8;
+                    let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+                    let escape = if i == 0 {
+                        format!(
+                            "\x1b_Ga=T,f=24,s={},v={},c={},r={},C=1,q=2,m={};{}\x1b\\",
+                            width, height, area.width, area.height, more, chunk
+                        )
+                    } else {
+                        format!("\x1b_Gm={};{}\x1b\\", more, chunk)
+                    };
+                    out.push_str(&passed_through(&escape));
+                }
+            }
+            CoverProtocol::Iterm2 => {
+                let escape = format!(
+                    "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+                    self.encoded.len(),
+                    area.width,
+                    area.height,
+                    engine.encode(&self.encoded)
+                );
+                out.push_str(&passed_through(&escape));
+            }
+            CoverProtocol::Sixel => out.push_str(&passed_through(&sixel(&self.scaled(width, height), width, height))),
+            CoverProtocol::Auto | CoverProtocol::HalfBlocks | CoverProtocol::Off => {}
+        }
+        out
+    }
+}
+
+/// A terminal multiplexer tuilibre runs in, which has to be told to pass graphics on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Multiplexer {
+    Tmux,
+    Screen,
+}
+
+impl Multiplexer {
+    /// Whether its passthrough escape gets to the outer terminal: tmux 3.3 and later drop it
+    /// unless `allow-passthrough` is on, while older versions and screen always pass it
+    fn passes_through(self) -> bool {
+        match self {
+            Multiplexer::Tmux => {
+                // Set on the pane, or else globally; empty from a tmux without the option
+                let option = |scope: &str| {
+                    let output = std::process::Command::new("tmux").args(["show-options", scope, "allow-passthrough"]).output().ok()?;
+                    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+                };
+                match option("-pqv").filter(|value| !value.is_empty()).or_else(|| option("-gqv")) {
+                    Some(value) => value != "off",
+                    None => false,
+                }
+            }
+            Multiplexer::Screen => true,
+        }
+    }
+
+    /// `sequence` wrapped in the passthrough escape, which hands its contents to the outer
+    /// terminal as they are
+    fn wrap(self, sequence: &str) -> String {
+        match self {
+            Multiplexer::Tmux => format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b")),
+            // screen ends the escape at the first ST, so one is split between two escapes, and
+            // it drops strings longer than its limit, so long ones are split up too
+            Multiplexer::Screen => {
+                let mut out = String::new();
+                let mut chunk = String::new();
+                let mut previous = '\0';
+                for c in sequence.chars() {
+                    if chunk.len() >= SCREEN_CHUNK || (previous == '\x1b' && c == '\\') {
+                        let _ = write!(out, "\x1bP{}\x1b\\", std::mem::take(&mut chunk));
+                    }
+                    chunk.push(c);
+                    previous = c;
+                }
+                if !chunk.is_empty() {
+                    let _ = write!(out, "\x1bP{}\x1b\\", chunk);
+                }
+                out
+            }
+        }
+    }
+}
+
+/// The multiplexer tuilibre runs in, if any
+fn multiplexer() -> Option<Multiplexer> {
+    if std::env::var_os("TMUX").is_some() {
+        Some(Multiplexer::Tmux)
+    } else if std::env::var_os("STY").is_some() {
+        Some(Multiplexer::Screen)
+    } else {
+        None
+    }
+}
+
+/// The multiplexer whose passthrough graphics have to be wrapped in; None outside one, or when
+/// it won't pass them on
+fn passthrough() -> Option<Multiplexer> {
+    *PASSTHROUGH.get_or_init(|| multiplexer().filter(|multiplexer| multiplexer.passes_through()))
+}
+
+/// `sequence` as it has to be written to reach the terminal, wrapped for a multiplexer
+fn passed_through(sequence: &str) -> String {
+    match passthrough() {
+        Some(multiplexer) => multiplexer.wrap(sequence),
+        None => sequence.to_string(),
+    }
+}
+
+/// The protocol `TERM`, `TERM_PROGRAM` and kitty's own variable suggest, for terminals that
+/// couldn't be asked; inside tmux `TERM` is tmux's own, so the outer terminal's name is asked
+/// of tmux
+fn from_environment() -> CoverProtocol {
+    let outer = (multiplexer() == Some(Multiplexer::Tmux))
+        .then(|| std::process::Command::new("tmux").args(["display-message", "-p", "#{client_termname}"]).output().ok())
+        .flatten()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|name| !name.is_empty());
+    let term = outer.or_else(|| std::env::var("TERM").ok()).unwrap_or_default().to_lowercase();
+    let program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+    if std::env::var_os("KITTY_WINDOW_ID").is_some() || term.contains("kitty") || program == "WezTerm" || program == "ghostty" {
+        CoverProtocol::Kitty
+    } else if program == "iTerm.app" {
+        CoverProtocol::Iterm2
+    } else if term.contains("sixel") || term.starts_with("foot") || term.starts_with("mlterm") {
+        CoverProtocol::Sixel
+    } else {
+        CoverProtocol::HalfBlocks
+    }
+}
+
+/// The protocol the terminal says it speaks; None when it didn't answer in time
+fn query_terminal() -> Option<CoverProtocol> {
+    // Both wrapped, so the device attributes come from the outer terminal and not tmux
+    let reply = terminal_reply(&format!("{}{}", passed_through(KITTY_QUERY), passed_through(DEVICE_ATTRIBUTES_QUERY)))?;
+    if reply.contains("_Gi=31;OK") {
+        return Some(CoverProtocol::Kitty);
+    }
+    // e.g. "\x1b[?62;4;22c"
+    let start = reply.find("\x1b[?")? + 3;
+    let end = start + reply[start..].find('c')?;
+    let sixel = reply[start..end].split(';').any(|attribute| attribute == "4");
+    Some(if sixel { CoverProtocol::Sixel } else { CoverProtocol::HalfBlocks })
+}
+
+/// Write `queries` to the terminal and read what it answers, up to the device attributes
+/// that end every answer
+#[cfg(unix)]
+fn terminal_reply(queries: &str) -> Option<String> {
+    use std::io::{Read, Write};
+    use std::os::fd::AsRawFd;
+    use std::time::Instant;
+
+    let mut tty = std::fs::OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+    tty.write_all(queries.as_bytes()).ok()?;
+    tty.flush().ok()?;
+
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let mut pollfd = libc::pollfd { fd: tty.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        // SAFETY: one valid pollfd for the duration of the call
+        if left.is_zero() || unsafe { libc::poll(&mut pollfd, 1, left.as_millis() as libc::c_int) } <= 0 {
+            return None;
+        }
+        let read = tty.read(&mut buf).ok().filter(|&n| n > 0)?;
+        reply.extend_from_slice(&buf[..read]);
+        let text = String::from_utf8_lossy(&reply);
+        if text.find("\x1b[?").is_some_and(|at| text[at..].contains('c')) {
+            return Some(text.into_owned());
+        }
+    }
+}
+
+/// Without a way to wait on the console with a timeout, the environment decides
+#[cfg(not(unix))]
+fn terminal_reply(_queries: &str) -> Option<String> {
+    None
+}
+
+/// Source pixels `[start, end)` that output pixel `i` of `out` covers, out of `source`
+fn span(i: u32, out: u32, source: u32) -> (u32, u32) {
+    let start = i * source / out;
+    let end = ((i + 1) * source / out).max(start + 1).min(source);
+    (start.min(source.saturating_sub(1)), end)
+}
+
+/// Size of a cell in pixels, as the terminal reports it
+fn cell_pixels() -> (u32, u32) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => {
+            (size.width as u32 / size.columns as u32, size.height as u32 / size.rows as u32)
+        }
+        _ => FALLBACK_CELL_PIXELS,
+    }
+}
+
+/// Encode as sixels, quantized to the 6x6x6 color cube
+fn sixel(pixels: &[[u8; 3]], width: u32, height: u32) -> String {
+    let level = |c: u8| (c as u32 * 5 + 127) / 255;
+    let index: Vec<u32> = pixels.iter().map(|p| level(p[0]) * 36 + level(p[1]) * 6 + level(p[2])).collect();
+
+    let mut out = format!("\x1bP0;1q\"1;1;{};{}", width, height);
+    for i in 0..216 {
+        let percent = |l: u32| l * 100 / 5;
+        let _ = write!(out, "#{};2;{};{};{}", i, percent(i / 36), percent(i / 6 % 6), percent(i % 6));
+    }
+
+    for band in (0..height).step_by(6) {
+        let rows = (height - band).min(6);
+        let mut colors: Vec<u32> = (band..band + rows)
+            .flat_map(|y| index[(y * width) as usize..((y + 1) * width) as usize].iter().copied())
+            .collect();
+        colors.sort_unstable();
+        colors.dedup();
+
+        for color in colors {
+            let _ = write!(out, "#{}", color);
+            let mut run: Option<(char, u32)> = None;
+            for x in 0..width {
+                let bits = (0..rows)
+                    .filter(|&r| index[((band + r) * width + x) as usize] == color)
+                    .fold(0u8, |bits, r| bits | 1 << r);
+                let c = (63 + bits) as char;
+                run = match run {
+                    Some((prev, count)) if prev == c => Some((prev, count + 1)),
+                    Some((prev, count)) => {
+                        push_run(&mut out, prev, count);
+                        Some((c, 1))
+                    }
+                    None => Some((c, 1)),
+                };
+            }
+            if let Some((c, count)) = run {
+                push_run(&mut out, c, count);
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+/// A run of one sixel character, repeat-encoded when that is shorter
+fn push_run(out: &mut String, c: char, count: u32) {
+    if count > 3 {
+        let _ = write!(out, "!{}{}", count, c);
+    } else {
+        out.extend(std::iter::repeat_n(c, count as usize));
+    }
+}
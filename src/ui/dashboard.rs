@@ -0,0 +1,167 @@
+use crate::error::Result;
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{Connection, QueryBuilder, Sqlite, SqliteConnection};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::history::{LibraryEntry, LibraryHistory};
+use crate::settings::{LibrarySettingsStore, ReadStatus};
+use crate::ui::layout::Density;
+use crate::ui::theme::Theme;
+use crate::utils::locale::Locale;
+use crate::utils::time::{format_time, TimeFormat};
+
+/// Libraries the dashboard can open with a digit key
+pub const DIGIT_JUMPS: usize = 9;
+
+/// Start screen summarizing every library in history
+pub struct Dashboard {
+    summaries: Vec<LibrarySummary>,
+    pub selected: usize,
+    time_format: TimeFormat,
+    locale: Locale,
+    accessible: bool,
+    theme: Theme,
+    density: Density,
+}
+
+/// What a library in history holds, for the dashboard
+#[derive(Debug, Clone)]
+pub struct LibrarySummary {
+    pub path: PathBuf,
+    pub name: String,
+    pub pinned: bool,
+    pub last_used: DateTime<Utc>,
+    pub book_count: i64,
+    /// Titles most recently added, newest first
+    pub recent: Vec<String>,
+    /// Titles marked as being read in tuilibre
+    pub reading: Vec<String>,
+    /// Why the library couldn't be read, if it couldn't
+    pub error: Option<String>,
+}
+
+impl Dashboard {
+    /// Summarize the libraries in history, or `None` when there are too few to be worth it
+    ///
+    /// The dashboard replaces the selector for people with two or more libraries.
+    pub async fn load(config: &Config) -> Option<Dashboard> {
+        if !config.dashboard.enabled {
+            return None;
+        }
+        let history = LibraryHistory::load().ok()?;
+        let entries: Vec<&LibraryEntry> = history
+            .get_libraries()
+            .iter()
+            .filter(|entry| entry.path.join("metadata.db").is_file())
+            .collect();
+        if entries.len() < 2 {
+            return None;
+        }
+
+        let settings = LibrarySettingsStore::load().unwrap_or_default();
+        let mut summaries = Vec::new();
+        for entry in entries {
+            let reading: Vec<i32> = settings
+                .get(&entry.path)
+                .map(|s| s.reading.iter().filter(|(_, status)| **status == ReadStatus::Reading).map(|(id, _)| *id).collect())
+                .unwrap_or_default();
+            summaries.push(summarize(entry, &reading, config.dashboard.recent).await);
+        }
+        // Pinned libraries first, the rest as history orders them (last used first)
+        summaries.sort_by_key(|s| !s.pinned);
+
+        let display = &config.display;
+        Some(Dashboard {
+            summaries,
+            selected: 0,
+            time_format: display.time_format.clone(),
+            locale: display.locale(),
+            accessible: config.accessibility.enabled,
+            theme: Theme::from_config(display),
+            density: display.density,
+        })
+    }
+
+    pub fn summaries(&self) -> &[LibrarySummary] {
+        &self.summaries
+    }
+
+    pub fn selected_library(&self) -> Option<&LibrarySummary> {
+        self.summaries.get(self.selected)
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(self.summaries.len().saturating_sub(1));
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Whether to render for screen readers
+    pub fn is_accessible(&self) -> bool {
+        self.accessible
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn density(&self) -> Density {
+        self.density
+    }
+
+    /// When a library was last opened, as configured for display
+    pub fn format_last_used(&self, library: &LibrarySummary) -> String {
+        format_time(library.last_used, &self.time_format, false, &self.locale)
+    }
+
+    /// Number of books in a library, grouped as the locale writes numbers
+    pub fn format_book_count(&self, library: &LibrarySummary) -> String {
+        self.locale.format_number(library.book_count.max(0) as usize)
+    }
+}
+
+/// Read a library's counts and titles without taking its lock, noting the error if it fails
+async fn summarize(entry: &LibraryEntry, reading: &[i32], recent: usize) -> LibrarySummary {
+    let mut summary = LibrarySummary {
+        path: entry.path.clone(),
+        name: entry.display_name(),
+        pinned: entry.pinned,
+        last_used: entry.last_used,
+        book_count: entry.book_count.unwrap_or(0) as i64,
+        recent: Vec::new(),
+        reading: Vec::new(),
+        error: None,
+    };
+    if let Err(e) = fill_summary(&mut summary, &entry.path, reading, recent).await {
+        summary.error = Some(e.to_string());
+    }
+    summary
+}
+
+async fn fill_summary(summary: &mut LibrarySummary, path: &Path, reading: &[i32], recent: usize) -> Result<()> {
+    let options = SqliteConnectOptions::new().filename(path.join("metadata.db")).read_only(true);
+    let mut conn = SqliteConnection::connect_with(&options).await?;
+
+    summary.book_count = sqlx::query_scalar("SELECT COUNT(*) FROM books").fetch_one(&mut conn).await?;
+    summary.recent = sqlx::query_scalar("SELECT title FROM books ORDER BY timestamp DESC, id DESC LIMIT ?")
+        .bind(recent as i64)
+        .fetch_all(&mut conn)
+        .await?;
+
+    if !reading.is_empty() {
+        let mut query: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT title FROM books WHERE id IN (");
+        let mut ids = query.separated(", ");
+        for id in reading {
+            ids.push_bind(*id);
+        }
+        query.push(") ORDER BY sort");
+        summary.reading = query.build_query_scalar().fetch_all(&mut conn).await?;
+    }
+
+    conn.close().await?;
+    Ok(())
+}
@@ -46,4 +46,39 @@ impl EventHandler {
             _ => None,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Largest count accepted as a prefix, so mistyped digits can't overflow
+const MAX_COUNT: usize = 99_999;
+
+/// Keys typed ahead of a command, such as the count in `5j`
+#[derive(Debug, Default)]
+pub struct PendingKeys {
+    count: Option<usize>,
+}
+
+impl PendingKeys {
+    /// Feed a key in; returns the completed command with its count,
+    /// or None while the key only extended (or cancelled) the prefix
+    pub fn feed(&mut self, key: KeyEvent) -> Option<(Option<usize>, KeyEvent)> {
+        match key.code {
+            // A leading 0 isn't a count, matching vim
+            KeyCode::Char(c @ '0'..='9') if c != '0' || self.count.is_some() => {
+                let digit = c.to_digit(10).unwrap_or(0) as usize;
+                let count = self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit);
+                self.count = Some(count.min(MAX_COUNT));
+                None
+            }
+            KeyCode::Esc if self.count.is_some() => {
+                self.count = None;
+                None
+            }
+            _ => Some((self.count.take(), key)),
+        }
+    }
+
+    /// The count typed so far, if any
+    pub fn count(&self) -> Option<usize> {
+        self.count
+    }
+}
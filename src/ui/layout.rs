@@ -1,7 +1,55 @@
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
-    widgets::Block,
+    widgets::{block::Title, Block, Borders},
 };
+use serde::{Deserialize, Serialize};
+
+/// How much room borders and bars take, from roomy to as many rows as possible
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Density {
+    /// Bordered title and status bars and panes
+    #[default]
+    Comfortable,
+    /// One-line title and status bars; panes keep their borders
+    Compact,
+    /// One-line bars and no borders or pane titles at all
+    Borderless,
+}
+
+impl Density {
+    /// Height of the title and status bars
+    pub fn bar_height(self) -> u16 {
+        match self {
+            Density::Comfortable => 3,
+            Density::Compact | Density::Borderless => 1,
+        }
+    }
+
+    /// Block around the title and status bars
+    pub fn bar_block(self) -> Block<'static> {
+        match self {
+            Density::Comfortable => Block::default().borders(Borders::ALL),
+            Density::Compact | Density::Borderless => Block::default(),
+        }
+    }
+
+    /// Block around a content pane
+    pub fn block<'a, T: Into<Title<'a>>>(self, title: T) -> Block<'a> {
+        match self {
+            Density::Comfortable | Density::Compact => Block::default().borders(Borders::ALL).title(title),
+            Density::Borderless => Block::default(),
+        }
+    }
+
+    /// Borders drawn around each card of the grid view
+    pub fn card_borders(self) -> Borders {
+        match self {
+            Density::Comfortable | Density::Compact => Borders::ALL,
+            Density::Borderless => Borders::NONE,
+        }
+    }
+}
 
 /// Layout management utilities for the TUI
 pub struct LayoutManager;
@@ -20,12 +68,14 @@ impl LayoutManager {
             .to_vec()
     }
 
-    /// Create content layout for book list view
-    pub fn create_book_list_layout(area: Rect) -> Vec<Rect> {
+    /// Create content layout for book list view: the tag browser, empty while hidden, then the books
+    pub fn create_book_list_layout(area: Rect, tag_browser: bool) -> Vec<Rect> {
+        let tags_width = if tag_browser { (area.width / 4).clamp(16, 32) } else { 0 };
         Layout::default()
             .direction(Direction::Horizontal)
             .constraints([
-                Constraint::Percentage(100),  // Book list (full width for MVP)
+                Constraint::Length(tags_width),  // Tag browser
+                Constraint::Min(0),              // Book list
             ])
             .split(area)
             .to_vec()
@@ -1,150 +1,390 @@
-use anyhow::Result;
+use crate::error::{Error, Result};
+use chrono::{DateTime, Utc};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     Frame, Terminal,
 };
-use std::io;
-use std::time::Duration;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 
-use crate::app::{App, AppMode, Book};
-use crate::database::Database;
+use crate::app::{
+    hint_labels, parse_authors, parse_calibre_search, Action, App, AppMode, AuditField, Book, BookFormat, CleanupView, ComicArchive, ComicViewer, DeleteConfirm, DetailsTab, DeviceView, EditForm, JobsView, Filter, FilterClause, Menu, MenuKind, MetadataAudit, OpdsPage,
+    OpdsView, PendingEdit, PolicyAudit, PolicyBreach, Prompt, PromptKind, Reader, SavedSearch, ScrollView, SearchSource, SearchStats, TableColumn,
+    UsageGrouping, UsageView, VirtualLibrary,
+};
+use crate::app::action::BATCH_ACTIONS;
+use crate::app::audiobook::AUDIO_FORMATS;
+use crate::app::comic::COMIC_FORMATS;
+use crate::app::reader::{annotations_markdown, load_chapters, READABLE_FORMATS};
+use crate::app::usage::{format_size, BIGGEST_MARKED};
+use crate::app::tag_browser::toggle_tag_filter;
+use crate::calibre_web::{self, CalibreWebUser};
+use crate::config::{Config, ShelfBacking};
+use crate::database::prefs::DisplayPrefs;
+use crate::database::write::calibre_timestamp;
+use crate::database::{database_stamp, snapshot_taken, BookChange, ContentMatch, Database, DatabaseStamp, EditOutcome};
+use crate::device;
+use crate::epub;
 use crate::history::LibraryHistory;
-use std::path::PathBuf;
+use crate::jobs::merge::MergeAction;
+use crate::jobs::{self, CancelToken, ConversionQueue, ConversionTask, EmbedItem, ExportPlan, JobHistory, JobKind, JobRecord, JobStatus, JobUpdate};
+use crate::metadata::MetadataProposal;
+use crate::opds::{self, OpdsFeed};
+use crate::queue::{QueuedAction, QueuedKind};
+use crate::settings::{LibrarySettings, LibrarySettingsStore, ReadStatus};
+use crate::shelves;
+use crate::utils::clipboard;
+use crate::utils::mounts::removable_mount;
+use crate::utils::time::format_time;
+use std::path::{Path, PathBuf};
 
+pub mod color;
 pub mod components;
+pub mod cover;
+pub mod dashboard;
 pub mod layout;
 pub mod events;
+pub mod picker;
 pub mod selector;
+pub mod splash;
+pub mod theme;
 
 use components::UIComponents;
+use cover::CoverProtocol;
+use dashboard::{Dashboard, DIGIT_JUMPS};
+use events::PendingKeys;
 use selector::LibrarySelector;
+use theme::Theme;
 
 /// Main UI handler for the application
 pub struct UI {
     components: UIComponents,
+    job_updates: Option<mpsc::UnboundedReceiver<JobUpdate>>,
+    /// Stops the running job when the user presses Esc or Ctrl-C
+    job_cancel: Option<CancelToken>,
+    /// The running job as it will be recorded in the job history, with when it started
+    job_record: Option<(JobRecord, Instant)>,
+    /// When this run of tuilibre started, recorded with each job
+    session: DateTime<Utc>,
+    /// Set when the process is asked to terminate
+    shutdown: CancelToken,
+    pending_keys: PendingKeys,
+    /// Focus last spelled out in accessibility mode
+    last_announcement: Option<String>,
+    /// When an offline library was last checked for being back
+    online_checked: Instant,
+    /// `metadata.db` as last seen, and when, to notice changes made elsewhere
+    library_stamp: (DatabaseStamp, Instant),
+    /// Cover last written with a graphics protocol and where, to clear once it moves or goes
+    cover_shown: Option<(PathBuf, Rect)>,
+}
+
+type Tui = Terminal<CrosstermBackend<io::Stdout>>;
+
+/// Enter raw mode with mouse capture, on the alternate screen unless it is turned off
+fn setup_terminal(alternate_screen: bool) -> Result<Tui> {
+    enable_raw_mode().map_err(Error::TerminalError)?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnableMouseCapture).map_err(Error::TerminalError)?;
+    if alternate_screen {
+        execute!(stdout, EnterAlternateScreen).map_err(Error::TerminalError)?;
+    }
+    Terminal::new(CrosstermBackend::new(stdout)).map_err(Error::TerminalError)
+}
+
+/// Undo `setup_terminal`
+fn restore_terminal(terminal: &mut Tui, alternate_screen: bool) -> Result<()> {
+    disable_raw_mode().map_err(Error::TerminalError)?;
+    execute!(terminal.backend_mut(), DisableMouseCapture).map_err(Error::TerminalError)?;
+    if alternate_screen {
+        execute!(terminal.backend_mut(), LeaveAlternateScreen).map_err(Error::TerminalError)?;
+    }
+    terminal.show_cursor().map_err(Error::TerminalError)
+}
+
+/// Kinds of filter offered by the "Add filter" menu, with the calibre tag browser category
+/// hiding it
+const FILTER_KINDS: [(&str, Option<&str>); 8] = [
+    ("Tag", Some("tags")),
+    ("Format", Some("formats")),
+    ("Audiobook", Some("formats")),
+    ("DRM", Some("formats")),
+    ("Language", Some("languages")),
+    ("Rating", Some("rating")),
+    ("Added", None),
+    ("Shelf", None),
+];
+
+/// Choices offered when an edit meets a change made elsewhere, in `resolve_conflict` order
+const CONFLICT_CHOICES: [&str; 3] = [
+    "Reload (discard my edit)",
+    "Overwrite with my edit",
+    "Merge (keep their other changes)",
+];
+
+/// Formats offered by the "Convert to" menu
+const CONVERT_FORMATS: [&str; 5] = ["EPUB", "AZW3", "MOBI", "PDF", "TXT"];
+
+/// Most books "Open marked books" starts a viewer for in one go
+const MAX_OPEN_AT_ONCE: usize = 20;
+
+/// How long to wait for a cancelled job to stop when the process is terminated
+const JOB_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to look for an offline library's share or drive coming back
+const ONLINE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a stopped library search gets to hand over what it found before it's abandoned
+const DISCOVERY_GRACE: Duration = Duration::from_secs(1);
+
+/// Where the user went from the dashboard
+enum DashboardChoice {
+    Open(PathBuf),
+    AllLibraries,
+    Quit,
+}
+
+/// Outcome of handling a key event in the main loop
+enum KeyOutcome {
+    Continue,
+    Quit,
+    SwitchLibrary,
+}
+
+impl Default for UI {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl UI {
     pub fn new() -> Self {
         UI {
             components: UIComponents::new(),
+            job_updates: None,
+            job_cancel: None,
+            job_record: None,
+            session: Utc::now(),
+            shutdown: CancelToken::default(),
+            pending_keys: PendingKeys::default(),
+            last_announcement: None,
+            online_checked: Instant::now(),
+            library_stamp: (DatabaseStamp::new(), Instant::now()),
+            cover_shown: None,
+        }
+    }
+
+    /// Leave the UI cleanly once `shutdown` is cancelled, e.g. by `utils::signals`
+    pub fn with_shutdown(mut self, shutdown: CancelToken) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Look for libraries in a task of its own, so a scan stuck on a slow network mount can be
+    /// stopped: Esc lists what was found so far, Ctrl-C (or a termination signal) gives None
+    async fn discover_libraries(&mut self, terminal: &mut Tui, theme: &Theme) -> Result<Option<LibrarySelector>> {
+        let cancel = CancelToken::default();
+        let token = cancel.clone();
+        let scan = tokio::spawn(async move {
+            let mut selector = LibrarySelector::new();
+            selector.discover_libraries(&token).await.map(|()| selector)
+        });
+
+        let started = Instant::now();
+        let mut stopped_at = None;
+        while !scan.is_finished() {
+            if self.shutdown.is_cancelled() {
+                cancel.cancel();
+                return Ok(None);
+            }
+            // A scan blocked in the file system can't see the cancel, so it's left behind
+            if stopped_at.is_some_and(|at: Instant| at.elapsed() >= DISCOVERY_GRACE) {
+                scan.abort();
+                let mut selector = LibrarySelector::new();
+                selector.discover_libraries(&cancel).await?;
+                return Ok(Some(selector));
+            }
+
+            terminal.draw(|f| {
+                self.components.render_discovering(f, f.size(), theme, started.elapsed(), stopped_at.is_some());
+            }).map_err(Error::TerminalError)?;
+
+            if event::poll(Duration::from_millis(100)).map_err(Error::TerminalError)? {
+                if let Event::Key(key) = event::read().map_err(Error::TerminalError)? {
+                    match key.code {
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            cancel.cancel();
+                            self.shutdown.cancel();
+                            return Ok(None);
+                        }
+                        KeyCode::Esc if stopped_at.is_none() => {
+                            cancel.cancel();
+                            stopped_at = Some(Instant::now());
+                        }
+                        _ => {}
+                    }
+                }
+            }
         }
+        let selector = scan.await.map_err(|e| Error::Other(format!("Library discovery failed: {}", e)))??;
+        Ok(Some(selector))
     }
 
     /// Show library selection UI and return selected library path
+    ///
+    /// None when nothing was picked; Ctrl-C also cancels the shutdown token, like a SIGINT.
     pub async fn select_library(&mut self) -> Result<Option<PathBuf>> {
         // Initialize terminal
-        enable_raw_mode()?;
-        let mut stdout = io::stdout();
-        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-        let backend = CrosstermBackend::new(stdout);
-        let mut terminal = Terminal::new(backend)?;
+        let config = Config::load().unwrap_or_default();
+        let alternate_screen = config.accessibility.alternate_screen;
+        let theme = Theme::from_config(&config.display);
+        let mut terminal = setup_terminal(alternate_screen)?;
+
+        // People with several libraries start from a summary of all of them
+        if let Some(mut dashboard) = Dashboard::load(&config).await {
+            match self.run_dashboard(&mut terminal, &mut dashboard)? {
+                DashboardChoice::Open(path) => {
+                    if let Err(e) = LibrarySelector::new().save_to_history(&path, None).await {
+                        eprintln!("Warning: Failed to save library to history: {}", e);
+                    }
+                    restore_terminal(&mut terminal, alternate_screen)?;
+                    return Ok(Some(path));
+                }
+                DashboardChoice::Quit => {
+                    restore_terminal(&mut terminal, alternate_screen)?;
+                    return Ok(None);
+                }
+                DashboardChoice::AllLibraries => {}
+            }
+        }
 
-        // Discover libraries
-        let mut selector = LibrarySelector::new();
-        selector.discover_libraries().await?;
+        // Discover libraries; Ctrl-C while looking quits
+        let Some(mut selector) = self.discover_libraries(&mut terminal, &theme).await? else {
+            restore_terminal(&mut terminal, alternate_screen)?;
+            return Ok(None);
+        };
 
         if !selector.has_libraries() {
             // Show no libraries found message
             loop {
                 terminal.draw(|f| {
-                    self.components.render_no_libraries(f, f.size());
-                })?;
+                    self.components.render_no_libraries(f, f.size(), &theme);
+                }).map_err(Error::TerminalError)?;
 
-                if event::poll(Duration::from_millis(250))? {
-                    if let Event::Key(_) = event::read()? {
+                if event::poll(Duration::from_millis(250)).map_err(Error::TerminalError)? {
+                    if let Event::Key(_) = event::read().map_err(Error::TerminalError)? {
                         break;
                     }
                 }
             }
 
             // Cleanup terminal
-            disable_raw_mode()?;
-            execute!(
-                terminal.backend_mut(),
-                LeaveAlternateScreen,
-                DisableMouseCapture
-            )?;
-            terminal.show_cursor()?;
+            restore_terminal(&mut terminal, alternate_screen)?;
             return Ok(None);
         }
 
         let mut selected_index = 0;
         let mut in_search_mode = false;
+        // Alias being typed for the selected library
+        let mut renaming: Option<String> = None;
 
         // Library selection loop
         loop {
+            if self.shutdown.is_cancelled() {
+                restore_terminal(&mut terminal, alternate_screen)?;
+                return Ok(None);
+            }
+
             terminal.draw(|f| {
-                // Check if we need to render filtered libraries or all libraries
-                if in_search_mode {
-                    // We need to modify the render function to support search mode indicator
-                    self.render_library_selection_with_search(f, f.size(), &selector, selected_index, in_search_mode);
-                } else {
-                    self.components.render_library_selection(f, f.size(), &selector, selected_index);
-                }
-            })?;
+                self.components.render_library_selection(f, f.size(), &selector, selected_index, in_search_mode, renaming.as_deref());
+            }).map_err(Error::TerminalError)?;
 
-            if event::poll(Duration::from_millis(250))? {
-                if let Event::Key(key) = event::read()? {
+            if event::poll(Duration::from_millis(250)).map_err(Error::TerminalError)? {
+                if let Event::Key(key) = event::read().map_err(Error::TerminalError)? {
+                    selector.clear_notice();
+                    if let Some(alias) = renaming.as_mut() {
+                        match key.code {
+                            KeyCode::Enter => {
+                                if let Err(e) = selector.rename(selected_index, alias) {
+                                    eprintln!("Warning: Failed to save library history: {}", e);
+                                }
+                                renaming = None;
+                            }
+                            KeyCode::Esc => renaming = None,
+                            KeyCode::Backspace => {
+                                alias.pop();
+                            }
+                            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => alias.push(c),
+                            _ => {}
+                        }
+                        continue;
+                    }
                     match key.code {
+                        // As a SIGINT would outside raw mode
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            self.shutdown.cancel();
+                            restore_terminal(&mut terminal, alternate_screen)?;
+                            return Ok(None);
+                        }
                         // Handle search mode toggle
                         KeyCode::Char('/') if !in_search_mode => {
                             in_search_mode = true;
                             selector.set_search_query(String::new());
                             selected_index = 0; // Reset selection when entering search
                         }
-                        KeyCode::Esc | KeyCode::Left => {
-                            if in_search_mode {
-                                // Exit search mode
-                                in_search_mode = false;
-                                selector.set_search_query(String::new());
-                                selected_index = 0;
-                            }
-                        }
-                        // Navigation keys (work in both modes)
-                        KeyCode::Up | KeyCode::Char('k') => {
-                            if selected_index > 0 {
-                                selected_index -= 1;
-                            }
+                        KeyCode::Esc | KeyCode::Left if in_search_mode => {
+                            // Exit search mode
+                            in_search_mode = false;
+                            selector.set_search_query(String::new());
+                            selected_index = 0;
                         }
-                        KeyCode::Down | KeyCode::Char('j') => {
-                            if selected_index < selector.get_filtered_libraries().len().saturating_sub(1) {
-                                selected_index += 1;
-                            }
+                        // Navigation keys (j/k are typed into the query while searching)
+                        KeyCode::Up => selected_index = selected_index.saturating_sub(1),
+                        KeyCode::Char('k') if !in_search_mode => selected_index = selected_index.saturating_sub(1),
+                        KeyCode::Down | KeyCode::Char('j') if key.code == KeyCode::Down || !in_search_mode => {
+                            let last = selector.get_filtered_libraries().len().saturating_sub(1);
+                            selected_index = (selected_index + 1).min(last);
                         }
                         // Selection
                         KeyCode::Enter | KeyCode::Right => {
-                            // Get the library from filtered results if in search mode, or from all libraries otherwise
-                            let library = if in_search_mode {
-                                selector.get_filtered_library(selected_index)
-                            } else {
-                                selector.get_library(selected_index)
-                            };
-
-                            if let Some(library) = library {
+                            // An offline library opens once its share or drive is back, or else from its snapshot
+                            let online = selector.retry(selected_index);
+                            let from_snapshot = !online
+                                && selector.get_filtered_library(selected_index).is_some_and(|lib| lib.snapshot.is_some());
+                            if !online && !from_snapshot {
+                                continue;
+                            }
+                            // The list shown is always the filtered one; without a query it holds every library
+                            if let Some(library) = selector.get_filtered_library(selected_index) {
                                 // Clone the path to avoid borrowing issues
                                 let library_path = library.path.clone();
-                                let library_name = Some(library.name.clone());
+                                // `name` may be an alias, which history keeps separately
+                                let library_name = library.path.file_name().and_then(|n| n.to_str()).map(str::to_string);
+
+                                // A library found after a move takes over its old history entry
+                                if library.moved_from.is_some() {
+                                    if let Err(e) = selector.relink(selected_index) {
+                                        eprintln!("Warning: Failed to save library history: {}", e);
+                                    }
+                                }
 
-                                // Save to history with book count
-                                if let Err(e) = selector.save_to_history(&library_path, library_name).await {
-                                    eprintln!("Warning: Failed to save library to history: {}", e);
+                                // Save to history with book count; a snapshot's path can't tell its mount
+                                if !from_snapshot {
+                                    if let Err(e) = selector.save_to_history(&library_path, library_name).await {
+                                        eprintln!("Warning: Failed to save library to history: {}", e);
+                                    }
                                 }
 
                                 // Cleanup terminal
-                                disable_raw_mode()?;
-                                execute!(
-                                    terminal.backend_mut(),
-                                    LeaveAlternateScreen,
-                                    DisableMouseCapture
-                                )?;
-                                terminal.show_cursor()?;
+                                restore_terminal(&mut terminal, alternate_screen)?;
                                 return Ok(Some(library_path));
                             }
                         }
@@ -161,16 +401,20 @@ impl UI {
                             selector.set_search_query(current_query);
                             selected_index = 0; // Reset selection when search changes
                         }
+                        KeyCode::Char('t') if !in_search_mode => selector.toggle_absolute_times(),
+                        KeyCode::Char('r') if !in_search_mode && selector.has_filtered_libraries() => {
+                            renaming = Some(selector.alias(selected_index).unwrap_or_default().to_string());
+                        }
+                        KeyCode::Char('p') if !in_search_mode => {
+                            match selector.toggle_pinned(selected_index) {
+                                Ok(index) => selected_index = index,
+                                Err(e) => eprintln!("Warning: Failed to save library history: {}", e),
+                            }
+                        }
                         // Quit
                         KeyCode::Char('q') if !in_search_mode => {
                             // Cleanup terminal
-                            disable_raw_mode()?;
-                            execute!(
-                                terminal.backend_mut(),
-                                LeaveAlternateScreen,
-                                DisableMouseCapture
-                            )?;
-                            terminal.show_cursor()?;
+                            restore_terminal(&mut terminal, alternate_screen)?;
                             return Ok(None);
                         }
                         _ => {}
@@ -180,366 +424,3916 @@ impl UI {
         }
     }
 
-    /// Render library selection with search mode support
-    fn render_library_selection_with_search(&self, frame: &mut Frame, area: ratatui::layout::Rect, selector: &LibrarySelector, selected_index: usize, in_search_mode: bool) {
-        use ratatui::{
-            layout::{Constraint, Direction, Layout},
-            style::{Color, Style},
-            text::{Line, Span},
-            widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
-        };
-
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Length(3),  // Title bar
-                Constraint::Min(0),      // Library list
-                Constraint::Length(3),  // Status bar
-            ])
-            .split(area);
-
-        // Render title bar with search indicator
-        let title = if in_search_mode {
-            format!("搜索: {} (按 ESC 退出搜索)", selector.get_search_query())
-        } else {
-            "选择 calibre 图书馆".to_string()
-        };
-        let title_widget = Paragraph::new(title)
-            .style(Style::default().fg(Color::Cyan))
-            .block(Block::default().borders(Borders::ALL));
-
-        frame.render_widget(title_widget, chunks[0]);
-
-        // Render library list (using filtered libraries if in search mode)
-        let libraries = if in_search_mode {
-            selector.get_filtered_libraries()
-        } else {
-            selector.get_libraries()
-        };
-
-        let items: Vec<ListItem> = libraries
-            .iter()
-            .enumerate()
-            .map(|(i, lib)| {
-                let style = if i == selected_index {
-                    Style::default().bg(Color::Blue).fg(Color::White)
-                } else {
-                    Style::default()
-                };
+    /// Show the dashboard until the user opens a library, asks for all of them, or quits
+    fn run_dashboard(&mut self, terminal: &mut Tui, dashboard: &mut Dashboard) -> Result<DashboardChoice> {
+        loop {
+            if self.shutdown.is_cancelled() {
+                return Ok(DashboardChoice::Quit);
+            }
 
-                let book_count = lib.book_count.unwrap_or(0);
-                let mut content = if lib.from_history {
-                    format!("⭐ {} - {} ({} 本书)",
-                        lib.name,
-                        lib.path.display(),
-                        book_count
-                    )
-                } else {
-                    format!("{} - {} ({} 本书)",
-                        lib.name,
-                        lib.path.display(),
-                        book_count
-                    )
-                };
+            terminal.draw(|f| {
+                self.components.render_dashboard(f, f.size(), dashboard);
+            }).map_err(Error::TerminalError)?;
 
-                // Add last used info for history libraries
-                if let Some(last_used) = &lib.last_used {
-                    content.push_str(&format!(" [上次使用: {}]", last_used));
+            if !event::poll(Duration::from_millis(250)).map_err(Error::TerminalError)? {
+                continue;
+            }
+            let Event::Key(key) = event::read().map_err(Error::TerminalError)? else {
+                continue;
+            };
+            match key.code {
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.shutdown.cancel();
+                    return Ok(DashboardChoice::Quit);
                 }
-
-                ListItem::new(content).style(style)
-            })
-            .collect();
-
-        let list = List::new(items)
-            .block(Block::default().borders(Borders::ALL).title("发现的图书馆"));
-
-        let mut list_state = ListState::default();
-        list_state.select(Some(selected_index));
-
-        frame.render_stateful_widget(list, chunks[1], &mut list_state);
-
-        // Render status bar with search controls
-        let help_text = if in_search_mode {
-            "输入搜索 | ↑↓/j/k 导航 | Enter 选择 | ESC 退出搜索 | q 退出"
-        } else {
-            "↑↓/j/k 导航 | Enter 选择 | / 搜索 | q 退出 | ⭐ = 历史记录中的库"
-        };
-        let status_widget = Paragraph::new(help_text)
-            .style(Style::default().fg(Color::Gray))
-            .block(Block::default().borders(Borders::ALL));
-
-        frame.render_widget(status_widget, chunks[2]);
+                KeyCode::Char('q') => return Ok(DashboardChoice::Quit),
+                KeyCode::Char('l') | KeyCode::Char('/') | KeyCode::Tab => return Ok(DashboardChoice::AllLibraries),
+                KeyCode::Up | KeyCode::Char('k') => dashboard.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => dashboard.select_next(),
+                // "3" opens the third library straight away
+                KeyCode::Char(c @ '1'..='9') => {
+                    let index = c as usize - '1' as usize;
+                    if index < DIGIT_JUMPS && index < dashboard.summaries().len() {
+                        dashboard.selected = index;
+                        return Ok(DashboardChoice::Open(dashboard.summaries()[index].path.clone()));
+                    }
+                }
+                KeyCode::Enter | KeyCode::Right => {
+                    if let Some(library) = dashboard.selected_library() {
+                        return Ok(DashboardChoice::Open(library.path.clone()));
+                    }
+                }
+                _ => {}
+            }
+        }
     }
 
     /// Run the main application loop
-    /// Returns Some(new_library_path) if user wants to switch libraries, None if normal exit
+    /// Returns Some(new_library_path) if user wants to switch libraries, None if normal exit;
+    /// the path is empty to ask for the library selector, or the open library's own path
+    /// when it came back online and should be reopened from its `metadata.db`
     pub async fn run(&mut self, app: &mut App, database: &Database) -> Result<Option<PathBuf>> {
         // Initialize terminal
-        enable_raw_mode()?;
-        let mut stdout = io::stdout();
-        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-        let backend = CrosstermBackend::new(stdout);
-        let mut terminal = Terminal::new(backend)?;
+        let alternate_screen = app.config.accessibility.alternate_screen;
+        let mut terminal = setup_terminal(alternate_screen)?;
+        if app.config.display.graphics == CoverProtocol::Auto {
+            CoverProtocol::detect();
+        }
+        self.library_stamp = (database_stamp(&app.library_path), Instant::now());
 
         // Main event loop
         loop {
             // Check if we need to switch to library selection
             if app.mode == AppMode::LibrarySelection {
                 // Cleanup terminal
-                disable_raw_mode()?;
-                execute!(
-                    terminal.backend_mut(),
-                    LeaveAlternateScreen,
-                    DisableMouseCapture
-                )?;
-                terminal.show_cursor()?;
+                restore_terminal(&mut terminal, alternate_screen)?;
                 return Ok(Some(PathBuf::new())); // Signal to show library selector
             }
 
+            // Pick up progress from any running background job
+            let changed = self.poll_job_updates(app);
+            if !changed.is_empty() {
+                self.reload_books(app, database, &changed).await;
+            }
+
+            if self.shutdown.is_cancelled() {
+                self.finish_job(app).await;
+                break;
+            }
+
+            // An offline library is reopened once its share or drive is back, outside of jobs
+            if database.snapshot().is_some() && app.job.is_none() && self.online_checked.elapsed() >= ONLINE_CHECK_INTERVAL {
+                self.online_checked = Instant::now();
+                if app.library_path.join("metadata.db").is_file() {
+                    restore_terminal(&mut terminal, alternate_screen)?;
+                    return Ok(Some(app.library_path.clone()));
+                }
+            }
+
+            self.check_library_changed(app, database).await;
+
+            if let Some(took) = database.take_slow_query() {
+                self.warn_slow_library(app, database, took).await;
+            }
+
+            // A large library comes in a page at a time, with details only for the books in view
+            self.load_next_page(app, database).await;
+            let rows = terminal.size().map_err(Error::TerminalError)?.height as usize;
+            let missing = app.ids_missing_details(rows);
+            self.load_details(app, database, &missing).await;
+
             // Render UI
             terminal.draw(|f| {
                 self.render(f, app);
-            })?;
+            }).map_err(Error::TerminalError)?;
+            self.show_cover(&mut terminal, app)?;
 
-            // Handle events
-            if event::poll(Duration::from_millis(250))? {
-                if let Event::Key(key) = event::read()? {
-                    match self.handle_key_event(key, app, database).await? {
-                        Some(new_path) => {
+            // Handle events, not waiting for any while pages are left to load
+            let wait = if app.pages.is_some() { Duration::ZERO } else { Duration::from_millis(250) };
+            if event::poll(wait).map_err(Error::TerminalError)? {
+                let event = event::read().map_err(Error::TerminalError)?;
+                if let Event::Mouse(mouse) = event {
+                    self.handle_mouse_event(mouse, app, database).await;
+                    self.announce_focus(app);
+                }
+                if let Event::Key(key) = event {
+                    let outcome = self.handle_key_event(key, app, database).await?;
+                    self.announce_focus(app);
+                    match outcome {
+                        KeyOutcome::Continue => {}
+                        KeyOutcome::Quit => break,
+                        KeyOutcome::SwitchLibrary => {
                             // Cleanup terminal
-                            disable_raw_mode()?;
-                            execute!(
-                                terminal.backend_mut(),
-                                LeaveAlternateScreen,
-                                DisableMouseCapture
-                            )?;
-                            terminal.show_cursor()?;
-                            return Ok(Some(new_path));
-                        },
-                        None => {
-                            // Continue or handle exit
+                            restore_terminal(&mut terminal, alternate_screen)?;
+                            return Ok(Some(PathBuf::new())); // Signal to show library selector
                         }
                     }
                 }
             }
         }
 
+        // Quitting by key or by signal, keep what only lives in memory
+        self.save_session_state(app);
+
         // Cleanup terminal
-        disable_raw_mode()?;
-        execute!(
-            terminal.backend_mut(),
-            LeaveAlternateScreen,
-            DisableMouseCapture
-        )?;
-        terminal.show_cursor()?;
+        restore_terminal(&mut terminal, alternate_screen)?;
 
         Ok(None)
     }
 
+    /// Save the open book's reading position and annotations, which are otherwise saved when
+    /// the reader is closed
+    fn save_session_state(&self, app: &mut App) {
+        if app.reader.is_some() {
+            self.save_reading_position(app);
+            self.save_annotations(app);
+        }
+    }
+
+    /// Write the details view's cover past ratatui when a graphics protocol draws it, clearing
+    /// the one shown before once it moved or went away
+    fn show_cover(&mut self, terminal: &mut Tui, app: &App) -> Result<()> {
+        let placement = self.components.cover_placement().map(|(path, area, _)| (path.to_path_buf(), area));
+        if placement == self.cover_shown {
+            return Ok(());
+        }
+        let protocol = app.config.display.graphics.resolve();
+        if self.cover_shown.take().is_some() {
+            match protocol.clear_sequence() {
+                Some(clear) => write!(terminal.backend_mut(), "{}", clear).map_err(Error::TerminalError)?,
+                None => {
+                    // Sixel and iTerm2 pictures are just cells to the terminal, so repaint them all
+                    terminal.clear().map_err(Error::TerminalError)?;
+                    terminal.draw(|f| self.render(f, app)).map_err(Error::TerminalError)?;
+                }
+            }
+        }
+        if let Some((_, area, image)) = self.components.cover_placement() {
+            let sequence = image.graphics_sequence(protocol, area);
+            write!(terminal.backend_mut(), "{}", sequence).map_err(Error::TerminalError)?;
+        }
+        terminal.backend_mut().flush().map_err(Error::TerminalError)?;
+        self.cover_shown = placement;
+        Ok(())
+    }
+
+    /// In accessibility mode, spell out a new focus in the status line unless a message is showing
+    fn announce_focus(&mut self, app: &mut App) {
+        if !app.accessible() {
+            return;
+        }
+        let announcement = app.announcement();
+        if announcement != self.last_announcement {
+            if app.status_message.is_none() {
+                app.status_message = announcement.clone();
+            }
+            self.last_announcement = announcement;
+        }
+    }
+
     /// Main render function
+    #[tracing::instrument(skip_all, fields(mode = ?app.mode))]
     fn render(&mut self, frame: &mut Frame, app: &App) {
+        self.components.clear_cover_placement();
+        let in_list = matches!(app.mode, AppMode::Normal | AppMode::Search);
+        let show_chips = !app.filters.is_empty() && in_list;
+        let search_error = if app.mode == AppMode::Search { app.search_error() } else { None };
+        let quick_filters = &app.config.quick_filters;
+        let show_quick_bar = quick_filters.show_bar && !quick_filters.filters.is_empty() && in_list;
+        let bar_height = app.density().bar_height();
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
-                Constraint::Length(3),  // Title bar
+                Constraint::Length(bar_height),  // Title bar
+                Constraint::Length(if search_error.is_some() { 1 } else { 0 }),  // Search error
+                Constraint::Length(if show_quick_bar { 1 } else { 0 }),  // Quick filters
+                Constraint::Length(if show_chips { 1 } else { 0 }),  // Filter chips
                 Constraint::Min(0),      // Main content
-                Constraint::Length(3),  // Status bar
+                Constraint::Length(bar_height),  // Status bar
             ])
             .split(frame.size());
 
         // Render title bar
         self.components.render_title_bar(frame, chunks[0], app);
+        if let Some(error) = &search_error {
+            self.components.render_search_error(frame, chunks[1], chunks[0], app, error);
+        }
+        self.components.render_quick_filter_bar(frame, chunks[2], app);
+        self.components.render_filter_chips(frame, chunks[3], app);
 
         // Render main content
         match app.mode {
             AppMode::Normal | AppMode::Search => {
-                self.components.render_book_list(frame, chunks[1], app);
+                self.components.render_book_list(frame, chunks[4], app);
             }
             AppMode::Details | AppMode::DetailsFromSearch => {
-                self.components.render_book_details(frame, chunks[1], app);
+                self.components.render_book_details(frame, chunks[4], app);
+            }
+            AppMode::Device => {
+                self.components.render_device_view(frame, chunks[4], app);
+            }
+            AppMode::Opds => {
+                self.components.render_opds_view(frame, chunks[4], app);
+            }
+            AppMode::Review => {
+                self.components.render_review_view(frame, chunks[4], app);
+            }
+            AppMode::Edit => {
+                self.components.render_edit_form(frame, chunks[4], app);
+            }
+            AppMode::Reader => {
+                self.components.render_reader(frame, chunks[4], app);
+            }
+            AppMode::Comic => {
+                self.components.render_comic(frame, chunks[4], app);
+            }
+            AppMode::Jobs => {
+                self.components.render_jobs_view(frame, chunks[4], app);
+            }
+            AppMode::Usage => {
+                self.components.render_usage_view(frame, chunks[4], app);
+            }
+            AppMode::Cleanup => {
+                self.components.render_cleanup_view(frame, chunks[4], app);
+            }
+            AppMode::FormatPolicy => {
+                self.components.render_policy_audit(frame, chunks[4], app);
+            }
+            AppMode::MetadataAudit => {
+                self.components.render_metadata_audit(frame, chunks[4], app);
             }
             AppMode::LibrarySelection => {
                 // This should not happen in the main app, but just in case
-                self.components.render_no_libraries(frame, chunks[1]);
+                self.components.render_no_libraries(frame, chunks[4], &app.theme());
             }
         }
 
         // Render status bar
-        self.components.render_status_bar(frame, chunks[2], app);
+        self.components.render_status_bar(frame, chunks[5], app);
+
+        // Popup menus are drawn over everything else
+        if let Some(menu) = &app.menu {
+            self.components.render_menu(frame, frame.size(), menu, &app.theme());
+        }
+        if let Some(prompt) = &app.prompt {
+            self.components.render_prompt(frame, frame.size(), prompt);
+        }
+        if let Some(confirm) = &app.pending_delete {
+            self.components.render_delete_confirm(frame, frame.size(), confirm, &app.library_path, &app.theme());
+        }
+        if app.menu.is_some() || app.prompt.is_some() || app.pending_delete.is_some() {
+            self.components.clear_cover_placement();
+        }
     }
 
     /// Handle keyboard events
-    /// Returns Some(new_library_path) if switching libraries, None for continue/exit
-    async fn handle_key_event(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> Result<Option<PathBuf>> {
-        match app.mode {
-            AppMode::Normal => {
-                let continue_running = self.handle_normal_mode(key, app).await?;
-                Ok(if continue_running && app.mode == AppMode::LibrarySelection {
-                    // User wants to switch libraries
-                    Some(PathBuf::new()) // Signal to show library selector
-                } else {
-                    None
-                })
-            },
-            AppMode::Search => {
-                let continue_running = self.handle_search_mode(key, app, database).await;
-                Ok(if continue_running { None } else { Some(PathBuf::new()) })
-            },
-            AppMode::Details | AppMode::DetailsFromSearch => {
-                let continue_running = self.handle_details_mode(key, app).await;
-                Ok(if continue_running { None } else { Some(PathBuf::new()) })
-            },
-            AppMode::LibrarySelection => {
-                // This shouldn't happen in the main app loop
-                Ok(None)
-            },
+    async fn handle_key_event(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> Result<KeyOutcome> {
+        // Any key press dismisses the last status message
+        app.status_message = None;
+
+        // Ctrl-C anywhere, or Esc in the book list, cancels a running job
+        let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+        let esc_in_list = key.code == KeyCode::Esc && app.mode == AppMode::Normal && app.menu.is_none() && app.prompt.is_none();
+        if app.job.is_some() && (ctrl_c || esc_in_list) {
+            self.cancel_job(app);
+            return Ok(KeyOutcome::Continue);
+        }
+        // With nothing to cancel, Ctrl-C quits as it would outside raw mode
+        if ctrl_c {
+            return Ok(KeyOutcome::Quit);
         }
-    }
 
-    async fn handle_normal_mode(&mut self, key: KeyEvent, app: &mut App) -> Result<bool> {
-        match key.code {
-            KeyCode::Up | KeyCode::Char('k') => {
-                app.select_previous();
-                Ok(true)
-            }
-            KeyCode::Down | KeyCode::Char('j') => {
-                app.select_next();
-                Ok(true)
-            }
-            KeyCode::Enter | KeyCode::Right => {
-                app.mode = AppMode::Details;
-                Ok(true)
-            }
-            KeyCode::Char('/') => {
-                app.mode = AppMode::Search;
-                app.search_query.clear();
-                Ok(true)
-            }
-            KeyCode::Esc | KeyCode::Left => {
-                // Return to library selection
-                app.mode = AppMode::LibrarySelection;
-                Ok(true)
-            }
-            KeyCode::Char('q') => Ok(false), // Exit application
-            _ => Ok(true),  // Ignore all other keys but don't exit
+        // An open popup menu or prompt captures all keys
+        if app.menu.is_some() {
+            self.handle_menu_key(key, app, database).await;
+            return Ok(KeyOutcome::Continue);
+        }
+        if app.prompt.is_some() {
+            self.handle_prompt_key(key, app, database).await;
+            return Ok(KeyOutcome::Continue);
         }
-    }
 
-    async fn handle_search_mode(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> bool {
-        match key.code {
-            KeyCode::Esc | KeyCode::Left => {
-                // Clear search, show all books, and exit search mode
-                app.search_query.clear();
-                app.books = app.all_books.clone();
-                app.selected_book_index = 0;
-                app.mode = AppMode::Normal;
-                true
-            }
-            KeyCode::Enter | KeyCode::Right => {
-                // Accept search and go directly to details view from search mode
-                if !app.books.is_empty() {
-                    app.mode = AppMode::DetailsFromSearch;
-                } else {
-                    app.mode = AppMode::Search;
-                }
-                true
-            }
-            KeyCode::Char(c) => {
-                // Handle Ctrl+j and Ctrl+k for navigation
-                if key.modifiers.contains(KeyModifiers::CONTROL) {
-                    if c == 'j' {
-                        app.select_next();
-                    } else if c == 'k' {
-                        app.select_previous();
-                    }
-                } else {
-                    app.search_query.push(c);
-                    // Trigger real-time search
-                    self.perform_realtime_search(app, database).await;
+        // A pending delete only proceeds on an explicit 'y' or Enter; 'f' keeps or removes the files
+        if let Some(mut confirm) = app.pending_delete.take() {
+            match key.code {
+                KeyCode::Char('y') | KeyCode::Enter => self.delete_book(app, database, confirm).await,
+                KeyCode::Char('f') => {
+                    confirm.remove_files = !confirm.remove_files;
+                    app.pending_delete = Some(confirm);
                 }
-                true
-            }
-            KeyCode::Backspace => {
-                app.search_query.pop();
-                // Trigger real-time search
-                self.perform_realtime_search(app, database).await;
-                true
-            }
-            KeyCode::Up => {
-                app.select_previous();
-                true
-            }
-            KeyCode::Down => {
-                app.select_next();
-                true
+                _ => app.status_message = Some("Delete cancelled".to_string()),
             }
-            _ => true,  // Ignore other keys but don't exit
+            return Ok(KeyOutcome::Continue);
         }
+
+        let continue_running = match app.mode {
+            AppMode::Normal => self.handle_normal_mode(key, app, database).await?,
+            AppMode::Search => self.handle_search_mode(key, app, database).await,
+            AppMode::Details | AppMode::DetailsFromSearch => self.handle_details_mode(key, app, database).await,
+            AppMode::Device => self.handle_device_mode(key, app),
+            AppMode::Opds => self.handle_opds_mode(key, app, database).await,
+            AppMode::Review => self.handle_review_mode(key, app, database).await,
+            AppMode::Edit => self.handle_edit_mode(key, app, database).await,
+            AppMode::Reader => self.handle_reader_mode(key, app),
+            AppMode::Comic => self.handle_comic_mode(key, app),
+            AppMode::Jobs => self.handle_jobs_mode(key, app, database).await,
+            AppMode::Usage => self.handle_usage_mode(key, app, database).await,
+            AppMode::Cleanup => self.handle_cleanup_mode(key, app, database).await,
+            AppMode::FormatPolicy => self.handle_policy_mode(key, app, database).await,
+            AppMode::MetadataAudit => self.handle_metadata_audit_mode(key, app, database).await,
+            // This shouldn't happen in the main app loop
+            AppMode::LibrarySelection => true,
+        };
+
+        Ok(if !continue_running {
+            KeyOutcome::Quit
+        } else if app.mode == AppMode::LibrarySelection {
+            // User wants to switch libraries
+            KeyOutcome::SwitchLibrary
+        } else {
+            KeyOutcome::Continue
+        })
     }
 
-    /// Perform real-time search and update the book list
-    async fn perform_realtime_search(&self, app: &mut App, database: &Database) {
-        if app.search_query.is_empty() {
-            // If search query is empty, show all books
-            app.books = app.all_books.clone();
-            app.selected_book_index = 0;
+    /// Handle mouse clicks on the index strip and filter chips
+    ///
+    /// A right click negates a chip, or opens the quick actions menu of the book under it.
+    async fn handle_mouse_event(&mut self, mouse: MouseEvent, app: &mut App, database: &Database) {
+        if app.mode != AppMode::Normal || app.menu.is_some() || app.prompt.is_some() {
             return;
         }
-
-        match database.search_books(&app.search_query).await {
-            Ok(search_results) => {
-                app.books = search_results;
-                // Reset selection to first result
-                app.selected_book_index = 0;
+        if let MouseEventKind::Down(MouseButton::Left) = mouse.kind {
+            if let Some(letter) = self.components.index_strip_hit(mouse.column, mouse.row) {
+                app.jump_to_letter(letter);
+            } else if let Some(chip) = self.components.filter_chip_hit(mouse.column, mouse.row) {
+                self.remove_filter(app, database, Some(chip)).await;
             }
-            Err(_) => {
-                // In real-time mode, we don't want to spam error messages
-                // Just continue with current results if search fails
+        }
+        if let MouseEventKind::Down(MouseButton::Right) = mouse.kind {
+            if let Some(chip) = self.components.filter_chip_hit(mouse.column, mouse.row) {
+                self.negate_filter(app, database, Some(chip)).await;
+            } else if let Some(index) = self.components.book_hit(mouse.column, mouse.row) {
+                app.selected_book_index = index;
+                self.open_book_actions(app);
             }
         }
     }
 
-    async fn handle_details_mode(&mut self, key: KeyEvent, app: &mut App) -> bool {
-        match key.code {
-            KeyCode::Esc | KeyCode::Left => {
-                // Return to search mode if we came from search, otherwise normal mode
-                if app.mode == AppMode::DetailsFromSearch {
-                    app.mode = AppMode::Search;
-                } else {
-                    app.mode = AppMode::Normal;
+    async fn handle_normal_mode(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> Result<bool> {
+        // The tag pane has the keyboard until Tab hands it back
+        if app.tag_browser.as_ref().is_some_and(|b| b.focused) {
+            self.handle_tag_browser_key(key, app, database).await;
+            return Ok(true);
+        }
+
+        // "'" followed by a letter (or '#') jumps to that section of the index
+        if app.pending_jump {
+            app.pending_jump = false;
+            if let KeyCode::Char(c) = key.code {
+                if c.is_ascii_alphabetic() || c == '#' {
+                    app.jump_to_letter(c);
                 }
-                true
             }
-            KeyCode::Enter | KeyCode::Right => {
-                if let Some(book) = app.get_selected_book() {
-                    self.open_book_file(&book, &app.library_path).await;
+            return Ok(true);
+        }
+
+        // Hint letters pick a visible book and open its details; any other key gives up
+        if let Some(mut typed) = app.hint_input.take() {
+            app.status_message = None;
+            if let KeyCode::Char(c) = key.code {
+                typed.push(c.to_ascii_lowercase());
+                let rows = self.components.visible_books();
+                let labels = hint_labels(rows.len());
+                if let Some(i) = labels.iter().position(|label| *label == typed) {
+                    app.selected_book_index = rows[i];
+                    app.mode = AppMode::Details;
+                } else if labels.iter().any(|label| label.starts_with(&typed)) {
+                    app.hint_input = Some(typed);
+                } else {
+                    app.status_message = Some(format!("No hint \"{}\"", typed));
                 }
-                true
             }
-            KeyCode::Char('q') => false, // Exit application
-            _ => true,  // Ignore other keys but don't exit
+            return Ok(true);
         }
-    }
 
-    /// Open the book file using the system default application
-    async fn open_book_file(&self, book: &Book, library_path: &PathBuf) {
-        use std::process::Command;
+        if app.visual_select && self.handle_visual_key(key, app) {
+            return Ok(true);
+        }
 
-        // Skip if we don't have file information
-        if book.filename.is_empty() || book.format.is_empty() {
-            eprintln!("❌ No file information available for book: {}", book.title);
+        // With type-ahead on, lowercase letters start a title prefix and anything typed soon after extends it
+        if app.config.navigation.type_ahead && !key.modifiers.contains(KeyModifiers::CONTROL) {
+            if let KeyCode::Char(c) = key.code {
+                if c.is_lowercase() || app.type_ahead_active() {
+                    let found = app.type_ahead_find(c);
+                    let prefix = app.type_ahead.as_ref().map(|(prefix, _)| prefix.as_str()).unwrap_or_default();
+                    app.status_message = Some(if found {
+                        format!("Find: {}", prefix)
+                    } else {
+                        format!("Find: {} (no match)", prefix)
+                    });
+                    return Ok(true);
+                }
+            }
+        }
+
+        // Counts like the 5 in "5j" are collected before the command key arrives
+        let Some((count, key)) = self.pending_keys.feed(key) else {
+            if let Some(count) = self.pending_keys.count() {
+                app.status_message = Some(count.to_string());
+            }
+            return Ok(true);
+        };
+
+        if let KeyCode::F(n) = key.code {
+            self.apply_quick_filter(app, database, n).await;
+            return Ok(true);
+        }
+
+        match Action::for_key(key.code) {
+            Some(action) => Ok(self.perform(action, count, app, database).await),
+            None => Ok(true), // Ignore all other keys but don't exit
+        }
+    }
+
+    /// Run an action from the registry on the book list
+    ///
+    /// `count` is the number typed before the key, e.g. the 5 in "5j". Returns false to quit.
+    async fn perform(&mut self, action: Action, count: Option<usize>, app: &mut App, database: &Database) -> bool {
+        let book = app.get_selected_book().cloned();
+
+        match action {
+            Action::MoveUp => app.select_previous_by(count.unwrap_or(1)),
+            Action::MoveDown => app.select_next_by(count.unwrap_or(1)),
+            // "10G" goes to the 10th book, a bare "G" to the last
+            Action::GoToLine => app.select_line(count.unwrap_or(usize::MAX)),
+            Action::JumpToLetter => {
+                app.pending_jump = true;
+                app.status_message = Some("Jump to letter: press A-Z or #".to_string());
+            }
+            Action::Hints => {
+                if !app.books.is_empty() {
+                    app.hint_input = Some(String::new());
+                    app.status_message = Some("Type a book's hint to open it (Esc cancels)".to_string());
+                }
+            }
+            Action::Open => {
+                if let Some(book) = book {
+                    let opened = match self.open_by_rule(app, &book).await {
+                        Some(opened) => opened,
+                        None => self.open_book_file(&book, None, &app.library_path).await,
+                    };
+                    if opened {
+                        self.record_open(app, database, book.id).await;
+                    }
+                }
+            }
+            Action::OpenAll => self.open_marked_books(app, database).await,
+            Action::Play => {
+                if let Some(book) = book {
+                    if self.play_audiobook(app, &book).await {
+                        self.record_open(app, database, book.id).await;
+                    }
+                }
+            }
+            Action::OpenWith => {
+                app.prompt = Some(Prompt::new(PromptKind::OpenWith, "Open with (command)", ""));
+            }
+            Action::Read => {
+                if let Some(book) = book {
+                    self.open_reader(app, database, &book, None).await;
+                }
+            }
+            Action::ReadAtMatch => {
+                if let Some(book) = book {
+                    self.read_at_match(app, database, &book).await;
+                }
+            }
+            Action::Details => app.mode = AppMode::Details,
+            Action::EditTitle => {
+                if let Some(book) = book {
+                    app.prompt = Some(Prompt::new(PromptKind::EditTitle, "Title", book.title));
+                }
+            }
+            Action::Convert => {
+                if let Some(book) = book {
+                    let formats: Vec<String> = CONVERT_FORMATS
+                        .iter()
+                        .filter(|f| !book.has_format(f))
+                        .map(|f| f.to_string())
+                        .collect();
+                    let items = formats.clone();
+                    app.menu = Some(Menu::new(MenuKind::ConvertTo(formats), "Convert to", items));
+                }
+            }
+            Action::EmbedMetadata => {
+                let ids: Vec<i32> = app.selection_or_current().iter().map(|b| b.id).collect();
+                self.embed_book_ids(app, database, &ids).await;
+                app.selected_ids.clear();
+            }
+            Action::Send => self.send_to_device(app, database).await,
+            Action::Email => self.open_email_menu(app),
+            Action::Export => {
+                let directory = app.config.export.directory.clone();
+                app.prompt = Some(Prompt::new(PromptKind::ExportTo, "Export to (folder)", directory));
+            }
+            Action::Archive => {
+                let name = format!("tuilibre-{}.zip", chrono::Local::now().format("%Y-%m-%d"));
+                let destination = Path::new(&app.config.export.directory).join(name);
+                let destination = destination.display().to_string();
+                app.prompt = Some(Prompt::new(PromptKind::ArchiveTo, "Archive to (.zip or .tar)", destination));
+            }
+            Action::Mark => {
+                app.toggle_selected();
+                app.select_next();
+            }
+            Action::VisualSelect => {
+                app.visual_select = true;
+                app.range_anchor = None;
+                app.status_message = Some("Visual select: Space marks, V marks a range, Enter for batch actions, Esc ends".to_string());
+            }
+            Action::AddTag => {
+                let title = match app.selected_ids.len() {
+                    0 => "Add tag".to_string(),
+                    marked => format!("Add tag to {} marked books", marked),
+                };
+                app.prompt = Some(Prompt::new(PromptKind::AddTag, title, ""));
+            }
+            Action::RemoveTag => {
+                let (title, tag) = match app.selected_ids.len() {
+                    0 => ("Remove tag".to_string(), book.and_then(|b| b.tags.first().cloned()).unwrap_or_default()),
+                    marked => (format!("Remove tag from {} marked books", marked), String::new()),
+                };
+                app.prompt = Some(Prompt::new(PromptKind::RemoveTag, title, tag));
+            }
+            Action::AddToShelf | Action::RemoveFromShelf => {
+                // Offer the shelf being viewed, or failing that one the book is on
+                let viewed = app.filters.iter().find_map(|c| match &c.filter {
+                    Filter::Shelf(name) if !c.negated => Some(name.clone()),
+                    _ => None,
+                });
+                let name = viewed.or_else(|| book.and_then(|b| b.shelves.first().cloned())).unwrap_or_default();
+                app.prompt = Some(if action == Action::AddToShelf {
+                    Prompt::new(PromptKind::AddToShelf, "Add to shelf", name)
+                } else {
+                    Prompt::new(PromptKind::RemoveFromShelf, "Remove from shelf", name)
+                });
+            }
+            Action::CopyPath => {
+                if let Some(path) = book.and_then(|b| b.file_path(&app.library_path)) {
+                    app.status_message = Some(match clipboard::copy_to_clipboard(&path.display().to_string()) {
+                        Ok(()) => format!("📋 Copied {}", path.display()),
+                        Err(e) => format!("❌ Failed to copy path: {}", e),
+                    });
+                }
+            }
+            Action::Delete => {
+                if let Some(book) = book {
+                    self.confirm_delete(app, database, &book).await;
+                }
+            }
+            Action::BookMenu => self.open_book_actions(app),
+            Action::AddBook => app.prompt = Some(Prompt::new(PromptKind::AddBook, "Add book from file", "~/")),
+            Action::Search => {
+                app.mode = AppMode::Search;
+                app.search_query.clear();
+                app.search_layers.clear();
+                app.search_plain = false;
+            }
+            Action::AddFilter => {
+                let items = FILTER_KINDS
+                    .iter()
+                    .filter(|(_, category)| category.is_none_or(|c| !app.hidden_categories.contains(c)))
+                    .map(|(kind, _)| kind.to_string())
+                    .collect();
+                app.menu = Some(Menu::new(MenuKind::FilterKind, "Add filter", items));
+            }
+            Action::RemoveFilter => {
+                // "2x" removes the second chip, a bare "x" the last one
+                let index = match count {
+                    Some(n) => n.checked_sub(1),
+                    None => app.filters.len().checked_sub(1),
+                };
+                self.remove_filter(app, database, index).await;
+            }
+            Action::NegateFilter => {
+                // "2!" negates the second chip, a bare "!" the last one
+                let index = match count {
+                    Some(n) => n.checked_sub(1),
+                    None => app.filters.len().checked_sub(1),
+                };
+                self.negate_filter(app, database, index).await;
+            }
+            Action::TagBrowser => self.focus_tag_browser(app, database).await,
+            Action::VirtualLibrary => self.open_virtual_library_menu(app, database).await,
+            Action::SavedSearches => self.open_saved_searches(app, database).await,
+            Action::RenameLibrary => {
+                let alias = app.library_alias.clone().unwrap_or_default();
+                app.prompt = Some(Prompt::new(PromptKind::RenameLibrary, "Library name (empty for the folder name)", alias));
+            }
+            Action::CycleView => app.view = app.view.next(),
+            Action::CycleSort => {
+                app.sort.cycle();
+                self.resort_books(app, database).await;
+            }
+            Action::ReverseSort => {
+                app.sort.reverse();
+                self.resort_books(app, database).await;
+            }
+            Action::AbsoluteTimes => app.absolute_times = !app.absolute_times,
+            Action::CycleTheme => {
+                // For this session only; `display.theme` in the config picks the one to start with
+                app.config.display.theme = app.config.display.theme.next();
+                app.status_message = Some(format!("Theme: {}", app.config.display.theme.label()));
+            }
+            Action::SaveDefaults => self.save_library_defaults(app),
+            Action::Opds => self.open_opds_view(app),
+            Action::Device => self.open_device_view(app, database).await,
+            Action::LookUpMetadata => self.enrich_metadata(app, database),
+            Action::DetectLanguages => {
+                if let Some((tx, cancel)) = self.start_job(app, JobKind::DetectLanguages, "Detecting languages", 0) {
+                    tokio::spawn(jobs::detect_languages(database.clone(), app.library_path.clone(), tx, cancel));
+                }
+            }
+            Action::SuggestTags => {
+                // Marked books only, or the whole library when nothing is marked
+                let ids: Vec<i32> = app.selected_ids.iter().copied().collect();
+                if let Some((tx, cancel)) = self.start_job(app, JobKind::SuggestTags, "Suggesting tags", 0) {
+                    tokio::spawn(jobs::suggest_tags(database.clone(), ids, tx, cancel));
+                    app.selected_ids.clear();
+                }
+            }
+            Action::Verify => {
+                // Marked books only, or the whole library when nothing is marked
+                let ids: Vec<i32> = if app.selected_ids.is_empty() {
+                    app.all_books.iter().map(|b| b.id).collect()
+                } else {
+                    app.selected_ids.iter().copied().collect()
+                };
+                self.verify_book_ids(app, &ids);
+                app.selected_ids.clear();
+            }
+            Action::Review => {
+                if app.review.proposals.is_empty() {
+                    app.status_message = Some("No metadata proposals to review (M look up, L detect languages, T suggest tags)".to_string());
+                } else {
+                    app.mode = AppMode::Review;
+                }
+            }
+            // Return to library selection
+            Action::SwitchLibrary => app.mode = AppMode::LibrarySelection,
+            Action::StealLock => self.steal_lock(app, database),
+            Action::QueuedActions => self.open_queue_menu(app, database),
+            Action::Jobs => self.open_jobs_view(app),
+            Action::DiskUsage => self.open_usage_view(app, database, UsageGrouping::default()).await,
+            Action::Cleanup => self.open_cleanup_view(app, database).await,
+            Action::AuditMetadata => {
+                // Marked books only, or the whole library when nothing is marked
+                let ids: Vec<i32> = if app.selected_ids.is_empty() {
+                    app.all_books.iter().map(|b| b.id).collect()
+                } else {
+                    app.selected_ids.iter().copied().collect()
+                };
+                self.audit_book_ids(app, database, ids).await;
+                app.selected_ids.clear();
+            }
+            Action::IndexContent => {
+                let items = jobs::plan_content_index(&app.library_path, &app.all_books);
+                let total = items.len();
+                let pdftotext = app.config.searches.pdftotext.clone();
+                if let Some((tx, cancel)) = self.start_job(app, JobKind::IndexContent, "Indexing book contents", total) {
+                    tokio::spawn(jobs::index_contents(database.clone(), items, pdftotext, tx, cancel));
+                }
+            }
+            Action::FormatPolicy => self.open_policy_audit(app, database).await,
+            Action::SplitLibrary => {
+                if app.book_query().is_empty() {
+                    app.status_message = Some("Search or filter first: the books listed are split off".to_string());
+                } else if !app.books.is_empty() {
+                    let name = app.library_path.file_name().unwrap_or_default().to_string_lossy();
+                    let target = app.library_path.with_file_name(format!("{} (split)", name));
+                    let title = format!("Split {} books into new library at", app.books.len());
+                    app.prompt = Some(Prompt::new(PromptKind::SplitTo, title, target.display().to_string()));
+                }
+            }
+            Action::MergeLibrary => self.open_merge_menu(app, database),
+            Action::ImportCalibreWeb => {
+                app.prompt = Some(Prompt::new(PromptKind::CalibreWebDb, "calibre-web database (app.db)", "~/.calibre-web/app.db"));
+            }
+            Action::Quit => return false,
+        }
+        true
+    }
+
+    async fn handle_search_mode(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> bool {
+        match key.code {
+            KeyCode::Esc | KeyCode::Left => {
+                // Clear search, show all books, and go back to where the list was before searching
+                app.search_query.clear();
+                app.search_layers.clear();
+                app.search_plain = false;
+                app.search_full_text = false;
+                app.search_stats = None;
+                self.refresh_books(app, database).await;
+                let place = app.scroll_state(ScrollView::Books);
+                app.selected_book_index = place.selected.min(app.books.len().saturating_sub(1));
+                app.mode = AppMode::Normal;
+                true
+            }
+            KeyCode::Enter | KeyCode::Right => {
+                // Accept search and go directly to details view from search mode
+                if !app.books.is_empty() {
+                    app.mode = AppMode::DetailsFromSearch;
+                } else {
+                    app.mode = AppMode::Search;
+                }
+                true
+            }
+            KeyCode::Char(c) => {
+                // Handle Ctrl+j and Ctrl+k for navigation
+                if key.modifiers.contains(KeyModifiers::CONTROL) {
+                    if c == 'j' {
+                        app.select_next();
+                    } else if c == 'k' {
+                        app.select_previous();
+                    } else if c == 't' {
+                        // Toggle taking field terms literally, e.g. for a title with a colon
+                        app.search_plain = !app.search_plain;
+                        self.perform_realtime_search(app, database).await;
+                    } else if c == 'f' {
+                        // Switch between searching the metadata and the text of the books
+                        app.search_full_text = !app.search_full_text;
+                        self.perform_realtime_search(app, database).await;
+                    } else if c == 's' && !app.full_search().is_empty() {
+                        app.prompt = Some(Prompt::new(PromptKind::SaveSearch, "Save search as", ""));
+                    }
+                } else {
+                    app.search_query.push(c);
+                    // Trigger real-time search
+                    self.perform_realtime_search(app, database).await;
+                }
+                true
+            }
+            KeyCode::Tab => {
+                // Keep these results and search within them
+                if app.push_search_layer() {
+                    self.perform_realtime_search(app, database).await;
+                }
+                true
+            }
+            KeyCode::Backspace => {
+                // Backspace with nothing typed goes back to the search being narrowed
+                if app.search_query.is_empty() {
+                    app.pop_search_layer();
+                } else {
+                    app.search_query.pop();
+                }
+                // Trigger real-time search
+                self.perform_realtime_search(app, database).await;
+                true
+            }
+            KeyCode::Up => {
+                app.select_previous();
+                true
+            }
+            KeyCode::Down => {
+                app.select_next();
+                true
+            }
+            _ => true,  // Ignore other keys but don't exit
+        }
+    }
+
+    /// Perform real-time search and update the book list
+    ///
+    /// While the query doesn't parse the list stays as it was, with the error shown under the
+    /// search bar.
+    async fn perform_realtime_search(&self, app: &mut App, database: &Database) {
+        if app.search_error().is_some() {
+            return;
+        }
+        let started = Instant::now();
+        self.refresh_books(app, database).await;
+
+        let searching = !app.search_query.is_empty() || !app.search_layers.is_empty();
+        app.search_stats = searching.then(|| SearchStats {
+            count: app.books.len(),
+            elapsed: started.elapsed(),
+        });
+        // Reset selection to first result
+        app.selected_book_index = 0;
+    }
+
+    async fn handle_details_mode(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> bool {
+        if app.details_tab == DetailsTab::Annotations && self.handle_annotations_tab(key, app, database).await {
+            return true;
+        }
+        match key.code {
+            KeyCode::Tab | KeyCode::BackTab => {
+                app.details_tab = app.details_tab.next();
+                true
+            }
+            KeyCode::Esc | KeyCode::Left => {
+                app.details_scroll.set(0);
+                app.details_tab = DetailsTab::Description;
+                // Return to search mode if we came from search, otherwise normal mode
+                if app.mode == AppMode::DetailsFromSearch {
+                    app.mode = AppMode::Search;
+                } else {
+                    app.mode = AppMode::Normal;
+                }
+                true
+            }
+            KeyCode::Enter | KeyCode::Right => {
+                if let Some(book) = app.get_selected_book().cloned() {
+                    if let Some(opened) = self.open_by_rule(app, &book).await {
+                        if opened {
+                            self.record_open(app, database, book.id).await;
+                        }
+                    } else if book.formats.len() > 1 {
+                        // Let the reader pick which file to open
+                        let locale = app.config.display.locale();
+                        let items = book
+                            .formats
+                            .iter()
+                            .map(|f| format!("{} ({} KB)", f.format, locale.format_number((f.size.max(0) / 1024) as usize)))
+                            .collect();
+                        app.menu = Some(Menu::new(MenuKind::OpenFormat(book.formats), "Open format", items));
+                    } else if self.open_book_file(&book, None, &app.library_path).await {
+                        self.record_open(app, database, book.id).await;
+                    }
+                }
+                true
+            }
+            KeyCode::Char('e') => {
+                if let Some(book) = app.get_selected_book() {
+                    app.edit = Some(EditForm::new(book, app.mode.clone()));
+                    app.mode = AppMode::Edit;
+                }
+                true
+            }
+            KeyCode::Char('p') => {
+                if let Some(book) = app.get_selected_book().cloned() {
+                    self.open_reader(app, database, &book, None).await;
+                }
+                true
+            }
+            KeyCode::Char('n') => {
+                if let Some(book) = app.get_selected_book().cloned() {
+                    self.read_at_match(app, database, &book).await;
+                }
+                true
+            }
+            KeyCode::Char('d') => {
+                if let Some(book) = app.get_selected_book().cloned() {
+                    self.confirm_delete(app, database, &book).await;
+                }
+                true
+            }
+            // Scroll the description; drawing keeps it from going past the end
+            KeyCode::Down | KeyCode::Char('j') => {
+                app.details_scroll.set(app.details_scroll.get().saturating_add(1));
+                true
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                app.details_scroll.set(app.details_scroll.get().saturating_sub(1));
+                true
+            }
+            KeyCode::PageDown | KeyCode::Char(' ') => {
+                app.details_scroll.set(app.details_scroll.get().saturating_add(10));
+                true
+            }
+            KeyCode::PageUp => {
+                app.details_scroll.set(app.details_scroll.get().saturating_sub(10));
+                true
+            }
+            KeyCode::Char('q') => false, // Exit application
+            _ => true,  // Ignore other keys but don't exit
+        }
+    }
+
+    /// Keys of the details view's annotations tab: ↑↓ select, Enter reads at the annotation, d
+    /// deletes it and x exports them all; false for keys left to the details view
+    async fn handle_annotations_tab(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> bool {
+        let Some(book) = app.get_selected_book().cloned() else {
+            return false;
+        };
+        let count = app.annotations.get(&book.id).map_or(0, Vec::len);
+        let selected = app.annotation_selected().min(count.saturating_sub(1));
+        match key.code {
+            KeyCode::Down | KeyCode::Char('j') => app.select_in(ScrollView::Annotations, (selected + 1).min(count.saturating_sub(1))),
+            KeyCode::Up | KeyCode::Char('k') => app.select_in(ScrollView::Annotations, selected.saturating_sub(1)),
+            KeyCode::Enter => {
+                let Some(annotation) = app.annotations.get(&book.id).and_then(|a| a.get(selected)).cloned() else {
+                    return true;
+                };
+                self.open_reader(app, database, &book, None).await;
+                if let Some(reader) = app.reader.as_mut().filter(|r| r.book_id == book.id) {
+                    reader.go_to_annotation(&annotation);
+                }
+            }
+            KeyCode::Char('d') => {
+                let Some(annotations) = app.annotations.get_mut(&book.id).filter(|a| selected < a.len()) else {
+                    return true;
+                };
+                annotations.remove(selected);
+                if annotations.is_empty() {
+                    app.annotations.remove(&book.id);
+                }
+                app.select_in(ScrollView::Annotations, selected.min(count.saturating_sub(2)));
+                app.status_message = Some("Annotation deleted".to_string());
+                self.save_annotations(app);
+            }
+            KeyCode::Char('x') => self.export_annotations(app, &book),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Open the book's EPUB, or else its TXT file, in the reader where it was left
+    async fn open_reader(&mut self, app: &mut App, database: &Database, book: &Book, found: Option<&ContentMatch>) {
+        let Some(format) = READABLE_FORMATS
+            .iter()
+            .find_map(|wanted| book.formats.iter().find(|f| f.format.eq_ignore_ascii_case(wanted)))
+        else {
+            if let Some(comic) = COMIC_FORMATS.iter().find_map(|wanted| book.formats.iter().find(|f| f.format.eq_ignore_ascii_case(wanted))) {
+                self.open_comic(app, database, book, comic).await;
+                return;
+            }
+            app.status_message = Some(format!("❌ \"{}\" has no EPUB, TXT or comic file to read", book.title));
+            return;
+        };
+        let path = book.format_path(&app.library_path, format);
+        let name = format.format.clone();
+        let loaded = tokio::task::spawn_blocking(move || load_chapters(&path, &name)).await;
+        let chapters = match loaded.map_err(|e| e.to_string()).and_then(|r| r.map_err(|e| e.to_string())) {
+            Ok(chapters) => chapters,
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to open the book: {}", e));
+                return;
+            }
+        };
+
+        let position = LibrarySettingsStore::load()
+            .ok()
+            .and_then(|store| store.get(&app.library_path).and_then(|s| s.positions.get(&book.id).copied()));
+        let mut reader = Reader::new(book.id, book.title.clone(), chapters, position, app.mode.clone());
+        reader.annotations = app.annotations.get(&book.id).cloned().unwrap_or_default();
+        // The match's chapter only counts in the file the text was indexed from
+        if let (Some(found), Some(phrase)) = (found.filter(|m| m.format.eq_ignore_ascii_case(&format.format)), app.content_phrase()) {
+            reader.go_to_match(found.chapter, &phrase);
+        }
+        app.reader = Some(reader);
+        app.mode = AppMode::Reader;
+        self.record_open(app, database, book.id).await;
+    }
+
+    /// Open the book in the reader where the `content:` search found its phrase
+    async fn read_at_match(&mut self, app: &mut App, database: &Database, book: &Book) {
+        let Some(found) = app.content_matches.get(&book.id).cloned() else {
+            app.status_message = Some("No search match in this book: search its text with content:\"...\" first".to_string());
+            return;
+        };
+        if !READABLE_FORMATS.contains(&found.format.as_str()) {
+            app.status_message = Some(format!("❌ The match is in the {}, which can't be read in tuilibre", found.format));
+            return;
+        }
+        self.open_reader(app, database, book, Some(&found)).await;
+    }
+
+    /// Page through the book in the reader; Esc or q remembers the position and closes it
+    ///
+    /// m bookmarks the paragraph at the top of the page, v starts a highlight there that j and
+    /// k extend and Enter saves.
+    fn handle_reader_mode(&mut self, key: KeyEvent, app: &mut App) -> bool {
+        let Some(reader) = app.reader.as_mut() else {
+            app.mode = AppMode::Normal;
+            return true;
+        };
+
+        if reader.selection.is_some() {
+            match key.code {
+                KeyCode::Esc => reader.selection = None,
+                KeyCode::Down | KeyCode::Char('j') => reader.move_selection(1),
+                KeyCode::Up | KeyCode::Char('k') => reader.move_selection(-1),
+                KeyCode::Enter | KeyCode::Char('v') if reader.finish_selection() => {
+                    app.status_message = Some("🖍 Highlighted".to_string());
+                    self.save_annotations(app);
+                }
+                _ => {}
+            }
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => {
+                app.mode = reader.return_to.clone();
+                self.save_reading_position(app);
+                app.reader = None;
+            }
+            KeyCode::Char(' ') | KeyCode::PageDown | KeyCode::Right | KeyCode::Char('l') => reader.next_page(),
+            KeyCode::Char('b') | KeyCode::PageUp | KeyCode::Left | KeyCode::Char('h') => reader.previous_page(),
+            KeyCode::Down | KeyCode::Char('j') => reader.scroll_by(1),
+            KeyCode::Up | KeyCode::Char('k') => reader.scroll_by(-1),
+            KeyCode::Char('n') | KeyCode::Char(']') => reader.next_chapter(),
+            KeyCode::Char('p') | KeyCode::Char('[') => reader.previous_chapter(),
+            KeyCode::Char('g') | KeyCode::Home => reader.go_to_chapter(reader.chapter),
+            KeyCode::Char('G') | KeyCode::End => reader.end_of_chapter(),
+            KeyCode::Char('m') => {
+                let added = reader.toggle_bookmark();
+                app.status_message = Some(if added { "🔖 Bookmarked" } else { "Bookmark removed" }.to_string());
+                self.save_annotations(app);
+            }
+            KeyCode::Char('v') => reader.start_selection(),
+            KeyCode::Char('t') => {
+                let items = reader
+                    .chapters
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| format!("{}. {}", i + 1, c.title))
+                    .collect();
+                let mut menu = Menu::new(MenuKind::Chapters, "Contents", items);
+                menu.selected = reader.chapter;
+                app.menu = Some(menu);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Keep the open book's annotations, or the details view's after one is deleted there
+    fn save_annotations(&self, app: &mut App) {
+        if let Some(reader) = &app.reader {
+            if reader.annotations.is_empty() {
+                app.annotations.remove(&reader.book_id);
+            } else {
+                app.annotations.insert(reader.book_id, reader.annotations.clone());
+            }
+        }
+        let result = LibrarySettingsStore::load().and_then(|mut store| {
+            let mut settings = store.get(&app.library_path).cloned().unwrap_or_default();
+            settings.annotations = app.annotations.clone();
+            store.set(&app.library_path, settings);
+            store.save()
+        });
+        if let Err(e) = result {
+            app.status_message = Some(format!("❌ Failed to save the annotations: {}", e));
+        }
+    }
+
+    /// Write the book's bookmarks and highlights to a Markdown file in the export directory
+    fn export_annotations(&self, app: &mut App, book: &Book) {
+        let Some(annotations) = app.annotations.get(&book.id).filter(|a| !a.is_empty()) else {
+            app.status_message = Some("No annotations to export: bookmark with m or highlight with v in the reader".to_string());
+            return;
+        };
+        let markdown = annotations_markdown(&book.title, &book.author_list(), annotations);
+        let directory = expand_home(&app.config.export.directory);
+        let path = directory.join(format!("{} - annotations.md", book.title.replace(['/', '\\'], "_")));
+        let written = std::fs::create_dir_all(&directory).and_then(|_| std::fs::write(&path, markdown));
+        app.status_message = Some(match written {
+            Ok(()) => format!("📝 Exported {} annotations to {}", annotations.len(), path.display()),
+            Err(e) => format!("❌ Failed to export the annotations: {}", e),
+        });
+    }
+
+    /// Show a comic's first page in the viewer, once its pages are listed
+    async fn open_comic(&mut self, app: &mut App, database: &Database, book: &Book, format: &BookFormat) {
+        let path = book.format_path(&app.library_path, format);
+        let opened = tokio::task::spawn_blocking(move || ComicArchive::open(&path)).await;
+        let archive = match opened.map_err(|e| e.to_string()).and_then(|r| r.map_err(|e| e.to_string())) {
+            Ok(archive) => archive,
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to open the comic: {}", e));
+                return;
+            }
+        };
+        app.comic = Some(ComicViewer::new(book.id, book.title.clone(), archive, app.mode.clone()));
+        app.mode = AppMode::Comic;
+        self.record_open(app, database, book.id).await;
+    }
+
+    /// Flip through a comic's pages; Esc or q closes it
+    fn handle_comic_mode(&mut self, key: KeyEvent, app: &mut App) -> bool {
+        let Some(comic) = app.comic.as_mut() else {
+            app.mode = AppMode::Normal;
+            return true;
+        };
+
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => {
+                app.mode = comic.return_to.clone();
+                app.comic = None;
+            }
+            KeyCode::Char(' ') | KeyCode::PageDown | KeyCode::Right | KeyCode::Down | KeyCode::Char('l') | KeyCode::Char('j') => comic.flip(1),
+            KeyCode::Char('b') | KeyCode::PageUp | KeyCode::Left | KeyCode::Up | KeyCode::Char('h') | KeyCode::Char('k') => comic.flip(-1),
+            KeyCode::Char('g') | KeyCode::Home => comic.go_to(0),
+            KeyCode::Char('G') | KeyCode::End => comic.go_to(usize::MAX),
+            _ => {}
+        }
+        true
+    }
+
+    /// Remember where the reader is in its book, for the next time it is opened
+    fn save_reading_position(&self, app: &mut App) {
+        let Some(reader) = &app.reader else {
+            return;
+        };
+        let (book_id, position) = (reader.book_id, reader.position());
+        let result = LibrarySettingsStore::load().and_then(|mut store| {
+            let mut settings = store.get(&app.library_path).cloned().unwrap_or_default();
+            settings.positions.insert(book_id, position);
+            store.set(&app.library_path, settings);
+            store.save()
+        });
+        if let Err(e) = result {
+            app.status_message = Some(format!("❌ Failed to save the reading position: {}", e));
+        }
+    }
+
+    /// Edit the selected book's title and authors; Enter writes what changed, Esc drops it
+    async fn handle_edit_mode(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> bool {
+        let Some(form) = app.edit.as_mut() else {
+            app.mode = AppMode::Details;
+            return true;
+        };
+        match key.code {
+            KeyCode::Esc => {
+                app.mode = form.return_mode.clone();
+                app.edit = None;
+            }
+            KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down => form.next_field(),
+            KeyCode::Backspace => {
+                form.input_mut().pop();
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => form.input_mut().push(c),
+            KeyCode::Enter => {
+                if form.title.trim().is_empty() {
+                    app.status_message = Some("❌ A book needs a title".to_string());
+                    return true;
+                }
+                if parse_authors(&form.authors).is_empty() {
+                    app.status_message = Some("❌ A book needs at least one author".to_string());
+                    return true;
+                }
+                let Some(form) = app.edit.take() else {
+                    return true;
+                };
+                app.mode = form.return_mode.clone();
+                let Some(book) = app.get_selected_book().cloned() else {
+                    return true;
+                };
+                let edits = form.edits(&book);
+                if edits.is_empty() {
+                    app.status_message = Some("Nothing changed".to_string());
+                } else {
+                    self.write_edits(&book, edits, app, database).await;
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Start a background job and return the channel it reports progress on, with its cancel token
+    ///
+    /// The job is added to the job history when it finishes.
+    fn start_job(
+        &mut self,
+        app: &mut App,
+        kind: JobKind,
+        label: &str,
+        total: usize,
+    ) -> Option<(mpsc::UnboundedSender<JobUpdate>, CancelToken)> {
+        if app.job.is_some() {
+            app.status_message = Some("⏳ Another job is still running".to_string());
+            return None;
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let cancel = CancelToken::default();
+        self.job_updates = Some(rx);
+        self.job_cancel = Some(cancel.clone());
+        app.job = Some(JobStatus {
+            label: label.to_string(),
+            done: 0,
+            total,
+            current: String::new(),
+            cancelling: false,
+        });
+        let record = JobRecord {
+            session: self.session,
+            library: app.library_path.canonicalize().unwrap_or_else(|_| app.library_path.clone()),
+            kind,
+            label: label.to_string(),
+            started: Utc::now(),
+            seconds: 0.0,
+            result: String::new(),
+            cancelled: false,
+            failures: Vec::new(),
+        };
+        self.job_record = Some((record, Instant::now()));
+        Some((tx, cancel))
+    }
+
+    /// Add the job that just finished to the job history
+    fn record_job(&mut self, app: &mut App, message: &str) {
+        let Some((mut record, started)) = self.job_record.take() else {
+            return;
+        };
+        record.seconds = started.elapsed().as_secs_f64();
+        record.result = message.to_string();
+        record.cancelled = self.job_cancel.as_ref().is_some_and(CancelToken::is_cancelled);
+
+        let saved = JobHistory::load().and_then(|mut history| {
+            history.push(record);
+            history.save()?;
+            Ok(history)
+        });
+        match saved {
+            Ok(history) => {
+                if let Some(view) = app.jobs_view.as_mut() {
+                    // Keep the same job selected now that the new one is on top
+                    let records = history.for_library(&app.library_path);
+                    view.selected = (view.selected + records.len() - view.records.len().min(records.len()))
+                        .min(records.len().saturating_sub(1));
+                    view.records = records;
+                }
+            }
+            Err(e) => tracing::warn!("Failed to save job history: {}", e),
+        }
+    }
+
+    /// Ask the running job to stop; it reports what it got done when it finishes
+    fn cancel_job(&mut self, app: &mut App) {
+        if let (Some(cancel), Some(job)) = (&self.job_cancel, app.job.as_mut()) {
+            cancel.cancel();
+            job.cancelling = true;
+        }
+    }
+
+    /// Cancel the running job and wait for it to stop, so the book it is writing is completed
+    async fn finish_job(&mut self, app: &mut App) {
+        self.cancel_job(app);
+        let Some(rx) = self.job_updates.as_mut() else {
+            return;
+        };
+
+        // A job that hangs, e.g. on an unresponsive network, doesn't hold up exiting for long
+        let record = self.job_record.as_mut().map(|(record, _)| record);
+        let finished = async {
+            let mut failures = Vec::new();
+            while let Some(update) = rx.recv().await {
+                match update {
+                    JobUpdate::Failed(failed) => failures.extend(failed),
+                    JobUpdate::Finished { message } => return Some((failures, message)),
+                    _ => {}
+                }
+            }
+            None
+        };
+        let finished = tokio::time::timeout(JOB_SHUTDOWN_TIMEOUT, finished).await.ok().flatten();
+        if let Some(record) = record {
+            let (failures, message) = finished.unwrap_or_else(|| (Vec::new(), "⏹ Stopped on exit".to_string()));
+            record.failures.extend(failures);
+            self.record_job(app, &message);
+        }
+        self.job_updates = None;
+        self.job_cancel = None;
+        app.job = None;
+    }
+
+    /// Apply pending updates from the running background job
+    /// Returns the ids of the books the job changed
+    fn poll_job_updates(&mut self, app: &mut App) -> Vec<i32> {
+        let Some(rx) = self.job_updates.as_mut() else {
+            return Vec::new();
+        };
+
+        let mut changed = Vec::new();
+        let mut drm_changed = false;
+        while let Ok(update) = rx.try_recv() {
+            match update {
+                JobUpdate::Progress { done, total, current } => {
+                    if let Some(job) = app.job.as_mut() {
+                        job.done = done;
+                        job.total = total;
+                        job.current = current;
+                    }
+                }
+                JobUpdate::LibraryChanged(ids) => changed.extend(ids),
+                JobUpdate::Proposal(proposal) => app.review.proposals.push(proposal),
+                JobUpdate::Mismatch(mismatch) => {
+                    if let Some(audit) = app.metadata_audit.as_mut() {
+                        audit.mismatches.push(*mismatch);
+                    }
+                }
+                JobUpdate::Drm { book_id, format, scheme } => drm_changed |= app.record_drm(book_id, &format, scheme),
+                JobUpdate::Failed(failures) => {
+                    if let Some((record, _)) = self.job_record.as_mut() {
+                        record.failures.extend(failures);
+                    }
+                }
+                JobUpdate::Finished { message } => {
+                    self.record_job(app, &message);
+                    app.job = None;
+                    app.status_message = Some(message);
+                    self.job_updates = None;
+                    self.job_cancel = None;
+                    break;
+                }
+            }
+        }
+        if drm_changed {
+            self.save_drm(app);
+        }
+        changed
+    }
+
+    /// Keep the DRM verify found with the library's settings, for the lock markers next time
+    fn save_drm(&self, app: &mut App) {
+        let result = LibrarySettingsStore::load().and_then(|mut store| {
+            let mut settings = store.get(&app.library_path).cloned().unwrap_or_default();
+            settings.drm = app.drm.clone();
+            store.set(&app.library_path, settings);
+            store.save()
+        });
+        if let Err(e) = result {
+            app.status_message = Some(format!("❌ Failed to save the DRM found: {}", e));
+        }
+    }
+
+    /// Re-read the books that changed and patch them into the list, keeping the current search;
+    /// returns how many books were re-read or dropped
+    ///
+    /// Besides `changed`, books modified since the list was loaded are picked up and deleted
+    /// ones dropped, so changes made by calibre come along without reloading every book.
+    async fn reload_books(&self, app: &mut App, database: &Database, changed: &[i32]) -> usize {
+        let since = app.last_modified();
+        let reloaded = async {
+            let existing = database.load_book_ids().await?;
+            let books = database.load_changed_books(changed, &since).await?;
+            Ok::<_, Error>((books, existing))
+        };
+        match reloaded.await {
+            Ok((books, existing)) => {
+                let touched = books.len() + app.all_books.iter().filter(|b| !existing.contains(&b.id)).count();
+                app.patch_books(books, &existing);
+                self.refresh_books(app, database).await;
+                self.refresh_tags(app, database).await;
+                touched
+            }
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to reload books: {}", e));
+                0
+            }
+        }
+    }
+
+    /// Reload the books calibre or calibre-web changed since `metadata.db` was last looked at
+    ///
+    /// Skipped while a job runs or pages are loading, which reload what they touch themselves.
+    /// tuilibre's own writes change the file too, but leave no book newer than the list.
+    async fn check_library_changed(&mut self, app: &mut App, database: &Database) {
+        let Some(interval) = app.config.database.refresh_interval() else {
+            return;
+        };
+        if database.snapshot().is_some() || app.job.is_some() || app.pages.is_some() || self.library_stamp.1.elapsed() < interval {
+            return;
+        }
+        let stamp = database_stamp(&app.library_path);
+        let changed = stamp != self.library_stamp.0;
+        self.library_stamp = (stamp, Instant::now());
+        if !changed {
+            return;
+        }
+
+        let touched = self.reload_books(app, database, &[]).await;
+        if touched > 0 {
+            app.status_message = Some(format!("📚 Library updated elsewhere: {} books reloaded", touched));
+        }
+    }
+
+    /// Load the next page of a large library into the list as it is searched and sorted
+    async fn load_next_page(&self, app: &mut App, database: &Database) {
+        let Some(pages) = app.pages.clone() else {
+            return;
+        };
+        let limit = app.config.database.page_size.max(1);
+        let (sort, id) = &pages.after;
+        let page = match database.load_books_page(Some((sort, *id)), limit).await {
+            Ok(page) => page,
+            Err(e) => {
+                app.pages = None;
+                app.status_message = Some(format!("❌ Failed to load more books: {}", e));
+                return;
+            }
+        };
+
+        let appended = app.append_page(page, limit);
+        let unfiltered = app.book_query().is_empty() && app.sort.is_default() && !app.search_full_text;
+        match appended {
+            Some(added) if unfiltered => app.books.extend(added),
+            _ => {
+                let selected = app.get_selected_book().map(|b| b.id);
+                self.refresh_books(app, database).await;
+                if let Some(index) = selected.and_then(|id| app.books.iter().position(|b| b.id == id)) {
+                    app.selected_book_index = index;
+                }
+                // The search now covers this page too
+                if let Some(stats) = &mut app.search_stats {
+                    stats.count = app.books.len();
+                }
+            }
+        }
+    }
+
+    /// Load the authors, tags and description of those of the books loaded in a page without them
+    async fn load_details(&self, app: &mut App, database: &Database, ids: &[i32]) {
+        if ids.is_empty() {
+            return;
+        }
+        let wanted: HashSet<i32> = ids.iter().copied().collect();
+        let missing: Vec<i32> = app.all_books.iter().filter(|b| !b.details_loaded && wanted.contains(&b.id)).map(|b| b.id).collect();
+        if missing.is_empty() {
+            return;
+        }
+        match database.load_book_details(&missing).await {
+            Ok(details) => app.fill_details(&details),
+            Err(e) => app.status_message = Some(format!("❌ Failed to load book details: {}", e)),
+        }
+    }
+
+    /// Rebuild the visible list from the current search and filters, keeping the cursor in range
+    async fn refresh_books(&self, app: &mut App, database: &Database) {
+        match app.book_query().run_indexed(&app.all_books, &app.search_index, database).await {
+            Ok(books) => app.books = books,
+            Err(e) => app.status_message = Some(format!("❌ Failed to apply filters: {}", e)),
+        }
+        app.fts_snippets.clear();
+        app.content_matches.clear();
+        if let Some(phrase) = app.content_phrase() {
+            // The filter above already said if the index is missing or broken
+            if let Ok(matches) = database.content_matches(&phrase).await {
+                app.content_matches = matches.into_iter().map(|m| (m.book_id, m)).collect();
+            }
+        }
+        if app.search_full_text && !app.search_query.trim().is_empty() {
+            match database.fts_search(&app.search_query).await {
+                Ok(matches) => {
+                    app.fts_snippets = matches.into_iter().map(|m| (m.book_id, format!("{}: {}", m.format, m.snippet))).collect();
+                    app.books.retain(|b| app.fts_snippets.contains_key(&b.id));
+                }
+                Err(Error::FileMissing(_)) => {
+                    app.books.clear();
+                    app.status_message = Some("No full-text index; turn on full-text searching in calibre 6 or later to build one".to_string());
+                }
+                Err(e) => {
+                    app.books.clear();
+                    app.status_message = Some(format!("❌ Full-text search failed: {}", e));
+                }
+            }
+        }
+        if !app.sort.is_default() {
+            match database.sorted_book_ids(app.sort).await {
+                Ok(order) => app.apply_sort_order(&order),
+                Err(e) => app.status_message = Some(format!("❌ Failed to sort books: {}", e)),
+            }
+        }
+        app.selected_book_index = app.selected_book_index.min(app.books.len().saturating_sub(1));
+    }
+
+    /// Rebuild the list in the new sort order, staying on the selected book
+    async fn resort_books(&self, app: &mut App, database: &Database) {
+        let selected = app.get_selected_book().map(|b| b.id);
+        self.refresh_books(app, database).await;
+        if let Some(index) = selected.and_then(|id| app.books.iter().position(|b| b.id == id)) {
+            app.selected_book_index = index;
+        }
+        app.status_message = Some(format!("Sorted by {}", app.sort.label()));
+    }
+
+    /// Give the tag pane the keyboard, showing it first if it is hidden
+    async fn focus_tag_browser(&mut self, app: &mut App, database: &Database) {
+        if let Some(browser) = &mut app.tag_browser {
+            browser.focused = true;
+            return;
+        }
+        match database.load_tags().await {
+            Ok(tags) if tags.is_empty() => app.status_message = Some("No tags in this library".to_string()),
+            Ok(tags) => app.show_tag_browser(tags),
+            Err(e) => app.status_message = Some(format!("❌ Failed to load tags: {}", e)),
+        }
+    }
+
+    /// Keys of the tag pane: move, Enter to filter by the tag, Tab back to the books, Esc to hide it
+    async fn handle_tag_browser_key(&mut self, key: KeyEvent, app: &mut App, database: &Database) {
+        let Some(browser) = &mut app.tag_browser else {
+            return;
+        };
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => browser.select_previous_by(1),
+            KeyCode::Down | KeyCode::Char('j') => browser.select_next_by(1),
+            KeyCode::PageUp => browser.select_previous_by(10),
+            KeyCode::PageDown => browser.select_next_by(10),
+            KeyCode::Home | KeyCode::Char('g') => browser.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => browser.select_next_by(usize::MAX),
+            KeyCode::Tab | KeyCode::Right | KeyCode::Char('l') => browser.focused = false,
+            KeyCode::Esc => app.hide_tag_browser(),
+            KeyCode::Enter => {
+                let Some(tag) = browser.selected_tag().map(|t| t.name.clone()) else {
+                    return;
+                };
+                let filtered = toggle_tag_filter(&mut app.filters, &tag);
+                app.selected_book_index = 0;
+                self.refresh_books(app, database).await;
+                app.status_message = Some(if filtered {
+                    format!("🏷 {} books tagged {}", app.books.len(), tag)
+                } else {
+                    format!("Tag filter off: {}", tag)
+                });
+            }
+            _ => {}
+        }
+    }
+
+    /// Recount the tags shown in the tag pane after books changed
+    async fn refresh_tags(&self, app: &mut App, database: &Database) {
+        let Some(browser) = &mut app.tag_browser else {
+            return;
+        };
+        match database.load_tags().await {
+            Ok(tags) => browser.set_tags(tags),
+            Err(e) => app.status_message = Some(format!("❌ Failed to load tags: {}", e)),
+        }
+    }
+
+    /// Offer the values a filter of the chosen kind can take
+    async fn open_filter_values(&mut self, app: &mut App, database: &Database, kind: &str) {
+        let filters: Vec<Filter> = match kind {
+            "Tag" => database.load_tag_names().await.unwrap_or_default().into_iter().map(Filter::Tag).collect(),
+            "Format" => database.load_format_names().await.unwrap_or_default().into_iter().map(Filter::Format).collect(),
+            "Language" => database.load_language_codes().await.unwrap_or_default().into_iter().map(Filter::Language).collect(),
+            "Rating" => (1..=5).rev().map(Filter::Rating).collect(),
+            "Audiobook" => vec![Filter::Audiobook(true), Filter::Audiobook(false)],
+            "DRM" => vec![Filter::Drm(true), Filter::Drm(false)],
+            "Added" => {
+                let today = chrono::Local::now().date_naive();
+                [7, 30, 365]
+                    .into_iter()
+                    .map(|days| Filter::Added { from: Some(today - chrono::Duration::days(days)), to: None })
+                    .collect()
+            }
+            "Shelf" => {
+                // Pick up shelves changed in calibre since the library was opened
+                self.sync_shelves(app, database).await;
+                app.shelves.keys().cloned().map(Filter::Shelf).collect()
+            }
+            _ => return,
+        };
+
+        if filters.is_empty() {
+            app.status_message = Some(format!("No {} values in this library", kind.to_lowercase()));
+            return;
+        }
+
+        let items = filters.iter().map(|f| f.label()).collect();
+        app.menu = Some(Menu::new(MenuKind::FilterValue(filters), kind, items));
+    }
+
+    /// Show only the books quick filter `n` (1 for F1) matches, or all of them again if it already is
+    async fn apply_quick_filter(&mut self, app: &mut App, database: &Database, n: u8) {
+        let Some(quick) = app.config.quick_filters.for_key(n).cloned() else {
+            return;
+        };
+        let clauses = parse_calibre_search(&quick.search);
+        if app.filters == clauses {
+            app.filters.clear();
+            app.status_message = Some(format!("Quick filter off: {}", quick.name));
+        } else {
+            app.filters = clauses;
+            app.status_message = Some(format!("⚡ {}", quick.name));
+        }
+        self.refresh_books(app, database).await;
+    }
+
+    /// Add a filter unless an identical one is already active
+    async fn add_filter(&mut self, app: &mut App, database: &Database, clause: FilterClause) {
+        app.filters.retain(|c| c.filter != clause.filter);
+        app.filters.push(clause);
+        self.refresh_books(app, database).await;
+    }
+
+    /// Flip a filter chip between "matches" and "doesn't match"
+    async fn negate_filter(&mut self, app: &mut App, database: &Database, index: Option<usize>) {
+        match index.and_then(|i| app.filters.get_mut(i)) {
+            Some(clause) => {
+                clause.negated = !clause.negated;
+                self.refresh_books(app, database).await;
+            }
+            None => app.status_message = Some("No such filter".to_string()),
+        }
+    }
+
+    /// Drop one filter chip and re-apply the rest
+    async fn remove_filter(&mut self, app: &mut App, database: &Database, index: Option<usize>) {
+        match index.filter(|&i| i < app.filters.len()) {
+            Some(i) => {
+                app.filters.remove(i);
+                self.refresh_books(app, database).await;
+            }
+            None => app.status_message = Some("No such filter".to_string()),
+        }
+    }
+
+    /// Show every action that applies to the selected book
+    fn open_book_actions(&mut self, app: &mut App) {
+        let Some(book) = app.get_selected_book() else {
+            return;
+        };
+        let actions = Action::for_book(book, app);
+        let items = actions.iter().map(|a| a.menu_item()).collect();
+        let title = book.display_title();
+        app.menu = Some(Menu::new(MenuKind::BookActions(actions), title, items));
+    }
+
+    /// Edit the open prompt's input; Enter submits it, Esc cancels
+    async fn handle_prompt_key(&mut self, key: KeyEvent, app: &mut App, database: &Database) {
+        let Some(prompt) = app.prompt.as_mut() else {
+            return;
+        };
+
+        match key.code {
+            KeyCode::Esc => app.prompt = None,
+            KeyCode::Backspace => {
+                prompt.input.pop();
+            }
+            KeyCode::Char(c) => prompt.input.push(c),
+            KeyCode::Enter => {
+                let Some(prompt) = app.prompt.take() else {
+                    return;
+                };
+                let input = prompt.input.trim().to_string();
+                if input.is_empty() {
+                    return;
+                }
+                self.submit_prompt(prompt.kind, input, app, database).await;
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply what was typed into a prompt to the selected book
+    async fn submit_prompt(&mut self, kind: PromptKind, input: String, app: &mut App, database: &Database) {
+        if kind == PromptKind::SaveSearch {
+            self.save_search(app, database, input.trim()).await;
+            return;
+        }
+        if kind == PromptKind::RenameLibrary {
+            self.rename_library(app, &input);
+            return;
+        }
+        if kind == PromptKind::AddBook {
+            self.add_book_file(app, database, &input).await;
+            return;
+        }
+        let Some(book) = app.get_selected_book().cloned() else {
+            return;
+        };
+
+        if kind == PromptKind::OpenWith {
+            if self.open_book_with(app, &book, None, &input) {
+                self.record_open(app, database, book.id).await;
+            }
+            return;
+        }
+        if kind == PromptKind::ExportTo {
+            self.preview_export(app, database, &input).await;
+            return;
+        }
+        if kind == PromptKind::ArchiveTo {
+            self.archive_books(app, database, &input).await;
+            return;
+        }
+        if kind == PromptKind::SplitTo {
+            self.open_split_menu(app, &input);
+            return;
+        }
+        if kind == PromptKind::CalibreWebDb {
+            self.open_calibre_web(app, database, &input).await;
+            return;
+        }
+        if kind == PromptKind::AddToShelf || kind == PromptKind::RemoveFromShelf {
+            self.change_shelf(app, database, input.trim(), kind == PromptKind::AddToShelf).await;
+            return;
+        }
+        if (kind == PromptKind::AddTag || kind == PromptKind::RemoveTag) && !app.selected_ids.is_empty() {
+            self.tag_marked_books(app, database, input.trim(), kind).await;
+            return;
+        }
+
+        self.write_edits(&book, vec![PendingEdit::new(kind, input, &book)], app, database).await;
+    }
+
+    /// Write edits to a book, unless calibre or another tuilibre changed it since the list was loaded
+    async fn write_edits(&mut self, book: &Book, edits: Vec<PendingEdit>, app: &mut App, database: &Database) {
+        if database.snapshot().is_some() {
+            let queued = edits.iter().filter_map(|edit| QueuedAction::edit(edit, book)).collect();
+            self.queue_actions(app, queued);
+            return;
+        }
+        self.apply_edits(edits, Some(book.last_modified.clone()), app, database).await;
+    }
+
+    /// Write edits to one book in one go, unless it was changed since `loaded_modified`, and
+    /// reload it; None writes over any change made elsewhere
+    async fn apply_edits(&mut self, edits: Vec<PendingEdit>, loaded_modified: Option<String>, app: &mut App, database: &Database) {
+        let Some(first) = edits.first() else {
+            return;
+        };
+        let (book_id, loaded_title) = (first.book_id, first.loaded_title.clone());
+        let changes: Vec<BookChange> = edits.iter().filter_map(PendingEdit::change).collect();
+
+        match database.edit_book(book_id, loaded_modified.as_deref(), &changes).await {
+            Ok(EditOutcome::Written) => {}
+            Ok(EditOutcome::Changed) => {
+                let title = format!("\"{}\" was changed elsewhere", loaded_title);
+                let items = CONFLICT_CHOICES.iter().map(|c| c.to_string()).collect();
+                app.menu = Some(Menu::new(MenuKind::WriteConflict(edits), title, items));
+                return;
+            }
+            Ok(EditOutcome::Deleted) => {
+                app.status_message = Some(format!("❌ \"{}\" was deleted elsewhere", loaded_title));
+                self.reload_books(app, database, &[]).await;
+                return;
+            }
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to update {}: {}", loaded_title, e));
+                self.reload_books(app, database, &[book_id]).await;
+                return;
+            }
+        }
+
+        app.status_message = Some(match edits.as_slice() {
+            [edit] if edit.kind == PromptKind::AddTag => format!("🏷 Tagged \"{}\" with {}", edit.loaded_title, edit.input),
+            [edit] if edit.kind == PromptKind::RemoveTag => format!("🏷 Removed {} from \"{}\"", edit.input, edit.loaded_title),
+            [edit] if edit.kind == PromptKind::EditTitle => format!("✏️ Renamed to \"{}\"", edit.input),
+            _ => format!("✏️ Saved \"{}\"", loaded_title),
+        });
+        self.reload_books(app, database, &[book_id]).await;
+    }
+
+    /// Act on the choice made in the write conflict menu, see `CONFLICT_CHOICES`
+    ///
+    /// Edits change single fields, so merging writes them unless the other change touched
+    /// one of those fields too. Adding a tag never conflicts with other tags.
+    async fn resolve_conflict(&mut self, edits: Vec<PendingEdit>, choice: usize, app: &mut App, database: &Database) {
+        let Some(first) = edits.first() else {
+            return;
+        };
+        let (book_id, loaded_title) = (first.book_id, first.loaded_title.clone());
+        match choice {
+            0 => {
+                self.reload_books(app, database, &[book_id]).await;
+                app.status_message = Some(format!("🔄 Reloaded \"{}\", your edit was discarded", loaded_title));
+            }
+            1 => self.apply_edits(edits, None, app, database).await,
+            _ => {
+                let (current, modified) = match database.book_revision(book_id).await {
+                    Ok(Some(revision)) => revision,
+                    Ok(None) => {
+                        app.status_message = Some(format!("❌ \"{}\" was deleted elsewhere", loaded_title));
+                        self.reload_books(app, database, &[book_id]).await;
+                        return;
+                    }
+                    Err(e) => {
+                        app.status_message = Some(format!("❌ Failed to update {}: {}", loaded_title, e));
+                        return;
+                    }
+                };
+                if edits.iter().any(|e| e.kind == PromptKind::EditTitle) && current != loaded_title {
+                    self.reload_books(app, database, &[book_id]).await;
+                    app.status_message = Some(format!(
+                        "❌ Can't merge: the title was also changed to \"{}\", edit it again to replace it",
+                        current
+                    ));
+                    return;
+                }
+                if let Some(edit) = edits.iter().find(|e| e.kind == PromptKind::EditAuthors) {
+                    match database.book_authors(book_id).await {
+                        Ok(authors) if authors != edit.loaded_authors => {
+                            self.reload_books(app, database, &[book_id]).await;
+                            app.status_message = Some(format!(
+                                "❌ Can't merge: the authors were also changed to {}, edit them again to replace them",
+                                authors.join(" & ")
+                            ));
+                            return;
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            app.status_message = Some(format!("❌ Failed to update {}: {}", loaded_title, e));
+                            return;
+                        }
+                    }
+                }
+                // Written only if nothing changed again since the fields were compared
+                self.apply_edits(edits, Some(modified), app, database).await;
+            }
+        }
+    }
+
+    /// Open one of the book's files, the first without `format`, with a user-given command,
+    /// e.g. "foliate" or "zathura --fork"; true if it started
+    fn open_book_with(&self, app: &mut App, book: &Book, format: Option<&BookFormat>, command: &str) -> bool {
+        let path = match format {
+            Some(format) => Some(book.format_path(&app.library_path, format)),
+            None => book.file_path(&app.library_path),
+        };
+        let Some(path) = path else {
+            return false;
+        };
+        let mut parts = command.split_whitespace();
+        let Some(program) = parts.next() else {
+            return false;
+        };
+
+        let result = std::process::Command::new(program)
+            .args(parts)
+            .arg(&path)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn();
+        if let Err(e) = result {
+            app.status_message = Some(format!("❌ Failed to run {}: {}", program, e));
+            return false;
+        }
+        true
+    }
+
+    /// Play the book's audio file with the configured player, or the system's
+    async fn play_audiobook(&self, app: &mut App, book: &Book) -> bool {
+        let Some(format) = AUDIO_FORMATS
+            .iter()
+            .find_map(|wanted| book.formats.iter().find(|f| f.format.eq_ignore_ascii_case(wanted)))
+        else {
+            app.status_message = Some(format!("❌ \"{}\" has no audio file", book.title));
+            return false;
+        };
+        match app.config.open.audio_player.clone() {
+            Some(player) => self.open_book_with(app, book, Some(format), &player),
+            None => self.open_book_file(book, Some(format), &app.library_path).await,
+        }
+    }
+
+    /// Open the book as the first open rule matching its tags says; None when no rule applies,
+    /// or when the rule only names a format the book doesn't have
+    async fn open_by_rule(&self, app: &mut App, book: &Book) -> Option<bool> {
+        let rule = app.config.open.rule_for(&book.tags)?.clone();
+        let format = rule
+            .format
+            .as_deref()
+            .and_then(|wanted| book.formats.iter().find(|f| f.format.eq_ignore_ascii_case(wanted)));
+        match (rule.command.as_deref(), format) {
+            (Some(command), format) => Some(self.open_book_with(app, book, format, command)),
+            (None, Some(format)) => Some(self.open_book_file(book, Some(format), &app.library_path).await),
+            (None, None) => None,
+        }
+    }
+
+    /// Count an opening of the book, and copy the count and time into calibre if configured
+    ///
+    /// calibre's count wins if it is higher, so opens from another frontend writing the same
+    /// column aren't lost.
+    async fn record_open(&self, app: &mut App, database: &Database, book_id: i32) {
+        let tracking = &app.config.tracking;
+        let writes_calibre = tracking.writes_calibre() && !database.is_read_only();
+        let in_calibre = if writes_calibre {
+            database.load_open_count(tracking, book_id).await.ok().flatten().unwrap_or(0)
+        } else {
+            0
+        };
+
+        let result = LibrarySettingsStore::load().and_then(|mut store| {
+            let mut settings = store.get(&app.library_path).cloned().unwrap_or_default();
+            let record = settings.opens.entry(book_id).or_default();
+            record.count = record.count.max(in_calibre) + 1;
+            record.last = calibre_timestamp();
+            let record = record.clone();
+            store.set(&app.library_path, settings);
+            store.save()?;
+            Ok(record)
+        });
+        let record = match result {
+            Ok(record) => record,
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to record the opening: {}", e));
+                return;
+            }
+        };
+
+        if writes_calibre {
+            match database.write_open_record(tracking, book_id, record.count, &record.last).await {
+                Ok(()) => {
+                    self.reload_books(app, database, &[book_id]).await;
+                }
+                Err(e) => app.status_message = Some(format!("❌ Failed to write the open count to calibre: {}", e)),
+            }
+        }
+    }
+
+    /// Convert the selected book to another format in the background
+    fn convert_book(&mut self, app: &mut App, database: &Database, format: &str) {
+        let Some(book) = app.get_selected_book() else {
+            return;
+        };
+        let ids = [book.id];
+        self.convert_book_ids(app, database, &ids, format);
+    }
+
+    /// Convert books to another format in the background, one at a time
+    fn convert_book_ids(&mut self, app: &mut App, database: &Database, ids: &[i32], format: &str) {
+        let mut queue = ConversionQueue::new(1);
+        for book in app.all_books.iter().filter(|b| ids.contains(&b.id)) {
+            let Some(input) = book.file_path(&app.library_path) else {
+                continue;
+            };
+            queue.push(ConversionTask {
+                book_id: book.id,
+                title: book.title.clone(),
+                output: input.with_extension(format.to_lowercase()),
+                input,
+                target_format: format.to_string(),
+            });
+        }
+        if queue.is_empty() {
+            return;
+        }
+
+        let label = format!("Converting to {}", format);
+        let total = queue.len();
+        if let Some((tx, cancel)) = self.start_job(app, JobKind::Convert { format: format.to_string() }, &label, total) {
+            tokio::spawn(jobs::convert_books(database.clone(), queue, tx, cancel));
+        }
+    }
+
+    /// Check the books' files in the background, several at a time
+    fn verify_book_ids(&mut self, app: &mut App, ids: &[i32]) {
+        let ids: HashSet<i32> = ids.iter().copied().collect();
+        let books: Vec<&Book> = app.all_books.iter().filter(|b| ids.contains(&b.id)).collect();
+        let items = jobs::plan_verify(&app.library_path, &books);
+        if items.is_empty() {
+            app.status_message = Some("Nothing to verify: the books have no files".to_string());
+            return;
+        }
+        let total = items.len();
+        if let Some((tx, cancel)) = self.start_job(app, JobKind::Verify, "Verifying files", total) {
+            tokio::spawn(jobs::verify_files(items, jobs::verify::default_workers(), tx, cancel));
+        }
+    }
+
+    /// Write the books' metadata into their files in the background
+    async fn embed_book_ids(&mut self, app: &mut App, database: &Database, ids: &[i32]) {
+        // Files are given the books' authors
+        self.load_details(app, database, ids).await;
+        let books: Vec<&Book> = app.all_books.iter().filter(|b| ids.contains(&b.id)).collect();
+        let items = jobs::plan_embed(&app.library_path, &books);
+        if items.is_empty() {
+            app.status_message = Some("Nothing to update: the books have no EPUB or other files metadata can be written into".to_string());
+            return;
+        }
+        let total = items.len();
+        let ebook_meta = app.config.metadata.ebook_meta.clone();
+        if let Some((tx, cancel)) = self.start_job(app, JobKind::EmbedMetadata, "Updating file metadata", total) {
+            tokio::spawn(jobs::embed_metadata(database.clone(), items, ebook_meta, tx, cancel));
+        }
+    }
+
+    /// Delete a book confirmed by the user from the library
+    /// Copy an ebook file into the library as a new book, described by its EPUB metadata or its
+    /// name, and select it
+    async fn add_book_file(&mut self, app: &mut App, database: &Database, input: &str) {
+        let file = expand_home(input);
+        let (book, cover) = epub::import_metadata(&file);
+        match database.add_book(&app.library_path, &book, &file, cover.as_deref()).await {
+            Ok(id) => {
+                self.reload_books(app, database, &[id]).await;
+                app.status_message = Some(match app.books.iter().position(|b| b.id == id) {
+                    Some(index) => {
+                        app.selected_book_index = index;
+                        format!("📚 Added: {}", book.title)
+                    }
+                    None => format!("📚 Added: {} (hidden by the search or filters)", book.title),
+                });
+            }
+            Err(e) => app.status_message = Some(format!("❌ Failed to add {}: {}", file.display(), e)),
+        }
+    }
+
+    /// Ask before deleting `book`, listing the rows and files that would go
+    async fn confirm_delete(&mut self, app: &mut App, database: &Database, book: &Book) {
+        match database.deletion_preview(&app.library_path, book.id).await {
+            Ok(preview) => {
+                app.pending_delete = Some(DeleteConfirm { book_id: book.id, title: book.title.clone(), preview, remove_files: true });
+            }
+            Err(e) => app.status_message = Some(format!("❌ Failed to delete {}: {}", book.title, e)),
+        }
+    }
+
+    async fn delete_book(&mut self, app: &mut App, database: &Database, confirm: DeleteConfirm) {
+        let DeleteConfirm { book_id, title, remove_files, .. } = confirm;
+        match database.delete_book(&app.library_path, book_id, remove_files).await {
+            Ok(()) => {
+                app.selected_ids.remove(&book_id);
+                app.status_message = Some(if remove_files {
+                    format!("🗑 Deleted from library: {}", title)
+                } else {
+                    format!("🗑 Deleted from library, files kept: {}", title)
+                });
+                // The details of a deleted book have nothing left to show
+                if matches!(app.mode, AppMode::Details | AppMode::DetailsFromSearch) {
+                    app.details_scroll.set(0);
+                    app.mode = if app.mode == AppMode::DetailsFromSearch { AppMode::Search } else { AppMode::Normal };
+                }
+                self.reload_books(app, database, &[]).await;
+            }
+            Err(e) => app.status_message = Some(format!("❌ Failed to delete {}: {}", title, e)),
+        }
+    }
+
+    /// Let the user restrict the list to one of the library's calibre virtual libraries
+    async fn open_virtual_library_menu(&mut self, app: &mut App, database: &Database) {
+        let libraries = match database.load_virtual_libraries().await {
+            Ok(libraries) => libraries,
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to load virtual libraries: {}", e));
+                return;
+            }
+        };
+        if libraries.is_empty() {
+            app.status_message = Some("No virtual libraries defined in this library".to_string());
+            return;
+        }
+
+        let items = std::iter::once("(whole library)".to_string()).chain(libraries.into_keys()).collect();
+        app.menu = Some(Menu::new(MenuKind::VirtualLibrary, "Virtual library", items));
+    }
+
+    /// Let the user pick a search saved in tuilibre or calibre, or one of calibre's virtual libraries
+    async fn open_saved_searches(&mut self, app: &mut App, database: &Database) {
+        let local = match LibrarySettingsStore::load() {
+            Ok(store) => store.get(&app.library_path).map(|s| s.saved_searches.clone()).unwrap_or_default(),
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to load library settings: {}", e));
+                return;
+            }
+        };
+        let calibre = match (database.load_saved_searches().await, database.load_virtual_libraries().await) {
+            (Ok(saved), Ok(libraries)) => (saved, libraries),
+            (Err(e), _) | (_, Err(e)) => {
+                app.status_message = Some(format!("❌ Failed to load calibre's saved searches: {}", e));
+                return;
+            }
+        };
+
+        let sources = [(local, SearchSource::Local), (calibre.0, SearchSource::Calibre), (calibre.1, SearchSource::VirtualLibrary)];
+        let searches: Vec<SavedSearch> = sources
+            .into_iter()
+            .flat_map(|(searches, source)| {
+                searches.into_iter().map(move |(name, expression)| SavedSearch { name, expression, source })
+            })
+            .collect();
+        if searches.is_empty() {
+            app.status_message = Some("No saved searches; save one with Ctrl-s while searching".to_string());
+            return;
+        }
+
+        let items = searches
+            .iter()
+            .map(|s| format!("{}: {}{}", s.name, s.expression, s.source.label()))
+            .collect();
+        app.menu = Some(Menu::new(MenuKind::SavedSearch(searches), "Saved searches (d deletes)", items));
+    }
+
+    /// Search for `expression` as if it had been typed
+    async fn run_saved_search(&self, app: &mut App, database: &Database, expression: String) {
+        app.mode = AppMode::Search;
+        app.search_query = expression;
+        app.search_layers.clear();
+        app.search_plain = false;
+        self.perform_realtime_search(app, database).await;
+    }
+
+    /// Save the search being typed, with the searches it narrows, under `name`, into calibre if
+    /// so configured
+    async fn save_search(&self, app: &mut App, database: &Database, name: &str) {
+        let expression = app.full_search();
+        if name.is_empty() || expression.is_empty() {
+            return;
+        }
+
+        let result = if app.config.searches.write_calibre {
+            database.write_saved_search(name, Some(&expression)).await
+        } else {
+            LibrarySettingsStore::load().and_then(|mut store| {
+                let mut settings = store.get(&app.library_path).cloned().unwrap_or_default();
+                settings.saved_searches.insert(name.to_string(), expression);
+                store.set(&app.library_path, settings);
+                store.save()
+            })
+        };
+        app.status_message = Some(match result {
+            Ok(()) => format!("💾 Saved search \"{}\"", name),
+            Err(e) => format!("❌ Failed to save search: {}", e),
+        });
+    }
+
+    /// Delete a saved search from wherever it is kept, and show the remaining ones
+    async fn delete_saved_search(&mut self, app: &mut App, database: &Database, search: &SavedSearch) {
+        let result = match search.source {
+            SearchSource::VirtualLibrary => {
+                app.status_message = Some("Virtual libraries can only be changed in calibre".to_string());
+                return;
+            }
+            SearchSource::Calibre => database.write_saved_search(&search.name, None).await,
+            SearchSource::Local => LibrarySettingsStore::load().and_then(|mut store| {
+                let mut settings = store.get(&app.library_path).cloned().unwrap_or_default();
+                settings.saved_searches.remove(&search.name);
+                store.set(&app.library_path, settings);
+                store.save()
+            }),
+        };
+        match result {
+            Ok(()) => {
+                self.open_saved_searches(app, database).await;
+                app.status_message = Some(format!("🗑 Deleted saved search \"{}\"", search.name));
+            }
+            Err(e) => app.status_message = Some(format!("❌ Failed to delete saved search: {}", e)),
+        }
+    }
+
+    /// Restrict the list to the named virtual library, or lift the restriction with `None`
+    async fn set_virtual_library(&self, app: &mut App, database: &Database, name: Option<&str>) {
+        app.virtual_library = None;
+        if let Some(name) = name {
+            match database.load_virtual_libraries().await {
+                Ok(libraries) => match libraries.get(name) {
+                    Some(expression) => {
+                        app.virtual_library = Some(VirtualLibrary {
+                            name: name.to_string(),
+                            clauses: parse_calibre_search(expression),
+                        });
+                    }
+                    None => app.status_message = Some(format!("❌ No virtual library named \"{}\"", name)),
+                },
+                Err(e) => app.status_message = Some(format!("❌ Failed to load virtual libraries: {}", e)),
+            }
+        }
+        self.refresh_books(app, database).await;
+    }
+
+    /// Apply the view, filters, virtual library and shelves saved for the open library
+    pub async fn apply_library_defaults(&mut self, app: &mut App, database: &Database) {
+        let settings = match LibrarySettingsStore::load() {
+            Ok(store) => store.get(&app.library_path).cloned().unwrap_or_default(),
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to load library settings: {}", e));
+                LibrarySettings::default()
+            }
+        };
+
+        app.view = settings.view;
+        app.sort = settings.sort;
+        app.filters = settings.filters;
+        app.library_alias = LibraryHistory::load()
+            .ok()
+            .and_then(|history| history.alias(&app.library_path).map(str::to_string));
+        app.set_shelves(settings.shelves);
+        app.set_reading(settings.reading);
+        app.set_drm(settings.drm);
+        app.annotations = settings.annotations;
+        app.queued = settings.queued;
+        self.apply_calibre_display(app, database).await;
+        self.set_virtual_library(app, database, settings.virtual_library.as_deref()).await;
+        self.refresh_tags(app, database).await;
+
+        app.read_only = database.lock_owner().map(|owner| owner.describe());
+        if let Some(holder) = &app.read_only {
+            app.status_message = Some(format!("🔒 Opened read-only, {} is writing to this library (W to steal the lock)", holder));
+        }
+        app.snapshot = database.snapshot();
+        if let Some(taken) = app.snapshot {
+            let taken = format_time(taken, &app.config.display.time_format, app.absolute_times, &app.locale());
+            app.status_message = Some(format!("📦 The library is offline, browsing its snapshot from {}; changes can't be saved", taken));
+        } else if app.config.offline.snapshots
+            && (removable_mount(&app.library_path).is_some() || snapshot_taken(&app.library_path).is_some())
+        {
+            if let Err(e) = database.save_snapshot().await {
+                app.status_message = Some(format!("⚠️ Failed to save an offline snapshot: {}", e));
+            }
+        }
+        self.sync_shelves(app, database).await;
+        if !app.queued.is_empty() && !database.is_read_only() {
+            self.apply_queued(app, database).await;
+        }
+    }
+
+    /// Tell that the library answers slowly, and keep a snapshot of it to browse when it is unreachable
+    async fn warn_slow_library(&self, app: &mut App, database: &Database, took: Duration) {
+        let took = format!("A query took {:.1}s, this library is slow to reach", took.as_secs_f64());
+        app.status_message = Some(if database.snapshot().is_some() || snapshot_taken(&app.library_path).is_some() {
+            format!("🐢 {}; its snapshot opens when it is offline", took)
+        } else if !app.config.offline.snapshots {
+            format!("🐢 {}; turn on [offline] snapshots to browse a cached copy when it is unreachable", took)
+        } else {
+            match database.save_snapshot().await {
+                Ok(()) => format!("🐢 {}; saved a snapshot to browse it when it is unreachable", took),
+                Err(e) => format!("🐢 {}; failed to save an offline snapshot: {}", took, e),
+            }
+        });
+    }
+
+    /// Add actions taken offline to the queue, saved so they survive a restart
+    fn queue_actions(&self, app: &mut App, actions: Vec<QueuedAction>) {
+        let Some(last) = actions.last().map(QueuedAction::describe) else {
+            return;
+        };
+        app.queued.extend(actions);
+        app.status_message = Some(match self.save_queue(app) {
+            Ok(()) => format!("📥 Queued until the library is back online: {} (Q lists the queue)", last),
+            Err(e) => format!("❌ Failed to save the queued action: {}", e),
+        });
+    }
+
+    /// Save `App::queued` with the library's settings
+    fn save_queue(&self, app: &App) -> crate::error::Result<()> {
+        LibrarySettingsStore::load().and_then(|mut store| {
+            let settings = LibrarySettings {
+                queued: app.queued.clone(),
+                ..store.get(&app.library_path).cloned().unwrap_or_default()
+            };
+            store.set(&app.library_path, settings);
+            store.save()
+        })
+    }
+
+    /// Apply what was queued while the library was offline, holding back actions that would
+    /// overwrite changes made elsewhere, or that can't run yet, such as a send with no e-reader
+    async fn apply_queued(&mut self, app: &mut App, database: &Database) {
+        let mut applied = 0;
+        let mut changed = Vec::new();
+        let mut held = Vec::new();
+        for mut action in std::mem::take(&mut app.queued) {
+            match self.run_queued(app, database, &action, false).await {
+                Ok(None) => {
+                    applied += 1;
+                    if let QueuedKind::Edit(edit) = &action.kind {
+                        changed.push(edit.book_id);
+                    }
+                }
+                Ok(Some(reason)) => {
+                    action.held = Some(reason);
+                    held.push(action);
+                }
+                Err(e) => {
+                    action.held = Some(e.to_string());
+                    held.push(action);
+                }
+            }
+        }
+        app.queued = held;
+        if !changed.is_empty() {
+            self.reload_books(app, database, &changed).await;
+        }
+
+        app.status_message = Some(match self.save_queue(app) {
+            Err(e) => format!("❌ Failed to save the queued actions: {}", e),
+            Ok(()) if app.queued.is_empty() => format!("📤 Applied {} actions queued while offline", applied),
+            Ok(()) => format!("📤 Applied {} actions queued while offline, {} held back (Q to review)", applied, app.queued.len()),
+        });
+    }
+
+    /// Run a queued action; Ok(Some(reason)) if it has to wait
+    ///
+    /// Forcing writes an edit even over a change made elsewhere.
+    async fn run_queued(&mut self, app: &mut App, database: &Database, action: &QueuedAction, force: bool) -> crate::error::Result<Option<String>> {
+        match &action.kind {
+            QueuedKind::Edit(edit) => {
+                if !force {
+                    if let Some(reason) = edit.conflict(database).await? {
+                        return Ok(Some(reason));
+                    }
+                }
+                edit.apply(database).await?;
+                Ok(None)
+            }
+            QueuedKind::Send { book_ids, .. } => Ok(self.send_books(app, database, book_ids).await.err()),
+            QueuedKind::Email { profile, book_ids, .. } => Ok(self.email_book_ids(app, database, profile, book_ids).await.err()),
+        }
+    }
+
+    /// List the actions queued while offline; Enter applies one now, d drops it
+    fn open_queue_menu(&self, app: &mut App, database: &Database) {
+        if app.queued.is_empty() {
+            app.status_message = Some("📭 Nothing is queued".to_string());
+            return;
+        }
+        let title = if database.snapshot().is_some() {
+            "Queued until the library is back online | d Drop"
+        } else {
+            "Queued while offline | Enter Apply now, overwriting changes made elsewhere | d Drop"
+        };
+        let items = app.queued.iter().map(QueuedAction::describe).collect();
+        app.menu = Some(Menu::new(MenuKind::QueuedActions, title, items));
+    }
+
+    /// Apply one queued action from the queue view
+    async fn apply_queued_action(&mut self, app: &mut App, database: &Database, index: usize) {
+        if database.snapshot().is_some() {
+            app.status_message = Some("📦 The library is still offline".to_string());
+            return;
+        }
+        if index >= app.queued.len() {
+            return;
+        }
+        let mut action = app.queued.remove(index);
+        let description = action.describe();
+        match self.run_queued(app, database, &action, true).await {
+            Ok(None) => {
+                app.status_message = Some(format!("📤 Applied: {}", description));
+                if let QueuedKind::Edit(edit) = &action.kind {
+                    self.reload_books(app, database, &[edit.book_id]).await;
+                }
+            }
+            Ok(Some(reason)) => {
+                app.status_message = Some(format!("❌ Can't apply it yet: {}", reason));
+                action.held = Some(reason);
+                app.queued.insert(index, action);
+            }
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to apply it: {}", e));
+                action.held = Some(e.to_string());
+                app.queued.insert(index, action);
+            }
+        }
+        if let Err(e) = self.save_queue(app) {
+            app.status_message = Some(format!("❌ Failed to save the queued actions: {}", e));
+        }
+    }
+
+    /// Give the open library an alias in history, or drop it with an empty one
+    fn rename_library(&self, app: &mut App, alias: &str) {
+        let result = LibraryHistory::load().and_then(|mut history| {
+            history.set_alias(&app.library_path, Some(alias.to_string()));
+            history.save()?;
+            Ok(history.alias(&app.library_path).map(str::to_string))
+        });
+        match result {
+            Ok(alias) => {
+                app.status_message = Some(match &alias {
+                    Some(alias) => format!("✏️ Library renamed to \"{}\"", alias),
+                    None => "✏️ Library shown under its folder name again".to_string(),
+                });
+                app.library_alias = alias;
+            }
+            Err(e) => app.status_message = Some(format!("❌ Failed to rename library: {}", e)),
+        }
+    }
+
+    /// Follow calibre's book list columns and hidden tag browser categories, unless the config
+    /// sets the columns
+    async fn apply_calibre_display(&self, app: &mut App, database: &Database) {
+        let prefs = match database.load_display_prefs().await {
+            Ok(prefs) => prefs,
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to read calibre's display preferences: {}", e));
+                DisplayPrefs::default()
+            }
+        };
+        app.table_columns = app
+            .config
+            .display
+            .table_columns
+            .clone()
+            .filter(|columns| !columns.is_empty())
+            .or_else(|| prefs.table_columns())
+            .unwrap_or_else(|| TableColumn::DEFAULT.to_vec());
+        app.hidden_categories = prefs.hidden_categories;
+    }
+
+    /// Read calibre-web's accounts, asking whose state to import when there are several
+    async fn open_calibre_web(&mut self, app: &mut App, database: &Database, input: &str) {
+        let path = expand_home(input.trim());
+        let users = match calibre_web::load_users(&path).await {
+            Ok(users) => users,
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to read calibre-web's database: {}", e));
+                return;
+            }
+        };
+        match users.as_slice() {
+            [] => app.status_message = Some(format!("No calibre-web users in {}", path.display())),
+            [user] => self.import_calibre_web(app, database, &path, user).await,
+            _ => {
+                let items = users.iter().map(|u| u.name.clone()).collect();
+                app.menu = Some(Menu::new(MenuKind::CalibreWebUser(path, users), "Import whose reading state", items));
+            }
+        }
+    }
+
+    /// Take one calibre-web user's read statuses and shelves into this library
+    ///
+    /// Statuses are kept in tuilibre and written to `tracking.read_column` if set; shelves are
+    /// added to tuilibre's, which syncs them to calibre when shelves are backed by it.
+    async fn import_calibre_web(&mut self, app: &mut App, database: &Database, path: &Path, user: &CalibreWebUser) {
+        let state = match calibre_web::load_state(path, user.id).await {
+            Ok(state) => state,
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to read calibre-web's database: {}", e));
+                return;
+            }
+        };
+
+        // calibre-web may remember books deleted from the library since
+        let known: HashSet<i32> = app.all_books.iter().map(|b| b.id).collect();
+        let reading: BTreeMap<i32, ReadStatus> = state.reading.into_iter().filter(|(id, _)| known.contains(id)).collect();
+        let mut all = app.reading.clone();
+        all.extend(reading.iter().map(|(id, status)| (*id, *status)));
+        app.set_reading(all);
+        let saved = LibrarySettingsStore::load().and_then(|mut store| {
+            let mut settings = store.get(&app.library_path).cloned().unwrap_or_default();
+            settings.reading = app.reading.clone();
+            store.set(&app.library_path, settings);
+            store.save()
+        });
+        if let Err(e) = saved {
+            app.status_message = Some(format!("❌ Failed to save reading status: {}", e));
+            return;
+        }
+
+        let flags: Vec<(i32, bool)> = reading.iter().map(|(id, status)| (*id, *status == ReadStatus::Read)).collect();
+        if let Err(e) = database.write_read_flags(&app.config.tracking, &flags).await {
+            app.status_message = Some(format!("❌ Failed to write read status to calibre: {}", e));
+            return;
+        }
+
+        let shelf_count = state.shelves.len();
+        let mut shelves = app.shelves.clone();
+        for (name, books) in state.shelves {
+            shelves.entry(name).or_default().books.extend(books.into_iter().filter(|id| known.contains(id)));
+        }
+        app.set_shelves(shelves);
+        if self.sync_shelves(app, database).await {
+            let read = reading.values().filter(|s| **s == ReadStatus::Read).count();
+            app.status_message = Some(format!(
+                "📥 Imported {}'s calibre-web state: {} read, {} reading, {} shelves",
+                user.name,
+                read,
+                reading.len() - read,
+                shelf_count
+            ));
+        }
+    }
+
+    /// Put the marked books (or the current one) on a shelf, or take them off it
+    async fn change_shelf(&mut self, app: &mut App, database: &Database, name: &str, add: bool) {
+        if name.is_empty() {
+            return;
+        }
+        if app.config.shelves.backing != ShelfBacking::Local && database.is_read_only() {
+            app.status_message = Some("🔒 Read-only: shelves are kept in calibre and can't be changed".to_string());
+            return;
+        }
+        if !add && !app.shelves.contains_key(name) {
+            app.status_message = Some(format!("❌ No shelf named \"{}\"", name));
+            return;
+        }
+
+        let ids: Vec<i32> = app.selection_or_current().iter().map(|b| b.id).collect();
+        let mut shelves = app.shelves.clone();
+        let shelf = shelves.entry(name.to_string()).or_default();
+        for id in &ids {
+            if add {
+                shelf.books.insert(*id);
+            } else {
+                shelf.books.remove(id);
+            }
+        }
+        app.set_shelves(shelves);
+        app.selected_ids.clear();
+        if self.sync_shelves(app, database).await {
+            let verb = if add { "Added to" } else { "Removed from" };
+            app.status_message = Some(format!("📚 {} shelf {}: {} books", verb, name, ids.len()));
+        }
+    }
+
+    /// Keys of visual select, taken before the book list's own; false for the keys it leaves alone
+    fn handle_visual_key(&mut self, key: KeyEvent, app: &mut App) -> bool {
+        match key.code {
+            KeyCode::Char(' ') => {
+                app.toggle_selected();
+                app.select_next();
+            }
+            KeyCode::Char('V') => {
+                app.status_message = Some(match app.mark_range() {
+                    Some(count) => format!("● Marked {} books, {} in all", count, app.selected_ids.len()),
+                    None => "Range started: move to its other end and press V".to_string(),
+                });
+            }
+            KeyCode::Enter => {
+                if app.selected_ids.is_empty() {
+                    app.status_message = Some("Nothing marked: Space marks a book, V a range".to_string());
+                    return true;
+                }
+                let items = BATCH_ACTIONS.iter().map(|a| a.menu_item()).collect();
+                let title = format!("{} marked books", app.selected_ids.len());
+                app.menu = Some(Menu::new(MenuKind::BookActions(BATCH_ACTIONS.to_vec()), title, items));
+            }
+            KeyCode::Esc => {
+                app.visual_select = false;
+                app.range_anchor = None;
+                app.status_message = Some(format!("Visual select ended, {} marked", app.selected_ids.len()));
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Open each marked book (or the current one) as `Action::Open` would, within reason
+    async fn open_marked_books(&mut self, app: &mut App, database: &Database) {
+        let books: Vec<Book> = app.selection_or_current().into_iter().cloned().collect();
+        if books.len() > MAX_OPEN_AT_ONCE {
+            app.status_message = Some(format!("❌ Not opening {} books at once; mark at most {}", books.len(), MAX_OPEN_AT_ONCE));
+            return;
+        }
+        let mut opened = 0;
+        for book in &books {
+            let ok = match self.open_by_rule(app, book).await {
+                Some(ok) => ok,
+                None => self.open_book_file(book, None, &app.library_path).await,
+            };
+            if ok {
+                self.record_open(app, database, book.id).await;
+                opened += 1;
+            }
+        }
+        app.status_message = Some(if opened == books.len() {
+            format!("📖 Opened {} books", opened)
+        } else {
+            format!("📖 Opened {} of {} books; the others have no file to open", opened, books.len())
+        });
+        app.selected_ids.clear();
+    }
+
+    /// Add a tag to every marked book, or remove it from them
+    ///
+    /// Like a single tag edit, this never conflicts with changes made elsewhere. Offline, the
+    /// edits are queued.
+    async fn tag_marked_books(&mut self, app: &mut App, database: &Database, tag: &str, kind: PromptKind) {
+        let books: Vec<Book> = app.selection_or_current().into_iter().cloned().collect();
+        let ids: Vec<i32> = books.iter().map(|b| b.id).collect();
+        let add = kind == PromptKind::AddTag;
+
+        if database.snapshot().is_some() {
+            let queued = books
+                .iter()
+                .filter_map(|book| QueuedAction::edit(&PendingEdit::new(kind, tag, book), book))
+                .collect();
+            self.queue_actions(app, queued);
+            app.selected_ids.clear();
+            return;
+        }
+
+        let tags = [tag.to_string()];
+        for book in &books {
+            let result = if add { database.add_tags(book.id, &tags).await } else { database.remove_tags(book.id, &tags).await };
+            if let Err(e) = result {
+                app.status_message = Some(format!("❌ Failed to update {}: {}", book.title, e));
+                self.reload_books(app, database, &ids).await;
+                return;
+            }
+        }
+        self.reload_books(app, database, &ids).await;
+        app.selected_ids.clear();
+        app.status_message = Some(if add {
+            format!("🏷 Tagged {} books with {}", ids.len(), tag)
+        } else {
+            format!("🏷 Removed {} from {} books", tag, ids.len())
+        });
+    }
+
+    /// Merge the shelves with calibre's copy when they are backed by it, then save and show them
+    ///
+    /// A read-only library is left alone; its shelves sync once it is writable again. Returns
+    /// false if syncing or saving failed.
+    async fn sync_shelves(&self, app: &mut App, database: &Database) -> bool {
+        let mut shelves = app.shelves.clone();
+        let changed = if database.is_read_only() {
+            Vec::new()
+        } else {
+            match shelves::sync_shelves(database, &app.config.shelves, &mut shelves).await {
+                Ok(changed) => changed,
+                Err(e) => {
+                    app.status_message = Some(format!("❌ Failed to sync shelves with calibre: {}", e));
+                    return false;
+                }
+            }
+        };
+        app.set_shelves(shelves);
+
+        let result = LibrarySettingsStore::load().and_then(|mut store| {
+            let mut settings = store.get(&app.library_path).cloned().unwrap_or_default();
+            settings.shelves = app.shelves.clone();
+            store.set(&app.library_path, settings);
+            store.save()
+        });
+        self.reload_books(app, database, &changed).await;
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to save shelves: {}", e));
+                false
+            }
+        }
+    }
+
+    /// Take the write lock from another instance, e.g. one that crashed on another machine
+    fn steal_lock(&self, app: &mut App, database: &Database) {
+        if app.snapshot.is_some() {
+            app.status_message = Some("📦 The library is offline, there is no lock to take".to_string());
+            return;
+        }
+        if app.read_only.is_none() {
+            app.status_message = Some("🔓 This library is already writable".to_string());
+            return;
+        }
+        match database.steal_lock() {
+            Ok(()) => {
+                app.read_only = None;
+                app.status_message = Some("🔓 Took the library lock, writes are allowed".to_string());
+            }
+            Err(e) => app.status_message = Some(format!("❌ Failed to take the library lock: {}", e)),
+        }
+    }
+
+    /// Remember the current view, filters and virtual library as this library's defaults
+    fn save_library_defaults(&self, app: &mut App) {
+        let result = LibrarySettingsStore::load().and_then(|mut store| {
+            let settings = LibrarySettings {
+                view: app.view,
+                sort: app.sort,
+                filters: app.filters.clone(),
+                virtual_library: app.virtual_library.as_ref().map(|vl| vl.name.clone()),
+                shelves: app.shelves.clone(),
+                ..store.get(&app.library_path).cloned().unwrap_or_default()
+            };
+            store.set(&app.library_path, settings);
+            store.save()
+        });
+        app.status_message = Some(match result {
+            Ok(()) => "💾 Saved view, sort and filters as this library's default".to_string(),
+            Err(e) => format!("❌ Failed to save library settings: {}", e),
+        });
+    }
+
+    /// Send the marked books (or the current one) to the first connected e-reader
+    ///
+    /// While the library is offline the send is queued instead.
+    async fn send_to_device(&mut self, app: &mut App, database: &Database) {
+        let books = app.selection_or_current();
+        if books.is_empty() {
+            return;
+        }
+        let ids: Vec<i32> = books.iter().map(|b| b.id).collect();
+
+        if database.snapshot().is_some() {
+            let titles = books.iter().map(|b| b.title.clone()).collect();
+            self.queue_actions(app, vec![QueuedAction::new(QueuedKind::Send { book_ids: ids, titles })]);
+        } else if let Err(message) = self.send_books(app, database, &ids).await {
+            app.status_message = Some(format!("❌ {}", message));
+            return;
+        }
+        app.selected_ids.clear();
+    }
+
+    /// Start sending books to the first connected e-reader, or say why it can't start
+    async fn send_books(&mut self, app: &mut App, database: &Database, ids: &[i32]) -> std::result::Result<(), String> {
+        let Some(device) = device::detect_devices(&app.config.devices).into_iter().next() else {
+            return Err("No configured or known e-reader detected".to_string());
+        };
+        if app.job.is_some() {
+            return Err("Another job is still running".to_string());
+        }
+
+        let formats = database.load_formats(ids).await.map_err(|e| format!("Failed to load formats: {}", e))?;
+        let books: Vec<&Book> = app.all_books.iter().filter(|b| ids.contains(&b.id)).collect();
+        let plan = jobs::plan_send(&device, &app.library_path, &books, &formats);
+        let label = format!("Sending to {}", device.profile.name);
+        let kind = JobKind::Send { device: device.profile.name.clone() };
+        if let Some((tx, cancel)) = self.start_job(app, kind, &label, plan.items.len()) {
+            tokio::spawn(jobs::send_books(device, plan, tx, cancel));
+        }
+        Ok(())
+    }
+
+    /// Show the names the marked books (or the current one) would be exported under
+    async fn preview_export(&mut self, app: &mut App, database: &Database, directory: &str) {
+        let ids: Vec<i32> = app.selection_or_current().iter().map(|b| b.id).collect();
+        self.preview_export_ids(app, database, &ids, &expand_home(directory)).await;
+    }
+
+    /// Show the names the books would be exported under, to export them once confirmed
+    async fn preview_export_ids(&mut self, app: &mut App, database: &Database, ids: &[i32], directory: &Path) {
+        // Templates name files by author
+        self.load_details(app, database, ids).await;
+        let books: Vec<&Book> = app.all_books.iter().filter(|b| ids.contains(&b.id)).collect();
+        let (formats, series) = match (database.load_formats(ids).await, database.load_series(ids).await) {
+            (Ok(formats), Ok(series)) => (formats, series),
+            (Err(e), _) | (_, Err(e)) => {
+                app.status_message = Some(format!("❌ Failed to load formats: {}", e));
+                return;
+            }
+        };
+
+        let plan = jobs::plan_export(&app.config.export.template, directory, &app.library_path, &books, &formats, &series);
+        if plan.items.is_empty() {
+            app.status_message = Some("❌ Nothing to export: the books have no files".to_string());
+            return;
+        }
+
+        let items = plan
+            .items
+            .iter()
+            .map(|item| {
+                let note = if item.renamed { "  (name taken, numbered)" } else { "" };
+                format!("{}{}", item.name.display(), note)
+            })
+            .chain(plan.skipped.iter().map(|failure| format!("✗ {} ({})", failure.title, failure.error)))
+            .collect();
+        let title = format!("Export {} files to {}? Enter runs, Esc cancels", plan.items.len(), directory.display());
+        app.menu = Some(Menu::new(MenuKind::ExportPreview(plan), title, items));
+    }
+
+    /// Copy the previewed files out of the library
+    fn export_books(&mut self, app: &mut App, plan: ExportPlan) {
+        let kind = JobKind::Export { directory: plan.directory.clone() };
+        if let Some((tx, cancel)) = self.start_job(app, kind, "Exporting", plan.items.len()) {
+            tokio::spawn(jobs::export_books(plan, tx, cancel));
+            app.selected_ids.clear();
+        }
+    }
+
+    /// Pack the marked books (or the current one) with their metadata into a zip or tar
+    async fn archive_books(&mut self, app: &mut App, database: &Database, destination: &str) {
+        let destination = expand_home(destination);
+        let Some(format) = jobs::ArchiveFormat::from_path(&destination) else {
+            app.status_message = Some("❌ Archive name must end in .zip or .tar".to_string());
+            return;
+        };
+        if destination.exists() {
+            app.status_message = Some(format!("❌ {} already exists", destination.display()));
+            return;
+        }
+
+        // The archive's metadata lists authors and tags
+        let ids: Vec<i32> = app.selection_or_current().iter().map(|b| b.id).collect();
+        self.load_details(app, database, &ids).await;
+        let books = app.selection_or_current();
+        let (formats, series) = match (database.load_formats(&ids).await, database.load_series(&ids).await) {
+            (Ok(formats), Ok(series)) => (formats, series),
+            (Err(e), _) | (_, Err(e)) => {
+                app.status_message = Some(format!("❌ Failed to load formats: {}", e));
+                return;
+            }
+        };
+
+        let plan = jobs::plan_archive(&destination, format, &app.library_path, &books, &formats, &series);
+        let kind = JobKind::Archive { destination: destination.clone() };
+        if let Some((tx, cancel)) = self.start_job(app, kind, "Archiving", plan.books.len()) {
+            tokio::spawn(jobs::archive_books(plan, tx, cancel));
+            app.selected_ids.clear();
+        }
+    }
+
+    /// Ask whether the listed books should be copied or moved into the new library
+    fn open_split_menu(&mut self, app: &mut App, target: &str) {
+        let target = expand_home(target);
+        if target.join("metadata.db").exists() {
+            app.status_message = Some(format!("❌ {} already has a calibre library", target.display()));
+            return;
+        }
+        if target == app.library_path {
+            app.status_message = Some("❌ Choose a folder other than this library".to_string());
+            return;
+        }
+
+        let count = app.books.len();
+        let items = vec![
+            format!("Copy {} books", count),
+            format!("Move {} books (remove them from this library)", count),
+        ];
+        let title = format!("New library at {}", target.display());
+        app.menu = Some(Menu::new(MenuKind::SplitMode(target), title, items));
+    }
+
+    /// Carve the books the search and filters list into a new library
+    fn split_library(&mut self, app: &mut App, database: &Database, target: PathBuf, mode: jobs::SplitMode) {
+        if mode == jobs::SplitMode::Move && database.is_read_only() {
+            app.status_message = Some("🔒 Read-only: books can be copied out but not moved".to_string());
+            return;
+        }
+
+        let books = app.books.clone();
+        let kind = JobKind::Split { target: target.clone() };
+        if let Some((tx, cancel)) = self.start_job(app, kind, "Splitting library", books.len()) {
+            tokio::spawn(jobs::split_library(database.clone(), app.library_path.clone(), books, target, mode, tx, cancel));
+        }
+    }
+
+    /// Offer the other libraries in history to merge into this one
+    fn open_merge_menu(&mut self, app: &mut App, database: &Database) {
+        if database.is_read_only() {
+            app.status_message = Some("🔒 Read-only: can't merge books into this library".to_string());
+            return;
+        }
+
+        let history = LibraryHistory::load().unwrap_or_default();
+        let paths: Vec<PathBuf> = history
+            .get_libraries()
+            .iter()
+            .map(|entry| entry.path.clone())
+            .filter(|path| *path != app.library_path && path.join("metadata.db").exists())
+            .collect();
+        if paths.is_empty() {
+            app.status_message = Some("No other libraries in history to merge".to_string());
+            return;
+        }
+
+        let items = paths.iter().map(|path| path.display().to_string()).collect();
+        app.menu = Some(Menu::new(MenuKind::MergeSource(paths), "Merge into this library", items));
+    }
+
+    /// Show which books of the other library would be added, get new formats, or be skipped
+    async fn preview_merge(&mut self, app: &mut App, database: &Database, source_path: PathBuf) {
+        let planned = async {
+            let source = Database::open_read_only(&source_path, &app.config.database).await?;
+            let plan = jobs::plan_merge(&source, source_path.clone(), database).await;
+            source.close().await;
+            plan
+        };
+        let plan = match planned.await {
+            Ok(plan) => plan,
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to read {}: {}", source_path.display(), e));
+                return;
+            }
+        };
+        if plan.books.is_empty() {
+            app.status_message = Some(format!("{} has no books", source_path.display()));
+            return;
+        }
+
+        let (added, merged, skipped) = plan.counts();
+        let items = plan
+            .books
+            .iter()
+            .map(|(book, action)| match action {
+                MergeAction::Add => format!("+ {} - {}", book.title, book.author_list()),
+                MergeAction::AddFormats { formats, .. } => format!("⇄ {} (adds {})", book.title, formats.join(", ")),
+                MergeAction::Skip { .. } => format!("= {} (already here)", book.title),
+            })
+            .collect();
+        let title = format!(
+            "{} new, {} with new formats, {} duplicates | Enter merges, Esc cancels",
+            added, merged, skipped
+        );
+        app.menu = Some(Menu::new(MenuKind::MergePreview(source_path), title, items));
+    }
+
+    /// Import the other library's books in the background
+    fn merge_library(&mut self, app: &mut App, database: &Database, source: PathBuf) {
+        let label = format!("Merging {}", source.file_name().unwrap_or_default().to_string_lossy());
+        if let Some((tx, cancel)) = self.start_job(app, JobKind::Merge { source: source.clone() }, &label, 0) {
+            tokio::spawn(jobs::merge_library(database.clone(), app.library_path.clone(), source, tx, cancel));
+        }
+    }
+
+    async fn handle_menu_key(&mut self, key: KeyEvent, app: &mut App, database: &Database) {
+        let Some(menu) = app.menu.as_mut() else {
+            return;
+        };
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => menu.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => menu.select_next(),
+            KeyCode::Esc | KeyCode::Left | KeyCode::Char('q') => app.menu = None,
+            KeyCode::Char('d') | KeyCode::Delete if matches!(menu.kind, MenuKind::SavedSearch(_)) => {
+                let Some(menu) = app.menu.take() else {
+                    return;
+                };
+                if let MenuKind::SavedSearch(searches) = menu.kind {
+                    if let Some(search) = searches.into_iter().nth(menu.selected) {
+                        self.delete_saved_search(app, database, &search).await;
+                    }
+                }
+            }
+            KeyCode::Char('d') | KeyCode::Delete if matches!(menu.kind, MenuKind::QueuedActions) => {
+                let selected = menu.selected;
+                app.menu = None;
+                if selected < app.queued.len() {
+                    let action = app.queued.remove(selected);
+                    app.status_message = Some(match self.save_queue(app) {
+                        Ok(()) => format!("🗑 Dropped: {}", action.describe()),
+                        Err(e) => format!("❌ Failed to save the queued actions: {}", e),
+                    });
+                }
+                if !app.queued.is_empty() {
+                    self.open_queue_menu(app, database);
+                    if let Some(menu) = app.menu.as_mut() {
+                        menu.selected = selected.min(menu.items.len() - 1);
+                    }
+                }
+            }
+            KeyCode::Char('!') if matches!(menu.kind, MenuKind::FilterValue(_)) => {
+                // Add the filter negated, e.g. "not tagged fantasy"
+                let Some(menu) = app.menu.take() else {
+                    return;
+                };
+                if let MenuKind::FilterValue(filters) = menu.kind {
+                    if let Some(filter) = filters.into_iter().nth(menu.selected) {
+                        let clause = FilterClause { filter, negated: true };
+                        self.add_filter(app, database, clause).await;
+                    }
+                }
+            }
+            KeyCode::Enter | KeyCode::Right => {
+                let Some(menu) = app.menu.take() else {
+                    return;
+                };
+                match menu.kind {
+                    MenuKind::EmailProfile => {
+                        let name = app.config.email.profiles.keys().nth(menu.selected).cloned();
+                        if let Some(name) = name {
+                            self.email_books(app, database, &name).await;
+                        }
+                    }
+                    MenuKind::FilterKind => {
+                        if let Some(kind) = menu.items.get(menu.selected) {
+                            self.open_filter_values(app, database, kind).await;
+                        }
+                    }
+                    MenuKind::FilterValue(filters) => {
+                        if let Some(filter) = filters.into_iter().nth(menu.selected) {
+                            self.add_filter(app, database, FilterClause::new(filter)).await;
+                        }
+                    }
+                    MenuKind::BookActions(actions) => {
+                        if let Some(action) = actions.get(menu.selected).copied() {
+                            self.perform(action, None, app, database).await;
+                        }
+                    }
+                    MenuKind::ConvertTo(formats) => {
+                        if let Some(format) = formats.get(menu.selected) {
+                            self.convert_book(app, database, format);
+                        }
+                    }
+                    MenuKind::OpenFormat(formats) => {
+                        if let (Some(format), Some(book)) = (formats.get(menu.selected), app.get_selected_book().cloned()) {
+                            if self.open_book_file(&book, Some(format), &app.library_path).await {
+                                self.record_open(app, database, book.id).await;
+                            }
+                        }
+                    }
+                    MenuKind::WriteConflict(edits) => self.resolve_conflict(edits, menu.selected, app, database).await,
+                    MenuKind::QueuedActions => self.apply_queued_action(app, database, menu.selected).await,
+                    MenuKind::Chapters => {
+                        if let Some(reader) = app.reader.as_mut() {
+                            reader.go_to_chapter(menu.selected);
+                        }
+                    }
+                    MenuKind::ExportPreview(plan) => self.export_books(app, plan),
+                    MenuKind::MergeSource(paths) => {
+                        if let Some(path) = paths.into_iter().nth(menu.selected) {
+                            self.preview_merge(app, database, path).await;
+                        }
+                    }
+                    MenuKind::MergePreview(source) => self.merge_library(app, database, source),
+                    MenuKind::CalibreWebUser(path, users) => {
+                        if let Some(user) = users.get(menu.selected) {
+                            self.import_calibre_web(app, database, &path, user).await;
+                        }
+                    }
+                    MenuKind::SplitMode(target) => {
+                        let mode = if menu.selected == 0 { jobs::SplitMode::Copy } else { jobs::SplitMode::Move };
+                        self.split_library(app, database, target, mode);
+                    }
+                    MenuKind::SavedSearch(searches) => {
+                        if let Some(search) = searches.into_iter().nth(menu.selected) {
+                            self.run_saved_search(app, database, search.expression).await;
+                        }
+                    }
+                    MenuKind::VirtualLibrary => {
+                        // Item 0 is "(whole library)"
+                        let name = menu.items.get(menu.selected).filter(|_| menu.selected > 0).cloned();
+                        self.set_virtual_library(app, database, name.as_deref()).await;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Let the user pick which configured recipient to email the books to
+    fn open_email_menu(&mut self, app: &mut App) {
+        let profiles = &app.config.email.profiles;
+        if profiles.is_empty() {
+            app.status_message = Some("❌ No email profiles configured ([email.profiles.<name>] in config.toml)".to_string());
+            return;
+        }
+
+        let items = profiles
+            .iter()
+            .map(|(name, profile)| format!("{} <{}> [{}]", name, profile.to, profile.formats.join(", ")))
+            .collect();
+        app.menu = Some(Menu::new(MenuKind::EmailProfile, "Email to", items));
+    }
+
+    /// Email the marked books (or the current one) to a configured recipient
+    ///
+    /// While the library is offline the email is queued instead.
+    async fn email_books(&mut self, app: &mut App, database: &Database, profile_name: &str) {
+        let books = app.selection_or_current();
+        let ids: Vec<i32> = books.iter().map(|b| b.id).collect();
+
+        if database.snapshot().is_some() {
+            let titles = books.iter().map(|b| b.title.clone()).collect();
+            let kind = QueuedKind::Email { profile: profile_name.to_string(), book_ids: ids, titles };
+            self.queue_actions(app, vec![QueuedAction::new(kind)]);
+        } else if let Err(message) = self.email_book_ids(app, database, profile_name, &ids).await {
+            app.status_message = Some(format!("❌ {}", message));
+            return;
+        }
+        app.selected_ids.clear();
+    }
+
+    /// Start emailing books with a configured profile, or say why it can't start
+    async fn email_book_ids(&mut self, app: &mut App, database: &Database, profile_name: &str, ids: &[i32]) -> std::result::Result<(), String> {
+        let Some(profile) = app.config.email.profiles.get(profile_name).cloned() else {
+            return Err(format!("No email profile named {}", profile_name));
+        };
+        if app.job.is_some() {
+            return Err("Another job is still running".to_string());
+        }
+
+        let formats = database.load_formats(ids).await.map_err(|e| format!("Failed to load formats: {}", e))?;
+        let books: Vec<&Book> = app.all_books.iter().filter(|b| ids.contains(&b.id)).collect();
+        let plan = jobs::plan_email(&profile, &app.library_path, &books, &formats);
+        let label = format!("Emailing {}", profile_name);
+        let config = app.config.email.clone();
+        let kind = JobKind::Email { profile: profile_name.to_string() };
+        if let Some((tx, cancel)) = self.start_job(app, kind, &label, plan.items.len()) {
+            tokio::spawn(jobs::send_emails(config, profile, plan, tx, cancel));
+        }
+        Ok(())
+    }
+
+    /// Show the configured OPDS catalogs
+    fn open_opds_view(&mut self, app: &mut App) {
+        let catalogs = &app.config.opds.catalogs;
+        if catalogs.is_empty() {
+            app.status_message = Some("❌ No OPDS catalogs configured ([opds.catalogs] in config.toml)".to_string());
+            return;
+        }
+
+        app.opds_view = Some(OpdsView::new(OpdsFeed::catalogs(catalogs.iter())));
+        app.mode = AppMode::Opds;
+    }
+
+    async fn handle_opds_mode(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> bool {
+        let Some(view) = app.opds_view.as_mut() else {
+            app.mode = AppMode::Normal;
+            return true;
+        };
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                if let Some(page) = view.current_mut() {
+                    page.selected = page.selected.saturating_sub(1);
+                }
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                if let Some(page) = view.current_mut() {
+                    page.selected = (page.selected + 1).min(page.feed.entries.len().saturating_sub(1));
+                }
+            }
+            KeyCode::Esc | KeyCode::Left => {
+                // Go back one feed, leaving the browser from the catalog list
+                if view.pages.len() > 1 {
+                    view.pages.pop();
+                } else {
+                    app.opds_view = None;
+                    app.mode = AppMode::Normal;
+                }
+            }
+            KeyCode::Char('n') => {
+                let next = view.current().and_then(|page| page.feed.next.clone());
+                match next {
+                    Some(url) => self.open_opds_feed(app, &url, true).await,
+                    None => app.status_message = Some("No more pages".to_string()),
+                }
+            }
+            KeyCode::Enter | KeyCode::Right => {
+                let Some(entry) = view.selected_entry().cloned() else {
+                    return true;
+                };
+
+                if entry.is_navigation() {
+                    if let Some(url) = &entry.navigation {
+                        self.open_opds_feed(app, url, false).await;
+                    }
+                } else if entry.best_acquisition().is_some() {
+                    let label = "Downloading from OPDS".to_string();
+                    if let Some((tx, cancel)) = self.start_job(app, JobKind::Download, &label, 1) {
+                        tokio::spawn(jobs::download_entries(database.clone(), app.library_path.clone(), vec![entry], tx, cancel));
+                    }
+                } else {
+                    app.status_message = Some(format!("❌ No supported format for: {}", entry.title));
+                }
+            }
+            KeyCode::Char('q') => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// Fetch a feed and show it, either as a new level or replacing the current page
+    async fn open_opds_feed(&mut self, app: &mut App, url: &str, replace: bool) {
+        match opds::fetch_feed(url).await {
+            Ok(feed) => {
+                if let Some(view) = app.opds_view.as_mut() {
+                    if replace && view.pages.len() > 1 {
+                        view.pages.pop();
+                    }
+                    view.pages.push(OpdsPage { feed, selected: 0 });
+                }
+            }
+            Err(e) => {
+                app.status_message = Some(format!("❌ {}", e));
+            }
+        }
+    }
+
+    /// Scan the connected e-reader and switch to the device pane
+    async fn open_device_view(&mut self, app: &mut App, database: &Database) {
+        let Some(device) = device::detect_devices(&app.config.devices).into_iter().next() else {
+            app.status_message = Some("❌ No configured or known e-reader detected".to_string());
+            return;
+        };
+
+        let formats = match database.load_all_formats().await {
+            Ok(formats) => formats,
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to load formats: {}", e));
+                return;
+            }
+        };
+
+        let books = device.list_books(&formats);
+        app.on_device = books.iter().filter_map(|b| b.book_id).collect();
+        let selected = app.scroll_state(ScrollView::Device).selected.min(books.len().saturating_sub(1));
+        app.device_view = Some(DeviceView {
+            device,
+            books,
+            selected,
+            pending_delete: false,
+        });
+        app.mode = AppMode::Device;
+    }
+
+    /// Show the background jobs run on this library, newest first
+    fn open_jobs_view(&mut self, app: &mut App) {
+        let records = match JobHistory::load() {
+            Ok(history) => history.for_library(&app.library_path),
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to load the job history: {}", e));
+                return;
+            }
+        };
+        if records.is_empty() {
+            app.status_message = Some("No background jobs run on this library yet".to_string());
+            return;
+        }
+        app.jobs_view = Some(JobsView { records, selected: 0, session: self.session });
+        app.mode = AppMode::Jobs;
+    }
+
+    /// Add up the library's files by `grouping` and list the groups, biggest first
+    async fn open_usage_view(&mut self, app: &mut App, database: &Database, grouping: UsageGrouping) {
+        let loaded = async { Ok::<_, Error>((database.load_usage(grouping).await?, database.total_file_size().await?)) };
+        match loaded.await {
+            Ok((groups, _)) if groups.is_empty() => {
+                app.status_message = Some("No files in this library".to_string());
+            }
+            Ok((groups, total)) => {
+                app.usage_view = Some(UsageView::new(grouping, groups, total));
+                app.mode = AppMode::Usage;
+            }
+            Err(e) => app.status_message = Some(format!("❌ Failed to add up file sizes: {}", e)),
+        }
+    }
+
+    /// Keys of the disk usage view: move, drill into a group, mark books to export or delete
+    async fn handle_usage_mode(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> bool {
+        let Some(view) = app.usage_view.as_mut() else {
+            app.mode = AppMode::Normal;
+            return true;
+        };
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => view.move_by(-1),
+            KeyCode::Down | KeyCode::Char('j') => view.move_by(1),
+            KeyCode::PageUp => view.move_by(-10),
+            KeyCode::PageDown => view.move_by(10),
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                if view.open.is_none() {
+                    view.drill_in();
+                } else if let Some(id) = view.selected_book().map(|book| book.id) {
+                    // Show the book in the list
+                    match app.books.iter().position(|b| b.id == id) {
+                        Some(index) => {
+                            app.selected_book_index = index;
+                            app.mode = AppMode::Normal;
+                        }
+                        None => app.status_message = Some("That book isn't in the list; clear the search and filters to see it".to_string()),
+                    }
+                }
+            }
+            KeyCode::Esc | KeyCode::Left | KeyCode::Char('h') => {
+                if view.open.is_some() {
+                    view.drill_out();
+                } else {
+                    app.mode = AppMode::Normal;
+                }
+            }
+            KeyCode::Tab => {
+                let grouping = view.grouping.next();
+                self.open_usage_view(app, database, grouping).await;
+            }
+            KeyCode::Char(' ') | KeyCode::Char('m') => {
+                // Marks the book, or the whole group; unmarks them if they all were
+                let ids = view.ids_at_cursor();
+                if ids.iter().all(|id| app.selected_ids.contains(id)) {
+                    for id in &ids {
+                        app.selected_ids.remove(id);
+                    }
+                } else {
+                    app.selected_ids.extend(ids);
+                }
+                app.status_message = Some(format!("{} marked", app.selected_ids.len()));
+            }
+            KeyCode::Char('b') => {
+                let biggest = view.biggest(BIGGEST_MARKED);
+                let bytes: u64 = biggest.iter().map(|(_, bytes)| bytes).sum();
+                app.selected_ids.extend(biggest.iter().map(|(id, _)| *id));
+                app.status_message = Some(format!(
+                    "✔ Marked the {} biggest books, {} in all; back in the list, E exports and Z archives them",
+                    biggest.len(),
+                    format_size(bytes)
+                ));
+            }
+            KeyCode::Char('q') => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// Suggest the largest files, books never opened and extra formats to prune
+    async fn open_cleanup_view(&mut self, app: &mut App, database: &Database) {
+        let files = match database.load_library_files().await {
+            Ok(files) if files.is_empty() => {
+                app.status_message = Some("No files in this library".to_string());
+                return;
+            }
+            Ok(files) => files,
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to list the library's files: {}", e));
+                return;
+            }
+        };
+
+        // Opened, read or being read in tuilibre; calibre doesn't record opens
+        let opened: HashSet<i32> = LibrarySettingsStore::load()
+            .ok()
+            .and_then(|store| {
+                store.get(&app.library_path).map(|settings| {
+                    settings.opens.keys().chain(settings.reading.keys()).chain(settings.positions.keys()).copied().collect()
+                })
+            })
+            .unwrap_or_default();
+        app.cleanup_view = Some(CleanupView::suggest(&files, &opened, &app.config.cleanup, Utc::now()));
+        app.mode = AppMode::Cleanup;
+    }
+
+    /// Keys of the cleanup assistant: switch lists, mark suggestions and prune the marked ones
+    async fn handle_cleanup_mode(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> bool {
+        let Some(view) = app.cleanup_view.as_mut() else {
+            app.mode = AppMode::Normal;
+            return true;
+        };
+
+        // Pruning only proceeds on an explicit 'y'
+        if view.pending_prune {
+            view.pending_prune = false;
+            if key.code == KeyCode::Char('y') {
+                self.prune_marked(app, database).await;
+            }
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => view.move_by(-1),
+            KeyCode::Down | KeyCode::Char('j') => view.move_by(1),
+            KeyCode::PageUp => view.move_by(-10),
+            KeyCode::PageDown => view.move_by(10),
+            KeyCode::Tab => view.section = view.section.next(),
+            KeyCode::Char(' ') | KeyCode::Char('m') => {
+                view.toggle_mark();
+                view.move_by(1);
+            }
+            KeyCode::Char('a') => view.toggle_all(),
+            KeyCode::Char('d') => {
+                let marked = view.marked();
+                if marked.is_empty() {
+                    app.status_message = Some("Mark suggestions with Space first, or all of them with a".to_string());
+                } else if database.is_read_only() {
+                    app.status_message = Some("🔒 Read-only: can't remove files from this library".to_string());
+                } else {
+                    let bytes: u64 = marked.iter().map(|item| item.bytes).sum();
+                    let books = marked.iter().filter(|item| item.format.is_none()).count();
+                    let files = marked.len() - books;
+                    let what = match (books, files) {
+                        (0, files) => format!("{} files", files),
+                        (books, 0) => format!("{} books", books),
+                        (books, files) => format!("{} books and {} files", books, files),
+                    };
+                    view.pending_prune = true;
+                    app.status_message = Some(format!("Delete {} from the library, freeing {}? (y/N)", what, format_size(bytes)));
+                }
+            }
+            KeyCode::Enter => {
+                if let Some(id) = view.selected_item().map(|item| item.book_id) {
+                    // Show the book in the list
+                    match app.books.iter().position(|b| b.id == id) {
+                        Some(index) => {
+                            app.selected_book_index = index;
+                            app.mode = AppMode::Normal;
+                        }
+                        None => app.status_message = Some("That book isn't in the list; clear the search and filters to see it".to_string()),
+                    }
+                }
+            }
+            KeyCode::Esc | KeyCode::Left => app.mode = AppMode::Normal,
+            KeyCode::Char('q') => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// Delete the marked books and formats, then drop them from every list of the assistant
+    async fn prune_marked(&mut self, app: &mut App, database: &Database) {
+        let Some(marked) = app.cleanup_view.as_ref().map(|view| view.marked()) else {
+            return;
+        };
+
+        let mut pruned = Vec::new();
+        let mut failures = Vec::new();
+        for item in marked {
+            let result = match &item.format {
+                Some(format) => database.remove_format(&app.library_path, item.book_id, format).await,
+                None => database.delete_book(&app.library_path, item.book_id, true).await,
+            };
+            match result {
+                Ok(()) => pruned.push(item),
+                Err(e) => failures.push(format!("{}: {}", item.label(), e)),
+            }
+        }
+
+        for item in pruned.iter().filter(|item| item.format.is_none()) {
+            app.selected_ids.remove(&item.book_id);
+        }
+        let freed: u64 = pruned.iter().map(|item| item.bytes).sum();
+        if let Some(view) = app.cleanup_view.as_mut() {
+            view.remove(&pruned);
+        }
+        app.status_message = Some(match failures.first() {
+            None => format!("🗑 Pruned {} items, freeing {}", pruned.len(), format_size(freed)),
+            Some(first) => format!("❌ Pruned {} items, {} failed: {}", pruned.len(), failures.len(), first),
+        });
+        let changed: Vec<i32> = pruned.iter().filter(|item| item.format.is_some()).map(|item| item.book_id).collect();
+        self.reload_books(app, database, &changed).await;
+    }
+
+    /// Check the library's files against `[format_policy]` and list the books breaking it
+    async fn open_policy_audit(&mut self, app: &mut App, database: &Database) {
+        if app.config.format_policy.is_empty() {
+            app.status_message = Some("No format policy; set required or redundant formats under [format_policy] in the config".to_string());
+            return;
+        }
+        let files = match database.load_library_files().await {
+            Ok(files) => files,
+            Err(e) => {
+                app.status_message = Some(format!("❌ Failed to list the library's files: {}", e));
+                return;
+            }
+        };
+        let audit = PolicyAudit::run(&files, &app.config.format_policy);
+        if audit.violations.is_empty() {
+            app.status_message = Some("✔ Every book follows the format policy".to_string());
+            return;
+        }
+        app.policy_audit = Some(audit);
+        app.mode = AppMode::FormatPolicy;
+    }
+
+    /// Keys of the format policy audit: mark violations, delete redundant files, convert missing formats
+    async fn handle_policy_mode(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> bool {
+        let Some(audit) = app.policy_audit.as_mut() else {
+            app.mode = AppMode::Normal;
+            return true;
+        };
+
+        // Deleting only proceeds on an explicit 'y'
+        if audit.pending_delete {
+            audit.pending_delete = false;
+            if key.code == KeyCode::Char('y') {
+                self.delete_redundant_formats(app, database).await;
+            }
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => audit.move_by(-1),
+            KeyCode::Down | KeyCode::Char('j') => audit.move_by(1),
+            KeyCode::PageUp => audit.move_by(-10),
+            KeyCode::PageDown => audit.move_by(10),
+            KeyCode::Char(' ') | KeyCode::Char('m') => {
+                audit.toggle_mark();
+                audit.move_by(1);
+            }
+            KeyCode::Char('a') => audit.toggle_all(),
+            KeyCode::Char('d') => {
+                let redundant = audit.marked_redundant();
+                if redundant.is_empty() {
+                    app.status_message = Some("Mark redundant formats with Space first, or everything with a".to_string());
+                } else if database.is_read_only() {
+                    app.status_message = Some("🔒 Read-only: can't remove files from this library".to_string());
+                } else {
+                    let bytes: u64 = redundant.iter().map(|(_, _, bytes)| bytes).sum();
+                    audit.pending_delete = true;
+                    app.status_message = Some(format!(
+                        "Delete {} redundant files from the library, freeing {}? (y/N)",
+                        redundant.len(),
+                        format_size(bytes)
+                    ));
+                }
+            }
+            KeyCode::Char('c') => {
+                // One format per job, as a job converts to a single format
+                let missing = audit.marked_missing();
+                let Some((format, ids)) = missing.iter().next() else {
+                    app.status_message = Some("Mark books missing a format with Space first, or everything with a".to_string());
+                    return true;
+                };
+                let queued: HashSet<i32> = ids.iter().copied().collect();
+                self.convert_book_ids(app, database, ids, format);
+                if app.job.is_some() {
+                    if let Some(audit) = app.policy_audit.as_mut() {
+                        audit.remove(|v| matches!(&v.breach, PolicyBreach::Missing { format: f } if f == format) && queued.contains(&v.book_id));
+                    }
+                    if missing.len() > 1 {
+                        app.status_message = Some(format!("Converting to {}; press c again for the other formats once it's done", format));
+                    }
+                }
+            }
+            KeyCode::Char('r') => self.open_policy_audit(app, database).await,
+            KeyCode::Enter => {
+                if let Some(id) = audit.selected_violation().map(|v| v.book_id) {
+                    // Show the book in the list
+                    match app.books.iter().position(|b| b.id == id) {
+                        Some(index) => {
+                            app.selected_book_index = index;
+                            app.mode = AppMode::Normal;
+                        }
+                        None => app.status_message = Some("That book isn't in the list; clear the search and filters to see it".to_string()),
+                    }
+                }
+            }
+            KeyCode::Esc | KeyCode::Left => app.mode = AppMode::Normal,
+            KeyCode::Char('q') => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// Compare the books' files with the library in the background, listing the ones that
+    /// differ as they are found
+    async fn audit_book_ids(&mut self, app: &mut App, database: &Database, ids: Vec<i32>) {
+        // Files are compared by author too
+        self.load_details(app, database, &ids).await;
+        let wanted: HashSet<i32> = ids.iter().copied().collect();
+        let books: Vec<&Book> = app.all_books.iter().filter(|b| wanted.contains(&b.id)).collect();
+        let items = jobs::plan_embed(&app.library_path, &books);
+        if items.is_empty() {
+            app.status_message = Some("Nothing to audit: the books have no EPUB or other files metadata can be read from".to_string());
+            return;
+        }
+        let total = items.len();
+        let ebook_meta = app.config.metadata.ebook_meta.clone();
+        if let Some((tx, cancel)) = self.start_job(app, JobKind::AuditMetadata, "Auditing file metadata", total) {
+            tokio::spawn(jobs::audit_metadata(items, ebook_meta, tx, cancel));
+            app.metadata_audit = Some(MetadataAudit::new(ids));
+            app.mode = AppMode::MetadataAudit;
+        }
+    }
+
+    /// Keys of the metadata audit: mark files, push the library's metadata into them or pull
+    /// theirs into the library
+    async fn handle_metadata_audit_mode(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> bool {
+        let Some(audit) = app.metadata_audit.as_mut() else {
+            app.mode = AppMode::Normal;
+            return true;
+        };
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => audit.move_by(-1),
+            KeyCode::Down | KeyCode::Char('j') => audit.move_by(1),
+            KeyCode::PageUp => audit.move_by(-10),
+            KeyCode::PageDown => audit.move_by(10),
+            KeyCode::Char(' ') | KeyCode::Char('m') => {
+                audit.toggle_mark();
+                audit.move_by(1);
+            }
+            KeyCode::Char('a') => audit.toggle_all(),
+            KeyCode::Char('p') | KeyCode::Char('l') if database.is_read_only() => {
+                app.status_message = Some("🔒 Read-only: can't change this library or its files".to_string());
+            }
+            KeyCode::Char('p') => {
+                if app.job.is_some() {
+                    app.status_message = Some("⏳ Wait for the running job to finish before pushing".to_string());
+                    return true;
+                }
+                let items: Vec<EmbedItem> = audit.take_chosen().into_iter().map(|mismatch| mismatch.item).collect();
+                if items.is_empty() {
+                    return true;
+                }
+                let total = items.len();
+                let ebook_meta = app.config.metadata.ebook_meta.clone();
+                if let Some((tx, cancel)) = self.start_job(app, JobKind::EmbedMetadata, "Updating file metadata", total) {
+                    tokio::spawn(jobs::embed_metadata(database.clone(), items, ebook_meta, tx, cancel));
+                }
+            }
+            KeyCode::Char('l') => self.pull_file_metadata(app, database).await,
+            KeyCode::Char('r') => {
+                let ids = audit.book_ids.clone();
+                self.audit_book_ids(app, database, ids).await;
+            }
+            KeyCode::Enter => {
+                if let Some(id) = audit.selected_mismatch().map(|m| m.item.book_id) {
+                    // Show the book in the list
+                    match app.books.iter().position(|b| b.id == id) {
+                        Some(index) => {
+                            app.selected_book_index = index;
+                            app.mode = AppMode::Normal;
+                        }
+                        None => app.status_message = Some("That book isn't in the list; clear the search and filters to see it".to_string()),
+                    }
+                }
+            }
+            KeyCode::Esc | KeyCode::Left => app.mode = AppMode::Normal,
+            KeyCode::Char('q') => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// Write what the marked files (or the one under the cursor) say into the library, field by
+    /// field where they differ
+    async fn pull_file_metadata(&mut self, app: &mut App, database: &Database) {
+        let Some(chosen) = app.metadata_audit.as_mut().map(MetadataAudit::take_chosen) else {
+            return;
+        };
+
+        let mut pulled = Vec::new();
+        let mut failures = Vec::new();
+        for mismatch in &chosen {
+            let (book_id, file) = (mismatch.item.book_id, &mismatch.file);
+            for field in &mismatch.fields {
+                let result = match field {
+                    AuditField::Title if file.title.is_empty() => Err(Error::Other("the file has no title".to_string())),
+                    AuditField::Title => database.set_title(book_id, &file.title).await,
+                    AuditField::Authors => database.set_authors(book_id, &file.authors).await,
+                    AuditField::Series => database.set_series(book_id, file.series.as_ref().map(|(s, i)| (s.as_str(), *i))).await,
+                };
+                if let Err(e) = result {
+                    failures.push(format!("{} ({}): {}", mismatch.item.title, field.label().to_lowercase(), e));
+                }
+            }
+            pulled.push(book_id);
+        }
+        if chosen.is_empty() {
+            return;
+        }
+
+        app.status_message = Some(match failures.first() {
+            None => format!("📥 Pulled the metadata of {} files into the library", chosen.len()),
+            Some(first) => format!("❌ Pulled {} files, {} fields failed: {}", chosen.len(), failures.len(), first),
+        });
+        self.reload_books(app, database, &pulled).await;
+    }
+
+    /// Delete the marked redundant files and drop them from the audit
+    async fn delete_redundant_formats(&mut self, app: &mut App, database: &Database) {
+        let Some(redundant) = app.policy_audit.as_ref().map(|audit| audit.marked_redundant()) else {
+            return;
+        };
+
+        let mut removed = HashSet::new();
+        let mut failures = Vec::new();
+        let mut freed = 0;
+        for (book_id, format, bytes) in redundant {
+            match database.remove_format(&app.library_path, book_id, &format).await {
+                Ok(()) => {
+                    freed += bytes;
+                    removed.insert((book_id, format));
+                }
+                Err(e) => failures.push(e.to_string()),
+            }
+        }
+
+        if let Some(audit) = app.policy_audit.as_mut() {
+            audit.remove(|v| match &v.breach {
+                PolicyBreach::Redundant { format, .. } => removed.contains(&(v.book_id, format.clone())),
+                PolicyBreach::Missing { .. } => false,
+            });
+        }
+        app.status_message = Some(match failures.first() {
+            None => format!("🗑 Deleted {} redundant files, freeing {}", removed.len(), format_size(freed)),
+            Some(first) => format!("❌ Deleted {} redundant files, {} failed: {}", removed.len(), failures.len(), first),
+        });
+        let changed: Vec<i32> = removed.iter().map(|(id, _)| *id).collect();
+        self.reload_books(app, database, &changed).await;
+    }
+
+    async fn handle_jobs_mode(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> bool {
+        let Some(view) = app.jobs_view.as_mut() else {
+            app.mode = AppMode::Normal;
+            return true;
+        };
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                view.selected = view.selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                view.selected = (view.selected + 1).min(view.records.len().saturating_sub(1));
+            }
+            KeyCode::Char('r') => {
+                if let Some(record) = view.get_selected().cloned() {
+                    self.retry_job(app, database, &record).await;
+                }
+            }
+            KeyCode::Char('y') => {
+                if let Some(record) = view.get_selected() {
+                    app.status_message = Some(match clipboard::copy_to_clipboard(&record.details()) {
+                        Ok(()) => format!("📋 Copied the details of {}", record.label),
+                        Err(e) => format!("❌ Failed to copy details: {}", e),
+                    });
+                }
+            }
+            KeyCode::Esc | KeyCode::Left => {
+                app.mode = AppMode::Normal;
+            }
+            KeyCode::Char('q') => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// Run a job again for just the books it failed on, with the same parameters
+    async fn retry_job(&mut self, app: &mut App, database: &Database, record: &JobRecord) {
+        if !record.kind.retries_books() {
+            app.status_message = Some(format!("❌ {} jobs can't be retried book by book", record.kind.name()));
+            return;
+        }
+        // Books deleted since can't be retried
+        let ids: Vec<i32> = record
+            .failed_book_ids()
+            .into_iter()
+            .filter(|id| app.all_books.iter().any(|b| b.id == *id))
+            .collect();
+        if ids.is_empty() {
+            app.status_message = Some("Nothing to retry: no failed books left in the library".to_string());
+            return;
+        }
+        if app.job.is_some() {
+            app.status_message = Some("⏳ Another job is still running".to_string());
+            return;
+        }
+
+        let started = match &record.kind {
+            JobKind::Convert { format } => {
+                self.convert_book_ids(app, database, &ids, format);
+                Ok(())
+            }
+            JobKind::Send { .. } => self.send_books(app, database, &ids).await,
+            JobKind::Verify => {
+                self.verify_book_ids(app, &ids);
+                Ok(())
+            }
+            JobKind::EmbedMetadata => {
+                self.embed_book_ids(app, database, &ids).await;
+                Ok(())
+            }
+            JobKind::Email { profile } => self.email_book_ids(app, database, profile, &ids).await,
+            JobKind::Export { directory } => {
+                self.preview_export_ids(app, database, &ids, directory).await;
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(message) = started {
+            app.status_message = Some(format!("❌ {}", message));
+        }
+    }
+
+    fn handle_device_mode(&mut self, key: KeyEvent, app: &mut App) -> bool {
+        let Some(view) = app.device_view.as_mut() else {
+            app.mode = AppMode::Normal;
+            return true;
+        };
+
+        // A pending delete only proceeds on an explicit 'y'
+        if view.pending_delete {
+            view.pending_delete = false;
+            if key.code == KeyCode::Char('y') {
+                if let Some(book) = view.get_selected_book().cloned() {
+                    match std::fs::remove_file(&book.path) {
+                        Ok(()) => {
+                            view.books.remove(view.selected);
+                            view.selected = view.selected.min(view.books.len().saturating_sub(1));
+                            if let Some(id) = book.book_id {
+                                app.on_device.remove(&id);
+                            }
+                            app.status_message = Some(format!("🗑 Deleted from device: {}", book.title));
+                        }
+                        Err(e) => {
+                            app.status_message = Some(format!("❌ Failed to delete {}: {}", book.title, e));
+                        }
+                    }
+                }
+            }
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                view.selected = view.selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                view.selected = (view.selected + 1).min(view.books.len().saturating_sub(1));
+            }
+            KeyCode::Char('d') => {
+                if let Some(title) = view.get_selected_book().map(|b| b.title.clone()) {
+                    view.pending_delete = true;
+                    app.status_message = Some(format!("Delete \"{}\" from device? (y/N)", title));
+                }
+            }
+            KeyCode::Esc | KeyCode::Left => {
+                app.mode = AppMode::Normal;
+            }
+            KeyCode::Char('q') => return false,
+            _ => {}
+        }
+        true
+    }
+
+    /// Look up missing metadata for the whole library in the background
+    fn enrich_metadata(&mut self, app: &mut App, database: &Database) {
+        let sources = app.config.metadata.sources.clone();
+        if sources.is_empty() {
+            app.status_message = Some("❌ No metadata sources configured ([metadata] sources in config.toml)".to_string());
             return;
         }
 
-        // Construct the full path to the book file
+        if let Some((tx, cancel)) = self.start_job(app, JobKind::LookUpMetadata, "Looking up metadata", 0) {
+            tokio::spawn(jobs::enrich_library(database.clone(), sources, tx, cancel));
+        }
+    }
+
+    async fn handle_review_mode(&mut self, key: KeyEvent, app: &mut App, database: &Database) -> bool {
+        let batch_size = app.config.metadata.batch_size;
+
+        let accepted = match key.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                app.review.select_previous();
+                Vec::new()
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                app.review.select_next(batch_size);
+                Vec::new()
+            }
+            KeyCode::Tab | KeyCode::Char('l') => {
+                app.review.cycle_field(true);
+                Vec::new()
+            }
+            KeyCode::BackTab | KeyCode::Char('h') => {
+                app.review.cycle_field(false);
+                Vec::new()
+            }
+            KeyCode::Char(' ') => {
+                app.review.toggle_field();
+                Vec::new()
+            }
+            KeyCode::Char('a') | KeyCode::Enter => app.review.take_selected().into_iter().collect(),
+            KeyCode::Char('A') => app.review.take_batch(batch_size),
+            KeyCode::Char('x') => {
+                app.review.take_selected();
+                Vec::new()
+            }
+            KeyCode::Char('X') => {
+                app.review.take_batch(batch_size);
+                Vec::new()
+            }
+            KeyCode::Esc | KeyCode::Left => {
+                app.mode = AppMode::Normal;
+                return true;
+            }
+            KeyCode::Char('q') => return false,
+            _ => Vec::new(),
+        };
+
+        if !accepted.is_empty() {
+            self.apply_proposals(app, database, accepted).await;
+        }
+        if app.review.proposals.is_empty() {
+            app.mode = AppMode::Normal;
+        }
+        true
+    }
+
+    /// Write the accepted fields right away, since the lookup job may still be running
+    async fn apply_proposals(&mut self, app: &mut App, database: &Database, proposals: Vec<MetadataProposal>) {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        jobs::apply_proposals(database.clone(), app.library_path.clone(), proposals, tx).await;
+
+        while let Ok(update) = rx.try_recv() {
+            match update {
+                JobUpdate::LibraryChanged(ids) => {
+                    self.reload_books(app, database, &ids).await;
+                }
+                JobUpdate::Finished { message } => app.status_message = Some(message),
+                _ => {}
+            }
+        }
+    }
+
+    /// Open one of the book's files, the first without `format`, using the system default
+    /// application; true if it was started
+    async fn open_book_file(&self, book: &Book, format: Option<&BookFormat>, library_path: &Path) -> bool {
+        use std::process::Command;
+
         // calibre structure: library_path/book_folder/filename.format
-        let book_filename = format!("{}.{}", book.filename, book.format.to_lowercase());
-        let book_path = library_path.join(&book.path).join(&book_filename);
+        let book_path = match format {
+            Some(format) => Some(book.format_path(library_path, format)),
+            None => book.file_path(library_path),
+        };
+        let Some(book_path) = book_path else {
+            eprintln!("❌ No file information available for book: {}", book.title);
+            return false;
+        };
 
         if !book_path.exists() {
             eprintln!("❌ Book file not found: {}", book_path.display());
-            return;
+            return false;
         }
 
         let result = if cfg!(target_os = "linux") {
@@ -552,21 +4346,28 @@ impl UI {
                 .spawn()
         } else if cfg!(target_os = "windows") {
             Command::new("cmd")
-                .arg(&format!("/c start \"\" \"{}\"", book_path.display()))
+                .arg(format!("/c start \"\" \"{}\"", book_path.display()))
                 .spawn()
         } else {
             eprintln!("❌ Unsupported operating system for opening files");
-            return;
+            return false;
         };
 
         match result {
-            Ok(_) => {
-                // Book opened successfully - silent operation
-            }
+            Ok(_) => true, // Book opened successfully - silent operation
             Err(e) => {
                 eprintln!("❌ Failed to open book file: {}", e);
                 eprintln!("💡 File path: {}", book_path.display());
+                false
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// A path typed by the user, with a leading `~/` meaning the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
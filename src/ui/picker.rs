@@ -0,0 +1,136 @@
+//! `tuilibre pick`: a bare fuzzy-search list for choosing one book from a shell pipeline
+//!
+//! It draws on stderr, so stdout stays free for the caller to capture what was picked, as in
+//! `$EDITOR "$(tuilibre pick)"`.
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{List, ListItem, ListState, Paragraph},
+    Terminal,
+};
+use std::io;
+
+use super::theme::Theme;
+use crate::app::Book;
+use crate::error::{Error, Result};
+use crate::utils::fuzzy::fuzzy_match;
+
+/// A book that matches what was typed, best first
+struct Candidate {
+    index: usize,
+    score: i64,
+    positions: Vec<usize>,
+}
+
+/// Let the user pick one of `books` by typing part of its title or authors
+///
+/// Returns the index of the book picked, None when Esc or Ctrl-C gave up.
+pub fn pick_book(books: &[Book], initial_query: &str, theme: &Theme) -> Result<Option<usize>> {
+    let lines: Vec<String> = books.iter().map(|b| format!("{} — {}", b.title, b.author_list())).collect();
+    let mut query = initial_query.to_string();
+    let mut candidates = rank(&lines, &query);
+    let mut selected = 0;
+
+    enable_raw_mode().map_err(Error::TerminalError)?;
+    let mut stderr = io::stderr();
+    execute!(stderr, EnterAlternateScreen).map_err(Error::TerminalError)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stderr)).map_err(Error::TerminalError)?;
+
+    let picked = loop {
+        let drawn = terminal.draw(|frame| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Min(0)])
+                .split(frame.size());
+
+            let prompt = Line::from(vec![
+                Span::styled("> ", Style::default().fg(theme.accent)),
+                Span::raw(query.as_str()),
+                Span::styled(format!("  {}/{}", candidates.len(), books.len()), Style::default().fg(theme.dim)),
+            ]);
+            frame.render_widget(Paragraph::new(prompt), chunks[0]);
+
+            let items: Vec<ListItem> = candidates
+                .iter()
+                .map(|c| ListItem::new(highlighted(&lines[c.index], &c.positions, theme)))
+                .collect();
+            let list = List::new(items).highlight_style(theme.selected()).highlight_symbol("▶ ");
+            let mut state = ListState::default();
+            state.select((!candidates.is_empty()).then_some(selected));
+            frame.render_stateful_widget(list, chunks[1], &mut state);
+        });
+        if let Err(e) = drawn {
+            break Err(Error::TerminalError(e));
+        }
+
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => key,
+            Ok(_) => continue,
+            Err(e) => break Err(Error::TerminalError(e)),
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => break Ok(None),
+            KeyCode::Char('c') if ctrl => break Ok(None),
+            KeyCode::Enter => break Ok(candidates.get(selected).map(|c| c.index)),
+            KeyCode::Up => selected = selected.saturating_sub(1),
+            KeyCode::Char('k' | 'p') if ctrl => selected = selected.saturating_sub(1),
+            KeyCode::Down => selected = (selected + 1).min(candidates.len().saturating_sub(1)),
+            KeyCode::Char('j' | 'n') if ctrl => selected = (selected + 1).min(candidates.len().saturating_sub(1)),
+            KeyCode::Backspace => {
+                query.pop();
+                candidates = rank(&lines, &query);
+                selected = 0;
+            }
+            KeyCode::Char(c) if !ctrl => {
+                query.push(c);
+                candidates = rank(&lines, &query);
+                selected = 0;
+            }
+            _ => {}
+        }
+    };
+
+    disable_raw_mode().map_err(Error::TerminalError)?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen).map_err(Error::TerminalError)?;
+    terminal.show_cursor().map_err(Error::TerminalError)?;
+    picked
+}
+
+/// The lines matching `query`, best first; ties keep the list's order
+fn rank(lines: &[String], query: &str) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            fuzzy_match(query, line).map(|m| Candidate { index, score: m.score, positions: m.positions })
+        })
+        .collect();
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.score));
+    candidates
+}
+
+/// A line with the matched characters in the accent colour
+fn highlighted(line: &str, positions: &[usize], theme: &Theme) -> Line<'static> {
+    let matched = Style::default().fg(theme.accent).add_modifier(Modifier::BOLD);
+    Line::from(
+        line.chars()
+            .enumerate()
+            .map(|(i, c)| {
+                if positions.contains(&i) {
+                    Span::styled(c.to_string(), matched)
+                } else {
+                    Span::raw(c.to_string())
+                }
+            })
+            .collect::<Vec<_>>(),
+    )
+}
@@ -1,14 +1,38 @@
-use anyhow::Result;
-use std::path::{Path, PathBuf};
-use sqlx::SqlitePool;
-use crate::history::LibraryHistory;
+use crate::error::Result;
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{Connection, SqliteConnection};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use crate::config::Config;
+use crate::database::snapshot_taken;
+use crate::history::{LibraryEntry, LibraryHistory};
+use crate::jobs::CancelToken;
+use crate::utils::fuzzy::fuzzy_match;
+use crate::ui::layout::Density;
+use crate::ui::theme::Theme;
+use crate::utils::locale::Locale;
+use crate::utils::mounts::is_mounted;
+use crate::utils::time::{format_time, TimeFormat};
+
+/// Added to name matches so they rank above matches found only in the path
+const NAME_MATCH_BONUS: i64 = 1000;
 
 /// Library selection functionality
 pub struct LibrarySelector {
     known_libraries: Vec<LibraryInfo>,
     history: LibraryHistory,
     search_query: String,
-    filtered_libraries: Vec<LibraryInfo>,
+    filtered_libraries: Vec<LibraryMatch>,
+    time_format: TimeFormat,
+    locale: Locale,
+    accessible: bool,
+    theme: Theme,
+    density: Density,
+    /// Show absolute last-used times even when the configured format is relative
+    absolute_times: bool,
+    /// Shown in the status bar until the next key
+    notice: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -17,11 +41,57 @@ pub struct LibraryInfo {
     pub name: String,
     pub book_count: Option<i32>,
     pub from_history: bool,
-    pub last_used: Option<String>, // Formatted last used time
+    pub last_used: Option<DateTime<Utc>>,
+    pub section: LibrarySection,
+    /// Where history last saw a library found elsewhere; selecting it relinks the entry
+    pub moved_from: Option<PathBuf>,
+    /// Mount point of the absent share or drive the library is on; it is shown greyed out
+    pub offline_mount: Option<PathBuf>,
+    /// When the snapshot an offline library opens from was taken, if it has one
+    pub snapshot: Option<DateTime<Utc>>,
+}
+
+/// Group a library is listed under in the selector, in display order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LibrarySection {
+    Pinned,
+    Recent,
+    Moved,
+    Discovered,
+}
+
+impl LibrarySection {
+    pub fn title(&self) -> &'static str {
+        match self {
+            LibrarySection::Pinned => "📌 置顶",
+            LibrarySection::Recent => "🕘 最近使用",
+            LibrarySection::Moved => "⚠️ 已移动 (Enter 更新路径并打开)",
+            LibrarySection::Discovered => "🔍 发现",
+        }
+    }
+}
+
+/// A library shown in the selector, with the characters the search matched
+#[derive(Debug, Clone)]
+pub struct LibraryMatch {
+    pub library: LibraryInfo,
+    pub score: i64,
+    /// Matched character positions in `library.name`
+    pub name_positions: Vec<usize>,
+    /// Matched character positions in the displayed path
+    pub path_positions: Vec<usize>,
+}
+
+impl Default for LibrarySelector {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl LibrarySelector {
     pub fn new() -> Self {
+        let config = Config::load().unwrap_or_default();
+        let display = config.display;
         LibrarySelector {
             known_libraries: Vec::new(),
             history: LibraryHistory::load().unwrap_or_else(|e| {
@@ -30,11 +100,21 @@ impl LibrarySelector {
             }),
             search_query: String::new(),
             filtered_libraries: Vec::new(),
+            theme: Theme::from_config(&display),
+            density: display.density,
+            locale: display.locale(),
+            time_format: display.time_format,
+            accessible: config.accessibility.enabled,
+            absolute_times: false,
+            notice: None,
         }
     }
 
     /// Discover calibre libraries on the system
-    pub async fn discover_libraries(&mut self) -> Result<()> {
+    ///
+    /// Once `cancel` is cancelled the scan stops before the next directory and lists the
+    /// libraries found so far, history's among them.
+    pub async fn discover_libraries(&mut self, cancel: &CancelToken) -> Result<()> {
         self.known_libraries.clear();
 
         // First, add libraries from history (with recently used first)
@@ -44,14 +124,27 @@ impl LibrarySelector {
         let search_paths = self.get_common_search_paths();
 
         for search_path in search_paths {
+            if cancel.is_cancelled() {
+                break;
+            }
             if search_path.exists() {
-                self.search_directory(&search_path).await?;
+                self.search_directory(&search_path, cancel).await?;
             }
         }
 
+        // Libraries that are gone from where history saw them may have been moved
+        self.find_moved_libraries(cancel).await;
+        if cancel.is_cancelled() {
+            self.notice = Some("⏹ 已停止查找，仅列出已找到的图书馆".to_string());
+        }
+
         // Update filtered libraries with current search query
         self.update_filtered_libraries();
 
+        // Pinned libraries come first
+        self.known_libraries.sort_by_key(|lib| lib.section);
+        self.update_filtered_libraries();
+
         Ok(())
     }
 
@@ -97,33 +190,78 @@ impl LibrarySelector {
         let mut existing_paths = std::collections::HashSet::new();
 
         for entry in self.history.get_libraries() {
-            if entry.path.exists() {
-                let db_path = entry.path.join("metadata.db");
-                if db_path.exists() {
-                    let library_info = LibraryInfo {
-                        path: entry.path.clone(),
-                        name: entry.name.clone()
-                            .unwrap_or_else(|| {
-                                entry.path.file_name()
-                                    .and_then(|n| n.to_str())
-                                    .unwrap_or("Unknown")
-                                    .to_string()
-                            }),
-                        book_count: entry.book_count,
-                        from_history: true,
-                        last_used: Some(
-                            entry.last_used.format("%Y-%m-%d %H:%M").to_string()
-                        ),
-                    };
-                    self.known_libraries.push(library_info);
-                    existing_paths.insert(entry.path.clone());
+            let available = entry.path.join("metadata.db").exists();
+            // Libraries on a share or drive that isn't mounted are listed, but can't be opened yet
+            let offline_mount = if available { None } else { entry.absent_mount().map(Path::to_path_buf) };
+            if available || offline_mount.is_some() {
+                let library_info = LibraryInfo {
+                    path: entry.path.clone(),
+                    name: entry.display_name(),
+                    book_count: entry.book_count,
+                    from_history: true,
+                    last_used: Some(entry.last_used),
+                    section: if entry.pinned { LibrarySection::Pinned } else { LibrarySection::Recent },
+                    moved_from: None,
+                    snapshot: offline_mount.as_ref().and_then(|_| snapshot_taken(&entry.path)),
+                    offline_mount,
+                };
+                self.known_libraries.push(library_info);
+                existing_paths.insert(entry.path.clone());
+            }
+        }
+    }
+
+    /// Look for the libraries in history that are no longer where they were
+    ///
+    /// A library found elsewhere must have the uuid recorded for it, or, for entries recorded
+    /// before uuids were kept, the same directory name.
+    async fn find_moved_libraries(&mut self, cancel: &CancelToken) {
+        let recorded: HashSet<PathBuf> = self.history.get_libraries().iter().map(|e| e.path.clone()).collect();
+        let missing: Vec<LibraryEntry> = self.history.missing_libraries().cloned().collect();
+
+        for entry in missing {
+            for candidate in candidate_paths(&entry.path) {
+                if cancel.is_cancelled() {
+                    return;
+                }
+                if !candidate.join("metadata.db").is_file() {
+                    continue;
                 }
+                let candidate = candidate.canonicalize().unwrap_or(candidate);
+                if recorded.contains(&candidate) {
+                    continue;
+                }
+                let same_library = match &entry.library_id {
+                    Some(id) => read_library_id(&candidate).await.as_ref() == Some(id),
+                    None => candidate.file_name() == entry.path.file_name(),
+                };
+                if !same_library {
+                    continue;
+                }
+
+                // It was probably also discovered, under the wrong section, or listed as offline
+                self.known_libraries.retain(|lib| {
+                    lib.path != entry.path && lib.path.canonicalize().unwrap_or_else(|_| lib.path.clone()) != candidate
+                });
+                let book_count = self.get_book_count(&candidate).await.ok();
+                self.known_libraries.push(LibraryInfo {
+                    path: candidate,
+                    name: entry.display_name(),
+                    book_count,
+                    from_history: true,
+                    last_used: Some(entry.last_used),
+                    section: LibrarySection::Moved,
+                    moved_from: Some(entry.path.clone()),
+                    offline_mount: None,
+                    snapshot: None,
+                });
+                break;
             }
         }
     }
 
     /// Search a directory for calibre libraries
-    async fn search_directory(&mut self, base_path: &Path) -> Result<()> {
+    async fn search_directory(&mut self, base_path: &Path, cancel: &CancelToken) -> Result<()> {
         // Get paths already in history to avoid duplicates
         let history_paths: std::collections::HashSet<_> = self.known_libraries
             .iter()
@@ -133,6 +271,9 @@ impl LibrarySelector {
 
         if let Ok(entries) = std::fs::read_dir(base_path) {
             for entry in entries.flatten() {
+                if cancel.is_cancelled() {
+                    break;
+                }
                 let path = entry.path();
                 if path.is_dir() {
                     // Skip if already in history
@@ -153,6 +294,10 @@ impl LibrarySelector {
                             book_count,
                             from_history: false,
                             last_used: None,
+                            section: LibrarySection::Discovered,
+                            moved_from: None,
+                            offline_mount: None,
+                            snapshot: None,
                         };
                         self.known_libraries.push(library_info);
                     }
@@ -209,43 +354,258 @@ impl LibrarySelector {
         self.update_filtered_libraries();
     }
 
+    /// Whether to render for screen readers
+    pub fn is_accessible(&self) -> bool {
+        self.accessible
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn density(&self) -> Density {
+        self.density
+    }
+
+    /// Switch last-used times between the configured format and absolute times
+    pub fn toggle_absolute_times(&mut self) {
+        self.absolute_times = !self.absolute_times;
+    }
+
+    /// When a library was last opened, as configured for display
+    pub fn format_last_used(&self, library: &LibraryInfo) -> Option<String> {
+        library.last_used.map(|time| format_time(time, &self.time_format, self.absolute_times, &self.locale))
+    }
+
+    /// When an offline library's snapshot was taken, as configured for display
+    pub fn format_snapshot(&self, library: &LibraryInfo) -> Option<String> {
+        library.snapshot.map(|time| format_time(time, &self.time_format, self.absolute_times, &self.locale))
+    }
+
+    /// Number of books in a library, grouped as the locale writes numbers
+    pub fn format_book_count(&self, library: &LibraryInfo) -> String {
+        self.locale.format_number(library.book_count.unwrap_or(0).max(0) as usize)
+    }
+
     /// Get current search query
     pub fn get_search_query(&self) -> &str {
         &self.search_query
     }
 
     /// Update filtered libraries based on search query
+    ///
+    /// Libraries are fuzzy matched on their name, falling back to their path, and ranked
+    /// best match first. Name matches outrank path matches; ties keep discovery order.
     fn update_filtered_libraries(&mut self) {
-        if self.search_query.is_empty() {
-            // If no search query, show all libraries
-            self.filtered_libraries = self.known_libraries.clone();
-        } else {
-            // Filter libraries based on search query
-            let search_term = self.search_query.to_lowercase();
-            self.filtered_libraries = self.known_libraries
-                .iter()
-                .filter(|lib| {
-                    let name_matches = lib.name.to_lowercase().contains(&search_term);
-                    let path_matches = lib.path.display().to_string().to_lowercase().contains(&search_term);
-                    name_matches || path_matches
+        let query = &self.search_query;
+        self.filtered_libraries = self.known_libraries
+            .iter()
+            .filter_map(|lib| {
+                if let Some(m) = fuzzy_match(query, &lib.name) {
+                    return Some(LibraryMatch {
+                        library: lib.clone(),
+                        score: m.score + NAME_MATCH_BONUS,
+                        name_positions: m.positions,
+                        path_positions: Vec::new(),
+                    });
+                }
+                fuzzy_match(query, &lib.path.display().to_string()).map(|m| LibraryMatch {
+                    library: lib.clone(),
+                    score: m.score,
+                    name_positions: Vec::new(),
+                    path_positions: m.positions,
                 })
-                .cloned()
-                .collect();
+            })
+            .collect();
+
+        if !query.is_empty() {
+            self.filtered_libraries.sort_by_key(|m| std::cmp::Reverse(m.score));
+        }
+        // Stable, so libraries stay ranked within their section
+        self.filtered_libraries.sort_by_key(|m| m.library.section);
+    }
+
+    /// Pin or unpin a library shown in the list, saving the change to history
+    ///
+    /// Returns the library's new index in the list, which moves to its new section.
+    pub fn toggle_pinned(&mut self, index: usize) -> Result<usize> {
+        let Some(path) = self.get_filtered_library(index).map(|lib| lib.path.clone()) else {
+            return Ok(index);
+        };
+
+        let pinned = self.history.toggle_pinned(&path);
+        self.history.save()?;
+
+        let from_history = self.known_libraries.iter().find(|lib| lib.path == path).map(|lib| lib.from_history);
+        let section = match (pinned, from_history) {
+            (true, _) => LibrarySection::Pinned,
+            (false, Some(true)) => LibrarySection::Recent,
+            (false, _) => LibrarySection::Discovered,
+        };
+        for lib in self.known_libraries.iter_mut().filter(|lib| lib.path == path) {
+            lib.section = section;
         }
+        self.update_filtered_libraries();
+        Ok(self.filtered_libraries.iter().position(|m| m.library.path == path).unwrap_or(index))
+    }
+
+    /// Point the history entry of a moved library shown in the list at where it was found
+    pub fn relink(&mut self, index: usize) -> Result<()> {
+        let Some(lib) = self.get_filtered_library(index) else {
+            return Ok(());
+        };
+        let (Some(old_path), new_path) = (lib.moved_from.clone(), lib.path.clone()) else {
+            return Ok(());
+        };
+
+        self.history.relink(&old_path, &new_path);
+        self.history.save()?;
+
+        let pinned = self.history.get_libraries().iter().any(|e| e.path == new_path && e.pinned);
+        for lib in self.known_libraries.iter_mut().filter(|lib| lib.path == new_path) {
+            lib.moved_from = None;
+            lib.section = if pinned { LibrarySection::Pinned } else { LibrarySection::Recent };
+        }
+        self.update_filtered_libraries();
+        Ok(())
+    }
+
+    /// Check again whether an offline library shown in the list can be opened, noting why not
+    pub fn retry(&mut self, index: usize) -> bool {
+        let Some(lib) = self.get_filtered_library(index) else {
+            return false;
+        };
+        let (path, Some(mount)) = (lib.path.clone(), lib.offline_mount.clone()) else {
+            return true;
+        };
+
+        if !path.join("metadata.db").is_file() {
+            self.notice = Some(if is_mounted(&mount) {
+                format!("⚠️ {} 已挂载，但图书馆不在 {}", mount.display(), path.display())
+            } else {
+                format!("💤 {} 未挂载，挂载后按 Enter 重试", mount.display())
+            });
+            return false;
+        }
+        for lib in self.known_libraries.iter_mut().filter(|lib| lib.path == path) {
+            lib.offline_mount = None;
+        }
+        self.update_filtered_libraries();
+        true
+    }
+
+    /// Message for the status bar, in place of the key help
+    pub fn notice(&self) -> Option<&str> {
+        self.notice.as_deref()
+    }
+
+    pub fn clear_notice(&mut self) {
+        self.notice = None;
+    }
+
+    /// The alias of a library shown in the list, if it has one
+    pub fn alias(&self, index: usize) -> Option<&str> {
+        self.get_filtered_library(index).and_then(|lib| self.history.alias(&lib.path))
+    }
+
+    /// Give a library shown in the list an alias, or drop it with an empty one, saving the
+    /// change to history
+    pub fn rename(&mut self, index: usize, alias: &str) -> Result<()> {
+        let Some(path) = self.get_filtered_library(index).map(|lib| lib.path.clone()) else {
+            return Ok(());
+        };
+
+        self.history.set_alias(&path, Some(alias.to_string()));
+        self.history.save()?;
+
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        let name = self
+            .history
+            .get_libraries()
+            .iter()
+            .find(|e| e.path == canonical)
+            .map(|e| e.display_name())
+            .unwrap_or_default();
+        for lib in self.known_libraries.iter_mut().filter(|lib| lib.path == path) {
+            lib.name = name.clone();
+        }
+        self.update_filtered_libraries();
+        Ok(())
     }
 
     /// Get the filtered libraries (for display)
-    pub fn get_filtered_libraries(&self) -> &[LibraryInfo] {
+    pub fn get_filtered_libraries(&self) -> &[LibraryMatch] {
         &self.filtered_libraries
     }
 
     /// Get a filtered library by index
     pub fn get_filtered_library(&self, index: usize) -> Option<&LibraryInfo> {
-        self.filtered_libraries.get(index)
+        self.filtered_libraries.get(index).map(|m| &m.library)
     }
 
     /// Check if any filtered libraries exist
     pub fn has_filtered_libraries(&self) -> bool {
         !self.filtered_libraries.is_empty()
     }
-}
\ No newline at end of file
+}
+/// Where a library no longer at `old_path` may have gone: near where it was, or on a mounted
+/// volume under the same trailing path
+fn candidate_paths(old_path: &Path) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    // Up to two levels below the closest directory that still exists
+    if let Some(ancestor) = old_path.ancestors().skip(1).find(|p| p.is_dir()) {
+        for child in subdirectories(ancestor) {
+            candidates.extend(subdirectories(&child));
+            candidates.push(child);
+        }
+    }
+
+    // /media/old-disk/Books/Calibre may now be /media/new-disk/Books/Calibre or /mnt/usb/Calibre
+    let components: Vec<_> = old_path.components().filter(|c| matches!(c, Component::Normal(_))).collect();
+    for volume in mounted_volumes() {
+        for start in 0..components.len() {
+            candidates.push(components[start..].iter().fold(volume.clone(), |path, c| path.join(c)));
+        }
+    }
+    candidates
+}
+
+/// Directories removable drives and network shares are usually mounted on
+fn mounted_volumes() -> Vec<PathBuf> {
+    let mut volumes = Vec::new();
+    if cfg!(target_os = "windows") {
+        for drive in 'C'..='Z' {
+            let drive_path = PathBuf::from(format!("{}:/", drive));
+            if drive_path.exists() {
+                volumes.push(drive_path);
+            }
+        }
+        return volumes;
+    }
+
+    let roots: &[&str] = if cfg!(target_os = "macos") { &["/Volumes"] } else { &["/media", "/run/media", "/mnt"] };
+    for root in roots {
+        for dir in subdirectories(Path::new(root)) {
+            // Desktop Linux mounts volumes in a directory per user
+            volumes.extend(subdirectories(&dir));
+            volumes.push(dir);
+        }
+    }
+    volumes
+}
+
+fn subdirectories(path: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|e| e.path()).filter(|p| p.is_dir()).collect())
+        .unwrap_or_default()
+}
+
+/// The uuid calibre gave a library, read without taking its lock
+async fn read_library_id(path: &Path) -> Option<String> {
+    let options = SqliteConnectOptions::new().filename(path.join("metadata.db")).read_only(true);
+    let mut conn = SqliteConnection::connect_with(&options).await.ok()?;
+    let uuid = sqlx::query_scalar("SELECT uuid FROM library_id LIMIT 1").fetch_optional(&mut conn).await.ok().flatten();
+    let _ = conn.close().await;
+    uuid
+}
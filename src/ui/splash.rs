@@ -0,0 +1,148 @@
+//! Loading screen shown while a library opens, so a big one doesn't look frozen
+
+use ratatui::{
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Gauge, Paragraph},
+};
+use std::path::Path;
+
+use super::theme::Theme;
+use super::{restore_terminal, setup_terminal, Tui};
+use crate::config::Config;
+use crate::error::{Error, Result};
+
+/// Steps of opening a library, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LoadStage {
+    Connecting,
+    Counting,
+    LoadingBooks,
+    BuildingIndex,
+}
+
+impl LoadStage {
+    pub const ALL: [LoadStage; 4] =
+        [LoadStage::Connecting, LoadStage::Counting, LoadStage::LoadingBooks, LoadStage::BuildingIndex];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LoadStage::Connecting => "Connecting to the library",
+            LoadStage::Counting => "Counting books",
+            LoadStage::LoadingBooks => "Loading books",
+            LoadStage::BuildingIndex => "Building the search index",
+        }
+    }
+}
+
+/// The loading screen, drawn on its own terminal until dropped
+pub struct Splash {
+    terminal: Tui,
+    alternate_screen: bool,
+    theme: Theme,
+    library: String,
+    stage: LoadStage,
+    /// Done and total of the current stage, when it can tell
+    progress: Option<(usize, usize)>,
+}
+
+impl Splash {
+    pub fn start(library_path: &Path, config: &Config) -> Result<Self> {
+        let alternate_screen = config.accessibility.alternate_screen;
+        let mut splash = Splash {
+            terminal: setup_terminal(alternate_screen)?,
+            alternate_screen,
+            theme: Theme::from_config(&config.display),
+            library: library_path.display().to_string(),
+            stage: LoadStage::Connecting,
+            progress: None,
+        };
+        splash.draw()?;
+        Ok(splash)
+    }
+
+    /// Move on to `stage`
+    pub fn stage(&mut self, stage: LoadStage) -> Result<()> {
+        self.stage = stage;
+        self.progress = None;
+        self.draw()
+    }
+
+    /// How far the current stage got
+    pub fn progress(&mut self, done: usize, total: usize) -> Result<()> {
+        self.progress = Some((done, total));
+        self.draw()
+    }
+
+    fn draw(&mut self) -> Result<()> {
+        let Splash { terminal, theme, library, stage, progress, .. } = self;
+        terminal
+            .draw(|frame| {
+                let area = centered(frame.size(), 56, LoadStage::ALL.len() as u16 + 6);
+                let block = Block::default()
+                    .borders(Borders::ALL)
+                    .title(" tuilibre ")
+                    .border_style(Style::default().fg(theme.accent));
+                let inner = block.inner(area);
+                frame.render_widget(Clear, area);
+                frame.render_widget(block, area);
+
+                let rows = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([
+                        Constraint::Length(2),                             // Library path
+                        Constraint::Length(LoadStage::ALL.len() as u16),   // Stages
+                        Constraint::Length(1),
+                        Constraint::Length(1),                             // Progress
+                    ])
+                    .split(inner);
+
+                let path = Paragraph::new(library.as_str()).style(Style::default().fg(theme.dim)).alignment(Alignment::Center);
+                frame.render_widget(path, rows[0]);
+
+                let lines: Vec<Line> = LoadStage::ALL
+                    .iter()
+                    .map(|&s| {
+                        if s < *stage {
+                            Line::from(vec![Span::styled("✓ ", Style::default().fg(theme.good)), Span::raw(s.label())])
+                        } else if s == *stage {
+                            Line::from(Span::styled(format!("› {}…", s.label()), Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)))
+                        } else {
+                            Line::from(Span::styled(format!("  {}", s.label()), Style::default().fg(theme.dim)))
+                        }
+                    })
+                    .collect();
+                frame.render_widget(Paragraph::new(lines), rows[1]);
+
+                if let Some((done, total)) = *progress {
+                    let ratio = if total == 0 { 1.0 } else { (done as f64 / total as f64).min(1.0) };
+                    let gauge = Gauge::default()
+                        .gauge_style(Style::default().fg(theme.accent))
+                        .ratio(ratio)
+                        .label(format!("{} / {}", done, total));
+                    frame.render_widget(gauge, rows[3]);
+                }
+            })
+            .map_err(Error::TerminalError)?;
+        Ok(())
+    }
+}
+
+impl Drop for Splash {
+    fn drop(&mut self) {
+        let _ = restore_terminal(&mut self.terminal, self.alternate_screen);
+    }
+}
+
+/// A `width` by `height` box in the middle of `area`, shrunk to fit
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
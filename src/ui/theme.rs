@@ -0,0 +1,252 @@
+//! Colors used across the UI, chosen by name in the `[display]` config, with any of them
+//! replaced in `[display.palette]`
+
+use ratatui::style::{Color, Modifier, Style};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use super::color::ColorSupport;
+use crate::config::DisplayConfig;
+
+/// A built-in color scheme
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    /// For dark terminal backgrounds
+    #[default]
+    #[serde(alias = "dark")]
+    Default,
+    /// Darker foregrounds for light terminal backgrounds
+    Light,
+    /// Ethan Schoonover's Solarized accents, which read on its dark and light backgrounds alike
+    Solarized,
+    /// Bright foregrounds only, no dark grays; each meets WCAG AA (4.5:1) on black
+    HighContrast,
+    /// The Okabe-Ito palette: never tells things apart by red versus green alone
+    Colorblind,
+}
+
+impl ThemeName {
+    const ALL: [ThemeName; 5] =
+        [ThemeName::Default, ThemeName::Light, ThemeName::Solarized, ThemeName::HighContrast, ThemeName::Colorblind];
+
+    /// The theme after this one, wrapping around, for switching themes while tuilibre runs
+    pub fn next(self) -> Self {
+        let at = ThemeName::ALL.iter().position(|&name| name == self).unwrap_or(0);
+        ThemeName::ALL[(at + 1) % ThemeName::ALL.len()]
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ThemeName::Default => "dark",
+            ThemeName::Light => "light",
+            ThemeName::Solarized => "solarized",
+            ThemeName::HighContrast => "high contrast",
+            ThemeName::Colorblind => "colorblind",
+        }
+    }
+}
+
+/// A color as written in the config: a name like "light-red", "#268bd2" or a 256-color index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ThemeColor(pub Color);
+
+impl TryFrom<String> for ThemeColor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Color::from_str(&value)
+            .map(ThemeColor)
+            .map_err(|_| format!("unknown color \"{}\", expected a name like \"light-red\", \"#rrggbb\" or 0-255", value))
+    }
+}
+
+impl From<ThemeColor> for String {
+    fn from(color: ThemeColor) -> Self {
+        color.0.to_string()
+    }
+}
+
+/// Colors replacing the theme's, by role; roles left out keep the theme's color
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaletteOverrides {
+    pub accent: Option<ThemeColor>,
+    pub label: Option<ThemeColor>,
+    pub muted: Option<ThemeColor>,
+    pub dim: Option<ThemeColor>,
+    pub good: Option<ThemeColor>,
+    pub bad: Option<ThemeColor>,
+    pub focus: Option<ThemeColor>,
+    pub on_color: Option<ThemeColor>,
+    pub selection_fg: Option<ThemeColor>,
+    pub selection_bg: Option<ThemeColor>,
+}
+
+/// Colors for each role in the UI
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// Titles, section headers and available index letters
+    pub accent: Color,
+    /// Field names, table headers and job progress
+    pub label: Color,
+    /// Key help and secondary text such as authors
+    pub muted: Color,
+    /// Things that are unavailable or left out
+    pub dim: Color,
+    /// Matches, accepted changes and confirmations
+    pub good: Color,
+    /// Errors, no results and negated filters
+    pub bad: Color,
+    /// Border of the focused card in the grid
+    pub focus: Color,
+    /// Text on `dim` and `bad` backgrounds, as on filter chips
+    pub on_color: Color,
+    pub selection_fg: Color,
+    pub selection_bg: Color,
+    /// Show the selection with a marker character rather than a background color
+    pub selection_marker: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::new(ThemeName::Default, false)
+    }
+}
+
+impl Theme {
+    pub fn new(name: ThemeName, selection_marker: bool) -> Self {
+        match name {
+            ThemeName::Default => Theme {
+                accent: Color::Cyan,
+                label: Color::Yellow,
+                muted: Color::Gray,
+                dim: Color::DarkGray,
+                good: Color::Green,
+                bad: Color::Red,
+                focus: Color::Blue,
+                on_color: Color::White,
+                selection_fg: Color::White,
+                selection_bg: Color::Blue,
+                selection_marker,
+            },
+            ThemeName::Light => Theme {
+                accent: Color::Rgb(0, 95, 175),
+                label: Color::Rgb(135, 95, 0),
+                muted: Color::Rgb(78, 78, 78),
+                dim: Color::Rgb(138, 138, 138),
+                good: Color::Rgb(0, 135, 0),
+                bad: Color::Rgb(175, 0, 0),
+                focus: Color::Rgb(0, 95, 175),
+                on_color: Color::White,
+                selection_fg: Color::Black,
+                selection_bg: Color::Rgb(175, 215, 255),
+                selection_marker,
+            },
+            ThemeName::Solarized => Theme {
+                accent: Color::Rgb(42, 161, 152),        // cyan
+                label: Color::Rgb(181, 137, 0),          // yellow
+                muted: Color::Rgb(131, 148, 150),        // base0
+                dim: Color::Rgb(88, 110, 117),           // base01
+                good: Color::Rgb(133, 153, 0),           // green
+                bad: Color::Rgb(220, 50, 47),            // red
+                focus: Color::Rgb(38, 139, 210),         // blue
+                on_color: Color::Rgb(253, 246, 227),     // base3
+                selection_fg: Color::Rgb(253, 246, 227),
+                selection_bg: Color::Rgb(38, 139, 210),
+                selection_marker,
+            },
+            ThemeName::HighContrast => Theme {
+                accent: Color::LightCyan,
+                label: Color::LightYellow,
+                muted: Color::White,
+                dim: Color::Gray,
+                good: Color::LightGreen,
+                bad: Color::LightRed,
+                focus: Color::White,
+                on_color: Color::Black,
+                selection_fg: Color::Black,
+                selection_bg: Color::White,
+                selection_marker,
+            },
+            ThemeName::Colorblind => Theme {
+                accent: Color::Rgb(86, 180, 233),  // sky blue
+                label: Color::Rgb(240, 228, 66),   // yellow
+                muted: Color::Gray,
+                dim: Color::DarkGray,
+                good: Color::Rgb(0, 158, 115),     // bluish green
+                bad: Color::Rgb(230, 159, 0),      // orange
+                focus: Color::Rgb(86, 180, 233),
+                on_color: Color::White,
+                selection_fg: Color::Black,
+                selection_bg: Color::Rgb(86, 180, 233),
+                selection_marker,
+            },
+        }
+    }
+
+    /// The configured theme with its overrides, limited to the colors the terminal supports
+    pub fn from_config(display: &DisplayConfig) -> Self {
+        let support = display.colors.unwrap_or_else(ColorSupport::detect);
+        Theme::new(display.theme, display.selection_marker).overridden(&display.palette).degraded(support)
+    }
+
+    /// The theme with the colors `palette` sets in place of its own
+    pub fn overridden(self, palette: &PaletteOverrides) -> Self {
+        let pick = |own: Color, set: Option<ThemeColor>| set.map_or(own, |color| color.0);
+        Theme {
+            accent: pick(self.accent, palette.accent),
+            label: pick(self.label, palette.label),
+            muted: pick(self.muted, palette.muted),
+            dim: pick(self.dim, palette.dim),
+            good: pick(self.good, palette.good),
+            bad: pick(self.bad, palette.bad),
+            focus: pick(self.focus, palette.focus),
+            on_color: pick(self.on_color, palette.on_color),
+            selection_fg: pick(self.selection_fg, palette.selection_fg),
+            selection_bg: pick(self.selection_bg, palette.selection_bg),
+            selection_marker: self.selection_marker,
+        }
+    }
+
+    /// Every color replaced by the closest one `support` can show
+    pub fn degraded(self, support: ColorSupport) -> Self {
+        Theme {
+            accent: support.map(self.accent),
+            label: support.map(self.label),
+            muted: support.map(self.muted),
+            dim: support.map(self.dim),
+            good: support.map(self.good),
+            bad: support.map(self.bad),
+            focus: support.map(self.focus),
+            on_color: support.map(self.on_color),
+            selection_fg: support.map(self.selection_fg),
+            selection_bg: support.map(self.selection_bg),
+            selection_marker: self.selection_marker,
+        }
+    }
+
+    /// Style of the selected row: colored background, or just bold next to the marker
+    pub fn selected(&self) -> Style {
+        if self.selection_marker {
+            Style::default().add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().bg(self.selection_bg).fg(self.selection_fg)
+        }
+    }
+
+    /// Prefix for the selected row of lists and tables; empty when the background shows it
+    pub fn selection_symbol(&self) -> &'static str {
+        if self.selection_marker { "▶ " } else { "" }
+    }
+
+    /// `selection_symbol` for a row drawn by hand, or matching padding when not selected
+    pub fn marker(&self, selected: bool) -> &'static str {
+        match (self.selection_marker, selected) {
+            (false, _) => "",
+            (true, true) => "▶ ",
+            (true, false) => "  ",
+        }
+    }
+}
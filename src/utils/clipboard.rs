@@ -0,0 +1,14 @@
+//! Copying text through the terminal, which also works over SSH
+
+use base64::Engine;
+use std::io::{self, Write};
+
+/// Put `text` on the system clipboard with the OSC 52 escape sequence
+///
+/// The terminal does the copying, so nothing happens in terminals that don't support it.
+pub fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", encoded)?;
+    stdout.flush()
+}
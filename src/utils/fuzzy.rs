@@ -0,0 +1,85 @@
+//! Fuzzy matching for pickers: the pattern's characters in order, ranked by how tightly they match
+
+/// Where and how well a pattern matched
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// Higher is better; only comparable between matches of the same pattern
+    pub score: i64,
+    /// Character (not byte) indices of the matched characters, for highlighting
+    pub positions: Vec<usize>,
+}
+
+/// Match `pattern` against `text` case-insensitively, ignoring whitespace in the pattern
+///
+/// Matches at word starts and runs of consecutive characters score higher; gaps and long
+/// texts score lower. An empty pattern matches everything with a score of 0.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+    let pattern: Vec<char> = pattern
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(fold_case)
+        .collect();
+    if pattern.is_empty() {
+        return Some(FuzzyMatch::default());
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    let folded: Vec<char> = chars.iter().copied().map(fold_case).collect();
+
+    // The earliest position where the whole pattern has been seen...
+    let mut matched = 0;
+    let mut end = None;
+    for (i, c) in folded.iter().enumerate() {
+        if *c == pattern[matched] {
+            matched += 1;
+            if matched == pattern.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    // ...then walk back from there to find the tightest span ending at it
+    let mut positions = vec![0; pattern.len()];
+    let mut remaining = pattern.len();
+    for i in (0..=end).rev() {
+        if folded[i] == pattern[remaining - 1] {
+            remaining -= 1;
+            positions[remaining] = i;
+            if remaining == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut score = 0;
+    for (k, &i) in positions.iter().enumerate() {
+        score += 16;
+        if is_word_start(&chars, i) {
+            score += 8;
+        }
+        if k > 0 {
+            match i - positions[k - 1] - 1 {
+                0 => score += 4,
+                gap => score -= gap.min(8) as i64,
+            }
+        }
+    }
+    score -= chars.len() as i64 / 8;
+
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Lowercase a character, keeping one char per char so positions stay aligned
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Start of the text, after a separator, or a camelCase hump
+fn is_word_start(chars: &[char], i: usize) -> bool {
+    match i.checked_sub(1).map(|p| chars[p]) {
+        None => true,
+        Some(prev) => !prev.is_alphanumeric() || (prev.is_lowercase() && chars[i].is_uppercase()),
+    }
+}
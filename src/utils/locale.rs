@@ -0,0 +1,89 @@
+//! Locale-aware formatting of numbers and dates
+//!
+//! Only the conventions the UI needs are covered: the thousands separator and the order of
+//! day, month and year. Unknown locales fall back to ISO dates and comma grouping.
+
+use chrono::{DateTime, TimeZone};
+
+/// Number and date conventions of a locale
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale {
+    /// Inserted between groups of three digits
+    pub thousands_separator: &'static str,
+    /// strftime pattern for a date
+    pub date_pattern: &'static str,
+    /// strftime pattern for a time of day
+    pub time_pattern: &'static str,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Locale { thousands_separator: ",", date_pattern: "%Y-%m-%d", time_pattern: "%H:%M" }
+    }
+}
+
+impl Locale {
+    /// Locale for a tag such as "de", "de-DE" or "de_DE.UTF-8"
+    pub fn from_tag(tag: &str) -> Self {
+        let tag = tag.split(['.', '@']).next().unwrap_or_default().replace('-', "_");
+        let (language, region) = match tag.split_once('_') {
+            Some((language, region)) => (language.to_lowercase(), region.to_uppercase()),
+            None => (tag.to_lowercase(), String::new()),
+        };
+
+        let (thousands_separator, date_pattern, time_pattern) = match (language.as_str(), region.as_str()) {
+            ("en", "US") => (",", "%m/%d/%Y", "%I:%M %p"),
+            ("en", "GB" | "AU" | "NZ" | "IE") => (",", "%d/%m/%Y", "%H:%M"),
+            ("de" | "da" | "tr" | "id", _) => (".", "%d.%m.%Y", "%H:%M"),
+            ("nl", _) => (".", "%d-%m-%Y", "%H:%M"),
+            ("es" | "it" | "pt", _) => (".", "%d/%m/%Y", "%H:%M"),
+            ("fr", _) => ("\u{202f}", "%d/%m/%Y", "%H:%M"),
+            ("ru" | "uk" | "pl" | "cs" | "sv" | "fi" | "nb", _) => ("\u{a0}", "%d.%m.%Y", "%H:%M"),
+            ("zh" | "ja", _) => (",", "%Y/%m/%d", "%H:%M"),
+            ("ko", _) => (",", "%Y. %m. %d.", "%H:%M"),
+            _ => return Self::default(),
+        };
+        Locale { thousands_separator, date_pattern, time_pattern }
+    }
+
+    /// The configured locale tag, or the one from the environment (`LC_ALL`, `LC_NUMERIC`, `LANG`)
+    pub fn resolve(configured: Option<&str>) -> Self {
+        let from_env = || {
+            ["LC_ALL", "LC_NUMERIC", "LANG"]
+                .iter()
+                .filter_map(|var| std::env::var(var).ok())
+                .find(|value| !value.is_empty())
+        };
+        match configured.filter(|tag| !tag.is_empty()).map(str::to_string).or_else(from_env) {
+            Some(tag) => Self::from_tag(&tag),
+            None => Self::default(),
+        }
+    }
+
+    /// E.g. 12345 as "12,345" or "12.345"
+    pub fn format_number(&self, n: usize) -> String {
+        let digits = n.to_string();
+        let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push_str(self.thousands_separator);
+            }
+            grouped.push(c);
+        }
+        grouped
+    }
+
+    pub fn format_date<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        time.format(self.date_pattern).to_string()
+    }
+
+    pub fn format_datetime<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        format!("{} {}", time.format(self.date_pattern), time.format(self.time_pattern))
+    }
+}
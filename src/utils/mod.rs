@@ -1 +1,9 @@
-pub mod events;
\ No newline at end of file
+pub mod clipboard;
+pub mod events;
+pub mod fuzzy;
+pub mod locale;
+pub mod mounts;
+pub mod output;
+pub mod signals;
+pub mod text;
+pub mod time;
@@ -0,0 +1,79 @@
+//! Network shares and removable drives, which libraries can be on while they come and go
+
+use std::path::{Path, PathBuf};
+
+/// Filesystems reached over the network, as the mount table names them
+const NETWORK_FILESYSTEMS: [&str; 11] = [
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "afpfs", "9p", "davfs", "fuse.davfs2", "fuse.sshfs", "fuse.rclone",
+];
+
+/// Directories removable drives are mounted below
+const REMOVABLE_ROOTS: [&str; 4] = ["/media", "/run/media", "/mnt", "/Volumes"];
+
+/// The network share or removable drive `path` is on, by its mount point
+///
+/// Uses the mount table where the system has one; elsewhere a path below one of the
+/// usual mount directories counts as removable.
+pub fn removable_mount(path: &Path) -> Option<PathBuf> {
+    match read_mounts() {
+        Some(mounts) => {
+            let (mount_point, fs_type) = mounts
+                .into_iter()
+                .filter(|(mount_point, _)| path.starts_with(mount_point))
+                .max_by_key(|(mount_point, _)| mount_point.components().count())?;
+            let removable = NETWORK_FILESYSTEMS.contains(&fs_type.as_str())
+                || REMOVABLE_ROOTS.iter().any(|root| mount_point.starts_with(root) && mount_point != Path::new(root));
+            removable.then_some(mount_point)
+        }
+        None => REMOVABLE_ROOTS.iter().find_map(|root| {
+            let volume = path.strip_prefix(root).ok()?.components().next()?;
+            Some(Path::new(root).join(volume))
+        }),
+    }
+}
+
+/// Whether something is mounted at `mount_point`, or without a mount table, whether it exists
+pub fn is_mounted(mount_point: &Path) -> bool {
+    match read_mounts() {
+        Some(mounts) => mounts.iter().any(|(point, _)| point == mount_point),
+        None => mount_point.is_dir(),
+    }
+}
+
+/// Mount points and filesystem types from the mount table, on systems that have one
+fn read_mounts() -> Option<Vec<(PathBuf, String)>> {
+    let table = std::fs::read_to_string("/proc/self/mounts").ok()?;
+    Some(
+        table
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                let mount_point = unescape(fields.nth(1)?);
+                let fs_type = fields.next()?.to_string();
+                Some((PathBuf::from(mount_point), fs_type))
+            })
+            .collect(),
+    )
+}
+
+/// Undo the octal escapes the mount table writes spaces and tabs as, e.g. `\040`
+fn unescape(field: &str) -> String {
+    let mut out = String::new();
+    let mut rest = field;
+    while let Some(index) = rest.find('\\') {
+        out.push_str(&rest[..index]);
+        let code = rest.get(index + 1..index + 4).and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match code {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[index + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,71 @@
+//! Book listings of the command line, as plain text, JSON or CSV for other tools to read
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+
+use crate::app::Book;
+
+/// How `tuilibre list` and `tuilibre search` print the books they find
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    /// A line per book: id, title and authors, separated by tabs
+    #[default]
+    Plain,
+    /// A JSON object per book and line, for `jq`
+    Json,
+    /// A header row and a row per book, for spreadsheets
+    Csv,
+}
+
+/// Columns of the CSV listing
+const CSV_HEADER: [&str; 10] = ["id", "title", "authors", "series", "series_index", "tags", "rating", "formats", "added", "path"];
+
+/// Write `books` to `out` in `format`
+pub fn write_books(out: &mut impl Write, books: &[Book], format: ListFormat) -> io::Result<()> {
+    match format {
+        ListFormat::Plain => {
+            for book in books {
+                writeln!(out, "{}\t{}\t{}", book.id, book.title, book.author_list())?;
+            }
+        }
+        ListFormat::Json => {
+            for book in books {
+                serde_json::to_writer(&mut *out, book)?;
+                writeln!(out)?;
+            }
+        }
+        ListFormat::Csv => {
+            writeln!(out, "{}", CSV_HEADER.join(","))?;
+            for book in books {
+                let formats: Vec<&str> = book.formats.iter().map(|f| f.format.as_str()).collect();
+                let row = [
+                    book.id.to_string(),
+                    book.title.clone(),
+                    // calibre separates authors with " & " since names can hold commas
+                    book.authors.join(" & "),
+                    book.series.clone().unwrap_or_default(),
+                    book.series.as_ref().map(|_| book.series_index.to_string()).unwrap_or_default(),
+                    book.tag_list(),
+                    book.rating.map(|rating| rating.to_string()).unwrap_or_default(),
+                    formats.join(", "),
+                    book.timestamp.clone(),
+                    book.path.clone(),
+                ];
+                let fields: Vec<Cow<str>> = row.iter().map(|field| csv_field(field)).collect();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A CSV field, quoted when it holds a comma, quote or line break
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
@@ -0,0 +1,40 @@
+//! Turning termination signals into an orderly shutdown
+
+use crate::jobs::CancelToken;
+
+/// Cancel `shutdown` on the first SIGINT, SIGTERM or SIGHUP (Ctrl-C elsewhere)
+///
+/// The handlers replace the default ones that kill the process outright, so whoever
+/// holds `shutdown` must check it and exit once it has finished writing.
+pub fn listen_for_shutdown(shutdown: CancelToken) {
+    tokio::spawn(async move {
+        if wait_for_signal().await {
+            shutdown.cancel();
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> bool {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let (Ok(mut interrupt), Ok(mut terminate), Ok(mut hangup)) = (
+        signal(SignalKind::interrupt()),
+        signal(SignalKind::terminate()),
+        signal(SignalKind::hangup()),
+    ) else {
+        return false;
+    };
+
+    tokio::select! {
+        _ = interrupt.recv() => {}
+        _ = terminate.recv() => {}
+        _ = hangup.recv() => {}
+    }
+    true
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> bool {
+    tokio::signal::ctrl_c().await.is_ok()
+}
@@ -0,0 +1,168 @@
+//! Plain-text helpers shared by jobs that read book content and the UI
+
+/// Drop markup, keeping only the text between tags
+pub fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Elements that start a new paragraph when HTML is turned into text
+const BLOCK_TAGS: [&str; 17] = [
+    "p", "div", "br", "li", "h1", "h2", "h3", "h4", "h5", "h6", "blockquote", "tr", "section", "hr", "pre", "dt", "dd",
+];
+
+/// An HTML document as paragraphs of plain text, with the text of its first heading
+///
+/// Lenient on purpose: ebook markup is often neither valid XML nor valid HTML.
+pub fn html_paragraphs(html: &str) -> (Option<String>, Vec<String>) {
+    let mut paragraphs = Vec::new();
+    let mut heading = None;
+    let mut current = String::new();
+    let mut in_heading = false;
+    let mut skipping: Option<&str> = None;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        if skipping.is_none() {
+            current.push_str(&rest[..start]);
+        }
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        let name = name.rsplit(':').next().unwrap_or_default();
+
+        if let Some(skipped) = skipping {
+            if closing && name == skipped {
+                skipping = None;
+            }
+            continue;
+        }
+        match name {
+            "script" | "style" | "head" if !closing && !tag.ends_with('/') => {
+                skipping = Some(match name {
+                    "script" => "script",
+                    "style" => "style",
+                    _ => "head",
+                });
+            }
+            "h1" | "h2" | "h3" if heading.is_none() => {
+                if closing && in_heading {
+                    let text = collapse_whitespace(&decode_entities(&current));
+                    heading = (!text.is_empty()).then_some(text);
+                }
+                in_heading = !closing;
+                push_paragraph(&mut paragraphs, &mut current);
+            }
+            _ if BLOCK_TAGS.contains(&name) => push_paragraph(&mut paragraphs, &mut current),
+            _ => {}
+        }
+    }
+    if skipping.is_none() {
+        current.push_str(rest);
+    }
+    push_paragraph(&mut paragraphs, &mut current);
+    (heading, paragraphs)
+}
+
+/// End the paragraph being collected, unless it has no text
+fn push_paragraph(paragraphs: &mut Vec<String>, current: &mut String) {
+    let text = collapse_whitespace(&decode_entities(current));
+    if !text.is_empty() {
+        paragraphs.push(text);
+    }
+    current.clear();
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Replace the named entities common in ebooks and numeric ones with the characters they stand for
+pub fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..].find(';').filter(|&end| end <= 10).map(|end| &rest[1..end + 1]);
+        let character = entity.and_then(|name| match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "mdash" => Some('—'),
+            "ndash" => Some('–'),
+            "hellip" => Some('…'),
+            "lsquo" => Some('‘'),
+            "rsquo" => Some('’'),
+            "ldquo" => Some('“'),
+            "rdquo" => Some('”'),
+            _ => {
+                let number = name.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (entity, character) {
+            (Some(name), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[name.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Drop emoji and pictographic symbols, for screen readers that would spell them out
+pub fn strip_emoji(text: &str) -> String {
+    let kept: String = text.chars().filter(|c| !is_pictograph(*c)).collect();
+    kept.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn is_pictograph(c: char) -> bool {
+    matches!(c as u32,
+        0x1F000..=0x1FAFF   // Emoji and pictographs
+        | 0x2190..=0x21FF   // Arrows
+        | 0x231A..=0x23FF   // Watches, hourglasses
+        | 0x25A0..=0x25FF   // Geometric shapes
+        | 0x2600..=0x27BF   // Miscellaneous symbols and dingbats
+        | 0x2B00..=0x2BFF   // Stars and more arrows
+        | 0xFE0F | 0x200D   // Emoji presentation selector and joiner
+    )
+}
@@ -0,0 +1,87 @@
+//! Formatting of timestamps shown in the UI
+
+use chrono::{DateTime, Local, Utc};
+
+use super::locale::Locale;
+use serde::{Deserialize, Serialize};
+
+/// How timestamps are displayed, set by `time_format` in the `[display]` config section
+///
+/// `"relative"` gives "2 days ago", `"absolute"` gives the date and time as the locale writes
+/// them, and anything else is used as a strftime pattern, e.g. `"%d %b %Y"`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum TimeFormat {
+    #[default]
+    Relative,
+    Absolute,
+    Custom(String),
+}
+
+impl From<String> for TimeFormat {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "relative" => TimeFormat::Relative,
+            "absolute" => TimeFormat::Absolute,
+            _ => TimeFormat::Custom(value),
+        }
+    }
+}
+
+impl From<TimeFormat> for String {
+    fn from(format: TimeFormat) -> Self {
+        match format {
+            TimeFormat::Relative => "relative".to_string(),
+            TimeFormat::Absolute => "absolute".to_string(),
+            TimeFormat::Custom(pattern) => pattern,
+        }
+    }
+}
+
+/// Format `time` in the user's time zone; `absolute` overrides a relative format on demand
+pub fn format_time(time: DateTime<Utc>, format: &TimeFormat, absolute: bool, locale: &Locale) -> String {
+    let local = time.with_timezone(&Local);
+    match format {
+        TimeFormat::Relative if !absolute => relative_time(time, Utc::now()),
+        TimeFormat::Custom(pattern) => local.format(pattern).to_string(),
+        _ => locale.format_datetime(&local),
+    }
+}
+
+/// E.g. "just now", "5 minutes ago", "yesterday", "3 weeks ago"
+pub fn relative_time(time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let seconds = (now - time).num_seconds();
+    if seconds < 0 {
+        return "in the future".to_string();
+    }
+
+    let plural = |n: i64, unit: &str| {
+        if n == 1 {
+            format!("1 {} ago", unit)
+        } else {
+            format!("{} {}s ago", n, unit)
+        }
+    };
+
+    let minutes = seconds / 60;
+    let hours = minutes / 60;
+    let days = hours / 24;
+    match () {
+        _ if seconds < 60 => "just now".to_string(),
+        _ if minutes < 60 => plural(minutes, "minute"),
+        _ if hours < 24 => plural(hours, "hour"),
+        _ if days == 1 => "yesterday".to_string(),
+        _ if days < 7 => plural(days, "day"),
+        _ if days < 30 => plural(days / 7, "week"),
+        _ if days < 365 => plural(days / 30, "month"),
+        _ => plural(days / 365, "year"),
+    }
+}
+
+/// Parse a timestamp as calibre stores it, e.g. "2024-01-11 10:00:00.123456+00:00"
+pub fn parse_calibre_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S%.f%:z")
+        .or_else(|_| DateTime::parse_from_rfc3339(timestamp))
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
@@ -0,0 +1,47 @@
+//! Each list keeps its own place when another view is shown and it comes back
+
+use std::path::PathBuf;
+
+use tuilibre::app::{AppMode, DetailsTab, ScrollView, TagCount};
+use tuilibre::App;
+
+fn tags(count: usize) -> Vec<TagCount> {
+    (0..count).map(|i| TagCount { name: format!("tag {}", i), count: 1 }).collect()
+}
+
+#[test]
+fn views_keep_their_place_when_switching_back() {
+    let mut app = App::new(PathBuf::from("/library"));
+
+    // The book list and a search over it scroll apart
+    app.record_scroll(ScrollView::Books, 40, 30);
+    app.mode = AppMode::Search;
+    app.record_scroll(app.book_scroll_view(), 3, 0);
+    app.mode = AppMode::Normal;
+    assert_eq!(app.scroll_state(app.book_scroll_view()).selected, 40);
+    assert_eq!(app.scroll_state(app.book_scroll_view()).offset, 30);
+
+    // The tag pane comes back on the tag it was left on
+    app.show_tag_browser(tags(20));
+    app.tag_browser.as_mut().unwrap().select_next_by(7);
+    app.hide_tag_browser();
+    assert!(app.tag_browser.is_none());
+    app.show_tag_browser(tags(20));
+    assert_eq!(app.tag_browser.as_ref().unwrap().selected, 7);
+
+    // Fewer tags than before: the pane stays within them
+    app.hide_tag_browser();
+    app.show_tag_browser(tags(5));
+    assert_eq!(app.tag_browser.as_ref().unwrap().selected, 4);
+
+    // The annotations tab keeps its selection while the description is shown
+    app.details_tab = app.details_tab.next();
+    assert_eq!(app.details_tab, DetailsTab::Annotations);
+    app.select_in(ScrollView::Annotations, 2);
+    app.details_tab = app.details_tab.next();
+    app.details_tab = app.details_tab.next();
+    assert_eq!(app.annotation_selected(), 2);
+
+    // None of that moved the book list
+    assert_eq!(app.scroll_state(ScrollView::Books).selected, 40);
+}